@@ -0,0 +1,73 @@
+/*
+ * Minimal embedding example:
+ *
+ *   cargo build -p converge-capi
+ *   cc crates/converge-capi/examples/embed.c -Icrates/converge-capi/include \
+ *      target/debug/libconverge_capi.a -lpthread -ldl -lm -o embed
+ *   ./embed examples/assert.cv
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "converge.h"
+
+static char *read_all(const char *path) {
+    FILE *f = fopen(path, "rb");
+    if (!f) return NULL;
+    fseek(f, 0, SEEK_END);
+    long n = ftell(f);
+    fseek(f, 0, SEEK_SET);
+    char *buf = malloc((size_t)n + 1);
+    if (buf && fread(buf, 1, (size_t)n, f) != (size_t)n) {
+        free(buf);
+        buf = NULL;
+    }
+    if (buf) buf[n] = '\0';
+    fclose(f);
+    return buf;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <file.cv>\n", argv[0]);
+        return 2;
+    }
+    char *src = read_all(argv[1]);
+    if (!src) {
+        perror(argv[1]);
+        return 2;
+    }
+
+    char *error = NULL;
+    ConvergeProgram *program = converge_parse(src, &error);
+    free(src);
+    if (!program) {
+        fprintf(stderr, "%s\n", error);
+        converge_string_free(error);
+        return 1;
+    }
+
+    ConvergeOptions options = {.record_spikes = 1, .kahan = 0};
+    ConvergeResult *result = converge_simulate(program, &options, &error);
+    converge_program_free(program);
+    if (!result) {
+        fprintf(stderr, "%s\n", error);
+        converge_string_free(error);
+        return 1;
+    }
+
+    size_t len = 0;
+    const ConvergeSpike *spikes = converge_result_spikes(result, &len);
+    printf("%llu spikes\n", (unsigned long long)converge_result_total_spikes(result));
+    for (size_t i = 0; i < len && i < 5; i++) {
+        printf("  step %llu layer %u neuron %llu\n", (unsigned long long)spikes[i].step,
+               spikes[i].layer, (unsigned long long)spikes[i].neuron);
+    }
+
+    int failed = converge_result_assert_failures(result) > 0;
+    for (size_t i = 0; i < converge_result_assert_failures(result); i++) {
+        fprintf(stderr, "%s\n", converge_result_assert_message(result, i));
+    }
+    converge_result_free(result);
+    return failed;
+}
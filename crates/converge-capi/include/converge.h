@@ -0,0 +1,94 @@
+/*
+ * converge.h: C API for embedding the Converge simulator.
+ *
+ * Link against libconverge_capi, built by `cargo build -p converge-capi
+ * --release` as target/release/libconverge_capi.{so,dylib,a}.
+ *
+ * Ownership: every pointer returned by a converge_* function is owned by the
+ * caller and must be released with the matching *_free function. Pointers
+ * borrowed from a result (spikes) stay valid until that result is freed.
+ * Functions that can fail return NULL and, when `error` is non-NULL, store a
+ * message there that the caller frees with converge_string_free.
+ *
+ * No Rust panic unwinds into C. converge_parse and converge_simulate report
+ * one through `error`; the other functions return NULL or 0 instead.
+ *
+ * All functions are thread-safe as long as a single object is not used from
+ * two threads at once.
+ */
+#ifndef CONVERGE_H
+#define CONVERGE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Bumped on any incompatible change to this header. */
+#define CONVERGE_CAPI_VERSION 1
+
+/* A parsed and validated program. */
+typedef struct ConvergeProgram ConvergeProgram;
+
+/* A finished simulation run. */
+typedef struct ConvergeResult ConvergeResult;
+
+typedef struct ConvergeOptions {
+    /* Nonzero to keep every spike, readable with converge_result_spikes. */
+    uint8_t record_spikes;
+    /* Nonzero for Kahan-compensated spike delivery. */
+    uint8_t kahan;
+} ConvergeOptions;
+
+typedef struct ConvergeSpike {
+    uint64_t step;
+    /* Index into the layers of the program, in declaration order. */
+    uint32_t layer;
+    uint64_t neuron;
+} ConvergeSpike;
+
+/* CONVERGE_CAPI_VERSION of the linked library. */
+uint32_t converge_capi_version(void);
+
+/* Parses and validates NUL-terminated UTF-8 source. */
+ConvergeProgram *converge_parse(const char *src, char **error);
+
+void converge_program_free(ConvergeProgram *program);
+
+/*
+ * Runs the program to completion. `options` may be NULL for defaults.
+ * Failed `assert` statements do not fail the run; see
+ * converge_result_assert_failures.
+ */
+ConvergeResult *converge_simulate(const ConvergeProgram *program,
+                                  const ConvergeOptions *options,
+                                  char **error);
+
+void converge_result_free(ConvergeResult *result);
+
+/* The summary printed by `converge sim`, as JSON. Free with converge_string_free. */
+char *converge_result_summary_json(const ConvergeResult *result);
+
+uint64_t converge_result_total_spikes(const ConvergeResult *result);
+
+/* Number of `assert` statements that did not hold. */
+size_t converge_result_assert_failures(const ConvergeResult *result);
+
+/* Message for failed assert `index`, borrowed from the result; NULL if out of range. */
+const char *converge_result_assert_message(const ConvergeResult *result, size_t index);
+
+/*
+ * Recorded spikes in emission order, with their count in *len. NULL with
+ * *len = 0 unless the run recorded spikes.
+ */
+const ConvergeSpike *converge_result_spikes(const ConvergeResult *result, size_t *len);
+
+void converge_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CONVERGE_H */
@@ -0,0 +1,319 @@
+//! C API for embedding the simulator; see `include/converge.h` for the
+//! contract. Every entry point catches panics so none unwind into C: the
+//! fallible ones through [`guarded`], the rest through [`shielded`].
+
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::ptr;
+
+use converge_lang::ast::Program;
+use converge_lang::parser::{format_diagnostic, parse_program};
+use converge_lang::validate::validate;
+use converge_sim::{SimConfig, SimSummary, check_asserts, simulate_with, summary_json};
+
+/// Matches `CONVERGE_CAPI_VERSION` in the header.
+pub const CAPI_VERSION: u32 = 1;
+
+pub struct ConvergeProgram {
+    src: String,
+    program: Program,
+}
+
+pub struct ConvergeResult {
+    summary: SimSummary,
+    spikes: Vec<ConvergeSpike>,
+    assert_messages: Vec<CString>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvergeOptions {
+    pub record_spikes: u8,
+    pub kahan: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConvergeSpike {
+    pub step: u64,
+    pub layer: u32,
+    pub neuron: u64,
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn converge_capi_version() -> u32 {
+    CAPI_VERSION
+}
+
+/// # Safety
+///
+/// `src` must be a valid NUL-terminated string, and `error` null or valid
+/// for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_parse(
+    src: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut ConvergeProgram {
+    guarded(error, || {
+        if src.is_null() {
+            return Err("source is NULL".to_string());
+        }
+        // SAFETY: non-null and NUL-terminated per the contract above.
+        let src = unsafe { CStr::from_ptr(src) }
+            .to_str()
+            .map_err(|_| "source is not valid UTF-8".to_string())?
+            .to_string();
+        let program = parse_program(&src).map_err(|diag| format_diagnostic(&src, &diag))?;
+        if let Err(diags) = validate(&program) {
+            let messages: Vec<String> = diags.iter().map(|d| format_diagnostic(&src, d)).collect();
+            return Err(messages.join("\n"));
+        }
+        Ok(Box::into_raw(Box::new(ConvergeProgram { src, program })))
+    })
+}
+
+/// # Safety
+///
+/// `program` must be null or a pointer from `converge_parse` that has not
+/// been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_program_free(program: *mut ConvergeProgram) {
+    if !program.is_null() {
+        // SAFETY: allocated by `converge_parse` and freed only once.
+        shielded((), || drop(unsafe { Box::from_raw(program) }));
+    }
+}
+
+/// # Safety
+///
+/// `program` must be a live pointer from `converge_parse`, `options` null or
+/// valid for reads, and `error` null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_simulate(
+    program: *const ConvergeProgram,
+    options: *const ConvergeOptions,
+    error: *mut *mut c_char,
+) -> *mut ConvergeResult {
+    guarded(error, || {
+        // SAFETY: null or live per the contract above.
+        let Some(program) = (unsafe { program.as_ref() }) else {
+            return Err("program is NULL".to_string());
+        };
+        // SAFETY: null or valid for reads.
+        let options = unsafe { options.as_ref() }.copied().unwrap_or_default();
+        let config = SimConfig {
+            record_spikes: options.record_spikes != 0,
+            compensated_summation: options.kahan != 0,
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&program.program, &config).map_err(|err| err.to_string())?;
+        let spikes = summary
+            .raster
+            .iter()
+            .flatten()
+            .map(|s| ConvergeSpike {
+                step: s.step,
+                layer: s.layer,
+                neuron: s.neuron,
+            })
+            .collect();
+        let assert_messages = check_asserts(&program.program, &summary)
+            .iter()
+            .map(|d| c_string(format_diagnostic(&program.src, d)))
+            .collect();
+        Ok(Box::into_raw(Box::new(ConvergeResult {
+            summary,
+            spikes,
+            assert_messages,
+        })))
+    })
+}
+
+/// # Safety
+///
+/// `result` must be null or a pointer from `converge_simulate` that has not
+/// been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_result_free(result: *mut ConvergeResult) {
+    if !result.is_null() {
+        // SAFETY: allocated by `converge_simulate` and freed only once.
+        shielded((), || drop(unsafe { Box::from_raw(result) }));
+    }
+}
+
+/// # Safety
+///
+/// `result` must be null or a live pointer from `converge_simulate`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_result_summary_json(
+    result: *const ConvergeResult,
+) -> *mut c_char {
+    // SAFETY: null or live per the contract above.
+    let Some(result) = (unsafe { result.as_ref() }) else {
+        return ptr::null_mut();
+    };
+    shielded(ptr::null_mut(), || {
+        c_string(summary_json(&result.summary)).into_raw()
+    })
+}
+
+/// # Safety
+///
+/// `result` must be null or a live pointer from `converge_simulate`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_result_total_spikes(result: *const ConvergeResult) -> u64 {
+    // SAFETY: null or live per the contract above.
+    let result = unsafe { result.as_ref() };
+    shielded(0, || result.map_or(0, |r| r.summary.total_spikes))
+}
+
+/// # Safety
+///
+/// `result` must be null or a live pointer from `converge_simulate`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_result_assert_failures(result: *const ConvergeResult) -> usize {
+    // SAFETY: null or live per the contract above.
+    let result = unsafe { result.as_ref() };
+    shielded(0, || result.map_or(0, |r| r.assert_messages.len()))
+}
+
+/// # Safety
+///
+/// `result` must be null or a live pointer from `converge_simulate`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_result_assert_message(
+    result: *const ConvergeResult,
+    index: usize,
+) -> *const c_char {
+    // SAFETY: null or live per the contract above.
+    let result = unsafe { result.as_ref() };
+    shielded(ptr::null(), || {
+        result
+            .and_then(|r| r.assert_messages.get(index))
+            .map_or(ptr::null(), |m| m.as_ptr())
+    })
+}
+
+/// # Safety
+///
+/// `result` must be null or a live pointer from `converge_simulate`, and
+/// `len` null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_result_spikes(
+    result: *const ConvergeResult,
+    len: *mut usize,
+) -> *const ConvergeSpike {
+    // SAFETY: null or live per the contract above.
+    let result = unsafe { result.as_ref() };
+    shielded(ptr::null(), || {
+        let spikes = result.map_or(&[][..], |r| &r.spikes[..]);
+        if !len.is_null() {
+            // SAFETY: non-null and valid for writes.
+            unsafe { *len = spikes.len() };
+        }
+        if spikes.is_empty() {
+            ptr::null()
+        } else {
+            spikes.as_ptr()
+        }
+    })
+}
+
+/// # Safety
+///
+/// `s` must be null or a string returned by this library that has not been
+/// freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn converge_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: produced by `CString::into_raw` here and freed only once.
+        shielded((), || drop(unsafe { CString::from_raw(s) }));
+    }
+}
+
+/// Runs `f`, turning errors and panics into a null return plus a message in
+/// `*error`.
+fn guarded<T>(error: *mut *mut c_char, f: impl FnOnce() -> Result<*mut T, String>) -> *mut T {
+    let message = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(message)) => message,
+        Err(_) => "internal error: converge panicked".to_string(),
+    };
+    if !error.is_null() {
+        // SAFETY: callers pass `error` straight from C, documented as null or
+        // valid for writes.
+        unsafe { *error = c_string(message).into_raw() };
+    }
+    ptr::null_mut()
+}
+
+/// Runs `f` for an entry point that can't report errors, returning
+/// `fallback` if it panics.
+fn shielded<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(fallback)
+}
+
+/// Interior NULs cannot cross the C boundary; they become U+FFFD.
+fn c_string(s: String) -> CString {
+    CString::new(s.replace('\0', "\u{fffd}")).expect("NULs replaced")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\nlayer A[4] : LIF\nstimulus A = Poisson(rate=300 Hz)\nrun for 20 ms\nassert spikes(A) < 0\n\0";
+
+    #[test]
+    fn parses_simulates_and_frees() {
+        unsafe {
+            let mut error = ptr::null_mut();
+            let program = converge_parse(SRC.as_ptr().cast(), &mut error);
+            assert!(!program.is_null() && error.is_null());
+
+            let options = ConvergeOptions {
+                record_spikes: 1,
+                kahan: 0,
+            };
+            let result = converge_simulate(program, &options, &mut error);
+            assert!(!result.is_null());
+            let mut len = 0;
+            let spikes = converge_result_spikes(result, &mut len);
+            assert_eq!(len as u64, converge_result_total_spikes(result));
+            assert!(len > 0 && !spikes.is_null());
+            assert_eq!(converge_result_assert_failures(result), 1);
+            assert!(converge_result_assert_message(result, 1).is_null());
+
+            let json = converge_result_summary_json(result);
+            assert!(
+                CStr::from_ptr(json)
+                    .to_str()
+                    .unwrap()
+                    .contains("total_spikes")
+            );
+            converge_string_free(json);
+            converge_result_free(result);
+            converge_program_free(program);
+        }
+    }
+
+    #[test]
+    fn reports_diagnostics_through_error() {
+        unsafe {
+            let mut error = ptr::null_mut();
+            let program = converge_parse(c"layer A[1] : Missing\n".as_ptr(), &mut error);
+            assert!(program.is_null());
+            let message = CStr::from_ptr(error).to_str().unwrap().to_string();
+            assert!(message.contains("Missing"), "{message}");
+            converge_string_free(error);
+        }
+    }
+
+    #[test]
+    fn getters_return_their_fallback_on_panic() {
+        assert_eq!(shielded(7, || panic!("getter panicked")), 7);
+        assert!(shielded(ptr::null::<c_char>(), || panic!("getter panicked")).is_null());
+    }
+}
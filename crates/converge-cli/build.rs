@@ -0,0 +1,225 @@
+//! Generates the messages and gRPC stubs of `converge cosim` from
+//! `proto/cosim.proto`, so the file other frameworks generate code from is
+//! the one the server is built from. The file only uses a small part of
+//! proto3 (scalar, repeated and message fields, and unary RPCs), which is
+//! parsed here so building needs no `protoc`; anything else fails the build.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use tonic_build::manual::{Builder, Method, Service};
+
+const PROTO: &str = "proto/cosim.proto";
+
+struct Field {
+    repeated: bool,
+    ty: String,
+    name: String,
+    tag: u32,
+}
+
+struct Message {
+    name: String,
+    fields: Vec<Field>,
+}
+
+struct Rpc {
+    name: String,
+    input: String,
+    output: String,
+}
+
+struct Proto {
+    package: String,
+    service: String,
+    rpcs: Vec<Rpc>,
+    messages: Vec<Message>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={PROTO}");
+    let src = std::fs::read_to_string(PROTO).expect("read proto/cosim.proto");
+    let proto = parse(&src).unwrap_or_else(|e| panic!("{PROTO}: {e}"));
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").expect("OUT_DIR"));
+    let messages = messages_rs(&proto).unwrap_or_else(|e| panic!("{PROTO}: {e}"));
+    std::fs::write(out_dir.join("cosim_messages.rs"), messages).expect("write messages");
+
+    let service = proto
+        .rpcs
+        .iter()
+        .fold(
+            Service::builder()
+                .name(&proto.service)
+                .package(&proto.package),
+            |service, rpc| {
+                service.method(
+                    Method::builder()
+                        .name(snake_case(&rpc.name))
+                        .route_name(&rpc.name)
+                        .input_type(format!("super::{}", rpc.input))
+                        .output_type(format!("super::{}", rpc.output))
+                        .codec_path("tonic_prost::ProstCodec")
+                        .build(),
+                )
+            },
+        )
+        .build();
+    Builder::new().compile(&[service]);
+}
+
+fn parse(src: &str) -> Result<Proto, String> {
+    let mut text = String::new();
+    for line in src.lines() {
+        let line = line.split_once("//").map_or(line, |(code, _)| code);
+        for c in line.chars() {
+            if "{}();=".contains(c) {
+                text.extend([' ', c, ' ']);
+            } else {
+                text.push(c);
+            }
+        }
+        text.push(' ');
+    }
+    let mut tokens = text.split_whitespace();
+    let mut next = || tokens.next().ok_or("unexpected end of file".to_string());
+    let expect = |token: &str, want: &str| match token == want {
+        true => Ok(()),
+        false => Err(format!("expected `{want}`, found `{token}`")),
+    };
+
+    let mut proto = Proto {
+        package: String::new(),
+        service: String::new(),
+        rpcs: Vec::new(),
+        messages: Vec::new(),
+    };
+    while let Ok(keyword) = next() {
+        match keyword {
+            "syntax" => {
+                expect(next()?, "=")?;
+                expect(next()?, "\"proto3\"")?;
+                expect(next()?, ";")?;
+            }
+            "package" => {
+                proto.package = next()?.to_string();
+                expect(next()?, ";")?;
+            }
+            "service" => {
+                proto.service = next()?.to_string();
+                expect(next()?, "{")?;
+                loop {
+                    match next()? {
+                        "}" => break,
+                        "rpc" => {}
+                        token => return Err(format!("expected `rpc`, found `{token}`")),
+                    }
+                    let name = next()?.to_string();
+                    expect(next()?, "(")?;
+                    let input = next()?.to_string();
+                    expect(next()?, ")")?;
+                    expect(next()?, "returns")?;
+                    expect(next()?, "(")?;
+                    let output = next()?.to_string();
+                    expect(next()?, ")")?;
+                    expect(next()?, ";")?;
+                    proto.rpcs.push(Rpc {
+                        name,
+                        input,
+                        output,
+                    });
+                }
+            }
+            "message" => {
+                let name = next()?.to_string();
+                expect(next()?, "{")?;
+                let mut fields = Vec::new();
+                loop {
+                    let mut token = next()?;
+                    if token == "}" {
+                        break;
+                    }
+                    let repeated = token == "repeated";
+                    if repeated {
+                        token = next()?;
+                    }
+                    let ty = token.to_string();
+                    let field = next()?.to_string();
+                    expect(next()?, "=")?;
+                    let tag = next()?;
+                    let tag = tag
+                        .parse()
+                        .map_err(|_| format!("bad tag `{tag}` on `{name}.{field}`"))?;
+                    expect(next()?, ";")?;
+                    fields.push(Field {
+                        repeated,
+                        ty,
+                        name: field,
+                        tag,
+                    });
+                }
+                proto.messages.push(Message { name, fields });
+            }
+            token => return Err(format!("unsupported item `{token}`")),
+        }
+    }
+    Ok(proto)
+}
+
+/// The prost structs of every message, as `prost-build` would write them.
+fn messages_rs(proto: &Proto) -> Result<String, String> {
+    let mut out = String::new();
+    for message in &proto.messages {
+        let _ = writeln!(out, "#[derive(Clone, PartialEq, prost::Message)]");
+        let _ = writeln!(out, "pub struct {} {{", message.name);
+        for field in &message.fields {
+            let scalar = match field.ty.as_str() {
+                "string" => Some("String"),
+                "bool" => Some("bool"),
+                "double" => Some("f64"),
+                "float" => Some("f32"),
+                "int32" => Some("i32"),
+                "int64" => Some("i64"),
+                "uint32" => Some("u32"),
+                "uint64" => Some("u64"),
+                _ => None,
+            };
+            let (kind, ty) = match scalar {
+                Some(rust) => (field.ty.clone(), rust.to_string()),
+                None if proto.messages.iter().any(|m| m.name == field.ty) => {
+                    ("message".to_string(), field.ty.clone())
+                }
+                None => {
+                    return Err(format!(
+                        "unsupported type `{}` of `{}.{}`",
+                        field.ty, message.name, field.name
+                    ));
+                }
+            };
+            let (label, ty) = match (field.repeated, kind.as_str()) {
+                (true, _) => ("repeated, ", format!("Vec<{ty}>")),
+                (false, "message") => ("optional, ", format!("Option<{ty}>")),
+                (false, _) => ("", ty),
+            };
+            let _ = writeln!(
+                out,
+                "    #[prost({kind}, {label}tag = \"{}\")]\n    pub {}: {ty},",
+                field.tag, field.name
+            );
+        }
+        let _ = writeln!(out, "}}\n");
+    }
+    Ok(out)
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
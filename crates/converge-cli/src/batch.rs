@@ -0,0 +1,264 @@
+//! `converge batch`: check and simulate many model files in parallel, writing
+//! one summary per input and an `index.json` over all of them. A failing
+//! input is recorded and the batch moves on.
+
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use converge_lang::json::Value;
+use converge_lang::lexer::process_env;
+use converge_lang::parser::{format_diagnostic, parse_program_with_env};
+use converge_lang::validate::validate;
+use converge_sim::{SimConfig, check_asserts, simulate_with, source_hash, summary_json};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Unreadable, or rejected by the parser or validator.
+    Invalid,
+    /// The simulator returned an error.
+    Failed,
+    /// The run completed but an `assert` did not hold.
+    AssertFailed,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Invalid => "invalid",
+            Status::Failed => "failed",
+            Status::AssertFailed => "assert_failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub input: PathBuf,
+    pub status: Status,
+    /// Summary file name inside the output directory, when a run completed.
+    pub summary: Option<String>,
+    pub total_spikes: Option<u64>,
+    pub errors: Vec<String>,
+    pub wall_ns: u128,
+}
+
+/// Runs every input on up to `jobs` threads. Results come back in input
+/// order; `on_done` sees each one as it finishes.
+pub fn run_batch(
+    inputs: &[PathBuf],
+    out_dir: &Path,
+    config: &SimConfig,
+    jobs: usize,
+    on_done: impl Fn(&BatchResult) + Sync,
+) -> Vec<BatchResult> {
+    let names = summary_names(inputs);
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; inputs.len()]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, inputs.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = inputs.get(idx) else {
+                        return;
+                    };
+                    let result = run_one(input, &names[idx], out_dir, config);
+                    on_done(&result);
+                    results.lock().expect("results lock")[idx] = Some(result);
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("results lock")
+        .into_iter()
+        .map(|r| r.expect("every input ran"))
+        .collect()
+}
+
+fn run_one(input: &Path, name: &str, out_dir: &Path, config: &SimConfig) -> BatchResult {
+    let started = Instant::now();
+    let mut result = BatchResult {
+        input: input.to_path_buf(),
+        status: Status::Invalid,
+        summary: None,
+        total_spikes: None,
+        errors: Vec::new(),
+        wall_ns: 0,
+    };
+
+    let src = match std::fs::read_to_string(input) {
+        Ok(s) => s,
+        Err(e) => {
+            result.errors.push(format!("failed to read: {e}"));
+            result.wall_ns = started.elapsed().as_nanos();
+            return result;
+        }
+    };
+    let program = match parse_program_with_env(&src, &[], &process_env) {
+        Ok(p) => Some(p),
+        Err(diag) => {
+            result.errors.push(format_diagnostic(&src, &diag));
+            None
+        }
+    };
+    let program = program.filter(|p| match validate(p) {
+        Ok(()) => true,
+        Err(diags) => {
+            for diag in diags {
+                result.errors.push(format_diagnostic(&src, &diag));
+            }
+            false
+        }
+    });
+
+    if let Some(program) = program {
+        let config = SimConfig {
+            data_dir: input.parent().map(Path::to_path_buf),
+            ..config.clone()
+        };
+        match simulate_with(&program, &config) {
+            Ok(mut summary) => {
+                summary.input_hash = Some(source_hash(&src));
+                match std::fs::write(out_dir.join(name), summary_json(&summary)) {
+                    Ok(()) => result.summary = Some(name.to_string()),
+                    Err(e) => result.errors.push(format!("failed to write summary: {e}")),
+                }
+                result.total_spikes = Some(summary.total_spikes);
+                for diag in check_asserts(&program, &summary) {
+                    result.errors.push(format_diagnostic(&src, &diag));
+                }
+                result.status = match (&result.summary, result.errors.is_empty()) {
+                    (None, _) => Status::Failed,
+                    (Some(_), true) => Status::Ok,
+                    (Some(_), false) => Status::AssertFailed,
+                };
+            }
+            Err(err) => {
+                result.status = Status::Failed;
+                result.errors.push(err.to_string());
+            }
+        }
+    }
+    result.wall_ns = started.elapsed().as_nanos();
+    result
+}
+
+/// The summary file name of every input, in order: [`summary_name`], with
+/// `-2`, `-3` and so on added to any name an earlier input already took.
+pub fn summary_names(inputs: &[PathBuf]) -> Vec<String> {
+    let mut taken = HashSet::new();
+    inputs
+        .iter()
+        .map(|input| {
+            let name = summary_name(input);
+            let stem = name.strip_suffix(".json").unwrap_or(&name);
+            let name = (1..)
+                .map(|n| match n {
+                    1 => name.clone(),
+                    n => format!("{stem}-{n}.json"),
+                })
+                .find(|name| !taken.contains(name))
+                .expect("some suffix is free");
+            taken.insert(name.clone());
+            name
+        })
+        .collect()
+}
+
+/// Flattens an input path into a file name, so `a/x.cv` and `b/x.cv` don't
+/// overwrite each other: `a__x.json`. Paths that differ only in `..`, a
+/// leading `/` or where `__` falls flatten alike; [`summary_names`] keeps
+/// those apart within a batch.
+pub fn summary_name(input: &Path) -> String {
+    let parts: Vec<String> = input
+        .with_extension("")
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    format!("{}.json", parts.join("__"))
+}
+
+pub fn index_json(results: &[BatchResult]) -> String {
+    let ok = results.iter().filter(|r| r.status == Status::Ok).count();
+    let mut s = String::new();
+    s.push_str("{\n");
+    s.push_str(&format!("  \"inputs\": {},\n", results.len()));
+    s.push_str(&format!("  \"ok\": {ok},\n"));
+    s.push_str(&format!("  \"failed\": {},\n", results.len() - ok));
+    s.push_str("  \"results\": [\n");
+    for (idx, r) in results.iter().enumerate() {
+        let string = |s: &str| Value::String(s.to_string()).to_string();
+        s.push_str("    {\n");
+        s.push_str(&format!(
+            "      \"input\": {},\n",
+            string(&r.input.to_string_lossy())
+        ));
+        s.push_str(&format!("      \"status\": \"{}\",\n", r.status.as_str()));
+        match &r.summary {
+            Some(name) => s.push_str(&format!("      \"summary\": {},\n", string(name))),
+            None => s.push_str("      \"summary\": null,\n"),
+        }
+        match r.total_spikes {
+            Some(n) => s.push_str(&format!("      \"total_spikes\": {n},\n")),
+            None => s.push_str("      \"total_spikes\": null,\n"),
+        }
+        let errors: Vec<String> = r.errors.iter().map(|e| string(e)).collect();
+        s.push_str(&format!("      \"errors\": [{}],\n", errors.join(", ")));
+        s.push_str(&format!("      \"wall_ns\": {}\n", r.wall_ns));
+        s.push_str("    }");
+        if idx + 1 != results.len() {
+            s.push(',');
+        }
+        s.push('\n');
+    }
+    s.push_str("  ]\n");
+    s.push_str("}\n");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_names_keep_directories_apart() {
+        assert_eq!(
+            summary_name(Path::new("models/a/x.cv")),
+            "models__a__x.json"
+        );
+        assert_eq!(summary_name(Path::new("../b/x.cv")), "b__x.json");
+        assert_eq!(
+            summary_name(Path::new("/abs/run.v2.cv")),
+            "abs__run.v2.json"
+        );
+    }
+
+    #[test]
+    fn colliding_summary_names_get_a_suffix() {
+        let inputs: Vec<PathBuf> = ["../b/x.cv", "b/x.cv", "a__b/x.cv", "a/b__x.cv", "b/x-2.cv"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(
+            summary_names(&inputs),
+            [
+                "b__x.json",
+                "b__x-2.json",
+                "a__b__x.json",
+                "a__b__x-2.json",
+                "b__x-2-2.json"
+            ]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::Instant;
+
+use converge_lang::ast::Program;
+use converge_lang::json::Value;
+use converge_sim::{SimConfig, SimError, Simulator};
+
+/// Networks run by `converge bench` when no file is given. Sized so a release
+/// build finishes each run in well under a second.
+pub const BUNDLED: &[(&str, &str)] = &[
+    (
+        "feedforward",
+        r#"
+neuron LIF { tau_m = 20 ms, v_th = 1.0 }
+layer In[200] : LIF
+layer Hidden[200] : LIF
+layer Out[50] : LIF
+connect In -> Hidden { w = Uniform(0.0, 0.1), d = 1 ms }
+connect Hidden -> Out { w = Uniform(0.0, 0.2), d = 2 ms }
+stimulus In = Poisson(rate=50 Hz)
+seed 1
+run for 500 ms step 1 ms
+"#,
+    ),
+    (
+        "recurrent",
+        r#"
+neuron LIF { tau_m = 15 ms, v_th = 1.0 }
+layer In[100] : LIF
+layer Exc[400] : LIF
+layer Inh[100] : LIF
+connect In -> Exc { w = Uniform(0.0, 0.2), d = 1 ms }
+connect Exc -> Inh { w = Normal(0.05, 0.02), d = 2 ms }
+connect Inh -> Exc { w = Uniform(-0.2, 0.0), d = 1 ms }
+connect Exc -> Exc { w = Normal(0.005, 0.01), d = 3 ms }
+stimulus In = Poisson(rate=100 Hz)
+stimulus Exc = Poisson(rate=5 Hz)
+seed 2
+run for 500 ms step 0.5 ms
+"#,
+    ),
+];
+
+/// Timings for one network over `runs` repetitions. Throughput is computed
+/// from the fastest run, which is the least disturbed by other load.
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    pub runs: usize,
+    pub steps: u64,
+    pub spikes: u64,
+    pub build_ns: u64,
+    pub min_ns: u64,
+    pub mean_ns: u64,
+}
+
+impl BenchResult {
+    pub fn steps_per_sec(&self) -> f64 {
+        self.steps as f64 * 1e9 / self.min_ns.max(1) as f64
+    }
+
+    pub fn spikes_per_sec(&self) -> f64 {
+        self.spikes as f64 * 1e9 / self.min_ns.max(1) as f64
+    }
+}
+
+pub fn bench_program(
+    name: &str,
+    program: &Program,
+    config: &SimConfig,
+    runs: usize,
+) -> Result<BenchResult, SimError> {
+    let mut build_total = 0u64;
+    let mut run_times = Vec::with_capacity(runs);
+    let mut steps = 0;
+    let mut spikes = 0;
+    for _ in 0..runs {
+        let started = Instant::now();
+        let mut sim = Simulator::new(program, config)?;
+        let built = Instant::now();
+        sim.run()?;
+        run_times.push(built.elapsed().as_nanos() as u64);
+        build_total += (built - started).as_nanos() as u64;
+        steps = sim.total_steps();
+        spikes = sim.total_spikes();
+    }
+    let runs = runs.max(1);
+    Ok(BenchResult {
+        name: name.to_string(),
+        runs,
+        steps,
+        spikes,
+        build_ns: build_total / runs as u64,
+        min_ns: run_times.iter().copied().min().unwrap_or(0),
+        mean_ns: run_times.iter().sum::<u64>() / runs as u64,
+    })
+}
+
+/// Peak resident set size of this process. Only Linux exposes it without
+/// platform calls, so other targets report `None`.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+pub fn bench_json(results: &[BenchResult], peak_rss: Option<u64>) -> String {
+    let mut s = String::new();
+    s.push_str("{\n");
+    s.push_str("  \"benchmarks\": [\n");
+    for (idx, r) in results.iter().enumerate() {
+        s.push_str("    {\n");
+        s.push_str(&format!(
+            "      \"name\": {},\n",
+            Value::String(r.name.clone())
+        ));
+        s.push_str(&format!("      \"runs\": {},\n", r.runs));
+        s.push_str(&format!("      \"steps\": {},\n", r.steps));
+        s.push_str(&format!("      \"spikes\": {},\n", r.spikes));
+        s.push_str(&format!("      \"build_ns\": {},\n", r.build_ns));
+        s.push_str(&format!("      \"min_ns\": {},\n", r.min_ns));
+        s.push_str(&format!("      \"mean_ns\": {},\n", r.mean_ns));
+        s.push_str(&format!(
+            "      \"steps_per_sec\": {:.1},\n",
+            r.steps_per_sec()
+        ));
+        s.push_str(&format!(
+            "      \"spikes_per_sec\": {:.1}\n",
+            r.spikes_per_sec()
+        ));
+        s.push_str("    }");
+        if idx + 1 != results.len() {
+            s.push(',');
+        }
+        s.push('\n');
+    }
+    s.push_str("  ],\n");
+    match peak_rss {
+        Some(bytes) => s.push_str(&format!("  \"peak_rss_bytes\": {bytes}\n")),
+        None => s.push_str("  \"peak_rss_bytes\": null\n"),
+    }
+    s.push_str("}\n");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converge_lang::parser::parse_program;
+    use converge_lang::validate::validate;
+
+    #[test]
+    fn bundled_networks_are_valid() {
+        for (name, src) in BUNDLED {
+            let program = parse_program(src).unwrap_or_else(|d| panic!("{name}: {d}"));
+            validate(&program).unwrap_or_else(|d| panic!("{name}: {d:?}"));
+            Simulator::new(&program, &SimConfig::default())
+                .unwrap_or_else(|e| panic!("{name}: {e}"));
+        }
+    }
+
+    #[test]
+    fn bench_json_escapes_names() {
+        let result = BenchResult {
+            name: "say \"hi\"\\now".to_string(),
+            runs: 1,
+            steps: 10,
+            spikes: 0,
+            build_ns: 1,
+            min_ns: 1,
+            mean_ns: 1,
+        };
+        let json = converge_lang::json::parse(&bench_json(&[result], None)).expect("valid JSON");
+        let name = json
+            .get("benchmarks")
+            .and_then(|b| b.as_array())
+            .and_then(|b| b[0].get("name"))
+            .and_then(|n| n.as_str());
+        assert_eq!(name, Some("say \"hi\"\\now"));
+    }
+}
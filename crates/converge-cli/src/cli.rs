@@ -0,0 +1,693 @@
+//! The command line: one struct per subcommand, parsed by clap so every
+//! command gets `--help`, typed values and the same error messages. Usage
+//! errors exit with status 2.
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use converge_lang::ast::Expr;
+use converge_lang::parser::parse_param_value;
+use converge_lang::units::time_to_nanos;
+use converge_sim::{Quantize, SimConfig};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "converge",
+    about = "converge: neuromorphic language toolchain (pre-α)",
+    arg_required_else_help = true,
+    after_help = AFTER_HELP
+)]
+pub struct Cli {
+    /// Phase timings and per-step debug events on stderr
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+    /// Report warnings, such as unknown keys, as errors
+    #[arg(long, global = true)]
+    pub strict: bool,
+    /// Ignore `.converge.toml` files
+    #[arg(long, global = true)]
+    pub no_config: bool,
+    /// End stderr with a JSON status line naming the outcome
+    #[arg(long, global = true)]
+    pub status_json: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+const AFTER_HELP: &str = "\
+Pass `-` as the file to read the program from stdin.
+
+Set RUST_LOG (e.g. RUST_LOG=converge_sim=trace) or pass --verbose for
+phase timings and per-step debug events on stderr.
+
+Unknown keys in `neuron` and `connect` bodies are reported as warnings;
+--strict makes them errors.
+
+Default flags come from `~/.converge.toml` and then the nearest
+`.converge.toml` in or above the working directory, unless --no-config is
+given: `strict = true` at the top level, and a table per command with one
+key per flag, e.g. `[sim]` with `out = \"runs/latest.ndjson\"` and
+`kahan = true`. Flags on the command line override them.
+
+EXIT CODES:
+  0 ok, 1 failed (differences, violations, deprecated forms, mismatches),
+  2 usage error, 3 parse error, 4 validation error, 5 simulation error,
+  6 I/O error, 7 assertion failed, 130 interrupted. --status-json ends
+  stderr with e.g. {\"status\": \"validation_error\", \"exit_code\": 4}.
+
+EXAMPLES:
+  cargo run -p converge-cli -- check examples/hello.cv
+  cargo run -p converge-cli -- ast   examples/hello.cv
+  cargo run -p converge-cli -- cvir  examples/hello.cv
+  cargo run -p converge-cli -- sim   examples/poisson.cv
+  cargo run -p converge-cli -- sim   examples/poisson.cv --compare golden.json
+  cargo run -p converge-cli -- estimate examples/poisson.cv
+  gen_model.py | converge sim -";
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Parse + validate Converge files
+    Check(CheckArgs),
+    /// Print the parse tree as JSON (--debug for the Rust dump)
+    Ast(AstArgs),
+    /// List deprecated forms, or rewrite them with --fix
+    Fmt(FmtArgs),
+    /// Emit canonical JSON IR (debug)
+    Cvir(CvirArgs),
+    /// Print the model after parameters, `${...}` and networks are resolved
+    Elaborate(ElaborateArgs),
+    /// Run deterministic simulator
+    Sim(Box<SimArgs>),
+    /// Train the connections into a `readout` and write their weights
+    Train(TrainArgs),
+    /// Report neurons, synapses, memory and work without simulating
+    Estimate(EstimateArgs),
+    /// Run an `experiment` block's seeds and report aggregate metrics
+    Experiment(ExperimentArgs),
+    /// Per-layer degrees and per-connection synapse counts and parameters
+    Stats(StatsArgs),
+    /// Check a program against a hardware profile's limits
+    Target {
+        #[command(subcommand)]
+        command: TargetCommand,
+    },
+    /// Partition layers across a target's cores, as placement JSON
+    Place(PlaceArgs),
+    /// Generate a standalone Rust crate or C99 sources for one network
+    Compile(CompileArgs),
+    /// Item-level differences between two programs
+    Diff(DiffArgs),
+    /// Check that two CVIR files mean the same, or show the first difference
+    CvirEq(CvirEqArgs),
+    /// Step two programs in lockstep and report their first divergence
+    DebugCompare(DebugCompareArgs),
+    /// Time repeated runs of a model or the bundled benchmarks
+    Bench(BenchArgs),
+    /// Self-contained HTML report with topology, parameters and raster
+    Report(ReportArgs),
+    /// Markdown or HTML reference of a model's items and parameters
+    Doc(DocArgs),
+    /// Terminal spike raster and rate sparklines from a spike file
+    Plot(PlotArgs),
+    /// HTTP service: submit programs, poll jobs, fetch summaries
+    Serve(ServeArgs),
+    /// Check and simulate many files in parallel, with an index JSON
+    Batch(BatchArgs),
+    /// gRPC co-simulation server that steps a network on request
+    Cosim(CosimArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// Files or glob patterns
+    #[arg(required = true, value_name = "FILE|GLOB")]
+    pub patterns: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct AstArgs {
+    pub file: String,
+    /// Print the Rust debug dump instead of JSON
+    #[arg(long)]
+    pub debug: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct FmtArgs {
+    pub file: String,
+    /// Rewrite deprecated forms, in place or to stdout for `-`
+    #[arg(long)]
+    pub fix: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CvirArgs {
+    pub file: String,
+    /// Record source spans
+    #[arg(long)]
+    pub with_spans: bool,
+    /// Record stable node ids
+    #[arg(long)]
+    pub with_ids: bool,
+    /// Run the optimization passes first
+    #[arg(long)]
+    pub optimize: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ElaborateArgs {
+    pub file: String,
+    /// The `network` to print on its own, with the items it shares
+    #[arg(long, value_name = "NAME")]
+    pub network: Option<String>,
+    /// Override a `params` value
+    #[arg(long = "set", value_name = "PARAM=VALUE", value_parser = param_override)]
+    pub overrides: Vec<(String, Expr)>,
+    #[arg(long, value_enum, default_value = "cv")]
+    pub format: ElaborateFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ElaborateFormat {
+    /// Canonical `.cv` source
+    Cv,
+    /// Canonical JSON IR
+    Cvir,
+}
+
+/// Simulator flags shared by every command that runs a program.
+#[derive(Debug, Args)]
+pub struct EngineArgs {
+    /// Use compensated summation for synaptic input
+    #[arg(long)]
+    pub kahan: bool,
+}
+
+impl EngineArgs {
+    /// A [`SimConfig`] with these flags and every other setting default.
+    pub fn config(&self) -> SimConfig {
+        SimConfig {
+            compensated_summation: self.kahan,
+            ..SimConfig::default()
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SimArgs {
+    pub file: String,
+    /// Write the summary here instead of stdout
+    #[arg(long, value_name = "PATH")]
+    pub out: Option<String>,
+    /// Write the summary, spikes, learned weights and effective config here
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["out", "spikes"])]
+    pub out_dir: Option<PathBuf>,
+    /// Reuse a non-empty --out-dir
+    #[arg(long, requires = "out_dir")]
+    pub force: bool,
+    /// Write the input, CVIR and output hashes, seeds and platform of the run here
+    #[arg(long, value_name = "MANIFEST.JSON")]
+    pub manifest: Option<PathBuf>,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+    /// Report where the time goes
+    #[arg(long)]
+    pub profile: bool,
+    /// No progress bar
+    #[arg(short, long)]
+    pub quiet: bool,
+    /// The `network` to run, when the file defines several
+    #[arg(long, value_name = "NAME")]
+    pub network: Option<String>,
+    /// Override a `params` value
+    #[arg(long = "set", value_name = "PARAM=VALUE", value_parser = param_override)]
+    pub overrides: Vec<(String, Expr)>,
+    /// Quantize weights, and compare against a float run
+    #[arg(long, value_name = "int8|int16", value_parser = quantize)]
+    pub quantize: Option<Quantize>,
+    /// Use fixed-point arithmetic
+    #[arg(long)]
+    pub fixed_point: bool,
+    /// Estimate energy with a target profile's per-event costs
+    #[arg(long, value_name = "TARGET.TOML")]
+    pub energy_report: Option<String>,
+    /// Spill large recordings to this directory
+    #[arg(long, value_name = "DIR")]
+    pub scratch: Option<PathBuf>,
+    /// Stream spikes to a file, binary for `.cvr`
+    #[arg(long, value_name = "SPIKES.NDJSON|SPIKES.CVR")]
+    pub spikes: Option<String>,
+    /// Draw a spike raster on stderr
+    #[arg(long)]
+    pub plot: bool,
+    /// Record the Poisson input for --replay-input
+    #[arg(long, value_name = "INPUT.CVR")]
+    pub record_input: Option<String>,
+    /// Apply recorded Poisson input instead of drawing new spikes
+    #[arg(long, value_name = "INPUT.CVR")]
+    pub replay_input: Option<String>,
+    /// Write whole-layer state as NDJSON
+    #[arg(long, value_name = "STATE.NDJSON")]
+    pub dump_state: Option<String>,
+    /// Dump every this many steps
+    #[arg(long, value_name = "STEPS", default_value_t = 1, value_parser = positive::<u64>)]
+    pub dump_every: u64,
+    /// Dump only these layers
+    #[arg(long, value_name = "A,B", value_delimiter = ',')]
+    pub dump_layers: Vec<String>,
+    /// Refuse dumps larger than this
+    #[arg(long, value_name = "MiB")]
+    pub dump_limit: Option<u64>,
+    /// Publish spikes to TCP subscribers
+    #[arg(long, value_name = "tcp://HOST:PORT")]
+    pub publish: Option<String>,
+    /// Wait for this many subscribers before running
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub subscribers: usize,
+    /// Write SVG plots to this directory
+    #[arg(long, value_name = "DIR")]
+    pub plot_out: Option<String>,
+    /// Write each connection's final weights as a PGM image to this directory
+    #[arg(long, value_name = "DIR")]
+    pub dump_weight_images: Option<String>,
+    /// Trace the membrane potential of this many neurons per layer
+    #[arg(long, value_name = "N")]
+    pub trace_neurons: Option<usize>,
+    /// Show a live dashboard
+    #[arg(long)]
+    pub tui: bool,
+    /// Fail unless the summary matches a golden one
+    #[arg(long, value_name = "GOLDEN.JSON")]
+    pub compare: Option<String>,
+    /// Relative tolerance for --compare
+    #[arg(long, value_name = "REL", default_value_t = 0.0, value_parser = tolerance)]
+    pub tolerance: f64,
+    /// Analyze the spikes once the run ends
+    #[arg(long, value_enum)]
+    pub analyze: Option<Analysis>,
+    /// Layers to analyze; every layer by default
+    #[arg(long, value_name = "A,B", value_delimiter = ',', requires = "analyze")]
+    pub analyze_layers: Vec<String>,
+    /// Bin width for correlations
+    #[arg(long, value_name = "TIME", default_value = "5 ms", value_parser = time_ns)]
+    pub bin: i64,
+    /// Correlogram lags either side of zero, in bins
+    #[arg(long, value_name = "BINS", default_value_t = 10)]
+    pub max_lag: usize,
+    /// Write the analysis here, CSV for `.csv`, instead of stderr
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "analyze",
+        conflicts_with = "out_dir"
+    )]
+    pub analysis_out: Option<String>,
+    /// Export spikes and traces as Neurodata Without Borders (NWB)
+    #[cfg(feature = "nwb")]
+    #[arg(long, value_name = "PATH")]
+    pub nwb: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Analysis {
+    /// Spike-count correlations and cross-correlograms between layers
+    Correlations,
+}
+
+#[derive(Debug, Args)]
+pub struct TrainArgs {
+    pub file: String,
+    /// Presentations of the dataset
+    #[arg(long, value_name = "N", value_parser = positive::<usize>)]
+    pub epochs: Option<usize>,
+    /// Learning rate
+    #[arg(long, value_name = "RATE", default_value_t = 0.1, value_parser = learning_rate)]
+    pub lr: f64,
+    /// Directory for the weight CSVs
+    #[arg(long, value_name = "DIR", default_value = "weights")]
+    pub out: String,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+    /// No progress bar
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct EstimateArgs {
+    pub file: String,
+    /// Print JSON
+    #[arg(long)]
+    pub json: bool,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExperimentArgs {
+    pub file: String,
+    /// Write the report here instead of stdout
+    #[arg(long, value_name = "PATH")]
+    pub out: Option<String>,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+    /// No per-run lines
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    pub file: String,
+    /// Print JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TargetCommand {
+    /// Report every limit of the profile the program exceeds
+    Check {
+        file: String,
+        #[arg(long, value_name = "TARGET.TOML")]
+        profile: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct PlaceArgs {
+    pub file: String,
+    #[arg(long, value_name = "TARGET.TOML")]
+    pub profile: String,
+    /// Write CVIR and placement JSON here instead of printing the placement
+    #[arg(long, value_name = "DIR")]
+    pub out: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompileTarget {
+    Rust,
+    C,
+}
+
+#[derive(Debug, Args)]
+pub struct CompileArgs {
+    pub file: String,
+    #[arg(long, value_enum)]
+    pub target: CompileTarget,
+    #[arg(long, value_name = "DIR")]
+    pub out: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    #[arg(value_name = "OLD.CV")]
+    pub old: String,
+    #[arg(value_name = "NEW.CV")]
+    pub new: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CvirEqArgs {
+    #[arg(value_name = "A.JSON")]
+    pub a: String,
+    #[arg(value_name = "B.JSON")]
+    pub b: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DebugCompareArgs {
+    #[arg(value_name = "A.CV")]
+    pub a: String,
+    #[arg(value_name = "B.CV")]
+    pub b: String,
+    /// The `network` to run, when the files define several
+    #[arg(long, value_name = "NAME")]
+    pub network: Option<String>,
+    /// Absolute tolerance on potentials
+    #[arg(long, value_name = "ABS", default_value_t = 0.0, value_parser = tolerance)]
+    pub tolerance: f64,
+    /// Give both runs this recorded Poisson input
+    #[arg(long, value_name = "INPUT.CVR")]
+    pub replay_input: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// Defaults to the bundled models
+    pub file: Option<String>,
+    /// Timed runs per model
+    #[arg(long, value_name = "N", default_value_t = 5, value_parser = positive::<usize>)]
+    pub runs: usize,
+    /// Write the results here instead of stdout
+    #[arg(long, value_name = "PATH")]
+    pub out: Option<String>,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    pub file: String,
+    /// Write the report here instead of stdout
+    #[arg(long, value_name = "REPORT.HTML")]
+    pub out: Option<String>,
+    /// Leave out the spike raster
+    #[arg(long)]
+    pub no_raster: bool,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DocArgs {
+    pub file: String,
+    /// Write `<file stem>.md` or `.html` into this directory instead of
+    /// stdout
+    #[arg(long, value_name = "DIR")]
+    pub out: Option<String>,
+    #[arg(long, value_enum, default_value = "markdown")]
+    pub format: DocFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DocFormat {
+    Markdown,
+    /// A self-contained page
+    Html,
+}
+
+#[derive(Debug, Args)]
+pub struct PlotArgs {
+    #[arg(value_name = "SPIKES.NDJSON|SPIKES.CVR")]
+    pub file: String,
+    /// Columns; defaults to COLUMNS or 80
+    #[arg(long, value_name = "COLS", value_parser = positive::<usize>)]
+    pub width: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1")]
+    pub host: String,
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+    /// Worker threads; defaults to one per core
+    #[arg(long, value_name = "N", value_parser = positive::<usize>)]
+    pub workers: Option<usize>,
+    /// Let jobs read data files from inside DIR; without it they read none
+    #[arg(long, value_name = "DIR")]
+    pub data_dir: Option<PathBuf>,
+    /// Run jobs with `External` stimuli, which open local ports
+    #[arg(long)]
+    pub allow_external: bool,
+    /// Refuse programs with more neurons than this
+    #[arg(long, value_name = "N", default_value_t = 1_000_000, value_parser = positive::<u64>)]
+    pub max_neurons: u64,
+    /// Refuse programs with more synapses than this
+    #[arg(long, value_name = "N", default_value_t = 100_000_000, value_parser = positive::<u64>)]
+    pub max_synapses: u64,
+    /// Refuse programs estimated to need more memory than this
+    #[arg(long, value_name = "MiB", default_value_t = 4096, value_parser = positive::<u64>)]
+    pub max_memory: u64,
+    /// Refuse programs that run for more steps than this
+    #[arg(long, value_name = "N", default_value_t = 10_000_000, value_parser = positive::<u64>)]
+    pub max_steps: u64,
+    /// Finished jobs to keep; older ones are forgotten
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    pub keep_jobs: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+    /// Files or glob patterns
+    #[arg(required = true, value_name = "FILE|GLOB")]
+    pub patterns: Vec<String>,
+    /// Parallel runs; defaults to one per core
+    #[arg(short, long, value_name = "N", value_parser = positive::<usize>)]
+    pub jobs: Option<usize>,
+    /// Directory for the summaries and `index.json`
+    #[arg(long, value_name = "DIR", default_value = "results")]
+    pub out: String,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CosimArgs {
+    pub file: String,
+    #[arg(long, value_name = "HOST:PORT", default_value = "127.0.0.1:50051", value_parser = listen_addr)]
+    pub listen: SocketAddr,
+    /// Saved states to keep before `SaveState` fails
+    #[arg(long, value_name = "N", default_value_t = 16, value_parser = positive::<usize>)]
+    pub max_states: usize,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+/// [`Cli::command`](clap::CommandFactory::command) with every (sub)command
+/// letting a flag repeat, the last one winning, so command-line flags can
+/// override the `.converge.toml` defaults placed before them.
+pub fn command() -> clap::Command {
+    fn override_self(cmd: clap::Command) -> clap::Command {
+        cmd.args_override_self(true).mut_subcommands(override_self)
+    }
+    override_self(<Cli as clap::CommandFactory>::command())
+}
+
+fn positive<T: std::str::FromStr + Default + PartialOrd>(value: &str) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err("expected a positive whole number".to_string()),
+    }
+}
+
+fn tolerance(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(t) if t >= 0.0 => Ok(t),
+        _ => Err("expected a non-negative number".to_string()),
+    }
+}
+
+fn learning_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err("expected a positive number".to_string()),
+    }
+}
+
+/// A positive time such as `5 ms`, in nanoseconds.
+fn time_ns(value: &str) -> Result<i64, String> {
+    let expected = || "expected a positive time, like `5 ms`".to_string();
+    match parse_param_value(value) {
+        Ok(Expr::Number(q)) => match time_to_nanos(&q, "time") {
+            Ok(ns) if ns > 0 => Ok(ns),
+            _ => Err(expected()),
+        },
+        _ => Err(expected()),
+    }
+}
+
+fn quantize(value: &str) -> Result<Quantize, String> {
+    Quantize::parse(value).ok_or_else(|| "expected int8 or int16".to_string())
+}
+
+fn listen_addr(value: &str) -> Result<SocketAddr, String> {
+    value
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("invalid listen address `{value}`"))
+}
+
+/// `name=value` from `--set`.
+fn param_override(arg: &str) -> Result<(String, Expr), String> {
+    let Some((name, value)) = arg.split_once('=') else {
+        return Err("expected `name=value`".to_string());
+    };
+    let name = name.trim();
+    match parse_param_value(value) {
+        Ok(value) => Ok((name.to_string(), value)),
+        Err(diag) => Err(format!(
+            "invalid value for parameter `{name}`: {}",
+            diag.message
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::FromArgMatches;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        let matches = command()
+            .try_get_matches_from(std::iter::once("converge").chain(args.iter().copied()))?;
+        Cli::from_arg_matches(&matches)
+    }
+
+    #[test]
+    fn flags_parse_in_any_order_and_later_ones_win() {
+        let cli = parse(&[
+            "sim", "--out", "a.json", "--strict", "m.cv", "-q", "--out", "b.json",
+        ])
+        .unwrap();
+        assert!(cli.strict);
+        let Command::Sim(sim) = cli.command else {
+            panic!("expected sim");
+        };
+        assert_eq!(sim.file, "m.cv");
+        assert_eq!(sim.out.as_deref(), Some("b.json"));
+        assert!(sim.quiet);
+
+        let err = parse(&["sim", "m.cv", "--out"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::InvalidValue);
+        let err = parse(&["sim", "m.cv", "--dump-every", "0"]).unwrap_err();
+        assert!(
+            err.to_string().contains("expected a positive whole number"),
+            "{err}"
+        );
+        let err = parse(&["sim", "m.cv", "--set", "g"]).unwrap_err();
+        assert!(err.to_string().contains("expected `name=value`"), "{err}");
+        let err =
+            parse(&["sim", "m.cv", "--analyze", "correlations", "--bin", "5 Hz"]).unwrap_err();
+        assert!(
+            err.to_string().contains("expected a positive time"),
+            "{err}"
+        );
+        let Command::Sim(sim) = parse(&["sim", "m.cv", "--bin", "2.5 ms"]).unwrap().command else {
+            panic!("expected sim");
+        };
+        assert_eq!(sim.bin, 2_500_000);
+        command().debug_assert();
+    }
+
+    #[test]
+    fn every_simulating_command_takes_the_engine_flags() {
+        for name in [
+            "sim",
+            "train",
+            "estimate",
+            "experiment",
+            "bench",
+            "report",
+            "batch",
+            "cosim",
+        ] {
+            let cli = parse(&[name, "m.cv", "--kahan"]).unwrap();
+            let engine = match &cli.command {
+                Command::Sim(args) => &args.engine,
+                Command::Train(args) => &args.engine,
+                Command::Estimate(args) => &args.engine,
+                Command::Experiment(args) => &args.engine,
+                Command::Bench(args) => &args.engine,
+                Command::Report(args) => &args.engine,
+                Command::Batch(args) => &args.engine,
+                Command::Cosim(args) => &args.engine,
+                _ => panic!("expected {name}"),
+            };
+            assert!(engine.config().compensated_summation, "{name}");
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! `.converge.toml`: default flags, so long invocations live in the project
+//! instead of being copied between shells. Top-level `strict`, `verbose` and
+//! `status-json` apply to every command; a table named after a command gives that
+//! command's flags, one key per flag:
+//!
+//! ```toml
+//! strict = true
+//!
+//! [sim]
+//! out = "runs/latest.ndjson"   # --out runs/latest.ndjson
+//! kahan = true                 # --kahan
+//! set = ["g=0.5", "dur=2s"]    # --set g=0.5 --set dur=2s
+//! ```
+//!
+//! The user file `~/.converge.toml` is read first, then the nearest
+//! `.converge.toml` in the working directory or above it, whose keys replace
+//! the user file's. The defaults go before the command line's own flags, so
+//! a flag given there wins. `--no-config` skips both files.
+
+use std::path::{Path, PathBuf};
+
+use converge_lang::json::Value;
+use converge_lang::parser::format_diagnostic;
+use converge_lang::toml;
+
+pub const FILE_NAME: &str = ".converge.toml";
+
+/// Keys that may appear outside a command table, as the global flag each
+/// stands for.
+const GLOBAL_FLAGS: &[(&str, &str)] = &[
+    ("strict", "--strict"),
+    ("verbose", "--verbose"),
+    ("status-json", "--status-json"),
+];
+
+/// The default arguments for `cmd` from the config files that apply in
+/// `dir`, with `home` the user's home directory.
+pub fn default_args(cmd: &str, dir: &Path, home: Option<&Path>) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    if let Some(home) = home {
+        files.push(home.join(FILE_NAME));
+    }
+    if let Some(project) = find_project_file(dir)
+        && !files.contains(&project)
+    {
+        files.push(project);
+    }
+
+    let mut global: Vec<(String, Value)> = Vec::new();
+    let mut command: Vec<(String, Value)> = Vec::new();
+    for path in files {
+        let src = match std::fs::read_to_string(&path) {
+            Ok(src) => src,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("failed to read `{}`: {e}", path.display())),
+        };
+        let doc = toml::parse(&src).map_err(|diag| {
+            format!(
+                "in `{}`:\n{}",
+                path.display(),
+                format_diagnostic(&src, &diag)
+            )
+        })?;
+        let Value::Object(fields) = doc else {
+            unreachable!("TOML documents are tables");
+        };
+        for (key, value) in fields {
+            if let Value::Object(flags) = value {
+                if key == cmd {
+                    for (flag, value) in flags {
+                        set(&mut command, flag, value);
+                    }
+                }
+            } else if GLOBAL_FLAGS.iter().any(|(k, _)| *k == key) {
+                set(&mut global, key, value);
+            } else {
+                return Err(format!(
+                    "in `{}`: unknown key `{key}`; put command flags under a `[command]` table",
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    let mut args = Vec::new();
+    for (key, value) in &global {
+        let Value::Bool(on) = value else {
+            return Err(format!("config key `{key}` must be true or false"));
+        };
+        if *on {
+            let (_, flag) = GLOBAL_FLAGS
+                .iter()
+                .find(|(k, _)| k == key)
+                .expect("checked");
+            args.push(flag.to_string());
+        }
+    }
+    for (key, value) in &command {
+        push_flag(&mut args, cmd, key, value)?;
+    }
+    Ok(args)
+}
+
+fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+fn set(fields: &mut Vec<(String, Value)>, key: String, value: Value) {
+    match fields.iter_mut().find(|(k, _)| *k == key) {
+        Some(field) => field.1 = value,
+        None => fields.push((key, value)),
+    }
+}
+
+fn push_flag(args: &mut Vec<String>, cmd: &str, key: &str, value: &Value) -> Result<(), String> {
+    let flag = format!("--{key}");
+    match value {
+        Value::Bool(true) => args.push(flag),
+        Value::Bool(false) => {}
+        Value::String(s) => args.extend([flag, s.clone()]),
+        Value::Number(n) => args.extend([flag, n.to_string()]),
+        Value::Array(items) => {
+            for item in items {
+                if matches!(item, Value::Array(_) | Value::Object(_) | Value::Bool(_)) {
+                    return Err(format!(
+                        "config key `{cmd}.{key}` must be a list of strings or numbers"
+                    ));
+                }
+                push_flag(args, cmd, key, item)?;
+            }
+        }
+        Value::Null | Value::Object(_) => {
+            return Err(format!(
+                "config key `{cmd}.{key}` must be a string, number, boolean or list"
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("converge-config-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn project_keys_override_user_keys() {
+        let home = temp_dir("home");
+        let project = temp_dir("project");
+        let nested = project.join("models");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(
+            home.join(FILE_NAME),
+            "verbose = true\n[sim]\nout = \"home.ndjson\"\nkahan = true\n",
+        )
+        .unwrap();
+        std::fs::write(
+            project.join(FILE_NAME),
+            "strict = true\n[sim]\nout = \"runs/latest.ndjson\"\nset = [\"g=0.5\", \"dur=2s\"]\ndump-every = 10\n\n[check]\n",
+        )
+        .unwrap();
+
+        let args = default_args("sim", &nested, Some(&home)).unwrap();
+        assert_eq!(
+            args,
+            [
+                "--verbose",
+                "--strict",
+                "--out",
+                "runs/latest.ndjson",
+                "--kahan",
+                "--set",
+                "g=0.5",
+                "--set",
+                "dur=2s",
+                "--dump-every",
+                "10",
+            ]
+        );
+        assert_eq!(
+            default_args("stats", &nested, Some(&home)).unwrap(),
+            ["--verbose", "--strict"]
+        );
+
+        std::fs::write(project.join(FILE_NAME), "out = \"x\"\n").unwrap();
+        let err = default_args("sim", &project, None).unwrap_err();
+        assert!(
+            err.ends_with("unknown key `out`; put command flags under a `[command]` table"),
+            "{err}"
+        );
+        std::fs::write(project.join(FILE_NAME), "[sim]\nout = { path = \"x\" }\n").unwrap();
+        let err = default_args("sim", &project, None).unwrap_err();
+        assert_eq!(
+            err,
+            "config key `sim.out` must be a string, number, boolean or list"
+        );
+    }
+}
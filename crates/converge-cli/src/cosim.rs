@@ -0,0 +1,355 @@
+//! `converge cosim <file>`: a gRPC server that steps one network on request,
+//! so Converge can run in lockstep with other simulators.
+//!
+//! The service is `converge.cosim.v1.Cosim` from `proto/cosim.proto`; point
+//! other frameworks' code generators at that file. `build.rs` generates the
+//! messages and the server and client stubs from it too. Layers are addressed by name, and `Spike.layer` indexes the
+//! layers of the `Info` reply.
+//!
+//! Saved states are kept in memory until the server exits, at most
+//! `max_states` of them, and `LoadState` only restores the network, not the
+//! spikes a client has already read.
+
+use std::error::Error;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use converge_sim::{RecordSink, RunInfo, SimError, Simulator, Snapshot};
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+
+include!(concat!(env!("OUT_DIR"), "/cosim_messages.rs"));
+include!(concat!(env!("OUT_DIR"), "/converge.cosim.v1.Cosim.rs"));
+
+use cosim_server::{Cosim, CosimServer};
+
+/// Spikes of the steps run since the last `Step` reply.
+#[derive(Clone, Default)]
+struct Collector(Arc<Mutex<Vec<converge_sim::Spike>>>);
+
+impl RecordSink for Collector {
+    fn on_spike(&mut self, spike: &converge_sim::Spike) -> io::Result<()> {
+        self.0.lock().expect("spike lock").push(*spike);
+        Ok(())
+    }
+}
+
+struct Session {
+    sim: Simulator,
+    run: RunInfo,
+    spikes: Collector,
+    /// Saved states; the id of `saved[i]` is `i + 1`.
+    saved: Vec<Snapshot>,
+    max_states: usize,
+}
+
+pub struct CosimService {
+    /// Shared with the blocking task that runs `Step`.
+    session: Arc<Mutex<Session>>,
+}
+
+impl CosimService {
+    /// Serves `sim`, refusing `SaveState` once `max_states` states are kept.
+    pub fn new(mut sim: Simulator, max_states: usize) -> Result<Self, SimError> {
+        let spikes = Collector::default();
+        sim.add_sink(Box::new(spikes.clone()))?;
+        let layers = (0..sim.layer_count())
+            .map(|i| {
+                let layer = sim.layer(i);
+                (layer.name.to_string(), layer.membrane.len() as u64)
+            })
+            .collect();
+        let run = RunInfo {
+            duration_ns: sim.duration_ns(),
+            step_ns: sim.step_ns(),
+            seed: sim.seed(),
+            layers,
+            // The session only streams spikes.
+            probes: Vec::new(),
+        };
+        Ok(Self {
+            session: Arc::new(Mutex::new(Session {
+                sim,
+                run,
+                spikes,
+                saved: Vec::new(),
+                max_states,
+            })),
+        })
+    }
+
+    fn session(&self) -> MutexGuard<'_, Session> {
+        self.session.lock().expect("session lock")
+    }
+}
+
+impl Session {
+    fn layer(&self, name: &str) -> Result<usize, Status> {
+        self.run
+            .layers
+            .iter()
+            .position(|(layer, _)| layer == name)
+            .ok_or_else(|| Status::not_found(format!("unknown layer `{name}`")))
+    }
+
+    fn step(&mut self, steps: u64) -> Result<StepReply, Status> {
+        for _ in 0..steps {
+            if self.sim.is_finished() {
+                break;
+            }
+            self.sim
+                .step()
+                .map_err(|e| Status::internal(e.to_string()))?;
+        }
+        let spikes = std::mem::take(&mut *self.spikes.0.lock().expect("spike lock"));
+        Ok(StepReply {
+            current_step: self.sim.current_step(),
+            finished: self.sim.is_finished(),
+            spikes: spikes
+                .into_iter()
+                .map(|s| Spike {
+                    step: s.step,
+                    layer: s.layer,
+                    neuron: s.neuron,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Cosim for CosimService {
+    async fn info(&self, _request: Request<InfoRequest>) -> Result<Response<InfoReply>, Status> {
+        let session = self.session();
+        let run = &session.run;
+        Ok(Response::new(InfoReply {
+            duration_ns: run.duration_ns,
+            step_ns: run.step_ns,
+            seed: run.seed,
+            total_steps: session.sim.total_steps(),
+            current_step: session.sim.current_step(),
+            layers: run
+                .layers
+                .iter()
+                .map(|(name, size)| Layer {
+                    name: name.clone(),
+                    size: *size,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn step(&self, request: Request<StepRequest>) -> Result<Response<StepReply>, Status> {
+        let steps = request.into_inner().steps;
+        let session = Arc::clone(&self.session);
+        // A long run would otherwise hold up the runtime's worker thread.
+        let reply =
+            tokio::task::spawn_blocking(move || session.lock().expect("session lock").step(steps))
+                .await
+                .map_err(|e| Status::internal(e.to_string()))??;
+        Ok(Response::new(reply))
+    }
+
+    async fn inject(
+        &self,
+        request: Request<InjectRequest>,
+    ) -> Result<Response<InjectReply>, Status> {
+        let request = request.into_inner();
+        let mut session = self.session();
+        let layer = session.layer(&request.layer)?;
+        session
+            .sim
+            .inject(layer, &request.currents)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        Ok(Response::new(InjectReply {}))
+    }
+
+    async fn read_voltages(
+        &self,
+        request: Request<ReadVoltagesRequest>,
+    ) -> Result<Response<ReadVoltagesReply>, Status> {
+        let session = self.session();
+        let layer = session.layer(&request.into_inner().layer)?;
+        Ok(Response::new(ReadVoltagesReply {
+            current_step: session.sim.current_step(),
+            membrane: session.sim.layer(layer).membrane.to_vec(),
+        }))
+    }
+
+    async fn save_state(
+        &self,
+        _request: Request<SaveStateRequest>,
+    ) -> Result<Response<SaveStateReply>, Status> {
+        let mut session = self.session();
+        if session.saved.len() >= session.max_states {
+            return Err(Status::resource_exhausted(format!(
+                "already keeping {} saved states",
+                session.max_states
+            )));
+        }
+        let snapshot = session.sim.snapshot();
+        let step = snapshot.step();
+        session.saved.push(snapshot);
+        Ok(Response::new(SaveStateReply {
+            state_id: session.saved.len() as u64,
+            step,
+        }))
+    }
+
+    async fn load_state(
+        &self,
+        request: Request<LoadStateRequest>,
+    ) -> Result<Response<LoadStateReply>, Status> {
+        let id = request.into_inner().state_id;
+        let mut session = self.session();
+        let Session { sim, saved, .. } = &mut *session;
+        let snapshot = (id as usize)
+            .checked_sub(1)
+            .and_then(|i| saved.get(i))
+            .ok_or_else(|| Status::not_found(format!("no saved state {id}")))?;
+        sim.restore(snapshot)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(LoadStateReply {
+            current_step: sim.current_step(),
+        }))
+    }
+}
+
+/// Serves `sim` on `addr` until the process is stopped.
+pub fn serve(sim: Simulator, addr: SocketAddr, max_states: usize) -> Result<(), Box<dyn Error>> {
+    let service = CosimService::new(sim, max_states)?;
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let incoming = TcpIncoming::bind(addr)?;
+        eprintln!("listening on grpc://{}", incoming.local_addr()?);
+        Server::builder()
+            .add_service(CosimServer::new(service))
+            .serve_with_incoming(incoming)
+            .await?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cosim_client::CosimClient;
+    use super::*;
+    use converge_lang::parser::parse_program;
+    use converge_sim::SimConfig;
+
+    const SRC: &str = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+                       layer In[4] : LIF\n\
+                       layer Out[2] : LIF\n\
+                       connect In -> Out { w = 0.6, d = 1 ms }\n\
+                       stimulus In = Poisson(rate=300 Hz)\n\
+                       seed 2\n\
+                       run for 20 ms step 1 ms\n";
+
+    #[test]
+    fn steps_injects_and_restores_over_grpc() {
+        let sim = Simulator::new(&parse_program(SRC).unwrap(), &SimConfig::default()).unwrap();
+        let service = CosimService::new(sim, 1).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let addr = incoming.local_addr().unwrap();
+            tokio::spawn(
+                Server::builder()
+                    .add_service(CosimServer::new(service))
+                    .serve_with_incoming(incoming),
+            );
+            let mut client = CosimClient::connect(format!("http://{addr}"))
+                .await
+                .unwrap();
+
+            let info = client.info(InfoRequest {}).await.unwrap().into_inner();
+            assert_eq!(
+                (info.step_ns, info.total_steps, info.seed),
+                (1_000_000, 20, 2)
+            );
+            assert_eq!(
+                info.layers,
+                [
+                    Layer {
+                        name: "In".to_string(),
+                        size: 4
+                    },
+                    Layer {
+                        name: "Out".to_string(),
+                        size: 2
+                    }
+                ]
+            );
+
+            let saved = client.save_state(SaveStateRequest {}).await.unwrap();
+            assert_eq!(saved.get_ref().step, 0);
+            let first = client.step(StepRequest { steps: 12 }).await.unwrap();
+            let first = first.into_inner();
+            assert_eq!((first.current_step, first.finished), (12, false));
+            assert!(!first.spikes.is_empty());
+
+            // Replaying from the saved state yields the same spikes.
+            let id = saved.get_ref().state_id;
+            let loaded = client.load_state(LoadStateRequest { state_id: id }).await;
+            assert_eq!(loaded.unwrap().get_ref().current_step, 0);
+            let again = client.step(StepRequest { steps: 12 }).await.unwrap();
+            assert_eq!(again.into_inner().spikes, first.spikes);
+
+            // `Out` neuron 1 crosses threshold from the injected current.
+            client
+                .inject(InjectRequest {
+                    layer: "Out".to_string(),
+                    currents: vec![0.0, 5.0],
+                })
+                .await
+                .unwrap();
+            let step = client.step(StepRequest { steps: 1 }).await.unwrap();
+            assert!(step.get_ref().spikes.contains(&Spike {
+                step: 12,
+                layer: 1,
+                neuron: 1
+            }));
+            let voltages = client
+                .read_voltages(ReadVoltagesRequest {
+                    layer: "Out".to_string(),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(voltages.current_step, 13);
+            assert_eq!(voltages.membrane.len(), 2);
+            assert_eq!(voltages.membrane[1], 0.0);
+
+            let done = client.step(StepRequest { steps: 100 }).await.unwrap();
+            assert_eq!(
+                (done.get_ref().current_step, done.get_ref().finished),
+                (20, true)
+            );
+
+            let unknown = client
+                .read_voltages(ReadVoltagesRequest {
+                    layer: "Hidden".to_string(),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(unknown.code(), tonic::Code::NotFound);
+            let wrong = client
+                .inject(InjectRequest {
+                    layer: "Out".to_string(),
+                    currents: vec![1.0],
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(wrong.code(), tonic::Code::InvalidArgument);
+            assert_eq!(wrong.message(), "layer `Out` has 2 neurons, got 1 currents");
+            let missing = client
+                .load_state(LoadStateRequest { state_id: 7 })
+                .await
+                .unwrap_err();
+            assert_eq!(missing.message(), "no saved state 7");
+            let full = client.save_state(SaveStateRequest {}).await.unwrap_err();
+            assert_eq!(full.code(), tonic::Code::ResourceExhausted);
+        });
+    }
+}
@@ -0,0 +1,314 @@
+//! `converge doc`: a reference page for a model, in Markdown or HTML, with
+//! every neuron type, layer, connection and stimulus, its doc comment and
+//! attributes, and its parameters both as written and in canonical units
+//! (`ns` and `Hz`, as CVIR comparison uses them).
+
+use std::fmt::Write;
+
+use converge_lang::ast::{Call, EncodeScheme, Item, Program, StimulusModel};
+use converge_lang::opt::{FoldQuantities, Pass};
+
+use crate::cli::DocFormat;
+use crate::svg::escape;
+
+/// The page for `program`, titled `title`. Items inside a `network` get a
+/// section of their own after the shared ones.
+pub fn render_doc(title: &str, program: &Program, format: DocFormat) -> String {
+    let mut canonical = program.clone();
+    FoldQuantities.run(&mut canonical);
+    let mut page = Page {
+        format,
+        out: String::new(),
+    };
+    if format == DocFormat::Html {
+        let _ = writeln!(
+            page.out,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>",
+            escape(title)
+        );
+    }
+    page.heading(1, title);
+    page.items(&program.items, &canonical.items, 2);
+    for (item, folded) in program.items.iter().zip(&canonical.items) {
+        if let (Item::Network(def), Item::Network(folded)) = (item, folded) {
+            page.heading(2, &format!("Network `{}`", def.name.name));
+            page.items(&def.items, &folded.items, 3);
+        }
+    }
+    if format == DocFormat::Html {
+        page.out.push_str("</body>\n</html>\n");
+    }
+    page.out
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 960px; margin: 2em auto; color: #222; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { padding: 0.25em 0.8em; border-bottom: 1px solid #eee; text-align: left; }
+code { background: #f4f4f4; padding: 0 0.2em; }
+.doc { white-space: pre-line; }
+";
+
+struct Page {
+    format: DocFormat,
+    out: String,
+}
+
+impl Page {
+    /// The sections for `items`, with `folded` the same items with their
+    /// quantities in canonical units. Sections without items are left out.
+    fn items(&mut self, items: &[Item], folded: &[Item], level: usize) {
+        let pairs = || items.iter().zip(folded);
+        if pairs().any(|(item, _)| matches!(item, Item::Neuron(_))) {
+            self.heading(level, "Neurons");
+        }
+        for pair in pairs() {
+            let (Item::Neuron(def), Item::Neuron(folded)) = pair else {
+                continue;
+            };
+            self.heading(level + 1, &format!("`{}`", def.name.name));
+            self.preamble(def.doc.as_deref(), &def.attributes);
+            self.parameters(
+                def.body
+                    .iter()
+                    .zip(&folded.body)
+                    .map(|(a, f)| (a.key.name.clone(), a.value.to_string(), f.value.to_string()))
+                    .collect(),
+            );
+        }
+
+        if pairs().any(|(item, _)| matches!(item, Item::Layer(_))) {
+            self.heading(level, "Layers");
+        }
+        for (item, _) in pairs() {
+            let Item::Layer(def) = item else {
+                continue;
+            };
+            self.heading(level + 1, &format!("`{}`", def.name.name));
+            self.preamble(def.doc.as_deref(), &def.attributes);
+            self.paragraph(&format!("{} × {}", def.size, self.code(&def.neuron.name)));
+        }
+
+        if pairs().any(|(item, _)| matches!(item, Item::Connect(_))) {
+            self.heading(level, "Connections");
+        }
+        for pair in pairs() {
+            let (Item::Connect(def), Item::Connect(folded)) = pair else {
+                continue;
+            };
+            self.heading(level + 1, &format!("`{}`", def.label()));
+            self.preamble(def.doc.as_deref(), &def.attributes);
+            self.parameters(
+                def.body
+                    .iter()
+                    .zip(&folded.body)
+                    .map(|(a, f)| (a.key.name.clone(), a.value.to_string(), f.value.to_string()))
+                    .collect(),
+            );
+        }
+
+        if pairs().any(|(item, _)| matches!(item, Item::Stimulus(_))) {
+            self.heading(level, "Stimuli");
+        }
+        for pair in pairs() {
+            let (Item::Stimulus(def), Item::Stimulus(folded)) = pair else {
+                continue;
+            };
+            let (kind, written) = stimulus_parameters(&def.model);
+            let (_, canonical) = stimulus_parameters(&folded.model);
+            self.heading(level + 1, &format!("`{}`: {kind}", def.layer.name));
+            if let Some(combine) = def.combine {
+                self.paragraph(&format!(
+                    "Combines with the earlier stimuli on the layer by {}.",
+                    self.code(combine.as_str())
+                ));
+            }
+            self.parameters(
+                written
+                    .into_iter()
+                    .zip(canonical)
+                    .map(|((key, value), (_, folded))| (key.to_string(), value, folded))
+                    .collect(),
+            );
+        }
+    }
+
+    fn heading(&mut self, level: usize, text: &str) {
+        let _ = match self.format {
+            DocFormat::Markdown => writeln!(self.out, "{} {text}\n", "#".repeat(level)),
+            DocFormat::Html => writeln!(self.out, "<h{level}>{}</h{level}>", inline_code(text)),
+        };
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        let _ = match self.format {
+            DocFormat::Markdown => writeln!(self.out, "{text}\n"),
+            DocFormat::Html => writeln!(self.out, "<p>{text}</p>"),
+        };
+    }
+
+    /// `text` as inline code, escaped for the format.
+    fn code(&self, text: &str) -> String {
+        match self.format {
+            DocFormat::Markdown => format!("`{}`", text.replace('|', "\\|")),
+            DocFormat::Html => format!("<code>{}</code>", escape(text)),
+        }
+    }
+
+    /// An item's doc comment and its attributes, one line each.
+    fn preamble(&mut self, doc: Option<&str>, attributes: &[Call]) {
+        if let Some(doc) = doc {
+            let _ = match self.format {
+                DocFormat::Markdown => writeln!(self.out, "{doc}\n"),
+                DocFormat::Html => writeln!(self.out, "<p class=\"doc\">{}</p>", escape(doc)),
+            };
+        }
+        if !attributes.is_empty() {
+            let attributes: Vec<String> = attributes
+                .iter()
+                .map(|a| {
+                    if a.args.is_empty() {
+                        self.code(&format!("@{}", a.name.name))
+                    } else {
+                        self.code(&format!("@{a}"))
+                    }
+                })
+                .collect();
+            self.paragraph(&format!("Attributes: {}", attributes.join(" ")));
+        }
+    }
+
+    /// A table of `(key, as written, canonical)` rows, or nothing when
+    /// there are none.
+    fn parameters(&mut self, rows: Vec<(String, String, String)>) {
+        if rows.is_empty() {
+            return;
+        }
+        match self.format {
+            DocFormat::Markdown => {
+                self.out
+                    .push_str("| parameter | value | canonical |\n|---|---|---|\n");
+                for (key, value, canonical) in rows {
+                    let _ = writeln!(
+                        self.out,
+                        "| {} | {} | {} |",
+                        self.code(&key),
+                        self.code(&value),
+                        self.code(&canonical)
+                    );
+                }
+                self.out.push('\n');
+            }
+            DocFormat::Html => {
+                self.out.push_str(
+                    "<table>\n<tr><th>parameter</th><th>value</th><th>canonical</th></tr>\n",
+                );
+                for (key, value, canonical) in rows {
+                    let _ = writeln!(
+                        self.out,
+                        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                        self.code(&key),
+                        self.code(&value),
+                        self.code(&canonical)
+                    );
+                }
+                self.out.push_str("</table>\n");
+            }
+        }
+    }
+}
+
+/// `text` escaped for HTML, with `` `...` `` spans as `<code>`.
+fn inline_code(text: &str) -> String {
+    text.split('`')
+        .enumerate()
+        .map(|(idx, part)| {
+            if idx % 2 == 1 {
+                format!("<code>{}</code>", escape(part))
+            } else {
+                escape(part)
+            }
+        })
+        .collect()
+}
+
+/// The stimulus model's name and its arguments, as written.
+fn stimulus_parameters(model: &StimulusModel) -> (&'static str, Vec<(&'static str, String)>) {
+    match model {
+        StimulusModel::Poisson { rate, correlation } => {
+            let mut params = vec![("rate", rate.to_string())];
+            if let Some(c) = correlation {
+                params.push(("correlation", c.to_string()));
+            }
+            ("Poisson", params)
+        }
+        StimulusModel::Encode(enc) => {
+            let mut params = vec![("file", enc.path.clone())];
+            match &enc.scheme {
+                EncodeScheme::Rate { max_rate } => {
+                    params.push(("scheme", "rate".to_string()));
+                    params.push(("max_rate", max_rate.to_string()));
+                }
+                EncodeScheme::Latency => params.push(("scheme", "latency".to_string())),
+            }
+            params.push(("present", enc.present.to_string()));
+            ("Encode", params)
+        }
+        StimulusModel::External(ext) => (
+            "External",
+            vec![
+                ("port", ext.port.to_string()),
+                ("input", ext.input.as_str().to_string()),
+                ("timeout", ext.timeout.to_string()),
+                ("on_timeout", ext.on_timeout.as_str().to_string()),
+            ],
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converge_lang::parser::parse_program;
+
+    const SRC: &str = "/// Leaky integrate-and-fire.\n\
+                       @tag(\"excitatory\")\n\
+                       neuron LIF { tau_m = 20 ms, v_th = 1 }\n\
+                       /// Sensory input.\n\
+                       layer In[8] : LIF\n\
+                       network Small {\n\
+                           layer Out[4] : LIF\n\
+                           connect ff: In -> Out { w = 0.5, d = 1 ms }\n\
+                       }\n\
+                       stimulus In = Poisson(rate=0.2 kHz)\n\
+                       run for 10 ms\n";
+
+    #[test]
+    fn markdown_lists_items_with_canonical_parameters() {
+        let program = parse_program(SRC).expect("parse");
+        let page = render_doc("model.cv", &program, DocFormat::Markdown);
+        assert!(page.starts_with("# model.cv\n\n## Neurons\n\n### `LIF`\n\n"));
+        assert!(page.contains("Leaky integrate-and-fire.\n\nAttributes: `@tag(\"excitatory\")`\n"));
+        assert!(page.contains("| `tau_m` | `20 ms` | `20000000 ns` |\n"));
+        assert!(page.contains("### `In`\n\nSensory input.\n\n8 × `LIF`\n"));
+        assert!(page.contains("### `In`: Poisson\n\n"));
+        assert!(page.contains("| `rate` | `0.2 kHz` | `200 Hz` |\n"));
+        let network = page.find("## Network `Small`").expect("network section");
+        let connection = page.find("#### `ff: In -> Out`").expect("connection");
+        assert!(network < connection);
+        assert!(page.contains("| `d` | `1 ms` | `1000000 ns` |\n"));
+    }
+
+    #[test]
+    fn html_escapes_text() {
+        let program = parse_program(SRC).expect("parse");
+        let page = render_doc("a<b>.cv", &program, DocFormat::Html);
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<title>a&lt;b&gt;.cv</title>"));
+        assert!(page.contains("<h4><code>ff: In -&gt; Out</code></h4>"));
+        assert!(page.contains(
+            "<td><code>rate</code></td><td><code>0.2 kHz</code></td><td><code>200 Hz</code></td>"
+        ));
+        assert!(page.ends_with("</html>\n"));
+    }
+}
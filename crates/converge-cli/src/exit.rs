@@ -0,0 +1,117 @@
+//! Exit codes, one per kind of failure, so scripts can branch on why a
+//! command failed instead of matching its messages. With `--status-json`
+//! the last line on stderr is a JSON object naming the outcome:
+//!
+//! ```text
+//! {"status": "validation_error", "exit_code": 4}
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Ok,
+    /// The command ran and answered "no": differences, violations,
+    /// deprecated forms, a golden mismatch or failed batch inputs.
+    Failed,
+    /// Bad flags or `.converge.toml`.
+    Usage,
+    /// An input did not parse: a model, CVIR, a target profile or a
+    /// recording.
+    Parse,
+    /// A model parsed but failed validation.
+    Invalid,
+    /// The simulator, or code generation from it, returned an error.
+    Simulation,
+    /// A file could not be read or written, or a socket not opened.
+    Io,
+    /// The run completed but an `assert` did not hold.
+    Assertion,
+    /// The user quit the `--tui` dashboard.
+    Interrupted,
+}
+
+impl Exit {
+    pub fn code(self) -> i32 {
+        match self {
+            Exit::Ok => 0,
+            Exit::Failed => 1,
+            Exit::Usage => 2,
+            Exit::Parse => 3,
+            Exit::Invalid => 4,
+            Exit::Simulation => 5,
+            Exit::Io => 6,
+            Exit::Assertion => 7,
+            Exit::Interrupted => 130,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Exit::Ok => "ok",
+            Exit::Failed => "failed",
+            Exit::Usage => "usage_error",
+            Exit::Parse => "parse_error",
+            Exit::Invalid => "validation_error",
+            Exit::Simulation => "simulation_error",
+            Exit::Io => "io_error",
+            Exit::Assertion => "assertion_failed",
+            Exit::Interrupted => "interrupted",
+        }
+    }
+
+    pub fn status_json(self) -> String {
+        format!(
+            "{{\"status\": \"{}\", \"exit_code\": {}}}",
+            self.as_str(),
+            self.code()
+        )
+    }
+}
+
+/// Set by the global `--status-json` flag.
+static STATUS_JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_status_json(on: bool) {
+    STATUS_JSON.store(on, Ordering::Relaxed);
+}
+
+/// Ends the process with `exit`'s code, writing the status line first when
+/// `--status-json` asked for it.
+pub fn exit(exit: Exit) -> ! {
+    if STATUS_JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", exit.status_json());
+    }
+    std::process::exit(exit.code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Exit; 9] = [
+        Exit::Ok,
+        Exit::Failed,
+        Exit::Usage,
+        Exit::Parse,
+        Exit::Invalid,
+        Exit::Simulation,
+        Exit::Io,
+        Exit::Assertion,
+        Exit::Interrupted,
+    ];
+
+    #[test]
+    fn codes_and_names_are_distinct() {
+        for (i, a) in ALL.iter().enumerate() {
+            for b in &ALL[i + 1..] {
+                assert_ne!(a.code(), b.code());
+                assert_ne!(a.as_str(), b.as_str());
+            }
+        }
+        assert_eq!(
+            Exit::Invalid.status_json(),
+            "{\"status\": \"validation_error\", \"exit_code\": 4}"
+        );
+    }
+}
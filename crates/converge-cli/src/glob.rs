@@ -0,0 +1,229 @@
+//! Minimal path globbing for commands that take many model files, so quoted
+//! patterns behave the same on every shell.
+//!
+//! Supports `*` and `?` within a path component, `[abc]`/`[a-z]`/`[!x]`
+//! classes, and `**` for zero or more directories. Hidden entries only match
+//! components that start with `.` themselves, and `**` doesn't descend into
+//! symlinked directories, so a link loop can't list a file over and over.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Expands `pattern` into the matching paths, sorted. A pattern without
+/// wildcards is returned unchanged, whether or not it exists.
+pub fn expand(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    if !is_pattern(pattern) {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let literal = components
+        .iter()
+        .position(|c| is_pattern(c))
+        .unwrap_or(components.len());
+    let mut base = PathBuf::from(if pattern.starts_with('/') { "/" } else { "" });
+    base.extend(&components[..literal]);
+
+    let mut out = Vec::new();
+    walk(&base, &components[literal..], &mut out)?;
+    out.sort();
+    out.dedup();
+    Ok(out)
+}
+
+fn walk(dir: &Path, rest: &[&str], out: &mut Vec<PathBuf>) -> io::Result<()> {
+    let Some((&head, tail)) = rest.split_first() else {
+        out.push(dir.to_path_buf());
+        return Ok(());
+    };
+
+    if head == "**" {
+        walk(dir, tail, out)?;
+        for entry in entries(dir)? {
+            if entry.is_dir && !entry.is_link && !entry.name.starts_with('.') {
+                let name = entry.name;
+                walk(&dir.join(name), rest, out)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let pattern: Vec<char> = head.chars().collect();
+    for Entry { name, is_dir, .. } in entries(dir)? {
+        if name.starts_with('.') && !head.starts_with('.') {
+            continue;
+        }
+        let chars: Vec<char> = name.chars().collect();
+        if (tail.is_empty() || is_dir) && matches(&pattern, &chars) {
+            walk(&dir.join(name), tail, out)?;
+        }
+    }
+    Ok(())
+}
+
+struct Entry {
+    name: String,
+    /// A directory or a symlink to one.
+    is_dir: bool,
+    is_link: bool,
+}
+
+/// A directory's entries; a missing directory has none.
+fn entries(dir: &Path) -> io::Result<Vec<Entry>> {
+    let read = if dir.as_os_str().is_empty() {
+        Path::new(".").read_dir()
+    } else {
+        dir.read_dir()
+    };
+    let read = match read {
+        Ok(r) => r,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+            ) =>
+        {
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for entry in read {
+        let entry = entry?;
+        entries.push(Entry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            is_dir: entry.path().is_dir(),
+            is_link: entry.file_type()?.is_symlink(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Matches one path component against a pattern, backtracking on `*`.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                '*' => {
+                    star = Some((p, n));
+                    p += 1;
+                    continue;
+                }
+                '?' => {
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+                '[' => match class(&pattern[p..], name[n]) {
+                    Some((true, len)) => {
+                        p += len;
+                        n += 1;
+                        continue;
+                    }
+                    None if name[n] == '[' => {
+                        p += 1;
+                        n += 1;
+                        continue;
+                    }
+                    _ => {}
+                },
+                c if c == name[n] => {
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        match star {
+            Some((sp, sn)) => {
+                star = Some((sp, sn + 1));
+                p = sp + 1;
+                n = sn + 1;
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Evaluates a `[...]` class at the start of `pattern` against `c`, returning
+/// whether it matched and how many pattern characters it spans. An unclosed
+/// `[` is not a class.
+fn class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.get(1), Some('!' | '^'));
+    let start = if negated { 2 } else { 1 };
+    // A `]` right after the opening bracket is a literal member.
+    let close = start + 1 + pattern.get(start + 1..)?.iter().position(|&ch| ch == ']')?;
+    let members = &pattern[start..close];
+    let mut hit = false;
+    let mut i = 0;
+    while i < members.len() {
+        if i + 2 < members.len() && members[i + 1] == '-' {
+            hit |= (members[i]..=members[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            hit |= members[i] == c;
+            i += 1;
+        }
+    }
+    Some((hit != negated, close + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(pattern: &str, name: &str) -> bool {
+        let p: Vec<char> = pattern.chars().collect();
+        let n: Vec<char> = name.chars().collect();
+        matches(&p, &n)
+    }
+
+    #[test]
+    fn matches_wildcards_and_classes() {
+        assert!(component("*.cv", "model.cv"));
+        assert!(!component("*.cv", "model.cvir"));
+        assert!(component("run_??.cv", "run_07.cv"));
+        assert!(component("run_[0-4]*", "run_3_b.cv"));
+        assert!(!component("run_[!0-4]*", "run_3_b.cv"));
+        assert!(component("a*b*c", "aXbYbZc"));
+        assert!(component("[x", "[x"));
+    }
+
+    #[test]
+    fn expands_recursive_patterns() {
+        let root = std::env::temp_dir().join(format!("converge-glob-{}", std::process::id()));
+        for file in [
+            "a.cv",
+            "sub/b.cv",
+            "sub/deep/c.cv",
+            "sub/notes.txt",
+            ".hidden/d.cv",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("sub/loop")).unwrap();
+        let pattern = format!("{}/**/*.cv", root.display());
+        let found = expand(&pattern).unwrap();
+        let expected: Vec<PathBuf> = ["a.cv", "sub/b.cv", "sub/deep/c.cv"]
+            .iter()
+            .map(|f| root.join(f))
+            .collect();
+        assert_eq!(found, expected);
+        assert!(
+            expand(&format!("{}/missing/*.cv", root.display()))
+                .unwrap()
+                .is_empty()
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,197 +1,1795 @@
 #![forbid(unsafe_code)]
 
-use std::path::Path;
+mod batch;
+mod bench;
+mod cli;
+mod config;
+mod cosim;
+mod doc;
+mod exit;
+mod glob;
+mod manifest;
+mod outdir;
+mod plot;
+mod progress;
+mod publish;
+mod report;
+mod serve;
+mod svg;
+mod tui;
 
-use converge_lang::parser::{format_diagnostic, parse_program};
-use converge_lang::validate::validate;
-use converge_sim::simulate;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use clap::FromArgMatches;
+use converge_lang::ast::{ConnectKind, Program};
+use converge_lang::cvir::program_from_cvir;
+use converge_lang::diagnostic::Diagnostic;
+use converge_lang::diff::diff_programs;
+use converge_lang::emit::CvirOptions;
+use converge_lang::equiv::first_difference;
+use converge_lang::lexer::process_env;
+use converge_lang::parser::{
+    format_diagnostic, format_warning, parse_program_recovering_with_env, parse_program_with_env,
+};
+use converge_lang::place::{place, placement_json};
+use converge_lang::stats::{stats_json, topology_stats};
+use converge_lang::target::{TargetProfile, check_target};
+use converge_lang::validate::{validate, validate_strict, warnings};
+use converge_sim::compare::compare_summary;
+use converge_sim::{
+    Arithmetic, BinarySink, NdjsonSink, Profile, Quantize, RecordSink, SimConfig, SimError,
+    SimErrorKind, SimSummary, Simulator, StateDump,
+};
+
+use crate::cli::{
+    Analysis, AstArgs, BatchArgs, BenchArgs, CheckArgs, Cli, Command, CompileArgs, CompileTarget,
+    CosimArgs, CvirArgs, CvirEqArgs, DebugCompareArgs, DiffArgs, DocArgs, DocFormat, ElaborateArgs,
+    ElaborateFormat, EstimateArgs, ExperimentArgs, FmtArgs, PlaceArgs, PlotArgs, ReportArgs,
+    ServeArgs, SimArgs, StatsArgs, TargetCommand, TrainArgs,
+};
+use crate::exit::{Exit, exit};
+use crate::progress::Progress;
 
 fn main() {
-    let mut args = std::env::args().skip(1);
-    let cmd = args.next().unwrap_or_else(|| "help".to_string());
+    let mut args: Vec<String> = std::env::args().collect();
+    if !args.iter().skip(1).any(|a| a == "--no-config") {
+        insert_config_defaults(&mut args);
+    }
+    // Set before parsing so usage errors get a status line too.
+    exit::set_status_json(args.iter().skip(1).any(|a| a == "--status-json"));
+    let cli = cli::command()
+        .try_get_matches_from(args)
+        .and_then(|matches| Cli::from_arg_matches(&matches))
+        .unwrap_or_else(|e| {
+            let _ = e.print();
+            exit(if e.use_stderr() {
+                Exit::Usage
+            } else {
+                Exit::Ok
+            })
+        });
+    STRICT.store(cli.strict, Ordering::Relaxed);
+    init_logging(cli.verbose);
+
+    match cli.command {
+        Command::Check(args) => cmd_check(args),
+        Command::Ast(args) => cmd_ast(args),
+        Command::Fmt(args) => cmd_fmt(args),
+        Command::Cvir(args) => cmd_cvir(args),
+        Command::Elaborate(args) => cmd_elaborate(args),
+        Command::Sim(args) => cmd_sim(*args),
+        Command::Train(args) => cmd_train(args),
+        Command::Estimate(args) => cmd_estimate(args),
+        Command::Experiment(args) => cmd_experiment(args),
+        Command::Stats(args) => cmd_stats(args),
+        Command::Target { command } => cmd_target(command),
+        Command::Place(args) => cmd_place(args),
+        Command::Compile(args) => cmd_compile(args),
+        Command::Diff(args) => cmd_diff(args),
+        Command::CvirEq(args) => cmd_cvir_eq(args),
+        Command::DebugCompare(args) => cmd_debug_compare(args),
+        Command::Bench(args) => cmd_bench(args),
+        Command::Report(args) => cmd_report(args),
+        Command::Doc(args) => cmd_doc(args),
+        Command::Plot(args) => cmd_plot(args),
+        Command::Serve(args) => cmd_serve(args),
+        Command::Batch(args) => cmd_batch(args),
+        Command::Cosim(args) => cmd_cosim(args),
+    }
+    exit(Exit::Ok)
+}
+
+/// Inserts the `.converge.toml` defaults for the command right after it, so
+/// the command line's own flags come later and win.
+fn insert_config_defaults(args: &mut Vec<String>) {
+    let Some(pos) = args
+        .iter()
+        .skip(1)
+        .position(|a| {
+            !matches!(
+                a.as_str(),
+                "-v" | "--verbose" | "--strict" | "--status-json"
+            )
+        })
+        .map(|pos| pos + 1)
+    else {
+        return;
+    };
+    let cmd = args[pos].clone();
+    if matches!(cmd.as_str(), "help" | "-h" | "--help" | "-V" | "--version") {
+        return;
+    }
+    // `target` takes its subcommand before any flag.
+    let at = (pos + if cmd == "target" { 2 } else { 1 }).min(args.len());
+    let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    match config::default_args(&cmd, &dir, home.as_deref()) {
+        Ok(defaults) => {
+            args.splice(at..at, defaults);
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            exit(Exit::Usage);
+        }
+    }
+}
+
+fn cmd_check(args: CheckArgs) {
+    let mut paths = Vec::new();
+    for pattern in args.patterns {
+        match glob::expand(&pattern) {
+            Ok(found) if found.is_empty() => {
+                eprintln!("error: `{pattern}` matched no files");
+                exit(Exit::Usage);
+            }
+            Ok(found) => paths.extend(found),
+            Err(e) => {
+                eprintln!("error: failed to expand `{pattern}`: {e}");
+                exit(Exit::Io);
+            }
+        }
+    }
+
+    let grouped = paths.len() > 1;
+    let mut invalid = 0;
+    let mut unreadable = 0;
+    let mut unparsed = 0;
+    for path in &paths {
+        let src = match read_source(path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("error: failed to read `{}`: {e}", path.display());
+                unreadable += 1;
+                continue;
+            }
+        };
+        let (diags, warnings) = match parse_program_recovering_with_env(&src, &process_env) {
+            Ok(program) if STRICT.load(Ordering::Relaxed) => (
+                validate_strict(&program).err().unwrap_or_default(),
+                Vec::new(),
+            ),
+            Ok(program) => (
+                validate(&program).err().unwrap_or_default(),
+                warnings(&program),
+            ),
+            Err(diags) => {
+                unparsed += 1;
+                (diags, Vec::new())
+            }
+        };
+        if diags.is_empty() && warnings.is_empty() {
+            continue;
+        }
+        if grouped {
+            eprintln!("{}:", path.display());
+        }
+        for warning in warnings {
+            eprintln!("{}", format_warning(&src, &warning));
+        }
+        if diags.is_empty() {
+            continue;
+        }
+        invalid += 1;
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+    }
+
+    if grouped {
+        eprintln!(
+            "checked {} files: {} ok, {invalid} invalid, {unreadable} unreadable",
+            paths.len(),
+            paths.len() - invalid - unreadable
+        );
+    }
+    if unreadable > 0 {
+        exit(Exit::Io);
+    }
+    if unparsed > 0 {
+        exit(Exit::Parse);
+    }
+    if invalid > 0 {
+        exit(Exit::Invalid);
+    }
+}
+
+fn cmd_ast(args: AstArgs) {
+    let AstArgs { file: path, debug } = args;
+    let src = read_file(&path);
+    match parse_local(&src) {
+        Ok(program) if debug => {
+            println!("{program:#?}");
+        }
+        Ok(program) => {
+            print!("{}", converge_lang::emit::ast_json(&program));
+        }
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
+        }
+    }
+}
+
+/// `converge fmt <file>` lists the deprecated forms in a file and fails if
+/// there are any; `--fix` rewrites them, in place or to stdout for `-`.
+fn cmd_fmt(args: FmtArgs) {
+    let FmtArgs { file: path, fix } = args;
+    let src = read_file(&path);
+    let program = parse_local(&src).unwrap_or_else(|diag| {
+        eprintln!("{}", format_diagnostic(&src, &diag));
+        exit(Exit::Parse);
+    });
+    let count = program.deprecated.len();
+    if fix {
+        let fixed = converge_lang::deprecated::fix(&src, &program);
+        if path == "-" {
+            print!("{fixed}");
+        } else if count > 0 {
+            if let Err(e) = std::fs::write(&path, fixed) {
+                eprintln!("error: failed to write `{path}`: {e}");
+                exit(Exit::Io);
+            }
+            eprintln!("fixed {count} deprecated form(s) in `{path}`");
+        }
+        return;
+    }
+    for deprecated in &program.deprecated {
+        eprintln!("{}", format_warning(&src, &deprecated.to_diagnostic()));
+    }
+    if count > 0 {
+        eprintln!("{count} deprecated form(s); `converge fmt --fix` rewrites them");
+        exit(Exit::Failed);
+    }
+}
+
+fn cmd_cvir(args: CvirArgs) {
+    let CvirArgs {
+        file: path,
+        with_spans,
+        with_ids,
+        optimize,
+    } = args;
+    let src = read_file(&path);
+    let mut program = match parse_local(&src) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
+        }
+    };
 
-    match cmd.as_str() {
-        "check" => cmd_check(args),
-        "ast" => cmd_ast(args),
-        "cvir" => cmd_cvir(args),
-        "sim" => cmd_sim(args),
-        "help" | "-h" | "--help" => {
-            print_usage();
-            std::process::exit(0);
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
         }
-        _ => {
-            eprintln!("error: unknown command `{cmd}`\n");
-            print_usage();
-            std::process::exit(2);
+        exit(Exit::Invalid);
+    }
+    if optimize {
+        converge_lang::opt::optimize(&mut program);
+    }
+
+    let opts = CvirOptions {
+        spans: with_spans,
+        file: (path != "-").then(|| path.clone()),
+        ids: with_ids,
+    };
+    print!("{}", converge_lang::emit::cvir_json_with(&program, &opts));
+}
+
+fn cmd_elaborate(args: ElaborateArgs) {
+    let ElaborateArgs {
+        file: path,
+        network,
+        overrides,
+        format,
+    } = args;
+    let src = read_file(&path);
+    let program = parse_program_with_env(&src, &overrides, &process_env).unwrap_or_else(|diag| {
+        eprintln!("{}", format_diagnostic(&src, &diag));
+        exit(Exit::Parse);
+    });
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
         }
+        exit(Exit::Invalid);
+    }
+    // Without `--network`, every network is printed as a block.
+    let mut model = match &network {
+        Some(name) => match program.select_network(Some(name)) {
+            Ok(p) => p.into_owned(),
+            Err(diag) => {
+                eprintln!("error: {}", diag.message);
+                exit(Exit::Invalid);
+            }
+        },
+        None => program.clone(),
+    };
+    model.version = program.version.clone();
+    model.params = program.params.clone();
+    match format {
+        ElaborateFormat::Cv => print!("{}", converge_lang::print::print_program(&model)),
+        ElaborateFormat::Cvir => print!("{}", converge_lang::emit::cvir_json(&model)),
     }
 }
 
-fn cmd_check(mut args: impl Iterator<Item = String>) {
-    let path = match args.next() {
-        Some(p) => p,
-        None => {
-            eprintln!("error: expected a file path\n");
-            print_usage();
-            std::process::exit(2);
+/// Whether the run is exported with `--nwb`, which needs the raster.
+fn wants_nwb(args: &SimArgs) -> bool {
+    #[cfg(feature = "nwb")]
+    return args.nwb.is_some();
+    #[cfg(not(feature = "nwb"))]
+    {
+        let _ = args;
+        false
+    }
+}
+
+fn cmd_sim(args: SimArgs) {
+    let nwb = wants_nwb(&args);
+    let SimArgs {
+        file: path,
+        out: out_path,
+        out_dir,
+        force,
+        manifest: manifest_path,
+        compare: compare_path,
+        tolerance,
+        quiet,
+        spikes: spikes_path,
+        record_input: record_input_path,
+        replay_input: replay_input_path,
+        dump_state: dump_path,
+        dump_every,
+        dump_layers,
+        dump_limit,
+        publish: publish_addr,
+        subscribers,
+        plot,
+        plot_out: plot_dir,
+        tui: dashboard,
+        network,
+        overrides,
+        ..
+    } = args;
+    if let Some(dir) = &out_dir {
+        match outdir::prepare(dir, force) {
+            Ok(()) => {}
+            Err(outdir::PrepareError::NotEmpty) => {
+                eprintln!(
+                    "error: `{}` is not empty; pass --force to write into it",
+                    dir.display()
+                );
+                exit(Exit::Usage);
+            }
+            Err(outdir::PrepareError::Io(e)) => {
+                eprintln!("error: failed to create `{}`: {e}", dir.display());
+                exit(Exit::Io);
+            }
         }
+    }
+    let spikes_path = out_dir.as_ref().map_or(spikes_path, |dir| {
+        Some(dir.join(outdir::SPIKES).to_string_lossy().into_owned())
+    });
+    let mut config = SimConfig {
+        profile: args.profile,
+        quantize: args.quantize,
+        scratch: args.scratch,
+        record_input: record_input_path.is_some(),
+        record_spikes: plot || plot_dir.is_some() || args.analyze.is_some() || nwb,
+        trace_neurons: args
+            .trace_neurons
+            .unwrap_or(if plot_dir.is_some() { 4 } else { 0 }),
+        energy: args.energy_report.as_deref().map(read_profile),
+        ..args.engine.config()
     };
+    if args.fixed_point {
+        config.arithmetic = Arithmetic::Fixed;
+    }
+    let dump_limit = dump_limit.map_or(converge_sim::DEFAULT_DUMP_LIMIT, |mib| {
+        mib.saturating_mul(1 << 20)
+    });
 
     let src = read_file(&path);
-    let program = match parse_program(&src) {
+    config.data_dir = data_dir(&path);
+    if let Some(input) = &replay_input_path {
+        let bytes = std::fs::read(input).unwrap_or_else(|e| {
+            eprintln!("error: failed to read `{input}`: {e}");
+            exit(Exit::Io);
+        });
+        match converge_sim::raster::decode(&bytes) {
+            Ok(recording) => config.replay_input = Some(recording),
+            Err(diag) => {
+                eprintln!("error: {input}: {diag}");
+                exit(Exit::Parse);
+            }
+        }
+    }
+    let started = Instant::now();
+    let program = match parse_program_with_env(&src, &overrides, &process_env) {
         Ok(p) => p,
         Err(diag) => {
             eprintln!("{}", format_diagnostic(&src, &diag));
-            std::process::exit(1);
+            exit(Exit::Parse);
         }
     };
+    let parsed = Instant::now();
 
-    if let Err(diags) = validate(&program) {
+    if let Err(diags) = validate_with_warnings(&src, &program) {
         for diag in diags {
             eprintln!("{}", format_diagnostic(&src, &diag));
         }
-        std::process::exit(1);
+        exit(Exit::Invalid);
+    }
+    let program = match program.select_network(network.as_deref()) {
+        Ok(p) => p.into_owned(),
+        Err(diag) => {
+            eprintln!("error: {}", diag.message);
+            exit(Exit::Invalid);
+        }
+    };
+    let validated = Instant::now();
+
+    if dashboard && !std::io::stderr().is_terminal() {
+        eprintln!("error: --tui needs a terminal on stderr\n");
+        exit(Exit::Usage);
+    }
+    let dump = dump_path.map(|path| DumpRequest {
+        path,
+        every: dump_every,
+        layers: dump_layers,
+        limit: dump_limit,
+    });
+    if dump.is_some() && dashboard {
+        eprintln!("error: --dump-state cannot be combined with --tui\n");
+        exit(Exit::Usage);
+    }
+    let mut sinks: Vec<Box<dyn RecordSink + Send>> = Vec::new();
+    if let Some(spikes) = &spikes_path {
+        let out = std::fs::File::create(spikes).unwrap_or_else(|e| {
+            eprintln!("error: failed to write `{spikes}`: {e}");
+            exit(Exit::Io);
+        });
+        let out = std::io::BufWriter::new(out);
+        if spikes.ends_with(".cvr") {
+            sinks.push(Box::new(BinarySink::new(out)));
+        } else {
+            sinks.push(Box::new(NdjsonSink::new(out)));
+        }
+    }
+    if let Some(addr) = &publish_addr {
+        let publisher = publish::Publisher::bind(addr).unwrap_or_else(|e| {
+            eprintln!("error: failed to publish on `{addr}`: {e}");
+            exit(Exit::Io);
+        });
+        eprintln!("publishing on tcp://{}", publisher.local_addr());
+        if subscribers > 0 {
+            eprintln!("waiting for {subscribers} subscriber(s)");
+            publisher.wait_for(subscribers);
+        }
+        sinks.push(Box::new(publisher));
+    }
+    let result = if dashboard {
+        run_with_tui(&program, &config, sinks, &path)
+    } else {
+        run_with_progress(&program, &config, sinks, dump.as_ref(), !quiet)
+    };
+    let finished = result.and_then(|mut sim| sim.finish().map(|summary| (sim, summary)));
+    let (sim, mut summary) = match finished {
+        Ok(run) => run,
+        Err(err) => {
+            eprintln!("{}", format_sim_error(&src, &err));
+            exit(Exit::Simulation);
+        }
+    };
+    summary.input_hash = Some(converge_sim::source_hash(&src));
+    if let Some(quantize) = config.quantize {
+        let float_config = SimConfig {
+            quantize: None,
+            arithmetic: Arithmetic::Float,
+            profile: false,
+            record_spikes: false,
+            record_input: false,
+            trace_neurons: 0,
+            ..config.clone()
+        };
+        match converge_sim::simulate_with(&program, &float_config) {
+            Ok(float) => eprint!("{}", quantization_report(quantize, &summary, &float)),
+            Err(err) => {
+                eprintln!("error: float reference run failed: {err}");
+                exit(Exit::Simulation);
+            }
+        }
+    }
+    if let Some(energy) = &summary.energy {
+        eprintln!("{energy}");
+    }
+    if let Some(profile) = &mut summary.profile {
+        let mut front = Profile::default();
+        front.push("parse", parsed - started);
+        front.push("validate", validated - parsed);
+        profile.phases.splice(0..0, front.phases);
+    }
+
+    let _output = tracing::info_span!("output").entered();
+    let output_started = Instant::now();
+    let json = converge_sim::summary_json(&summary);
+    // Every file the run writes, in order, for `--manifest`.
+    let mut written: Vec<PathBuf> = spikes_path.iter().map(PathBuf::from).collect();
+    written.extend(dump.as_ref().map(|d| PathBuf::from(&d.path)));
+    let mut printed = None;
+
+    if let Some(dir) = &out_dir {
+        let config = outdir::effective_config(&path, network.as_deref(), &program, &summary);
+        let (summary_file, config_file) = (dir.join(outdir::SUMMARY), dir.join(outdir::CONFIG));
+        let weights = std::fs::write(&summary_file, &json)
+            .and_then(|()| std::fs::write(&config_file, config))
+            .and_then(|()| {
+                if sim.trained_connections().is_empty() {
+                    return Ok(Vec::new());
+                }
+                write_weights(&sim, &dir.join(outdir::WEIGHTS))
+            });
+        match weights {
+            Ok(weights) => written.extend([summary_file, config_file].into_iter().chain(weights)),
+            Err(e) => {
+                eprintln!("error: failed to write to `{}`: {e}", dir.display());
+                exit(Exit::Io);
+            }
+        }
+    } else if let Some(out) = out_path {
+        std::fs::write(&out, &json).unwrap_or_else(|e| {
+            eprintln!("error: failed to write `{out}`: {e}");
+            exit(Exit::Io);
+        });
+        written.push(out.into());
+    } else {
+        print!("{json}");
+        printed = Some(manifest::Output::stdout(&json));
+    }
+
+    if plot {
+        eprint!(
+            "{}",
+            plot::render_ascii(&plot::Raster::from_summary(&summary), terminal_width())
+        );
+    }
+
+    if let Some(dir) = plot_dir {
+        match svg::write_plots(Path::new(&dir), &summary) {
+            Ok(plots) => written.extend(plots),
+            Err(e) => {
+                eprintln!("error: failed to write plots to `{dir}`: {e}");
+                exit(Exit::Io);
+            }
+        }
+    }
+
+    if let Some(dir) = &args.dump_weight_images {
+        match write_weight_images(&sim, Path::new(dir)) {
+            Ok(images) => written.extend(images),
+            Err(e) => {
+                eprintln!("error: failed to write weight images to `{dir}`: {e}");
+                exit(Exit::Io);
+            }
+        }
+    }
+
+    if args.analyze == Some(Analysis::Correlations) {
+        let report =
+            converge_sim::correlations(&summary, &args.analyze_layers, args.bin, args.max_lag)
+                .unwrap_or_else(|err| {
+                    eprintln!("error: --analyze-layers: {err}");
+                    exit(Exit::Usage);
+                });
+        let out = out_dir
+            .as_ref()
+            .map(|dir| {
+                dir.join(outdir::CORRELATIONS)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .or(args.analysis_out);
+        match out {
+            Some(out) => {
+                let text = if out.ends_with(".csv") {
+                    converge_sim::correlations_csv(&report)
+                } else {
+                    converge_sim::correlations_json(&report)
+                };
+                std::fs::write(&out, text).unwrap_or_else(|e| {
+                    eprintln!("error: failed to write `{out}`: {e}");
+                    exit(Exit::Io);
+                });
+                written.push(out.into());
+            }
+            None => eprint!("{}", converge_sim::correlations_json(&report)),
+        }
+    }
+
+    #[cfg(feature = "nwb")]
+    if let Some(out) = &args.nwb {
+        let session = converge_sim::nwb::Session {
+            identifier: format!(
+                "{path}:{}",
+                converge_sim::format_spike_hash(summary.spike_hash)
+            ),
+            description: format!("converge sim {path}, seed {}", summary.seed),
+            start_time: converge_sim::nwb::iso8601(std::time::SystemTime::now()),
+        };
+        let file = std::fs::File::create(out).and_then(|file| {
+            let mut w = std::io::BufWriter::new(file);
+            converge_sim::nwb::write_nwb(&mut w, &summary, &session)?;
+            w.flush()
+        });
+        match file {
+            Ok(()) => written.push(out.into()),
+            Err(e) => {
+                eprintln!("error: failed to write `{out}`: {e}");
+                exit(Exit::Io);
+            }
+        }
+    }
+
+    if let Some(input) = &record_input_path {
+        let file = std::fs::File::create(input).and_then(|file| {
+            let mut out = std::io::BufWriter::new(file);
+            converge_sim::raster::write_input(&mut out, &summary)?;
+            out.flush()
+        });
+        match file {
+            Ok(()) => written.push(input.into()),
+            Err(e) => {
+                eprintln!("error: failed to write `{input}`: {e}");
+                exit(Exit::Io);
+            }
+        }
+    }
+
+    if let Some(manifest_path) = &manifest_path {
+        let outputs = written
+            .iter()
+            .map(|file| manifest::Output::file(file))
+            .chain(printed.map(Ok))
+            .collect::<std::io::Result<Vec<_>>>();
+        let text = outputs.map(|outputs| {
+            manifest::manifest_json(
+                &path,
+                network.as_deref(),
+                &src,
+                &program,
+                &summary,
+                &outputs,
+            )
+        });
+        if let Err(e) = text.and_then(|text| std::fs::write(manifest_path, text)) {
+            eprintln!("error: failed to write `{}`: {e}", manifest_path.display());
+            exit(Exit::Io);
+        }
+    }
+
+    // Output time cannot be embedded in the JSON it measures, so it only
+    // appears in the stderr report.
+    if let Some(profile) = &mut summary.profile {
+        profile.push("output", output_started.elapsed());
+        eprintln!("{profile}");
+    }
+
+    let failures = converge_sim::check_asserts(&program, &summary);
+    if !failures.is_empty() {
+        for diag in failures {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+        exit(Exit::Assertion);
+    }
+
+    if let Some(golden_path) = compare_path {
+        let golden = read_file(&golden_path);
+        let diffs = match compare_summary(&summary, &golden, tolerance) {
+            Ok(d) => d,
+            Err(diag) => {
+                eprintln!("{}", format_diagnostic(&golden, &diag));
+                exit(Exit::Parse);
+            }
+        };
+        if !diffs.is_empty() {
+            eprintln!("error: summary does not match `{golden_path}`");
+            for diff in diffs {
+                eprintln!("  {diff}");
+            }
+            exit(Exit::Failed);
+        }
     }
 }
 
-fn cmd_ast(mut args: impl Iterator<Item = String>) {
-    let path = match args.next() {
-        Some(p) => p,
-        None => {
-            eprintln!("error: expected a file path\n");
-            print_usage();
-            std::process::exit(2);
+fn cmd_train(args: TrainArgs) {
+    let TrainArgs {
+        file: path,
+        out: out_dir,
+        quiet,
+        ..
+    } = args;
+    let mut config = SimConfig {
+        epochs: args.epochs,
+        learning_rate: Some(args.lr),
+        ..args.engine.config()
+    };
+
+    let src = read_file(&path);
+    config.data_dir = data_dir(&path);
+    let program = match parse_local(&src) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
+        }
+    };
+
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+        exit(Exit::Invalid);
+    }
+
+    let mut sim = match Simulator::new(&program, &config) {
+        Ok(sim) => sim,
+        Err(err) => {
+            eprintln!("{}", format_sim_error(&src, &err));
+            exit(Exit::Simulation);
         }
     };
+    let mut progress = Progress::new(!quiet);
+    while !sim.is_finished() {
+        if let Err(err) = sim.step() {
+            eprintln!("{}", format_sim_error(&src, &err));
+            exit(Exit::Simulation);
+        }
+        progress.update(&sim);
+    }
+    progress.finish(&sim);
+
+    match write_weights(&sim, Path::new(&out_dir)) {
+        Ok(paths) => {
+            for path in paths {
+                eprintln!("wrote {}", path.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("error: failed to write weights to `{out_dir}`: {e}");
+            exit(Exit::Io);
+        }
+    }
+
+    let mut summary = sim.summary();
+    summary.input_hash = Some(converge_sim::source_hash(&src));
+    if let Some(c) = &summary.classification {
+        eprintln!(
+            "accuracy {:.3} ({} of {} presentations, {} silent)",
+            c.accuracy(),
+            c.correct,
+            c.presentations,
+            c.silent
+        );
+    }
+    print!("{}", converge_sim::summary_json(&summary));
+}
+
+fn cmd_estimate(args: EstimateArgs) {
+    let EstimateArgs {
+        file: path, json, ..
+    } = args;
+    let config = args.engine.config();
 
     let src = read_file(&path);
-    match parse_program(&src) {
-        Ok(program) => {
-            println!("{program:#?}");
+    let program = match parse_local(&src) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
+        }
+    };
+
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
         }
+        exit(Exit::Invalid);
+    }
+
+    let estimate = match converge_sim::estimate(&program, &config) {
+        Ok(e) => e,
+        Err(err) => {
+            eprintln!("{}", format_sim_error(&src, &err));
+            exit(Exit::Simulation);
+        }
+    };
+    for warning in &estimate.warnings {
+        eprintln!("{}", format_warning(&src, warning));
+    }
+    if json {
+        print!("{}", converge_sim::estimate_json(&estimate));
+    } else {
+        println!("{estimate}");
+    }
+}
+
+fn cmd_experiment(args: ExperimentArgs) {
+    let ExperimentArgs {
+        file: path,
+        out: out_path,
+        quiet,
+        ..
+    } = args;
+    let mut config = args.engine.config();
+
+    let src = read_file(&path);
+    config.data_dir = data_dir(&path);
+    let program = match parse_local(&src) {
+        Ok(p) => p,
         Err(diag) => {
             eprintln!("{}", format_diagnostic(&src, &diag));
-            std::process::exit(1);
+            exit(Exit::Parse);
+        }
+    };
+
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+        exit(Exit::Invalid);
+    }
+
+    let total: u64 = converge_sim::find_sweep(&program)
+        .map(|def| def.runs.iter().map(|run| run.seeds().count() as u64).sum())
+        .unwrap_or(0);
+    let mut done = 0;
+    let report = converge_sim::run_sweep(&program, &config, |run, result| {
+        done += 1;
+        if !quiet {
+            eprintln!(
+                "[{done}/{total}] {run}: seed {} {}",
+                result.seed,
+                converge_sim::format_spike_hash(result.spike_hash)
+            );
+        }
+    });
+    let report = match report {
+        Ok(r) => r,
+        Err(err) => {
+            eprintln!("{}", format_sim_error(&src, &err));
+            exit(Exit::Simulation);
         }
+    };
+
+    let json = converge_sim::sweep_json(&report);
+    if let Some(out) = out_path {
+        std::fs::write(&out, json).unwrap_or_else(|e| {
+            eprintln!("error: failed to write `{out}`: {e}");
+            exit(Exit::Io);
+        });
+    } else {
+        print!("{json}");
     }
 }
 
-fn cmd_cvir(mut args: impl Iterator<Item = String>) {
-    let path = match args.next() {
-        Some(p) => p,
-        None => {
-            eprintln!("error: expected a file path\n");
-            print_usage();
-            std::process::exit(2);
+fn cmd_stats(args: StatsArgs) {
+    let StatsArgs { file: path, json } = args;
+
+    let src = read_file(&path);
+    let program = match parse_local(&src) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
         }
     };
 
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+        exit(Exit::Invalid);
+    }
+
+    let stats = topology_stats(&program);
+    if json {
+        print!("{}", stats_json(&stats));
+    } else {
+        println!("{stats}");
+    }
+}
+
+fn cmd_target(command: TargetCommand) {
+    let TargetCommand::Check {
+        file: path,
+        profile: profile_path,
+    } = command;
+
+    let profile = read_profile(&profile_path);
     let src = read_file(&path);
-    let program = match parse_program(&src) {
+    let program = match parse_local(&src) {
         Ok(p) => p,
         Err(diag) => {
             eprintln!("{}", format_diagnostic(&src, &diag));
-            std::process::exit(1);
+            exit(Exit::Parse);
         }
     };
 
-    if let Err(diags) = validate(&program) {
+    if let Err(diags) = validate_with_warnings(&src, &program) {
         for diag in diags {
             eprintln!("{}", format_diagnostic(&src, &diag));
         }
-        std::process::exit(1);
+        exit(Exit::Invalid);
     }
 
-    print!("{}", converge_lang::emit::cvir_json(&program));
+    let violations = check_target(&program, &profile);
+    if violations.is_empty() {
+        println!("{path}: fits {}", profile.name);
+        return;
+    }
+    for diag in &violations {
+        eprintln!("{}", format_diagnostic(&src, diag));
+    }
+    eprintln!(
+        "{path}: {} violation(s) of {}",
+        violations.len(),
+        profile.name
+    );
+    exit(Exit::Failed);
 }
 
-fn cmd_sim(mut args: impl Iterator<Item = String>) {
-    let mut file = None;
-    let mut out_path = None;
+fn cmd_place(args: PlaceArgs) {
+    let PlaceArgs {
+        file: path,
+        profile: profile_path,
+        out: out_dir,
+    } = args;
+    let profile = read_profile(&profile_path);
 
-    while let Some(arg) = args.next() {
-        if arg == "--out" {
-            out_path = args.next();
-        } else if file.is_none() {
-            file = Some(arg);
-        } else {
-            eprintln!("error: unexpected argument `{arg}`\n");
-            print_usage();
-            std::process::exit(2);
+    let src = read_file(&path);
+    let program = match parse_local(&src) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
+        }
+    };
+
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+        exit(Exit::Invalid);
+    }
+
+    let violations = check_target(&program, &profile);
+    if !violations.is_empty() {
+        for diag in &violations {
+            eprintln!("{}", format_diagnostic(&src, diag));
         }
+        eprintln!("error: `{path}` does not fit {}", profile.name);
+        exit(Exit::Failed);
     }
+    let placement = match place(&program, &profile) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Failed);
+        }
+    };
+    let placement_text = placement_json(&placement);
 
-    let path = match file {
-        Some(p) => p,
-        None => {
-            eprintln!("error: expected a file path\n");
-            print_usage();
-            std::process::exit(2);
+    let Some(out_dir) = out_dir else {
+        print!("{placement_text}");
+        return;
+    };
+    let out = Path::new(&out_dir);
+    if let Err(e) = std::fs::create_dir_all(out) {
+        eprintln!("error: failed to create `{out_dir}`: {e}");
+        exit(Exit::Io);
+    }
+    let stem = match Path::new(&path).file_stem() {
+        Some(stem) if path != "-" => stem.to_string_lossy().into_owned(),
+        _ => "program".to_string(),
+    };
+    let opts = CvirOptions {
+        spans: false,
+        file: (path != "-").then(|| path.clone()),
+        ids: false,
+    };
+    let cvir = converge_lang::emit::cvir_json_with(&program, &opts);
+    for (ext, text) in [("cvir.json", &cvir), ("placement.json", &placement_text)] {
+        let file = out.join(format!("{stem}.{ext}"));
+        if let Err(e) = std::fs::write(&file, text) {
+            eprintln!("error: failed to write `{}`: {e}", file.display());
+            exit(Exit::Io);
         }
+        eprintln!("wrote {}", file.display());
+    }
+    eprintln!(
+        "{} cores, {} inter-core routes",
+        placement.cores.len(),
+        placement.routes
+    );
+}
+
+fn cmd_compile(args: CompileArgs) {
+    let CompileArgs {
+        file: path,
+        target,
+        out: out_dir,
+    } = args;
+    let generate = match target {
+        CompileTarget::Rust => converge_sim::codegen::rust_crate,
+        CompileTarget::C => converge_sim::codegen::c_sources,
     };
 
     let src = read_file(&path);
-    let program = match parse_program(&src) {
+    let program = match parse_local(&src) {
         Ok(p) => p,
         Err(diag) => {
             eprintln!("{}", format_diagnostic(&src, &diag));
-            std::process::exit(1);
+            exit(Exit::Parse);
         }
     };
 
-    if let Err(diags) = validate(&program) {
+    if let Err(diags) = validate_with_warnings(&src, &program) {
         for diag in diags {
             eprintln!("{}", format_diagnostic(&src, &diag));
         }
-        std::process::exit(1);
+        exit(Exit::Invalid);
     }
 
-    let summary = match simulate(&program) {
-        Ok(s) => s,
+    let config = SimConfig {
+        data_dir: data_dir(&path),
+        ..SimConfig::default()
+    };
+    let stem = match Path::new(&path).file_stem() {
+        Some(stem) if path != "-" => stem.to_string_lossy().into_owned(),
+        _ => "program".to_string(),
+    };
+    let files = match generate(&program, &config, &stem) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("{}", format_sim_error(&src, &err));
+            exit(Exit::Simulation);
+        }
+    };
+    for generated in files {
+        let file = Path::new(&out_dir).join(&generated.path);
+        let written = match file.parent() {
+            Some(parent) => std::fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|()| std::fs::write(&file, &generated.contents));
+        if let Err(e) = written {
+            eprintln!("error: failed to write `{}`: {e}", file.display());
+            exit(Exit::Io);
+        }
+        eprintln!("wrote {}", file.display());
+    }
+}
+
+fn cmd_diff(args: DiffArgs) {
+    let DiffArgs {
+        old: old_path,
+        new: new_path,
+    } = args;
+
+    let parse = |path: &str| {
+        let src = read_file(path);
+        match parse_local(&src) {
+            Ok(p) => p,
+            Err(diag) => {
+                eprintln!("{path}: {}", format_diagnostic(&src, &diag));
+                exit(Exit::Parse);
+            }
+        }
+    };
+    let old = parse(&old_path);
+    let new = parse(&new_path);
+
+    let changes = diff_programs(&old, &new);
+    if changes.is_empty() {
+        println!("no semantic differences");
+    }
+    for change in changes {
+        println!("{change}");
+    }
+}
+
+fn cmd_cvir_eq(args: CvirEqArgs) {
+    let CvirEqArgs {
+        a: a_path,
+        b: b_path,
+    } = args;
+
+    let load = |path: &str| match program_from_cvir(&read_file(path)) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("error: `{path}` is not valid CVIR: {}", diag.message);
+            exit(Exit::Parse);
+        }
+    };
+    let a = load(&a_path);
+    let b = load(&b_path);
+
+    match first_difference(&a, &b) {
+        None => println!("equivalent"),
+        Some(difference) => {
+            println!("`{a_path}` and `{b_path}` differ: {difference}");
+            exit(Exit::Failed);
+        }
+    }
+}
+
+fn cmd_debug_compare(args: DebugCompareArgs) {
+    let DebugCompareArgs {
+        a: a_path,
+        b: b_path,
+        network,
+        tolerance,
+        replay_input: replay_path,
+    } = args;
+
+    let replay = replay_path.map(|input| {
+        let bytes = std::fs::read(&input).unwrap_or_else(|e| {
+            eprintln!("error: failed to read `{input}`: {e}");
+            exit(Exit::Io);
+        });
+        converge_sim::raster::decode(&bytes).unwrap_or_else(|diag| {
+            eprintln!("error: {input}: {diag}");
+            exit(Exit::Parse);
+        })
+    });
+    let build = |path: &str| {
+        let src = read_file(path);
+        let program = match parse_local(&src) {
+            Ok(p) => p,
+            Err(diag) => {
+                eprintln!("{path}: {}", format_diagnostic(&src, &diag));
+                exit(Exit::Parse);
+            }
+        };
+        if let Err(diags) = validate_with_warnings(&src, &program) {
+            for diag in diags {
+                eprintln!("{path}: {}", format_diagnostic(&src, &diag));
+            }
+            exit(Exit::Invalid);
+        }
+        let config = SimConfig {
+            record_spikes: true,
+            data_dir: data_dir(path),
+            replay_input: replay.clone(),
+            ..SimConfig::default()
+        };
+        let built = program
+            .select_network(network.as_deref())
+            .map_err(|diag| SimError::new(SimErrorKind::Invalid(diag.message)))
+            .and_then(|p| Simulator::new(&p, &config));
+        built.unwrap_or_else(|err| {
+            match err.span {
+                Some(_) => eprintln!("{path}: {}", format_sim_error(&src, &err)),
+                None => eprintln!("error: {path}: {err}"),
+            }
+            exit(Exit::Simulation);
+        })
+    };
+    let mut a = build(&a_path);
+    let mut b = build(&b_path);
+
+    match converge_sim::compare::first_divergence(&mut a, &mut b, tolerance) {
+        Ok(None) => {
+            println!("no divergence in {} steps", a.current_step());
+            if a.total_steps() != b.total_steps() {
+                println!(
+                    "note: `{a_path}` runs {} steps, `{b_path}` {}",
+                    a.total_steps(),
+                    b.total_steps()
+                );
+            }
+        }
+        Ok(Some(divergence)) => {
+            println!("first divergence at {divergence}");
+            exit(Exit::Failed);
+        }
         Err(err) => {
             eprintln!("error: {err}");
-            std::process::exit(1);
+            exit(Exit::Simulation);
+        }
+    }
+}
+
+fn cmd_bench(args: BenchArgs) {
+    let BenchArgs {
+        file,
+        runs,
+        out: out_path,
+        ..
+    } = args;
+    let mut config = args.engine.config();
+
+    let sources: Vec<(String, String)> = match file {
+        Some(path) => {
+            let name = Path::new(&path)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.clone());
+            config.data_dir = data_dir(&path);
+            vec![(name, read_file(&path))]
         }
+        None => bench::BUNDLED
+            .iter()
+            .map(|(name, src)| (name.to_string(), src.to_string()))
+            .collect(),
     };
-    let json = converge_sim::summary_json(&summary);
 
+    let mut results = Vec::new();
+    for (name, src) in &sources {
+        let program = match parse_local(src) {
+            Ok(p) => p,
+            Err(diag) => {
+                eprintln!("{}", format_diagnostic(src, &diag));
+                exit(Exit::Parse);
+            }
+        };
+        if let Err(diags) = validate_with_warnings(src, &program) {
+            for diag in diags {
+                eprintln!("{}", format_diagnostic(src, &diag));
+            }
+            exit(Exit::Invalid);
+        }
+        match bench::bench_program(name, &program, &config, runs) {
+            Ok(result) => results.push(result),
+            Err(err) => {
+                eprintln!("error: {name}: {err}");
+                exit(Exit::Simulation);
+            }
+        }
+    }
+
+    let json = bench::bench_json(&results, bench::peak_rss_bytes());
     if let Some(out) = out_path {
         std::fs::write(&out, json).unwrap_or_else(|e| {
             eprintln!("error: failed to write `{out}`: {e}");
-            std::process::exit(2);
+            exit(Exit::Io);
         });
     } else {
         print!("{json}");
     }
 }
 
-fn read_file(path: &str) -> String {
-    std::fs::read_to_string(Path::new(path)).unwrap_or_else(|e| {
+fn cmd_report(args: ReportArgs) {
+    let ReportArgs {
+        file: path,
+        out: out_path,
+        ..
+    } = args;
+    let mut config = SimConfig {
+        record_spikes: !args.no_raster,
+        ..args.engine.config()
+    };
+
+    let src = read_file(&path);
+    config.data_dir = data_dir(&path);
+    let program = match parse_local(&src) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
+        }
+    };
+
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+        exit(Exit::Invalid);
+    }
+
+    let summary = match converge_sim::simulate_with(&program, &config) {
+        Ok(s) => s,
+        Err(err) => {
+            eprintln!("{}", format_sim_error(&src, &err));
+            exit(Exit::Simulation);
+        }
+    };
+
+    let stats = topology_stats(&program);
+    let html = report::render_report(&path, &program, &stats, &summary);
+    if let Some(out) = out_path {
+        std::fs::write(&out, html).unwrap_or_else(|e| {
+            eprintln!("error: failed to write `{out}`: {e}");
+            exit(Exit::Io);
+        });
+    } else {
+        print!("{html}");
+    }
+}
+
+fn cmd_doc(args: DocArgs) {
+    let DocArgs {
+        file: path,
+        out,
+        format,
+    } = args;
+    let src = read_file(&path);
+    let program = match parse_local(&src) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
+        }
+    };
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+        exit(Exit::Invalid);
+    }
+
+    let page = doc::render_doc(&path, &program, format);
+    let Some(dir) = out else {
+        print!("{page}");
+        return;
+    };
+    let stem = Path::new(&path)
+        .file_stem()
+        .filter(|_| path != "-")
+        .map_or("model".into(), |stem| stem.to_string_lossy());
+    let extension = match format {
+        DocFormat::Markdown => "md",
+        DocFormat::Html => "html",
+    };
+    let out = Path::new(&dir).join(format!("{stem}.{extension}"));
+    if let Err(e) = std::fs::create_dir_all(&dir).and_then(|()| std::fs::write(&out, page)) {
+        eprintln!("error: failed to write `{}`: {e}", out.display());
+        exit(Exit::Io);
+    }
+}
+
+fn cmd_plot(args: PlotArgs) {
+    let path = args.file;
+    let width = args.width.unwrap_or_else(terminal_width);
+
+    let bytes = if path == "-" {
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut bytes).map(|_| bytes)
+    } else {
+        std::fs::read(&path)
+    }
+    .unwrap_or_else(|e| {
         eprintln!("error: failed to read `{path}`: {e}");
-        std::process::exit(2);
-    })
+        exit(Exit::Io);
+    });
+    match plot::Raster::from_bytes(&bytes) {
+        Ok(raster) => print!("{}", plot::render_ascii(&raster, width)),
+        Err(diag) => {
+            eprintln!("error: {path}: {diag}");
+            exit(Exit::Parse);
+        }
+    }
+}
+
+fn cmd_serve(args: ServeArgs) {
+    let ServeArgs {
+        host,
+        port,
+        workers,
+        data_dir,
+        allow_external,
+        max_neurons,
+        max_synapses,
+        max_memory,
+        max_steps,
+        keep_jobs,
+    } = args;
+    let workers =
+        workers.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+
+    let options = serve::ServeOptions {
+        workers,
+        data_dir,
+        allow_external,
+        max_neurons,
+        max_synapses,
+        max_memory_bytes: max_memory.saturating_mul(1 << 20),
+        max_steps,
+        keep_jobs,
+    };
+    if let Err(e) = serve::serve(&host, port, options) {
+        eprintln!("error: failed to serve on {host}:{port}: {e}");
+        exit(Exit::Io);
+    }
+}
+
+fn cmd_cosim(args: CosimArgs) {
+    let CosimArgs {
+        file: path,
+        listen: addr,
+        max_states,
+        engine,
+    } = args;
+    let mut config = engine.config();
+
+    let src = read_file(&path);
+    let program = match parse_local(&src) {
+        Ok(p) => p,
+        Err(diag) => {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+            exit(Exit::Parse);
+        }
+    };
+    if let Err(diags) = validate_with_warnings(&src, &program) {
+        for diag in diags {
+            eprintln!("{}", format_diagnostic(&src, &diag));
+        }
+        exit(Exit::Invalid);
+    }
+
+    config.data_dir = data_dir(&path);
+    let sim = match Simulator::new(&program, &config) {
+        Ok(sim) => sim,
+        Err(err) => {
+            eprintln!("{}", format_sim_error(&src, &err));
+            exit(Exit::Simulation);
+        }
+    };
+    if let Err(e) = cosim::serve(sim, addr, max_states) {
+        eprintln!("error: failed to serve on {addr}: {e}");
+        exit(Exit::Io);
+    }
 }
 
-fn print_usage() {
+fn cmd_batch(args: BatchArgs) {
+    let BatchArgs {
+        patterns,
+        jobs,
+        out: out_dir,
+        engine,
+    } = args;
+    let jobs = jobs.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let config = engine.config();
+
+    let mut inputs = Vec::new();
+    for pattern in &patterns {
+        match glob::expand(pattern) {
+            Ok(paths) if paths.is_empty() => {
+                eprintln!("warning: `{pattern}` matched no files");
+            }
+            Ok(paths) => inputs.extend(paths),
+            Err(e) => {
+                eprintln!("error: failed to expand `{pattern}`: {e}");
+                exit(Exit::Io);
+            }
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    inputs.retain(|p| seen.insert(p.clone()));
+    if inputs.is_empty() {
+        eprintln!("error: no input files");
+        exit(Exit::Usage);
+    }
+
+    let out = Path::new(&out_dir);
+    if let Err(e) = std::fs::create_dir_all(out) {
+        eprintln!("error: failed to create `{out_dir}`: {e}");
+        exit(Exit::Io);
+    }
+
+    let results = batch::run_batch(&inputs, out, &config, jobs, |r| {
+        eprintln!("{:<13} {}", r.status.as_str(), r.input.display());
+        for error in &r.errors {
+            for line in error.lines() {
+                eprintln!("    {line}");
+            }
+        }
+    });
+
+    let index = out.join("index.json");
+    if let Err(e) = std::fs::write(&index, batch::index_json(&results)) {
+        eprintln!("error: failed to write `{}`: {e}", index.display());
+        exit(Exit::Io);
+    }
+    let failed = results
+        .iter()
+        .filter(|r| r.status != batch::Status::Ok)
+        .count();
     eprintln!(
-        "\
-converge: neuromorphic language toolchain (pre-α)
-
-USAGE:
-  converge <command> <file>
-
-COMMANDS:
-  check   Parse + validate a Converge file
-  ast     Print parsed AST (debug)
-  cvir    Emit canonical JSON IR (debug)
-  sim     Run deterministic simulator
-  help    Show this help
-
-EXAMPLES:
-  cargo run -p converge-cli -- check examples/hello.cv
-  cargo run -p converge-cli -- ast   examples/hello.cv
-  cargo run -p converge-cli -- cvir  examples/hello.cv
-  cargo run -p converge-cli -- sim   examples/poisson.cv
-"
+        "{} ok, {failed} failed; index written to {}",
+        results.len() - failed,
+        index.display()
+    );
+    if failed > 0 {
+        exit(Exit::Failed);
+    }
+}
+
+/// Width for terminal plots: `COLUMNS` when the shell exports it, else 80.
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(80)
+}
+
+/// Spike counts, and accuracy when there is a `readout`, of a quantized run
+/// next to the same run with float weights.
+fn quantization_report(quantize: Quantize, quantized: &SimSummary, float: &SimSummary) -> String {
+    let delta = |q: u64, f: u64| {
+        if f == 0 {
+            format!("{:+}", q as i64 - f as i64)
+        } else {
+            format!("{:+.1}%", (q as f64 - f as f64) / f as f64 * 100.0)
+        }
+    };
+    let mut out = format!(
+        "  {:<16} {:>10} {:>10} {:>9}\n",
+        format!("{quantize} weights"),
+        "quantized",
+        "float",
+        "delta"
     );
+    out.push_str(&format!(
+        "  {:<16} {:>10} {:>10} {:>9}\n",
+        "spikes",
+        quantized.total_spikes,
+        float.total_spikes,
+        delta(quantized.total_spikes, float.total_spikes)
+    ));
+    for (q, f) in quantized.layers.iter().zip(&float.layers) {
+        out.push_str(&format!(
+            "  {:<16} {:>10} {:>10} {:>9}\n",
+            q.name,
+            q.spikes,
+            f.spikes,
+            delta(q.spikes, f.spikes)
+        ));
+    }
+    if let (Some(q), Some(f)) = (&quantized.classification, &float.classification) {
+        out.push_str(&format!(
+            "  {:<16} {:>10.3} {:>10.3} {:>+9.3}\n",
+            "accuracy",
+            q.accuracy(),
+            f.accuracy(),
+            q.accuracy() - f.accuracy()
+        ));
+    }
+    out
+}
+
+/// `converge sim --dump-state` and its options.
+struct DumpRequest {
+    path: String,
+    every: u64,
+    layers: Vec<String>,
+    limit: u64,
+}
+
+/// Set by the global `--strict` flag: warnings fail validation.
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// [`validate`] for commands that go on to use the program: prints its
+/// warnings, or under `--strict` reports them as errors.
+fn validate_with_warnings(src: &str, program: &Program) -> Result<(), Vec<Diagnostic>> {
+    if STRICT.load(Ordering::Relaxed) {
+        return validate_strict(program);
+    }
+    for warning in warnings(program) {
+        eprintln!("{}", format_warning(src, &warning));
+    }
+    validate(program)
+}
+
+fn run_with_progress(
+    program: &Program,
+    config: &SimConfig,
+    sinks: Vec<Box<dyn RecordSink + Send>>,
+    dump: Option<&DumpRequest>,
+    show_progress: bool,
+) -> Result<Simulator, SimError> {
+    let mut sim = Simulator::new(program, config)?;
+    for sink in sinks {
+        sim.add_sink(sink)?;
+    }
+    let mut dump = dump
+        .map(|req| {
+            let out = std::fs::File::create(&req.path)
+                .map_err(|e| SimError::io(format!("failed to write `{}`", req.path), e))?;
+            StateDump::new(
+                std::io::BufWriter::new(out),
+                &sim,
+                req.every,
+                &req.layers,
+                req.limit,
+            )
+            .map_err(|e| {
+                SimError::new(SimErrorKind::InvalidInput(format!(
+                    "{e}; dump fewer layers (--dump-layers), fewer steps (--dump-every) \
+                     or raise --dump-limit"
+                )))
+            })
+        })
+        .transpose()?;
+    let dump_err = |e: std::io::Error| SimError::io("failed to write the state dump", e);
+    let mut progress = Progress::new(show_progress);
+    let _phase = tracing::info_span!("simulate").entered();
+    while !sim.is_finished() {
+        sim.step()?;
+        if let Some(dump) = &mut dump {
+            dump.after_step(&sim).map_err(dump_err)?;
+        }
+        progress.update(&sim);
+    }
+    progress.finish(&sim);
+    if let Some(dump) = dump {
+        dump.finish().map_err(dump_err)?;
+    }
+    Ok(sim)
+}
+
+fn run_with_tui(
+    program: &Program,
+    config: &SimConfig,
+    sinks: Vec<Box<dyn RecordSink + Send>>,
+    title: &str,
+) -> Result<Simulator, SimError> {
+    let mut sim = Simulator::new(program, config)?;
+    for sink in sinks {
+        sim.add_sink(sink)?;
+    }
+    let _phase = tracing::info_span!("simulate").entered();
+    match tui::run_tui(&mut sim, title)? {
+        tui::TuiExit::Finished => Ok(sim),
+        tui::TuiExit::Quit => {
+            eprintln!(
+                "interrupted at step {}/{}",
+                sim.current_step(),
+                sim.total_steps()
+            );
+            exit(Exit::Interrupted);
+        }
+    }
+}
+
+fn init_logging(verbose: bool) {
+    use tracing_subscriber::EnvFilter;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    let filter = if verbose {
+        EnvFilter::new("converge=debug,converge_lang=debug,converge_sim=debug")
+    } else if std::env::var_os("RUST_LOG").is_some() {
+        EnvFilter::from_default_env()
+    } else {
+        return;
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+/// Writes the weights of every connection that learned during the run to
+/// `dir/<name>.csv`, and the delays in steps of those that learned delays
+/// to `dir/<name>.delays.csv`, creating `dir`, and returns the paths.
+fn write_weights(sim: &Simulator, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    for idx in sim.trained_connections() {
+        let stem = connection_file_stem(sim, idx);
+        let mut matrices = vec![(format!("{stem}.csv"), sim.weights(idx))];
+        if sim.connection(idx).learns_delays {
+            matrices.push((format!("{stem}.delays.csv"), sim.delays(idx)));
+        }
+        for (name, matrix) in matrices {
+            let path = dir.join(name);
+            let mut w = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            converge_sim::write_weights_csv(&mut w, &matrix)?;
+            w.flush()?;
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
+
+/// Writes the weights of every chemical connection as a grayscale image to
+/// `dir/<name>.pgm`, creating `dir`, and returns the paths.
+fn write_weight_images(sim: &Simulator, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    for idx in 0..sim.connection_count() {
+        if sim.connection(idx).kind != ConnectKind::Chemical {
+            continue;
+        }
+        let path = dir.join(format!("{}.pgm", connection_file_stem(sim, idx)));
+        let mut w = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        converge_sim::write_weights_pgm(&mut w, &sim.weights(idx))?;
+        w.flush()?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// The connection's name, or `<source>_<target>` for an unnamed one.
+fn connection_file_stem(sim: &Simulator, idx: usize) -> String {
+    let conn = sim.connection(idx);
+    match conn.name {
+        Some(name) => name.to_string(),
+        None => format!(
+            "{}_{}",
+            sim.layer(conn.src_layer).name,
+            sim.layer(conn.dst_layer).name
+        ),
+    }
+}
+
+/// Data files named by a model, such as `Encode` CSVs, resolve next to it.
+fn data_dir(path: &str) -> Option<PathBuf> {
+    Path::new(path).parent().map(Path::to_path_buf)
+}
+
+fn read_profile(path: &str) -> TargetProfile {
+    let src = read_file(path);
+    TargetProfile::from_toml(&src).unwrap_or_else(|diag| {
+        eprintln!("{path}: {}", format_diagnostic(&src, &diag));
+        exit(Exit::Parse);
+    })
+}
+
+/// `err` shown against the model's source when it points into it, the way
+/// [`format_diagnostic`] shows parse and validation errors.
+fn format_sim_error(src: &str, err: &SimError) -> String {
+    match err.span {
+        Some(_) => format_diagnostic(src, &err.to_diagnostic()),
+        None => format!("error: {err}"),
+    }
+}
+
+/// Parses a program the user runs locally, so `${NAME}` and
+/// `seed from_env(...)` read the process environment.
+fn parse_local(src: &str) -> Result<Program, Diagnostic> {
+    parse_program_with_env(src, &[], &process_env)
+}
+
+fn read_file(path: &str) -> String {
+    read_source(Path::new(path)).unwrap_or_else(|e| {
+        eprintln!("error: failed to read `{path}`: {e}");
+        exit(Exit::Io);
+    })
+}
+
+/// Reads a program from `path`, or from stdin when the path is `-`.
+fn read_source(path: &Path) -> std::io::Result<String> {
+    if path == Path::new("-") {
+        let mut src = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut src)?;
+        Ok(src)
+    } else {
+        std::fs::read_to_string(path)
+    }
 }
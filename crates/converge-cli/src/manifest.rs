@@ -0,0 +1,118 @@
+//! `converge sim --manifest`: one JSON document that ties a run together,
+//! to archive next to the results it describes.
+//!
+//! ```text
+//! manifest_version   raised whenever a field changes meaning or is removed
+//! toolchain_version  the toolchain that ran the model
+//! platform           `os`, `arch` and `family`, as Rust names them
+//! file, network      the model file and the network run from it
+//! input_hash         FNV-1a of the program text, as in the summary
+//! cvir_hash          FNV-1a of the CVIR of the model as run, after
+//!                    `--set`, `${...}` and `--network`
+//! seed, streams      the run's seed and the seed of each random stream
+//! spike_hash         the summary's hash of every spike
+//! outputs            every file the run wrote, with its size and FNV-1a
+//! ```
+//!
+//! A summary printed to stdout is listed among the outputs as `-`.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use converge_lang::ast::Program;
+use converge_lang::emit::cvir_json;
+use converge_lang::json::Value;
+use converge_sim::{SimSummary, content_hash, format_spike_hash, source_hash, streams_json};
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A file the run wrote.
+pub struct Output {
+    pub path: String,
+    pub bytes: u64,
+    pub hash: u64,
+}
+
+impl Output {
+    /// The file at `path`, read back.
+    pub fn file(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let bytes = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_string_lossy().into_owned(),
+            bytes,
+            hash: content_hash(io::BufReader::new(file))?,
+        })
+    }
+
+    /// Text printed to stdout.
+    pub fn stdout(text: &str) -> Self {
+        Self {
+            path: "-".to_string(),
+            bytes: text.len() as u64,
+            hash: source_hash(text),
+        }
+    }
+}
+
+/// The manifest of a run of `program`, the model as run, from the text
+/// `src` of `file`.
+pub fn manifest_json(
+    file: &str,
+    network: Option<&str>,
+    src: &str,
+    program: &Program,
+    summary: &SimSummary,
+    outputs: &[Output],
+) -> String {
+    let string = |s: &str| Value::String(s.to_string()).to_string();
+    let hash = |h: u64| string(&format_spike_hash(h));
+    let outputs: Vec<String> = outputs
+        .iter()
+        .map(|o| {
+            format!(
+                "    {{ \"path\": {}, \"bytes\": {}, \"hash\": {} }}",
+                string(&o.path),
+                o.bytes,
+                hash(o.hash)
+            )
+        })
+        .collect();
+    let outputs = if outputs.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n  ]", outputs.join(",\n"))
+    };
+    let mut s = String::from("{\n");
+    s.push_str(&format!("  \"manifest_version\": {MANIFEST_VERSION},\n"));
+    s.push_str(&format!(
+        "  \"toolchain_version\": {},\n",
+        string(env!("CARGO_PKG_VERSION"))
+    ));
+    s.push_str(&format!(
+        "  \"platform\": {{ \"os\": {}, \"arch\": {}, \"family\": {} }},\n",
+        string(std::env::consts::OS),
+        string(std::env::consts::ARCH),
+        string(std::env::consts::FAMILY)
+    ));
+    s.push_str(&format!("  \"file\": {},\n", string(file)));
+    s.push_str(&format!(
+        "  \"network\": {},\n",
+        network.map_or("null".to_string(), string)
+    ));
+    s.push_str(&format!("  \"input_hash\": {},\n", hash(source_hash(src))));
+    s.push_str(&format!(
+        "  \"cvir_hash\": {},\n",
+        hash(source_hash(&cvir_json(program)))
+    ));
+    s.push_str(&format!("  \"seed\": {},\n", summary.seed));
+    s.push_str(&format!("  \"streams\": {},\n", streams_json(summary.seed)));
+    s.push_str(&format!(
+        "  \"spike_hash\": {},\n",
+        hash(summary.spike_hash)
+    ));
+    s.push_str(&format!("  \"outputs\": {outputs}\n"));
+    s.push_str("}\n");
+    s
+}
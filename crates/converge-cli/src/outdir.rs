@@ -0,0 +1,104 @@
+//! `converge sim --out-dir`: every artifact of a run in one directory,
+//! instead of a flag per file.
+//!
+//! ```text
+//! results/run1/
+//!   summary.json           the summary `converge sim` prints
+//!   spikes.ndjson          every spike, as `--spikes` writes them
+//!   weights/<conn>.csv     final weights of plastic connections, if any
+//!   effective-config.json  the file, parameters and settings of the run
+//!   correlations.json      with `--analyze correlations`
+//! ```
+
+use std::io;
+use std::path::Path;
+
+use converge_lang::ast::Program;
+use converge_lang::json::Value;
+use converge_sim::{SimSummary, run_config_json};
+
+pub const SUMMARY: &str = "summary.json";
+pub const SPIKES: &str = "spikes.ndjson";
+pub const WEIGHTS: &str = "weights";
+pub const CONFIG: &str = "effective-config.json";
+pub const CORRELATIONS: &str = "correlations.json";
+
+pub enum PrepareError {
+    /// The directory has files in it and `--force` was not given.
+    NotEmpty,
+    Io(io::Error),
+}
+
+/// Creates `dir`, or checks that an existing one is empty. With `force`, an
+/// existing directory is reused and its `weights` from an earlier run are
+/// removed, so they can't be mistaken for this run's.
+pub fn prepare(dir: &Path, force: bool) -> Result<(), PrepareError> {
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => {
+            if entries.next().is_some() && !force {
+                return Err(PrepareError::NotEmpty);
+            }
+            let weights = dir.join(WEIGHTS);
+            if weights.is_dir() {
+                std::fs::remove_dir_all(weights).map_err(PrepareError::Io)?;
+            }
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            std::fs::create_dir_all(dir).map_err(PrepareError::Io)
+        }
+        Err(e) => Err(PrepareError::Io(e)),
+    }
+}
+
+/// The settings a run used, enough to repeat it: the model file and
+/// network, the `params` after `--set`, the seed and the simulator settings
+/// as in the summary's `config`.
+pub fn effective_config(
+    file: &str,
+    network: Option<&str>,
+    program: &Program,
+    summary: &SimSummary,
+) -> String {
+    let string = |s: &str| Value::String(s.to_string()).to_string();
+    let params: Vec<String> = program
+        .params
+        .iter()
+        .map(|p| {
+            format!(
+                "    {}: {}",
+                string(&p.key.name),
+                string(&p.value.to_string())
+            )
+        })
+        .collect();
+    let params = if params.is_empty() {
+        "{}".to_string()
+    } else {
+        format!("{{\n{}\n  }}", params.join(",\n"))
+    };
+    let mut s = String::from("{\n");
+    s.push_str(&format!("  \"file\": {},\n", string(file)));
+    s.push_str(&format!(
+        "  \"network\": {},\n",
+        network.map_or("null".to_string(), string)
+    ));
+    s.push_str(&format!("  \"params\": {params},\n"));
+    s.push_str(&format!("  \"seed\": {},\n", summary.seed));
+    s.push_str(&format!(
+        "  \"quantize\": {},\n",
+        summary
+            .quantize
+            .map_or("null".to_string(), |q| string(&q.to_string()))
+    ));
+    s.push_str(&format!(
+        "  \"arithmetic\": {},\n",
+        string(&summary.arithmetic.to_string())
+    ));
+    s.push_str(&format!(
+        "  \"sim\": {}\n",
+        run_config_json(&summary.config)
+    ));
+    s.push_str("}\n");
+    s
+}
@@ -0,0 +1,253 @@
+//! Terminal spike rasters and per-layer rate sparklines.
+
+use std::fmt::Write;
+
+use converge_lang::diagnostic::Diagnostic;
+use converge_lang::json::{self, Value};
+use converge_sim::{SimSummary, Spike, raster};
+
+const DENSITY: [char; 5] = [' ', '.', ':', '*', '#'];
+const SPARK: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Neuron rows drawn per layer; larger layers are binned into this many rows.
+const MAX_ROWS: u64 = 8;
+
+/// A raster with enough context to draw it: run timing and layer sizes.
+#[derive(Debug, Clone)]
+pub struct Raster {
+    pub duration_ns: i64,
+    pub step_ns: i64,
+    pub layers: Vec<(String, u64)>,
+    pub spikes: Vec<Spike>,
+}
+
+impl Raster {
+    pub fn from_summary(summary: &SimSummary) -> Self {
+        Self {
+            duration_ns: summary.duration_ns,
+            step_ns: summary.step_ns,
+            layers: summary
+                .layers
+                .iter()
+                .map(|l| (l.name.clone(), l.size))
+                .collect(),
+            spikes: summary.raster.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Reads a recording written by `converge sim --spikes`, binary or
+    /// NDJSON.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Diagnostic> {
+        if !raster::is_binary(bytes) {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| Diagnostic::new("spike file: neither NDJSON nor binary"))?;
+            return Self::from_ndjson(text);
+        }
+        let recording = raster::decode(bytes)?;
+        Ok(Self {
+            duration_ns: recording.duration_ns,
+            step_ns: recording.step_ns,
+            layers: recording.layers,
+            spikes: recording.spikes,
+        })
+    }
+
+    /// Reads the NDJSON written by `converge sim --spikes`.
+    pub fn from_ndjson(text: &str) -> Result<Self, Diagnostic> {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let header = json::parse(lines.next().unwrap_or("{}"))?;
+        let field = |value: &Value, key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_f64)
+                .ok_or_else(|| Diagnostic::new(format!("spike file: missing `{key}`")))
+        };
+        let duration_ns = field(&header, "duration_ns")? as i64;
+        let step_ns = field(&header, "step_ns")? as i64;
+        let mut layers = Vec::new();
+        for layer in header
+            .get("layers")
+            .and_then(Value::as_array)
+            .unwrap_or(&[])
+        {
+            let name = layer
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| Diagnostic::new("spike file: layer without a name"))?;
+            layers.push((name.to_string(), field(layer, "size")? as u64));
+        }
+
+        let mut spikes = Vec::new();
+        for line in lines {
+            let value = json::parse(line)?;
+            // Membrane samples, from a sink with traced neurons.
+            if value.get("v").is_some() {
+                continue;
+            }
+            let name = value
+                .get("layer")
+                .and_then(Value::as_str)
+                .ok_or_else(|| Diagnostic::new("spike file: spike without a layer"))?;
+            let layer = layers
+                .iter()
+                .position(|(n, _)| n == name)
+                .ok_or_else(|| Diagnostic::new(format!("spike file: unknown layer `{name}`")))?;
+            spikes.push(Spike {
+                step: field(&value, "step")? as u64,
+                layer: layer as u32,
+                neuron: field(&value, "neuron")? as u64,
+            });
+        }
+
+        Ok(Self {
+            duration_ns,
+            step_ns,
+            layers,
+            spikes,
+        })
+    }
+}
+
+/// Draws a coarse raster `width` columns wide: each layer gets up to
+/// `MAX_ROWS` rows of binned neurons, shaded by spike count, followed by a
+/// sparkline of its population rate.
+pub fn render_ascii(raster: &Raster, width: usize) -> String {
+    let label_w = raster
+        .layers
+        .iter()
+        .map(|(name, size)| name.len() + size.to_string().len() + 3)
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    let steps = (raster.duration_ns / raster.step_ns.max(1)).max(1) as u64;
+    // Never more columns than steps, so no column is left structurally empty.
+    let width = width
+        .saturating_sub(label_w + 3)
+        .max(10)
+        .min(steps as usize);
+    let column = |step: u64| ((step * width as u64) / steps).min(width as u64 - 1) as usize;
+
+    let mut s = String::new();
+    for (layer_idx, (name, size)) in raster.layers.iter().enumerate() {
+        let rows = (*size).clamp(1, MAX_ROWS);
+        let per_row = size.div_ceil(rows).max(1);
+        let mut cells = vec![vec![0u64; width]; rows as usize];
+        let mut per_col = vec![0u64; width];
+        for spike in raster
+            .spikes
+            .iter()
+            .filter(|s| s.layer as usize == layer_idx)
+        {
+            let col = column(spike.step);
+            let row = (spike.neuron / per_row).min(rows - 1) as usize;
+            cells[row][col] += 1;
+            per_col[col] += 1;
+        }
+
+        let peak = cells.iter().flatten().copied().max().unwrap_or(0).max(1);
+        for (row_idx, row) in cells.iter().enumerate() {
+            let label = if row_idx == 0 {
+                format!("{name} ({size})")
+            } else {
+                String::new()
+            };
+            let line: String = row
+                .iter()
+                .map(|&count| shade(count, peak, &DENSITY))
+                .collect();
+            let _ = writeln!(s, "{label:<label_w$} |{line}|");
+        }
+
+        // Population rate per column in Hz: spikes / (neurons * column time).
+        let col_s = raster.duration_ns as f64 / 1e9 / width as f64;
+        let rates: Vec<f64> = per_col
+            .iter()
+            .map(|&c| c as f64 / (*size.max(&1) as f64 * col_s))
+            .collect();
+        let max_rate = rates.iter().copied().fold(0.0, f64::max);
+        let spark: String = per_col
+            .iter()
+            .map(|&count| {
+                if count == 0 {
+                    ' '
+                } else {
+                    shade(count, per_col.iter().copied().max().unwrap_or(1), &SPARK)
+                }
+            })
+            .collect();
+        let _ = writeln!(s, "{:<label_w$}  {spark}  max {max_rate:.1} Hz", "  rate");
+    }
+
+    let end = format!("{} ms", raster.duration_ns as f64 / 1e6);
+    let _ = writeln!(
+        s,
+        "{:<label_w$}  0 ms{end:>pad$}",
+        "",
+        pad = width.saturating_sub(4)
+    );
+    s
+}
+
+fn shade(count: u64, peak: u64, ramp: &[char]) -> char {
+    if count == 0 {
+        return ramp[0];
+    }
+    let levels = ramp.len() as u64 - 1;
+    let level = (count * levels).div_ceil(peak.max(1)).clamp(1, levels);
+    ramp[level as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raster() -> Raster {
+        Raster {
+            duration_ns: 10_000_000,
+            step_ns: 1_000_000,
+            layers: vec![("A".to_string(), 2), ("B".to_string(), 1)],
+            spikes: vec![
+                Spike {
+                    step: 0,
+                    layer: 0,
+                    neuron: 0,
+                },
+                Spike {
+                    step: 9,
+                    layer: 0,
+                    neuron: 1,
+                },
+                Spike {
+                    step: 5,
+                    layer: 1,
+                    neuron: 0,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn renders_one_row_per_neuron_for_small_layers() {
+        let text = render_ascii(&raster(), 20);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].starts_with("A (2)"));
+        assert!(lines[0].contains("|#"));
+        assert!(lines[1].ends_with("#|"));
+        assert!(lines[3].starts_with("B (1)"));
+        assert!(text.contains("  rate"));
+    }
+
+    #[test]
+    fn reads_ndjson_written_by_sim() {
+        let text = "{\"duration_ns\":10000000,\"step_ns\":1000000,\"seed\":0,\"layers\":[{\"name\":\"A\",\"size\":2}]}\n{\"step\":4,\"t_ns\":4000000,\"layer\":\"A\",\"neuron\":1}\n";
+        let raster = Raster::from_ndjson(text).expect("parse");
+        assert_eq!(raster.layers, vec![("A".to_string(), 2)]);
+        assert_eq!(
+            raster.spikes,
+            vec![Spike {
+                step: 4,
+                layer: 0,
+                neuron: 1
+            }]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use converge_sim::Simulator;
+
+const FIRST_REPORT: Duration = Duration::from_millis(500);
+const TTY_INTERVAL: Duration = Duration::from_millis(200);
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+const BAR_WIDTH: usize = 24;
+
+/// Progress on stderr for long runs. Runs that finish before the first report
+/// print nothing; a terminal gets a redrawn bar, anything else gets lines.
+pub struct Progress {
+    started: Instant,
+    last_report: Option<Instant>,
+    tty: bool,
+    enabled: bool,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            last_report: None,
+            tty: std::io::stderr().is_terminal(),
+            enabled,
+        }
+    }
+
+    pub fn update(&mut self, sim: &Simulator) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        let due = match self.last_report {
+            None => now - self.started >= FIRST_REPORT,
+            Some(last) => {
+                let interval = if self.tty { TTY_INTERVAL } else { LOG_INTERVAL };
+                now - last >= interval
+            }
+        };
+        if due {
+            self.report(sim, now);
+        }
+    }
+
+    pub fn finish(&mut self, sim: &Simulator) {
+        if self.enabled && self.last_report.is_some() {
+            self.report(sim, Instant::now());
+            if self.tty {
+                eprintln!();
+            }
+        }
+    }
+
+    fn report(&mut self, sim: &Simulator, now: Instant) {
+        self.last_report = Some(now);
+        let done = sim.current_step();
+        let total = sim.total_steps().max(1);
+        let fraction = done as f64 / total as f64;
+        let elapsed = (now - self.started).as_secs_f64().max(1e-9);
+        let line = format!(
+            "{:>5.1}%  {:.3} s / {:.3} s simulated  spikes {}  {:.0} steps/s",
+            fraction * 100.0,
+            done as f64 * sim.step_ns() as f64 / 1e9,
+            sim.duration_ns() as f64 / 1e9,
+            sim.total_spikes(),
+            done as f64 / elapsed
+        );
+        let mut err = std::io::stderr().lock();
+        if self.tty {
+            let filled = ((fraction * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+            let _ = write!(
+                err,
+                "\r[{}{}] {line}",
+                "#".repeat(filled),
+                " ".repeat(BAR_WIDTH - filled)
+            );
+            let _ = err.flush();
+        } else {
+            let _ = writeln!(err, "progress: {line}");
+        }
+    }
+}
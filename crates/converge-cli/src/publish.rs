@@ -0,0 +1,256 @@
+//! `converge sim --publish tcp://host:port`: live run output for external
+//! visualizers and closed-loop controllers.
+//!
+//! Subscribers connect over plain TCP and receive NDJSON messages as the
+//! run produces them, starting with the run header:
+//!
+//! ```text
+//! {"type":"start","duration_ns":..,"step_ns":..,"seed":..,"layers":[{"name":"A","size":8}]}
+//! {"type":"spike","step":3,"t_ns":3000000,"layer":"A","neuron":5}
+//! {"type":"rates","t_ns":100000000,"window_ns":100000000,"rates_hz":{"A":12.5}}
+//! {"type":"summary","total_spikes":412,"spike_hash":"..."}
+//! ```
+//!
+//! `rates` gives each layer's mean firing rate over the last `window_ns` of
+//! simulated time, every [`RATE_WINDOW_NS`] and at the end of the run.
+//! Subscribers only see messages sent after they connect, and one that
+//! falls [`QUEUE`] messages behind misses messages until it catches up, so
+//! a slow client never stalls the run.
+
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use converge_sim::{RecordSink, RunInfo, SimSummary, Spike, format_spike_hash};
+
+/// Simulated time between `rates` messages.
+pub const RATE_WINDOW_NS: i64 = 100_000_000;
+/// Messages buffered per subscriber.
+const QUEUE: usize = 1024;
+
+struct Subscriber {
+    tx: SyncSender<Arc<str>>,
+    writer: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    /// The `start` message, for subscribers connecting after it was sent.
+    header: Option<Arc<str>>,
+    list: Vec<Subscriber>,
+}
+
+impl Subscribers {
+    fn send(&mut self, message: &Arc<str>) {
+        self.list
+            .retain(|s| match s.tx.try_send(Arc::clone(message)) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
+
+pub struct Publisher {
+    addr: SocketAddr,
+    subscribers: Arc<Mutex<Subscribers>>,
+    run: Option<RunInfo>,
+    /// Steps per `rates` window.
+    window_steps: u64,
+    /// Spikes per layer since the window started.
+    counts: Vec<u64>,
+    window_start: u64,
+    /// Steps run so far.
+    steps: u64,
+    /// Messages of the current step, sent together when it ends.
+    pending: String,
+}
+
+impl Publisher {
+    /// Listens on `address`, which must be `tcp://host:port`.
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let Some(host_port) = address.strip_prefix("tcp://") else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported publish address `{address}` (expected tcp://host:port)"),
+            ));
+        };
+        let listener = TcpListener::bind(host_port)?;
+        let addr = listener.local_addr()?;
+        let subscribers = Arc::new(Mutex::new(Subscribers::default()));
+        let shared = Arc::clone(&subscribers);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut subscribers = shared.lock().expect("subscribers lock");
+                let subscriber = subscribe(stream);
+                if let Some(header) = &subscribers.header {
+                    let _ = subscriber.tx.try_send(Arc::clone(header));
+                }
+                subscribers.list.push(subscriber);
+            }
+        });
+        Ok(Self {
+            addr,
+            subscribers,
+            run: None,
+            window_steps: 1,
+            counts: Vec::new(),
+            window_start: 0,
+            steps: 0,
+            pending: String::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Blocks until `n` subscribers are connected.
+    pub fn wait_for(&self, n: usize) {
+        while self
+            .subscribers
+            .lock()
+            .expect("subscribers lock")
+            .list
+            .len()
+            < n
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn send(&mut self, message: String) {
+        let message: Arc<str> = message.into();
+        self.subscribers
+            .lock()
+            .expect("subscribers lock")
+            .send(&message);
+    }
+
+    /// Appends the `rates` message of the window ending before `end`.
+    fn close_window(&mut self, end: u64) {
+        let Some(run) = &self.run else {
+            return;
+        };
+        if end <= self.window_start {
+            return;
+        }
+        let window_ns = (end - self.window_start) as i64 * run.step_ns;
+        let rates: Vec<String> = run
+            .layers
+            .iter()
+            .zip(&self.counts)
+            .map(|((name, size), &count)| {
+                let hz = if *size == 0 {
+                    0.0
+                } else {
+                    count as f64 / *size as f64 / (window_ns as f64 / 1e9)
+                };
+                format!("\"{name}\":{hz}")
+            })
+            .collect();
+        self.pending.push_str(&format!(
+            "{{\"type\":\"rates\",\"t_ns\":{},\"window_ns\":{window_ns},\"rates_hz\":{{{}}}}}\n",
+            end as i64 * run.step_ns,
+            rates.join(",")
+        ));
+        self.counts.iter_mut().for_each(|c| *c = 0);
+        self.window_start = end;
+    }
+}
+
+/// Starts a thread writing queued messages to `stream` until it fails or
+/// the publisher finishes.
+fn subscribe(stream: TcpStream) -> Subscriber {
+    let (tx, rx) = mpsc::sync_channel::<Arc<str>>(QUEUE);
+    let writer = thread::spawn(move || {
+        let mut out = io::BufWriter::new(stream);
+        while let Ok(message) = rx.recv() {
+            let mut next = Some(message);
+            while let Some(message) = next {
+                if out.write_all(message.as_bytes()).is_err() {
+                    return;
+                }
+                next = rx.try_recv().ok();
+            }
+            // Flush once the backlog is written, so messages arrive live.
+            if out.flush().is_err() {
+                return;
+            }
+        }
+    });
+    Subscriber { tx, writer }
+}
+
+impl RecordSink for Publisher {
+    fn on_start(&mut self, run: &RunInfo) -> io::Result<()> {
+        let layers: Vec<String> = run
+            .layers
+            .iter()
+            .map(|(name, size)| format!("{{\"name\":\"{name}\",\"size\":{size}}}"))
+            .collect();
+        let header: Arc<str> = format!(
+            "{{\"type\":\"start\",\"duration_ns\":{},\"step_ns\":{},\"seed\":{},\"layers\":[{}]}}\n",
+            run.duration_ns,
+            run.step_ns,
+            run.seed,
+            layers.join(",")
+        )
+        .into();
+        let mut subscribers = self.subscribers.lock().expect("subscribers lock");
+        subscribers.send(&header);
+        subscribers.header = Some(header);
+        drop(subscribers);
+        self.window_steps = (RATE_WINDOW_NS / run.step_ns.max(1)).max(1) as u64;
+        self.counts = vec![0; run.layers.len()];
+        self.run = Some(run.clone());
+        Ok(())
+    }
+
+    fn on_spike(&mut self, spike: &Spike) -> io::Result<()> {
+        let Some(run) = &self.run else {
+            return Ok(());
+        };
+        self.pending.push_str(&format!(
+            "{{\"type\":\"spike\",\"step\":{},\"t_ns\":{},\"layer\":\"{}\",\"neuron\":{}}}\n",
+            spike.step,
+            spike.step as i64 * run.step_ns,
+            run.layers[spike.layer as usize].0,
+            spike.neuron
+        ));
+        self.counts[spike.layer as usize] += 1;
+        Ok(())
+    }
+
+    fn on_step_end(&mut self, step: u64) -> io::Result<()> {
+        self.steps = step + 1;
+        if self.steps - self.window_start >= self.window_steps {
+            self.close_window(self.steps);
+        }
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.send(pending);
+        }
+        Ok(())
+    }
+
+    fn on_summary(&mut self, summary: &SimSummary) -> io::Result<()> {
+        self.close_window(self.steps);
+        self.pending.push_str(&format!(
+            "{{\"type\":\"summary\",\"total_spikes\":{},\"spike_hash\":\"{}\"}}\n",
+            summary.total_spikes,
+            format_spike_hash(summary.spike_hash)
+        ));
+        let pending = std::mem::take(&mut self.pending);
+        self.send(pending);
+        // Closing the queues lets every writer drain and exit.
+        let list = std::mem::take(&mut self.subscribers.lock().expect("subscribers lock").list);
+        for subscriber in list {
+            drop(subscriber.tx);
+            let _ = subscriber.writer.join();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt::Write;
+
+use converge_lang::ast::{Expr, Item, Program};
+use converge_lang::stats::TopologyStats;
+use converge_sim::{SimSummary, format_spike_hash};
+
+use crate::svg::{escape, raster_svg, topology_svg};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 960px; margin: 2em auto; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.2em; margin-top: 2em; border-bottom: 1px solid #ddd; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { padding: 0.25em 0.8em; border-bottom: 1px solid #eee; text-align: left; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+code { background: #f4f4f4; padding: 0 0.2em; }
+figure { margin: 0; overflow-x: auto; }
+.doc { color: #666; font-size: 0.9em; white-space: pre-line; }
+";
+
+/// A doc comment under an item's name.
+fn description(doc: Option<&str>) -> String {
+    doc.map(|doc| format!("<div class=\"doc\">{}</div>", escape(doc)))
+        .unwrap_or_default()
+}
+
+/// Renders a self-contained HTML page: topology diagram, parameter tables,
+/// run summary and, when the summary carries a raster, a spike raster plot.
+pub fn render_report(
+    title: &str,
+    program: &Program,
+    stats: &TopologyStats,
+    summary: &SimSummary,
+) -> String {
+    let mut s = String::new();
+    let _ = writeln!(
+        s,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>",
+        escape(title)
+    );
+    let _ = writeln!(s, "<h1>{}</h1>", escape(title));
+
+    s.push_str("<h2>Topology</h2>\n<figure>\n");
+    s.push_str(&topology_svg(stats));
+    s.push_str("</figure>\n");
+
+    s.push_str("<h2>Neurons</h2>\n<table>\n<tr><th>name</th><th>parameters</th></tr>\n");
+    for item in &program.items {
+        if let Item::Neuron(def) = item {
+            let params: Vec<String> = def
+                .body
+                .iter()
+                .map(|a| format!("{} = {}", a.key.name, a.value))
+                .collect();
+            let _ = writeln!(
+                s,
+                "<tr><td>{}{}</td><td><code>{}</code></td></tr>",
+                escape(&def.name.name),
+                description(def.doc.as_deref()),
+                escape(&params.join(", "))
+            );
+        }
+    }
+    s.push_str("</table>\n");
+
+    s.push_str("<h2>Layers</h2>\n<table>\n<tr><th>name</th><th>neuron</th><th>size</th><th>in-degree</th><th>out-degree</th></tr>\n");
+    for item in &program.items {
+        let Item::Layer(def) = item else {
+            continue;
+        };
+        let Some(layer) = stats.layers.iter().find(|l| l.name == def.name.name) else {
+            continue;
+        };
+        let _ = writeln!(
+            s,
+            "<tr><td>{}{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+            escape(&layer.name),
+            description(def.doc.as_deref()),
+            escape(&def.neuron.name),
+            layer.size,
+            layer.in_degree,
+            layer.out_degree
+        );
+    }
+    s.push_str("</table>\n");
+
+    s.push_str("<h2>Connections</h2>\n<table>\n<tr><th>connection</th><th>synapses</th><th>weight</th><th>delay</th></tr>\n");
+    for conn in &stats.connections {
+        let param = |expr: Option<&Expr>, default: &str| match expr {
+            Some(e) => e.to_string(),
+            None => format!("{default} (default)"),
+        };
+        let _ = writeln!(
+            s,
+            "<tr><td>{} &rarr; {}{}</td><td class=\"num\">{}</td><td><code>{}</code></td><td><code>{}</code></td></tr>",
+            escape(&conn.src),
+            escape(&conn.dst),
+            description(conn.description.as_deref()),
+            conn.synapses,
+            escape(&param(conn.weight.as_ref(), "1")),
+            escape(&param(conn.delay.as_ref(), "0 ms"))
+        );
+    }
+    let _ = writeln!(
+        s,
+        "<tr><th>total</th><td class=\"num\">{}</td><td></td><td></td></tr>\n</table>",
+        stats.total_synapses()
+    );
+
+    s.push_str("<h2>Stimuli</h2>\n<table>\n<tr><th>layer</th><th>model</th></tr>\n");
+    for item in &program.items {
+        if let Item::Stimulus(def) = item {
+            let model = def.model.to_string();
+            let _ = writeln!(
+                s,
+                "<tr><td>{}</td><td><code>{}</code></td></tr>",
+                escape(&def.layer.name),
+                escape(&model)
+            );
+        }
+    }
+    s.push_str("</table>\n");
+
+    let duration_s = summary.duration_ns as f64 / 1e9;
+    s.push_str("<h2>Run</h2>\n<table>\n");
+    let _ = writeln!(
+        s,
+        "<tr><th>duration</th><td class=\"num\">{} ms</td></tr>",
+        summary.duration_ns as f64 / 1e6
+    );
+    let _ = writeln!(
+        s,
+        "<tr><th>step</th><td class=\"num\">{} ms</td></tr>",
+        summary.step_ns as f64 / 1e6
+    );
+    let _ = writeln!(
+        s,
+        "<tr><th>seed</th><td class=\"num\">{}</td></tr>",
+        summary.seed
+    );
+    let _ = writeln!(
+        s,
+        "<tr><th>total spikes</th><td class=\"num\">{}</td></tr>",
+        summary.total_spikes
+    );
+    let _ = writeln!(
+        s,
+        "<tr><th>spike hash</th><td><code>{}</code></td></tr>\n</table>",
+        format_spike_hash(summary.spike_hash)
+    );
+
+    s.push_str("<table>\n<tr><th>layer</th><th>spikes</th><th>mean rate</th></tr>\n");
+    for layer in &summary.layers {
+        let rate = layer.spikes as f64 / (layer.size.max(1) as f64 * duration_s);
+        let _ = writeln!(
+            s,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{rate:.2} Hz</td></tr>",
+            escape(&layer.name),
+            layer.spikes
+        );
+    }
+    s.push_str("</table>\n");
+
+    if let Some(raster) = &summary.raster {
+        s.push_str("<h2>Spike raster</h2>\n<figure>\n");
+        s.push_str(&raster_svg(summary, raster));
+        s.push_str("</figure>\n");
+    }
+
+    s.push_str("</body>\n</html>\n");
+    s
+}
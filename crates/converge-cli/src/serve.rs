@@ -0,0 +1,510 @@
+//! `converge serve`: a small HTTP/1.1 simulation service on std networking.
+//!
+//! Programs are submitted as `.cv` source or CVIR JSON, validated up front,
+//! and queued for a fixed pool of worker threads. Clients poll job status and
+//! fetch the summary (and raster, when requested) once the job is done.
+//!
+//! ```text
+//! GET  /health                 {"status": "ok"}
+//! POST /jobs[?raster=1]        submit a program; 202 with the job id
+//! GET  /jobs                   every job with its status
+//! GET  /jobs/<id>              status and step progress
+//! GET  /jobs/<id>/summary      simulation summary, once done
+//! GET  /jobs/<id>/raster       recorded spikes, once done
+//! DELETE /jobs/<id>            forget a finished job
+//! ```
+//!
+//! Jobs read data files only from `--data-dir`, can't use `External`
+//! stimuli unless allowed and are refused when their estimated size or
+//! step count passes the server's limits. Only the last `--keep-jobs`
+//! finished jobs are kept. That still isn't enough for an open network:
+//! there is no authentication or TLS. Serve trusted clients only.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use converge_lang::ast::{Item, Program, StimulusModel};
+use converge_lang::cvir::program_from_cvir;
+use converge_lang::json::Value;
+use converge_lang::lexer::no_env;
+use converge_lang::parser::{format_diagnostic, parse_program_with_env};
+use converge_lang::validate::validate;
+use converge_sim::{SimConfig, SimSummary, Simulator, estimate, format_bytes, summary_json};
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// How long a client may leave the server waiting for its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+enum JobState {
+    Queued,
+    Running,
+    Done(Box<SimSummary>),
+    Failed(String),
+}
+
+struct Job {
+    state: Mutex<JobState>,
+    step: AtomicU64,
+    total_steps: AtomicU64,
+}
+
+impl Job {
+    fn is_finished(&self) -> bool {
+        matches!(
+            *self.state.lock().expect("job lock"),
+            JobState::Done(_) | JobState::Failed(_)
+        )
+    }
+}
+
+struct Queued {
+    job: Arc<Job>,
+    program: Program,
+    /// Boxed to keep channel messages small.
+    config: Box<SimConfig>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: u64,
+    jobs: BTreeMap<u64, Arc<Job>>,
+}
+
+/// How the server runs the programs it's sent.
+pub struct ServeOptions {
+    pub workers: usize,
+    /// The only directory jobs may read data files from; with none they may
+    /// read no files at all.
+    pub data_dir: Option<PathBuf>,
+    /// Run jobs with `External` stimuli, which listen on a local port and
+    /// wait for a controller.
+    pub allow_external: bool,
+    /// Refuse programs whose [`estimate`] passes any of these.
+    pub max_neurons: u64,
+    pub max_synapses: u64,
+    pub max_memory_bytes: u64,
+    pub max_steps: u64,
+    /// Finished jobs kept for clients to fetch; submitting a job forgets
+    /// the oldest ones past this.
+    pub keep_jobs: usize,
+}
+
+struct Server {
+    jobs: Mutex<Jobs>,
+    queue: Mutex<Sender<Queued>>,
+    options: ServeOptions,
+}
+
+pub fn serve(host: &str, port: u16, options: ServeOptions) -> io::Result<()> {
+    let listener = TcpListener::bind((host, port))?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+
+    let (sender, receiver) = mpsc::channel();
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..options.workers.max(1) {
+        let receiver = Arc::clone(&receiver);
+        thread::spawn(move || worker(&receiver));
+    }
+
+    let server = Arc::new(Server {
+        jobs: Mutex::new(Jobs::default()),
+        queue: Mutex::new(sender),
+        options,
+    });
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+            continue;
+        }
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            if let Err(err) = handle(&server, stream) {
+                tracing::debug!(%err, "connection failed");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn worker(receiver: &Mutex<Receiver<Queued>>) {
+    loop {
+        let next = receiver.lock().map(|r| r.recv());
+        let Ok(Ok(queued)) = next else {
+            return;
+        };
+        let Queued {
+            job,
+            program,
+            config,
+        } = queued;
+        *job.state.lock().expect("job lock") = JobState::Running;
+        let result = Simulator::new(&program, &config).and_then(|mut sim| {
+            job.total_steps.store(sim.total_steps(), Ordering::Relaxed);
+            while !sim.is_finished() {
+                sim.step()?;
+                job.step.store(sim.current_step(), Ordering::Relaxed);
+            }
+            Ok(sim.summary())
+        });
+        *job.state.lock().expect("job lock") = match result {
+            Ok(summary) => JobState::Done(Box::new(summary)),
+            Err(err) => JobState::Failed(err.to_string()),
+        };
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    content_type: String,
+    body: String,
+}
+
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Self {
+        Self { status, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(
+            status,
+            format!("{{\"error\": {}}}\n", Value::String(message.to_string())),
+        )
+    }
+}
+
+fn handle(server: &Server, mut stream: TcpStream) -> io::Result<()> {
+    let response = match read_request(&mut stream) {
+        Ok(request) => route(server, &request),
+        Err(err) if err.kind() == io::ErrorKind::FileTooLarge => {
+            Response::error(413, &err.to_string())
+        }
+        Err(err) => Response::error(400, &err.to_string()),
+    };
+    let reason = match response.status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut content_length = 0;
+    let mut content_type = String::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length")
+                })?;
+            } else if name.eq_ignore_ascii_case("content-type") {
+                content_type = value.to_ascii_lowercase();
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            "request body too large",
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "body is not UTF-8"))?;
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        content_type,
+        body,
+    })
+}
+
+fn route(server: &Server, request: &Request) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["health"]) => Response::json(200, "{\"status\": \"ok\"}\n".to_string()),
+        ("POST", ["jobs"]) => submit(server, request),
+        ("GET", ["jobs"]) => list_jobs(server),
+        ("GET", ["jobs", id, rest @ ..]) => {
+            let Some(job) = id
+                .parse::<u64>()
+                .ok()
+                .and_then(|id| server.jobs.lock().ok()?.jobs.get(&id).cloned())
+            else {
+                return Response::error(404, "no such job");
+            };
+            match rest {
+                [] => Response::json(200, status_json(id, &job)),
+                ["summary"] => with_summary(&job, |s| Response::json(200, summary_json(s))),
+                ["raster"] => with_summary(&job, raster_response),
+                _ => Response::error(404, "not found"),
+            }
+        }
+        ("DELETE", ["jobs", id]) => delete_job(server, id),
+        (_, ["jobs", ..]) | (_, ["health"]) => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "not found"),
+    }
+}
+
+fn submit(server: &Server, request: &Request) -> Response {
+    let is_cvir =
+        request.content_type.contains("json") || request.body.trim_start().starts_with('{');
+    let program = if is_cvir {
+        program_from_cvir(&request.body).map_err(|diag| diag.message)
+    } else {
+        // The server's environment is no business of its clients.
+        parse_program_with_env(&request.body, &[], &no_env)
+            .map_err(|diag| format_diagnostic(&request.body, &diag))
+    };
+    let program = match program {
+        Ok(p) => p,
+        Err(message) => return Response::error(400, &message),
+    };
+    if let Err(diags) = validate(&program) {
+        let messages: Vec<String> = diags
+            .iter()
+            .map(|d| format_diagnostic(&request.body, d))
+            .collect();
+        return Response::error(400, &messages.join("\n"));
+    }
+    if !server.options.allow_external && has_external(&program.items) {
+        return Response::error(
+            400,
+            "`External` stimuli are disabled; start the server with --allow-external",
+        );
+    }
+
+    let config = SimConfig {
+        record_spikes: request
+            .query
+            .split('&')
+            .any(|kv| kv == "raster=1" || kv == "raster=true"),
+        data_dir: server.options.data_dir.clone(),
+        confine_data: true,
+        ..SimConfig::default()
+    };
+    if let Err(message) = check_limits(&server.options, &program, &config) {
+        return Response::error(400, &message);
+    }
+    let job = Arc::new(Job {
+        state: Mutex::new(JobState::Queued),
+        step: AtomicU64::new(0),
+        total_steps: AtomicU64::new(0),
+    });
+    let id = {
+        let Ok(mut jobs) = server.jobs.lock() else {
+            return Response::error(500, "job table poisoned");
+        };
+        jobs.next_id += 1;
+        let id = jobs.next_id;
+        jobs.jobs.insert(id, Arc::clone(&job));
+        forget_old_jobs(&mut jobs, server.options.keep_jobs);
+        id
+    };
+    let sent = server.queue.lock().map(|q| {
+        q.send(Queued {
+            job,
+            program,
+            config: Box::new(config),
+        })
+    });
+    if !matches!(sent, Ok(Ok(()))) {
+        return Response::error(500, "worker pool is gone");
+    }
+    Response::json(202, format!("{{\"id\": {id}, \"status\": \"queued\"}}\n"))
+}
+
+/// Estimates the job up front so one request can't take the server's memory.
+fn check_limits(
+    options: &ServeOptions,
+    program: &Program,
+    config: &SimConfig,
+) -> Result<(), String> {
+    let estimate = estimate(program, config).map_err(|err| err.to_string())?;
+    let over = |what: &str, needed: String, limit: String| {
+        Err(format!(
+            "the program needs {needed} {what}, more than this server's limit of {limit}"
+        ))
+    };
+    if estimate.neurons > options.max_neurons {
+        return over(
+            "neurons",
+            estimate.neurons.to_string(),
+            options.max_neurons.to_string(),
+        );
+    }
+    if estimate.synapses > options.max_synapses {
+        return over(
+            "synapses",
+            estimate.synapses.to_string(),
+            options.max_synapses.to_string(),
+        );
+    }
+    if estimate.total_bytes() > options.max_memory_bytes {
+        return over(
+            "of memory",
+            format_bytes(estimate.total_bytes()),
+            format_bytes(options.max_memory_bytes),
+        );
+    }
+    if estimate.steps > options.max_steps {
+        return over(
+            "steps",
+            estimate.steps.to_string(),
+            options.max_steps.to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Drops the oldest finished jobs until at most `keep` are left.
+fn forget_old_jobs(jobs: &mut Jobs, keep: usize) {
+    let finished: Vec<u64> = jobs
+        .jobs
+        .iter()
+        .filter(|(_, job)| job.is_finished())
+        .map(|(&id, _)| id)
+        .collect();
+    for id in &finished[..finished.len().saturating_sub(keep)] {
+        jobs.jobs.remove(id);
+    }
+}
+
+fn delete_job(server: &Server, id: &str) -> Response {
+    let Ok(mut jobs) = server.jobs.lock() else {
+        return Response::error(500, "job table poisoned");
+    };
+    let Some(id) = id
+        .parse::<u64>()
+        .ok()
+        .filter(|id| jobs.jobs.contains_key(id))
+    else {
+        return Response::error(404, "no such job");
+    };
+    if !jobs.jobs[&id].is_finished() {
+        return Response::error(409, "job has not finished");
+    }
+    jobs.jobs.remove(&id);
+    Response::json(200, format!("{{\"id\": {id}, \"status\": \"deleted\"}}\n"))
+}
+
+fn has_external(items: &[Item]) -> bool {
+    items.iter().any(|item| match item {
+        Item::Stimulus(s) => matches!(s.model, StimulusModel::External(_)),
+        Item::Network(def) => has_external(&def.items),
+        _ => false,
+    })
+}
+
+fn list_jobs(server: &Server) -> Response {
+    let Ok(jobs) = server.jobs.lock() else {
+        return Response::error(500, "job table poisoned");
+    };
+    let entries: Vec<String> = jobs
+        .jobs
+        .iter()
+        .map(|(id, job)| format!("  {}", status_json(&id.to_string(), job).trim_end()))
+        .collect();
+    Response::json(200, format!("[\n{}\n]\n", entries.join(",\n")))
+}
+
+fn status_json(id: &str, job: &Job) -> String {
+    let state = job.state.lock().expect("job lock");
+    let (status, error) = match &*state {
+        JobState::Queued => ("queued", None),
+        JobState::Running => ("running", None),
+        JobState::Done(_) => ("done", None),
+        JobState::Failed(message) => ("failed", Some(message.clone())),
+    };
+    let mut s = format!(
+        "{{\"id\": {id}, \"status\": \"{status}\", \"step\": {}, \"total_steps\": {}",
+        job.step.load(Ordering::Relaxed),
+        job.total_steps.load(Ordering::Relaxed)
+    );
+    if let Some(message) = error {
+        s.push_str(&format!(", \"error\": {}", Value::String(message)));
+    }
+    s.push_str("}\n");
+    s
+}
+
+fn with_summary(job: &Job, render: impl FnOnce(&SimSummary) -> Response) -> Response {
+    match &*job.state.lock().expect("job lock") {
+        JobState::Done(summary) => render(summary),
+        JobState::Failed(message) => Response::error(409, message),
+        _ => Response::error(409, "job has not finished"),
+    }
+}
+
+/// Recorded spikes as `[step, layer, neuron]` triples, with layer names so the
+/// indices can be resolved.
+fn raster_response(summary: &SimSummary) -> Response {
+    let Some(raster) = &summary.raster else {
+        return Response::error(404, "raster not recorded; submit with ?raster=1");
+    };
+    let layers: Vec<String> = summary
+        .layers
+        .iter()
+        .map(|l| Value::String(l.name.clone()).to_string())
+        .collect();
+    let spikes: Vec<String> = raster
+        .iter()
+        .map(|s| format!("[{},{},{}]", s.step, s.layer, s.neuron))
+        .collect();
+    Response::json(
+        200,
+        format!(
+            "{{\"step_ns\": {}, \"layers\": [{}], \"spikes\": [{}]}}\n",
+            summary.step_ns,
+            layers.join(", "),
+            spikes.join(",")
+        ),
+    )
+}
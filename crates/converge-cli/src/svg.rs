@@ -0,0 +1,340 @@
+//! SVG figures for reports and `converge sim --plot-out`. Plain string building,
+//! no plotting crate.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use converge_lang::stats::TopologyStats;
+use converge_sim::{SimSummary, Spike, VoltageTrace};
+
+const PALETTE: [&str; 6] = [
+    "#3b6ea8", "#c8553d", "#4f9d69", "#8e5ea2", "#d4a017", "#5f6b7a",
+];
+
+/// Rasters with more spikes than this are thinned to every n-th spike so the
+/// page stays small; the caption says so.
+const MAX_RASTER_DOTS: usize = 50_000;
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Layers as boxes in source order; forward connections arc above the row,
+/// backward ones below, and self-connections loop over their layer.
+pub fn topology_svg(stats: &TopologyStats) -> String {
+    const BOX_W: f64 = 120.0;
+    const BOX_H: f64 = 44.0;
+    const GAP: f64 = 80.0;
+    const ROW_Y: f64 = 110.0;
+
+    let width = 40.0 + stats.layers.len().max(1) as f64 * (BOX_W + GAP) - GAP + 40.0;
+    let height = 260.0;
+    let center = |idx: usize| 40.0 + idx as f64 * (BOX_W + GAP) + BOX_W / 2.0;
+
+    let mut s = String::new();
+    let _ = write!(
+        s,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {width} {height}" width="{width}" height="{height}" font-family="sans-serif" font-size="12">
+<defs><marker id="arrow" viewBox="0 0 10 10" refX="9" refY="5" markerWidth="7" markerHeight="7" orient="auto-start-reverse"><path d="M0,0 L10,5 L0,10 z" fill="#555"/></marker></defs>
+"##
+    );
+
+    for conn in &stats.connections {
+        let (Some(src), Some(dst)) = (
+            stats.layers.iter().position(|l| l.name == conn.src),
+            stats.layers.iter().position(|l| l.name == conn.dst),
+        ) else {
+            continue;
+        };
+        let (x1, x2) = (center(src), center(dst));
+        let (path, label_x, label_y) = if src == dst {
+            let top = ROW_Y - BOX_H / 2.0;
+            (
+                format!(
+                    "M{},{top} C{},{} {},{} {},{top}",
+                    x1 - 20.0,
+                    x1 - 40.0,
+                    top - 60.0,
+                    x1 + 40.0,
+                    top - 60.0,
+                    x1 + 20.0
+                ),
+                x1,
+                top - 50.0,
+            )
+        } else {
+            let above = src < dst;
+            let y = if above {
+                ROW_Y - BOX_H / 2.0
+            } else {
+                ROW_Y + BOX_H / 2.0
+            };
+            let bend = (x2 - x1).abs() * 0.35 + 20.0;
+            let ctrl_y = if above { y - bend } else { y + bend };
+            (
+                format!("M{x1},{y} Q{},{ctrl_y} {x2},{y}", (x1 + x2) / 2.0),
+                (x1 + x2) / 2.0,
+                if above {
+                    y - bend / 2.0 - 4.0
+                } else {
+                    y + bend / 2.0 + 12.0
+                },
+            )
+        };
+        let _ = writeln!(
+            s,
+            r##"<path d="{path}" fill="none" stroke="#555" stroke-width="1.5" marker-end="url(#arrow)"/>"##
+        );
+        let _ = writeln!(
+            s,
+            r##"<text x="{label_x}" y="{label_y}" text-anchor="middle" fill="#333">{}</text>"##,
+            conn.synapses
+        );
+    }
+
+    for (idx, layer) in stats.layers.iter().enumerate() {
+        let x = center(idx) - BOX_W / 2.0;
+        let y = ROW_Y - BOX_H / 2.0;
+        let color = PALETTE[idx % PALETTE.len()];
+        let _ = writeln!(
+            s,
+            r##"<rect x="{x}" y="{y}" width="{BOX_W}" height="{BOX_H}" rx="6" fill="{color}" fill-opacity="0.15" stroke="{color}"/>"##
+        );
+        let _ = writeln!(
+            s,
+            r##"<text x="{}" y="{}" text-anchor="middle" font-weight="bold">{}</text>"##,
+            center(idx),
+            ROW_Y - 2.0,
+            escape(&layer.name)
+        );
+        let _ = writeln!(
+            s,
+            r##"<text x="{}" y="{}" text-anchor="middle" fill="#555">{} neurons</text>"##,
+            center(idx),
+            ROW_Y + 14.0,
+            layer.size
+        );
+    }
+    s.push_str("</svg>\n");
+    s
+}
+
+/// Spike raster: time on x, neurons stacked by layer on y.
+pub fn raster_svg(summary: &SimSummary, spikes: &[Spike]) -> String {
+    const WIDTH: f64 = 800.0;
+    const LEFT: f64 = 90.0;
+    const RIGHT: f64 = 20.0;
+    const TOP: f64 = 20.0;
+    const BOTTOM: f64 = 40.0;
+
+    let total_neurons: u64 = summary.layers.iter().map(|l| l.size).sum();
+    let plot_h = (total_neurons as f64 * 2.0).clamp(120.0, 600.0);
+    let plot_w = WIDTH - LEFT - RIGHT;
+    let height = TOP + plot_h + BOTTOM;
+    let steps = (summary.duration_ns / summary.step_ns.max(1)).max(1) as f64;
+    let row_h = plot_h / total_neurons.max(1) as f64;
+
+    let mut offsets = Vec::with_capacity(summary.layers.len());
+    let mut acc = 0u64;
+    for layer in &summary.layers {
+        offsets.push(acc);
+        acc += layer.size;
+    }
+
+    let mut s = String::new();
+    let _ = writeln!(
+        s,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {WIDTH} {height}" width="{WIDTH}" height="{height}" font-family="sans-serif" font-size="11">"##
+    );
+    for (idx, layer) in summary.layers.iter().enumerate() {
+        let y = TOP + offsets[idx] as f64 * row_h;
+        let h = layer.size as f64 * row_h;
+        if idx % 2 == 1 {
+            let _ = writeln!(
+                s,
+                r##"<rect x="{LEFT}" y="{y}" width="{plot_w}" height="{h}" fill="#f2f2f2"/>"##
+            );
+        }
+        let _ = writeln!(
+            s,
+            r##"<text x="{}" y="{}" text-anchor="end" dominant-baseline="middle">{}</text>"##,
+            LEFT - 6.0,
+            y + h / 2.0,
+            escape(&layer.name)
+        );
+    }
+
+    let stride = spikes.len().div_ceil(MAX_RASTER_DOTS).max(1);
+    let dot = row_h.clamp(1.0, 3.0);
+    for spike in spikes.iter().step_by(stride) {
+        let Some(offset) = offsets.get(spike.layer as usize) else {
+            continue;
+        };
+        let x = LEFT + spike.step as f64 / steps * plot_w;
+        let y = TOP + (offset + spike.neuron) as f64 * row_h;
+        let color = PALETTE[spike.layer as usize % PALETTE.len()];
+        let _ = writeln!(
+            s,
+            r##"<rect x="{x:.1}" y="{y:.1}" width="{dot:.1}" height="{dot:.1}" fill="{color}"/>"##
+        );
+    }
+
+    let axis_y = TOP + plot_h;
+    let _ = writeln!(
+        s,
+        r##"<line x1="{LEFT}" y1="{axis_y}" x2="{}" y2="{axis_y}" stroke="#333"/>"##,
+        LEFT + plot_w
+    );
+    let duration_ms = summary.duration_ns as f64 / 1e6;
+    for tick in 0..=4 {
+        let frac = tick as f64 / 4.0;
+        let x = LEFT + frac * plot_w;
+        let _ = writeln!(
+            s,
+            r##"<text x="{x}" y="{}" text-anchor="middle">{} ms</text>"##,
+            axis_y + 16.0,
+            format_ms(frac * duration_ms)
+        );
+    }
+    if stride > 1 {
+        let _ = writeln!(
+            s,
+            r##"<text x="{}" y="{}" text-anchor="end" fill="#777">showing every {stride}th spike</text>"##,
+            LEFT + plot_w,
+            axis_y + 32.0
+        );
+    }
+    s.push_str("</svg>\n");
+    s
+}
+
+/// Membrane potential against time for the traced neurons of one layer.
+pub fn voltage_svg(summary: &SimSummary, layer: &str, traces: &[&VoltageTrace]) -> String {
+    const WIDTH: f64 = 800.0;
+    const HEIGHT: f64 = 300.0;
+    const LEFT: f64 = 60.0;
+    const RIGHT: f64 = 110.0;
+    const TOP: f64 = 30.0;
+    const BOTTOM: f64 = 40.0;
+
+    let plot_w = WIDTH - LEFT - RIGHT;
+    let plot_h = HEIGHT - TOP - BOTTOM;
+    let samples = traces.iter().map(|t| t.samples.len()).max().unwrap_or(0);
+    let (mut lo, mut hi) = traces
+        .iter()
+        .flat_map(|t| t.samples.iter().copied())
+        .fold((0.0f64, 0.0f64), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if hi - lo < 1e-9 {
+        hi = lo + 1.0;
+    }
+    let pad = (hi - lo) * 0.05;
+    lo -= pad;
+    hi += pad;
+    let x_of = |i: usize| LEFT + i as f64 / samples.saturating_sub(1).max(1) as f64 * plot_w;
+    let y_of = |v: f64| TOP + (hi - v) / (hi - lo) * plot_h;
+
+    let mut s = String::new();
+    let _ = writeln!(
+        s,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {WIDTH} {HEIGHT}" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="11">"##
+    );
+    let _ = writeln!(
+        s,
+        r##"<text x="{LEFT}" y="18" font-size="13" font-weight="bold">{} membrane potential</text>"##,
+        escape(layer)
+    );
+    let _ = writeln!(
+        s,
+        r##"<rect x="{LEFT}" y="{TOP}" width="{plot_w}" height="{plot_h}" fill="none" stroke="#333"/>"##
+    );
+    for tick in 0..=4 {
+        let v = lo + (hi - lo) * tick as f64 / 4.0;
+        let _ = writeln!(
+            s,
+            r##"<text x="{}" y="{:.1}" text-anchor="end" dominant-baseline="middle">{v:.2}</text>"##,
+            LEFT - 6.0,
+            y_of(v)
+        );
+    }
+
+    for (idx, trace) in traces.iter().enumerate() {
+        let color = PALETTE[idx % PALETTE.len()];
+        let mut points = String::new();
+        for (i, v) in trace.samples.iter().enumerate() {
+            let _ = write!(points, "{:.1},{:.1} ", x_of(i), y_of(*v));
+        }
+        let _ = writeln!(
+            s,
+            r##"<polyline points="{}" fill="none" stroke="{color}" stroke-width="1.2"/>"##,
+            points.trim_end()
+        );
+        let _ = writeln!(
+            s,
+            r##"<text x="{}" y="{}" fill="{color}">neuron {}</text>"##,
+            LEFT + plot_w + 10.0,
+            TOP + 14.0 + idx as f64 * 16.0,
+            trace.neuron
+        );
+    }
+
+    let axis_y = TOP + plot_h;
+    let duration_ms = summary.duration_ns as f64 / 1e6;
+    for tick in 0..=4 {
+        let frac = tick as f64 / 4.0;
+        let _ = writeln!(
+            s,
+            r##"<text x="{}" y="{}" text-anchor="middle">{} ms</text>"##,
+            LEFT + frac * plot_w,
+            axis_y + 16.0,
+            format_ms(frac * duration_ms)
+        );
+    }
+    s.push_str("</svg>\n");
+    s
+}
+
+/// Writes `raster.svg` and one `voltage-<layer>.svg` per traced layer into
+/// `dir`, creating it if needed. Returns the paths written.
+pub fn write_plots(dir: &Path, summary: &SimSummary) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    if let Some(raster) = &summary.raster {
+        let path = dir.join("raster.svg");
+        std::fs::write(&path, raster_svg(summary, raster))?;
+        written.push(path);
+    }
+    if let Some(voltages) = &summary.voltages {
+        for (idx, layer) in summary.layers.iter().enumerate() {
+            let traces: Vec<&VoltageTrace> = voltages
+                .iter()
+                .filter(|t| t.layer as usize == idx)
+                .collect();
+            if traces.is_empty() {
+                continue;
+            }
+            let path = dir.join(format!("voltage-{}.svg", layer.name));
+            std::fs::write(&path, voltage_svg(summary, &layer.name, &traces))?;
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
+
+fn format_ms(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{value:.0}")
+    } else {
+        format!("{value:.1}")
+    }
+}
@@ -0,0 +1,291 @@
+//! Live dashboard for `converge sim --tui`: per-layer firing rates, a membrane
+//! histogram for one layer, and elapsed/remaining time. Drawn on stderr so the
+//! summary on stdout stays machine-readable.
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use converge_sim::{SimError, Simulator};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, style, terminal};
+
+const FRAME: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 30;
+const HIST_BINS: usize = 12;
+
+/// How a dashboard session ended.
+pub enum TuiExit {
+    Finished,
+    Quit,
+}
+
+/// Per-layer counters from the previous frame, for rates over the last frame.
+struct RateWindow {
+    step: u64,
+    spikes: Vec<u64>,
+    rates: Vec<f64>,
+}
+
+pub fn run_tui(sim: &mut Simulator, title: &str) -> Result<TuiExit, SimError> {
+    let mut err = io::stderr();
+    let _guard = TerminalGuard::enter(&mut err).map_err(io_error)?;
+
+    let started = Instant::now();
+    let mut paused_for = Duration::ZERO;
+    let mut paused_at: Option<Instant> = None;
+    let mut selected = 0;
+    let mut window = RateWindow {
+        step: 0,
+        spikes: vec![0; sim.layer_count()],
+        rates: vec![0.0; sim.layer_count()],
+    };
+
+    loop {
+        let frame_start = Instant::now();
+        if paused_at.is_none() {
+            while !sim.is_finished() && frame_start.elapsed() < FRAME {
+                sim.step()?;
+            }
+        }
+        update_rates(sim, &mut window);
+
+        let running = paused_at.map_or(started.elapsed() - paused_for, |at| {
+            at - started - paused_for
+        });
+        let lines = render_frame(
+            sim,
+            title,
+            &window.rates,
+            selected,
+            running,
+            paused_at.is_some(),
+        );
+        draw(&mut err, &lines).map_err(io_error)?;
+
+        if sim.is_finished() {
+            return Ok(TuiExit::Finished);
+        }
+
+        let wait = if paused_at.is_some() {
+            FRAME
+        } else {
+            Duration::ZERO
+        };
+        while event::poll(wait).map_err(io_error)? {
+            let Event::Key(key) = event::read().map_err(io_error)? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(TuiExit::Quit),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(TuiExit::Quit);
+                }
+                KeyCode::Char(' ') | KeyCode::Char('p') => match paused_at.take() {
+                    Some(at) => paused_for += at.elapsed(),
+                    None => paused_at = Some(Instant::now()),
+                },
+                KeyCode::Tab | KeyCode::Down => {
+                    selected = (selected + 1) % sim.layer_count().max(1);
+                }
+                KeyCode::BackTab | KeyCode::Up => {
+                    selected = (selected + sim.layer_count().max(1) - 1) % sim.layer_count().max(1);
+                }
+                _ => {}
+            }
+            if paused_at.is_none() {
+                break;
+            }
+        }
+    }
+}
+
+fn update_rates(sim: &Simulator, window: &mut RateWindow) {
+    let step = sim.current_step();
+    if step == window.step {
+        return;
+    }
+    let elapsed_s = (step - window.step) as f64 * sim.step_ns() as f64 / 1e9;
+    for idx in 0..sim.layer_count() {
+        let layer = sim.layer(idx);
+        let fired = layer.spikes - window.spikes[idx];
+        window.rates[idx] = fired as f64 / (layer.membrane.len().max(1) as f64 * elapsed_s);
+        window.spikes[idx] = layer.spikes;
+    }
+    window.step = step;
+}
+
+/// Builds the dashboard as plain lines; kept free of terminal I/O so it can be
+/// tested.
+fn render_frame(
+    sim: &Simulator,
+    title: &str,
+    rates: &[f64],
+    selected: usize,
+    running: Duration,
+    paused: bool,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let done = sim.current_step();
+    let total = sim.total_steps().max(1);
+    let fraction = done as f64 / total as f64;
+    let remaining = if done == 0 || paused {
+        "-".to_string()
+    } else {
+        format!(
+            "{:.1} s",
+            running.as_secs_f64() * (total - done) as f64 / done as f64
+        )
+    };
+
+    lines.push(format!(
+        "converge sim  {title}  [{}]",
+        if paused { "paused" } else { "running" }
+    ));
+    lines.push(format!(
+        "step {done}/{total} ({:.1}%)  simulated {:.1} / {:.1} ms  elapsed {:.1} s  remaining {remaining}",
+        fraction * 100.0,
+        done as f64 * sim.step_ns() as f64 / 1e6,
+        sim.duration_ns() as f64 / 1e6,
+        running.as_secs_f64()
+    ));
+    lines.push(String::new());
+
+    let peak = rates.iter().copied().fold(1.0, f64::max);
+    lines.push(format!(
+        "  {:<16} {:>8}  {:<BAR_WIDTH$} {:>10} {:>10}",
+        "layer", "size", "rate", "Hz", "spikes"
+    ));
+    for idx in 0..sim.layer_count() {
+        let layer = sim.layer(idx);
+        let rate = rates.get(idx).copied().unwrap_or(0.0);
+        let filled = ((rate / peak) * BAR_WIDTH as f64).round() as usize;
+        lines.push(format!(
+            "{} {:<16} {:>8}  {:<BAR_WIDTH$} {:>10.1} {:>10}",
+            if idx == selected { '>' } else { ' ' },
+            layer.name,
+            layer.membrane.len(),
+            "#".repeat(filled.min(BAR_WIDTH)),
+            rate,
+            layer.spikes
+        ));
+    }
+    lines.push(String::new());
+
+    if sim.layer_count() > 0 {
+        let layer = sim.layer(selected.min(sim.layer_count() - 1));
+        lines.push(format!("membrane potential: {}", layer.name));
+        lines.extend(histogram(layer.membrane, layer.v_th));
+        lines.push(String::new());
+    }
+    lines.push("space pause/resume   tab next layer   q quit".to_string());
+    lines
+}
+
+/// Histogram of membrane potentials from min(v, 0) up to the threshold.
+fn histogram(values: &[f64], v_th: f64) -> Vec<String> {
+    let lo = values.iter().copied().fold(0.0, f64::min);
+    let hi = v_th.max(lo + f64::EPSILON);
+    let width = (hi - lo) / HIST_BINS as f64;
+    let mut counts = [0usize; HIST_BINS];
+    for &v in values {
+        let bin = (((v - lo) / width) as usize).min(HIST_BINS - 1);
+        counts[bin] += 1;
+    }
+    let peak = counts.iter().copied().max().unwrap_or(0).max(1);
+    counts
+        .iter()
+        .enumerate()
+        .map(|(bin, &count)| {
+            let start = lo + bin as f64 * width;
+            let filled = (count * BAR_WIDTH).div_ceil(peak);
+            format!(
+                "  {:>7.3} .. {:>7.3}  {:<BAR_WIDTH$} {count}",
+                start,
+                start + width,
+                "#".repeat(filled)
+            )
+        })
+        .collect()
+}
+
+fn draw(out: &mut impl Write, lines: &[String]) -> io::Result<()> {
+    queue!(out, cursor::MoveTo(0, 0))?;
+    for line in lines {
+        queue!(
+            out,
+            style::Print(line),
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+            cursor::MoveToNextLine(1)
+        )?;
+    }
+    queue!(out, terminal::Clear(terminal::ClearType::FromCursorDown))?;
+    out.flush()
+}
+
+fn io_error(err: io::Error) -> SimError {
+    SimError::io("terminal", err)
+}
+
+/// Restores the terminal on every exit path, including errors and panics
+/// unwinding through the dashboard.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter(out: &mut impl Write) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converge_lang::parser::parse_program;
+    use converge_sim::SimConfig;
+
+    #[test]
+    fn frame_shows_layers_and_histogram() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer Input[4] : LIF
+layer Output[2] : LIF
+connect Input -> Output { w = 0.7, d = 2 ms }
+stimulus Input = Poisson(rate=400 Hz)
+run for 30 ms
+seed 3
+"#;
+        let program = parse_program(src).expect("parse");
+        let mut sim = Simulator::new(&program, &SimConfig::default()).expect("build");
+        for _ in 0..10 {
+            sim.step().expect("step");
+        }
+        let lines = render_frame(
+            &sim,
+            "test.cv",
+            &[100.0, 0.0],
+            1,
+            Duration::from_secs(1),
+            true,
+        );
+        assert!(lines[0].contains("[paused]"));
+        assert!(lines[1].starts_with("step 10/30"));
+        assert!(lines.iter().any(|l| l.starts_with("> Output")));
+        assert!(lines.iter().any(|l| l == "membrane potential: Output"));
+
+        let hist = histogram(&[0.0, 0.0, 0.5, 0.99], 1.0);
+        assert_eq!(hist.len(), HIST_BINS);
+        assert!(hist[0].ends_with(" 2"));
+        assert!(hist[HIST_BINS - 1].ends_with(" 1"));
+    }
+}
@@ -1,19 +1,2000 @@
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-#[test]
-fn sim_cli_runs() {
+fn converge() -> Command {
     let exe = std::env::var("CARGO_BIN_EXE_converge").unwrap_or_else(|_| {
-        let manifest = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
         manifest
             .join("../../target/debug/converge")
             .to_string_lossy()
             .to_string()
     });
-    let manifest = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
-    let example = manifest.join("../../examples/poisson.cv");
-    let output = Command::new(exe)
+    Command::new(exe)
+}
+
+fn example(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../examples")
+        .join(name)
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("converge-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    dir.join(name)
+}
+
+#[test]
+fn sim_cli_runs() {
+    let example = example("poisson.cv");
+    let output = converge()
         .args(["sim", example.to_string_lossy().as_ref()])
         .output()
         .expect("run converge sim");
     assert!(output.status.success());
 }
+
+#[test]
+fn sim_cli_checks_asserts() {
+    let example = example("assert.cv");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref()])
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success());
+}
+
+#[test]
+fn sim_cli_compares_against_golden() {
+    let example = example("assert.cv");
+    let golden = temp_path("golden.json");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--out"])
+        .arg(&golden)
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success());
+
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--compare"])
+        .arg(&golden)
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success());
+
+    let text = std::fs::read_to_string(&golden).expect("read golden");
+    std::fs::write(&golden, text.replace("\"seed\": 7", "\"seed\": 8")).expect("write golden");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--compare"])
+        .arg(&golden)
+        .output()
+        .expect("run converge sim");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("seed: expected 8, got 7"));
+}
+
+#[test]
+fn estimate_cli_reports_without_simulating() {
+    let example = example("assert.cv");
+    let output = converge()
+        .args(["estimate", example.to_string_lossy().as_ref(), "--json"])
+        .output()
+        .expect("run converge estimate");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"synapses\": 32,"));
+}
+
+#[test]
+fn bench_cli_reports_throughput() {
+    let example = example("assert.cv");
+    let output = converge()
+        .args(["bench", example.to_string_lossy().as_ref(), "--runs", "2"])
+        .output()
+        .expect("run converge bench");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let value = converge_lang::json::parse(&stdout).expect("bench json");
+    let bench = &value
+        .get("benchmarks")
+        .and_then(|b| b.as_array())
+        .expect("benchmarks")[0];
+    assert_eq!(bench.get("name").and_then(|n| n.as_str()), Some("assert"));
+    assert_eq!(bench.get("runs").and_then(|n| n.as_f64()), Some(2.0));
+    assert!(bench.get("steps_per_sec").is_some());
+}
+
+#[test]
+fn report_cli_writes_self_contained_html() {
+    let example = example("assert.cv");
+    let out = temp_path("report.html");
+    let output = converge()
+        .args(["report", example.to_string_lossy().as_ref(), "--out"])
+        .arg(&out)
+        .output()
+        .expect("run converge report");
+    assert!(output.status.success());
+    let html = std::fs::read_to_string(&out).expect("read report");
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<h2>Spike raster</h2>"));
+    assert!(html.contains("Input &rarr; Output"));
+    assert!(html.contains("<div class=\"doc\">Poisson-driven sensory input.</div>"));
+    assert!(!html.contains("<script"));
+}
+
+#[test]
+fn doc_cli_writes_a_reference_into_the_directory() {
+    let example = example("assert.cv");
+    let dir = temp_path("docs");
+    let output = converge()
+        .args(["doc", example.to_string_lossy().as_ref(), "--out"])
+        .arg(&dir)
+        .output()
+        .expect("run converge doc");
+    assert!(output.status.success());
+    let page = std::fs::read_to_string(dir.join("assert.md")).expect("read doc");
+    assert!(page.contains("## Layers"));
+    assert!(page.contains("Poisson-driven sensory input."));
+
+    let output = converge()
+        .args([
+            "doc",
+            example.to_string_lossy().as_ref(),
+            "--format",
+            "html",
+        ])
+        .output()
+        .expect("run converge doc");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("<!DOCTYPE html>"));
+}
+
+#[test]
+fn plot_cli_reads_recorded_spikes() {
+    let example = example("assert.cv");
+    let spikes = temp_path("spikes.ndjson");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--spikes"])
+        .arg(&spikes)
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success());
+
+    let output = converge()
+        .arg("plot")
+        .arg(&spikes)
+        .args(["--width", "60"])
+        .output()
+        .expect("run converge plot");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Input (8)"));
+    assert!(stdout.contains("Output (4)"));
+}
+
+#[test]
+fn plot_cli_reads_binary_recordings() {
+    let example = example("assert.cv");
+    let mut plots = Vec::new();
+    for name in ["same.ndjson", "same.cvr"] {
+        let spikes = temp_path(name);
+        let output = converge()
+            .args(["sim", example.to_string_lossy().as_ref(), "--spikes"])
+            .arg(&spikes)
+            .output()
+            .expect("run converge sim");
+        assert!(output.status.success());
+        let output = converge()
+            .arg("plot")
+            .arg(&spikes)
+            .output()
+            .expect("run converge plot");
+        assert!(output.status.success(), "{output:?}");
+        plots.push(output.stdout);
+    }
+    let binary = std::fs::read(temp_path("same.cvr")).expect("read recording");
+    assert!(binary.starts_with(b"CVRS"));
+    assert_eq!(plots[0], plots[1]);
+}
+
+#[test]
+fn sim_cli_writes_svg_plots() {
+    let example = example("assert.cv");
+    let dir = temp_path("plots");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--plot-out"])
+        .arg(&dir)
+        .args(["--trace-neurons", "2"])
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success());
+    for name in ["raster.svg", "voltage-Input.svg", "voltage-Output.svg"] {
+        let svg = std::fs::read_to_string(dir.join(name)).expect(name);
+        assert!(svg.starts_with("<svg xmlns="));
+    }
+}
+
+fn http(addr: &str, request: &str) -> String {
+    use std::io::{Read, Write};
+    let mut stream = std::net::TcpStream::connect(addr).expect("connect");
+    stream.write_all(request.as_bytes()).expect("send request");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("read response");
+    response
+}
+
+/// `converge serve` with `args` on a free port, and its address.
+fn spawn_serve(args: &[&str], env: &[(&str, &str)]) -> (std::process::Child, String) {
+    use std::io::BufRead;
+    let mut child = converge()
+        .args(["serve", "--port", "0", "--workers", "1"])
+        .args(args)
+        .envs(env.iter().copied())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn converge serve");
+    let mut stderr = std::io::BufReader::new(child.stderr.take().expect("stderr"));
+    let mut line = String::new();
+    stderr.read_line(&mut line).expect("read listen line");
+    let addr = line
+        .trim()
+        .strip_prefix("listening on http://")
+        .expect("listen line")
+        .to_string();
+    (child, addr)
+}
+
+/// `POST /jobs{query}` with `src` as the body.
+fn submit_job(addr: &str, query: &str, src: &str) -> String {
+    http(
+        addr,
+        &format!(
+            "POST /jobs{query} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n{src}",
+            src.len()
+        ),
+    )
+}
+
+/// Polls job `id` until it is done or failed and returns its last status.
+fn wait_for_job(addr: &str, id: u64) -> String {
+    let mut status = String::new();
+    for _ in 0..200 {
+        status = http(addr, &format!("GET /jobs/{id} HTTP/1.1\r\n\r\n"));
+        if status.contains("\"status\": \"done\"") || status.contains("\"status\": \"failed\"") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(25));
+    }
+    status
+}
+
+#[test]
+fn serve_cli_runs_submitted_jobs() {
+    let (mut child, addr) = spawn_serve(&[], &[]);
+    let src = std::fs::read_to_string(example("assert.cv")).expect("read example");
+    let submitted = submit_job(&addr, "?raster=1", &src);
+    assert!(submitted.starts_with("HTTP/1.1 202"), "{submitted}");
+    assert!(submitted.contains("\"id\": 1"));
+
+    let status = wait_for_job(&addr, 1);
+    let summary = http(&addr, "GET /jobs/1/summary HTTP/1.1\r\n\r\n");
+    let raster = http(&addr, "GET /jobs/1/raster HTTP/1.1\r\n\r\n");
+    let missing = http(&addr, "GET /jobs/9 HTTP/1.1\r\n\r\n");
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(status.contains("\"status\": \"done\""), "{status}");
+    assert!(summary.contains("total_spikes"), "{summary}");
+    assert!(raster.contains("\"spikes\": ["), "{raster}");
+    assert!(missing.starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+fn serve_cli_keeps_its_environment_from_clients() {
+    let (mut child, addr) = spawn_serve(&[], &[("CONVERGE_TEST_SECRET", "hunter2")]);
+    let home = submit_job(&addr, "", "layer A[1] : ${HOME}\nrun for 1 ms\n");
+    let secret = submit_job(&addr, "", "layer A[${CONVERGE_TEST_SECRET}] : LIF\n");
+    let seed = submit_job(&addr, "", "seed from_env(\"CONVERGE_TEST_SECRET\")\n");
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(home.starts_with("HTTP/1.1 400"), "{home}");
+    assert!(home.contains("`HOME` is not set"), "{home}");
+    assert!(
+        secret.contains("`CONVERGE_TEST_SECRET` is not set"),
+        "{secret}"
+    );
+    assert!(!secret.contains("hunter2"), "{secret}");
+    assert!(seed.starts_with("HTTP/1.1 400"), "{seed}");
+    assert!(seed.contains("`CONVERGE_TEST_SECRET` is not set"), "{seed}");
+}
+
+#[test]
+fn serve_cli_reads_data_files_only_from_its_data_dir() {
+    let root = temp_path("serve-data");
+    let data = root.join("data");
+    std::fs::create_dir_all(&data).expect("create data dir");
+    std::fs::write(data.join("rows.csv"), "4,2,0\n0,0,4\n").expect("write rows");
+    std::fs::write(data.join("notes.txt"), "hunter2\n").expect("write notes");
+    std::fs::write(root.join("secret.csv"), "1,2,3\n").expect("write secret");
+    let job = |path: &str| {
+        format!(
+            "neuron LIF {{ tau_m = 10 ms, v_th = 0.5 }}\n\
+             layer In[3] : LIF\n\
+             stimulus In = Encode(\"{path}\", scheme=latency, present=5 ms)\n\
+             run for 10 ms\n"
+        )
+    };
+    let refused = ["/etc/passwd", "../secret.csv", "notes.txt"];
+
+    let (mut child, addr) = spawn_serve(&["--data-dir", data.to_str().unwrap()], &[]);
+    let statuses: Vec<String> = (1..)
+        .zip(std::iter::once("rows.csv").chain(refused))
+        .map(|(id, path)| {
+            submit_job(&addr, "", &job(path));
+            wait_for_job(&addr, id)
+        })
+        .collect();
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(
+        statuses[0].contains("\"status\": \"done\""),
+        "{}",
+        statuses[0]
+    );
+    for (status, path) in statuses[1..].iter().zip(refused) {
+        assert!(status.contains("\"status\": \"failed\""), "{status}");
+        assert!(
+            status.contains(&format!("cannot read data file `{path}`")),
+            "{status}"
+        );
+    }
+    assert!(!statuses[1].contains("root:"), "{}", statuses[1]);
+    assert!(!statuses[3].contains("hunter2"), "{}", statuses[3]);
+}
+
+#[test]
+fn serve_cli_refuses_programs_over_its_limits() {
+    let src = "neuron LIF { tau_m = 10 ms, v_th = 0.5 }\n\
+               layer A[2000] : LIF\n\
+               layer B[10] : LIF\n\
+               connect B -> B { w = 0.1 }\n\
+               run for 1 ms\n";
+    let (mut child, addr) = spawn_serve(&["--max-neurons", "1000"], &[]);
+    let neurons = submit_job(&addr, "", src);
+    child.kill().expect("stop server");
+    let _ = child.wait();
+    let (mut child, addr) = spawn_serve(&["--max-synapses", "50"], &[]);
+    let synapses = submit_job(&addr, "", &src.replace("2000", "20"));
+    child.kill().expect("stop server");
+    let _ = child.wait();
+    let (mut child, addr) = spawn_serve(&["--max-steps", "100"], &[]);
+    let steps = submit_job(&addr, "", &src.replace("1 ms", "1 s"));
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(neurons.starts_with("HTTP/1.1 400"), "{neurons}");
+    assert!(
+        neurons.contains("needs 2010 neurons, more than this server's limit of 1000"),
+        "{neurons}"
+    );
+    assert!(
+        synapses.contains("needs 100 synapses, more than this server's limit of 50"),
+        "{synapses}"
+    );
+    assert!(steps.starts_with("HTTP/1.1 400"), "{steps}");
+    assert!(
+        steps.contains("needs 1000 steps, more than this server's limit of 100"),
+        "{steps}"
+    );
+}
+
+#[test]
+fn serve_cli_forgets_old_and_deleted_jobs() {
+    let src = "neuron LIF { tau_m = 10 ms, v_th = 0.5 }\n\
+               layer A[2] : LIF\n\
+               run for 1 ms\n";
+    let (mut child, addr) = spawn_serve(&["--keep-jobs", "1"], &[]);
+    let delete = |id: u64| http(&addr, &format!("DELETE /jobs/{id} HTTP/1.1\r\n\r\n"));
+    submit_job(&addr, "", src);
+    wait_for_job(&addr, 1);
+    submit_job(&addr, "", src);
+    let second = wait_for_job(&addr, 2);
+    // The third submission leaves two finished jobs, one over the limit.
+    submit_job(&addr, "", src);
+    let first = http(&addr, "GET /jobs/1 HTTP/1.1\r\n\r\n");
+    wait_for_job(&addr, 3);
+    let deleted = delete(3);
+    let gone = http(&addr, "GET /jobs/3 HTTP/1.1\r\n\r\n");
+    let again = delete(3);
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(second.contains("\"status\": \"done\""), "{second}");
+    assert!(first.starts_with("HTTP/1.1 404"), "{first}");
+    assert!(deleted.starts_with("HTTP/1.1 200"), "{deleted}");
+    assert!(gone.starts_with("HTTP/1.1 404"), "{gone}");
+    assert!(again.starts_with("HTTP/1.1 404"), "{again}");
+}
+
+#[test]
+fn serve_cli_refuses_external_stimuli_unless_allowed() {
+    let src = "neuron LIF { tau_m = 10 ms, v_th = 0.5 }\n\
+               network a {\n\
+                 layer Motor[1] : LIF\n\
+                 stimulus Motor = External(port=5556)\n\
+               }\n\
+               run for 1 ms\n";
+    let (mut child, addr) = spawn_serve(&[], &[]);
+    let refused = submit_job(&addr, "", src);
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(refused.starts_with("HTTP/1.1 400"), "{refused}");
+    assert!(refused.contains("--allow-external"), "{refused}");
+}
+
+#[test]
+fn serve_cli_reads_weight_and_label_files_only_from_its_data_dir() {
+    let data = temp_path("serve-weights");
+    std::fs::create_dir_all(&data).expect("create data dir");
+    std::fs::write(data.join("rows.csv"), "4,0\n0,4\n").expect("write rows");
+    std::fs::write(data.join("labels.csv"), "1\n0\n").expect("write labels");
+    let model = |w: &str, labels: &str| {
+        format!(
+            "neuron LIF {{ tau_m = 10 ms, v_th = 0.5 }}\n\
+             layer In[2] : LIF\n\
+             layer Out[2] : LIF\n\
+             connect In -> Out {{ w = {w} }}\n\
+             stimulus In = Encode(\"rows.csv\", scheme=latency, present=5 ms)\n\
+             experiment {{ epochs = 1 }}\n\
+             readout Out labels \"{labels}\"\n\
+             run for 1 ms\n"
+        )
+    };
+
+    let (mut child, addr) = spawn_serve(&["--data-dir", data.to_str().unwrap()], &[]);
+    submit_job(
+        &addr,
+        "",
+        &model("from_file(\"/etc/hostname\")", "labels.csv"),
+    );
+    let weights = wait_for_job(&addr, 1);
+    submit_job(&addr, "", &model("1", "/etc/passwd"));
+    let labels = wait_for_job(&addr, 2);
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(weights.contains("\"status\": \"failed\""), "{weights}");
+    assert!(
+        weights.contains("cannot read data file `/etc/hostname`"),
+        "{weights}"
+    );
+    assert!(labels.contains("\"status\": \"failed\""), "{labels}");
+    assert!(
+        labels.contains("cannot read data file `/etc/passwd`"),
+        "{labels}"
+    );
+}
+
+#[test]
+fn batch_cli_continues_past_failures() {
+    let dir = temp_path("batch");
+    let models = dir.join("models");
+    std::fs::create_dir_all(models.join("sub")).expect("create models");
+    std::fs::copy(example("poisson.cv"), models.join("a.cv")).expect("copy");
+    std::fs::copy(example("assert.cv"), models.join("sub/b.cv")).expect("copy");
+    std::fs::write(models.join("sub/broken.cv"), "layer X[").expect("write");
+    let out = dir.join("results");
+
+    let output = converge()
+        .args([
+            "batch",
+            &format!("{}/**/*.cv", models.display()),
+            "--jobs",
+            "2",
+            "--out",
+            out.to_string_lossy().as_ref(),
+        ])
+        .output()
+        .expect("run converge batch");
+    assert_eq!(output.status.code(), Some(1));
+
+    let index = std::fs::read_to_string(out.join("index.json")).expect("read index");
+    assert!(index.contains("\"inputs\": 3"), "{index}");
+    assert!(index.contains("\"ok\": 2"), "{index}");
+    assert!(index.contains("\"status\": \"invalid\""));
+    let summaries = std::fs::read_dir(&out)
+        .expect("list results")
+        .filter(|e| {
+            let name = e.as_ref().unwrap().file_name();
+            name.to_string_lossy().ends_with("b.json")
+        })
+        .count();
+    assert_eq!(summaries, 1);
+}
+
+#[test]
+fn check_cli_groups_diagnostics_per_file() {
+    let dir = temp_path("check-many");
+    std::fs::create_dir_all(&dir).expect("create dir");
+    std::fs::copy(example("poisson.cv"), dir.join("good.cv")).expect("copy");
+    std::fs::write(dir.join("bad.cv"), "layer X[").expect("write");
+
+    let output = converge()
+        .args(["check", &format!("{}/*.cv", dir.display())])
+        .output()
+        .expect("run converge check");
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("bad.cv:\nexpected layer size"), "{stderr}");
+    assert!(!stderr.contains("good.cv:"));
+    assert!(stderr.contains("checked 2 files: 1 ok, 1 invalid"));
+
+    let output = converge()
+        .args(["check", dir.join("good.cv").to_string_lossy().as_ref()])
+        .output()
+        .expect("run converge check");
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn check_cli_reports_every_lexical_problem() {
+    let program = temp_path("lexical.cv");
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_m = 5 ms } #\n\
+         layer A[2] : LIF ?\n\
+         run for 5 ms\n",
+    )
+    .expect("write program");
+    let output = converge()
+        .arg("check")
+        .arg(&program)
+        .output()
+        .expect("run converge check");
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("error: unexpected character '#'\n  --> line 1, col 29"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("error: unexpected character '?'\n  --> line 2, col 18"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn sim_cli_takes_the_seed_from_the_environment() {
+    let src = std::fs::read_to_string(example("poisson.cv")).expect("read example");
+    let program = temp_path("env-seed.cv");
+    std::fs::write(
+        &program,
+        src.replace("seed 42", "seed from_env(\"CONVERGE_TEST_SEED\")"),
+    )
+    .expect("write program");
+    let run = |seed: &str| {
+        converge()
+            .args(["sim", program.to_string_lossy().as_ref(), "--quiet"])
+            .env("CONVERGE_TEST_SEED", seed)
+            .output()
+            .expect("run converge sim")
+    };
+
+    let output = run("0x2A");
+    assert!(output.status.success());
+    let summary = String::from_utf8_lossy(&output.stdout);
+    assert!(summary.contains("\"seed\": 42,"), "{summary}");
+    let direct = converge()
+        .args([
+            "sim",
+            example("poisson.cv").to_string_lossy().as_ref(),
+            "--quiet",
+        ])
+        .output()
+        .expect("run converge sim");
+    assert_eq!(
+        results(&summary),
+        results(&String::from_utf8_lossy(&direct.stdout))
+    );
+
+    let output = run("forty-two");
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("`CONVERGE_TEST_SEED` is `forty-two`, not a seed"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn sim_cli_reads_program_from_stdin() {
+    use std::io::Write;
+    let src = std::fs::read_to_string(example("poisson.cv")).expect("read example");
+    let mut child = converge()
+        .args(["sim", "-", "--quiet"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn converge sim");
+    child
+        .stdin
+        .take()
+        .expect("stdin")
+        .write_all(src.as_bytes())
+        .expect("write program");
+    let piped = child.wait_with_output().expect("wait");
+    assert!(piped.status.success());
+
+    let direct = converge()
+        .args([
+            "sim",
+            example("poisson.cv").to_string_lossy().as_ref(),
+            "--quiet",
+        ])
+        .output()
+        .expect("run converge sim");
+    assert_eq!(
+        results(&String::from_utf8_lossy(&piped.stdout)),
+        results(&String::from_utf8_lossy(&direct.stdout))
+    );
+}
+
+#[test]
+fn train_cli_writes_weights_that_from_file_loads() {
+    let dir = temp_path("train");
+    std::fs::create_dir_all(&dir).expect("create train dir");
+    std::fs::write(dir.join("rows.csv"), "4,0\n0,4\n").expect("write rows");
+    std::fs::write(dir.join("labels.csv"), "1\n0\n").expect("write labels");
+    let model = |w: &str| {
+        format!(
+            "neuron LIF {{ tau_m = 10 ms, v_th = 0.5 }}\n\
+             layer In[2] : LIF\n\
+             layer Out[2] : LIF\n\
+             connect ff: In -> Out {{ w = {w} }}\n\
+             stimulus In = Encode(\"rows.csv\", scheme=latency, present=5 ms)\n\
+             experiment {{ epochs = 1 }}\n\
+             readout Out labels \"labels.csv\"\n\
+             run for 1 ms step 1 ms\n"
+        )
+    };
+    let untrained = dir.join("untrained.cv");
+    std::fs::write(&untrained, model("0")).expect("write model");
+
+    let output = converge()
+        .args([
+            "train",
+            untrained.to_string_lossy().as_ref(),
+            "--epochs",
+            "3",
+            "--lr",
+            "1",
+            "--out",
+            dir.join("weights").to_string_lossy().as_ref(),
+            "--quiet",
+        ])
+        .output()
+        .expect("run converge train");
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("accuracy 0.667 (4 of 6 presentations, 2 silent)"),
+        "{stderr}"
+    );
+
+    let trained = dir.join("trained.cv");
+    std::fs::write(&trained, model("from_file(\"weights/ff.csv\")")).expect("write model");
+    let output = converge()
+        .args(["sim", trained.to_string_lossy().as_ref(), "--quiet"])
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+    let summary = String::from_utf8_lossy(&output.stdout);
+    assert!(summary.contains("\"accuracy\": 1,"), "{summary}");
+}
+
+#[test]
+fn sim_cli_compares_quantized_run_with_float() {
+    let example = example("poisson.cv");
+    let output = converge()
+        .args([
+            "sim",
+            example.to_string_lossy().as_ref(),
+            "--quantize",
+            "int8",
+            "--quiet",
+        ])
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("int8 weights"), "{stderr}");
+    assert!(stderr.contains("Output"), "{stderr}");
+    let summary = String::from_utf8_lossy(&output.stdout);
+    assert!(summary.contains("\"quantize\": \"int8\""), "{summary}");
+
+    let output = converge()
+        .args([
+            "sim",
+            example.to_string_lossy().as_ref(),
+            "--quantize",
+            "int4",
+        ])
+        .output()
+        .expect("run converge sim");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn sim_cli_runs_fixed_point_arithmetic() {
+    let example = example("poisson.cv");
+    let output = converge()
+        .args([
+            "sim",
+            example.to_string_lossy().as_ref(),
+            "--fixed-point",
+            "--quiet",
+        ])
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+    let summary = String::from_utf8_lossy(&output.stdout);
+    assert!(summary.contains("\"arithmetic\": \"q16.16\""), "{summary}");
+}
+
+#[test]
+fn target_cli_reports_profile_violations() {
+    let example = example("poisson.cv");
+    let profile = temp_path("target.toml");
+    let check = |profile_src: &str| {
+        std::fs::write(&profile, profile_src).expect("write profile");
+        converge()
+            .args([
+                "target",
+                "check",
+                example.to_string_lossy().as_ref(),
+                "--profile",
+                profile.to_string_lossy().as_ref(),
+            ])
+            .output()
+            .expect("run converge target check")
+    };
+
+    let output = check("name = \"tiny\"\n[core]\nmax_fan_in = 4\n[delays]\nmax_steps = 0\n");
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("layer `Output` has a fan-in of 8, tiny allows 4"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("delay of 1 ms is 1 step, tiny allows 0 to 0"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("2 violation(s) of tiny"), "{stderr}");
+
+    let output = check("name = \"roomy\"\n[core]\nmax_fan_in = 8\n");
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("fits roomy"));
+}
+
+#[test]
+fn place_cli_writes_placement_alongside_cvir() {
+    let example = example("poisson.cv");
+    let dir = temp_path("place");
+    let profile = temp_path("place.toml");
+    std::fs::write(&profile, "name = \"tiny\"\n[core]\nmax_neurons = 8\n").expect("write profile");
+
+    let output = converge()
+        .args([
+            "place",
+            example.to_string_lossy().as_ref(),
+            "--profile",
+            profile.to_string_lossy().as_ref(),
+            "--out",
+            dir.to_string_lossy().as_ref(),
+        ])
+        .output()
+        .expect("run converge place");
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("2 cores, 8 inter-core routes"), "{stderr}");
+    assert!(dir.join("poisson.cvir.json").exists());
+    let placement =
+        std::fs::read_to_string(dir.join("poisson.placement.json")).expect("read placement");
+    assert!(placement.contains("\"target\": \"tiny\""), "{placement}");
+    assert!(
+        placement.contains("\"inter_core_routes\": 8"),
+        "{placement}"
+    );
+}
+
+#[test]
+fn sim_cli_reports_energy_on_a_target() {
+    let example = example("assert.cv");
+    let profile = temp_path("energy.toml");
+    std::fs::write(
+        &profile,
+        "name = \"chip\"\n[core]\nmax_neurons = 8\n[energy]\nneuron_update_pj = 0.5\n",
+    )
+    .expect("write profile");
+    let output = converge()
+        .args([
+            "sim",
+            example.to_string_lossy().as_ref(),
+            "--quiet",
+            "--energy-report",
+            profile.to_string_lossy().as_ref(),
+        ])
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("energy on chip (2 cores)"), "{stderr}");
+    assert!(stderr.contains("300.000 pJ"), "{stderr}");
+    let summary = String::from_utf8_lossy(&output.stdout);
+    assert!(summary.contains("\"neuron_updates\": 600,"), "{summary}");
+}
+
+/// A program with both a dense connection and a sampled one, for the
+/// code generators.
+fn codegen_program(name: &str) -> PathBuf {
+    let program = temp_path(name);
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_m = 10 ms\n v_th = 0.8 }\n\
+         layer A[20] : LIF\n\
+         layer B[10] : LIF\n\
+         connect A -> B { w = 0.3, d = 3 ms }\n\
+         connect B -> A { w = Normal(0.3, 0.1), d = 2 ms }\n\
+         disconnect B -> A where src == dst\n\
+         stimulus A = Poisson(rate=300 Hz)\n\
+         seed 3\n\
+         run for 100 ms\n",
+    )
+    .expect("write program");
+    program
+}
+
+/// Output of `converge sim --quiet`.
+/// A summary without the fields that describe how the run was made, which
+/// differ between runs with the same results.
+fn results(summary: &str) -> converge_lang::json::Value {
+    use converge_lang::json::Value;
+    const METADATA: &[&str] = &[
+        "schema_version",
+        "toolchain_version",
+        "input_hash",
+        "wall_ns",
+        "steps",
+        "config",
+        "queues",
+    ];
+    let Ok(Value::Object(fields)) = converge_lang::json::parse(summary) else {
+        panic!("not a summary: {summary}");
+    };
+    Value::Object(
+        fields
+            .into_iter()
+            .filter(|(key, _)| !METADATA.contains(&key.as_str()))
+            .collect(),
+    )
+}
+
+fn sim_stdout(program: &Path) -> String {
+    let sim = converge()
+        .args(["sim", program.to_string_lossy().as_ref(), "--quiet"])
+        .output()
+        .expect("run converge sim");
+    assert!(sim.status.success(), "{sim:?}");
+    String::from_utf8_lossy(&sim.stdout).into_owned()
+}
+
+#[test]
+fn compile_cli_generates_a_crate_matching_sim() {
+    let program = codegen_program("compiled.cv");
+    let dir = temp_path("compiled");
+    let output = converge()
+        .args([
+            "compile",
+            program.to_string_lossy().as_ref(),
+            "--target",
+            "rust",
+            "--out",
+            dir.to_string_lossy().as_ref(),
+        ])
+        .output()
+        .expect("run converge compile");
+    assert!(output.status.success(), "{output:?}");
+    let manifest = std::fs::read_to_string(dir.join("Cargo.toml")).expect("read manifest");
+    assert!(manifest.contains("name = \"compiled\""), "{manifest}");
+
+    let binary = dir.join("compiled");
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let built = Command::new(rustc)
+        .args(["--edition", "2021", "-O", "-o"])
+        .arg(&binary)
+        .arg(dir.join("src/main.rs"))
+        .output()
+        .expect("run rustc");
+    assert!(built.status.success(), "{built:?}");
+    let generated = Command::new(&binary)
+        .output()
+        .expect("run generated binary");
+    assert!(generated.status.success(), "{generated:?}");
+    assert_eq!(
+        results(&String::from_utf8_lossy(&generated.stdout)),
+        results(&sim_stdout(&program))
+    );
+}
+
+#[test]
+fn compile_cli_generates_c_matching_sim() {
+    let program = codegen_program("compiled_c.cv");
+    let dir = temp_path("compiled_c");
+    let output = converge()
+        .args([
+            "compile",
+            program.to_string_lossy().as_ref(),
+            "--target",
+            "c",
+            "--out",
+            dir.to_string_lossy().as_ref(),
+        ])
+        .output()
+        .expect("run converge compile");
+    assert!(output.status.success(), "{output:?}");
+    let header = std::fs::read_to_string(dir.join("compiled_c.h")).expect("read header");
+    assert!(
+        header.contains("void compiled_c_run(compiled_c_summary *out);"),
+        "{header}"
+    );
+
+    let binary = dir.join("compiled_c");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let built = Command::new(cc)
+        .args(["-std=c99", "-O2", "-ffp-contract=off", "-o"])
+        .arg(&binary)
+        .arg(dir.join("compiled_c.c"))
+        .arg(dir.join("main.c"))
+        .output()
+        .expect("run cc");
+    assert!(built.status.success(), "{built:?}");
+    let generated = Command::new(&binary)
+        .output()
+        .expect("run generated binary");
+    assert!(generated.status.success(), "{generated:?}");
+    assert_eq!(
+        results(&String::from_utf8_lossy(&generated.stdout)),
+        results(&sim_stdout(&program))
+    );
+}
+
+#[test]
+fn cvir_cli_optimizes_before_emitting() {
+    let program = temp_path("optimize.cv");
+    std::fs::write(
+        &program,
+        "neuron A { tau_m = 20 ms }\n\
+         neuron B { tau_m = 0.02 s }\n\
+         layer In[4] : A\n\
+         layer Out[2] : B\n\
+         layer Idle[3] : A\n\
+         connect In -> Out { w = 0.5, d = 1 ms }\n\
+         stimulus In = Poisson(rate=0.2 kHz)\n\
+         run for 10 ms\n",
+    )
+    .expect("write program");
+    let output = converge()
+        .args(["cvir", program.to_string_lossy().as_ref(), "--optimize"])
+        .output()
+        .expect("run converge cvir");
+    assert!(output.status.success(), "{output:?}");
+    let cvir = String::from_utf8_lossy(&output.stdout);
+    assert!(!cvir.contains("Idle"), "{cvir}");
+    assert!(!cvir.contains("\"name\": \"B\""), "{cvir}");
+    assert!(cvir.contains("\"unit\": \"ns\""), "{cvir}");
+    assert!(!cvir.contains("kHz"), "{cvir}");
+}
+
+#[test]
+fn cvir_eq_cli_accepts_optimized_ir_and_reports_differences() {
+    let cvir = |name: &str, extra: &[&str]| {
+        let output = converge()
+            .args(["cvir", example(name).to_string_lossy().as_ref()])
+            .args(extra)
+            .output()
+            .expect("run converge cvir");
+        assert!(output.status.success(), "{output:?}");
+        let path = temp_path(&format!("eq-{name}{}.json", extra.len()));
+        std::fs::write(&path, &output.stdout).expect("write cvir");
+        path
+    };
+    let plain = cvir("assert.cv", &[]);
+    let optimized = cvir("assert.cv", &["--optimize"]);
+    let other = cvir("poisson.cv", &[]);
+
+    let eq = |a: &Path, b: &Path| {
+        converge()
+            .args([
+                "cvir-eq",
+                a.to_string_lossy().as_ref(),
+                b.to_string_lossy().as_ref(),
+            ])
+            .output()
+            .expect("run converge cvir-eq")
+    };
+    let same = eq(&plain, &optimized);
+    assert!(same.status.success(), "{same:?}");
+    assert_eq!(String::from_utf8_lossy(&same.stdout), "equivalent\n");
+
+    let differ = eq(&plain, &other);
+    assert_eq!(differ.status.code(), Some(1), "{differ:?}");
+    let stdout = String::from_utf8_lossy(&differ.stdout);
+    assert!(stdout.contains("differ: "), "{stdout}");
+}
+
+#[test]
+fn sim_cli_scratch_matches_in_memory_run() {
+    let program = codegen_program("scratch.cv");
+    let dir = temp_path("scratch");
+    let output = converge()
+        .args([
+            "sim",
+            program.to_string_lossy().as_ref(),
+            "--quiet",
+            "--scratch",
+            dir.to_string_lossy().as_ref(),
+        ])
+        .output()
+        .expect("run converge sim --scratch");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        results(&String::from_utf8_lossy(&output.stdout)),
+        results(&sim_stdout(&program))
+    );
+    assert_eq!(std::fs::read_dir(&dir).expect("scratch dir").count(), 0);
+
+    let output = converge()
+        .args(["sim", program.to_string_lossy().as_ref(), "--scratch"])
+        .output()
+        .expect("run converge sim --scratch");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn sim_cli_publishes_spikes_to_subscribers() {
+    use std::io::{BufRead, Read};
+    let program = codegen_program("publish.cv");
+    let mut child = converge()
+        .args(["sim", program.to_string_lossy().as_ref(), "--quiet"])
+        .args(["--publish", "tcp://127.0.0.1:0", "--subscribers", "1"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn converge sim --publish");
+    let mut stderr = std::io::BufReader::new(child.stderr.take().expect("stderr"));
+    let mut line = String::new();
+    stderr.read_line(&mut line).expect("read publish line");
+    let addr = line
+        .trim()
+        .strip_prefix("publishing on tcp://")
+        .expect("publish line")
+        .to_string();
+
+    let mut messages = String::new();
+    std::net::TcpStream::connect(&addr)
+        .expect("subscribe")
+        .read_to_string(&mut messages)
+        .expect("read messages");
+    let output = child.wait_with_output().expect("wait for converge sim");
+    assert!(output.status.success(), "{output:?}");
+
+    let lines: Vec<&str> = messages.lines().collect();
+    assert!(lines[0].starts_with("{\"type\":\"start\",\"duration_ns\":100000000,"));
+    let spikes = lines
+        .iter()
+        .filter(|l| l.contains("\"type\":\"spike\""))
+        .count();
+    let summary = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        summary.contains(&format!("\"total_spikes\": {spikes},")),
+        "{summary}"
+    );
+    // One 100 ms window covers the whole run.
+    let rates: Vec<&&str> = lines
+        .iter()
+        .filter(|l| l.contains("\"type\":\"rates\""))
+        .collect();
+    assert_eq!(rates.len(), 1);
+    assert!(rates[0].contains("\"window_ns\":100000000"));
+    assert!(lines.last().unwrap().starts_with("{\"type\":\"summary\""));
+}
+
+#[test]
+fn cosim_cli_listens_for_grpc_clients() {
+    use std::io::BufRead;
+    let mut child = converge()
+        .args(["cosim", example("poisson.cv").to_string_lossy().as_ref()])
+        .args(["--listen", "127.0.0.1:0"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn converge cosim");
+    let mut stderr = std::io::BufReader::new(child.stderr.take().expect("stderr"));
+    let mut line = String::new();
+    stderr.read_line(&mut line).expect("read listen line");
+    let addr = line
+        .trim()
+        .strip_prefix("listening on grpc://")
+        .expect("listen line")
+        .to_string();
+    let connected = std::net::TcpStream::connect(&addr);
+    child.kill().expect("stop server");
+    let _ = child.wait();
+    assert!(connected.is_ok(), "{connected:?}");
+
+    let output = converge()
+        .args(["cosim", example("poisson.cv").to_string_lossy().as_ref()])
+        .args(["--listen", "nowhere"])
+        .output()
+        .expect("run converge cosim");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid listen address `nowhere`"));
+}
+
+#[test]
+fn sim_cli_selects_a_network() {
+    let program = temp_path("networks.cv");
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_m = 10 ms }\n\
+         run for 20 ms\n\
+         network Small { layer X[2] : LIF }\n\
+         network Large {\n\
+             layer X[8] : LIF\n\
+             stimulus X = Poisson(rate=200 Hz)\n\
+             seed 7\n\
+         }\n",
+    )
+    .expect("write program");
+    let sim = |args: &[&str]| {
+        converge()
+            .args(["sim", program.to_string_lossy().as_ref(), "--quiet"])
+            .args(args)
+            .output()
+            .expect("run converge sim")
+    };
+
+    let output = sim(&["--network", "Large"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"seed\": 7"), "{stdout}");
+    assert!(stdout.contains("\"size\": 8"), "{stdout}");
+
+    let output = sim(&[]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("the program defines several networks (`Small`, `Large`); select one"),
+        "{stderr}"
+    );
+
+    let output = sim(&["--network", "Medium"]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no network `Medium` (defined: `Small`, `Large`)"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn experiment_cli_reports_every_seed() {
+    let program = temp_path("sweep.cv");
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_m = 10 ms }\n\
+         run for 20 ms\n\
+         network Small { layer In[4] : LIF\n stimulus In = Poisson(rate=100 Hz) }\n\
+         network Large { layer In[16] : LIF\n stimulus In = Poisson(rate=100 Hz) }\n\
+         experiment {\n\
+             run Small with seed 1..2\n\
+             run Large with seed 7..9\n\
+             report total_spikes, rate(In)\n\
+         }\n",
+    )
+    .expect("write program");
+    let output = converge()
+        .args(["experiment", program.to_string_lossy().as_ref()])
+        .output()
+        .expect("run converge experiment");
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[5/5] run Large with seed 7..9: seed 9"),
+        "{stderr}"
+    );
+    let report = converge_lang::json::parse(&String::from_utf8_lossy(&output.stdout))
+        .expect("report is JSON");
+    let groups = report
+        .get("groups")
+        .and_then(|g| g.as_array())
+        .expect("groups");
+    let runs: Vec<usize> = groups
+        .iter()
+        .map(|g| {
+            g.get("runs")
+                .and_then(|r| r.as_array())
+                .map_or(0, |r| r.len())
+        })
+        .collect();
+    assert_eq!(runs, [2, 3]);
+    assert!(
+        groups[1]
+            .get("stats")
+            .and_then(|s| s.get("rate(In)"))
+            .and_then(|s| s.get("mean"))
+            .is_some()
+    );
+}
+
+#[test]
+fn sim_cli_replays_recorded_input() {
+    let write = |name: &str, seed: u64, size: u64| {
+        let path = temp_path(name);
+        std::fs::write(
+            &path,
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 1.0 }}\n\
+                 layer In[{size}] : LIF\n\
+                 layer Out[4] : LIF\n\
+                 connect In -> Out {{ w = 0.6, d = 1 ms }}\n\
+                 stimulus In = Poisson(rate=300 Hz)\n\
+                 seed {seed}\n\
+                 run for 50 ms\n"
+            ),
+        )
+        .expect("write program");
+        path
+    };
+    let input = temp_path("input.cvr");
+    let sim = |program: &Path, args: &[&str]| {
+        converge()
+            .args(["sim", program.to_string_lossy().as_ref(), "--quiet"])
+            .args(args)
+            .output()
+            .expect("run converge sim")
+    };
+    let hash = |output: &std::process::Output| {
+        let summary = converge_lang::json::parse(&String::from_utf8_lossy(&output.stdout))
+            .expect("summary is JSON");
+        summary
+            .get("spike_hash")
+            .and_then(|h| h.as_str())
+            .expect("spike_hash")
+            .to_string()
+    };
+
+    let recorded = sim(
+        &write("record.cv", 1, 8),
+        &["--record-input", input.to_string_lossy().as_ref()],
+    );
+    assert!(recorded.status.success(), "{recorded:?}");
+
+    // Another seed draws other Poisson spikes, unless they are replayed.
+    let reseeded = write("reseeded.cv", 2, 8);
+    assert_ne!(hash(&sim(&reseeded, &[])), hash(&recorded));
+    let replayed = sim(
+        &reseeded,
+        &["--replay-input", input.to_string_lossy().as_ref()],
+    );
+    assert!(replayed.status.success(), "{replayed:?}");
+    assert_eq!(hash(&replayed), hash(&recorded));
+
+    let output = sim(
+        &write("resized.cv", 1, 6),
+        &["--replay-input", input.to_string_lossy().as_ref()],
+    );
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("layer `In` has 6 neurons, got 8 in the input recording"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn debug_compare_cli_reports_the_first_divergence() {
+    let write = |name: &str, weight: &str| {
+        let path = temp_path(name);
+        std::fs::write(
+            &path,
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 1.0 }}\n\
+                 layer In[8] : LIF\n\
+                 layer Out[4] : LIF\n\
+                 connect In -> Out {{ w = {weight}, d = 1 ms }}\n\
+                 stimulus In = Poisson(rate=300 Hz)\n\
+                 seed 5\n\
+                 run for 40 ms\n"
+            ),
+        )
+        .expect("write program");
+        path
+    };
+    let a = write("compare_a.cv", "0.6");
+    let compare = |b: &Path| {
+        converge()
+            .args([
+                "debug-compare",
+                a.to_string_lossy().as_ref(),
+                b.to_string_lossy().as_ref(),
+            ])
+            .output()
+            .expect("run converge debug-compare")
+    };
+
+    let output = compare(&write("compare_same.cv", "0.6"));
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "no divergence in 40 steps\n");
+
+    let output = compare(&write("compare_b.cv", "0.61"));
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("first divergence at step ") && stdout.contains("layer `Out`"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn sim_cli_dumps_layer_state() {
+    let program = temp_path("dump.cv");
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_m = 10 ms, v_th = 2.0 }\n\
+         layer In[4] : LIF\n\
+         layer Out[2] : LIF\n\
+         connect In -> Out { w = 0.5, d = 1 ms }\n\
+         stimulus In = Poisson(rate=300 Hz)\n\
+         run for 20 ms\n",
+    )
+    .expect("write program");
+    let dump = temp_path("state.ndjson");
+    let sim = |args: &[&str]| {
+        converge()
+            .args(["sim", program.to_string_lossy().as_ref(), "--quiet"])
+            .args(["--dump-state", dump.to_string_lossy().as_ref()])
+            .args(args)
+            .output()
+            .expect("run converge sim")
+    };
+
+    let output = sim(&["--dump-every", "5", "--dump-layers", "Out"]);
+    assert!(output.status.success(), "{output:?}");
+    let text = std::fs::read_to_string(&dump).expect("read dump");
+    let lines: Vec<_> = text
+        .lines()
+        .map(|l| converge_lang::json::parse(l).expect("line is JSON"))
+        .collect();
+    assert_eq!(lines.len(), 4, "{text}");
+    for (line, step) in lines.iter().zip([0.0, 5.0, 10.0, 15.0]) {
+        assert_eq!(line.get("step").and_then(|s| s.as_f64()), Some(step));
+        assert_eq!(line.get("layer").and_then(|l| l.as_str()), Some("Out"));
+        assert_eq!(
+            line.get("v").and_then(|v| v.as_array()).map(|v| v.len()),
+            Some(2)
+        );
+    }
+
+    let output = sim(&["--dump-limit", "0"]);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("over the 0 B limit; dump fewer layers (--dump-layers)"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn strict_flag_turns_unknown_key_warnings_into_errors() {
+    let program = temp_path("typo.cv");
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_n = 5 ms }\n\
+         layer A[2] : LIF\n\
+         run for 5 ms\n",
+    )
+    .expect("write program");
+    let run = |args: &[&str]| {
+        converge()
+            .args(args)
+            .arg(&program)
+            .output()
+            .expect("run converge")
+    };
+
+    let output = run(&["sim", "--quiet"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: unknown neuron key `tau_n`; did you mean `tau_m`?"),
+        "{stderr}"
+    );
+
+    let output = run(&["check"]);
+    assert!(output.status.success(), "{output:?}");
+    let output = run(&["--strict", "check"]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("error: unknown neuron key `tau_n`"),
+        "{stderr}"
+    );
+    let output = run(&["--strict", "sim"]);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn simulation_errors_point_at_the_source() {
+    let program = temp_path("negative-delay.cv");
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_m = 5 ms }\n\
+         layer A[2] : LIF\n\
+         connect A -> A { w = 0.5, d = -1 ms }\n\
+         run for 5 ms\n",
+    )
+    .expect("write program");
+    let output = converge()
+        .args(["sim", "--quiet"])
+        .arg(&program)
+        .output()
+        .expect("run converge");
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "error: negative delay is not allowed\n  \
+             --> line 3, col 27\n   \
+             |\n  \
+             3 | connect A -> A { w = 0.5, d = -1 ms }\n   \
+             |                           ^^^^^^^^^\n"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn fmt_fix_rewrites_deprecated_keys() {
+    let program = temp_path("deprecated.cv");
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_m = 5 ms }\n\
+         layer A[2] : LIF\n\
+         connect A -> A { weight = 0.5 }\n\
+         run for 5 ms\n",
+    )
+    .expect("write program");
+
+    let output = converge()
+        .arg("fmt")
+        .arg(&program)
+        .output()
+        .expect("run converge");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: the connection key `weight` is deprecated; use `w`"),
+        "{stderr}"
+    );
+
+    let output = converge()
+        .args(["fmt", "--fix"])
+        .arg(&program)
+        .output()
+        .expect("run converge");
+    assert!(output.status.success(), "{output:?}");
+    let fixed = std::fs::read_to_string(&program).expect("read program");
+    assert!(fixed.contains("connect A -> A { w = 0.5 }"), "{fixed}");
+    let output = converge()
+        .arg("fmt")
+        .arg(&program)
+        .output()
+        .expect("run converge");
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn sim_cli_overrides_params() {
+    let program = temp_path("params.cv");
+    std::fs::write(
+        &program,
+        "params { input_rate = 0 Hz }\n\
+         neuron LIF { tau_m = 10 ms, v_th = 0.5 }\n\
+         layer In[4] : LIF\n\
+         stimulus In = Poisson(rate=input_rate)\n\
+         run for 20 ms\n",
+    )
+    .expect("write program");
+    let total = |args: &[&str]| {
+        let output = converge()
+            .arg("sim")
+            .arg(&program)
+            .args(args)
+            .output()
+            .expect("run converge");
+        assert!(output.status.success(), "{output:?}");
+        let summary =
+            converge_lang::json::parse(&String::from_utf8_lossy(&output.stdout)).expect("json");
+        summary
+            .get("total_spikes")
+            .and_then(|t| t.as_f64())
+            .unwrap()
+    };
+    assert_eq!(total(&[]), 0.0);
+    assert!(total(&["--set", "input_rate=500Hz"]) > 0.0);
+
+    let output = converge()
+        .arg("sim")
+        .arg(&program)
+        .args(["--set", "rate=5Hz"])
+        .output()
+        .expect("run converge");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown parameter `rate`"));
+}
+
+#[test]
+fn elaborate_cli_prints_the_resolved_model() {
+    let program = temp_path("elaborate.cv");
+    std::fs::write(
+        &program,
+        "params { rate = 20 Hz }\n\
+         neuron LIF { tau_m = 10 ms }\n\
+         run for 20 ms\n\
+         network Small {\n\
+             layer In[${ELABORATE_SIZE:4}] : LIF\n\
+             connect In -> In { weight = 0.2 }\n\
+             stimulus In = Poisson(rate=rate)\n\
+         }\n\
+         network Large {\n\
+             layer In[64] : LIF\n\
+             seed 7\n\
+         }\n",
+    )
+    .expect("write program");
+    let output = converge()
+        .arg("elaborate")
+        .arg(&program)
+        .args(["--network", "Small", "--set", "rate=50Hz"])
+        .output()
+        .expect("run converge elaborate");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "neuron LIF { tau_m = 10 ms }\n\
+         run for 20 ms\n\
+         layer In[4] : LIF\n\
+         connect In -> In { w = 0.2 }\n\
+         stimulus In = Poisson(rate=50 Hz)\n"
+    );
+
+    // The printed model simulates exactly like the original.
+    let elaborated = temp_path("elaborated.cv");
+    std::fs::write(&elaborated, &output.stdout).expect("write elaborated");
+    let original = converge()
+        .arg("sim")
+        .arg(&program)
+        .args(["--network", "Small", "--set", "rate=50Hz", "--quiet"])
+        .output()
+        .expect("run converge sim");
+    assert_eq!(
+        results(&sim_stdout(&elaborated)),
+        results(&String::from_utf8_lossy(&original.stdout))
+    );
+
+    let output = converge()
+        .arg("elaborate")
+        .arg(&program)
+        .args(["--format", "cvir"])
+        .output()
+        .expect("run converge elaborate");
+    assert!(output.status.success(), "{output:?}");
+    let cvir = converge_lang::json::parse(&String::from_utf8_lossy(&output.stdout)).expect("cvir");
+    let kinds: Vec<&str> = cvir
+        .get("items")
+        .and_then(|items| items.as_array())
+        .expect("items")
+        .iter()
+        .filter_map(|item| item.get("kind").and_then(|k| k.as_str()))
+        .collect();
+    assert_eq!(kinds, ["neuron", "run", "network", "network"]);
+}
+
+#[test]
+fn config_file_supplies_default_flags() {
+    let project = temp_path("config-project");
+    let _ = std::fs::remove_dir_all(&project);
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join(".converge.toml"),
+        "[sim]\nout = \"from-config.ndjson\"\nquiet = true\n",
+    )
+    .unwrap();
+    let example = example("poisson.cv");
+    let run = |extra: &[&str]| {
+        let output = converge()
+            .current_dir(&project)
+            .env("HOME", &project)
+            .args(extra)
+            .args(["sim", example.to_string_lossy().as_ref()])
+            .output()
+            .expect("run converge");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+
+    run(&[]);
+    assert!(project.join("from-config.ndjson").is_file());
+
+    std::fs::remove_file(project.join("from-config.ndjson")).unwrap();
+    run(&["--no-config"]);
+    assert!(!project.join("from-config.ndjson").exists());
+
+    std::fs::write(project.join(".converge.toml"), "[sim]\nout = [\n").unwrap();
+    let output = converge()
+        .current_dir(&project)
+        .args(["sim", example.to_string_lossy().as_ref()])
+        .output()
+        .expect("run converge");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(".converge.toml"), "{stderr}");
+}
+
+#[test]
+fn exit_codes_name_the_failure() {
+    let src = std::fs::read_to_string(example("assert.cv")).expect("read example");
+    let run = |name: &str, src: &str| {
+        let program = temp_path(name);
+        std::fs::write(&program, src).expect("write program");
+        converge()
+            .args(["--status-json", "sim", "--quiet"])
+            .arg(&program)
+            .output()
+            .expect("run converge")
+    };
+    let status = |output: &std::process::Output| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last = stderr.lines().last().unwrap_or_default().to_string();
+        let value = converge_lang::json::parse(&last).unwrap_or_else(|_| panic!("{stderr}"));
+        (
+            value
+                .get("status")
+                .and_then(|s| s.as_str())
+                .map(str::to_string),
+            value.get("exit_code").and_then(|c| c.as_f64()),
+            output.status.code(),
+        )
+    };
+
+    let ok = run("exit_ok.cv", &src);
+    assert_eq!(status(&ok), (Some("ok".into()), Some(0.0), Some(0)));
+    let parse = run("exit_parse.cv", "layer X[");
+    assert_eq!(
+        status(&parse),
+        (Some("parse_error".into()), Some(3.0), Some(3))
+    );
+    let invalid = run("exit_invalid.cv", &src.replace(": LIF", ": Missing"));
+    assert_eq!(
+        status(&invalid),
+        (Some("validation_error".into()), Some(4.0), Some(4))
+    );
+    let assertion = run(
+        "exit_assert.cv",
+        &src.replace("spikes(Input) > 0", "spikes(Input) > 100000"),
+    );
+    assert_eq!(
+        status(&assertion),
+        (Some("assertion_failed".into()), Some(7.0), Some(7))
+    );
+
+    let output = converge()
+        .args(["sim", "--status-json"])
+        .arg(temp_path("missing.cv"))
+        .output()
+        .expect("run converge");
+    assert_eq!(
+        status(&output),
+        (Some("io_error".into()), Some(6.0), Some(6))
+    );
+    let output = converge()
+        .args(["sim", "--status-json", "--frobnicate"])
+        .output()
+        .expect("run converge");
+    assert_eq!(
+        status(&output),
+        (Some("usage_error".into()), Some(2.0), Some(2))
+    );
+}
+
+#[test]
+fn sim_out_dir_writes_every_artifact() {
+    let program = temp_path("outdir.cv");
+    std::fs::write(
+        &program,
+        "params { w0 = 0.5 }\n\
+         neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+         layer A[8] : LIF\n\
+         layer B[4] : LIF\n\
+         connect A -> B { w = w0, d = 1 ms, plasticity = RSTDP(w_max = 2), \
+                          delay_plasticity = DelaySTDP(d_max = 4 steps) }\n\
+         stimulus A = Poisson(rate=300 Hz)\n\
+         seed 3\n\
+         run for 20 ms\n",
+    )
+    .expect("write program");
+    let dir = temp_path("outdir");
+    let _ = std::fs::remove_dir_all(&dir);
+    let sim = |extra: &[&str]| {
+        converge()
+            .args(["sim", program.to_string_lossy().as_ref(), "--quiet"])
+            .arg("--out-dir")
+            .arg(&dir)
+            .args(extra)
+            .output()
+            .expect("run converge sim")
+    };
+
+    let output = sim(&["--set", "w0=0.6"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty());
+    let summary = std::fs::read_to_string(dir.join("summary.json")).expect("summary");
+    let summary = converge_lang::json::parse(&summary).expect("summary json");
+    let config = std::fs::read_to_string(dir.join("effective-config.json")).expect("config");
+    let config = converge_lang::json::parse(&config).expect("config json");
+    assert_eq!(
+        config
+            .get("params")
+            .and_then(|p| p.get("w0"))
+            .map(|v| v.to_string()),
+        Some("\"0.6\"".to_string())
+    );
+    assert_eq!(
+        config.get("seed").map(|v| v.to_string()),
+        summary.get("seed").map(|v| v.to_string())
+    );
+    let spikes = std::fs::read_to_string(dir.join("spikes.ndjson")).expect("spikes");
+    assert!(spikes.lines().count() > 1, "{spikes}");
+    let weights = std::fs::read_to_string(dir.join("weights/A_B.csv")).expect("weights");
+    assert_eq!(weights.lines().count(), 1 + 8);
+    let delays = std::fs::read_to_string(dir.join("weights/A_B.delays.csv")).expect("delays");
+    assert_eq!(delays.lines().count(), 1 + 8);
+
+    let refused = sim(&[]);
+    assert_eq!(refused.status.code(), Some(2), "{refused:?}");
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(stderr.contains("pass --force"), "{stderr}");
+    let forced = sim(&["--force"]);
+    assert!(forced.status.success(), "{forced:?}");
+
+    let both = sim(&["--out", "summary.json"]);
+    assert_eq!(both.status.code(), Some(2), "{both:?}");
+}
+
+#[test]
+fn sim_cli_writes_a_manifest_of_the_run() {
+    let example = example("poisson.cv");
+    let dir = temp_path("manifest-run");
+    let _ = std::fs::remove_dir_all(&dir);
+    let manifest = temp_path("manifest.json");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--quiet"])
+        .arg("--out-dir")
+        .arg(&dir)
+        .arg("--manifest")
+        .arg(&manifest)
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+
+    let text = std::fs::read_to_string(&manifest).expect("manifest");
+    let manifest = converge_lang::json::parse(&text).expect("manifest json");
+    let field = |key: &str| manifest.get(key).map(|v| v.to_string());
+    let summary = std::fs::read_to_string(dir.join("summary.json")).expect("summary");
+    let parsed = converge_lang::json::parse(&summary).expect("summary json");
+    for key in [
+        "input_hash",
+        "seed",
+        "streams",
+        "spike_hash",
+        "toolchain_version",
+    ] {
+        assert_eq!(field(key), parsed.get(key).map(|v| v.to_string()), "{key}");
+    }
+    assert_eq!(field("manifest_version"), Some("1".to_string()));
+    assert!(manifest.get("platform").and_then(|p| p.get("os")).is_some());
+
+    let cvir = converge()
+        .args(["elaborate", example.to_string_lossy().as_ref()])
+        .args(["--format", "cvir"])
+        .output()
+        .expect("run converge elaborate");
+    let cvir_hash = converge_sim::source_hash(&String::from_utf8_lossy(&cvir.stdout));
+    assert_eq!(
+        field("cvir_hash"),
+        Some(format!(
+            "\"{}\"",
+            converge_sim::format_spike_hash(cvir_hash)
+        ))
+    );
+
+    let outputs = manifest
+        .get("outputs")
+        .and_then(|o| o.as_array())
+        .expect("outputs");
+    let listed: Vec<(String, String)> = outputs
+        .iter()
+        .map(|o| {
+            let path = o.get("path").and_then(|p| p.as_str()).expect("path");
+            let hash = o.get("hash").and_then(|h| h.as_str()).expect("hash");
+            (path.to_string(), hash.to_string())
+        })
+        .collect();
+    let names: Vec<&str> = listed
+        .iter()
+        .map(|(path, _)| path.rsplit('/').next().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        ["spikes.ndjson", "summary.json", "effective-config.json"]
+    );
+    let (_, summary_hash) = &listed[1];
+    assert_eq!(
+        summary_hash,
+        &converge_sim::format_spike_hash(converge_sim::source_hash(&summary))
+    );
+}
+
+#[test]
+fn sim_analyzes_correlations() {
+    let example = example("poisson.cv");
+    let csv = temp_path("correlations.csv");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--quiet"])
+        .args([
+            "--analyze",
+            "correlations",
+            "--bin",
+            "2 ms",
+            "--max-lag",
+            "3",
+        ])
+        .arg("--analysis-out")
+        .arg(&csv)
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+    let csv = std::fs::read_to_string(&csv).expect("read correlations");
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("a,b,count_correlation,lag_ns,pairs"));
+    let lags: Vec<&str> = lines
+        .take(7)
+        .map(|l| l.split(',').nth(3).unwrap())
+        .collect();
+    assert_eq!(
+        lags,
+        [
+            "-6000000", "-4000000", "-2000000", "0", "2000000", "4000000", "6000000"
+        ]
+    );
+
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--quiet"])
+        .args(["--analyze", "correlations", "--analyze-layers", "Nope"])
+        .output()
+        .expect("run converge sim");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown layer `Nope`"), "{stderr}");
+}
+
+#[test]
+fn sim_reports_applied_defaults() {
+    let program = temp_path("defaults.cv");
+    std::fs::write(
+        &program,
+        "neuron LIF { tau_m = 10 ms }\n\
+         layer A[4] : LIF\n\
+         layer B[2] : LIF\n\
+         connect A -> B { d = 1 ms }\n\
+         stimulus A = Poisson(rate=100 Hz)\n\
+         run for 5 ms\n",
+    )
+    .expect("write program");
+    let output = converge()
+        .args([
+            "--verbose",
+            "sim",
+            program.to_string_lossy().as_ref(),
+            "--quiet",
+        ])
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+    let summary =
+        converge_lang::json::parse(&String::from_utf8_lossy(&output.stdout)).expect("summary json");
+    let defaults: Vec<String> = summary
+        .get("defaults")
+        .and_then(|d| d.as_array())
+        .expect("defaults")
+        .iter()
+        .map(|d| {
+            let field = |k| d.get(k).and_then(|v| v.as_str()).unwrap_or_default();
+            format!("{}: {} = {}", field("item"), field("key"), field("value"))
+        })
+        .collect();
+    assert_eq!(
+        defaults,
+        [
+            "neuron LIF: v_th = 1",
+            "connect A -> B: w = 1",
+            "run: step = 1 ms"
+        ]
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("effective model: neuron LIF: v_th = 1; connect A -> B: w = 1"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn sim_dumps_weight_images() {
+    let example = example("poisson.cv");
+    let dir = temp_path("weight-images");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--quiet"])
+        .arg("--dump-weight-images")
+        .arg(&dir)
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+    let image = std::fs::read(dir.join("Input_Output.pgm")).expect("read image");
+    let text = String::from_utf8_lossy(&image);
+    let mut header = text.lines();
+    assert_eq!(header.next(), Some("P5"));
+    assert!(header.next().unwrap().starts_with("# weights from 0.5"));
+    assert_eq!(header.next(), Some("4 8"));
+    assert_eq!(header.next(), Some("255"));
+    let pixels = &image[image.len() - 32..];
+    assert!(pixels.contains(&0) && pixels.contains(&255), "{pixels:?}");
+}
+
+#[cfg(feature = "nwb")]
+#[test]
+fn sim_exports_nwb() {
+    let example = example("poisson.cv");
+    let nwb = temp_path("run.nwb");
+    let output = converge()
+        .args(["sim", example.to_string_lossy().as_ref(), "--quiet"])
+        .arg("--nwb")
+        .arg(&nwb)
+        .output()
+        .expect("run converge sim");
+    assert!(output.status.success(), "{output:?}");
+    let bytes = std::fs::read(&nwb).expect("read nwb");
+    assert!(bytes.starts_with(b"\x89HDF\r\n\x1a\n"));
+    let has = |s: &[u8]| bytes.windows(s.len()).any(|w| w == s);
+    assert!(has(b"NWBFile"));
+    assert!(has(b"spike_times_index"));
+}
@@ -1,17 +1,107 @@
-use crate::diagnostic::Span;
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::deprecated::DeprecatedUse;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::lexer::quote;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Program {
+    /// The leading `converge "0.2"` pragma, if the file has one.
+    pub version: Option<VersionPragma>,
     pub items: Vec<Item>,
+    /// Deprecated keys the parser replaced, in source order.
+    pub deprecated: Vec<DeprecatedUse>,
+    /// The `params` block, with overrides applied. The parser has already
+    /// substituted every reference to them.
+    pub params: Vec<Assign>,
 }
 
 impl Program {
     pub fn new(items: Vec<Item>) -> Self {
-        Self { items }
+        Self {
+            version: None,
+            items,
+            deprecated: Vec::new(),
+            params: Vec::new(),
+        }
+    }
+
+    /// The language version the file targets: its pragma's, or
+    /// [`LanguageVersion::CURRENT`] without one.
+    pub fn language_version(&self) -> LanguageVersion {
+        self.version
+            .as_ref()
+            .map_or(LanguageVersion::CURRENT, |pragma| pragma.version)
+    }
+
+    /// The `network` blocks, in source order.
+    pub fn networks(&self) -> impl Iterator<Item = &NetworkDef> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Network(def) => Some(def),
+            _ => None,
+        })
+    }
+
+    /// The model of network `name`: the items outside every network, which
+    /// all networks share, followed by the network's own. A network's own
+    /// `seed` or `run` replaces the shared one. Without a name, the only
+    /// network, or the program itself when it has none.
+    pub fn select_network(&self, name: Option<&str>) -> Result<Cow<'_, Program>, Diagnostic> {
+        let names: Vec<&str> = self.networks().map(|n| n.name.name.as_str()).collect();
+        let listed = || {
+            names
+                .iter()
+                .map(|n| format!("`{n}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let network = match name {
+            None if names.is_empty() => return Ok(Cow::Borrowed(self)),
+            None if names.len() > 1 => {
+                return Err(Diagnostic::new(format!(
+                    "the program defines several networks ({}); select one",
+                    listed()
+                )));
+            }
+            None => names[0],
+            Some(name) if names.contains(&name) => name,
+            Some(name) if names.is_empty() => {
+                return Err(Diagnostic::new(format!(
+                    "no network `{name}`: the program has no `network` blocks"
+                )));
+            }
+            Some(name) => {
+                return Err(Diagnostic::new(format!(
+                    "no network `{name}` (defined: {})",
+                    listed()
+                )));
+            }
+        };
+        let own: Vec<&Item> = self
+            .networks()
+            .filter(|n| n.name.name == network)
+            .flat_map(|n| &n.items)
+            .collect();
+        let has_seed = own.iter().any(|item| matches!(item, Item::Seed(_)));
+        let has_run = own.iter().any(|item| matches!(item, Item::Run(_)));
+        let shared = self.items.iter().filter(|item| match item {
+            Item::Network(_) => false,
+            Item::Seed(_) => !has_seed,
+            Item::Run(_) => !has_run,
+            _ => true,
+        });
+        Ok(Cow::Owned(Program::new(
+            shared.chain(own).cloned().collect(),
+        )))
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Item {
     Neuron(NeuronDef),
     Layer(LayerDef),
@@ -19,58 +109,770 @@ pub enum Item {
     Stimulus(StimulusDef),
     Run(RunStmt),
     Seed(SeedStmt),
+    Assert(AssertStmt),
+    Disconnect(DisconnectStmt),
+    Reward(RewardStmt),
+    Record(RecordStmt),
+    Probe(ProbeDef),
+    Modulator(ModulatorDef),
+    Experiment(ExperimentDef),
+    Readout(ReadoutDef),
+    Network(NetworkDef),
+    Sweep(SweepDef),
 }
 
+/// `converge "<major>.<minor>"`: the language version a file is written
+/// against, before its first item.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct VersionPragma {
+    pub version: LanguageVersion,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LanguageVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl LanguageVersion {
+    /// The version this toolchain implements.
+    pub const CURRENT: LanguageVersion = LanguageVersion { major: 0, minor: 2 };
+
+    /// Parses `"<major>.<minor>"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (major, minor) = s.split_once('.')?;
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if !digits(major) || !digits(minor) {
+            return None;
+        }
+        Some(Self {
+            major: major.parse().ok()?,
+            minor: minor.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for LanguageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct NeuronDef {
     pub name: Ident,
     pub body: Vec<Assign>,
+    /// The `///` lines before the item, joined by newlines.
+    pub doc: Option<String>,
+    /// `@name(args...)` attributes, in order, as calls; `@name` has no
+    /// arguments.
+    pub attributes: Vec<Call>,
+    /// From `neuron` to the closing `}`, without doc comments and
+    /// attributes.
+    pub span: Span,
+}
+
+/// `modulator <name> { tau = ..., source = <layer>, gain = ... }`: a global
+/// scalar that decays with `tau` and rises with spikes from `source`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ModulatorDef {
+    pub name: Ident,
+    pub body: Vec<Assign>,
+    pub span: Span,
+}
+
+/// `experiment { epochs = ..., isi = ..., shuffle = ..., readout = <layer> }`:
+/// how `Encode` rows are scheduled, and which layer's spikes are counted per
+/// presentation.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ExperimentDef {
+    pub body: Vec<Assign>,
+    pub span: Span,
+}
+
+/// `experiment { run <network> with seed 1..10; report total_spikes, ... }`:
+/// a set of runs, one per seed, whose metrics are aggregated into one
+/// report.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SweepDef {
+    pub runs: Vec<SweepRun>,
+    pub report: Vec<ReportMetric>,
+    pub span: Span,
 }
 
+/// `run [<network>] with seed <first>..<last>`, both seeds included.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SweepRun {
+    /// `None` runs the program's only model.
+    pub network: Option<Ident>,
+    pub first_seed: u64,
+    pub last_seed: u64,
+    pub span: Span,
+}
+
+impl SweepRun {
+    pub fn seeds(&self) -> std::ops::RangeInclusive<u64> {
+        self.first_seed..=self.last_seed
+    }
+}
+
+impl fmt::Display for SweepRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("run ")?;
+        if let Some(network) = &self.network {
+            write!(f, "{} ", network.name)?;
+        }
+        write!(f, "with seed {}..{}", self.first_seed, self.last_seed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ReportMetric {
+    /// Spikes of every layer.
+    TotalSpikes,
+    /// Firing rate over every neuron, in Hz.
+    MeanRate,
+    /// `spikes(<layer>)` or `rate(<layer>)`, as in `assert`.
+    Layer(Metric),
+}
+
+impl fmt::Display for ReportMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportMetric::TotalSpikes => f.write_str("total_spikes"),
+            ReportMetric::MeanRate => f.write_str("mean_rate"),
+            ReportMetric::Layer(metric) => write!(f, "{metric}"),
+        }
+    }
+}
+
+/// `network <name> { ... }`: one model among several in a file. Layer,
+/// connection and modulator names are scoped to the network; items outside
+/// every network, such as a library of neuron models, are shared.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct NetworkDef {
+    pub name: Ident,
+    pub items: Vec<Item>,
+    pub span: Span,
+}
+
+/// `readout <layer> labels "<file>" [rule = <rule>]`: scores the layer's
+/// response to every experiment presentation against one label per dataset
+/// row.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ReadoutDef {
+    pub layer: Ident,
+    pub labels: String,
+    /// How a prediction is read off the layer; `max_spikes` when unset.
+    pub rule: Option<Ident>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct LayerDef {
     pub name: Ident,
     pub size: u64,
+    /// The size between the brackets.
+    pub size_span: Span,
     pub neuron: Ident,
+    /// The `///` lines before the item, joined by newlines.
+    pub doc: Option<String>,
+    /// `@name(args...)` attributes, in order, as calls; `@name` has no
+    /// arguments.
+    pub attributes: Vec<Call>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ConnectDef {
+    /// Set by `connect name: A -> B`, so other items can refer to it.
+    pub name: Option<Ident>,
     pub src: Ident,
     pub dst: Ident,
+    pub kind: ConnectKind,
     pub body: Vec<Assign>,
+    /// The `///` lines before the item, joined by newlines.
+    pub doc: Option<String>,
+    /// `@name(args...)` attributes, in order, as calls; `@name` has no
+    /// arguments.
+    pub attributes: Vec<Call>,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ConnectKind {
+    /// `A -> B`: spikes delivered through weighted, delayed synapses.
+    #[default]
+    Chemical,
+    /// `A <-> B electrical`: gap junctions coupling membrane potentials in
+    /// both directions every step.
+    Electrical,
+}
+
+impl ConnectDef {
+    /// `name: src -> dst`, or `src -> dst` when unnamed; electrical
+    /// connections use `<->`.
+    pub fn label(&self) -> String {
+        let arrow = self.kind.arrow();
+        match &self.name {
+            Some(name) => format!("{}: {} {arrow} {}", name.name, self.src.name, self.dst.name),
+            None => format!("{} {arrow} {}", self.src.name, self.dst.name),
+        }
+    }
+}
+
+/// `disconnect <target> [where <filter>]`: removes the synapses of earlier
+/// connections that match `filter`, or all of them when it is absent.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct DisconnectStmt {
+    pub target: ConnectionRef,
+    pub filter: Option<SynapseFilter>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ConnectionRef {
+    /// A connection declared as `connect name: ...`.
+    Named(Ident),
+    /// Every connection from `src` to `dst`.
+    Layers { src: Ident, dst: Ident },
+}
+
+impl ConnectionRef {
+    pub fn matches(&self, def: &ConnectDef) -> bool {
+        match self {
+            ConnectionRef::Named(name) => def.name.as_ref().is_some_and(|n| n.name == name.name),
+            ConnectionRef::Layers { src, dst } => {
+                def.src.name == src.name && def.dst.name == dst.name
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConnectionRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionRef::Named(name) => write!(f, "{}", name.name),
+            ConnectionRef::Layers { src, dst } => write!(f, "{} -> {}", src.name, dst.name),
+        }
+    }
+}
+
+/// Predicate over a synapse's source and destination neuron indices.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum SynapseFilter {
+    Compare {
+        lhs: SynapseOperand,
+        op: CompareOp,
+        rhs: SynapseOperand,
+        span: Span,
+    },
+    And(Box<SynapseFilter>, Box<SynapseFilter>),
+    Or(Box<SynapseFilter>, Box<SynapseFilter>),
+}
+
+impl SynapseFilter {
+    pub fn matches(&self, src: u64, dst: u64) -> bool {
+        match self {
+            SynapseFilter::Compare { lhs, op, rhs, .. } => {
+                op.holds(lhs.eval(src, dst) as f64, rhs.eval(src, dst) as f64)
+            }
+            SynapseFilter::And(a, b) => a.matches(src, dst) && b.matches(src, dst),
+            SynapseFilter::Or(a, b) => a.matches(src, dst) || b.matches(src, dst),
+        }
+    }
+}
+
+impl fmt::Display for SynapseFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SynapseFilter::Compare { lhs, op, rhs, .. } => {
+                write!(f, "{lhs} {} {rhs}", op.as_str())
+            }
+            SynapseFilter::And(a, b) => {
+                // `and` binds tighter than `or`, so only `or` needs grouping.
+                for (idx, side) in [a, b].into_iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, " and ")?;
+                    }
+                    match **side {
+                        SynapseFilter::Or(..) => write!(f, "({side})")?,
+                        _ => write!(f, "{side}")?,
+                    }
+                }
+                Ok(())
+            }
+            SynapseFilter::Or(a, b) => write!(f, "{a} or {b}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum SynapseOperand {
+    /// Index of the presynaptic neuron within its layer.
+    Src,
+    /// Index of the postsynaptic neuron within its layer.
+    Dst,
+    Index(u64),
+}
+
+impl SynapseOperand {
+    pub fn eval(self, src: u64, dst: u64) -> u64 {
+        match self {
+            SynapseOperand::Src => src,
+            SynapseOperand::Dst => dst,
+            SynapseOperand::Index(n) => n,
+        }
+    }
+}
+
+impl fmt::Display for SynapseOperand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SynapseOperand::Src => write!(f, "src"),
+            SynapseOperand::Dst => write!(f, "dst"),
+            SynapseOperand::Index(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+impl ConnectKind {
+    pub fn arrow(self) -> &'static str {
+        match self {
+            ConnectKind::Chemical => "->",
+            ConnectKind::Electrical => "<->",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct RunStmt {
     pub duration: Quantity,
     pub step: Option<Quantity>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SeedStmt {
     pub value: u64,
     pub span: Span,
 }
 
+/// `reward <value> at <time>`: a reward pulse for reward-modulated
+/// plasticity, delivered on the step containing `at`.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct RewardStmt {
+    pub value: Quantity,
+    pub at: Quantity,
+    pub span: Span,
+}
+
+/// `record rate from <layer> window <time>`: the layer's population rate,
+/// smoothed with an exponential window, recorded every step.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct RecordStmt {
+    pub layer: Ident,
+    pub window: Quantity,
+    pub span: Span,
+}
+
+/// `probe <name> = v(<layer>[<first>..<end>]) every <time>`: samples a
+/// variable of neurons `first` up to but not including `end`, or of the
+/// whole layer without the brackets, every `<time>`, or every step without
+/// `every`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ProbeDef {
+    pub name: Ident,
+    pub variable: ProbeVariable,
+    pub layer: Ident,
+    pub neurons: Option<(u64, u64)>,
+    pub every: Option<Quantity>,
+    pub span: Span,
+}
+
+impl fmt::Display for ProbeDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({}", self.variable, self.layer.name)?;
+        if let Some((first, end)) = self.neurons {
+            write!(f, "[{first}..{end}]")?;
+        }
+        write!(f, ")")?;
+        if let Some(every) = &self.every {
+            write!(f, " every {every}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ProbeVariable {
+    /// `v`, the membrane potential.
+    Membrane,
+}
+
+impl ProbeVariable {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "v" => Some(ProbeVariable::Membrane),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ProbeVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeVariable::Membrane => write!(f, "v"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct AssertStmt {
+    pub metric: Metric,
+    pub cond: AssertCond,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Metric {
+    Spikes { layer: Ident },
+    Rate { layer: Ident },
+}
+
+impl Metric {
+    pub fn layer(&self) -> &Ident {
+        match self {
+            Metric::Spikes { layer } | Metric::Rate { layer } => layer,
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Metric::Spikes { layer } => write!(f, "spikes({})", layer.name),
+            Metric::Rate { layer } => write!(f, "rate({})", layer.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum AssertCond {
+    Compare { op: CompareOp, value: Quantity },
+    Between { low: Quantity, high: Quantity },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum CompareOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl CompareOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "<" => Some(CompareOp::Lt),
+            "<=" => Some(CompareOp::Le),
+            ">" => Some(CompareOp::Gt),
+            ">=" => Some(CompareOp::Ge),
+            "==" => Some(CompareOp::Eq),
+            "!=" => Some(CompareOp::Ne),
+            _ => None,
+        }
+    }
+
+    pub fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct StimulusDef {
     pub layer: Ident,
     pub model: StimulusModel,
+    /// `combine = ...` after the model. Without it the stimulus adds to
+    /// the earlier ones on its layer, as with [`StimulusCombine::Sum`], and
+    /// [`crate::validate::warnings`] says so.
+    pub combine: Option<StimulusCombine>,
+    pub span: Span,
+}
+
+/// How a stimulus combines with the stimuli before it on the same layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum StimulusCombine {
+    /// Rates, spikes and currents add up.
+    Sum,
+    /// The earlier stimuli are dropped.
+    Replace,
+    /// Each neuron's rate is the larger of this stimulus's rate and the
+    /// rate of the stimuli before it.
+    Max,
+}
+
+impl StimulusCombine {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sum" => Some(Self::Sum),
+            "replace" => Some(Self::Replace),
+            "max" => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sum => "sum",
+            Self::Replace => "replace",
+            Self::Max => "max",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum StimulusModel {
-    Poisson { rate: Quantity },
+    /// `Poisson(rate=..., correlation=...)`: spikes at `rate` per neuron.
+    /// With a `correlation` above 0, every neuron copies the spikes of one
+    /// shared process with that probability, so any two neurons' spike
+    /// counts correlate by about that much.
+    Poisson {
+        rate: Quantity,
+        correlation: Option<Quantity>,
+    },
+    /// `Encode("<file>", scheme=..., present=...)`: the rows of a CSV file,
+    /// one value per neuron, presented one after another.
+    Encode(Encoder),
+    /// `External(port=..., input=..., timeout=..., on_timeout=...)`: values
+    /// received every step from a controller over TCP.
+    External(ExternalInput),
+}
+
+impl fmt::Display for StimulusModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StimulusModel::Poisson { rate, correlation } => {
+                write!(f, "Poisson(rate={rate}")?;
+                if let Some(c) = correlation {
+                    write!(f, ", correlation={c}")?;
+                }
+                write!(f, ")")
+            }
+            StimulusModel::Encode(enc) => {
+                write!(f, "Encode({}, ", quote(&enc.path))?;
+                match &enc.scheme {
+                    EncodeScheme::Rate { max_rate } => {
+                        write!(f, "scheme=rate, max_rate={max_rate}")?;
+                    }
+                    EncodeScheme::Latency => write!(f, "scheme=latency")?,
+                }
+                write!(f, ", present={})", enc.present)
+            }
+            StimulusModel::External(ext) => write!(
+                f,
+                "External(port={}, input={}, timeout={}, on_timeout={})",
+                ext.port,
+                ext.input.as_str(),
+                ext.timeout,
+                ext.on_timeout.as_str()
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ExternalInput {
+    /// Local TCP port the simulator listens on.
+    pub port: u16,
+    pub input: ExternalSignal,
+    /// Wall-clock time to wait for each step's values.
+    pub timeout: Quantity,
+    pub on_timeout: TimeoutPolicy,
+    /// The `External` name, for diagnostics.
+    pub span: Span,
+}
+
+/// What the received values are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ExternalSignal {
+    /// Poisson rates in Hz.
+    Rate,
+    /// Added to the membrane potential.
+    Current,
+}
+
+impl ExternalSignal {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExternalSignal::Rate => "rate",
+            ExternalSignal::Current => "current",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rate" => Some(ExternalSignal::Rate),
+            "current" => Some(ExternalSignal::Current),
+            _ => None,
+        }
+    }
+}
+
+/// What a step uses when the controller sends nothing in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum TimeoutPolicy {
+    /// The last values received.
+    Hold,
+    /// Zero for every neuron.
+    Zero,
+    /// Fail the run.
+    Error,
+}
+
+impl TimeoutPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TimeoutPolicy::Hold => "hold",
+            TimeoutPolicy::Zero => "zero",
+            TimeoutPolicy::Error => "error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hold" => Some(TimeoutPolicy::Hold),
+            "zero" => Some(TimeoutPolicy::Zero),
+            "error" => Some(TimeoutPolicy::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Encoder {
+    /// CSV file, relative paths resolved by the host.
+    pub path: String,
+    pub scheme: EncodeScheme,
+    /// How long each row is presented.
+    pub present: Quantity,
+    /// The `Encode` name, for diagnostics.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum EncodeScheme {
+    /// Each value sets a Poisson rate, scaled so the largest value in the
+    /// file fires at `max_rate`.
+    Rate { max_rate: Quantity },
+    /// Each value spikes once per presentation, larger values earlier.
+    Latency,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Assign {
     pub key: Ident,
     pub value: Expr,
+    /// From the key to the end of the value.
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Expr {
     Number(Quantity),
     String(String),
@@ -78,19 +880,53 @@ pub enum Expr {
     Call(Call),
 }
 
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(q) => write!(f, "{q}"),
+            Expr::String(s) => f.write_str(&quote(s)),
+            Expr::Ident(id) => write!(f, "{}", id.name),
+            Expr::Call(call) => write!(f, "{call}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Call {
     pub name: Ident,
     pub args: Vec<CallArg>,
+    /// From the name to the closing `)`; just the name without arguments.
+    pub span: Span,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name.name)?;
+        for (idx, arg) in self.args.iter().enumerate() {
+            if idx != 0 {
+                write!(f, ", ")?;
+            }
+            match arg {
+                CallArg::Positional(e) => write!(f, "{e}")?,
+                CallArg::Named { name, value } => write!(f, "{}={value}", name.name)?,
+            }
+        }
+        write!(f, ")")
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum CallArg {
     Positional(Expr),
     Named { name: Ident, value: Expr },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ident {
     pub name: String,
     pub span: Span,
@@ -105,9 +941,69 @@ impl Ident {
     }
 }
 
+/// Names the lexer reads back as one identifier: a letter or `_`, then up
+/// to seven letters, digits or `_`.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Ident {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        const START: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_";
+        const CONTINUE: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_0123456789";
+        let mut name = String::from(*u.choose(START)? as char);
+        for _ in 0..u.int_in_range(0..=7)? {
+            name.push(*u.choose(CONTINUE)? as char);
+        }
+        Ok(Self::new(name, u.arbitrary()?))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quantity {
     pub value: f64,
     pub unit: Option<Ident>,
     pub span: Span,
 }
+
+/// Finite values with at most three decimals, so they print exactly, in
+/// a unit the language knows or none.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Quantity {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        const UNITS: &[&str] = &["s", "ms", "us", "ns", "Hz", "kHz"];
+        let value = f64::from(u.arbitrary::<i32>()?) / 1000.0;
+        let unit = if u.arbitrary()? {
+            Some(Ident::new(*u.choose(UNITS)?, u.arbitrary()?))
+        } else {
+            None
+        };
+        Ok(Self {
+            value,
+            unit,
+            span: u.arbitrary()?,
+        })
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.unit {
+            Some(unit) => write!(f, "{} {}", self.value, unit.name),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn serde_round_trips_programs() {
+        let src = "neuron LIF { tau_m = 20 ms }\nlayer A[3] : LIF\nconnect A -> A { w = Normal(0.5, 0.1) }\nseed 2\nrun for 5 ms\nassert rate(A) between 1 Hz and 9 Hz\n";
+        let program = parse_program(src).expect("parse");
+        let json = serde_json::to_string(&program).expect("serialize");
+        let back: Program = serde_json::from_str(&json).expect("deserialize");
+        assert_eq!(back, program);
+    }
+}
@@ -0,0 +1,1438 @@
+//! CVIR as Rust types, and CVIR JSON read back into an AST.
+//!
+//! [`lower`] turns a [`Program`] into a [`CvirProgram`], the model that
+//! [`crate::emit::cvir_json`] writes out: `seed` items folded into the
+//! `run`, the default step filled in, and names, metrics and modes spelled
+//! as the JSON spells them. Crates that consume the IR can walk these types
+//! instead of parsing the JSON. With the `serde` feature they serialize to,
+//! and deserialize from, the same fields as the JSON.
+//!
+//! [`program_from_cvir`] is the inverse of [`crate::emit::cvir_json`].
+//! Nodes take their spans from CVIR emitted with `--with-spans`, and are
+//! otherwise empty since there is no source text to point into.
+
+use crate::ast::*;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::ids::{child_id, item_ids};
+use crate::json::{self, Value};
+
+/// The `cvir_version` this crate writes and reads.
+pub const CVIR_VERSION: &str = "0.2";
+
+/// Options for [`lower_with`] and [`crate::emit::cvir_json_with`].
+#[derive(Debug, Clone, Default)]
+pub struct CvirOptions {
+    /// Attach a source `span` to every item, assignment and expression.
+    pub spans: bool,
+    /// Source file recorded in each span, when known.
+    pub file: Option<String>,
+    /// Attach the stable [`crate::ids`] id to every item, assignment and
+    /// call argument.
+    pub ids: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirProgram {
+    pub cvir_version: String,
+    /// The values of the `params` block, overrides included. The items
+    /// already hold them.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub params: Vec<CvirAssign>,
+    pub items: Vec<CvirItem>,
+}
+
+/// An item, tagged by its `kind`. There is no `seed` item: a seed is a
+/// field of the `run`, or of a `network` without a `run` of its own.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum CvirItem {
+    Neuron(CvirNeuron),
+    Layer(CvirLayer),
+    Connect(CvirConnect),
+    Stimulus(CvirStimulus),
+    Run(CvirRun),
+    Assert(CvirAssert),
+    Disconnect(CvirDisconnect),
+    Modulator(CvirModulator),
+    Experiment(CvirExperiment),
+    Readout(CvirReadout),
+    Reward(CvirReward),
+    Record(CvirRecord),
+    Probe(CvirProbe),
+    Network(CvirNetwork),
+    Sweep(CvirSweep),
+}
+
+/// Byte offsets into the source, end exclusive, and the file when known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirSpan {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub file: Option<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirNeuron {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub description: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub attributes: Vec<CvirCall>,
+    pub body: Vec<CvirAssign>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirLayer {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub description: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub attributes: Vec<CvirCall>,
+    pub size: u64,
+    pub neuron: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirConnect {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub name: Option<String>,
+    pub src: String,
+    pub dst: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub description: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub attributes: Vec<CvirCall>,
+    /// A gap junction (`<->`) rather than a chemical synapse.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub electrical: bool,
+    pub body: Vec<CvirAssign>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirStimulus {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub layer: String,
+    pub model: CvirStimulusModel,
+    /// `sum`, `replace` or `max`, when written.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub combine: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+/// A stimulus model, tagged by its `type`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum CvirStimulusModel {
+    Poisson {
+        rate: CvirQuantity,
+        #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+        correlation: Option<CvirQuantity>,
+    },
+    Encode {
+        path: String,
+        /// `rate` or `latency`.
+        scheme: String,
+        /// The `rate` scheme's peak rate.
+        #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+        max_rate: Option<CvirQuantity>,
+        present: CvirQuantity,
+    },
+    External {
+        port: u16,
+        /// `rate` or `current`.
+        input: String,
+        timeout: CvirQuantity,
+        /// `hold`, `zero` or `error`.
+        on_timeout: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirRun {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub duration: CvirQuantity,
+    /// The step as written, or `1 ms`.
+    pub step: CvirQuantity,
+    /// The program's `seed`, the network's own when it has one, or 0.
+    pub seed: u64,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+/// `op` is `between`, with `low` and `high`, or a comparison such as `>=`
+/// with a `value`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirAssert {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub metric: CvirMetric,
+    pub op: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub value: Option<CvirQuantity>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub low: Option<CvirQuantity>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub high: Option<CvirQuantity>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+/// `spikes` or `rate` of a `layer`, or in a sweep's report also
+/// `total_spikes` or `mean_rate` of the whole network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirMetric {
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub kind: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub layer: Option<String>,
+}
+
+impl From<&Metric> for CvirMetric {
+    fn from(metric: &Metric) -> Self {
+        let kind = match metric {
+            Metric::Spikes { .. } => "spikes",
+            Metric::Rate { .. } => "rate",
+        };
+        Self {
+            kind: kind.to_string(),
+            layer: Some(metric.layer().name.clone()),
+        }
+    }
+}
+
+/// The target is either a named `connection` or the `src` and `dst`
+/// layers.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirDisconnect {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub connection: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub src: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub dst: Option<String>,
+    /// The `where` filter.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "where", skip_serializing_if = "Option::is_none")
+    )]
+    pub filter: Option<CvirFilter>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum CvirFilter {
+    /// `op` is a comparison such as `==`.
+    Compare {
+        op: String,
+        lhs: CvirOperand,
+        rhs: CvirOperand,
+    },
+    /// `op` is `and` or `or`.
+    Logic {
+        op: String,
+        args: Box<[CvirFilter; 2]>,
+    },
+}
+
+impl From<&SynapseFilter> for CvirFilter {
+    fn from(filter: &SynapseFilter) -> Self {
+        match filter {
+            SynapseFilter::Compare { lhs, op, rhs, .. } => Self::Compare {
+                op: op.as_str().to_string(),
+                lhs: (*lhs).into(),
+                rhs: (*rhs).into(),
+            },
+            SynapseFilter::And(a, b) | SynapseFilter::Or(a, b) => Self::Logic {
+                op: if matches!(filter, SynapseFilter::And(..)) {
+                    "and"
+                } else {
+                    "or"
+                }
+                .to_string(),
+                args: Box::new([a.as_ref().into(), b.as_ref().into()]),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CvirOperand {
+    Src,
+    Dst,
+    #[cfg_attr(feature = "serde", serde(untagged))]
+    Index(u64),
+}
+
+impl From<SynapseOperand> for CvirOperand {
+    fn from(operand: SynapseOperand) -> Self {
+        match operand {
+            SynapseOperand::Src => Self::Src,
+            SynapseOperand::Dst => Self::Dst,
+            SynapseOperand::Index(n) => Self::Index(n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirModulator {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub name: String,
+    pub body: Vec<CvirAssign>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirExperiment {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub body: Vec<CvirAssign>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirReadout {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub layer: String,
+    pub labels: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub rule: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirReward {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub value: CvirQuantity,
+    pub at: CvirQuantity,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirRecord {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub layer: String,
+    pub window: CvirQuantity,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+/// `neurons` and `every` are `null` in the JSON when left out.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirProbe {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub name: String,
+    pub variable: String,
+    pub layer: String,
+    /// `[first, end]`, end exclusive.
+    pub neurons: Option<[u64; 2]>,
+    pub every: Option<CvirQuantity>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirNetwork {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub name: String,
+    /// The network's own seed, when it has no `run` of its own to fold
+    /// into.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub seed: Option<u64>,
+    pub items: Vec<CvirItem>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirSweep {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub runs: Vec<CvirSweepRun>,
+    pub report: Vec<CvirMetric>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirSweepRun {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub network: Option<String>,
+    pub first_seed: u64,
+    pub last_seed: u64,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+/// `key = value` in a body or in `params`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirAssign {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub key: String,
+    pub value: CvirExpr,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+/// A value: a quantity object, a JSON string, `{"ident": ...}` or
+/// `{"call": ..., "args": [...]}`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum CvirExpr {
+    Call(CvirCall),
+    Ident(CvirIdent),
+    Quantity(CvirQuantity),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirCall {
+    pub call: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+    pub args: Vec<CvirArg>,
+}
+
+/// A positional argument is the bare value; a named one is
+/// `{"name": ..., "value": ...}`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum CvirArg {
+    Named(CvirNamedArg),
+    Positional(CvirExpr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirNamedArg {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub id: Option<String>,
+    pub name: String,
+    pub value: CvirExpr,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirIdent {
+    pub ident: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+/// A number with its unit as written; `unit` is absent for a plain number.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CvirQuantity {
+    pub value: f64,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub unit: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub span: Option<CvirSpan>,
+}
+
+/// The CVIR of `program`, without spans or ids.
+pub fn lower(program: &Program) -> CvirProgram {
+    lower_with(program, &CvirOptions::default())
+}
+
+pub fn lower_with(program: &Program, opts: &CvirOptions) -> CvirProgram {
+    let lower = Lower { opts };
+    CvirProgram {
+        cvir_version: CVIR_VERSION.to_string(),
+        params: lower.assigns(&program.params, "params"),
+        items: lower.items(&program.items, "", seed_of(&program.items).unwrap_or(0)),
+    }
+}
+
+/// `e` as CVIR, without spans or ids.
+pub(crate) fn lower_expr(e: &Expr) -> CvirExpr {
+    Lower {
+        opts: &CvirOptions::default(),
+    }
+    .expr(e, "")
+}
+
+fn seed_of(items: &[Item]) -> Option<u64> {
+    items.iter().find_map(|item| match item {
+        Item::Seed(s) => Some(s.value),
+        _ => None,
+    })
+}
+
+/// The span an item is reported at: its name, or the whole `src -> dst`.
+fn item_span(item: &Item) -> Span {
+    match item {
+        Item::Neuron(d) => d.name.span.clone(),
+        Item::Layer(d) => d.name.span.clone(),
+        Item::Connect(d) => Span::new(d.src.span.start, d.dst.span.end),
+        Item::Stimulus(d) => d.layer.span.clone(),
+        Item::Run(d) => d.duration.span.clone(),
+        Item::Seed(d) => d.span.clone(),
+        Item::Assert(d) => d.span.clone(),
+        Item::Disconnect(d) => d.span.clone(),
+        Item::Reward(d) => d.span.clone(),
+        Item::Record(d) => d.span.clone(),
+        Item::Probe(d) => d.span.clone(),
+        Item::Modulator(d) => d.name.span.clone(),
+        Item::Experiment(d) => d.span.clone(),
+        Item::Readout(d) => d.layer.span.clone(),
+        Item::Network(d) => d.name.span.clone(),
+        Item::Sweep(d) => d.span.clone(),
+    }
+}
+
+struct Lower<'a> {
+    opts: &'a CvirOptions,
+}
+
+impl Lower<'_> {
+    fn id(&self, id: &str) -> Option<String> {
+        self.opts.ids.then(|| id.to_string())
+    }
+
+    fn span(&self, span: &Span) -> Option<CvirSpan> {
+        self.opts.spans.then(|| CvirSpan {
+            file: self.opts.file.clone(),
+            start: span.start,
+            end: span.end,
+        })
+    }
+
+    /// `items`, with ids under `prefix` and `seed` folded into the `run`.
+    fn items(&self, items: &[Item], prefix: &str, seed: u64) -> Vec<CvirItem> {
+        items
+            .iter()
+            .zip(item_ids(items, prefix))
+            .filter_map(|(item, id)| self.item(item, &id, seed))
+            .collect()
+    }
+
+    fn item(&self, item: &Item, id: &str, seed: u64) -> Option<CvirItem> {
+        let span = self.span(&item_span(item));
+        let lowered = match item {
+            Item::Neuron(d) => CvirItem::Neuron(CvirNeuron {
+                id: self.id(id),
+                name: d.name.name.clone(),
+                description: d.doc.clone(),
+                attributes: self.attributes(&d.attributes, id),
+                body: self.assigns(&d.body, id),
+                span,
+            }),
+            Item::Layer(d) => CvirItem::Layer(CvirLayer {
+                id: self.id(id),
+                name: d.name.name.clone(),
+                description: d.doc.clone(),
+                attributes: self.attributes(&d.attributes, id),
+                size: d.size,
+                neuron: d.neuron.name.clone(),
+                span,
+            }),
+            Item::Connect(d) => CvirItem::Connect(CvirConnect {
+                id: self.id(id),
+                name: d.name.as_ref().map(|n| n.name.clone()),
+                src: d.src.name.clone(),
+                dst: d.dst.name.clone(),
+                description: d.doc.clone(),
+                attributes: self.attributes(&d.attributes, id),
+                electrical: d.kind == ConnectKind::Electrical,
+                body: self.assigns(&d.body, id),
+                span,
+            }),
+            Item::Stimulus(d) => CvirItem::Stimulus(CvirStimulus {
+                id: self.id(id),
+                layer: d.layer.name.clone(),
+                model: self.stimulus_model(&d.model),
+                combine: d.combine.map(|c| c.as_str().to_string()),
+                span,
+            }),
+            Item::Run(d) => CvirItem::Run(CvirRun {
+                id: self.id(id),
+                duration: self.quantity(&d.duration),
+                step: match &d.step {
+                    Some(step) => self.quantity(step),
+                    None => CvirQuantity {
+                        value: 1.0,
+                        unit: Some("ms".to_string()),
+                        span: None,
+                    },
+                },
+                seed,
+                span,
+            }),
+            Item::Assert(d) => {
+                let (op, value, low, high) = match &d.cond {
+                    AssertCond::Compare { op, value } => {
+                        (op.as_str(), Some(self.quantity(value)), None, None)
+                    }
+                    AssertCond::Between { low, high } => (
+                        "between",
+                        None,
+                        Some(self.quantity(low)),
+                        Some(self.quantity(high)),
+                    ),
+                };
+                CvirItem::Assert(CvirAssert {
+                    id: self.id(id),
+                    metric: (&d.metric).into(),
+                    op: op.to_string(),
+                    value,
+                    low,
+                    high,
+                    span,
+                })
+            }
+            Item::Disconnect(d) => {
+                let (connection, src, dst) = match &d.target {
+                    ConnectionRef::Named(name) => (Some(name.name.clone()), None, None),
+                    ConnectionRef::Layers { src, dst } => {
+                        (None, Some(src.name.clone()), Some(dst.name.clone()))
+                    }
+                };
+                CvirItem::Disconnect(CvirDisconnect {
+                    id: self.id(id),
+                    connection,
+                    src,
+                    dst,
+                    filter: d.filter.as_ref().map(CvirFilter::from),
+                    span,
+                })
+            }
+            Item::Modulator(d) => CvirItem::Modulator(CvirModulator {
+                id: self.id(id),
+                name: d.name.name.clone(),
+                body: self.assigns(&d.body, id),
+                span,
+            }),
+            Item::Experiment(d) => CvirItem::Experiment(CvirExperiment {
+                id: self.id(id),
+                body: self.assigns(&d.body, id),
+                span,
+            }),
+            Item::Readout(d) => CvirItem::Readout(CvirReadout {
+                id: self.id(id),
+                layer: d.layer.name.clone(),
+                labels: d.labels.clone(),
+                rule: d.rule.as_ref().map(|r| r.name.clone()),
+                span,
+            }),
+            Item::Reward(d) => CvirItem::Reward(CvirReward {
+                id: self.id(id),
+                value: self.quantity(&d.value),
+                at: self.quantity(&d.at),
+                span,
+            }),
+            Item::Record(d) => CvirItem::Record(CvirRecord {
+                id: self.id(id),
+                layer: d.layer.name.clone(),
+                window: self.quantity(&d.window),
+                span,
+            }),
+            Item::Probe(d) => CvirItem::Probe(CvirProbe {
+                id: self.id(id),
+                name: d.name.name.clone(),
+                variable: d.variable.to_string(),
+                layer: d.layer.name.clone(),
+                neurons: d.neurons.map(|(first, end)| [first, end]),
+                every: d.every.as_ref().map(|every| self.quantity(every)),
+                span,
+            }),
+            Item::Network(d) => {
+                // A network's own `seed` wins over a shared one. Without a
+                // `run` of its own to fold into, it is a field of the
+                // network.
+                let own_seed = seed_of(&d.items);
+                let has_run = d.items.iter().any(|item| matches!(item, Item::Run(_)));
+                CvirItem::Network(CvirNetwork {
+                    id: self.id(id),
+                    name: d.name.name.clone(),
+                    seed: own_seed.filter(|_| !has_run),
+                    items: self.items(&d.items, id, own_seed.unwrap_or(seed)),
+                    span,
+                })
+            }
+            Item::Sweep(d) => CvirItem::Sweep(CvirSweep {
+                id: self.id(id),
+                runs: d
+                    .runs
+                    .iter()
+                    .map(|run| CvirSweepRun {
+                        network: run.network.as_ref().map(|n| n.name.clone()),
+                        first_seed: run.first_seed,
+                        last_seed: run.last_seed,
+                        span: self.span(&run.span),
+                    })
+                    .collect(),
+                report: d
+                    .report
+                    .iter()
+                    .map(|metric| match metric {
+                        ReportMetric::Layer(m) => m.into(),
+                        other => CvirMetric {
+                            kind: other.to_string(),
+                            layer: None,
+                        },
+                    })
+                    .collect(),
+                span,
+            }),
+            Item::Seed(_) => return None,
+        };
+        Some(lowered)
+    }
+
+    fn stimulus_model(&self, model: &StimulusModel) -> CvirStimulusModel {
+        match model {
+            StimulusModel::Poisson { rate, correlation } => CvirStimulusModel::Poisson {
+                rate: self.quantity(rate),
+                correlation: correlation.as_ref().map(|c| self.quantity(c)),
+            },
+            StimulusModel::Encode(enc) => {
+                let (scheme, max_rate) = match &enc.scheme {
+                    EncodeScheme::Rate { max_rate } => ("rate", Some(self.quantity(max_rate))),
+                    EncodeScheme::Latency => ("latency", None),
+                };
+                CvirStimulusModel::Encode {
+                    path: enc.path.clone(),
+                    scheme: scheme.to_string(),
+                    max_rate,
+                    present: self.quantity(&enc.present),
+                }
+            }
+            StimulusModel::External(ext) => CvirStimulusModel::External {
+                port: ext.port,
+                input: ext.input.as_str().to_string(),
+                timeout: self.quantity(&ext.timeout),
+                on_timeout: ext.on_timeout.as_str().to_string(),
+            },
+        }
+    }
+
+    /// Attributes as calls, with ids `owner/@name`.
+    fn attributes(&self, attributes: &[Call], owner: &str) -> Vec<CvirCall> {
+        attributes
+            .iter()
+            .map(|attr| self.call(attr, &child_id(owner, &format!("@{}", attr.name.name))))
+            .collect()
+    }
+
+    fn assigns(&self, assigns: &[Assign], owner: &str) -> Vec<CvirAssign> {
+        assigns
+            .iter()
+            .map(|a| {
+                let id = child_id(owner, &a.key.name);
+                CvirAssign {
+                    id: self.id(&id),
+                    key: a.key.name.clone(),
+                    value: self.expr(&a.value, &id),
+                    span: self.span(&a.key.span),
+                }
+            })
+            .collect()
+    }
+
+    /// `id` is the id of `e` itself, which its call arguments extend.
+    fn expr(&self, e: &Expr, id: &str) -> CvirExpr {
+        match e {
+            Expr::Number(q) => CvirExpr::Quantity(self.quantity(q)),
+            Expr::String(s) => CvirExpr::String(s.clone()),
+            Expr::Ident(ident) => CvirExpr::Ident(CvirIdent {
+                ident: ident.name.clone(),
+                span: self.span(&ident.span),
+            }),
+            Expr::Call(c) => CvirExpr::Call(self.call(c, id)),
+        }
+    }
+
+    fn call(&self, c: &Call, id: &str) -> CvirCall {
+        let args = c
+            .args
+            .iter()
+            .enumerate()
+            .map(|(idx, arg)| match arg {
+                CallArg::Positional(e) => {
+                    CvirArg::Positional(self.expr(e, &child_id(id, &idx.to_string())))
+                }
+                CallArg::Named { name, value } => {
+                    let id = child_id(id, &name.name);
+                    CvirArg::Named(CvirNamedArg {
+                        id: self.id(&id),
+                        name: name.name.clone(),
+                        value: self.expr(value, &id),
+                        span: self.span(&name.span),
+                    })
+                }
+            })
+            .collect();
+        CvirCall {
+            call: c.name.name.clone(),
+            span: self.span(&c.name.span),
+            args,
+        }
+    }
+
+    fn quantity(&self, q: &Quantity) -> CvirQuantity {
+        CvirQuantity {
+            value: q.value,
+            unit: q.unit.as_ref().map(|u| u.name.clone()),
+            span: self.span(&q.span),
+        }
+    }
+}
+
+pub fn program_from_cvir(text: &str) -> Result<Program, Diagnostic> {
+    let root = json::parse(text)?;
+    match root.get("cvir_version").and_then(Value::as_str) {
+        Some(CVIR_VERSION) => {}
+        Some(other) => {
+            return Err(Diagnostic::new(format!(
+                "unsupported CVIR version `{other}`"
+            )));
+        }
+        None => return Err(Diagnostic::new("missing `cvir_version`")),
+    }
+    let params = match root.get("params") {
+        Some(_) => assigns_at(&root, "params")?,
+        None => Vec::new(),
+    };
+    Ok(Program {
+        params,
+        ..Program::new(items(&root)?)
+    })
+}
+
+/// `{"type": "spikes" | "rate", "layer": ...}`, as in `assert`.
+fn metric(value: &Value, at: &Span) -> Result<Metric, Diagnostic> {
+    let layer = Ident::new(str_field(value, "layer")?, at.clone());
+    match str_field(value, "type")? {
+        "spikes" => Ok(Metric::Spikes { layer }),
+        "rate" => Ok(Metric::Rate { layer }),
+        other => Err(Diagnostic::new(format!("unknown metric `{other}`"))),
+    }
+}
+
+/// The `items` array of the root or of a `network`.
+fn items(parent: &Value) -> Result<Vec<Item>, Diagnostic> {
+    let mut items = Vec::new();
+    for item in array(parent, "items")? {
+        let at = span_of(item);
+        let ident = |name: &str| Ident::new(name, at.clone());
+        match str_field(item, "kind")? {
+            "neuron" => items.push(Item::Neuron(NeuronDef {
+                name: ident(str_field(item, "name")?),
+                body: assigns(item)?,
+                doc: description(item),
+                attributes: attributes(item)?,
+                span: at.clone(),
+            })),
+            "modulator" => items.push(Item::Modulator(ModulatorDef {
+                name: ident(str_field(item, "name")?),
+                body: assigns(item)?,
+                span: at.clone(),
+            })),
+            "experiment" => items.push(Item::Experiment(ExperimentDef {
+                body: assigns(item)?,
+                span: at.clone(),
+            })),
+            "readout" => items.push(Item::Readout(ReadoutDef {
+                layer: ident(str_field(item, "layer")?),
+                labels: str_field(item, "labels")?.to_string(),
+                rule: item.get("rule").and_then(Value::as_str).map(ident),
+                span: at.clone(),
+            })),
+            "layer" => items.push(Item::Layer(LayerDef {
+                name: ident(str_field(item, "name")?),
+                size: num_field(item, "size")? as u64,
+                size_span: at.clone(),
+                neuron: ident(str_field(item, "neuron")?),
+                doc: description(item),
+                attributes: attributes(item)?,
+                span: at.clone(),
+            })),
+            "connect" => items.push(Item::Connect(ConnectDef {
+                name: item.get("name").and_then(Value::as_str).map(ident),
+                src: ident(str_field(item, "src")?),
+                dst: ident(str_field(item, "dst")?),
+                kind: if item.get("electrical") == Some(&Value::Bool(true)) {
+                    ConnectKind::Electrical
+                } else {
+                    ConnectKind::Chemical
+                },
+                body: assigns(item)?,
+                doc: description(item),
+                attributes: attributes(item)?,
+                span: at.clone(),
+            })),
+            "stimulus" => {
+                let model = field(item, "model")?;
+                let model = match str_field(model, "type")? {
+                    "poisson" => StimulusModel::Poisson {
+                        rate: quantity(field(model, "rate")?)?,
+                        correlation: model.get("correlation").map(quantity).transpose()?,
+                    },
+                    "encode" => StimulusModel::Encode(Encoder {
+                        path: str_field(model, "path")?.to_string(),
+                        scheme: match str_field(model, "scheme")? {
+                            "rate" => EncodeScheme::Rate {
+                                max_rate: quantity(field(model, "max_rate")?)?,
+                            },
+                            "latency" => EncodeScheme::Latency,
+                            other => {
+                                return Err(Diagnostic::new(format!(
+                                    "unknown encoding scheme `{other}`"
+                                )));
+                            }
+                        },
+                        present: quantity(field(model, "present")?)?,
+                        span: at.clone(),
+                    }),
+                    "external" => StimulusModel::External(ExternalInput {
+                        port: u16::try_from(num_field(model, "port")? as u64)
+                            .map_err(|_| Diagnostic::new("external port out of range"))?,
+                        input: ExternalSignal::from_name(str_field(model, "input")?)
+                            .ok_or_else(|| Diagnostic::new("unknown external input"))?,
+                        timeout: quantity(field(model, "timeout")?)?,
+                        on_timeout: TimeoutPolicy::from_name(str_field(model, "on_timeout")?)
+                            .ok_or_else(|| Diagnostic::new("unknown timeout policy"))?,
+                        span: at.clone(),
+                    }),
+                    other => {
+                        return Err(Diagnostic::new(format!("unknown stimulus model `{other}`")));
+                    }
+                };
+                items.push(Item::Stimulus(StimulusDef {
+                    layer: ident(str_field(item, "layer")?),
+                    model,
+                    combine: match item.get("combine").and_then(Value::as_str) {
+                        Some(name) => Some(
+                            StimulusCombine::from_name(name)
+                                .ok_or_else(|| Diagnostic::new("unknown combine mode"))?,
+                        ),
+                        None => None,
+                    },
+                    span: at.clone(),
+                }));
+            }
+            "run" => {
+                if let Some(seed) = item.get("seed").and_then(Value::as_f64) {
+                    items.push(Item::Seed(SeedStmt {
+                        value: seed as u64,
+                        span: at.clone(),
+                    }));
+                }
+                items.push(Item::Run(RunStmt {
+                    duration: quantity(field(item, "duration")?)?,
+                    step: item.get("step").map(quantity).transpose()?,
+                    span: at.clone(),
+                }));
+            }
+            "assert" => {
+                let metric = metric(field(item, "metric")?, &at)?;
+                let cond = match str_field(item, "op")? {
+                    "between" => AssertCond::Between {
+                        low: quantity(field(item, "low")?)?,
+                        high: quantity(field(item, "high")?)?,
+                    },
+                    op => AssertCond::Compare {
+                        op: CompareOp::from_symbol(op)
+                            .ok_or_else(|| Diagnostic::new(format!("unknown comparison `{op}`")))?,
+                        value: quantity(field(item, "value")?)?,
+                    },
+                };
+                items.push(Item::Assert(AssertStmt {
+                    metric,
+                    cond,
+                    span: at.clone(),
+                }));
+            }
+            "disconnect" => {
+                let target = match item.get("connection").and_then(Value::as_str) {
+                    Some(name) => ConnectionRef::Named(ident(name)),
+                    None => ConnectionRef::Layers {
+                        src: ident(str_field(item, "src")?),
+                        dst: ident(str_field(item, "dst")?),
+                    },
+                };
+                items.push(Item::Disconnect(DisconnectStmt {
+                    target,
+                    filter: item.get("where").map(|f| filter(f, &at)).transpose()?,
+                    span: at.clone(),
+                }));
+            }
+            "probe" => {
+                let variable = str_field(item, "variable")?;
+                let neurons = match field(item, "neurons")? {
+                    Value::Null => None,
+                    range => {
+                        let bound = |i: usize| {
+                            range
+                                .as_array()
+                                .and_then(|r| r.get(i))
+                                .and_then(Value::as_f64)
+                                .map(|n| n as u64)
+                                .ok_or_else(|| {
+                                    Diagnostic::new("`neurons` must be `[first, end]` or null")
+                                })
+                        };
+                        Some((bound(0)?, bound(1)?))
+                    }
+                };
+                items.push(Item::Probe(ProbeDef {
+                    name: ident(str_field(item, "name")?),
+                    variable: ProbeVariable::from_name(variable).ok_or_else(|| {
+                        Diagnostic::new(format!("unknown probe variable `{variable}`"))
+                    })?,
+                    layer: ident(str_field(item, "layer")?),
+                    neurons,
+                    every: match field(item, "every")? {
+                        Value::Null => None,
+                        every => Some(quantity(every)?),
+                    },
+                    span: at.clone(),
+                }));
+            }
+            "record" => items.push(Item::Record(RecordStmt {
+                layer: ident(str_field(item, "layer")?),
+                window: quantity(field(item, "window")?)?,
+                span: at.clone(),
+            })),
+            "reward" => items.push(Item::Reward(RewardStmt {
+                value: quantity(field(item, "value")?)?,
+                at: quantity(field(item, "at")?)?,
+                span: at.clone(),
+            })),
+            "sweep" => {
+                let mut runs = Vec::new();
+                for run in array(item, "runs")? {
+                    runs.push(SweepRun {
+                        network: run.get("network").and_then(Value::as_str).map(ident),
+                        first_seed: num_field(run, "first_seed")? as u64,
+                        last_seed: num_field(run, "last_seed")? as u64,
+                        span: span_of(run),
+                    });
+                }
+                let mut report = Vec::new();
+                for entry in array(item, "report")? {
+                    report.push(match str_field(entry, "type")? {
+                        "total_spikes" => ReportMetric::TotalSpikes,
+                        "mean_rate" => ReportMetric::MeanRate,
+                        _ => ReportMetric::Layer(metric(entry, &at)?),
+                    });
+                }
+                items.push(Item::Sweep(SweepDef {
+                    runs,
+                    report,
+                    span: at.clone(),
+                }));
+            }
+            "network" => {
+                let mut own = Vec::new();
+                if let Some(seed) = item.get("seed").and_then(Value::as_f64) {
+                    own.push(Item::Seed(SeedStmt {
+                        value: seed as u64,
+                        span: at.clone(),
+                    }));
+                }
+                own.extend(self::items(item)?);
+                items.push(Item::Network(NetworkDef {
+                    name: ident(str_field(item, "name")?),
+                    items: own,
+                    span: at.clone(),
+                }));
+            }
+            other => return Err(Diagnostic::new(format!("unknown item kind `{other}`"))),
+        }
+    }
+    Ok(items)
+}
+
+fn filter(value: &Value, at: &Span) -> Result<SynapseFilter, Diagnostic> {
+    let op = str_field(value, "op")?;
+    if op == "and" || op == "or" {
+        let args = array(value, "args")?;
+        let [a, b] = args else {
+            return Err(Diagnostic::new(format!("`{op}` takes two args")));
+        };
+        let (a, b) = (Box::new(filter(a, at)?), Box::new(filter(b, at)?));
+        return Ok(if op == "and" {
+            SynapseFilter::And(a, b)
+        } else {
+            SynapseFilter::Or(a, b)
+        });
+    }
+    let operand = |key: &str| match field(value, key)? {
+        Value::String(s) if s == "src" => Ok(SynapseOperand::Src),
+        Value::String(s) if s == "dst" => Ok(SynapseOperand::Dst),
+        v => v
+            .as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| SynapseOperand::Index(n as u64))
+            .ok_or_else(|| Diagnostic::new(format!("invalid synapse operand for `{key}`"))),
+    };
+    Ok(SynapseFilter::Compare {
+        lhs: operand("lhs")?,
+        op: CompareOp::from_symbol(op)
+            .ok_or_else(|| Diagnostic::new(format!("unknown comparison `{op}`")))?,
+        rhs: operand("rhs")?,
+        span: at.clone(),
+    })
+}
+
+fn assigns(item: &Value) -> Result<Vec<Assign>, Diagnostic> {
+    assigns_at(item, "body")
+}
+
+/// An array of `{ "key": ..., "value": ... }` under `key`.
+fn assigns_at(item: &Value, key: &str) -> Result<Vec<Assign>, Diagnostic> {
+    array(item, key)?
+        .iter()
+        .map(|a| {
+            Ok(Assign {
+                key: Ident::new(str_field(a, "key")?, span_of(a)),
+                value: expr(field(a, "value")?)?,
+                span: span_of(a),
+            })
+        })
+        .collect()
+}
+
+fn expr(value: &Value) -> Result<Expr, Diagnostic> {
+    if let Value::String(s) = value {
+        return Ok(Expr::String(s.clone()));
+    }
+    if let Some(name) = value.get("ident").and_then(Value::as_str) {
+        return Ok(Expr::Ident(Ident::new(name, span_of(value))));
+    }
+    if let Some(name) = value.get("call").and_then(Value::as_str) {
+        let args = array(value, "args")?
+            .iter()
+            .map(|arg| match arg.get("name").and_then(Value::as_str) {
+                Some(name) => Ok(CallArg::Named {
+                    name: Ident::new(name, span_of(arg)),
+                    value: expr(field(arg, "value")?)?,
+                }),
+                None => Ok(CallArg::Positional(expr(arg)?)),
+            })
+            .collect::<Result<_, Diagnostic>>()?;
+        return Ok(Expr::Call(Call {
+            name: Ident::new(name, span_of(value)),
+            args,
+            span: span_of(value),
+        }));
+    }
+    Ok(Expr::Number(quantity(value)?))
+}
+
+fn quantity(value: &Value) -> Result<Quantity, Diagnostic> {
+    Ok(Quantity {
+        value: num_field(value, "value")?,
+        unit: value
+            .get("unit")
+            .and_then(Value::as_str)
+            .map(|unit| Ident::new(unit, span_of(value))),
+        span: span_of(value),
+    })
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, Diagnostic> {
+    value
+        .get(key)
+        .ok_or_else(|| Diagnostic::new(format!("missing `{key}`")))
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> Result<&'a str, Diagnostic> {
+    field(value, key)?
+        .as_str()
+        .ok_or_else(|| Diagnostic::new(format!("`{key}` must be a string")))
+}
+
+/// The `attributes` of a neuron, layer or connection; none when missing.
+fn attributes(item: &Value) -> Result<Vec<Call>, Diagnostic> {
+    let Some(attributes) = item.get("attributes") else {
+        return Ok(Vec::new());
+    };
+    attributes
+        .as_array()
+        .ok_or_else(|| Diagnostic::new("`attributes` must be an array"))?
+        .iter()
+        .map(|attr| match expr(attr)? {
+            Expr::Call(call) => Ok(call),
+            _ => Err(Diagnostic::new("an attribute must be a call")),
+        })
+        .collect()
+}
+
+/// The optional `description` of a neuron, layer or connection.
+fn description(item: &Value) -> Option<String> {
+    item.get("description")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+fn num_field(value: &Value, key: &str) -> Result<f64, Diagnostic> {
+    field(value, key)?
+        .as_f64()
+        .ok_or_else(|| Diagnostic::new(format!("`{key}` must be a number")))
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> Result<&'a [Value], Diagnostic> {
+    field(value, key)?
+        .as_array()
+        .ok_or_else(|| Diagnostic::new(format!("`{key}` must be an array")))
+}
+
+/// The node's `span`, or an empty one when CVIR was emitted without spans.
+fn span_of(value: &Value) -> Span {
+    let offset = |key| {
+        value
+            .get("span")
+            .and_then(|s| s.get(key))
+            .and_then(Value::as_f64)
+            .map_or(0, |n| n as usize)
+    };
+    Span::new(offset("start"), offset("end"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::{cvir_json, cvir_json_with, cvir_program_json};
+    use crate::parser::parse_program;
+
+    #[test]
+    fn round_trips_through_cvir() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms, v_th = 1.0 }
+layer A[4] : LIF
+layer B[2] : LIF
+connect A -> B { w = Normal(0.5, 0.1), d = 1 ms }
+modulator DA { tau = 200 ms, source = B, gain = 0.1 }
+connect fb: B -> A { w = 0.2, plasticity = RSTDP(lr = 0.5, tau_e = 100 ms, modulator = DA) }
+reward 1.0 at 5 ms
+record rate from B window 2 ms
+probe pb = v(B[0..2]) every 2 ms
+probe pa = v(A)
+disconnect fb where src == dst or (src > 0 and dst != 3)
+disconnect A -> B
+stimulus A = Poisson(rate=50 Hz)
+stimulus B = Encode("digits.csv", scheme=rate, max_rate=200 Hz, present=5 ms)
+stimulus B = Encode("digits.csv", scheme=latency, present=5 ms)
+experiment { epochs = 2, isi = 1 ms, shuffle = true, readout = B }
+readout B labels "labels.csv" rule = max_spikes
+seed 9
+run for 10 ms step 0.5 ms
+assert rate(A) between 10 Hz and 90 Hz
+assert spikes(B) >= 1
+"#;
+        let program = parse_program(src).expect("parse");
+        let cvir = cvir_json(&program);
+        let back = program_from_cvir(&cvir).expect("read cvir");
+        assert_eq!(cvir_json(&back), cvir);
+        assert!(
+            back.items
+                .iter()
+                .any(|i| matches!(i, Item::Seed(s) if s.value == 9))
+        );
+    }
+
+    #[test]
+    fn round_trips_networks() {
+        let src = "neuron LIF { tau_m = 20 ms }\n\
+                   network A { layer X[2] : LIF\n seed 3 }\n\
+                   network B { layer X[4] : LIF }\n\
+                   run for 10 ms\n\
+                   experiment { run A with seed 1..4; report mean_rate, spikes(X) }\n";
+        let program = parse_program(src).expect("parse");
+        let cvir = cvir_json(&program);
+        assert!(cvir.contains("\"kind\": \"network\""));
+        assert!(cvir.contains("\"kind\": \"sweep\""));
+        let back = program_from_cvir(&cvir).expect("read cvir");
+        assert_eq!(cvir_json(&back), cvir);
+        let names: Vec<&str> = back.networks().map(|n| n.name.name.as_str()).collect();
+        assert_eq!(names, ["A", "B"]);
+        let seeds = |name| {
+            let model = back.select_network(Some(name)).expect("select");
+            model
+                .items
+                .iter()
+                .filter_map(|i| match i {
+                    Item::Seed(s) => Some(s.value),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(seeds("A"), [3], "A's seed replaces the shared one");
+        assert_eq!(seeds("B"), [0]);
+    }
+
+    #[test]
+    fn reads_spans_back() {
+        let src = "layer A[4] : LIF\nrun for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        let opts = CvirOptions {
+            spans: true,
+            file: Some("a.cv".to_string()),
+            ids: false,
+        };
+        let cvir = cvir_json_with(&program, &opts);
+        assert!(cvir.contains("\"span\": {\"file\": \"a.cv\", \"start\": 6, \"end\": 7}"));
+
+        let back = program_from_cvir(&cvir).expect("read cvir");
+        let Some(Item::Run(run)) = back.items.last() else {
+            panic!("expected run");
+        };
+        assert_eq!(
+            &src[run.duration.span.start..run.duration.span.end],
+            "10 ms"
+        );
+        assert_eq!(cvir_json(&back), cvir_json(&program));
+    }
+
+    #[test]
+    fn lowers_seeds_steps_and_ids() {
+        let src = "layer A[4] : LIF\n\
+                   network Small { layer B[2] : LIF\n seed 3 }\n\
+                   seed 7\n\
+                   run for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        let opts = CvirOptions {
+            ids: true,
+            ..CvirOptions::default()
+        };
+        let cvir = lower_with(&program, &opts);
+        assert_eq!(cvir.cvir_version, CVIR_VERSION);
+        let [
+            CvirItem::Layer(layer),
+            CvirItem::Network(network),
+            CvirItem::Run(run),
+        ] = cvir.items.as_slice()
+        else {
+            panic!("expected a layer, a network and a run: {:?}", cvir.items);
+        };
+        assert_eq!(layer.id.as_deref(), Some("layer:A"));
+        assert_eq!(layer.span, None);
+        assert_eq!(network.seed, Some(3));
+        assert_eq!(network.items.len(), 1, "the seed folds into the network");
+        assert_eq!(run.seed, 7);
+        assert_eq!(run.step.unit.as_deref(), Some("ms"));
+        assert_eq!(cvir_program_json(&cvir), cvir_json_with(&program, &opts));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_reads_and_writes_the_json_shape() {
+        let src = "/// Input.\n\
+                   @tag(\"in\")\n\
+                   layer A[4] : LIF\n\
+                   connect gap: A <-> A electrical { g = Normal(0.5, sd=0.1) }\n\
+                   disconnect gap where src == dst or (src > 0 and dst != 3)\n\
+                   stimulus A = Encode(\"digits.csv\", scheme=latency, present=5 ms)\n\
+                   probe p = v(A)\n\
+                   run for 10 ms\n\
+                   assert rate(A) between 10 Hz and 90 Hz\n";
+        let program = parse_program(src).expect("parse");
+        let opts = CvirOptions {
+            spans: true,
+            file: Some("a.cv".to_string()),
+            ids: true,
+        };
+        let cvir = lower_with(&program, &opts);
+        let text = cvir_json_with(&program, &opts);
+        let read: CvirProgram = serde_json::from_str(&text).expect("deserialize");
+        assert_eq!(read, cvir);
+        // The same fields, though serde puts an item's `kind` before its
+        // `id` and writes `5.0` where CVIR has `5`.
+        fn floats(value: serde_json::Value) -> serde_json::Value {
+            use serde_json::Value;
+            match value {
+                Value::Number(n) => n.as_f64().into(),
+                Value::Array(a) => a.into_iter().map(floats).collect(),
+                Value::Object(o) => o.into_iter().map(|(k, v)| (k, floats(v))).collect(),
+                other => other,
+            }
+        }
+        let written = serde_json::to_value(&cvir).expect("serialize");
+        let expected = serde_json::from_str(&text).expect("valid json");
+        assert_eq!(floats(written), floats(expected));
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let err = program_from_cvir(r#"{"cvir_version": "9.9", "items": []}"#).unwrap_err();
+        assert!(err.message.contains("unsupported CVIR version"));
+    }
+}
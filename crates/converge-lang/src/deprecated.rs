@@ -0,0 +1,146 @@
+//! Deprecated spellings, kept working so renaming a key is not a hard break.
+//!
+//! The parser replaces every deprecated key with its replacement as it reads
+//! a body, so nothing after it sees the old spelling, and records where each
+//! one was in [`Program::deprecated`]. [`crate::validate::warnings`] reports
+//! them with the replacement, and [`fix`] rewrites them in the source.
+
+use crate::ast::{Assign, Program};
+use crate::diagnostic::{Diagnostic, Span};
+
+/// The bodies a deprecated key may appear in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Body {
+    Neuron,
+    /// Chemical `connect` bodies.
+    Connection,
+}
+
+impl Body {
+    fn name(self) -> &'static str {
+        match self {
+            Body::Neuron => "neuron",
+            Body::Connection => "connection",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecation {
+    pub body: Body,
+    pub old: &'static str,
+    pub new: &'static str,
+}
+
+/// Every deprecated key, with the key that replaces it.
+pub const DEPRECATIONS: &[Deprecation] = &[
+    Deprecation {
+        body: Body::Connection,
+        old: "weight",
+        new: "w",
+    },
+    Deprecation {
+        body: Body::Connection,
+        old: "delay",
+        new: "d",
+    },
+];
+
+/// One deprecated key in a source file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct DeprecatedUse {
+    pub message: String,
+    /// The replacement for the text at `span`.
+    pub replacement: String,
+    pub span: Span,
+}
+
+impl DeprecatedUse {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(self.message.clone()).with_span(self.span.clone())
+    }
+}
+
+/// Renames the deprecated keys of a `body` block in place, returning where
+/// each one was.
+pub(crate) fn resolve_keys(assigns: &mut [Assign], body: Body) -> Vec<DeprecatedUse> {
+    let mut uses = Vec::new();
+    for assign in assigns {
+        let Some(dep) = DEPRECATIONS
+            .iter()
+            .find(|d| d.body == body && d.old == assign.key.name)
+        else {
+            continue;
+        };
+        uses.push(DeprecatedUse {
+            message: format!(
+                "the {} key `{}` is deprecated; use `{}`",
+                body.name(),
+                dep.old,
+                dep.new
+            ),
+            replacement: dep.new.to_string(),
+            span: assign.key.span.clone(),
+        });
+        assign.key.name = dep.new.to_string();
+    }
+    uses
+}
+
+/// `src` with every deprecated form of `program`, which must have been
+/// parsed from it, replaced.
+pub fn fix(src: &str, program: &Program) -> String {
+    let mut uses: Vec<&DeprecatedUse> = program.deprecated.iter().collect();
+    uses.sort_by_key(|u| u.span.start);
+    let mut out = String::with_capacity(src.len());
+    let mut at = 0;
+    for u in uses {
+        out.push_str(&src[at..u.span.start]);
+        out.push_str(&u.replacement);
+        at = u.span.end;
+    }
+    out.push_str(&src[at..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Item;
+    use crate::parser::parse_program;
+    use crate::validate::{validate, warnings};
+
+    #[test]
+    fn deprecated_keys_still_work_warn_and_fix() {
+        let src = "neuron LIF { tau_m = 10 ms }\n\
+                   layer A[2] : LIF\n\
+                   connect A -> A { weight = 0.5, delay = 2 ms }\n\
+                   stimulus A = Poisson(rate=10 Hz)\n\
+                   run for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("deprecated keys are valid");
+        let Some(Item::Connect(def)) = program.items.get(2) else {
+            panic!("expected a connection");
+        };
+        let keys: Vec<&str> = def.body.iter().map(|a| a.key.name.as_str()).collect();
+        assert_eq!(keys, ["w", "d"]);
+
+        let messages: Vec<String> = warnings(&program).into_iter().map(|d| d.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "the connection key `weight` is deprecated; use `w`",
+                "the connection key `delay` is deprecated; use `d`",
+            ]
+        );
+
+        let fixed = fix(src, &program);
+        assert!(
+            fixed.contains("connect A -> A { w = 0.5, d = 2 ms }"),
+            "{fixed}"
+        );
+        assert!(parse_program(&fixed).unwrap().deprecated.is_empty());
+    }
+}
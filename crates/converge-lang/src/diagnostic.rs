@@ -1,6 +1,8 @@
 use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -10,9 +12,37 @@ impl Span {
     pub fn new(start: usize, end: usize) -> Self {
         Self { start, end }
     }
+
+    /// The 1-based line and column where the span starts in `src`, the
+    /// column counted in characters, so `µs` or an accented comment earlier
+    /// on the line doesn't push it right.
+    pub fn line_col(&self, src: &str) -> (usize, usize) {
+        let (line, before) = self.line_prefix(src);
+        (line, before.chars().count() + 1)
+    }
+
+    /// [`Self::line_col`] with the column counted in UTF-16 code units, as
+    /// JavaScript strings and the Language Server Protocol count them (from
+    /// 0 there).
+    pub fn line_col_utf16(&self, src: &str) -> (usize, usize) {
+        let (line, before) = self.line_prefix(src);
+        (line, before.encode_utf16().count() + 1)
+    }
+
+    /// The span's line number and the text of that line before it.
+    fn line_prefix<'a>(&self, src: &'a str) -> (usize, &'a str) {
+        let mut start = self.start.min(src.len());
+        while !src.is_char_boundary(start) {
+            start -= 1;
+        }
+        let before = &src[..start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (before.matches('\n').count() + 1, &before[line_start..])
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Diagnostic {
     pub message: String,
     pub span: Option<Span>,
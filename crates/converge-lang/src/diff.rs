@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{AssertCond, Assign, Item, Program};
+
+/// One item-level difference between two programs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Changed {
+        item: String,
+        fields: Vec<FieldChange>,
+    },
+}
+
+/// A parameter that differs between two versions of the same item. `None`
+/// means the parameter is absent on that side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Compares two programs item by item. Items are matched by identity (layer
+/// and neuron names, connection endpoints, stimulus targets) rather than
+/// position, and values are compared as written with spans and formatting
+/// ignored, so reordering or reformatting a file produces no changes.
+pub fn diff_programs(old: &Program, new: &Program) -> Vec<Change> {
+    let old_items = describe_items(&old.items, "");
+    let new_items = describe_items(&new.items, "");
+    let old_index: HashMap<&str, &ItemFields> =
+        old_items.iter().map(|i| (i.key.as_str(), i)).collect();
+    let new_index: HashMap<&str, &ItemFields> =
+        new_items.iter().map(|i| (i.key.as_str(), i)).collect();
+
+    let mut changes = Vec::new();
+    for item in &old_items {
+        if !new_index.contains_key(item.key.as_str()) {
+            changes.push(Change::Removed(item.summary()));
+        }
+    }
+    for item in &new_items {
+        match old_index.get(item.key.as_str()) {
+            None => changes.push(Change::Added(item.summary())),
+            Some(before) => {
+                let fields = diff_fields(&before.fields, &item.fields);
+                if !fields.is_empty() {
+                    changes.push(Change::Changed {
+                        item: item.key.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    changes
+}
+
+struct ItemFields {
+    key: String,
+    fields: Vec<(String, String)>,
+}
+
+impl ItemFields {
+    fn summary(&self) -> String {
+        if self.fields.is_empty() {
+            return self.key.clone();
+        }
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(k, v)| format!("{k} = {v}"))
+            .collect();
+        format!("{} {{ {} }}", self.key, fields.join(", "))
+    }
+}
+
+/// Items inside `network A` are keyed `network A: <item>`, so they only
+/// match items of the same network.
+fn describe_items(program_items: &[Item], prefix: &str) -> Vec<ItemFields> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut items = Vec::new();
+    for item in program_items {
+        let (key, fields) = match item {
+            Item::Network(def) => {
+                let key = format!("{prefix}network {}", def.name.name);
+                items.extend(describe_items(&def.items, &format!("{key}: ")));
+                items.push(ItemFields {
+                    key,
+                    fields: Vec::new(),
+                });
+                continue;
+            }
+            Item::Neuron(def) => (
+                format!("neuron {}", def.name.name),
+                assign_fields(&def.body),
+            ),
+            Item::Modulator(def) => (
+                format!("modulator {}", def.name.name),
+                assign_fields(&def.body),
+            ),
+            Item::Experiment(def) => ("experiment".to_string(), assign_fields(&def.body)),
+            Item::Readout(def) => {
+                let mut fields = vec![("labels".to_string(), def.labels.clone())];
+                if let Some(rule) = &def.rule {
+                    fields.push(("rule".to_string(), rule.name.clone()));
+                }
+                (format!("readout {}", def.layer.name), fields)
+            }
+            Item::Layer(def) => (
+                format!("layer {}", def.name.name),
+                vec![
+                    ("size".to_string(), def.size.to_string()),
+                    ("neuron".to_string(), def.neuron.name.clone()),
+                ],
+            ),
+            Item::Connect(def) => (format!("connect {}", def.label()), assign_fields(&def.body)),
+            Item::Stimulus(def) => {
+                let mut fields = vec![("model".to_string(), def.model.to_string())];
+                if let Some(combine) = def.combine {
+                    fields.push(("combine".to_string(), combine.as_str().to_string()));
+                }
+                (format!("stimulus {}", def.layer.name), fields)
+            }
+            Item::Run(run) => {
+                let mut fields = vec![("duration".to_string(), run.duration.to_string())];
+                if let Some(step) = &run.step {
+                    fields.push(("step".to_string(), step.to_string()));
+                }
+                ("run".to_string(), fields)
+            }
+            Item::Seed(seed) => (
+                "seed".to_string(),
+                vec![("value".to_string(), seed.value.to_string())],
+            ),
+            Item::Assert(stmt) => {
+                let cond = match &stmt.cond {
+                    AssertCond::Compare { op, value } => format!("{} {value}", op.as_str()),
+                    AssertCond::Between { low, high } => format!("between {low} and {high}"),
+                };
+                (format!("assert {} {cond}", stmt.metric), Vec::new())
+            }
+            Item::Disconnect(stmt) => (
+                format!("disconnect {}", stmt.target),
+                stmt.filter
+                    .iter()
+                    .map(|f| ("where".to_string(), f.to_string()))
+                    .collect(),
+            ),
+            Item::Reward(stmt) => (
+                format!("reward at {}", stmt.at),
+                vec![("value".to_string(), stmt.value.to_string())],
+            ),
+            Item::Record(stmt) => (
+                format!("record rate from {}", stmt.layer.name),
+                vec![("window".to_string(), stmt.window.to_string())],
+            ),
+            Item::Probe(def) => (
+                format!("probe {}", def.name.name),
+                vec![("samples".to_string(), def.to_string())],
+            ),
+            Item::Sweep(def) => {
+                let mut fields: Vec<(String, String)> = def
+                    .runs
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, run)| (format!("run #{}", idx + 1), run.to_string()))
+                    .collect();
+                let report: Vec<String> = def.report.iter().map(|m| m.to_string()).collect();
+                fields.push(("report".to_string(), report.join(", ")));
+                ("experiment runs".to_string(), fields)
+            }
+        };
+        // Repeated keys (two connections between the same layers) are matched
+        // in source order.
+        let key = format!("{prefix}{key}");
+        let count = seen.entry(key.clone()).or_insert(0);
+        *count += 1;
+        let key = if *count > 1 {
+            format!("{key} #{count}")
+        } else {
+            key
+        };
+        items.push(ItemFields { key, fields });
+    }
+    items
+}
+
+fn assign_fields(body: &[Assign]) -> Vec<(String, String)> {
+    body.iter()
+        .map(|a| (a.key.name.clone(), a.value.to_string()))
+        .collect()
+}
+
+fn diff_fields(old: &[(String, String)], new: &[(String, String)]) -> Vec<FieldChange> {
+    let lookup = |fields: &[(String, String)], key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+    let mut changes = Vec::new();
+    for (key, value) in old {
+        match lookup(new, key) {
+            Some(v) if v == *value => {}
+            other => changes.push(FieldChange {
+                field: key.clone(),
+                old: Some(value.clone()),
+                new: other,
+            }),
+        }
+    }
+    for (key, value) in new {
+        if lookup(old, key).is_none() {
+            changes.push(FieldChange {
+                field: key.clone(),
+                old: None,
+                new: Some(value.clone()),
+            });
+        }
+    }
+    changes
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(item) => write!(f, "+ {item}"),
+            Change::Removed(item) => write!(f, "- {item}"),
+            Change::Changed { item, fields } => {
+                write!(f, "~ {item}")?;
+                for field in fields {
+                    write!(f, "\n    {field}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, "{}: {old} -> {new}", self.field),
+            (Some(old), None) => write!(f, "{}: {old} -> (removed)", self.field),
+            (None, Some(new)) => write!(f, "{}: (added) -> {new}", self.field),
+            (None, None) => write!(f, "{}", self.field),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn ignores_order_and_formatting() {
+        let a = parse_program(
+            "neuron LIF { tau_m = 20 ms }\nlayer A[4] : LIF\nlayer B[2] : LIF\nconnect A -> B { w = 0.5 }\nrun for 10 ms\n",
+        )
+        .expect("parse a");
+        let b = parse_program(
+            "layer B[2]:LIF\n\nlayer A[4]   : LIF\nneuron LIF {\n  tau_m = 20 ms\n}\nrun for 10 ms\nconnect A->B { w = 0.5 }\n",
+        )
+        .expect("parse b");
+        assert!(diff_programs(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_items() {
+        let a = parse_program(
+            "neuron LIF { tau_m = 20 ms }\nlayer A[4] : LIF\nlayer B[2] : LIF\nconnect A -> B { w = 0.5 }\nrun for 10 ms\n",
+        )
+        .expect("parse a");
+        let b = parse_program(
+            "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\nlayer A[8] : LIF\nlayer C[2] : LIF\nconnect A -> C { w = 0.5 }\nrun for 10 ms\n",
+        )
+        .expect("parse b");
+        let text: Vec<String> = diff_programs(&a, &b)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            text,
+            vec![
+                "- layer B { size = 2, neuron = LIF }",
+                "- connect A -> B { w = 0.5 }",
+                "~ neuron LIF\n    tau_m: 20 ms -> 10 ms\n    v_th: (added) -> 1",
+                "~ layer A\n    size: 4 -> 8",
+                "+ layer C { size = 2, neuron = LIF }",
+                "+ connect A -> C { w = 0.5 }",
+            ]
+        );
+    }
+}
@@ -1,117 +1,474 @@
 use crate::ast::*;
+pub use crate::cvir::CvirOptions;
+use crate::cvir::{
+    CvirArg, CvirAssign, CvirCall, CvirExpr, CvirFilter, CvirItem, CvirMetric, CvirOperand,
+    CvirProgram, CvirQuantity, CvirSpan, CvirStimulusModel, lower_expr, lower_with,
+};
+use crate::diagnostic::Span;
+use crate::ids::{child_id, item_ids};
+use crate::units::{rate_to_hz, time_to_nanos};
+use crate::visit::{self, Visit};
 
+/// The canonical IR of `program`. It depends on nothing but the program's
+/// items and `params`, not on spans or on how the source was laid out, so a
+/// program and its [`crate::print::print_program`] text give the same IR
+/// when it has no `params`.
 pub fn cvir_json(program: &Program) -> String {
+    cvir_json_with(program, &CvirOptions::default())
+}
+
+pub fn cvir_json_with(program: &Program, opts: &CvirOptions) -> String {
+    cvir_program_json(&lower_with(program, opts))
+}
+
+/// `cvir` as CVIR JSON, laid out the way [`cvir_json`] lays it out.
+pub fn cvir_program_json(cvir: &CvirProgram) -> String {
     let mut w = JsonWriter::new();
     w.obj_begin();
 
-    w.kv_str("cvir_version", "0.2");
+    w.kv_str("cvir_version", &cvir.cvir_version);
     w.comma_nl();
+    if !cvir.params.is_empty() {
+        w.key("params");
+        emit_assigns(&mut w, &cvir.params);
+        w.comma_nl();
+    }
     w.key("items");
+    emit_items(&mut w, &cvir.items);
+
+    w.nl();
+    w.obj_end();
+    w.nl();
+    w.finish()
+}
+
+/// `"description": ...,` for a documented item; nothing otherwise.
+fn emit_description(w: &mut JsonWriter, doc: Option<&str>) {
+    if let Some(doc) = doc {
+        w.kv_str("description", doc);
+        w.comma_nl();
+    }
+}
+
+/// `"attributes": [...],` as calls, for an item with attributes; nothing
+/// otherwise.
+fn emit_attributes(w: &mut JsonWriter, attributes: &[CvirCall]) {
+    if attributes.is_empty() {
+        return;
+    }
+    w.key("attributes");
+    w.array_begin();
+    for (idx, attr) in attributes.iter().enumerate() {
+        if idx != 0 {
+            w.comma();
+        }
+        w.nl();
+        emit_call(w, attr);
+    }
+    w.nl();
+    w.array_end();
+    w.comma_nl();
+}
+
+fn emit_items(w: &mut JsonWriter, items: &[CvirItem]) {
     w.array_begin();
-    let seed = program
-        .items
-        .iter()
-        .find_map(|item| match item {
-            Item::Seed(s) => Some(s.value),
-            _ => None,
-        })
-        .unwrap_or(0);
-
-    let mut first = true;
-    for item in program.items.iter() {
-        if matches!(item, Item::Seed(_)) {
-            continue;
-        }
-        if !first {
+    for (idx, item) in items.iter().enumerate() {
+        if idx != 0 {
             w.comma();
         }
-        first = false;
         w.nl();
-        emit_item(&mut w, item, seed);
+        emit_item(w, item);
     }
-    if !first {
+    if !items.is_empty() {
         w.nl();
     }
     w.array_end();
-
-    w.nl();
-    w.obj_end();
-    w.nl();
-    w.finish()
 }
 
-fn emit_item(w: &mut JsonWriter, item: &Item, seed: u64) {
+fn emit_item(w: &mut JsonWriter, item: &CvirItem) {
     w.obj_begin();
-    match item {
-        Item::Neuron(d) => {
+    let span = match item {
+        CvirItem::Neuron(d) => {
+            emit_id_field(w, d.id.as_deref());
             w.kv_str("kind", "neuron");
             w.comma_nl();
-            w.kv_str("name", &d.name.name);
+            w.kv_str("name", &d.name);
             w.comma_nl();
+            emit_description(w, d.description.as_deref());
+            emit_attributes(w, &d.attributes);
             w.key("body");
             emit_assigns(w, &d.body);
+            &d.span
         }
-        Item::Layer(d) => {
+        CvirItem::Layer(d) => {
+            emit_id_field(w, d.id.as_deref());
             w.kv_str("kind", "layer");
             w.comma_nl();
-            w.kv_str("name", &d.name.name);
+            w.kv_str("name", &d.name);
             w.comma_nl();
+            emit_description(w, d.description.as_deref());
+            emit_attributes(w, &d.attributes);
             w.kv_u64("size", d.size);
             w.comma_nl();
-            w.kv_str("neuron", &d.neuron.name);
+            w.kv_str("neuron", &d.neuron);
+            &d.span
         }
-        Item::Connect(d) => {
+        CvirItem::Connect(d) => {
+            emit_id_field(w, d.id.as_deref());
             w.kv_str("kind", "connect");
             w.comma_nl();
-            w.kv_str("src", &d.src.name);
+            if let Some(name) = &d.name {
+                w.kv_str("name", name);
+                w.comma_nl();
+            }
+            w.kv_str("src", &d.src);
             w.comma_nl();
-            w.kv_str("dst", &d.dst.name);
+            w.kv_str("dst", &d.dst);
             w.comma_nl();
+            emit_description(w, d.description.as_deref());
+            emit_attributes(w, &d.attributes);
+            if d.electrical {
+                w.key("electrical");
+                w.write("true");
+                w.comma_nl();
+            }
             w.key("body");
             emit_assigns(w, &d.body);
+            &d.span
         }
-        Item::Stimulus(d) => {
+        CvirItem::Stimulus(d) => {
+            emit_id_field(w, d.id.as_deref());
             w.kv_str("kind", "stimulus");
             w.comma_nl();
-            w.kv_str("layer", &d.layer.name);
+            w.kv_str("layer", &d.layer);
             w.comma_nl();
             w.key("model");
             emit_stimulus_model(w, &d.model);
+            if let Some(combine) = &d.combine {
+                w.comma_nl();
+                w.kv_str("combine", combine);
+            }
+            &d.span
         }
-        Item::Run(d) => {
+        CvirItem::Run(d) => {
+            emit_id_field(w, d.id.as_deref());
             w.kv_str("kind", "run");
             w.comma_nl();
             w.key("duration");
             emit_quantity(w, &d.duration);
             w.comma_nl();
             w.key("step");
-            if let Some(step) = &d.step {
-                emit_quantity(w, step);
-            } else {
-                emit_quantity_value(w, 1.0, Some("ms"));
+            emit_quantity(w, &d.step);
+            w.comma_nl();
+            w.kv_u64("seed", d.seed);
+            &d.span
+        }
+        CvirItem::Assert(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "assert");
+            w.comma_nl();
+            w.key("metric");
+            emit_metric(w, &d.metric);
+            w.comma_nl();
+            w.kv_str("op", &d.op);
+            for (key, value) in [("value", &d.value), ("low", &d.low), ("high", &d.high)] {
+                if let Some(value) = value {
+                    w.comma_nl();
+                    w.key(key);
+                    emit_quantity(w, value);
+                }
+            }
+            &d.span
+        }
+        CvirItem::Disconnect(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "disconnect");
+            let target = [
+                ("connection", &d.connection),
+                ("src", &d.src),
+                ("dst", &d.dst),
+            ];
+            for (key, name) in target {
+                if let Some(name) = name {
+                    w.comma_nl();
+                    w.kv_str(key, name);
+                }
+            }
+            if let Some(filter) = &d.filter {
+                w.comma_nl();
+                w.key("where");
+                emit_filter(w, filter);
+            }
+            &d.span
+        }
+        CvirItem::Modulator(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "modulator");
+            w.comma_nl();
+            w.kv_str("name", &d.name);
+            w.comma_nl();
+            w.key("body");
+            emit_assigns(w, &d.body);
+            &d.span
+        }
+        CvirItem::Experiment(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "experiment");
+            w.comma_nl();
+            w.key("body");
+            emit_assigns(w, &d.body);
+            &d.span
+        }
+        CvirItem::Readout(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "readout");
+            w.comma_nl();
+            w.kv_str("layer", &d.layer);
+            w.comma_nl();
+            w.kv_str("labels", &d.labels);
+            if let Some(rule) = &d.rule {
+                w.comma_nl();
+                w.kv_str("rule", rule);
+            }
+            &d.span
+        }
+        CvirItem::Reward(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "reward");
+            w.comma_nl();
+            w.key("value");
+            emit_quantity(w, &d.value);
+            w.comma_nl();
+            w.key("at");
+            emit_quantity(w, &d.at);
+            &d.span
+        }
+        CvirItem::Record(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "record");
+            w.comma_nl();
+            w.kv_str("layer", &d.layer);
+            w.comma_nl();
+            w.key("window");
+            emit_quantity(w, &d.window);
+            &d.span
+        }
+        CvirItem::Probe(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "probe");
+            w.comma_nl();
+            w.kv_str("name", &d.name);
+            w.comma_nl();
+            w.kv_str("variable", &d.variable);
+            w.comma_nl();
+            w.kv_str("layer", &d.layer);
+            w.comma_nl();
+            w.key("neurons");
+            match d.neurons {
+                Some([first, end]) => w.write(&format!("[{first}, {end}]")),
+                None => w.write("null"),
             }
             w.comma_nl();
-            w.kv_u64("seed", seed);
+            w.key("every");
+            match &d.every {
+                Some(every) => emit_quantity(w, every),
+                None => w.write("null"),
+            }
+            &d.span
+        }
+        CvirItem::Network(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "network");
+            w.comma_nl();
+            w.kv_str("name", &d.name);
+            w.comma_nl();
+            if let Some(seed) = d.seed {
+                w.kv_u64("seed", seed);
+                w.comma_nl();
+            }
+            w.key("items");
+            emit_items(w, &d.items);
+            &d.span
+        }
+        CvirItem::Sweep(d) => {
+            emit_id_field(w, d.id.as_deref());
+            w.kv_str("kind", "sweep");
+            w.comma_nl();
+            w.key("runs");
+            w.array_begin();
+            for (idx, run) in d.runs.iter().enumerate() {
+                if idx != 0 {
+                    w.comma();
+                }
+                w.nl();
+                w.obj_begin();
+                if let Some(network) = &run.network {
+                    w.kv_str("network", network);
+                    w.comma_nl();
+                }
+                w.kv_u64("first_seed", run.first_seed);
+                w.comma_nl();
+                w.kv_u64("last_seed", run.last_seed);
+                emit_span_field(w, &run.span);
+                w.obj_end();
+            }
+            if !d.runs.is_empty() {
+                w.nl();
+            }
+            w.array_end();
+            w.comma_nl();
+            w.key("report");
+            w.array_begin();
+            for (idx, metric) in d.report.iter().enumerate() {
+                if idx != 0 {
+                    w.comma();
+                }
+                w.nl();
+                emit_metric(w, metric);
+            }
+            if !d.report.is_empty() {
+                w.nl();
+            }
+            w.array_end();
+            &d.span
+        }
+    };
+    emit_span_field(w, span);
+    w.obj_end();
+}
+
+/// `"id": ...,` first in a node's object, when it has one.
+fn emit_id_field(w: &mut JsonWriter, id: Option<&str>) {
+    if let Some(id) = id {
+        w.kv_str("id", id);
+        w.comma_nl();
+    }
+}
+
+fn emit_span_field(w: &mut JsonWriter, span: &Option<CvirSpan>) {
+    let Some(span) = span else {
+        return;
+    };
+    w.comma_nl();
+    w.key("span");
+    match &span.file {
+        Some(file) => {
+            w.write("{\"file\": ");
+            w.str(file);
+            w.write(&format!(
+                ", \"start\": {}, \"end\": {}}}",
+                span.start, span.end
+            ));
+        }
+        None => emit_span(w, &Span::new(span.start, span.end)),
+    }
+}
+
+/// Comparisons are `{"op", "lhs", "rhs"}` with `"src"`, `"dst"` or an index
+/// as operands; `and`/`or` nodes carry their two sides as `args`.
+fn emit_filter(w: &mut JsonWriter, filter: &CvirFilter) {
+    w.obj_begin();
+    match filter {
+        CvirFilter::Compare { op, lhs, rhs } => {
+            w.kv_str("op", op);
+            w.comma_nl();
+            w.key("lhs");
+            emit_operand(w, *lhs);
+            w.comma_nl();
+            w.key("rhs");
+            emit_operand(w, *rhs);
+        }
+        CvirFilter::Logic { op, args } => {
+            let [a, b] = args.as_ref();
+            w.kv_str("op", op);
+            w.comma_nl();
+            w.key("args");
+            w.array_begin();
+            w.nl();
+            emit_filter(w, a);
+            w.comma_nl();
+            emit_filter(w, b);
+            w.nl();
+            w.array_end();
         }
-        Item::Seed(_) => {}
     }
     w.obj_end();
 }
 
-fn emit_stimulus_model(w: &mut JsonWriter, model: &StimulusModel) {
+fn emit_operand(w: &mut JsonWriter, operand: CvirOperand) {
+    match operand {
+        CvirOperand::Src => w.str("src"),
+        CvirOperand::Dst => w.str("dst"),
+        CvirOperand::Index(n) => w.write(&n.to_string()),
+    }
+}
+
+fn emit_metric(w: &mut JsonWriter, metric: &CvirMetric) {
+    w.obj_begin();
+    w.kv_str("type", &metric.kind);
+    if let Some(layer) = &metric.layer {
+        w.comma_nl();
+        w.kv_str("layer", layer);
+    }
+    w.obj_end();
+}
+
+fn emit_stimulus_model(w: &mut JsonWriter, model: &CvirStimulusModel) {
     w.obj_begin();
     match model {
-        StimulusModel::Poisson { rate } => {
+        CvirStimulusModel::Poisson { rate, correlation } => {
             w.kv_str("type", "poisson");
             w.comma_nl();
             w.key("rate");
             emit_quantity(w, rate);
+            if let Some(c) = correlation {
+                w.comma_nl();
+                w.key("correlation");
+                emit_quantity(w, c);
+            }
+        }
+        CvirStimulusModel::Encode {
+            path,
+            scheme,
+            max_rate,
+            present,
+        } => {
+            w.kv_str("type", "encode");
+            w.comma_nl();
+            w.kv_str("path", path);
+            w.comma_nl();
+            w.kv_str("scheme", scheme);
+            if let Some(max_rate) = max_rate {
+                w.comma_nl();
+                w.key("max_rate");
+                emit_quantity(w, max_rate);
+            }
+            w.comma_nl();
+            w.key("present");
+            emit_quantity(w, present);
+        }
+        CvirStimulusModel::External {
+            port,
+            input,
+            timeout,
+            on_timeout,
+        } => {
+            w.kv_str("type", "external");
+            w.comma_nl();
+            w.kv_u64("port", u64::from(*port));
+            w.comma_nl();
+            w.kv_str("input", input);
+            w.comma_nl();
+            w.key("timeout");
+            emit_quantity(w, timeout);
+            w.comma_nl();
+            w.kv_str("on_timeout", on_timeout);
         }
     }
     w.obj_end();
 }
 
-fn emit_assigns(w: &mut JsonWriter, assigns: &[Assign]) {
+fn emit_assigns(w: &mut JsonWriter, assigns: &[CvirAssign]) {
     w.array_begin();
     for (idx, a) in assigns.iter().enumerate() {
         if idx != 0 {
@@ -119,10 +476,12 @@ fn emit_assigns(w: &mut JsonWriter, assigns: &[Assign]) {
         }
         w.nl();
         w.obj_begin();
-        w.kv_str("key", &a.key.name);
+        emit_id_field(w, a.id.as_deref());
+        w.kv_str("key", &a.key);
         w.comma_nl();
         w.key("value");
-        emit_expr(w, &a.value);
+        emit_cvir_expr(w, &a.value);
+        emit_span_field(w, &a.span);
         w.obj_end();
     }
     if !assigns.is_empty() {
@@ -131,69 +490,742 @@ fn emit_assigns(w: &mut JsonWriter, assigns: &[Assign]) {
     w.array_end();
 }
 
-fn emit_expr(w: &mut JsonWriter, e: &Expr) {
+pub(crate) fn emit_expr(w: &mut JsonWriter, e: &Expr) {
+    emit_cvir_expr(w, &lower_expr(e));
+}
+
+fn emit_cvir_expr(w: &mut JsonWriter, e: &CvirExpr) {
     match e {
-        Expr::Number(q) => emit_quantity(w, q),
-        Expr::String(s) => w.str(s),
-        Expr::Ident(id) => {
+        CvirExpr::Quantity(q) => emit_quantity(w, q),
+        CvirExpr::String(s) => w.str(s),
+        CvirExpr::Ident(ident) => {
             w.obj_begin();
-            w.kv_str("ident", &id.name);
+            w.kv_str("ident", &ident.ident);
+            emit_span_field(w, &ident.span);
             w.obj_end();
         }
-        Expr::Call(c) => {
-            w.obj_begin();
-            w.kv_str("call", &c.name.name);
-            w.comma_nl();
-            w.key("args");
-            w.array_begin();
-            for (idx, arg) in c.args.iter().enumerate() {
+        CvirExpr::Call(c) => emit_call(w, c),
+    }
+}
+
+fn emit_call(w: &mut JsonWriter, c: &CvirCall) {
+    w.obj_begin();
+    w.kv_str("call", &c.call);
+    emit_span_field(w, &c.span);
+    w.comma_nl();
+    w.key("args");
+    w.array_begin();
+    for (idx, arg) in c.args.iter().enumerate() {
+        if idx != 0 {
+            w.comma();
+        }
+        w.nl();
+        match arg {
+            CvirArg::Positional(e) => emit_cvir_expr(w, e),
+            CvirArg::Named(arg) => {
+                w.obj_begin();
+                emit_id_field(w, arg.id.as_deref());
+                w.kv_str("name", &arg.name);
+                w.comma_nl();
+                w.key("value");
+                emit_cvir_expr(w, &arg.value);
+                emit_span_field(w, &arg.span);
+                w.obj_end();
+            }
+        }
+    }
+    if !c.args.is_empty() {
+        w.nl();
+    }
+    w.array_end();
+    w.obj_end();
+}
+
+fn emit_quantity(w: &mut JsonWriter, q: &CvirQuantity) {
+    w.obj_begin();
+    w.kv_f64("value", q.value);
+    if let Some(u) = &q.unit {
+        w.comma_nl();
+        w.kv_str("unit", u);
+    }
+    emit_span_field(w, &q.span);
+    w.obj_end();
+}
+
+/// The parse tree as JSON for external tools. Unlike CVIR this keeps every
+/// node, including `seed`, with its kind, byte-offset spans, and quantities
+/// resolved to nanoseconds or hertz where the unit is known. Items,
+/// assignments and call arguments carry their [`crate::ids`] id.
+pub fn ast_json(program: &Program) -> String {
+    let mut writer = AstWriter {
+        w: JsonWriter::new(),
+        id: String::new(),
+    };
+    writer.visit_program(program);
+    writer.w.finish()
+}
+
+/// Writes each node as it is visited; nodes that hold several children
+/// write the separators between them.
+struct AstWriter {
+    w: JsonWriter,
+    /// The id of the node being written, which its children extend.
+    id: String,
+}
+
+impl AstWriter {
+    /// Writes `"id": ...,` for the node `id`, and makes it the current node
+    /// until the returned parent id is given back to [`Self::leave`].
+    fn enter(&mut self, id: String) -> String {
+        self.w.kv_str("id", &id);
+        self.w.comma_nl();
+        std::mem::replace(&mut self.id, id)
+    }
+
+    fn leave(&mut self, parent: String) {
+        self.id = parent;
+    }
+
+    fn kind(&mut self, kind: &str) {
+        self.w.kv_str("kind", kind);
+        self.w.comma_nl();
+    }
+
+    fn kv_ident(&mut self, k: &str, id: &Ident) {
+        self.w.key(k);
+        self.visit_ident(id);
+    }
+
+    fn kv_quantity(&mut self, k: &str, q: &Quantity) {
+        self.w.key(k);
+        self.visit_quantity(q);
+    }
+
+    fn filter(&mut self, filter: &SynapseFilter) {
+        self.w.obj_begin();
+        match filter {
+            SynapseFilter::Compare { lhs, op, rhs, span } => {
+                self.kind("compare");
+                self.w.kv_str("op", op.as_str());
+                self.w.comma_nl();
+                self.w.key("lhs");
+                emit_operand(&mut self.w, (*lhs).into());
+                self.w.comma_nl();
+                self.w.key("rhs");
+                emit_operand(&mut self.w, (*rhs).into());
+                self.w.comma_nl();
+                self.w.key("span");
+                emit_span(&mut self.w, span);
+            }
+            SynapseFilter::And(a, b) | SynapseFilter::Or(a, b) => {
+                self.kind(if matches!(filter, SynapseFilter::And(..)) {
+                    "and"
+                } else {
+                    "or"
+                });
+                self.w.key("lhs");
+                self.filter(a);
+                self.w.comma_nl();
+                self.w.key("rhs");
+                self.filter(b);
+            }
+        }
+        self.w.obj_end();
+    }
+
+    fn items(&mut self, items: &[Item]) {
+        self.w.key("items");
+        self.w.array_begin();
+        let ids = item_ids(items, &self.id);
+        for (idx, (item, id)) in items.iter().zip(ids).enumerate() {
+            if idx != 0 {
+                self.w.comma();
+            }
+            self.w.nl();
+            self.w.obj_begin();
+            let parent = self.enter(id);
+            self.visit_item(item);
+            self.leave(parent);
+            self.w.obj_end();
+        }
+        if !items.is_empty() {
+            self.w.nl();
+        }
+        self.w.array_end();
+    }
+
+    fn assigns(&mut self, assigns: &[Assign]) {
+        self.w.key("body");
+        self.w.array_begin();
+        for (idx, a) in assigns.iter().enumerate() {
+            if idx != 0 {
+                self.w.comma();
+            }
+            self.w.nl();
+            self.visit_assign(a);
+        }
+        if !assigns.is_empty() {
+            self.w.nl();
+        }
+        self.w.array_end();
+    }
+
+    /// `"attributes": [...],` when there are any.
+    fn attributes(&mut self, attributes: &[Call]) {
+        if attributes.is_empty() {
+            return;
+        }
+        self.w.key("attributes");
+        self.w.array_begin();
+        for (idx, attr) in attributes.iter().enumerate() {
+            if idx != 0 {
+                self.w.comma();
+            }
+            self.w.nl();
+            let parent = self.id.clone();
+            self.id = child_id(&parent, &format!("@{}", attr.name.name));
+            self.visit_call(attr);
+            self.leave(parent);
+        }
+        self.w.nl();
+        self.w.array_end();
+        self.w.comma_nl();
+    }
+}
+
+impl Visit for AstWriter {
+    fn visit_program(&mut self, program: &Program) {
+        self.w.obj_begin();
+        self.w.kv_str("ast_version", "0.1");
+        self.w.comma_nl();
+        if let Some(pragma) = &program.version {
+            self.w
+                .kv_str("language_version", &pragma.version.to_string());
+            self.w.comma_nl();
+        }
+        if !program.params.is_empty() {
+            self.w.key("params");
+            self.w.array_begin();
+            self.id = "params".to_string();
+            for (idx, a) in program.params.iter().enumerate() {
                 if idx != 0 {
-                    w.comma();
+                    self.w.comma();
                 }
-                w.nl();
-                match arg {
-                    CallArg::Positional(e) => emit_expr(w, e),
-                    CallArg::Named { name, value } => {
-                        w.obj_begin();
-                        w.kv_str("name", &name.name);
-                        w.comma_nl();
-                        w.key("value");
-                        emit_expr(w, value);
-                        w.obj_end();
+                self.w.nl();
+                self.visit_assign(a);
+            }
+            self.id.clear();
+            self.w.nl();
+            self.w.array_end();
+            self.w.comma_nl();
+        }
+        self.items(&program.items);
+        self.w.nl();
+        self.w.obj_end();
+        self.w.nl();
+    }
+
+    fn visit_network(&mut self, d: &NetworkDef) {
+        self.kind("network");
+        self.kv_ident("name", &d.name);
+        self.w.comma_nl();
+        self.items(&d.items);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_sweep(&mut self, d: &SweepDef) {
+        self.kind("sweep");
+        self.w.key("runs");
+        self.w.array_begin();
+        for (idx, run) in d.runs.iter().enumerate() {
+            if idx != 0 {
+                self.w.comma();
+            }
+            self.w.nl();
+            self.w.obj_begin();
+            match &run.network {
+                Some(network) => self.kv_ident("network", network),
+                None => {
+                    self.w.key("network");
+                    self.w.write("null");
+                }
+            }
+            self.w.comma_nl();
+            self.w.kv_u64("first_seed", run.first_seed);
+            self.w.comma_nl();
+            self.w.kv_u64("last_seed", run.last_seed);
+            self.w.comma_nl();
+            self.w.key("span");
+            emit_span(&mut self.w, &run.span);
+            self.w.obj_end();
+        }
+        if !d.runs.is_empty() {
+            self.w.nl();
+        }
+        self.w.array_end();
+        self.w.comma_nl();
+        self.w.key("report");
+        self.w.array_begin();
+        for (idx, metric) in d.report.iter().enumerate() {
+            if idx != 0 {
+                self.w.comma();
+            }
+            self.w.nl();
+            match metric {
+                ReportMetric::Layer(metric) => self.visit_metric(metric),
+                other => {
+                    self.w.obj_begin();
+                    self.kind(&other.to_string());
+                    self.w.key("layer");
+                    self.w.write("null");
+                    self.w.obj_end();
+                }
+            }
+        }
+        if !d.report.is_empty() {
+            self.w.nl();
+        }
+        self.w.array_end();
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_neuron(&mut self, d: &NeuronDef) {
+        self.kind("neuron");
+        self.kv_ident("name", &d.name);
+        self.w.comma_nl();
+        emit_description(&mut self.w, d.doc.as_deref());
+        self.attributes(&d.attributes);
+        self.assigns(&d.body);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_modulator(&mut self, d: &ModulatorDef) {
+        self.kind("modulator");
+        self.kv_ident("name", &d.name);
+        self.w.comma_nl();
+        self.assigns(&d.body);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_experiment(&mut self, d: &ExperimentDef) {
+        self.kind("experiment");
+        self.assigns(&d.body);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_readout(&mut self, d: &ReadoutDef) {
+        self.kind("readout");
+        self.kv_ident("layer", &d.layer);
+        self.w.comma_nl();
+        self.w.kv_str("labels", &d.labels);
+        self.w.comma_nl();
+        match &d.rule {
+            Some(rule) => self.kv_ident("rule", rule),
+            None => {
+                self.w.key("rule");
+                self.w.write("null");
+            }
+        }
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_layer(&mut self, d: &LayerDef) {
+        self.kind("layer");
+        self.kv_ident("name", &d.name);
+        self.w.comma_nl();
+        emit_description(&mut self.w, d.doc.as_deref());
+        self.attributes(&d.attributes);
+        self.w.kv_u64("size", d.size);
+        self.w.comma_nl();
+        self.w.key("size_span");
+        emit_span(&mut self.w, &d.size_span);
+        self.w.comma_nl();
+        self.kv_ident("neuron", &d.neuron);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_connect(&mut self, d: &ConnectDef) {
+        self.kind("connect");
+        match &d.name {
+            Some(name) => self.kv_ident("name", name),
+            None => {
+                self.w.key("name");
+                self.w.write("null");
+            }
+        }
+        self.w.comma_nl();
+        self.kv_ident("src", &d.src);
+        self.w.comma_nl();
+        self.kv_ident("dst", &d.dst);
+        self.w.comma_nl();
+        emit_description(&mut self.w, d.doc.as_deref());
+        self.attributes(&d.attributes);
+        self.w.key("electrical");
+        self.w
+            .write(&(d.kind == ConnectKind::Electrical).to_string());
+        self.w.comma_nl();
+        self.assigns(&d.body);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_stimulus(&mut self, d: &StimulusDef) {
+        self.kind("stimulus");
+        self.kv_ident("layer", &d.layer);
+        self.w.comma_nl();
+        self.w.key("model");
+        self.visit_stimulus_model(&d.model);
+        if let Some(combine) = d.combine {
+            self.w.comma_nl();
+            self.w.kv_str("combine", combine.as_str());
+        }
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_stimulus_model(&mut self, model: &StimulusModel) {
+        self.w.obj_begin();
+        match model {
+            StimulusModel::Poisson { rate, correlation } => {
+                self.kind("poisson");
+                self.kv_quantity("rate", rate);
+                if let Some(c) = correlation {
+                    self.w.comma_nl();
+                    self.kv_quantity("correlation", c);
+                }
+            }
+            StimulusModel::Encode(enc) => {
+                self.kind("encode");
+                self.w.kv_str("path", &enc.path);
+                self.w.comma_nl();
+                match &enc.scheme {
+                    EncodeScheme::Rate { max_rate } => {
+                        self.w.kv_str("scheme", "rate");
+                        self.w.comma_nl();
+                        self.kv_quantity("max_rate", max_rate);
                     }
+                    EncodeScheme::Latency => self.w.kv_str("scheme", "latency"),
                 }
+                self.w.comma_nl();
+                self.kv_quantity("present", &enc.present);
+                self.w.comma_nl();
+                self.w.key("span");
+                emit_span(&mut self.w, &enc.span);
             }
-            if !c.args.is_empty() {
-                w.nl();
+            StimulusModel::External(ext) => {
+                self.kind("external");
+                self.w.kv_u64("port", u64::from(ext.port));
+                self.w.comma_nl();
+                self.w.kv_str("input", ext.input.as_str());
+                self.w.comma_nl();
+                self.kv_quantity("timeout", &ext.timeout);
+                self.w.comma_nl();
+                self.w.kv_str("on_timeout", ext.on_timeout.as_str());
+                self.w.comma_nl();
+                self.w.key("span");
+                emit_span(&mut self.w, &ext.span);
             }
-            w.array_end();
-            w.obj_end();
         }
+        self.w.obj_end();
     }
-}
 
-fn emit_quantity(w: &mut JsonWriter, q: &Quantity) {
-    emit_quantity_value(w, q.value, q.unit.as_ref().map(|u| u.name.as_str()));
-}
+    fn visit_run(&mut self, d: &RunStmt) {
+        self.kind("run");
+        self.kv_quantity("duration", &d.duration);
+        self.w.comma_nl();
+        match &d.step {
+            Some(step) => self.kv_quantity("step", step),
+            None => {
+                self.w.key("step");
+                self.w.write("null");
+            }
+        }
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
 
-fn emit_quantity_value(w: &mut JsonWriter, value: f64, unit: Option<&str>) {
-    w.obj_begin();
-    w.kv_f64("value", value);
-    if let Some(u) = unit {
+    fn visit_seed(&mut self, d: &SeedStmt) {
+        self.kind("seed");
+        self.w.kv_u64("value", d.value);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_assert(&mut self, d: &AssertStmt) {
+        self.kind("assert");
+        self.w.key("metric");
+        self.visit_metric(&d.metric);
+        self.w.comma_nl();
+        self.w.key("cond");
+        self.visit_assert_cond(&d.cond);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_disconnect(&mut self, d: &DisconnectStmt) {
+        self.kind("disconnect");
+        self.w.key("target");
+        self.w.obj_begin();
+        match &d.target {
+            ConnectionRef::Named(name) => {
+                self.kind("named");
+                self.kv_ident("name", name);
+            }
+            ConnectionRef::Layers { src, dst } => {
+                self.kind("layers");
+                self.kv_ident("src", src);
+                self.w.comma_nl();
+                self.kv_ident("dst", dst);
+            }
+        }
+        self.w.obj_end();
+        self.w.comma_nl();
+        self.w.key("filter");
+        match &d.filter {
+            Some(filter) => self.filter(filter),
+            None => self.w.write("null"),
+        }
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_reward(&mut self, d: &RewardStmt) {
+        self.kind("reward");
+        self.kv_quantity("value", &d.value);
+        self.w.comma_nl();
+        self.kv_quantity("at", &d.at);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_record(&mut self, d: &RecordStmt) {
+        self.kind("record");
+        self.kv_ident("layer", &d.layer);
+        self.w.comma_nl();
+        self.kv_quantity("window", &d.window);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_probe(&mut self, d: &ProbeDef) {
+        self.kind("probe");
+        self.kv_ident("name", &d.name);
+        self.w.comma_nl();
+        self.w.kv_str("variable", &d.variable.to_string());
+        self.w.comma_nl();
+        self.kv_ident("layer", &d.layer);
+        self.w.comma_nl();
+        self.w.key("neurons");
+        match d.neurons {
+            Some((first, end)) => self.w.write(&format!("[{first}, {end}]")),
+            None => self.w.write("null"),
+        }
+        self.w.comma_nl();
+        self.w.key("every");
+        match &d.every {
+            Some(every) => self.visit_quantity(every),
+            None => self.w.write("null"),
+        }
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &d.span);
+    }
+
+    fn visit_metric(&mut self, metric: &Metric) {
+        self.w.obj_begin();
+        self.kind(match metric {
+            Metric::Spikes { .. } => "spikes",
+            Metric::Rate { .. } => "rate",
+        });
+        self.kv_ident("layer", metric.layer());
+        self.w.obj_end();
+    }
+
+    fn visit_assert_cond(&mut self, cond: &AssertCond) {
+        self.w.obj_begin();
+        match cond {
+            AssertCond::Compare { op, value } => {
+                self.kind("compare");
+                self.w.kv_str("op", op.as_str());
+                self.w.comma_nl();
+                self.kv_quantity("value", value);
+            }
+            AssertCond::Between { low, high } => {
+                self.kind("between");
+                self.kv_quantity("low", low);
+                self.w.comma_nl();
+                self.kv_quantity("high", high);
+            }
+        }
+        self.w.obj_end();
+    }
+
+    fn visit_assign(&mut self, a: &Assign) {
+        self.w.obj_begin();
+        let parent = self.enter(child_id(&self.id, &a.key.name));
+        self.kind("assign");
+        self.kv_ident("key", &a.key);
+        self.w.comma_nl();
+        self.w.key("value");
+        self.visit_expr(&a.value);
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &a.span);
+        self.leave(parent);
+        self.w.obj_end();
+    }
+
+    fn visit_expr(&mut self, e: &Expr) {
+        match e {
+            Expr::String(s) => {
+                self.w.obj_begin();
+                self.kind("string");
+                self.w.kv_str("value", s);
+                self.w.obj_end();
+            }
+            _ => visit::walk_expr(self, e),
+        }
+    }
+
+    fn visit_call(&mut self, c: &Call) {
+        self.w.obj_begin();
+        self.kind("call");
+        self.kv_ident("name", &c.name);
+        self.w.comma_nl();
+        self.w.key("args");
+        self.w.array_begin();
+        for (idx, arg) in c.args.iter().enumerate() {
+            if idx != 0 {
+                self.w.comma();
+            }
+            self.w.nl();
+            let key = match arg {
+                CallArg::Positional(_) => idx.to_string(),
+                CallArg::Named { name, .. } => name.name.clone(),
+            };
+            let parent = self.id.clone();
+            self.id = child_id(&parent, &key);
+            self.visit_call_arg(arg);
+            self.leave(parent);
+        }
+        if !c.args.is_empty() {
+            self.w.nl();
+        }
+        self.w.array_end();
+        self.w.comma_nl();
+        self.w.key("span");
+        emit_span(&mut self.w, &c.span);
+        self.w.obj_end();
+    }
+
+    fn visit_call_arg(&mut self, arg: &CallArg) {
+        self.w.obj_begin();
+        self.w.kv_str("id", &self.id);
+        self.w.comma_nl();
+        match arg {
+            CallArg::Positional(e) => {
+                self.kind("positional");
+                self.w.key("value");
+                self.visit_expr(e);
+            }
+            CallArg::Named { name, value } => {
+                self.kind("named");
+                self.kv_ident("name", name);
+                self.w.comma_nl();
+                self.w.key("value");
+                self.visit_expr(value);
+            }
+        }
+        self.w.obj_end();
+    }
+
+    fn visit_ident(&mut self, id: &Ident) {
+        let w = &mut self.w;
+        w.obj_begin();
+        w.kv_str("kind", "ident");
         w.comma_nl();
-        w.kv_str("unit", u);
+        w.kv_str("name", &id.name);
+        w.comma_nl();
+        w.key("span");
+        emit_span(w, &id.span);
+        w.obj_end();
     }
-    w.obj_end();
+
+    fn visit_quantity(&mut self, q: &Quantity) {
+        let w = &mut self.w;
+        w.obj_begin();
+        w.kv_str("kind", "quantity");
+        w.comma_nl();
+        w.kv_f64("value", q.value);
+        w.comma_nl();
+        w.key("unit");
+        match &q.unit {
+            Some(unit) => w.str(&unit.name),
+            None => w.write("null"),
+        }
+        if let Ok(ns) = time_to_nanos(q, "") {
+            w.comma_nl();
+            w.key("resolved");
+            w.obj_begin();
+            w.kv_str("dimension", "time");
+            w.comma_nl();
+            w.key("ns");
+            w.write(&ns.to_string());
+            w.obj_end();
+        } else if let Ok(hz) = rate_to_hz(q, "") {
+            w.comma_nl();
+            w.key("resolved");
+            w.obj_begin();
+            w.kv_str("dimension", "rate");
+            w.comma_nl();
+            w.kv_f64("hz", hz);
+            w.obj_end();
+        }
+        w.comma_nl();
+        w.key("span");
+        emit_span(w, &q.span);
+        w.obj_end();
+    }
+}
+
+/// Byte offsets into the source, end exclusive.
+pub(crate) fn emit_span(w: &mut JsonWriter, span: &Span) {
+    w.write(&format!(
+        "{{\"start\": {}, \"end\": {}}}",
+        span.start, span.end
+    ));
 }
 
-struct JsonWriter {
+pub(crate) struct JsonWriter {
     out: String,
     indent: usize,
     at_line_start: bool,
 }
 
 impl JsonWriter {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             out: String::new(),
             indent: 0,
@@ -201,11 +1233,11 @@ impl JsonWriter {
         }
     }
 
-    fn finish(self) -> String {
+    pub(crate) fn finish(self) -> String {
         self.out
     }
 
-    fn write(&mut self, s: &str) {
+    pub(crate) fn write(&mut self, s: &str) {
         if self.at_line_start {
             for _ in 0..self.indent {
                 self.out.push_str("  ");
@@ -215,43 +1247,43 @@ impl JsonWriter {
         self.out.push_str(s);
     }
 
-    fn nl(&mut self) {
+    pub(crate) fn nl(&mut self) {
         self.out.push('\n');
         self.at_line_start = true;
     }
 
-    fn comma(&mut self) {
+    pub(crate) fn comma(&mut self) {
         self.write(",");
     }
 
-    fn comma_nl(&mut self) {
+    pub(crate) fn comma_nl(&mut self) {
         self.comma();
         self.nl();
     }
 
-    fn obj_begin(&mut self) {
+    pub(crate) fn obj_begin(&mut self) {
         self.write("{");
         self.indent += 1;
     }
 
-    fn obj_end(&mut self) {
+    pub(crate) fn obj_end(&mut self) {
         self.indent = self.indent.saturating_sub(1);
         self.nl();
         self.write("}");
     }
 
-    fn array_begin(&mut self) {
+    pub(crate) fn array_begin(&mut self) {
         self.write("[");
         self.indent += 1;
     }
 
-    fn array_end(&mut self) {
+    pub(crate) fn array_end(&mut self) {
         self.indent = self.indent.saturating_sub(1);
         self.nl();
         self.write("]");
     }
 
-    fn key(&mut self, k: &str) {
+    pub(crate) fn key(&mut self, k: &str) {
         if !self.at_line_start {
             self.nl();
         }
@@ -259,17 +1291,17 @@ impl JsonWriter {
         self.write(": ");
     }
 
-    fn kv_str(&mut self, k: &str, v: &str) {
+    pub(crate) fn kv_str(&mut self, k: &str, v: &str) {
         self.key(k);
         self.str(v);
     }
 
-    fn kv_u64(&mut self, k: &str, v: u64) {
+    pub(crate) fn kv_u64(&mut self, k: &str, v: u64) {
         self.key(k);
         self.write(&v.to_string());
     }
 
-    fn kv_f64(&mut self, k: &str, v: f64) {
+    pub(crate) fn kv_f64(&mut self, k: &str, v: f64) {
         self.key(k);
         if v.is_finite() {
             self.write(&format!("{v}"));
@@ -282,7 +1314,7 @@ impl JsonWriter {
         }
     }
 
-    fn str(&mut self, s: &str) {
+    pub(crate) fn str(&mut self, s: &str) {
         self.write("\"");
         for ch in s.chars() {
             match ch {
@@ -298,3 +1330,84 @@ impl JsonWriter {
         self.write("\"");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{self, Value};
+    use crate::parser::parse_program;
+
+    #[test]
+    fn ast_json_keeps_kinds_spans_and_resolved_units() {
+        let src = "layer A[2] : LIF\nstimulus A = Poisson(rate=2 kHz)\nseed 4\nrun for 5 ms\n";
+        let program = parse_program(src).expect("parse");
+        let root = json::parse(&ast_json(&program)).expect("valid json");
+        let items = root.get("items").and_then(Value::as_array).expect("items");
+        let kinds: Vec<&str> = items
+            .iter()
+            .filter_map(|i| i.get("kind").and_then(Value::as_str))
+            .collect();
+        assert_eq!(kinds, ["layer", "stimulus", "seed", "run"]);
+
+        let name = items[0].get("name").expect("name");
+        let span = name.get("span").expect("span");
+        let start = span.get("start").and_then(Value::as_f64).unwrap() as usize;
+        let end = span.get("end").and_then(Value::as_f64).unwrap() as usize;
+        assert_eq!(&src[start..end], "A");
+
+        let rate = items[1].get("model").and_then(|m| m.get("rate")).unwrap();
+        let resolved = rate.get("resolved").expect("resolved");
+        assert_eq!(resolved.get("hz").and_then(Value::as_f64), Some(2000.0));
+        let duration = items[3]
+            .get("duration")
+            .and_then(|d| d.get("resolved"))
+            .unwrap();
+        assert_eq!(duration.get("ns").and_then(Value::as_f64), Some(5e6));
+        assert!(matches!(items[3].get("step"), Some(Value::Null)));
+    }
+
+    #[test]
+    fn ids_name_items_values_and_arguments() {
+        let src = "layer A[2] : LIF
+                   network Small {
+                       connect ff: A -> A { w = Normal(0.5, sd=0.1) }
+                   }
+                   run for 5 ms
+";
+        let program = parse_program(src).expect("parse");
+        let root = json::parse(&ast_json(&program)).expect("valid json");
+        let id = |v: &Value| v.get("id").and_then(Value::as_str).map(str::to_string);
+        let items = root.get("items").and_then(Value::as_array).expect("items");
+        assert_eq!(id(&items[0]).as_deref(), Some("layer:A"));
+        let inner = &items[1].get("items").and_then(Value::as_array).unwrap()[0];
+        assert_eq!(id(inner).as_deref(), Some("network:Small/connect:ff"));
+        let w = &inner.get("body").and_then(Value::as_array).unwrap()[0];
+        assert_eq!(id(w).as_deref(), Some("network:Small/connect:ff/w"));
+        let args = w
+            .get("value")
+            .and_then(|v| v.get("args"))
+            .and_then(Value::as_array)
+            .unwrap();
+        let arg_ids: Vec<_> = args.iter().filter_map(id).collect();
+        assert_eq!(
+            arg_ids,
+            [
+                "network:Small/connect:ff/w/0",
+                "network:Small/connect:ff/w/sd"
+            ]
+        );
+
+        assert!(!cvir_json(&program).contains("\"id\""));
+        let opts = CvirOptions {
+            ids: true,
+            ..CvirOptions::default()
+        };
+        let cvir = cvir_json_with(&program, &opts);
+        assert!(cvir.contains("\"id\": \"network:Small/connect:ff/w\""));
+        assert!(cvir.contains("\"id\": \"network:Small/connect:ff/w/sd\""));
+        assert_eq!(
+            crate::cvir::program_from_cvir(&cvir).map(|p| cvir_json(&p)),
+            Ok(cvir_json(&program))
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! Semantic comparison of programs, for `converge cvir-eq`.
+//!
+//! Both programs are brought to a canonical form first: quantities are
+//! folded to `ns` and `Hz` as by [`FoldQuantities`], assignments are sorted
+//! by key, and each layer carries its neuron model's parameters rather than
+//! the model's name, so renamed or merged models still compare equal.
+//! Layers, and connections together with the disconnects between them, are
+//! compared in order, since their order decides layer numbering and the
+//! order weights are sampled in. Stimuli on a layer are compared in order
+//! once one of them uses `combine = replace` or `max`. Every other item is
+//! compared as a set.
+//! Shared items are compared first, then each `network` by name, with the
+//! shared items it sees.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::ast::*;
+use crate::opt::{FoldQuantities, Pass};
+
+/// Describes the first difference between `a` and `b`, or `None` when they
+/// mean the same.
+pub fn first_difference(a: &Program, b: &Program) -> Option<String> {
+    if let Some(difference) = canonical_difference(a, b) {
+        return Some(difference);
+    }
+    let names =
+        |p: &Program| -> BTreeSet<String> { p.networks().map(|n| n.name.name.clone()).collect() };
+    let (names_a, names_b) = (names(a), names(b));
+    if let Some(name) = names_a.difference(&names_b).next() {
+        return Some(format!("network `{name}` only in the first"));
+    }
+    if let Some(name) = names_b.difference(&names_a).next() {
+        return Some(format!("network `{name}` only in the second"));
+    }
+    names_a.iter().find_map(|name| {
+        let (Ok(a), Ok(b)) = (a.select_network(Some(name)), b.select_network(Some(name))) else {
+            unreachable!("both programs define network `{name}`");
+        };
+        canonical_difference(&a, &b).map(|d| format!("network `{name}`: {d}"))
+    })
+}
+
+/// Compares the items outside any network.
+fn canonical_difference(a: &Program, b: &Program) -> Option<String> {
+    let (a, b) = (Canonical::new(a), Canonical::new(b));
+    for (what, a, b) in [
+        ("layer", &a.layers, &b.layers),
+        ("connection item", &a.network, &b.network),
+    ] {
+        for idx in 0..a.len().max(b.len()) {
+            match (a.get(idx), b.get(idx)) {
+                (Some(x), Some(y)) if x == y => {}
+                (Some(x), Some(y)) => {
+                    return Some(format!("{what} #{}: `{x}` vs `{y}`", idx + 1));
+                }
+                (Some(x), None) => {
+                    return Some(format!("{what} #{} only in the first: `{x}`", idx + 1));
+                }
+                (None, Some(y)) => {
+                    return Some(format!("{what} #{} only in the second: `{y}`", idx + 1));
+                }
+                (None, None) => unreachable!("index below the longer length"),
+            }
+        }
+    }
+    if let Some(x) = a.others.difference(&b.others).next() {
+        return Some(format!("only in the first: `{x}`"));
+    }
+    if let Some(y) = b.others.difference(&a.others).next() {
+        return Some(format!("only in the second: `{y}`"));
+    }
+    None
+}
+
+struct Canonical {
+    layers: Vec<String>,
+    network: Vec<String>,
+    others: BTreeSet<String>,
+}
+
+impl Canonical {
+    fn new(program: &Program) -> Self {
+        let mut program = program.clone();
+        FoldQuantities.run(&mut program);
+        let neurons: HashMap<&str, String> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Neuron(def) => Some((def.name.name.as_str(), body(&def.body))),
+                _ => None,
+            })
+            .collect();
+
+        let mut canonical = Self {
+            layers: Vec::new(),
+            network: Vec::new(),
+            others: BTreeSet::new(),
+        };
+        let mut stimuli: HashMap<&str, Vec<(Option<StimulusCombine>, String)>> = HashMap::new();
+        for item in &program.items {
+            match item {
+                // Only the layers using a model give it meaning.
+                Item::Neuron(_) => {}
+                Item::Network(_) => {}
+                Item::Layer(def) => {
+                    let neuron = neurons
+                        .get(def.neuron.name.as_str())
+                        .cloned()
+                        .unwrap_or_else(|| def.neuron.name.clone());
+                    canonical
+                        .layers
+                        .push(format!("layer {}[{}] : {neuron}", def.name.name, def.size));
+                }
+                Item::Connect(def) => {
+                    canonical
+                        .network
+                        .push(format!("connect {} {}", def.label(), body(&def.body)))
+                }
+                Item::Disconnect(stmt) => canonical.network.push(match &stmt.filter {
+                    Some(filter) => format!("disconnect {} where {filter}", stmt.target),
+                    None => format!("disconnect {}", stmt.target),
+                }),
+                Item::Stimulus(def) => {
+                    let mut text = format!("stimulus {} = {}", def.layer.name, def.model);
+                    if let Some(combine) = def.combine {
+                        text.push_str(&format!(" combine = {}", combine.as_str()));
+                    }
+                    stimuli
+                        .entry(def.layer.name.as_str())
+                        .or_default()
+                        .push((def.combine, text));
+                }
+                Item::Run(stmt) => {
+                    let step = stmt
+                        .step
+                        .as_ref()
+                        .map(|step| format!(" step {step}"))
+                        .unwrap_or_default();
+                    canonical
+                        .others
+                        .insert(format!("run for {}{step}", stmt.duration));
+                }
+                Item::Seed(stmt) => {
+                    canonical.others.insert(format!("seed {}", stmt.value));
+                }
+                Item::Assert(stmt) => {
+                    let cond = match &stmt.cond {
+                        AssertCond::Compare { op, value } => format!("{} {value}", op.as_str()),
+                        AssertCond::Between { low, high } => format!("between {low} and {high}"),
+                    };
+                    canonical
+                        .others
+                        .insert(format!("assert {} {cond}", stmt.metric));
+                }
+                Item::Reward(stmt) => {
+                    canonical
+                        .others
+                        .insert(format!("reward {} at {}", stmt.value, stmt.at));
+                }
+                Item::Record(stmt) => {
+                    canonical.others.insert(format!(
+                        "record rate from {} window {}",
+                        stmt.layer.name, stmt.window
+                    ));
+                }
+                Item::Probe(def) => {
+                    canonical
+                        .others
+                        .insert(format!("probe {} = {def}", def.name.name));
+                }
+                Item::Modulator(def) => {
+                    canonical.others.insert(format!(
+                        "modulator {} {}",
+                        def.name.name,
+                        body(&def.body)
+                    ));
+                }
+                Item::Experiment(def) => {
+                    canonical
+                        .others
+                        .insert(format!("experiment {}", body(&def.body)));
+                }
+                // Runs in the order written; the report lists them so.
+                Item::Sweep(def) => {
+                    let runs: Vec<String> = def.runs.iter().map(|r| r.to_string()).collect();
+                    let report: Vec<String> = def.report.iter().map(|m| m.to_string()).collect();
+                    canonical.others.insert(format!(
+                        "experiment {{ {}; report {} }}",
+                        runs.join("; "),
+                        report.join(", ")
+                    ));
+                }
+                Item::Readout(def) => {
+                    let rule = def
+                        .rule
+                        .as_ref()
+                        .map(|rule| format!(" rule = {}", rule.name))
+                        .unwrap_or_default();
+                    canonical.others.insert(format!(
+                        "readout {} labels {:?}{rule}",
+                        def.layer.name, def.labels
+                    ));
+                }
+            }
+        }
+        // Stimuli that add up compare as a set, but `replace` and `max`
+        // make their order on a layer matter.
+        for (_, stimuli) in stimuli {
+            let ordered = stimuli.iter().any(|(combine, _)| {
+                matches!(
+                    combine,
+                    Some(StimulusCombine::Replace | StimulusCombine::Max)
+                )
+            });
+            if ordered {
+                let texts: Vec<String> = stimuli.into_iter().map(|(_, text)| text).collect();
+                canonical.others.insert(texts.join("; "));
+            } else {
+                canonical
+                    .others
+                    .extend(stimuli.into_iter().map(|(_, text)| text));
+            }
+        }
+        canonical
+    }
+}
+
+/// `{ a = 1, b = 2 ns }`, sorted by key.
+fn body(body: &[Assign]) -> String {
+    let mut assigns: Vec<String> = body
+        .iter()
+        .map(|a| format!("{} = {}", a.key.name, a.value))
+        .collect();
+    assigns.sort();
+    format!("{{ {} }}", assigns.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opt::optimize;
+    use crate::parser::parse_program;
+
+    fn parse(src: &str) -> Program {
+        parse_program(src).expect("parse")
+    }
+
+    #[test]
+    fn ignores_unordered_items_formatting_and_model_names() {
+        let a = parse(
+            "neuron LIF { tau_m = 20 ms, v_th = 1.0 }\n\
+             layer In[4] : LIF\n\
+             layer Out[2] : LIF\n\
+             connect In -> Out { w = 0.5, d = 1 ms }\n\
+             stimulus In = Poisson(rate=200 Hz)\n\
+             seed 3\n\
+             run for 10 ms\n",
+        );
+        let b = parse(
+            "run for 0.01 s\n\
+             seed 3\n\
+             stimulus In = Poisson(rate=0.2 kHz)\n\
+             neuron Cell { v_th = 1.0, tau_m = 20000 us }\n\
+             layer In[4] : Cell\n\
+             layer Out[2] : Cell\n\
+             connect In -> Out { d = 1 ms, w = 0.5 }\n",
+        );
+        assert_eq!(first_difference(&a, &b), None);
+        let mut optimized = a.clone();
+        optimize(&mut optimized);
+        assert_eq!(first_difference(&a, &optimized), None);
+    }
+
+    #[test]
+    fn reports_the_first_difference() {
+        let a = parse(
+            "neuron LIF { tau_m = 20 ms }\n\
+             layer A[4] : LIF\n\
+             layer B[4] : LIF\n\
+             connect A -> B { w = 0.5 }\n\
+             connect B -> A { w = 0.5 }\n\
+             run for 10 ms\n",
+        );
+        let swapped = parse(
+            "neuron LIF { tau_m = 20 ms }\n\
+             layer A[4] : LIF\n\
+             layer B[4] : LIF\n\
+             connect B -> A { w = 0.5 }\n\
+             connect A -> B { w = 0.5 }\n\
+             run for 10 ms\n",
+        );
+        assert_eq!(
+            first_difference(&a, &swapped).as_deref(),
+            Some(
+                "connection item #1: `connect A -> B { w = 0.5 }` vs `connect B -> A { w = 0.5 }`"
+            )
+        );
+        let longer = parse(
+            "neuron LIF { tau_m = 20 ms }\n\
+             layer A[4] : LIF\n\
+             layer B[4] : LIF\n\
+             connect A -> B { w = 0.5 }\n\
+             connect B -> A { w = 0.5 }\n\
+             run for 20 ms\n",
+        );
+        assert_eq!(
+            first_difference(&a, &longer).as_deref(),
+            Some("only in the first: `run for 10000000 ns`")
+        );
+    }
+}
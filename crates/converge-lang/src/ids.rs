@@ -0,0 +1,122 @@
+//! Stable ids for items and the values in them, for tools that need to name
+//! "the weight of connection `ff`" across runs and edits. Spans change with
+//! every edit above a node; an id only changes when the node itself does.
+//!
+//! An item's id is its kind and what it declares or acts on:
+//! `neuron:LIF`, `layer:In`, `connect:ff` for a named connection and
+//! `connect:In->Out` (`connect:A<->B` for gap junctions) for others,
+//! `stimulus:In`, `record:Out`, `readout:Out`, `probe:p1`, `modulator:DA`,
+//! `network:Small`, `assert:rate(Out)`, `disconnect:ff` or
+//! `disconnect:In->Out`, and plain `run`, `seed`, `reward`, `experiment`
+//! and `sweep`. The second item with the same id gets `#2` appended, the
+//! third `#3`, and so on. Items inside a network are prefixed with the
+//! network's id and `/`: `network:Small/layer:In`.
+//!
+//! A value in an item's body is the item's id, `/` and the key:
+//! `connect:ff/w`, and `params/rate` for parameters. An argument of a call
+//! appends its position from 0, or its name: `connect:ff/w/1` is the
+//! standard deviation of `w = Normal(0.5, 0.1)`.
+
+use std::collections::HashMap;
+
+use crate::ast::{ConnectionRef, Item};
+
+/// The ids of `items`, in order, each prefixed with `prefix` and `/` when
+/// `prefix` is not empty.
+pub fn item_ids(items: &[Item], prefix: &str) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    items
+        .iter()
+        .map(|item| {
+            let base = base_id(item);
+            let count = seen.entry(base.clone()).or_insert(0);
+            *count += 1;
+            let id = match *count {
+                1 => base,
+                n => format!("{base}#{n}"),
+            };
+            child_id(prefix, &id)
+        })
+        .collect()
+}
+
+/// `parent/child`, or `child` alone at the top level.
+pub fn child_id(parent: &str, child: &str) -> String {
+    if parent.is_empty() {
+        child.to_string()
+    } else {
+        format!("{parent}/{child}")
+    }
+}
+
+fn base_id(item: &Item) -> String {
+    match item {
+        Item::Neuron(d) => format!("neuron:{}", d.name.name),
+        Item::Layer(d) => format!("layer:{}", d.name.name),
+        Item::Connect(d) => match &d.name {
+            Some(name) => format!("connect:{}", name.name),
+            None => format!("connect:{}{}{}", d.src.name, d.kind.arrow(), d.dst.name),
+        },
+        Item::Stimulus(d) => format!("stimulus:{}", d.layer.name),
+        Item::Run(_) => "run".to_string(),
+        Item::Seed(_) => "seed".to_string(),
+        Item::Assert(d) => format!("assert:{}", d.metric),
+        Item::Disconnect(d) => match &d.target {
+            ConnectionRef::Named(name) => format!("disconnect:{}", name.name),
+            ConnectionRef::Layers { src, dst } => format!("disconnect:{}->{}", src.name, dst.name),
+        },
+        Item::Reward(_) => "reward".to_string(),
+        Item::Record(d) => format!("record:{}", d.layer.name),
+        Item::Probe(d) => format!("probe:{}", d.name.name),
+        Item::Modulator(d) => format!("modulator:{}", d.name.name),
+        Item::Experiment(_) => "experiment".to_string(),
+        Item::Readout(d) => format!("readout:{}", d.layer.name),
+        Item::Network(d) => format!("network:{}", d.name.name),
+        Item::Sweep(_) => "sweep".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn ids_survive_edits_to_other_items() {
+        let src = "neuron LIF { tau_m = 20 ms }\n\
+                   layer In[4] : LIF\n\
+                   layer Out[2] : LIF\n\
+                   connect In -> Out { w = 0.5 }\n\
+                   connect In -> Out { w = 0.2 }\n\
+                   connect back: Out -> In { w = 0.1 }\n\
+                   connect In <-> Out electrical { g = 0.1 }\n\
+                   reward 1 at 5 ms\n\
+                   reward 1 at 8 ms\n\
+                   assert rate(Out) > 1 Hz\n\
+                   run for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        assert_eq!(
+            item_ids(&program.items, ""),
+            [
+                "neuron:LIF",
+                "layer:In",
+                "layer:Out",
+                "connect:In->Out",
+                "connect:In->Out#2",
+                "connect:back",
+                "connect:In<->Out",
+                "reward",
+                "reward#2",
+                "assert:rate(Out)",
+                "run",
+            ]
+        );
+
+        let edited = format!("layer Extra[1] : LIF\nseed 3\n{src}");
+        let edited = parse_program(&edited).expect("parse");
+        assert_eq!(
+            item_ids(&edited.items, "")[2..],
+            item_ids(&program.items, "")[..]
+        );
+    }
+}
@@ -1,3 +1,7 @@
+use std::collections::VecDeque;
+
+use unicode_ident::{is_xid_continue, is_xid_start};
+
 use crate::diagnostic::{Diagnostic, Span};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -5,16 +9,8 @@ pub enum TokenKind {
     Ident(String),
     Number(String),
     String(String),
-
-    KwNeuron,
-    KwLayer,
-    KwConnect,
-    KwRun,
-    KwFor,
-    KwSeed,
-    KwStep,
-    KwStimulus,
-    KwRate,
+    /// A `/// ...` line, without the slashes and one space after them.
+    DocComment(String),
 
     LBrace,
     RBrace,
@@ -23,9 +19,19 @@ pub enum TokenKind {
     LParen,
     RParen,
     Colon,
+    Semi,
     Comma,
+    DotDot,
     Eq,
+    EqEq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
     Arrow,
+    BiArrow,
+    At,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -34,43 +40,138 @@ pub struct Token {
     pub span: Span,
 }
 
+/// Looks up the variables `${NAME}` refers to.
+pub type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// An [`Env`] with no variables, so only `${NAME:default}` references
+/// resolve. Anything parsing text it didn't write itself, such as a program
+/// sent to `converge serve`, should use this rather than [`process_env`].
+pub fn no_env(_: &str) -> Option<String> {
+    None
+}
+
+/// An [`Env`] reading the process environment, for a program the user runs
+/// locally.
+pub fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Lexes `input` with [`no_env`].
 pub fn lex(input: &str) -> Result<Vec<Token>, Diagnostic> {
-    let mut lexer = Lexer::new(input);
+    lex_with_env(input, &no_env)
+}
+
+/// Lexes `input`, replacing `${NAME}` and `${NAME:default}` outside comments
+/// with `env`'s value for `NAME`, or the default when it has none. Tokens
+/// from a substituted value carry the span of the whole `${...}`, so
+/// diagnostics point into `input`. Inside strings `$${` is a literal `${`.
+pub fn lex_with_env(input: &str, env: Env<'_>) -> Result<Vec<Token>, Diagnostic> {
+    let (tokens, diags) = lex_recovering(input, env);
+    match diags.into_iter().next() {
+        Some(diag) => Err(diag),
+        None => Ok(tokens),
+    }
+}
+
+/// [`lex_with_env`], going on past every problem instead of stopping at
+/// the first: the tokens it could make, and a diagnostic per problem in
+/// source order. An unexpected character is skipped, a bad escape is left
+/// out of its string, an unterminated string runs to the end of the input,
+/// and a `${...}` that can't be substituted stands for nothing.
+pub fn lex_recovering(input: &str, env: Env<'_>) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut lexer = Lexer::new(input, env);
     let mut tokens = Vec::new();
-    while let Some(tok) = lexer.next_token()? {
+    while let Some(tok) = lexer.next_token() {
         tokens.push(tok);
     }
-    Ok(tokens)
+    (tokens, lexer.diagnostics)
+}
+
+/// `s` as a string literal that lexes back to `s`: quotes, backslashes and
+/// line breaks escaped, and `${` written as `$${` so nothing is substituted.
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    let mut rest = s;
+    while let Some(ch) = rest.chars().next() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if rest.starts_with("${") => out.push_str("$$"),
+            ch => out.push(ch),
+        }
+        rest = &rest[ch.len_utf8()..];
+    }
+    out.push('"');
+    out
 }
 
 struct Lexer<'a> {
     input: &'a str,
     bytes: &'a [u8],
     i: usize,
+    env: Env<'a>,
+    /// Tokens of a substituted value not yet returned.
+    pending: VecDeque<Token>,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'a> Lexer<'a> {
-    fn new(input: &'a str) -> Self {
+    fn new(input: &'a str, env: Env<'a>) -> Self {
         Self {
             input,
             bytes: input.as_bytes(),
             i: 0,
+            env,
+            pending: VecDeque::new(),
+            diagnostics: Vec::new(),
         }
     }
 
-    fn next_token(&mut self) -> Result<Option<Token>, Diagnostic> {
+    fn next_token(&mut self) -> Option<Token> {
+        if let Some(tok) = self.pending.pop_front() {
+            return Some(tok);
+        }
         self.skip_ws_and_comments();
+        if self.i < self.bytes.len() && self.bytes[self.i..].starts_with(b"${") {
+            if let Some((value, span)) = self.substitution() {
+                let (tokens, diags) = lex_recovering(&value, &|_| None);
+                for diag in diags {
+                    self.diagnostics.push(
+                        Diagnostic::new(format!(
+                            "in `{}`: {}",
+                            &self.input[span.start..span.end],
+                            diag.message
+                        ))
+                        .with_span(span.clone()),
+                    );
+                }
+                self.pending.extend(tokens.into_iter().map(|tok| Token {
+                    kind: tok.kind,
+                    span: span.clone(),
+                }));
+            }
+            return self.next_token();
+        }
         if self.i >= self.bytes.len() {
-            return Ok(None);
+            return None;
         }
 
         let start = self.i;
         let b = self.bytes[self.i];
         let tok = match b {
+            b'/' if self.at_doc_comment() => self.lex_doc_comment(),
             b'{' => {
                 self.i += 1;
                 TokenKind::LBrace
             }
+            b'@' => {
+                self.i += 1;
+                TokenKind::At
+            }
             b'}' => {
                 self.i += 1;
                 TokenKind::RBrace
@@ -95,36 +196,87 @@ impl<'a> Lexer<'a> {
                 self.i += 1;
                 TokenKind::Colon
             }
+            b';' => {
+                self.i += 1;
+                TokenKind::Semi
+            }
             b',' => {
                 self.i += 1;
                 TokenKind::Comma
             }
+            b'.' if self.peek_is(b'.') => {
+                self.i += 2;
+                TokenKind::DotDot
+            }
+            b'=' if self.peek_is(b'=') => {
+                self.i += 2;
+                TokenKind::EqEq
+            }
             b'=' => {
                 self.i += 1;
                 TokenKind::Eq
             }
+            b'!' if self.peek_is(b'=') => {
+                self.i += 2;
+                TokenKind::NotEq
+            }
+            b'<' if self.input[self.i..].starts_with("<->") => {
+                self.i += 3;
+                TokenKind::BiArrow
+            }
+            b'<' if self.peek_is(b'=') => {
+                self.i += 2;
+                TokenKind::Le
+            }
+            b'<' => {
+                self.i += 1;
+                TokenKind::Lt
+            }
+            b'>' if self.peek_is(b'=') => {
+                self.i += 2;
+                TokenKind::Ge
+            }
+            b'>' => {
+                self.i += 1;
+                TokenKind::Gt
+            }
             b'-' if self.peek_is(b'>') => {
                 self.i += 2;
                 TokenKind::Arrow
             }
-            b'"' => return self.lex_string(start).map(Some),
-            b'0'..=b'9' | b'-' => return self.lex_number_or_ident().map(Some),
+            b'"' => return Some(self.lex_string(start)),
+            b'0'..=b'9' => return Some(self.lex_number()),
+            b'-' => {
+                self.i += 1;
+                if !self.bytes.get(self.i).is_some_and(u8::is_ascii_digit) {
+                    self.error("unexpected '-'", Span::new(start, self.i));
+                    return self.next_token();
+                }
+                return Some(self.lex_number_from(start));
+            }
             _ => {
-                if is_ident_start(b) {
-                    return self.lex_ident().map(Some);
+                let ch = self.input[self.i..].chars().next().unwrap_or('\u{FFFD}');
+                if ch == '_' || is_xid_start(ch) {
+                    return Some(self.lex_ident());
                 }
-                return Err(Diagnostic::new(format!(
-                    "unexpected character '{}'",
-                    self.input[self.i..].chars().next().unwrap_or('\u{FFFD}')
-                ))
-                .with_span(Span::new(self.i, self.i + 1)));
+                self.error(
+                    format!("unexpected character '{ch}'"),
+                    Span::new(self.i, self.i + ch.len_utf8()),
+                );
+                self.i += ch.len_utf8();
+                return self.next_token();
             }
         };
 
-        Ok(Some(Token {
+        Some(Token {
             kind: tok,
             span: Span::new(start, self.i),
-        }))
+        })
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.diagnostics
+            .push(Diagnostic::new(message).with_span(span));
     }
 
     fn skip_ws_and_comments(&mut self) {
@@ -137,10 +289,7 @@ impl<'a> Lexer<'a> {
                     break;
                 }
             }
-            if self.i + 1 < self.bytes.len()
-                && self.bytes[self.i] == b'/'
-                && self.bytes[self.i + 1] == b'/'
-            {
+            if self.bytes[self.i..].starts_with(b"//") && !self.at_doc_comment() {
                 self.i += 2;
                 while self.i < self.bytes.len() && self.bytes[self.i] != b'\n' {
                     self.i += 1;
@@ -151,11 +300,63 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// `///`, but not `////`, which stays a plain comment.
+    fn at_doc_comment(&self) -> bool {
+        self.bytes[self.i..].starts_with(b"///") && self.bytes.get(self.i + 3) != Some(&b'/')
+    }
+
+    fn lex_doc_comment(&mut self) -> TokenKind {
+        self.i += 3;
+        let start = self.i;
+        while self.i < self.bytes.len() && self.bytes[self.i] != b'\n' {
+            self.i += 1;
+        }
+        let text = self.input[start..self.i].trim_end_matches('\r');
+        TokenKind::DocComment(text.strip_prefix(' ').unwrap_or(text).to_string())
+    }
+
     fn peek_is(&self, b: u8) -> bool {
         self.i + 1 < self.bytes.len() && self.bytes[self.i + 1] == b
     }
 
-    fn lex_string(&mut self, start: usize) -> Result<Token, Diagnostic> {
+    /// `${NAME}` or `${NAME:default}` at the cursor: its value and span,
+    /// or `None` after recording why it has none.
+    fn substitution(&mut self) -> Option<(String, Span)> {
+        let start = self.i;
+        let Some(len) = self.input[start..].find('}') else {
+            self.i += 2;
+            self.error("unterminated `${`", Span::new(start, start + 2));
+            return None;
+        };
+        self.i = start + len + 1;
+        let span = Span::new(start, self.i);
+        let inner = &self.input[start + 2..start + len];
+        let (name, default) = match inner.split_once(':') {
+            Some((name, default)) => (name, Some(default)),
+            None => (inner, None),
+        };
+        let bytes = name.as_bytes();
+        if bytes.is_empty()
+            || !is_ident_start(bytes[0])
+            || !bytes.iter().all(|&b| is_ident_continue(b))
+        {
+            self.error(
+                format!("invalid variable name `{name}` in `${{...}}`"),
+                span,
+            );
+            return None;
+        }
+        let value = (self.env)(name).or_else(|| default.map(str::to_string));
+        if value.is_none() {
+            self.error(
+                format!("`{name}` is not set and has no default"),
+                span.clone(),
+            );
+        }
+        Some((value?, span))
+    }
+
+    fn lex_string(&mut self, start: usize) -> Token {
         debug_assert_eq!(self.bytes[self.i], b'"');
         self.i += 1;
         let mut s = String::new();
@@ -163,28 +364,37 @@ impl<'a> Lexer<'a> {
             match self.bytes[self.i] {
                 b'"' => {
                     self.i += 1;
-                    return Ok(Token {
+                    return Token {
                         kind: TokenKind::String(s),
                         span: Span::new(start, self.i),
-                    });
+                    };
                 }
                 b'\\' => {
                     self.i += 1;
                     if self.i >= self.bytes.len() {
                         break;
                     }
-                    let esc = self.bytes[self.i];
-                    self.i += 1;
+                    let esc = self.input[self.i..].chars().next().unwrap_or('\u{FFFD}');
+                    self.i += esc.len_utf8();
                     match esc {
-                        b'"' => s.push('"'),
-                        b'\\' => s.push('\\'),
-                        b'n' => s.push('\n'),
-                        b'r' => s.push('\r'),
-                        b't' => s.push('\t'),
-                        _ => {
-                            return Err(Diagnostic::new("invalid string escape")
-                                .with_span(Span::new(self.i.saturating_sub(2), self.i)));
-                        }
+                        '"' => s.push('"'),
+                        '\\' => s.push('\\'),
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        _ => self.error(
+                            "invalid string escape",
+                            Span::new(self.i - esc.len_utf8() - 1, self.i),
+                        ),
+                    }
+                }
+                b'$' if self.bytes[self.i..].starts_with(b"$${") => {
+                    self.i += 3;
+                    s.push_str("${");
+                }
+                b'$' if self.bytes[self.i..].starts_with(b"${") => {
+                    if let Some((value, _)) = self.substitution() {
+                        s.push_str(&value);
                     }
                 }
                 _ => {
@@ -194,57 +404,71 @@ impl<'a> Lexer<'a> {
                 }
             }
         }
-        Err(Diagnostic::new("unterminated string").with_span(Span::new(start, self.i)))
+        self.error("unterminated string", Span::new(start, self.i));
+        Token {
+            kind: TokenKind::String(s),
+            span: Span::new(start, self.i),
+        }
     }
 
-    fn lex_ident(&mut self) -> Result<Token, Diagnostic> {
+    /// An identifier: a letter or `_`, then letters, digits and `_`, where
+    /// a letter is anything Unicode allows to start an identifier, such as
+    /// `τ` or `Schicht`.
+    fn lex_ident(&mut self) -> Token {
         let start = self.i;
-        self.i += 1;
-        while self.i < self.bytes.len() && is_ident_continue(self.bytes[self.i]) {
-            self.i += 1;
-        }
+        let len = self.input[start..]
+            .char_indices()
+            .find(|&(idx, ch)| idx > 0 && !is_xid_continue(ch))
+            .map_or(self.input.len() - start, |(idx, _)| idx);
+        self.i = start + len;
         let text = &self.input[start..self.i];
-        let kind = match text {
-            "neuron" => TokenKind::KwNeuron,
-            "layer" => TokenKind::KwLayer,
-            "connect" => TokenKind::KwConnect,
-            "run" => TokenKind::KwRun,
-            "for" => TokenKind::KwFor,
-            "seed" => TokenKind::KwSeed,
-            "step" => TokenKind::KwStep,
-            "stimulus" => TokenKind::KwStimulus,
-            "rate" => TokenKind::KwRate,
-            _ => TokenKind::Ident(text.to_string()),
-        };
-        Ok(Token {
+        // Keywords are words the parser looks for in context, so they are
+        // identifiers here.
+        let kind = TokenKind::Ident(text.to_string());
+        Token {
             kind,
             span: Span::new(start, self.i),
-        })
+        }
     }
 
-    fn lex_number_or_ident(&mut self) -> Result<Token, Diagnostic> {
-        let start = self.i;
-        if self.bytes[self.i] == b'-' {
-            self.i += 1;
-            if self.i >= self.bytes.len() || !self.bytes[self.i].is_ascii_digit() {
-                // It's just '-' and not a number; let caller handle as unexpected.
-                return Err(Diagnostic::new("unexpected '-'").with_span(Span::new(start, self.i)));
+    fn lex_number(&mut self) -> Token {
+        self.lex_number_from(self.i)
+    }
+
+    /// A number from `start`, with the cursor past its sign if it has one.
+    /// `0x` starts a hexadecimal integer.
+    fn lex_number_from(&mut self, start: usize) -> Token {
+        if self.bytes.get(self.i) == Some(&b'0')
+            && matches!(self.bytes.get(self.i + 1), Some(b'x' | b'X'))
+            && self
+                .bytes
+                .get(self.i + 2)
+                .is_some_and(u8::is_ascii_hexdigit)
+        {
+            self.i += 2;
+            while self.i < self.bytes.len() && self.bytes[self.i].is_ascii_hexdigit() {
+                self.i += 1;
             }
+            return Token {
+                kind: TokenKind::Number(self.input[start..self.i].to_string()),
+                span: Span::new(start, self.i),
+            };
         }
         while self.i < self.bytes.len() && self.bytes[self.i].is_ascii_digit() {
             self.i += 1;
         }
-        if self.i < self.bytes.len() && self.bytes[self.i] == b'.' {
+        // `1..10` is a range, not the number `1.` followed by `.10`.
+        if self.i < self.bytes.len() && self.bytes[self.i] == b'.' && !self.peek_is(b'.') {
             self.i += 1;
             while self.i < self.bytes.len() && self.bytes[self.i].is_ascii_digit() {
                 self.i += 1;
             }
         }
         let text = self.input[start..self.i].to_string();
-        Ok(Token {
+        Token {
             kind: TokenKind::Number(text),
             span: Span::new(start, self.i),
-        })
+        }
     }
 }
 
@@ -1,9 +1,26 @@
 #![forbid(unsafe_code)]
 
 pub mod ast;
+pub mod builder;
+pub mod cvir;
+pub mod deprecated;
 pub mod diagnostic;
+pub mod diff;
 pub mod emit;
+pub mod equiv;
+pub mod ids;
+pub mod json;
 pub mod lexer;
+pub mod opt;
 pub mod parser;
+pub mod place;
+pub mod print;
+pub mod stats;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+pub mod target;
+pub mod test_support;
+pub mod toml;
 pub mod units;
 pub mod validate;
+pub mod visit;
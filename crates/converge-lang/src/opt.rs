@@ -0,0 +1,408 @@
+//! Optimization passes over a validated program, run by `converge cvir
+//! --optimize`.
+//!
+//! A [`PassManager`] holds an ordered list of [`Pass`]es and reruns the whole
+//! list until none of them changes the program. The standard pipeline is:
+//!
+//! - `fold-quantities`: every time becomes whole nanoseconds and every rate
+//!   hertz, the values the simulator reads them as.
+//! - `dead-code`: drops connections that can never hold a synapse, layers
+//!   that can never spike and that nothing refers to, and neuron models no
+//!   layer uses. Layers and connections are only dropped from programs
+//!   without `network` blocks, whose names are scoped per network.
+//! - `merge-neurons`: layers whose neuron models have identical parameters
+//!   share the first of them.
+//!
+//! Passes never change which random numbers are drawn, so an optimized
+//! program samples the same weights and reports the same spikes for every
+//! layer it keeps. Removing a layer does renumber the ones after it, which
+//! changes the spike hash when they spike.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+use crate::units::{rate_to_hz, time_to_nanos};
+use crate::visit::{Visit, VisitMut};
+
+pub trait Pass {
+    /// Short kebab-case name, as reported by [`PassManager::run`].
+    fn name(&self) -> &'static str;
+
+    /// Rewrites `program` in place and returns whether anything changed.
+    fn run(&mut self, program: &mut Program) -> bool;
+}
+
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    /// A manager with no passes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The standard pipeline of this module.
+    pub fn standard() -> Self {
+        let mut manager = Self::new();
+        manager.register(FoldQuantities);
+        manager.register(DeadCode);
+        manager.register(MergeNeurons);
+        manager
+    }
+
+    /// Appends `pass` to the pipeline.
+    pub fn register(&mut self, pass: impl Pass + 'static) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    /// Runs the pipeline until it reaches a fixed point, returning the
+    /// names of the passes that changed something, in pipeline order.
+    pub fn run(&mut self, program: &mut Program) -> Vec<&'static str> {
+        let mut changed = vec![false; self.passes.len()];
+        loop {
+            let mut again = false;
+            for (pass, changed) in self.passes.iter_mut().zip(&mut changed) {
+                if pass.run(program) {
+                    *changed = true;
+                    again = true;
+                }
+            }
+            if !again {
+                break;
+            }
+        }
+        self.passes
+            .iter()
+            .zip(changed)
+            .filter(|(_, changed)| *changed)
+            .map(|(pass, _)| pass.name())
+            .collect()
+    }
+}
+
+/// Runs the standard pipeline on `program`.
+pub fn optimize(program: &mut Program) -> Vec<&'static str> {
+    PassManager::standard().run(program)
+}
+
+pub struct FoldQuantities;
+
+impl Pass for FoldQuantities {
+    fn name(&self) -> &'static str {
+        "fold-quantities"
+    }
+
+    fn run(&mut self, program: &mut Program) -> bool {
+        struct Fold(bool);
+
+        impl VisitMut for Fold {
+            fn visit_quantity_mut(&mut self, q: &mut Quantity) {
+                let Some(unit) = &q.unit else {
+                    return;
+                };
+                let (value, base) = match unit.name.as_str() {
+                    "s" | "ms" | "us" | "ns" => match time_to_nanos(q, "") {
+                        Ok(nanos) => (nanos as f64, "ns"),
+                        Err(_) => return,
+                    },
+                    "Hz" | "kHz" => match rate_to_hz(q, "") {
+                        Ok(hz) => (hz, "Hz"),
+                        Err(_) => return,
+                    },
+                    _ => return,
+                };
+                let unit = q.unit.as_mut().expect("checked above");
+                if q.value.to_bits() != value.to_bits() || unit.name != base {
+                    q.value = value;
+                    unit.name = base.to_string();
+                    self.0 = true;
+                }
+            }
+        }
+
+        let mut fold = Fold(false);
+        fold.visit_program_mut(program);
+        fold.0
+    }
+}
+
+pub struct DeadCode;
+
+impl Pass for DeadCode {
+    fn name(&self) -> &'static str {
+        "dead-code"
+    }
+
+    fn run(&mut self, program: &mut Program) -> bool {
+        let before = program.items.len();
+        if program.networks().next().is_none() {
+            remove_dead_connections(program);
+            remove_dead_layers(program);
+        }
+        remove_unused_neurons(program);
+        program.items.len() != before
+    }
+}
+
+/// Connections into or out of an empty layer, and connections a later
+/// `disconnect` removes entirely whose parameters are plain numbers, so
+/// sampling them draws nothing. Disconnects left matching nothing go too.
+fn remove_dead_connections(program: &mut Program) {
+    let sizes: HashMap<&str, u64> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Layer(def) => Some((def.name.name.as_str(), def.size)),
+            _ => None,
+        })
+        .collect();
+    let empty = |layer: &Ident| sizes.get(layer.name.as_str()) == Some(&0);
+    let draws_nothing = |def: &ConnectDef| {
+        def.body
+            .iter()
+            .filter(|a| matches!(a.key.name.as_str(), "w" | "d" | "g"))
+            .all(|a| matches!(a.value, Expr::Number(_)))
+    };
+    let mut dead = vec![false; program.items.len()];
+    for (idx, item) in program.items.iter().enumerate() {
+        let Item::Connect(def) = item else {
+            continue;
+        };
+        let cleared = program.items[idx + 1..].iter().any(|later| {
+            matches!(later, Item::Disconnect(stmt) if stmt.filter.is_none() && stmt.target.matches(def))
+        });
+        dead[idx] = empty(&def.src) || empty(&def.dst) || (cleared && draws_nothing(def));
+    }
+    for (idx, item) in program.items.iter().enumerate() {
+        let Item::Disconnect(stmt) = item else {
+            continue;
+        };
+        let live = program.items[..idx]
+            .iter()
+            .zip(&dead)
+            .any(|(earlier, &dead)| {
+                !dead && matches!(earlier, Item::Connect(def) if stmt.target.matches(def))
+            });
+        dead[idx] = !live;
+    }
+    let mut dead = dead.into_iter();
+    program.items.retain(|_| !dead.next().unwrap_or(false));
+}
+
+/// Layers that can never spike: no stimulus, connection or other item
+/// names them, and their neuron model has a positive `v_th` and no
+/// modulator to lower it.
+fn remove_dead_layers(program: &mut Program) {
+    #[derive(Default)]
+    struct References(HashSet<String>);
+
+    impl Visit for References {
+        // A layer's own name and its neuron model are not references to a
+        // layer.
+        fn visit_layer(&mut self, _def: &LayerDef) {}
+
+        fn visit_ident(&mut self, ident: &Ident) {
+            self.0.insert(ident.name.clone());
+        }
+    }
+
+    let mut refs = References::default();
+    refs.visit_program(program);
+    let silent: HashSet<&str> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Neuron(def) => Some(def),
+            _ => None,
+        })
+        .filter(|def| {
+            let threshold = def.body.iter().find(|a| a.key.name == "v_th");
+            let modulated = def.body.iter().any(|a| a.key.name == "modulator");
+            !modulated
+                && match threshold.map(|a| &a.value) {
+                    None => true,
+                    Some(Expr::Number(q)) => q.value > 0.0,
+                    Some(_) => false,
+                }
+        })
+        .map(|def| def.name.name.as_str())
+        .collect();
+    let dead: Vec<bool> = program
+        .items
+        .iter()
+        .map(|item| match item {
+            Item::Layer(def) => {
+                !refs.0.contains(&def.name.name) && silent.contains(def.neuron.name.as_str())
+            }
+            _ => false,
+        })
+        .collect();
+    let mut dead = dead.into_iter();
+    program.items.retain(|_| !dead.next().unwrap_or(false));
+}
+
+/// Neuron models no layer uses, in any network. Networks share the models
+/// declared outside them, so only those are candidates.
+fn remove_unused_neurons(program: &mut Program) {
+    #[derive(Default)]
+    struct Used(HashSet<String>);
+
+    impl Visit for Used {
+        fn visit_layer(&mut self, def: &LayerDef) {
+            self.0.insert(def.neuron.name.clone());
+        }
+    }
+
+    let mut used = Used::default();
+    used.visit_program(program);
+    program
+        .items
+        .retain(|item| !matches!(item, Item::Neuron(def) if !used.0.contains(&def.name.name)));
+}
+
+pub struct MergeNeurons;
+
+impl Pass for MergeNeurons {
+    fn name(&self) -> &'static str {
+        "merge-neurons"
+    }
+
+    fn run(&mut self, program: &mut Program) -> bool {
+        // Parameters as written, in key order, ignoring spans.
+        let params = |def: &NeuronDef| {
+            let mut params: Vec<(String, String)> = def
+                .body
+                .iter()
+                .map(|a| (a.key.name.clone(), a.value.to_string()))
+                .collect();
+            params.sort();
+            params
+        };
+        let mut first: HashMap<Vec<(String, String)>, String> = HashMap::new();
+        let mut renamed: HashMap<String, String> = HashMap::new();
+        for item in &program.items {
+            if let Item::Neuron(def) = item {
+                let name = &def.name.name;
+                match first.get(&params(def)) {
+                    Some(kept) => {
+                        renamed.insert(name.clone(), kept.clone());
+                    }
+                    None => {
+                        first.insert(params(def), name.clone());
+                    }
+                }
+            }
+        }
+        if renamed.is_empty() {
+            return false;
+        }
+        program.items.retain(
+            |item| !matches!(item, Item::Neuron(def) if renamed.contains_key(&def.name.name)),
+        );
+        struct Rename<'a>(&'a HashMap<String, String>);
+
+        impl VisitMut for Rename<'_> {
+            fn visit_layer_mut(&mut self, def: &mut LayerDef) {
+                if let Some(kept) = self.0.get(&def.neuron.name) {
+                    def.neuron.name = kept.clone();
+                }
+            }
+        }
+
+        Rename(&renamed).visit_program_mut(program);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::cvir_json;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn standard_pipeline_folds_prunes_and_merges() {
+        let src = r#"
+neuron A { tau_m = 20 ms, v_th = 1.0 }
+neuron B { v_th = 1.0, tau_m = 0.02 s }
+neuron Unused { tau_m = 5 ms }
+layer In[4] : A
+layer Out[2] : B
+layer Empty[0] : A
+layer Idle[3] : A
+connect In -> Out { w = 0.5, d = 1 ms }
+connect In -> Empty { w = Normal(0.5, 0.1) }
+connect lesion: Out -> In { w = 0.2 }
+disconnect lesion
+stimulus In = Poisson(rate=0.2 kHz)
+run for 10 ms
+"#;
+        let mut program = parse_program(src).expect("parse");
+        let changed = optimize(&mut program);
+        assert_eq!(changed, ["fold-quantities", "dead-code", "merge-neurons"]);
+        let expected = parse_program(
+            r#"
+neuron A { tau_m = 20000000 ns, v_th = 1.0 }
+layer In[4] : A
+layer Out[2] : A
+connect In -> Out { w = 0.5, d = 1000000 ns }
+stimulus In = Poisson(rate=200 Hz)
+run for 10000000 ns
+"#,
+        )
+        .expect("parse");
+        assert_eq!(cvir_json(&program), cvir_json(&expected));
+        assert!(optimize(&mut program).is_empty());
+    }
+
+    #[test]
+    fn keeps_what_can_spike_or_draw_random_numbers() {
+        let src = r#"
+neuron Eager { v_th = 0.0 }
+neuron LIF { tau_m = 20 ms }
+layer A[2] : LIF
+layer Hot[2] : Eager
+layer Watched[2] : LIF
+connect lesion: A -> A { w = Uniform(0.1, 0.2) }
+disconnect lesion
+run for 1 ns
+assert spikes(Watched) == 0
+"#;
+        let mut program = parse_program(src).expect("parse");
+        let mut manager = PassManager::new();
+        manager.register(DeadCode);
+        assert_eq!(manager.pass_names(), ["dead-code"]);
+        assert!(manager.run(&mut program).is_empty());
+    }
+
+    #[test]
+    fn keeps_neurons_and_layers_of_networks() {
+        let src = r#"
+neuron A { tau_m = 20 ms }
+neuron B { tau_m = 20 ms }
+neuron Unused { tau_m = 5 ms }
+run for 1 ms
+network M { layer Idle[2] : A }
+network N { layer Idle[2] : B }
+"#;
+        let mut program = parse_program(src).expect("parse");
+        let mut manager = PassManager::new();
+        manager.register(DeadCode).register(MergeNeurons);
+        assert_eq!(manager.run(&mut program), ["dead-code", "merge-neurons"]);
+        let expected = parse_program(
+            "neuron A { tau_m = 20 ms }\n\
+             run for 1 ms\n\
+             network M { layer Idle[2] : A }\n\
+             network N { layer Idle[2] : A }\n",
+        )
+        .expect("parse");
+        assert_eq!(cvir_json(&program), cvir_json(&expected));
+    }
+}
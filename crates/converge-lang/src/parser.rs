@@ -1,25 +1,245 @@
 use crate::ast::*;
+use crate::deprecated::{self, DeprecatedUse};
 use crate::diagnostic::{Diagnostic, Span};
-use crate::lexer::{Token, TokenKind, lex};
+use crate::lexer::{Env, Token, TokenKind, lex, lex_recovering, lex_with_env, no_env};
 
+/// Parses `src` without access to the environment: `${NAME}` references
+/// need a default and `seed from_env(...)` fails. Local tools opt in with
+/// [`parse_program_with_env`] and [`crate::lexer::process_env`].
 pub fn parse_program(src: &str) -> Result<Program, Diagnostic> {
-    let tokens = lex(src)?;
-    let mut p = Parser::new(&tokens);
+    parse_program_with(src, &[])
+}
+
+/// [`parse_program`] with some `params` values replaced, as by
+/// `converge sim --set name=value`. Every name must be a declared parameter.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "parse", skip_all, fields(bytes = src.len()))
+)]
+pub fn parse_program_with(src: &str, overrides: &[(String, Expr)]) -> Result<Program, Diagnostic> {
+    parse_program_with_env(src, overrides, &no_env)
+}
+
+/// [`parse_program_with`] taking `${NAME}` and `from_env` variables from
+/// `env`.
+pub fn parse_program_with_env(
+    src: &str,
+    overrides: &[(String, Expr)],
+    env: Env<'_>,
+) -> Result<Program, Diagnostic> {
+    let tokens = lex_with_env(src, env)?;
+    let mut p = Parser::new(&tokens, env);
+    let version = p.parse_version_pragma()?;
+    if p.peek_params() {
+        p.parse_params(overrides)?;
+    }
+    if let Some((name, _)) = overrides
+        .iter()
+        .find(|(name, _)| !p.params.iter().any(|a| &a.key.name == name))
+    {
+        return Err(Diagnostic::new(format!("unknown parameter `{name}`")));
+    }
     let mut items = Vec::new();
     while !p.is_eof() {
         items.push(p.parse_item()?);
     }
-    Ok(Program::new(items))
+    #[cfg(feature = "tracing")]
+    tracing::debug!(tokens = tokens.len(), items = items.len(), "parsed program");
+    Ok(Program {
+        version,
+        deprecated: p.deprecated,
+        params: p.params,
+        ..Program::new(items)
+    })
+}
+
+/// [`parse_program`], reporting every problem it can find instead of the
+/// first, in source order: all lexical ones and the first syntax error in
+/// each item. After an error it skips to the next line that starts with an
+/// item keyword, a doc comment or an attribute, and goes on from there.
+/// Syntax errors on a line with a lexical problem are left out, as they
+/// usually follow from it.
+pub fn parse_program_recovering(src: &str) -> Result<Program, Vec<Diagnostic>> {
+    parse_program_recovering_with_env(src, &no_env)
+}
+
+/// [`parse_program_recovering`] taking `${NAME}` variables from `env`.
+pub fn parse_program_recovering_with_env(
+    src: &str,
+    env: Env<'_>,
+) -> Result<Program, Vec<Diagnostic>> {
+    let (tokens, lexical) = lex_recovering(src, env);
+    let mut p = Parser::new(&tokens, env);
+    let mut syntax = Vec::new();
+    let version = p.parse_version_pragma().unwrap_or_else(|diag| {
+        syntax.push(diag);
+        p.skip_to_item(src);
+        None
+    });
+    if p.peek_params()
+        && let Err(diag) = p.parse_params(&[])
+    {
+        syntax.push(diag);
+        p.skip_to_item(src);
+    }
+    let mut items = Vec::new();
+    while !p.is_eof() {
+        match p.parse_item() {
+            Ok(item) => items.push(item),
+            Err(diag) => {
+                syntax.push(diag);
+                p.skip_to_item(src);
+            }
+        }
+    }
+    let line = |offset: usize| src[..offset.min(src.len())].matches('\n').count();
+    let follows_lexical = |diag: &Diagnostic| {
+        let Some(at) = &diag.span else {
+            return false;
+        };
+        lexical
+            .iter()
+            .filter_map(|l| l.span.as_ref())
+            .any(|l| line(l.start) == line(at.start) || (l.start..=l.end).contains(&at.start))
+    };
+    syntax.retain(|diag| !follows_lexical(diag));
+    if lexical.is_empty() && syntax.is_empty() {
+        return Ok(Program {
+            version,
+            deprecated: p.deprecated,
+            params: p.params,
+            ..Program::new(items)
+        });
+    }
+    let mut diags = lexical;
+    diags.extend(syntax);
+    diags.sort_by_key(|diag| diag.span.as_ref().map_or(usize::MAX, |span| span.start));
+    Err(diags)
+}
+
+/// A parameter value as given on the command line: `50Hz`, `0.4`,
+/// `Normal(0.5, 0.1)`.
+pub fn parse_param_value(text: &str) -> Result<Expr, Diagnostic> {
+    let tokens = lex(text)?;
+    let mut p = Parser::new(&tokens, &no_env);
+    let value = p.parse_expr()?;
+    match p.peek() {
+        Some(t) => Err(
+            Diagnostic::new(format!("unexpected text after the value in `{text}`"))
+                .with_span(t.span.clone()),
+        ),
+        None => Ok(value),
+    }
+}
+
+/// A whole number in decimal or, after `0x`, hexadecimal.
+fn parse_integer(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
 }
 
+/// The words an item can start with.
+const ITEM_KEYWORDS: &[&str] = &[
+    "neuron",
+    "layer",
+    "connect",
+    "stimulus",
+    "run",
+    "seed",
+    "assert",
+    "disconnect",
+    "reward",
+    "modulator",
+    "experiment",
+    "readout",
+    "network",
+];
+
 struct Parser<'a> {
     tokens: &'a [Token],
     i: usize,
+    /// Where `seed from_env(...)` looks up its variable.
+    env: Env<'a>,
+    deprecated: Vec<DeprecatedUse>,
+    /// Parameters declared so far, which expressions after them refer to
+    /// by name.
+    params: Vec<Assign>,
 }
 
 impl<'a> Parser<'a> {
-    fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens, i: 0 }
+    fn new(tokens: &'a [Token], env: Env<'a>) -> Self {
+        Self {
+            tokens,
+            i: 0,
+            env,
+            deprecated: Vec::new(),
+            params: Vec::new(),
+        }
+    }
+
+    fn peek_params(&self) -> bool {
+        self.peek_word("params")
+            && matches!(
+                self.tokens.get(self.i + 1).map(|t| &t.kind),
+                Some(TokenKind::LBrace)
+            )
+    }
+
+    /// `params { name = value, ... }`. A value in `overrides` replaces the
+    /// written one before any later parameter can refer to it.
+    fn parse_params(&mut self, overrides: &[(String, Expr)]) -> Result<(), Diagnostic> {
+        let span = self.bump().unwrap().span.clone();
+        self.bump();
+        loop {
+            let Some(t) = self.peek() else {
+                return Err(
+                    Diagnostic::new("expected `}` to close the `params` block").with_span(span)
+                );
+            };
+            match &t.kind {
+                TokenKind::RBrace => {
+                    self.bump();
+                    return Ok(());
+                }
+                TokenKind::Ident(_) => {
+                    let key = self.parse_ident("parameter name")?;
+                    self.expect(|k| matches!(k, TokenKind::Eq), "`=`")?;
+                    let mut value = self.parse_expr()?;
+                    let span = self.span_from(key.span.start);
+                    if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Comma)) {
+                        self.bump();
+                    }
+                    if self.params.iter().any(|a| a.key.name == key.name) {
+                        return Err(
+                            Diagnostic::new(format!("duplicate parameter `{}`", key.name))
+                                .with_span(key.span.clone()),
+                        );
+                    }
+                    if let Some((_, new)) = overrides.iter().find(|(name, _)| *name == key.name) {
+                        value = new.clone();
+                    }
+                    self.params.push(Assign { key, value, span });
+                }
+                _ => {
+                    return Err(Diagnostic::new("unexpected token in `params` block")
+                        .with_span(t.span.clone()));
+                }
+            }
+        }
+    }
+
+    /// The value of parameter `name`, standing in for a reference at `at`.
+    fn param(&self, name: &str, at: &Span) -> Option<Expr> {
+        let param = self.params.iter().find(|a| a.key.name == name)?;
+        Some(match &param.value {
+            Expr::Number(q) => Expr::Number(Quantity {
+                span: at.clone(),
+                ..q.clone()
+            }),
+            value => value.clone(),
+        })
     }
 
     fn is_eof(&self) -> bool {
@@ -30,6 +250,39 @@ impl<'a> Parser<'a> {
         self.tokens.get(self.i)
     }
 
+    /// Where the last token taken ends.
+    fn prev_end(&self) -> usize {
+        self.i
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+            .map_or(0, |t| t.span.end)
+    }
+
+    /// From `start` to the end of the last token taken.
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.prev_end())
+    }
+
+    /// Skips past the token an error was found at, then to the first token
+    /// that starts a line and could start an item.
+    fn skip_to_item(&mut self, src: &str) {
+        self.bump();
+        while let Some(t) = self.peek() {
+            let starts_item = match &t.kind {
+                TokenKind::Ident(word) => {
+                    ITEM_KEYWORDS.contains(&word.as_str()) || word == "record" || word == "probe"
+                }
+                TokenKind::DocComment(_) | TokenKind::At => true,
+                _ => false,
+            };
+            let line_start = src[..t.span.start].rfind('\n').map_or(0, |i| i + 1);
+            if starts_item && src[line_start..t.span.start].trim().is_empty() {
+                return;
+            }
+            self.bump();
+        }
+    }
+
     fn bump(&mut self) -> Option<&'a Token> {
         let t = self.tokens.get(self.i);
         if t.is_some() {
@@ -53,362 +306,2673 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_item(&mut self) -> Result<Item, Diagnostic> {
+    /// `converge "0.2"`, only as the first thing in a file. `converge` stays
+    /// an ordinary identifier everywhere else.
+    fn parse_version_pragma(&mut self) -> Result<Option<VersionPragma>, Diagnostic> {
         match self.peek().map(|t| &t.kind) {
-            Some(TokenKind::KwNeuron) => Ok(Item::Neuron(self.parse_neuron_def()?)),
-            Some(TokenKind::KwLayer) => Ok(Item::Layer(self.parse_layer_def()?)),
-            Some(TokenKind::KwConnect) => Ok(Item::Connect(self.parse_connect_def()?)),
-            Some(TokenKind::KwStimulus) => Ok(Item::Stimulus(self.parse_stimulus_def()?)),
-            Some(TokenKind::KwRun) => Ok(Item::Run(self.parse_run_stmt()?)),
-            Some(TokenKind::KwSeed) => Ok(Item::Seed(self.parse_seed_stmt()?)),
-            Some(_) => {
+            Some(TokenKind::Ident(word)) if word == "converge" => {}
+            _ => return Ok(None),
+        }
+        let start = self.bump().unwrap().span.start;
+        let t = self.expect(
+            |k| matches!(k, TokenKind::String(_)),
+            "a language version string",
+        )?;
+        let TokenKind::String(text) = &t.kind else {
+            unreachable!()
+        };
+        let version = LanguageVersion::parse(text).ok_or_else(|| {
+            Diagnostic::new(format!(
+                "invalid language version `{text}` (expected `<major>.<minor>`)"
+            ))
+            .with_span(t.span.clone())
+        })?;
+        Ok(Some(VersionPragma {
+            version,
+            span: Span::new(start, t.span.end),
+        }))
+    }
+
+    /// An item with the `///` lines and `@name(...)` attributes before it,
+    /// which only `neuron`, `layer` and `connect` items take.
+    fn parse_item(&mut self) -> Result<Item, Diagnostic> {
+        let mut lines = Vec::new();
+        let mut attributes = Vec::new();
+        let (mut doc_span, mut attr_span): (Option<Span>, Option<Span>) = (None, None);
+        let cover = |span: &Option<Span>, start: usize, end: usize| {
+            Some(Span::new(span.as_ref().map_or(start, |s| s.start), end))
+        };
+        while let Some(t) = self.peek() {
+            match &t.kind {
+                TokenKind::DocComment(line) => {
+                    lines.push(line.as_str());
+                    doc_span = cover(&doc_span, t.span.start, t.span.end);
+                    self.bump();
+                }
+                TokenKind::At => {
+                    self.bump();
+                    attributes.push(self.parse_attribute()?);
+                    let end = self.tokens[self.i - 1].span.end;
+                    attr_span = cover(&attr_span, t.span.start, end);
+                }
+                _ => break,
+            }
+        }
+        if doc_span.is_none() && attr_span.is_none() {
+            return self.parse_bare_item();
+        }
+        if matches!(self.peek().map(|t| &t.kind), None | Some(TokenKind::RBrace)) {
+            let (what, span) = match (&attr_span, &doc_span) {
+                (Some(span), _) => ("attributes", span),
+                (None, Some(span)) => ("doc comment", span),
+                (None, None) => unreachable!(),
+            };
+            return Err(
+                Diagnostic::new(format!("expected an item after the {what}"))
+                    .with_span(span.clone()),
+            );
+        }
+        let doc = (!lines.is_empty()).then(|| lines.join("\n"));
+        let mut item = self.parse_bare_item()?;
+        match &mut item {
+            Item::Neuron(def) => (def.doc, def.attributes) = (doc, attributes),
+            Item::Layer(def) => (def.doc, def.attributes) = (doc, attributes),
+            Item::Connect(def) => (def.doc, def.attributes) = (doc, attributes),
+            _ => {
+                return Err(match (attr_span, doc_span) {
+                    (Some(span), _) => Diagnostic::new(
+                        "attributes can only be put on `neuron`, `layer` and `connect` items",
+                    )
+                    .with_span(span),
+                    (None, span) => Diagnostic::new(
+                        "doc comments can only document `neuron`, `layer` and `connect` items",
+                    )
+                    .with_span(span.expect("a doc comment")),
+                });
+            }
+        }
+        Ok(item)
+    }
+
+    /// `name` or `name(args...)` after an `@`.
+    fn parse_attribute(&mut self) -> Result<Call, Diagnostic> {
+        let name = self.parse_ident("attribute name")?;
+        if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::LParen)) {
+            self.parse_call_after_name(name)
+        } else {
+            Ok(Call {
+                span: name.span.clone(),
+                name,
+                args: Vec::new(),
+            })
+        }
+    }
+
+    /// Every item starts with one of a few words. They are only keywords
+    /// here and in the spots their own syntax puts them, so they still work
+    /// as names and keys: `layer rate[4]`, `{ step = 2 }`, `modulator = DA`.
+    fn parse_bare_item(&mut self) -> Result<Item, Diagnostic> {
+        let word = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Ident(word)) => word.as_str(),
+            _ => "",
+        };
+        match word {
+            "neuron" => Ok(Item::Neuron(self.parse_neuron_def()?)),
+            "layer" => Ok(Item::Layer(self.parse_layer_def()?)),
+            "connect" => Ok(Item::Connect(self.parse_connect_def()?)),
+            "stimulus" => Ok(Item::Stimulus(self.parse_stimulus_def()?)),
+            "run" => Ok(Item::Run(self.parse_run_stmt()?)),
+            "seed" => Ok(Item::Seed(self.parse_seed_stmt()?)),
+            "assert" => Ok(Item::Assert(self.parse_assert_stmt()?)),
+            "disconnect" => Ok(Item::Disconnect(self.parse_disconnect_stmt()?)),
+            "reward" => Ok(Item::Reward(self.parse_reward_stmt()?)),
+            "record" => Ok(Item::Record(self.parse_record_stmt()?)),
+            "probe" => Ok(Item::Probe(self.parse_probe_def()?)),
+            "modulator" => Ok(Item::Modulator(self.parse_modulator_def()?)),
+            "experiment" => {
+                // `run` or `report` first, other than as a key, makes it a
+                // sweep; anything else is a training experiment's settings.
+                let sweep = matches!(
+                    self.tokens.get(self.i + 2).map(|t| &t.kind),
+                    Some(TokenKind::Ident(word)) if word == "run" || word == "report"
+                ) && !matches!(
+                    self.tokens.get(self.i + 3).map(|t| &t.kind),
+                    Some(TokenKind::Eq)
+                );
+                if sweep {
+                    Ok(Item::Sweep(self.parse_sweep_def()?))
+                } else {
+                    Ok(Item::Experiment(self.parse_experiment_def()?))
+                }
+            }
+            "readout" => Ok(Item::Readout(self.parse_readout_def()?)),
+            "network" => Ok(Item::Network(self.parse_network_def()?)),
+            "params" if self.peek_params() => Err(Diagnostic::new(
+                "the `params` block must come before every item",
+            )
+            .with_span(self.peek().unwrap().span.clone())),
+            "converge"
+                if matches!(
+                    self.tokens.get(self.i + 1).map(|t| &t.kind),
+                    Some(TokenKind::String(_))
+                ) =>
+            {
                 let t = self.bump().unwrap();
-                Err(Diagnostic::new("unexpected token at top-level").with_span(t.span.clone()))
+                Err(
+                    Diagnostic::new("the `converge` version pragma must come before every item")
+                        .with_span(t.span.clone()),
+                )
             }
-            None => Err(Diagnostic::new("unexpected end of input")),
+            _ => match self.peek() {
+                Some(_) => {
+                    let t = self.bump().unwrap();
+                    Err(Diagnostic::new("unexpected token at top-level").with_span(t.span.clone()))
+                }
+                None => Err(Diagnostic::new("unexpected end of input")),
+            },
         }
     }
 
     fn parse_neuron_def(&mut self) -> Result<NeuronDef, Diagnostic> {
-        self.expect(|k| matches!(k, TokenKind::KwNeuron), "`neuron`")?;
+        let start = self.expect_word("neuron")?.span.start;
         let name = self.parse_ident("neuron name")?;
         self.expect(|k| matches!(k, TokenKind::LBrace), "`{`")?;
-        let body = self.parse_assign_block()?;
-        Ok(NeuronDef { name, body })
-    }
-
-    fn parse_layer_def(&mut self) -> Result<LayerDef, Diagnostic> {
-        self.expect(|k| matches!(k, TokenKind::KwLayer), "`layer`")?;
-        let name = self.parse_ident("layer name")?;
-        self.expect(|k| matches!(k, TokenKind::LBracket), "`[`")?;
-        let size = self.parse_u64("layer size")?;
-        self.expect(|k| matches!(k, TokenKind::RBracket), "`]`")?;
-        self.expect(|k| matches!(k, TokenKind::Colon), "`:`")?;
-        let neuron = self.parse_ident("neuron type")?;
-        Ok(LayerDef { name, size, neuron })
+        let mut body = self.parse_assign_block()?;
+        self.deprecated.extend(deprecated::resolve_keys(
+            &mut body,
+            deprecated::Body::Neuron,
+        ));
+        Ok(NeuronDef {
+            name,
+            body,
+            doc: None,
+            attributes: Vec::new(),
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_connect_def(&mut self) -> Result<ConnectDef, Diagnostic> {
-        self.expect(|k| matches!(k, TokenKind::KwConnect), "`connect`")?;
-        let src = self.parse_ident("source layer")?;
-        self.expect(|k| matches!(k, TokenKind::Arrow), "`->`")?;
-        let dst = self.parse_ident("destination layer")?;
+    fn parse_modulator_def(&mut self) -> Result<ModulatorDef, Diagnostic> {
+        let start = self.bump().unwrap().span.start;
+        let name = self.parse_ident("modulator name")?;
         self.expect(|k| matches!(k, TokenKind::LBrace), "`{`")?;
         let body = self.parse_assign_block()?;
-        Ok(ConnectDef { src, dst, body })
-    }
-
-    fn parse_run_stmt(&mut self) -> Result<RunStmt, Diagnostic> {
-        self.expect(|k| matches!(k, TokenKind::KwRun), "`run`")?;
-        self.expect(|k| matches!(k, TokenKind::KwFor), "`for`")?;
-        let duration = self.parse_quantity("duration")?;
-        let step = if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::KwStep)) {
-            self.bump();
-            Some(self.parse_quantity("step")?)
-        } else {
-            None
-        };
-        Ok(RunStmt { duration, step })
-    }
-
-    fn parse_seed_stmt(&mut self) -> Result<SeedStmt, Diagnostic> {
-        let kw = self.expect(|k| matches!(k, TokenKind::KwSeed), "`seed`")?;
-        let value = self.parse_u64("seed value")?;
-        Ok(SeedStmt {
-            value,
-            span: kw.span.clone(),
+        Ok(ModulatorDef {
+            name,
+            body,
+            span: self.span_from(start),
         })
     }
 
-    fn parse_stimulus_def(&mut self) -> Result<StimulusDef, Diagnostic> {
-        self.expect(|k| matches!(k, TokenKind::KwStimulus), "`stimulus`")?;
-        let layer = self.parse_ident("layer name")?;
-        self.expect(|k| matches!(k, TokenKind::Eq), "`=`")?;
-        let expr = self.parse_expr()?;
-        let call = match expr {
-            Expr::Call(call) => call,
-            _ => {
-                return Err(
-                    Diagnostic::new("expected stimulus model call").with_span(layer.span.clone())
-                );
-            }
-        };
-        let model = match call.name.name.as_str() {
-            "Poisson" => {
-                let mut rate = None;
-                for arg in call.args {
-                    if let CallArg::Named { name, value } = arg
-                        && name.name == "rate"
-                    {
-                        match value {
-                            Expr::Number(q) => rate = Some(q),
-                            _ => {
-                                return Err(Diagnostic::new("rate must be a quantity")
-                                    .with_span(name.span.clone()));
-                            }
-                        }
-                    }
-                }
-                let rate = rate.ok_or_else(|| {
-                    Diagnostic::new("Poisson stimulus requires rate").with_span(layer.span.clone())
-                })?;
-                StimulusModel::Poisson { rate }
-            }
-            _ => {
-                return Err(
-                    Diagnostic::new("unknown stimulus model").with_span(call.name.span.clone())
-                );
-            }
-        };
-        Ok(StimulusDef { layer, model })
+    fn parse_experiment_def(&mut self) -> Result<ExperimentDef, Diagnostic> {
+        let span = self.bump().unwrap().span.clone();
+        self.expect(|k| matches!(k, TokenKind::LBrace), "`{`")?;
+        let body = self.parse_assign_block()?;
+        Ok(ExperimentDef { body, span })
     }
 
-    fn parse_assign_block(&mut self) -> Result<Vec<Assign>, Diagnostic> {
-        let mut assigns = Vec::new();
-        while let Some(t) = self.peek() {
+    fn parse_sweep_def(&mut self) -> Result<SweepDef, Diagnostic> {
+        let span = self.bump().unwrap().span.clone();
+        self.expect(|k| matches!(k, TokenKind::LBrace), "`{`")?;
+        let mut runs = Vec::new();
+        let mut report = Vec::new();
+        loop {
+            let Some(t) = self.peek() else {
+                return Err(Diagnostic::new("expected `}` to close the experiment").with_span(span));
+            };
             match &t.kind {
                 TokenKind::RBrace => {
                     self.bump();
                     break;
                 }
-                TokenKind::Ident(_) | TokenKind::KwRate => {
-                    let key = self.parse_ident("field name")?;
-                    self.expect(|k| matches!(k, TokenKind::Eq), "`=`")?;
-                    let value = self.parse_expr()?;
-                    // Optional commas to support single-line blocks.
-                    if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Comma)) {
+                TokenKind::Semi => {
+                    self.bump();
+                }
+                TokenKind::Ident(word) if word == "run" => runs.push(self.parse_sweep_run()?),
+                TokenKind::Ident(word) if word == "report" => {
+                    self.bump();
+                    loop {
+                        report.push(self.parse_report_metric()?);
+                        if !matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Comma)) {
+                            break;
+                        }
                         self.bump();
                     }
-                    assigns.push(Assign { key, value });
                 }
                 _ => {
-                    let t = self.bump().unwrap();
-                    return Err(
-                        Diagnostic::new("unexpected token in block").with_span(t.span.clone())
-                    );
+                    return Err(Diagnostic::new("expected `run` or `report` in experiment")
+                        .with_span(t.span.clone()));
                 }
             }
         }
-        Ok(assigns)
+        Ok(SweepDef { runs, report, span })
     }
 
-    fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
-        let t = self
-            .peek()
-            .ok_or_else(|| Diagnostic::new("expected expression, found end of input"))?;
-        match &t.kind {
-            TokenKind::Number(_) => Ok(Expr::Number(self.parse_quantity("number")?)),
-            TokenKind::String(_) => {
-                let s = match self.bump().unwrap().kind.clone() {
-                    TokenKind::String(s) => s,
-                    _ => unreachable!(),
-                };
-                Ok(Expr::String(s))
-            }
-            TokenKind::Ident(_) | TokenKind::KwRate => {
-                let ident = self.parse_ident("identifier")?;
-                if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::LParen)) {
-                    Ok(Expr::Call(self.parse_call_after_name(ident)?))
+    fn parse_sweep_run(&mut self) -> Result<SweepRun, Diagnostic> {
+        let span = self.bump().unwrap().span.clone();
+        let network = if self.peek_word("with") {
+            None
+        } else {
+            Some(self.parse_ident("network name")?)
+        };
+        self.expect(
+            |k| matches!(k, TokenKind::Ident(s) if s == "with"),
+            "`with`",
+        )?;
+        self.expect_word("seed")?;
+        let first_seed = self.parse_u64("first seed")?;
+        self.expect(|k| matches!(k, TokenKind::DotDot), "`..`")?;
+        let last_seed = self.parse_u64("last seed")?;
+        Ok(SweepRun {
+            network,
+            first_seed,
+            last_seed,
+            span,
+        })
+    }
+
+    fn parse_report_metric(&mut self) -> Result<ReportMetric, Diagnostic> {
+        let name = self.parse_ident("report metric")?;
+        let metric = match name.name.as_str() {
+            "total_spikes" => ReportMetric::TotalSpikes,
+            "mean_rate" => ReportMetric::MeanRate,
+            "spikes" | "rate" => {
+                self.expect(|k| matches!(k, TokenKind::LParen), "`(`")?;
+                let layer = self.parse_ident("layer name")?;
+                self.expect(|k| matches!(k, TokenKind::RParen), "`)`")?;
+                ReportMetric::Layer(if name.name == "spikes" {
+                    Metric::Spikes { layer }
                 } else {
-                    Ok(Expr::Ident(ident))
-                }
+                    Metric::Rate { layer }
+                })
             }
-            _ => Err(Diagnostic::new("unexpected token in expression").with_span(t.span.clone())),
-        }
+            other => {
+                return Err(Diagnostic::new(format!(
+                    "unknown report metric `{other}` (expected `total_spikes`, `mean_rate`, \
+                     `spikes(..)` or `rate(..)`)"
+                ))
+                .with_span(name.span.clone()));
+            }
+        };
+        Ok(metric)
     }
 
-    fn parse_call_after_name(&mut self, name: Ident) -> Result<Call, Diagnostic> {
-        self.expect(|k| matches!(k, TokenKind::LParen), "`(`")?;
-        let mut args = Vec::new();
-        while let Some(t) = self.peek() {
-            match &t.kind {
-                TokenKind::RParen => {
+    fn parse_network_def(&mut self) -> Result<NetworkDef, Diagnostic> {
+        let span = self.bump().unwrap().span.clone();
+        let name = self.parse_ident("network name")?;
+        self.expect(|k| matches!(k, TokenKind::LBrace), "`{`")?;
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                Some(t) if t.kind == TokenKind::RBrace => {
                     self.bump();
                     break;
                 }
-                TokenKind::Comma => {
-                    self.bump();
-                }
-                TokenKind::Ident(_) | TokenKind::KwRate => {
-                    // Lookahead for named args: ident '=' ...
-                    let save = self.i;
-                    let name_tok = self.bump().unwrap().clone();
-                    if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Eq)) {
-                        self.bump();
-                        let value = self.parse_expr()?;
-                        let arg_name = match name_tok.kind {
-                            TokenKind::Ident(s) => Ident::new(s, name_tok.span.clone()),
-                            TokenKind::KwRate => Ident::new("rate", name_tok.span.clone()),
-                            _ => unreachable!(),
-                        };
-                        args.push(CallArg::Named {
-                            name: arg_name,
-                            value,
-                        });
-                    } else {
-                        // Not named; rewind and parse as expr.
-                        self.i = save;
-                        let e = self.parse_expr()?;
-                        args.push(CallArg::Positional(e));
-                    }
+                Some(t) if matches!(&t.kind, TokenKind::Ident(word) if word == "network") => {
+                    return Err(
+                        Diagnostic::new("networks cannot be nested").with_span(t.span.clone())
+                    );
                 }
-                _ => {
-                    let e = self.parse_expr()?;
-                    args.push(CallArg::Positional(e));
+                Some(_) => items.push(self.parse_item()?),
+                None => {
+                    return Err(Diagnostic::new(format!(
+                        "expected `}}` to close network `{}`",
+                        name.name
+                    ))
+                    .with_span(name.span.clone()));
                 }
             }
         }
-        Ok(Call { name, args })
+        Ok(NetworkDef { name, items, span })
     }
 
-    fn parse_ident(&mut self, what: &'static str) -> Result<Ident, Diagnostic> {
-        let t = self.expect(
-            |k| matches!(k, TokenKind::Ident(_) | TokenKind::KwRate),
-            what,
-        )?;
-        match &t.kind {
-            TokenKind::Ident(s) => Ok(Ident::new(s.clone(), t.span.clone())),
-            TokenKind::KwRate => Ok(Ident::new("rate", t.span.clone())),
-            _ => unreachable!(),
+    fn parse_readout_def(&mut self) -> Result<ReadoutDef, Diagnostic> {
+        let span = self.bump().unwrap().span.clone();
+        let layer = self.parse_ident("readout layer")?;
+        if !self.peek_word("labels") {
+            let span = self.peek().map_or(layer.span.clone(), |t| t.span.clone());
+            return Err(Diagnostic::new("expected `labels` after readout layer").with_span(span));
         }
-    }
-
-    fn parse_u64(&mut self, what: &'static str) -> Result<u64, Diagnostic> {
-        let t = self.expect(|k| matches!(k, TokenKind::Number(_)), what)?;
-        let s = match &t.kind {
-            TokenKind::Number(s) => s.as_str(),
-            _ => unreachable!(),
+        self.bump();
+        let t = self.expect(|k| matches!(k, TokenKind::String(_)), "label file path")?;
+        let TokenKind::String(labels) = &t.kind else {
+            unreachable!()
         };
-        s.parse::<u64>().map_err(|_| {
-            Diagnostic::new(format!("invalid integer for {what}")).with_span(t.span.clone())
+        let labels = labels.clone();
+        let rule = if self.peek_word("rule") {
+            self.bump();
+            self.expect(|k| matches!(k, TokenKind::Eq), "`=`")?;
+            Some(self.parse_ident("readout rule")?)
+        } else {
+            None
+        };
+        Ok(ReadoutDef {
+            layer,
+            labels,
+            rule,
+            span,
         })
     }
 
-    fn parse_quantity(&mut self, what: &'static str) -> Result<Quantity, Diagnostic> {
-        let t = self.expect(|k| matches!(k, TokenKind::Number(_)), what)?;
-        let num_str = match &t.kind {
-            TokenKind::Number(s) => s.as_str(),
+    fn parse_layer_def(&mut self) -> Result<LayerDef, Diagnostic> {
+        let start = self.expect_word("layer")?.span.start;
+        let name = self.parse_ident("layer name")?;
+        self.expect(|k| matches!(k, TokenKind::LBracket), "`[`")?;
+        let size_start = self.peek().map_or(self.prev_end(), |t| t.span.start);
+        let size = self.parse_u64("layer size")?;
+        let size_span = self.span_from(size_start);
+        self.expect(|k| matches!(k, TokenKind::RBracket), "`]`")?;
+        self.expect(|k| matches!(k, TokenKind::Colon), "`:`")?;
+        let neuron = self.parse_ident("neuron type")?;
+        Ok(LayerDef {
+            name,
+            size,
+            size_span,
+            neuron,
+            doc: None,
+            attributes: Vec::new(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_connect_def(&mut self) -> Result<ConnectDef, Diagnostic> {
+        let start = self.expect_word("connect")?.span.start;
+        let name = if matches!(
+            self.tokens.get(self.i + 1).map(|t| &t.kind),
+            Some(TokenKind::Colon)
+        ) {
+            let name = self.parse_ident("connection name")?;
+            self.bump();
+            Some(name)
+        } else {
+            None
+        };
+        let src = self.parse_ident("source layer")?;
+        let arrow = self.expect(
+            |k| matches!(k, TokenKind::Arrow | TokenKind::BiArrow),
+            "`->` or `<->`",
+        )?;
+        let dst = self.parse_ident("destination layer")?;
+        let kind = match (
+            arrow.kind == TokenKind::BiArrow,
+            self.peek_word("electrical"),
+        ) {
+            (false, false) => ConnectKind::Chemical,
+            (true, true) => {
+                self.bump();
+                ConnectKind::Electrical
+            }
+            (false, true) => {
+                return Err(
+                    Diagnostic::new("electrical connections are written `A <-> B`")
+                        .with_span(arrow.span.clone()),
+                );
+            }
+            (true, false) => {
+                return Err(Diagnostic::new("expected `electrical` after `A <-> B`")
+                    .with_span(arrow.span.clone()));
+            }
+        };
+        self.expect(|k| matches!(k, TokenKind::LBrace), "`{`")?;
+        let mut body = self.parse_assign_block()?;
+        if kind == ConnectKind::Chemical {
+            self.deprecated.extend(deprecated::resolve_keys(
+                &mut body,
+                deprecated::Body::Connection,
+            ));
+        }
+        Ok(ConnectDef {
+            name,
+            src,
+            dst,
+            kind,
+            body,
+            doc: None,
+            attributes: Vec::new(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_run_stmt(&mut self) -> Result<RunStmt, Diagnostic> {
+        let start = self.expect_word("run")?.span.start;
+        self.expect_word("for")?;
+        let duration = self.parse_quantity_until("duration", Some("step"))?;
+        let step = if self.peek_word("step") {
+            self.bump();
+            Some(self.parse_quantity("step")?)
+        } else {
+            None
+        };
+        Ok(RunStmt {
+            duration,
+            step,
+            span: self.span_from(start),
+        })
+    }
+
+    /// `seed <n>`, with `n` in decimal or `0x` hex, or `seed from_env("NAME")`
+    /// to read it from an environment variable when the program is parsed.
+    fn parse_seed_stmt(&mut self) -> Result<SeedStmt, Diagnostic> {
+        let kw = self.expect_word("seed")?;
+        let value = if self.peek_word("from_env") {
+            self.parse_seed_from_env()?
+        } else {
+            self.parse_u64("seed value")?
+        };
+        Ok(SeedStmt {
+            value,
+            span: kw.span.clone(),
+        })
+    }
+
+    fn parse_seed_from_env(&mut self) -> Result<u64, Diagnostic> {
+        let start = self.expect_word("from_env")?.span.start;
+        self.expect(|k| matches!(k, TokenKind::LParen), "`(`")?;
+        let name = self.expect(|k| matches!(k, TokenKind::String(_)), "variable name")?;
+        let TokenKind::String(name) = &name.kind else {
+            unreachable!()
+        };
+        self.expect(|k| matches!(k, TokenKind::RParen), "`)`")?;
+        let span = self.span_from(start);
+        let Some(text) = (self.env)(name) else {
+            return Err(Diagnostic::new(format!("`{name}` is not set")).with_span(span));
+        };
+        parse_integer(text.trim()).ok_or_else(|| {
+            Diagnostic::new(format!("`{name}` is `{text}`, not a seed")).with_span(span)
+        })
+    }
+
+    fn parse_disconnect_stmt(&mut self) -> Result<DisconnectStmt, Diagnostic> {
+        let kw = self.expect_word("disconnect")?;
+        let first = self.parse_ident("connection name or source layer")?;
+        let target = if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Arrow)) {
+            self.bump();
+            ConnectionRef::Layers {
+                src: first,
+                dst: self.parse_ident("destination layer")?,
+            }
+        } else {
+            ConnectionRef::Named(first)
+        };
+        let filter = if self.peek_word("where") {
+            self.bump();
+            Some(self.parse_filter_or()?)
+        } else {
+            None
+        };
+        Ok(DisconnectStmt {
+            target,
+            filter,
+            span: kw.span.clone(),
+        })
+    }
+
+    fn parse_reward_stmt(&mut self) -> Result<RewardStmt, Diagnostic> {
+        let kw = self.expect_word("reward")?;
+        let value = self.parse_quantity_until("reward value", Some("at"))?;
+        if !self.peek_word("at") {
+            let span = self.peek().map_or(value.span.clone(), |t| t.span.clone());
+            return Err(Diagnostic::new("expected `at` after reward value").with_span(span));
+        }
+        self.bump();
+        let at = self.parse_quantity("reward time")?;
+        Ok(RewardStmt {
+            value,
+            at,
+            span: kw.span.clone(),
+        })
+    }
+
+    fn parse_record_stmt(&mut self) -> Result<RecordStmt, Diagnostic> {
+        let kw = self.expect_word("record")?;
+        let what = self.parse_ident("what to record")?;
+        if what.name != "rate" {
+            return Err(Diagnostic::new(format!(
+                "unknown recording `{}` (expected `rate`)",
+                what.name
+            ))
+            .with_span(what.span.clone()));
+        }
+        self.expect_word("from")?;
+        let layer = self.parse_ident("layer name")?;
+        self.expect_word("window")?;
+        let window = self.parse_quantity("smoothing window")?;
+        Ok(RecordStmt {
+            layer,
+            window,
+            span: kw.span.clone(),
+        })
+    }
+
+    fn parse_probe_def(&mut self) -> Result<ProbeDef, Diagnostic> {
+        let kw = self.expect_word("probe")?;
+        let name = self.parse_ident("probe name")?;
+        self.expect(|k| matches!(k, TokenKind::Eq), "`=`")?;
+        let variable = self.parse_ident("probed variable")?;
+        let variable = ProbeVariable::from_name(&variable.name).ok_or_else(|| {
+            Diagnostic::new(format!(
+                "unknown probe variable `{}` (expected `v`)",
+                variable.name
+            ))
+            .with_span(variable.span.clone())
+        })?;
+        self.expect(|k| matches!(k, TokenKind::LParen), "`(`")?;
+        let layer = self.parse_ident("layer name")?;
+        let neurons = if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::LBracket)) {
+            self.bump();
+            let first = self.parse_u64("first neuron")?;
+            self.expect(|k| matches!(k, TokenKind::DotDot), "`..`")?;
+            let end = self.parse_u64("end neuron")?;
+            self.expect(|k| matches!(k, TokenKind::RBracket), "`]`")?;
+            Some((first, end))
+        } else {
+            None
+        };
+        self.expect(|k| matches!(k, TokenKind::RParen), "`)`")?;
+        let every = if self.peek_word("every") {
+            self.bump();
+            Some(self.parse_quantity("probe interval")?)
+        } else {
+            None
+        };
+        Ok(ProbeDef {
+            name,
+            variable,
+            layer,
+            neurons,
+            every,
+            span: kw.span.clone(),
+        })
+    }
+
+    /// `or` binds loosest, then `and`, then parenthesized groups and
+    /// comparisons.
+    fn parse_filter_or(&mut self) -> Result<SynapseFilter, Diagnostic> {
+        let mut lhs = self.parse_filter_and()?;
+        while self.peek_word("or") {
+            self.bump();
+            let rhs = self.parse_filter_and()?;
+            lhs = SynapseFilter::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_filter_and(&mut self) -> Result<SynapseFilter, Diagnostic> {
+        let mut lhs = self.parse_filter_atom()?;
+        while self.peek_word("and") {
+            self.bump();
+            let rhs = self.parse_filter_atom()?;
+            lhs = SynapseFilter::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_filter_atom(&mut self) -> Result<SynapseFilter, Diagnostic> {
+        if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::LParen)) {
+            self.bump();
+            let inner = self.parse_filter_or()?;
+            self.expect(|k| matches!(k, TokenKind::RParen), "`)`")?;
+            return Ok(inner);
+        }
+        let start = self.peek().map(|t| t.span.start);
+        let lhs = self.parse_synapse_operand()?;
+        let op = self.parse_compare_op("comparison operator")?;
+        let rhs = self.parse_synapse_operand()?;
+        let end = self.tokens[self.i - 1].span.end;
+        Ok(SynapseFilter::Compare {
+            lhs,
+            op,
+            rhs,
+            span: Span::new(start.unwrap_or(end), end),
+        })
+    }
+
+    fn parse_synapse_operand(&mut self) -> Result<SynapseOperand, Diagnostic> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Number(_)) => {
+                Ok(SynapseOperand::Index(self.parse_u64("neuron index")?))
+            }
+            _ => {
+                let id = self.parse_ident("`src`, `dst` or a neuron index")?;
+                match id.name.as_str() {
+                    "src" => Ok(SynapseOperand::Src),
+                    "dst" => Ok(SynapseOperand::Dst),
+                    _ => Err(Diagnostic::new(format!(
+                        "unknown synapse operand `{}` (expected `src` or `dst`)",
+                        id.name
+                    ))
+                    .with_span(id.span)),
+                }
+            }
+        }
+    }
+
+    fn peek_word(&self, word: &str) -> bool {
+        matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Ident(s)) if s == word)
+    }
+
+    /// Takes `word`, a keyword only where the caller expects it.
+    fn expect_word(&mut self, word: &'static str) -> Result<&'a Token, Diagnostic> {
+        match self.peek() {
+            Some(_) if self.peek_word(word) => Ok(self.bump().unwrap()),
+            Some(t) => Err(Diagnostic::new(format!("expected `{word}`")).with_span(t.span.clone())),
+            None => Err(Diagnostic::new(format!(
+                "expected `{word}`, found end of input"
+            ))),
+        }
+    }
+
+    fn parse_assert_stmt(&mut self) -> Result<AssertStmt, Diagnostic> {
+        let kw = self.expect_word("assert")?;
+        let metric_name = self.parse_ident("assertion metric")?;
+        self.expect(|k| matches!(k, TokenKind::LParen), "`(`")?;
+        let layer = self.parse_ident("layer name")?;
+        self.expect(|k| matches!(k, TokenKind::RParen), "`)`")?;
+        let metric = match metric_name.name.as_str() {
+            "spikes" => Metric::Spikes { layer },
+            "rate" => Metric::Rate { layer },
+            _ => {
+                return Err(Diagnostic::new(format!(
+                    "unknown assertion metric `{}`",
+                    metric_name.name
+                ))
+                .with_span(metric_name.span.clone()));
+            }
+        };
+
+        let cond = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Ident(s)) if s == "between" => {
+                self.bump();
+                // `and` separates the bounds, so it cannot double as a unit.
+                let low = self.parse_quantity_until("lower bound", Some("and"))?;
+                self.expect(|k| matches!(k, TokenKind::Ident(s) if s == "and"), "`and`")?;
+                let high = self.parse_quantity("upper bound")?;
+                AssertCond::Between { low, high }
+            }
+            _ => {
+                let op = self.parse_compare_op("comparison operator or `between`")?;
+                let value = self.parse_quantity("assertion value")?;
+                AssertCond::Compare { op, value }
+            }
+        };
+
+        let end = match &cond {
+            AssertCond::Compare { value, .. } => value.span.end,
+            AssertCond::Between { high, .. } => high.span.end,
+        };
+        Ok(AssertStmt {
+            metric,
+            cond,
+            span: Span::new(kw.span.start, end),
+        })
+    }
+
+    fn parse_stimulus_def(&mut self) -> Result<StimulusDef, Diagnostic> {
+        let start = self.expect_word("stimulus")?.span.start;
+        let layer = self.parse_ident("layer name")?;
+        self.expect(|k| matches!(k, TokenKind::Eq), "`=`")?;
+        let expr = self.parse_expr()?;
+        let call = match expr {
+            Expr::Call(call) => call,
+            _ => {
+                return Err(
+                    Diagnostic::new("expected stimulus model call").with_span(layer.span.clone())
+                );
+            }
+        };
+        let model = match call.name.name.as_str() {
+            "Poisson" => {
+                let (mut rate, mut correlation) = (None, None);
+                for arg in call.args {
+                    let CallArg::Named { name, value } = arg else {
+                        continue;
+                    };
+                    let slot = match name.name.as_str() {
+                        "rate" => &mut rate,
+                        "correlation" => &mut correlation,
+                        _ => continue,
+                    };
+                    match value {
+                        Expr::Number(q) => *slot = Some(q),
+                        _ => {
+                            return Err(Diagnostic::new(format!(
+                                "{} must be a quantity",
+                                name.name
+                            ))
+                            .with_span(name.span.clone()));
+                        }
+                    }
+                }
+                let rate = rate.ok_or_else(|| {
+                    Diagnostic::new("Poisson stimulus requires rate").with_span(layer.span.clone())
+                })?;
+                StimulusModel::Poisson { rate, correlation }
+            }
+            "Encode" => StimulusModel::Encode(parse_encoder(call)?),
+            "External" => StimulusModel::External(parse_external(call)?),
+            _ => {
+                return Err(
+                    Diagnostic::new("unknown stimulus model").with_span(call.name.span.clone())
+                );
+            }
+        };
+        let combine = if self.peek_word("combine") {
+            self.bump();
+            self.expect(|k| matches!(k, TokenKind::Eq), "`=`")?;
+            let name = self.parse_ident("combine mode")?;
+            Some(StimulusCombine::from_name(&name.name).ok_or_else(|| {
+                Diagnostic::new(format!(
+                    "unknown combine mode `{}` (expected `sum`, `replace` or `max`)",
+                    name.name
+                ))
+                .with_span(name.span.clone())
+            })?)
+        } else {
+            None
+        };
+        Ok(StimulusDef {
+            layer,
+            model,
+            combine,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_assign_block(&mut self) -> Result<Vec<Assign>, Diagnostic> {
+        let mut assigns = Vec::new();
+        while let Some(t) = self.peek() {
+            match &t.kind {
+                TokenKind::RBrace => {
+                    self.bump();
+                    break;
+                }
+                TokenKind::Ident(_) => {
+                    let key = self.parse_ident("field name")?;
+                    self.expect(|k| matches!(k, TokenKind::Eq), "`=`")?;
+                    let value = self.parse_expr()?;
+                    let span = self.span_from(key.span.start);
+                    // Optional commas to support single-line blocks.
+                    if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Comma)) {
+                        self.bump();
+                    }
+                    assigns.push(Assign { key, value, span });
+                }
+                _ => {
+                    let t = self.bump().unwrap();
+                    return Err(
+                        Diagnostic::new("unexpected token in block").with_span(t.span.clone())
+                    );
+                }
+            }
+        }
+        Ok(assigns)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let t = self
+            .peek()
+            .ok_or_else(|| Diagnostic::new("expected expression, found end of input"))?;
+        match &t.kind {
+            TokenKind::Number(_) => Ok(Expr::Number(self.parse_quantity("number")?)),
+            TokenKind::String(_) => {
+                let s = match self.bump().unwrap().kind.clone() {
+                    TokenKind::String(s) => s,
+                    _ => unreachable!(),
+                };
+                Ok(Expr::String(s))
+            }
+            TokenKind::Ident(_) => {
+                let ident = self.parse_ident("identifier")?;
+                if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::LParen)) {
+                    Ok(Expr::Call(self.parse_call_after_name(ident)?))
+                } else if let Some(value) = self.param(&ident.name, &ident.span) {
+                    Ok(value)
+                } else {
+                    Ok(Expr::Ident(ident))
+                }
+            }
+            _ => Err(Diagnostic::new("unexpected token in expression").with_span(t.span.clone())),
+        }
+    }
+
+    fn parse_call_after_name(&mut self, name: Ident) -> Result<Call, Diagnostic> {
+        self.expect(|k| matches!(k, TokenKind::LParen), "`(`")?;
+        let mut args = Vec::new();
+        while let Some(t) = self.peek() {
+            match &t.kind {
+                TokenKind::RParen => {
+                    self.bump();
+                    break;
+                }
+                TokenKind::Comma => {
+                    self.bump();
+                }
+                TokenKind::Ident(_) => {
+                    // Lookahead for named args: ident '=' ...
+                    let save = self.i;
+                    let name_tok = self.bump().unwrap().clone();
+                    if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Eq)) {
+                        self.bump();
+                        let value = self.parse_expr()?;
+                        let TokenKind::Ident(s) = name_tok.kind else {
+                            unreachable!()
+                        };
+                        let arg_name = Ident::new(s, name_tok.span.clone());
+                        args.push(CallArg::Named {
+                            name: arg_name,
+                            value,
+                        });
+                    } else {
+                        // Not named; rewind and parse as expr.
+                        self.i = save;
+                        let e = self.parse_expr()?;
+                        args.push(CallArg::Positional(e));
+                    }
+                }
+                _ => {
+                    let e = self.parse_expr()?;
+                    args.push(CallArg::Positional(e));
+                }
+            }
+        }
+        Ok(Call {
+            span: self.span_from(name.span.start),
+            name,
+            args,
+        })
+    }
+
+    fn parse_compare_op(&mut self, what: &'static str) -> Result<CompareOp, Diagnostic> {
+        let t = self.expect(
+            |k| {
+                matches!(
+                    k,
+                    TokenKind::Lt
+                        | TokenKind::Le
+                        | TokenKind::Gt
+                        | TokenKind::Ge
+                        | TokenKind::EqEq
+                        | TokenKind::NotEq
+                )
+            },
+            what,
+        )?;
+        Ok(match t.kind {
+            TokenKind::Lt => CompareOp::Lt,
+            TokenKind::Le => CompareOp::Le,
+            TokenKind::Gt => CompareOp::Gt,
+            TokenKind::Ge => CompareOp::Ge,
+            TokenKind::EqEq => CompareOp::Eq,
+            TokenKind::NotEq => CompareOp::Ne,
+            _ => unreachable!(),
+        })
+    }
+
+    fn parse_ident(&mut self, what: &'static str) -> Result<Ident, Diagnostic> {
+        let t = self.expect(|k| matches!(k, TokenKind::Ident(_)), what)?;
+        let TokenKind::Ident(s) = &t.kind else {
+            unreachable!()
+        };
+        Ok(Ident::new(s.clone(), t.span.clone()))
+    }
+
+    fn parse_u64(&mut self, what: &'static str) -> Result<u64, Diagnostic> {
+        let t = self.expect(|k| matches!(k, TokenKind::Number(_)), what)?;
+        let s = match &t.kind {
+            TokenKind::Number(s) => s.as_str(),
+            _ => unreachable!(),
+        };
+        parse_integer(s).ok_or_else(|| {
+            Diagnostic::new(format!("invalid integer for {what}")).with_span(t.span.clone())
+        })
+    }
+
+    fn parse_quantity(&mut self, what: &'static str) -> Result<Quantity, Diagnostic> {
+        self.parse_quantity_until(what, None)
+    }
+
+    fn parse_quantity_until(
+        &mut self,
+        what: &'static str,
+        stop: Option<&str>,
+    ) -> Result<Quantity, Diagnostic> {
+        if let Some(Token {
+            kind: TokenKind::Ident(name),
+            span,
+        }) = self.peek()
+            && let Some(value) = self.param(name, span)
+        {
+            self.bump();
+            return match value {
+                Expr::Number(q) => Ok(q),
+                _ => Err(
+                    Diagnostic::new(format!("parameter `{name}` is not a quantity"))
+                        .with_span(span.clone()),
+                ),
+            };
+        }
+        let t = self.expect(|k| matches!(k, TokenKind::Number(_)), what)?;
+        let num_str = match &t.kind {
+            TokenKind::Number(s) => s.as_str(),
             _ => unreachable!(),
         };
         let value = num_str.parse::<f64>().map_err(|_| {
             Diagnostic::new(format!("invalid number for {what}")).with_span(t.span.clone())
         })?;
 
-        // Optional unit: an identifier immediately after the number.
-        let unit = match self.peek().map(|t| &t.kind) {
-            Some(TokenKind::Ident(_)) => Some(self.parse_ident("unit")?),
-            _ => None,
-        };
+        // Optional unit: an identifier immediately after the number, unless
+        // it starts the next item.
+        let unit = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Ident(s))
+                if Some(s.as_str()) != stop && !ITEM_KEYWORDS.contains(&s.as_str()) =>
+            {
+                Some(self.parse_ident("unit")?)
+            }
+            _ => None,
+        };
+
+        let end = unit.as_ref().map(|u| u.span.end).unwrap_or(t.span.end);
+        let span = Span::new(t.span.start, end);
+
+        Ok(Quantity { value, unit, span })
+    }
+}
+
+/// `Encode("<file>", scheme=rate, max_rate=<rate>, present=<time>)` or
+/// `Encode("<file>", scheme=latency, present=<time>)`.
+fn parse_encoder(call: Call) -> Result<Encoder, Diagnostic> {
+    let span = call.name.span;
+    let mut args = call.args.into_iter();
+    let path = match args.next() {
+        Some(CallArg::Positional(Expr::String(path))) => path,
+        _ => {
+            return Err(
+                Diagnostic::new("`Encode` needs a file path as its first argument").with_span(span),
+            );
+        }
+    };
+    let mut scheme = None;
+    let mut max_rate = None;
+    let mut present = None;
+    for arg in args {
+        let CallArg::Named { name, value } = arg else {
+            return Err(
+                Diagnostic::new("`Encode` arguments after the path must be named").with_span(span),
+            );
+        };
+        match (name.name.as_str(), value) {
+            ("scheme", Expr::Ident(id)) => scheme = Some(id),
+            ("max_rate", Expr::Number(q)) => max_rate = Some(q),
+            ("present", Expr::Number(q)) => present = Some(q),
+            ("scheme" | "max_rate" | "present", _) => {
+                let expected = if name.name == "scheme" {
+                    "a scheme name"
+                } else {
+                    "a quantity"
+                };
+                return Err(
+                    Diagnostic::new(format!("`{}` must be {expected}", name.name))
+                        .with_span(name.span),
+                );
+            }
+            (other, _) => {
+                return Err(
+                    Diagnostic::new(format!("unknown `Encode` argument `{other}`"))
+                        .with_span(name.span),
+                );
+            }
+        }
+    }
+    let scheme = scheme.ok_or_else(|| {
+        Diagnostic::new("`Encode` stimulus requires scheme").with_span(span.clone())
+    })?;
+    let scheme = match scheme.name.as_str() {
+        "rate" => EncodeScheme::Rate {
+            max_rate: max_rate.ok_or_else(|| {
+                Diagnostic::new("rate encoding requires max_rate").with_span(scheme.span.clone())
+            })?,
+        },
+        "latency" => match max_rate {
+            Some(q) => {
+                return Err(
+                    Diagnostic::new("max_rate only applies to rate encoding").with_span(q.span)
+                );
+            }
+            None => EncodeScheme::Latency,
+        },
+        other => {
+            return Err(Diagnostic::new(format!(
+                "unknown encoding scheme `{other}` (expected `rate` or `latency`)"
+            ))
+            .with_span(scheme.span));
+        }
+    };
+    let present = present.ok_or_else(|| {
+        Diagnostic::new("`Encode` stimulus requires present").with_span(span.clone())
+    })?;
+    Ok(Encoder {
+        path,
+        scheme,
+        present,
+        span,
+    })
+}
+
+fn parse_external(call: Call) -> Result<ExternalInput, Diagnostic> {
+    let span = call.name.span;
+    let mut port = None;
+    let mut input = ExternalSignal::Rate;
+    let mut timeout = None;
+    let mut on_timeout = TimeoutPolicy::Error;
+    for arg in call.args {
+        let CallArg::Named { name, value } = arg else {
+            return Err(Diagnostic::new("`External` arguments must be named").with_span(span));
+        };
+        match (name.name.as_str(), value) {
+            ("port", Expr::Number(q)) => {
+                let valid = q.unit.is_none() && q.value.fract() == 0.0 && q.value >= 1.0;
+                port = match u16::try_from(q.value as u64) {
+                    Ok(port) if valid => Some(port),
+                    _ => {
+                        return Err(Diagnostic::new(
+                            "`port` must be an integer between 1 and 65535",
+                        )
+                        .with_span(q.span));
+                    }
+                };
+            }
+            ("input", Expr::Ident(id)) => {
+                input = ExternalSignal::from_name(&id.name).ok_or_else(|| {
+                    Diagnostic::new(format!(
+                        "unknown external input `{}` (expected `rate` or `current`)",
+                        id.name
+                    ))
+                    .with_span(id.span.clone())
+                })?;
+            }
+            ("timeout", Expr::Number(q)) => timeout = Some(q),
+            ("on_timeout", Expr::Ident(id)) => {
+                on_timeout = TimeoutPolicy::from_name(&id.name).ok_or_else(|| {
+                    Diagnostic::new(format!(
+                        "unknown timeout policy `{}` (expected `hold`, `zero` or `error`)",
+                        id.name
+                    ))
+                    .with_span(id.span.clone())
+                })?;
+            }
+            ("port" | "timeout", _) => {
+                return Err(
+                    Diagnostic::new(format!("`{}` must be a quantity", name.name))
+                        .with_span(name.span),
+                );
+            }
+            ("input" | "on_timeout", _) => {
+                return Err(
+                    Diagnostic::new(format!("`{}` must be a name", name.name)).with_span(name.span)
+                );
+            }
+            (other, _) => {
+                return Err(
+                    Diagnostic::new(format!("unknown `External` argument `{other}`"))
+                        .with_span(name.span),
+                );
+            }
+        }
+    }
+    let port = port.ok_or_else(|| {
+        Diagnostic::new("`External` stimulus requires port").with_span(span.clone())
+    })?;
+    let timeout = timeout.unwrap_or_else(|| Quantity {
+        value: 1.0,
+        unit: Some(Ident::new("s", span.clone())),
+        span: span.clone(),
+    });
+    Ok(ExternalInput {
+        port,
+        input,
+        timeout,
+        on_timeout,
+        span,
+    })
+}
+
+pub fn format_diagnostic(src: &str, diag: &Diagnostic) -> String {
+    match &diag.span {
+        None => diag.to_string(),
+        Some(span) => format_labeled(src, "error", &diag.message, span),
+    }
+}
+
+/// [`format_diagnostic`] for a diagnostic that doesn't stop the program,
+/// such as one from [`warnings`](crate::validate::warnings).
+pub fn format_warning(src: &str, diag: &Diagnostic) -> String {
+    match &diag.span {
+        None => format!("warning: {}", diag.message),
+        Some(span) => format_labeled(src, "warning", &diag.message, span),
+    }
+}
+
+fn format_labeled(src: &str, label: &str, message: &str, span: &Span) -> String {
+    let (line_no, col) = span.line_col(src);
+    let line = src.lines().nth(line_no - 1).unwrap_or("");
+
+    let (start, end) = (span.start.min(src.len()), span.end.min(src.len()));
+    let caret_len = src
+        .get(start..end.max(start))
+        .map_or(end.saturating_sub(start), |text| text.chars().count())
+        .max(1);
+
+    let mut out = String::new();
+    out.push_str(&format!("{label}: {message}\n"));
+    out.push_str(&format!("  --> line {line_no}, col {col}\n"));
+    out.push_str("   |\n");
+    out.push_str(&format!("{line_no:>3} | {line}\n"));
+    out.push_str("   | ");
+    for _ in 1..col {
+        out.push(' ');
+    }
+    for _ in 0..caret_len {
+        out.push('^');
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_diagnostic, parse_program};
+    use crate::ast::{
+        AssertCond, ConnectKind, Expr, Item, LanguageVersion, ProbeDef, StimulusCombine,
+    };
+    use crate::diagnostic::{Diagnostic, Span};
+    use crate::validate::validate;
+
+    const HELLO: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../examples/hello.cv"
+    ));
+    const POISSON: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../examples/poisson.cv"
+    ));
+
+    #[test]
+    fn parses_and_validates_example() {
+        let program = parse_program(HELLO).expect("example should parse");
+        validate(&program).expect("example should validate");
+    }
+
+    #[test]
+    fn parses_and_validates_poisson_example() {
+        let program = parse_program(POISSON).expect("example should parse");
+        validate(&program).expect("example should validate");
+    }
+
+    #[test]
+    fn poisson_correlation_is_a_fraction() {
+        let src = "neuron LIF { tau_m = 10 ms }
+                   layer X[8] : LIF
+                   stimulus X = Poisson(rate=20 Hz, correlation=0.2)
+                   run for 1 ms
+";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("valid");
+        let Item::Stimulus(stim) = &program.items[2] else {
+            panic!("expected a stimulus");
+        };
+        assert_eq!(
+            stim.model.to_string(),
+            "Poisson(rate=20 Hz, correlation=0.2)"
+        );
+        for bad in ["1.5", "-0.1", "0.2 Hz"] {
+            let program = parse_program(&src.replace("0.2)", &format!("{bad})"))).expect("parse");
+            let diags = validate(&program).expect_err("out of range");
+            assert_eq!(
+                diags[0].message,
+                "Poisson correlation must be a number from 0 to 1"
+            );
+        }
+    }
+
+    #[test]
+    fn delays_take_time_or_whole_steps() {
+        let src = "neuron LIF { tau_m = 10 ms }
+                   layer X[8] : LIF
+                   connect X -> X { d = 3 steps }
+                   run for 1 ms
+";
+        for good in [
+            "3 steps",
+            "1 step",
+            "0 steps",
+            "Normal(4 steps, 1 steps)",
+            "2 ms",
+        ] {
+            let program = parse_program(&src.replace("3 steps", good)).expect("parse");
+            validate(&program).expect(good);
+        }
+        for (bad, message) in [
+            (
+                "1.5 steps",
+                "a delay in steps must be a whole number of them, 0 or more",
+            ),
+            (
+                "-1 steps",
+                "a delay in steps must be a whole number of them, 0 or more",
+            ),
+            (
+                "Uniform(1 steps, 2 ms)",
+                "connection delay mixes `steps` with other units",
+            ),
+            ("Gamma(1 steps, 2 steps)", "unsupported delay expression"),
+            ("3 Hz", "unsupported time unit `Hz` for connection delay"),
+        ] {
+            let program = parse_program(&src.replace("3 steps", bad)).expect("parse");
+            let diags = validate(&program).expect_err(bad);
+            assert_eq!(diags[0].message, message, "{bad}");
+        }
+    }
+
+    #[test]
+    fn checks_weight_normalization() {
+        let src = "neuron LIF { tau_m = 10 ms }
+                   layer X[8] : LIF
+                   connect X -> X { w = Uniform(0, 1), normalize = per_target(0.5) }
+                   run for 1 ms
+";
+        validate(&parse_program(src).expect("parse")).expect("valid");
+        for (bad, message) in [
+            (
+                "scaled(0.5)",
+                "unknown normalization `scaled` (expected `total` or `per_target`)",
+            ),
+            (
+                "per_target(0.5, 1)",
+                "`per_target` takes one number, the sum of the weights",
+            ),
+            ("total(1 ms)", "`total` sum takes no unit"),
+            (
+                "0.5",
+                "expected `total(<sum>)` or `per_target(<sum>)` for `normalize`",
+            ),
+        ] {
+            let bad = src.replace("per_target(0.5)", bad);
+            let diags = validate(&parse_program(&bad).expect("parse")).expect_err(message);
+            assert_eq!(diags[0].message, message);
+        }
+    }
+
+    #[test]
+    fn checks_delay_plasticity() {
+        let src = "neuron LIF { tau_m = 10 ms }
+                   layer X[8] : LIF
+                   connect X -> X { d = 2 ms, delay_plasticity = DelaySTDP(lr = 0.5, window = 10 ms, d_max = 8 steps) }
+                   run for 1 ms
+";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("valid");
+        for (bad, message) in [
+            (
+                "DelaySTDP(lr = 0.5)",
+                "`DelaySTDP` needs a `d_max`, the longest delay it may learn",
+            ),
+            (
+                "DelaySTDP(d_max = 8 steps, window = 0 ms)",
+                "`DelaySTDP` argument `window` must be positive",
+            ),
+            (
+                "DelaySTDP(d_max = 2.5 steps)",
+                "a delay in steps must be a whole number of them, 0 or more",
+            ),
+            (
+                "DelaySTDP(d_max = 8 steps, tau = 5 ms)",
+                "unknown `DelaySTDP` argument `tau`",
+            ),
+            (
+                "RSTDP(d_max = 8 steps)",
+                "unknown delay plasticity rule `RSTDP` (expected `DelaySTDP`)",
+            ),
+        ] {
+            let bad = src.replace("DelaySTDP(lr = 0.5, window = 10 ms, d_max = 8 steps)", bad);
+            let diags = validate(&parse_program(&bad).expect("parse")).expect_err(message);
+            assert_eq!(diags[0].message, message);
+        }
+    }
+
+    #[test]
+    fn stimuli_say_how_they_combine() {
+        let src = "neuron LIF { tau_m = 10 ms }\n\
+                   layer X[8] : LIF\n\
+                   stimulus X = Poisson(rate=20 Hz)\n\
+                   stimulus X = Poisson(rate=50 Hz) combine = max\n\
+                   stimulus X = Encode(\"x.csv\", scheme=latency, present=1 ms)\n\
+                   run for 1 ms\n";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("valid");
+        let Item::Stimulus(stim) = &program.items[3] else {
+            panic!("expected a stimulus");
+        };
+        assert_eq!(stim.combine, Some(StimulusCombine::Max));
+        let printed = crate::print::print_program(&program);
+        assert!(printed.contains("stimulus X = Poisson(rate=50 Hz) combine = max\n"));
+        let warnings = crate::validate::warnings(&program);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].message,
+            "this stimulus adds to an earlier one on layer `X`; say `combine = sum` to keep \
+             that, or `combine = replace` or `combine = max`"
+        );
+        let explicit = parse_program(&src.replace("present=1 ms)", "present=1 ms) combine = sum"))
+            .expect("parse");
+        assert!(crate::validate::warnings(&explicit).is_empty());
+
+        let latency_max = src.replace("present=1 ms)", "present=1 ms) combine = max");
+        let diags = validate(&parse_program(&latency_max).expect("parse")).expect_err("no rate");
+        assert!(
+            diags[0]
+                .message
+                .starts_with("`combine = max` needs a stimulus with a rate")
+        );
+        let err = parse_program(&src.replace("max", "min")).expect_err("unknown mode");
+        assert_eq!(
+            err.message,
+            "unknown combine mode `min` (expected `sum`, `replace` or `max`)"
+        );
+    }
+
+    #[test]
+    fn validation_fails_for_unknown_neuron_type() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer X[1] : NoSuchNeuron
+run for 1 ms
+"#;
+
+        let program = parse_program(src).expect("src should parse");
+        let diags = validate(&program).expect_err("validation should fail");
+        assert!(
+            diags
+                .iter()
+                .any(|d| d.message.contains("unknown neuron type"))
+        );
+    }
+
+    #[test]
+    fn parses_seed_and_step() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer X[1] : LIF
+seed 7
+run for 2 ms step 1 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("validate");
+        assert!(
+            program
+                .items
+                .iter()
+                .any(|item| matches!(item, Item::Seed(_)))
+        );
+    }
+
+    #[test]
+    fn parses_named_connections() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer A[2] : LIF
+layer B[2] : LIF
+connect ff: A -> B { w = 0.5 }
+connect A -> B { w = 0.1 }
+connect ff: B -> A { }
+run for 2 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let Item::Connect(def) = &program.items[3] else {
+            panic!("expected connect");
+        };
+        assert_eq!(def.label(), "ff: A -> B");
+        let diags = validate(&program).expect_err("duplicate name");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "duplicate connection `ff`");
+        assert_eq!(diags[0].span.as_ref().map(|s| s.start), src.rfind("ff"));
+    }
+
+    #[test]
+    fn parses_electrical_connections() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer A[2] : LIF
+connect gj: A <-> A electrical { g = 0.1 }
+connect A <-> A electrical { w = 0.5, g = 2 ms }
+connect A <-> A electrical { }
+run for 2 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let Item::Connect(def) = &program.items[2] else {
+            panic!("expected connect");
+        };
+        assert_eq!(def.kind, ConnectKind::Electrical);
+        assert_eq!(def.label(), "gj: A <-> A");
+        let messages: Vec<String> = validate(&program)
+            .expect_err("bad bodies")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "electrical connections only take `g`, not `w`",
+                "coupling `g` takes no unit",
+                "electrical connection needs a coupling conductance `g`",
+            ]
+        );
+
+        let err = parse_program("connect A <-> B { g = 1 }\n").unwrap_err();
+        assert_eq!(err.message, "expected `electrical` after `A <-> B`");
+        let err = parse_program("connect A -> B electrical { g = 1 }\n").unwrap_err();
+        assert_eq!(err.message, "electrical connections are written `A <-> B`");
+    }
+
+    #[test]
+    fn validates_compartment_keys() {
+        let src = r#"
+neuron Pyr { tau_m = 10 ms, compartments = 2, g_axial = 0.5 }
+neuron Bad { compartments = 1.5, g_axial = 2 ms }
+layer A[2] : Pyr
+connect A -> A { w = 0.5, compartment = 2 }
+connect A -> A { w = 0.5, compartment = -1 }
+run for 2 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let messages: Vec<String> = validate(&program)
+            .expect_err("bad compartments")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "`compartments` must be a non-negative integer without a unit",
+                "axial conductance `g_axial` takes no unit",
+                "`compartment` must be a non-negative integer without a unit",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_rewards_and_checks_plasticity() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer A[2] : LIF
+connect A -> A { w = 0.5, plasticity = RSTDP(a_plus = 0.02, tau_e = 100 ms) }
+reward -0.5 at 20 ms
+run for 50 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let Item::Reward(stmt) = &program.items[3] else {
+            panic!("expected reward");
+        };
+        assert_eq!(
+            (stmt.value.value, stmt.at.to_string()),
+            (-0.5, "20 ms".to_string())
+        );
+        validate(&program).expect("valid");
+
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer A[2] : LIF
+connect A -> A { plasticity = STDP() }
+connect A -> A { plasticity = RSTDP(0.1, tau_e = 5, eta = 1) }
+reward 1 Hz at -1 ms
+run for 50 ms
+"#;
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("bad rules")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown plasticity rule `STDP` (expected `RSTDP`)",
+                "`RSTDP` arguments must be named",
+                "missing unit for `RSTDP` argument `tau_e`",
+                "unknown `RSTDP` argument `eta`",
+                "reward value takes no unit",
+                "reward time must not be negative",
+            ]
+        );
+
+        let err = parse_program("reward 1.0 5 ms\n").unwrap_err();
+        assert_eq!(err.message, "expected `at` after reward value");
+    }
+
+    #[test]
+    fn parses_rate_recordings() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer E[4] : LIF
+record rate from E window 10 ms
+run for 50 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let Item::Record(stmt) = &program.items[2] else {
+            panic!("expected record");
+        };
+        assert_eq!(
+            (stmt.layer.name.as_str(), stmt.window.to_string()),
+            ("E", "10 ms".to_string())
+        );
+        validate(&program).expect("valid");
+
+        let src = "neuron LIF { tau_m = 10 ms }\nlayer E[4] : LIF\nrecord rate from I window 10 ms\nrecord rate from E window 0 ms\nrecord rate from E window 5 Hz\nrun for 5 ms\n";
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("bad recordings")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(messages.len(), 3, "{messages:?}");
+        assert_eq!(messages[0], "unknown recorded layer `I`");
+        assert_eq!(messages[1], "smoothing window must be positive");
+
+        let err = parse_program("record spikes from E window 10 ms\n").unwrap_err();
+        assert_eq!(err.message, "unknown recording `spikes` (expected `rate`)");
+        let err = parse_program("record rate E window 10 ms\n").unwrap_err();
+        assert_eq!(err.message, "expected `from`");
+    }
+
+    #[test]
+    fn parses_probes() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer Output[8] : LIF
+probe p1 = v(Output[0..4]) every 0.5 ms
+probe all = v(Output)
+run for 50 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let probes: Vec<&ProbeDef> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Probe(def) => Some(def),
+                _ => None,
+            })
+            .collect();
+        let [p1, all] = probes[..] else {
+            panic!("expected two probes");
+        };
+        assert_eq!(p1.name.name, "p1");
+        assert_eq!(p1.neurons, Some((0, 4)));
+        assert_eq!(p1.to_string(), "v(Output[0..4]) every 0.5 ms");
+        assert_eq!((all.neurons, all.every.is_none()), (None, true));
+        validate(&program).expect("valid");
+
+        let src = "neuron LIF { tau_m = 10 ms }\nlayer E[4] : LIF\nprobe p = v(I)\nprobe p = v(E[2..2])\nprobe q = v(E) every 0 ms\nrun for 5 ms\n";
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("bad probes")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "duplicate probe `p`",
+                "unknown probed layer `I`",
+                "probe `p` has no neurons in `2..2`",
+                "probe interval must be positive",
+            ]
+        );
+
+        let err = parse_program("probe p = u(E)\n").unwrap_err();
+        assert_eq!(err.message, "unknown probe variable `u` (expected `v`)");
+    }
+
+    #[test]
+    fn validates_experiments() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer In[4] : LIF
+layer Out[2] : LIF
+stimulus In = Encode("rows.csv", scheme=latency, present=20 ms)
+experiment { epochs = 3, isi = 10 ms, shuffle = true, readout = Out }
+run for 1 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        assert!(matches!(&program.items[4], Item::Experiment(e) if e.body.len() == 4));
+        validate(&program).expect("valid");
+
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer In[4] : LIF
+stimulus In = Encode("a.csv", scheme=latency, present=20 ms)
+stimulus In = Encode("b.csv", scheme=latency, present=10 ms)
+experiment { epochs = 0, isi = -1 ms, shuffle = yes, readout = Missing, order = 1 }
+experiment { }
+run for 1 ms
+"#;
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("bad experiment")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "only one `experiment` is allowed",
+                "every `Encode` stimulus in an experiment needs the same `present` time",
+                "experiment `epochs` must be a positive integer without a unit",
+                "experiment `isi` must not be negative",
+                "experiment `shuffle` must be `true` or `false`",
+                "unknown experiment readout layer `Missing`",
+                "experiments take `epochs`, `isi`, `shuffle` and `readout`, not `order`",
+            ]
+        );
+
+        let src = "neuron LIF { }\nlayer In[1] : LIF\nexperiment { }\nrun for 1 ms\n";
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("nothing to present")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            ["an experiment needs an `Encode` stimulus to present"]
+        );
+    }
+
+    #[test]
+    fn validates_readouts() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer In[4] : LIF
+layer Out[2] : LIF
+stimulus In = Encode("rows.csv", scheme=latency, present=20 ms)
+experiment { epochs = 2 }
+readout Out labels "labels.csv" rule = max_spikes
+run for 1 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        assert!(matches!(
+            &program.items[5],
+            Item::Readout(r) if r.layer.name == "Out" && r.labels == "labels.csv"
+        ));
+        validate(&program).expect("valid");
 
-        let end = unit.as_ref().map(|u| u.span.end).unwrap_or(t.span.end);
-        let span = Span::new(t.span.start, end);
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer In[4] : LIF
+readout Missing labels "" rule = vote
+readout In labels "labels.csv"
+run for 1 ms
+"#;
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("bad readout")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "only one `readout` is allowed",
+                "a `readout` needs an `experiment` to present samples",
+                "unknown readout layer `Missing`",
+                "readout label file path is empty",
+                "unknown readout rule `vote` (expected `max_spikes`)",
+            ]
+        );
 
-        Ok(Quantity { value, unit, span })
+        let err = parse_program("readout Out \"labels.csv\"\n").unwrap_err();
+        assert_eq!(err.message, "expected `labels` after readout layer");
     }
-}
 
-pub fn format_diagnostic(src: &str, diag: &Diagnostic) -> String {
-    match &diag.span {
-        None => diag.to_string(),
-        Some(span) => {
-            let mut line_start = 0usize;
-            let mut line_no = 1usize;
-            for (idx, ch) in src.char_indices() {
-                if idx >= span.start {
-                    break;
-                }
-                if ch == '\n' {
-                    line_no += 1;
-                    line_start = idx + 1;
-                }
-            }
+    #[test]
+    fn validates_weight_files() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer A[2] : LIF
+connect A -> A { w = from_file("weights/ff.csv") }
+connect A -> A { w = from_file("") }
+connect A -> A { w = from_file("a.csv", "b.csv") }
+run for 1 ms
+"#;
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("bad weight files")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "`from_file` path is empty",
+                "`from_file` takes one file path"
+            ]
+        );
+    }
 
-            let line_end = src[line_start..]
-                .find('\n')
-                .map(|off| line_start + off)
-                .unwrap_or(src.len());
-            let line = &src[line_start..line_end];
+    #[test]
+    fn parses_and_checks_encode_stimuli() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer In[4] : LIF
+stimulus In = Encode("rows.csv", scheme=rate, max_rate=200 Hz, present=50 ms)
+stimulus In = Encode("rows.csv", scheme=latency, present=20 ms)
+run for 100 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let models: Vec<String> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Stimulus(def) => Some(def.model.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            models,
+            [
+                r#"Encode("rows.csv", scheme=rate, max_rate=200 Hz, present=50 ms)"#,
+                r#"Encode("rows.csv", scheme=latency, present=20 ms)"#,
+            ]
+        );
+        validate(&program).expect("valid");
 
-            let col = span.start.saturating_sub(line_start) + 1;
-            let caret_len = (span.end.saturating_sub(span.start)).max(1);
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer In[4] : LIF
+stimulus In = Encode("", scheme=rate, max_rate=200 ms, present=-1 ms)
+run for 100 ms
+"#;
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("bad encoder")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "`Encode` file path is empty",
+                "unsupported rate unit `ms` for max_rate",
+                "presentation time must be positive",
+            ]
+        );
 
-            let mut out = String::new();
-            out.push_str(&format!("error: {}\n", diag.message));
-            out.push_str(&format!("  --> line {line_no}, col {col}\n"));
-            out.push_str("   |\n");
-            out.push_str(&format!("{line_no:>3} | {line}\n"));
-            out.push_str("   | ");
-            for _ in 1..col {
-                out.push(' ');
-            }
-            for _ in 0..caret_len {
-                out.push('^');
-            }
-            out.push('\n');
-            out
+        for (src, expected) in [
+            (
+                "Encode(scheme=rate)",
+                "`Encode` needs a file path as its first argument",
+            ),
+            (
+                r#"Encode("a.csv", scheme=rate, present=5 ms)"#,
+                "rate encoding requires max_rate",
+            ),
+            (
+                r#"Encode("a.csv", scheme=burst, present=5 ms)"#,
+                "unknown encoding scheme `burst` (expected `rate` or `latency`)",
+            ),
+            (
+                r#"Encode("a.csv", scheme=latency, max_rate=5 Hz, present=5 ms)"#,
+                "max_rate only applies to rate encoding",
+            ),
+            (
+                r#"Encode("a.csv", scheme=rate, max_rate=5 Hz)"#,
+                "`Encode` stimulus requires present",
+            ),
+            (
+                r#"Encode("a.csv", scheme=rate, gain=2)"#,
+                "unknown `Encode` argument `gain`",
+            ),
+        ] {
+            let err = parse_program(&format!("stimulus In = {src}\n")).unwrap_err();
+            assert_eq!(err.message, expected, "{src}");
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::parse_program;
-    use crate::ast::Item;
-    use crate::validate::validate;
+    #[test]
+    fn parses_external_stimuli() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer Motor[4] : LIF
+stimulus Motor = External(port=5556)
+stimulus Motor = External(port=5557, input=current, timeout=20 ms, on_timeout=hold)
+run for 100 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let models: Vec<String> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Stimulus(def) => Some(def.model.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            models,
+            [
+                "External(port=5556, input=rate, timeout=1 s, on_timeout=error)",
+                "External(port=5557, input=current, timeout=20 ms, on_timeout=hold)",
+            ]
+        );
+        validate(&program).expect("valid");
 
-    const HELLO: &str = include_str!(concat!(
-        env!("CARGO_MANIFEST_DIR"),
-        "/../../examples/hello.cv"
-    ));
-    const POISSON: &str = include_str!(concat!(
-        env!("CARGO_MANIFEST_DIR"),
-        "/../../examples/poisson.cv"
-    ));
+        let src = "neuron LIF { tau_m = 10 ms }\n\
+                   layer Motor[4] : LIF\n\
+                   stimulus Motor = External(port=5556, timeout=0 ms)\n\
+                   run for 100 ms\n";
+        let errors = validate(&parse_program(src).expect("parse")).expect_err("zero timeout");
+        assert_eq!(errors[0].message, "external timeout must be positive");
 
-    #[test]
-    fn parses_and_validates_example() {
-        let program = parse_program(HELLO).expect("example should parse");
-        validate(&program).expect("example should validate");
+        for (src, expected) in [
+            ("External()", "`External` stimulus requires port"),
+            (
+                "External(port=70000)",
+                "`port` must be an integer between 1 and 65535",
+            ),
+            (
+                "External(port=1.5)",
+                "`port` must be an integer between 1 and 65535",
+            ),
+            (
+                "External(port=5556, input=voltage)",
+                "unknown external input `voltage` (expected `rate` or `current`)",
+            ),
+            (
+                "External(port=5556, on_timeout=retry)",
+                "unknown timeout policy `retry` (expected `hold`, `zero` or `error`)",
+            ),
+            ("External(5556)", "`External` arguments must be named"),
+        ] {
+            let err = parse_program(&format!("stimulus In = {src}\n")).unwrap_err();
+            assert_eq!(err.message, expected, "{src}");
+        }
     }
 
     #[test]
-    fn parses_and_validates_poisson_example() {
-        let program = parse_program(POISSON).expect("example should parse");
-        validate(&program).expect("example should validate");
+    fn validates_modulators_and_their_readers() {
+        let src = r#"
+modulator DA { tau = 200 ms, source = VTA, gain = 0.1 }
+neuron LIF { tau_m = 10 ms, modulator = DA, modulator_gain = -0.5 }
+layer VTA[2] : LIF
+connect VTA -> VTA { plasticity = RSTDP(modulator = DA) }
+run for 5 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        assert!(matches!(&program.items[0], Item::Modulator(m) if m.name.name == "DA"));
+        validate(&program).expect("valid");
+
+        let src = r#"
+modulator DA { tau = 200 ms, source = VTA }
+modulator DA { gain = 1 Hz, decay = 2 }
+neuron LIF { modulator = NE }
+layer VTA[2] : LIF
+connect VTA -> VTA { plasticity = RSTDP(modulator = 5) }
+run for 5 ms
+"#;
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("bad modulators")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "duplicate modulator `DA`",
+                "modulator `gain` takes no unit",
+                "modulators take `tau`, `source` and `gain`, not `decay`",
+                "modulator `DA` needs a decay time `tau`",
+                "modulator `DA` needs a `source` layer",
+                "unknown modulator `NE`",
+                "expected a modulator name",
+            ]
+        );
     }
 
     #[test]
-    fn validation_fails_for_unknown_neuron_type() {
+    fn parses_disconnect_filters() {
         let src = r#"
 neuron LIF { tau_m = 10 ms }
-layer X[1] : NoSuchNeuron
-run for 1 ms
+layer A[4] : LIF
+connect rec: A -> A { w = 0.5 }
+disconnect A -> A where src == dst
+disconnect rec where (src < 2 or dst > 2) and src != 3 or 1 == 0
+disconnect rec
+run for 2 ms
 "#;
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("validate");
+        let filters: Vec<String> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Disconnect(stmt) => Some(
+                    stmt.filter
+                        .as_ref()
+                        .map_or(String::new(), |f| f.to_string()),
+                ),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            filters,
+            [
+                "src == dst",
+                "(src < 2 or dst > 2) and src != 3 or 1 == 0",
+                ""
+            ]
+        );
 
-        let program = parse_program(src).expect("src should parse");
-        let diags = validate(&program).expect_err("validation should fail");
-        assert!(
-            diags
-                .iter()
-                .any(|d| d.message.contains("unknown neuron type"))
+        let src = "neuron LIF { tau_m = 10 ms }\nlayer A[4] : LIF\ndisconnect A -> A\nconnect A -> A { }\nrun for 2 ms\n";
+        let diags =
+            validate(&parse_program(src).expect("parse")).expect_err("no earlier connection");
+        assert_eq!(
+            diags[0].message,
+            "`disconnect A -> A` does not match an earlier connection"
         );
+        let err = parse_program("disconnect A -> A where src == pre\n").unwrap_err();
+        assert!(err.message.contains("unknown synapse operand `pre`"));
     }
 
     #[test]
-    fn parses_seed_and_step() {
+    fn parses_assert_statements() {
         let src = r#"
 neuron LIF { tau_m = 10 ms }
 layer X[1] : LIF
-seed 7
-run for 2 ms step 1 ms
+run for 2 ms
+assert spikes(X) >= 1
+assert rate(X) between 40 Hz and 60 Hz
+assert spikes(X) between 0 and 3
 "#;
         let program = parse_program(src).expect("parse");
         validate(&program).expect("validate");
+        let asserts = program
+            .items
+            .iter()
+            .filter(|item| matches!(item, Item::Assert(_)))
+            .count();
+        assert_eq!(asserts, 3);
+    }
+
+    #[test]
+    fn validation_rejects_bad_assert_units() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer X[1] : LIF
+run for 2 ms
+assert spikes(X) > 1 Hz
+assert rate(X) < 3
+assert rate(Y) > 1 Hz
+"#;
+        let program = parse_program(src).expect("parse");
+        let diags = validate(&program).expect_err("validation should fail");
+        assert_eq!(diags.len(), 3);
+    }
+
+    #[test]
+    fn networks_scope_their_names() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+run for 10 ms
+network A {
+    layer In[4] : LIF
+    layer Out[2] : LIF
+    connect In -> Out { w = 0.5 }
+}
+network B {
+    layer In[8] : LIF
+    stimulus In = Poisson(rate=20 Hz)
+}
+"#;
+        let program = parse_program(src).expect("parse");
+        let names: Vec<&str> = program.networks().map(|n| n.name.name.as_str()).collect();
+        assert_eq!(names, ["A", "B"]);
+        validate(&program).expect("layer names are per network");
+
+        let b = program.select_network(Some("B")).expect("select B");
+        let layers: Vec<String> = b
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Layer(def) => Some(format!("{}[{}]", def.name.name, def.size)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(layers, ["In[8]"]);
+        assert_eq!(b.items.len(), 4, "shared neuron and run, then B's items");
+
+        let message = |result: Result<_, Diagnostic>| result.map(|_| ()).unwrap_err().message;
+        assert_eq!(
+            message(program.select_network(None)),
+            "the program defines several networks (`A`, `B`); select one"
+        );
+        assert_eq!(
+            message(program.select_network(Some("C"))),
+            "no network `C` (defined: `A`, `B`)"
+        );
+        let plain = parse_program("run for 1 ms\n").expect("parse");
+        assert_eq!(
+            message(plain.select_network(Some("A"))),
+            "no network `A`: the program has no `network` blocks"
+        );
+
+        // `Out` is only declared in A, and a shared error is reported once.
+        let src = "neuron LIF { tau_m = 10 ms }\n\
+                   network A { layer Out[2] : LIF }\n\
+                   network B { layer In[2] : LIF\n connect In -> Out { w = 1.0 } }\n\
+                   network A { layer X[1] : LIF }\n";
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("invalid")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "duplicate network `A`",
+                "missing `run` statement",
+                "unknown destination layer `Out`",
+            ]
+        );
+
+        for (src, expected) in [
+            ("network A { network B { } }", "networks cannot be nested"),
+            (
+                "network A { run for 1 ms",
+                "expected `}` to close network `A`",
+            ),
+        ] {
+            let err = parse_program(src).expect_err(src);
+            assert_eq!(err.message, expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn parses_experiment_runs() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+run for 10 ms
+network A { layer In[4] : LIF }
+network B { layer In[2] : LIF
+ layer Out[1] : LIF }
+experiment {
+    run A with seed 1..10; run B with seed 3..3
+    report total_spikes, mean_rate, rate(In)
+}
+"#;
+        let program = parse_program(src).expect("parse");
+        let Some(Item::Sweep(def)) = program.items.last() else {
+            panic!("expected a sweep: {:?}", program.items.last());
+        };
+        let runs: Vec<String> = def.runs.iter().map(|r| r.to_string()).collect();
+        assert_eq!(runs, ["run A with seed 1..10", "run B with seed 3..3"]);
+        let report: Vec<String> = def.report.iter().map(|m| m.to_string()).collect();
+        assert_eq!(report, ["total_spikes", "mean_rate", "rate(In)"]);
+        validate(&program).expect("valid");
+
+        let src = "neuron LIF { tau_m = 10 ms }\n\
+                   layer In[1] : LIF\n\
+                   run for 10 ms\n\
+                   experiment { run A with seed 5..1; report spikes(Out) }\n\
+                   experiment { report total_spikes }\n";
+        let messages: Vec<String> = validate(&parse_program(src).expect("parse"))
+            .expect_err("invalid")
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "only one `experiment` with runs is allowed",
+                "empty seed range `5..1`",
+                "no network `A`: the program has no `network` blocks",
+                "an experiment with a `report` needs a `run`",
+            ]
+        );
+
+        let err = parse_program("experiment { run with seed 1..2; report median }")
+            .expect_err("unknown metric");
+        assert_eq!(
+            err.message,
+            "unknown report metric `median` (expected `total_spikes`, `mean_rate`, \
+             `spikes(..)` or `rate(..)`)"
+        );
+    }
+
+    #[test]
+    fn warns_about_unknown_body_keys() {
+        let src = "neuron LIF { tau_n = 10 ms, v_th = 1.0 }\n\
+                   layer A[2] : LIF\n\
+                   connect A -> A { w = 0.5, plastisity = 1, colour = 3 }\n\
+                   connect A <-> A electrical { g = 0.1 }\n\
+                   stimulus A = Poisson(rate=10 Hz)\n\
+                   run for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("unknown keys only warn");
+        let warnings = crate::validate::warnings(&program);
+        let messages: Vec<&str> = warnings.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown neuron key `tau_n`; did you mean `tau_m`?",
+                "unknown connection key `plastisity`; did you mean `plasticity`?",
+                "unknown connection key `colour`; expected one of `w`, `d`, `compartment`, \
+                 `normalize`, `plasticity`, `delay_plasticity`",
+            ]
+        );
         assert!(
-            program
-                .items
-                .iter()
-                .any(|item| matches!(item, Item::Seed(_)))
+            super::format_warning(src, &warnings[0]).starts_with("warning: unknown neuron key")
+        );
+        let errors = crate::validate::validate_strict(&program).expect_err("strict");
+        assert_eq!(errors, warnings);
+    }
+
+    #[test]
+    fn warns_about_models_that_cannot_spike() {
+        let messages = |src: &str| -> Vec<String> {
+            let program = parse_program(src).expect("parse");
+            validate(&program).expect("silent models are valid");
+            crate::validate::warnings(&program)
+                .into_iter()
+                .map(|d| d.message)
+                .collect()
+        };
+        assert_eq!(
+            messages("run for 10 ms\n"),
+            ["the model has no layers, so the run produces no spikes"]
+        );
+        assert_eq!(
+            messages(
+                "neuron LIF {}\n\
+                 layer A[2] : LIF\n\
+                 layer B[2] : LIF\n\
+                 connect A -> B { w = 0.5 }\n\
+                 run for 10 ms\n"
+            ),
+            [
+                "the model has no stimulus, so nothing drives its layers and the run \
+                 produces no spikes"
+            ]
+        );
+        assert_eq!(
+            messages(
+                "neuron LIF {}\n\
+                 layer A[2] : LIF\n\
+                 layer Lone[2] : LIF\n\
+                 stimulus A = Poisson(rate=10 Hz)\n\
+                 network Small {\n\
+                     layer B[2] : LIF\n\
+                     connect A -> B { w = 0.5 }\n\
+                 }\n\
+                 run for 10 ms\n"
+            ),
+            ["layer `Lone` has no connections or stimulus, so it never spikes"]
+        );
+    }
+
+    #[test]
+    fn parses_and_checks_the_version_pragma() {
+        let model = "neuron LIF { tau_m = 10 ms }\n\
+                     layer A[2] : LIF\n\
+                     run for 10 ms\n";
+        let program = parse_program(&format!("converge \"0.1\"\n{model}")).expect("parse");
+        let pragma = program.version.clone().expect("pragma");
+        assert_eq!(pragma.version, LanguageVersion { major: 0, minor: 1 });
+        assert_eq!(pragma.span, Span::new(0, 14));
+        validate(&program).expect("0.1 syntax only");
+        assert_eq!(
+            parse_program(model).unwrap().language_version(),
+            LanguageVersion::CURRENT
+        );
+
+        let newer = parse_program(&format!("converge \"0.9\"\n{model}")).unwrap();
+        validate(&newer).expect("a newer minor version only warns");
+        let warnings = crate::validate::warnings(&newer);
+        assert_eq!(
+            warnings[0].message,
+            "the file targets language version 0.9, newer than the 0.2 this toolchain implements"
+        );
+
+        let major = parse_program(&format!("converge \"1.0\"\n{model}")).unwrap();
+        let errors = validate(&major).expect_err("incompatible");
+        assert_eq!(
+            errors[0].message,
+            "language version 1.0 is incompatible with this toolchain, which reads 0.x up to 0.2"
+        );
+
+        let err = parse_program("converge \"zero\"\n").expect_err("bad version");
+        assert_eq!(
+            err.message,
+            "invalid language version `zero` (expected `<major>.<minor>`)"
+        );
+        let err = parse_program(&format!("{model}converge \"0.2\"\n")).expect_err("late pragma");
+        assert_eq!(
+            err.message,
+            "the `converge` version pragma must come before every item"
+        );
+    }
+
+    #[test]
+    fn gates_newer_syntax_on_the_declared_version() {
+        let src = "converge \"0.1\"\n\
+                   neuron LIF { tau_m = 10 ms }\n\
+                   layer A[2] : LIF\n\
+                   network Small {\n\
+                       connect A <-> A electrical { g = 0.1 }\n\
+                   }\n\
+                   run for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        let errors = validate(&program).expect_err("0.2 syntax");
+        let messages: Vec<&str> = errors.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "`network` blocks need language version 0.2, but the file declares 0.1",
+                "electrical connections need language version 0.2, but the file declares 0.1",
+            ]
+        );
+        let span = errors[1].span.clone().unwrap();
+        assert_eq!(&src[span.start..span.end], "A <-> A");
+        validate(&parse_program(&src.replace("0.1", "0.2")).unwrap()).expect("0.2 file");
+    }
+
+    #[test]
+    fn keywords_are_contextual() {
+        let src = "neuron rate { tau_m = 10 ms }\n\
+                   layer step[2] : rate\n\
+                   layer run[2] : rate\n\
+                   stimulus step = Poisson(rate=100 Hz)\n\
+                   connect seed: step -> run { w = 1.0 }\n\
+                   assert spikes(run) >= 0\n\
+                   run for 10 ms step 1 ms\n";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("valid");
+        let names: Vec<&str> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Layer(def) => Some(def.name.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["step", "run"]);
+        let Some(Item::Assert(stmt)) = program.items.get(5) else {
+            panic!("expected an assertion");
+        };
+        // A unitless bound stops before the next item.
+        assert!(
+            matches!(&stmt.cond, AssertCond::Compare { value, .. } if value.unit.is_none()),
+            "{stmt:?}"
+        );
+        assert!(matches!(program.items[6], Item::Run(_)));
+
+        let err = parse_program("layer A[2] : LIF\nrun 10 ms\n").expect_err("no `for`");
+        assert_eq!(err.message, "expected `for`");
+    }
+
+    #[test]
+    fn doc_comments_document_items() {
+        let src = "/// Leaky integrate-and-fire.\n\
+                   //// not a doc comment\n\
+                   neuron LIF { tau_m = 10 ms }\n\
+                   network Net {\n\
+                       /// Retina.\n\
+                       ///   Eight channels.\n\
+                       layer A[8] : LIF\n\
+                       /// Recurrent.\n\
+                       connect A -> A { w = 0.1 }\n\
+                   }\n\
+                   run for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        let Item::Neuron(neuron) = &program.items[0] else {
+            panic!("expected a neuron");
+        };
+        assert_eq!(neuron.doc.as_deref(), Some("Leaky integrate-and-fire."));
+        let Item::Network(net) = &program.items[1] else {
+            panic!("expected a network");
+        };
+        let (Item::Layer(layer), Item::Connect(conn)) = (&net.items[0], &net.items[1]) else {
+            panic!("expected a layer and a connection");
+        };
+        assert_eq!(layer.doc.as_deref(), Some("Retina.\n  Eight channels."));
+        assert_eq!(conn.doc.as_deref(), Some("Recurrent."));
+
+        let cvir = crate::emit::cvir_json(&program);
+        assert!(
+            cvir.contains("\"description\": \"Retina.\\n  Eight channels.\""),
+            "{cvir}"
+        );
+        let back = crate::cvir::program_from_cvir(&cvir).expect("read back");
+        let Item::Neuron(neuron) = &back.items[0] else {
+            panic!("expected a neuron");
+        };
+        assert_eq!(neuron.doc.as_deref(), Some("Leaky integrate-and-fire."));
+
+        let err = parse_program("/// Ten seconds.\nrun for 10 s\n").expect_err("run");
+        assert_eq!(
+            err.message,
+            "doc comments can only document `neuron`, `layer` and `connect` items"
+        );
+        let err = parse_program("layer A[2] : LIF\n/// Dangling.\n").expect_err("dangling");
+        assert_eq!(err.message, "expected an item after the doc comment");
+    }
+
+    #[test]
+    fn attributes_annotate_items() {
+        let src = "neuron LIF { tau_m = 10 ms }\n\
+                   /// Excitatory population.\n\
+                   @tag(\"excitatory\") @color(\"red\")\n\
+                   @region(x = 2, y = 3) @hidden\n\
+                   layer E[8] : LIF\n\
+                   @weight_scale(0.5)\n\
+                   connect E -> E { w = 0.1 }\n\
+                   run for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("attributes are metadata only");
+        let Item::Layer(layer) = &program.items[1] else {
+            panic!("expected a layer");
+        };
+        assert_eq!(layer.doc.as_deref(), Some("Excitatory population."));
+        let attrs: Vec<String> = layer.attributes.iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            attrs,
+            [
+                "tag(\"excitatory\")",
+                "color(\"red\")",
+                "region(x=2, y=3)",
+                "hidden()"
+            ]
+        );
+
+        let cvir = crate::emit::cvir_json(&program);
+        let back = crate::cvir::program_from_cvir(&cvir).expect("read back");
+        let (Item::Layer(a), Item::Layer(b)) = (&program.items[1], &back.items[1]) else {
+            panic!("expected layers");
+        };
+        let names = |l: &crate::ast::LayerDef| -> Vec<String> {
+            l.attributes.iter().map(|a| a.to_string()).collect()
+        };
+        assert_eq!(names(a), names(b));
+        let Item::Connect(conn) = &back.items[2] else {
+            panic!("expected a connection");
+        };
+        assert_eq!(conn.attributes[0].to_string(), "weight_scale(0.5)");
+
+        let err = parse_program("@tag(\"x\")\nrun for 10 ms\n").expect_err("run");
+        assert_eq!(
+            err.message,
+            "attributes can only be put on `neuron`, `layer` and `connect` items"
+        );
+        let err = parse_program("layer A[2] : LIF\n@tag\n").expect_err("dangling");
+        assert_eq!(err.message, "expected an item after the attributes");
+    }
+
+    #[test]
+    fn params_are_substituted_and_overridable() {
+        let src = "params { g = 0.4, input_rate = 20 Hz, dur = 50 ms, rate_x2 = input_rate }\n\
+                   neuron LIF { tau_m = 10 ms }\n\
+                   layer In[4] : LIF\n\
+                   layer Out[2] : LIF\n\
+                   connect In -> Out { w = Normal(g, 0.1) }\n\
+                   stimulus In = Poisson(rate=input_rate)\n\
+                   run for dur\n";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("valid");
+        let Item::Connect(conn) = &program.items[3] else {
+            panic!("expected a connection");
+        };
+        assert_eq!(conn.body[0].value.to_string(), "Normal(0.4, 0.1)");
+        let Item::Stimulus(stim) = &program.items[4] else {
+            panic!("expected a stimulus");
+        };
+        assert_eq!(stim.model.to_string(), "Poisson(rate=20 Hz)");
+        let span = match &stim.model {
+            crate::ast::StimulusModel::Poisson { rate, .. } => rate.span.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            &src[span.start..span.end],
+            "input_rate",
+            "spans point at the use"
+        );
+        assert_eq!(program.params.len(), 4);
+
+        let overrides = [(
+            "input_rate".to_string(),
+            super::parse_param_value("50Hz").unwrap(),
+        )];
+        let program = super::parse_program_with(src, &overrides).expect("override");
+        let Item::Stimulus(stim) = &program.items[4] else {
+            panic!("expected a stimulus");
+        };
+        assert_eq!(stim.model.to_string(), "Poisson(rate=50 Hz)");
+        assert_eq!(program.params[3].value.to_string(), "50 Hz");
+
+        let unknown = [("gain".to_string(), super::parse_param_value("1").unwrap())];
+        let err = super::parse_program_with(src, &unknown).expect_err("unknown");
+        assert_eq!(err.message, "unknown parameter `gain`");
+        let err = parse_program("params { s = \"x\" }\nrun for s\n").expect_err("not a quantity");
+        assert_eq!(err.message, "parameter `s` is not a quantity");
+        let err = parse_program("run for 1 ms\nparams { g = 1 }\n").expect_err("late");
+        assert_eq!(
+            err.message,
+            "the `params` block must come before every item"
+        );
+        let err = parse_program("params { g = 1, g = 2 }\n").expect_err("duplicate");
+        assert_eq!(err.message, "duplicate parameter `g`");
+    }
+
+    #[test]
+    fn env_references_are_substituted_before_parsing() {
+        let env = |name: &str| match name {
+            "RATE" => Some("40".to_string()),
+            "JOB" => Some("1234".to_string()),
+            _ => None,
+        };
+        let src = "params { label = \"job-${JOB}, $${JOB}\" }\n\
+                   neuron LIF { tau_m = 10 ms }\n\
+                   layer In[${SIZE:8}] : LIF\n\
+                   stimulus In = Poisson(rate=${RATE}Hz)\n\
+                   // ${UNSET} in a comment is left alone\n\
+                   run for ${DUR:20 ms}\n";
+        let program = super::parse_program_with_env(src, &[], &env).expect("parse");
+        validate(&program).expect("valid");
+        assert_eq!(
+            program.params[0].value,
+            Expr::String("job-1234, ${JOB}".to_string())
+        );
+        let Item::Stimulus(stim) = &program.items[2] else {
+            panic!("expected a stimulus");
+        };
+        assert_eq!(stim.model.to_string(), "Poisson(rate=40 Hz)");
+        let Item::Run(run) = &program.items[3] else {
+            panic!("expected a run");
+        };
+        assert_eq!(
+            &src[run.duration.span.start..run.duration.span.end],
+            "${DUR:20 ms}"
+        );
+
+        let err = super::parse_program_with_env("run for ${DUR}\n", &[], &env).expect_err("unset");
+        assert_eq!(err.message, "`DUR` is not set and has no default");
+        assert_eq!(err.span, Some(Span::new(8, 14)));
+        let err =
+            super::parse_program_with_env("run for ${DUR:1 #}\n", &[], &env).expect_err("bad");
+        assert!(
+            err.message.starts_with("in `${DUR:1 #}`: "),
+            "{}",
+            err.message
+        );
+        let err = super::parse_program_with_env("run for ${DUR\n", &[], &env).expect_err("open");
+        assert_eq!(err.message, "unterminated `${`");
+    }
+
+    #[test]
+    fn seeds_may_be_hex_or_come_from_the_environment() {
+        let env = |name: &str| match name {
+            "RUN_SEED" => Some("0xDEADBEEF\n".to_string()),
+            "JOB" => Some("17".to_string()),
+            "BAD" => Some("-3".to_string()),
+            _ => None,
+        };
+        let seed = |src: &str| match super::parse_program_with_env(src, &[], &env) {
+            Ok(program) => match &program.items[0] {
+                Item::Seed(stmt) => Ok(stmt.value),
+                other => panic!("expected a seed, got {other:?}"),
+            },
+            Err(diag) => Err(diag.message),
+        };
+        assert_eq!(seed("seed 0xDEADBEEF\n"), Ok(0xdead_beef));
+        assert_eq!(seed("seed 0Xff\n"), Ok(255));
+        assert_eq!(seed("seed from_env(\"RUN_SEED\")\n"), Ok(0xdead_beef));
+        assert_eq!(seed("seed from_env(\"JOB\")\n"), Ok(17));
+        assert_eq!(
+            seed("seed from_env(\"BAD\")\n"),
+            Err("`BAD` is `-3`, not a seed".to_string())
+        );
+
+        let err = super::parse_program_with_env("seed from_env(\"UNSET\")\n", &[], &env)
+            .expect_err("unset");
+        assert_eq!(err.message, "`UNSET` is not set");
+        assert_eq!(err.span, Some(Span::new(5, 22)));
+        // Without an environment to read, as for `converge serve`.
+        let err = parse_program("seed from_env(\"PATH\")\n").expect_err("no env");
+        assert_eq!(err.message, "`PATH` is not set");
+        let err = parse_program("seed 0x10000000000000000\n").expect_err("too large");
+        assert_eq!(err.message, "invalid integer for seed value");
+    }
+
+    #[test]
+    fn items_assignments_and_calls_have_spans() {
+        let src = "/// Leaky.\n\
+                   neuron LIF { tau_m = 10 ms, v_th = 1 }\n\
+                   layer In[16] : LIF\n\
+                   layer Out[4] : LIF\n\
+                   connect In -> Out { w = Normal(0.5, 0.1) }\n\
+                   stimulus In = Poisson(rate=20 Hz)\n\
+                   run for 10 ms step 0.5 ms\n\
+                   run for 5 ms\n\
+                   seed 1\n\
+                   seed 2\n";
+        let program = parse_program(src).expect("parse");
+        let text = |span: &Span| &src[span.start..span.end];
+        let [
+            Item::Neuron(neuron),
+            Item::Layer(layer),
+            _,
+            Item::Connect(connect),
+            Item::Stimulus(stim),
+            Item::Run(run),
+            ..,
+        ] = &program.items[..]
+        else {
+            panic!("unexpected items");
+        };
+        assert_eq!(text(&neuron.span), "neuron LIF { tau_m = 10 ms, v_th = 1 }");
+        assert_eq!(text(&neuron.body[0].span), "tau_m = 10 ms");
+        assert_eq!(text(&layer.span), "layer In[16] : LIF");
+        assert_eq!(text(&layer.size_span), "16");
+        assert_eq!(
+            text(&connect.span),
+            "connect In -> Out { w = Normal(0.5, 0.1) }"
+        );
+        let Expr::Call(call) = &connect.body[0].value else {
+            panic!("expected a call");
+        };
+        assert_eq!(text(&call.span), "Normal(0.5, 0.1)");
+        assert_eq!(text(&stim.span), "stimulus In = Poisson(rate=20 Hz)");
+        assert_eq!(text(&run.span), "run for 10 ms step 0.5 ms");
+
+        let diags = validate(&program).expect_err("two runs and seeds");
+        let at: Vec<(&str, &str)> = diags
+            .iter()
+            .map(|d| (d.message.as_str(), text(d.span.as_ref().expect("span"))))
+            .collect();
+        assert_eq!(
+            at,
+            [
+                ("only one `seed` statement is allowed", "seed"),
+                ("only one `run` statement is allowed", "run for 5 ms"),
+            ]
+        );
+    }
+
+    #[test]
+    fn recovering_parse_reports_every_problem() {
+        let src = "neuron LIF { tau_m = 10 ms, note = \"a\\q\" }\n\
+                   layer In[4] : LIF #\n\
+                   layer Out[4] LIF\n\
+                   connect In -> Out { w = 0.5 }\n\
+                   stimulus In = Poisson(rate=20 Hz) ~\n\
+                   run for 10 ms\n\
+                   record rate from Out window \"10 ms\n";
+        let first = parse_program(src).expect_err("invalid");
+        assert_eq!(first.message, "invalid string escape");
+
+        let diags = super::parse_program_recovering(src).expect_err("invalid");
+        let found: Vec<(&str, &str)> = diags
+            .iter()
+            .map(|d| {
+                let span = d.span.clone().expect("span");
+                (d.message.as_str(), &src[span.start..span.end])
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("invalid string escape", "\\q"),
+                ("unexpected character '#'", "#"),
+                ("expected `:`", "LIF"),
+                ("unexpected character '~'", "~"),
+                ("unterminated string", "\"10 ms\n"),
+            ]
+        );
+
+        let fixed = src.replace(" #", "").replace(" ~", "");
+        let fixed = fixed.replace("\\q", "q").replace("\"10 ms", "10 ms");
+        let fixed = fixed.replace("[4] LIF", "[4] : LIF");
+        let program = super::parse_program_recovering(&fixed).expect("parse");
+        assert_eq!(program, parse_program(&fixed).expect("parse"));
+    }
+
+    #[test]
+    fn identifiers_may_be_unicode() {
+        let src = "neuron Nœud { tau_m = 10 ms }\n\
+                   layer Schicht_1[4] : Nœud\n\
+                   layer τ[2] : Nœud\n\
+                   connect Schicht_1 -> τ { w = 0.5 }\n\
+                   run for 5 ms\n";
+        let program = parse_program(src).expect("parse");
+        validate(&program).expect("valid");
+        let Item::Layer(layer) = &program.items[2] else {
+            panic!("expected a layer");
+        };
+        assert_eq!(layer.name.name, "τ");
+        assert_eq!(&src[layer.name.span.start..layer.name.span.end], "τ");
+
+        let err = parse_program("layer A[4] : LIF ·\n").expect_err("not a letter");
+        assert_eq!(err.message, "unexpected character '·'");
+        let err = parse_program("layer 1a[4] : LIF\n").expect_err("digit first");
+        assert_eq!(err.span, Some(Span::new(6, 7)));
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let src = "// Zeitkonstante τ in µs\n\
+                   neuron LIF { note = \"café\", tau_m = 10 ms # }\n";
+        let err = parse_program(src).expect_err("stray character");
+        let span = err.span.clone().expect("span");
+        assert_eq!(span.line_col(src), (2, 43));
+        assert_eq!(span.line_col_utf16(src), (2, 43));
+        assert_eq!(Span::new(22, 22).line_col(src), (1, 22));
+        let emoji = "// 🧠 x\n";
+        assert_eq!(Span::new(8, 9).line_col(emoji), (1, 6));
+        assert_eq!(Span::new(8, 9).line_col_utf16(emoji), (1, 7));
+
+        let shown = format_diagnostic(src, &err);
+        let lines: Vec<&str> = shown.lines().collect();
+        assert_eq!(lines[1], "  --> line 2, col 43");
+        assert_eq!(
+            lines[3],
+            "  2 | neuron LIF { note = \"café\", tau_m = 10 ms # }"
         );
+        assert_eq!(lines[4], format!("   | {}^", " ".repeat(42)));
     }
 }
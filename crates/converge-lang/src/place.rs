@@ -0,0 +1,332 @@
+//! Placement of a program onto the cores of a [`TargetProfile`].
+//!
+//! Each layer is split into the fewest contiguous, equal-sized neuron
+//! ranges that fit a core's neuron and synapse limits, where a core stores
+//! the synapses into its own neurons. Ranges are then packed onto cores in
+//! program order: each joins the core with room that it shares the most
+//! synapses with, so small connected layers land together and their spikes
+//! stay on one core. Only a range with nowhere to go opens a new core.
+
+use std::collections::BTreeSet;
+
+use crate::ast::{ConnectKind, Item, Program};
+use crate::diagnostic::Diagnostic;
+use crate::emit::JsonWriter;
+use crate::stats::topology;
+use crate::target::TargetProfile;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub target: String,
+    pub cores: Vec<Core>,
+    /// Pairs of a source neuron and another core it has synapses on. Each
+    /// spike of the neuron is sent once along each of its routes.
+    pub routes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Core {
+    pub slices: Vec<Slice>,
+    pub neurons: u64,
+    /// Chemical synapses into the core's neurons.
+    pub synapses: u64,
+}
+
+/// Neurons `start..end` of one layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slice {
+    pub layer: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Places a validated program. Fails only when a single neuron has more
+/// synapses than a core holds; a placement needing more cores than the
+/// target has is returned, and [`check_target`](crate::target::check_target)
+/// reports it.
+pub fn place(program: &Program, profile: &TargetProfile) -> Result<Placement, Diagnostic> {
+    let topo = topology(program);
+    let layers = &topo.stats.layers;
+    let max_neurons = profile.max_neurons_per_core.unwrap_or(u64::MAX);
+    let max_synapses = profile.max_synapses_per_core.unwrap_or(u64::MAX);
+    let defs: Vec<_> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Layer(def) => Some(def),
+            _ => None,
+        })
+        .collect();
+
+    // Synapses exchanged between each pair of layers, either way.
+    let mut affinity = vec![vec![0u64; layers.len()]; layers.len()];
+    for (edge, conn) in topo.edges.iter().zip(&topo.stats.connections) {
+        affinity[edge.src][edge.dst] = affinity[edge.src][edge.dst].saturating_add(conn.synapses);
+        affinity[edge.dst][edge.src] = affinity[edge.dst][edge.src].saturating_add(conn.synapses);
+    }
+
+    let mut cores: Vec<Core> = Vec::new();
+    let mut core_layers: Vec<BTreeSet<usize>> = Vec::new();
+    // Per layer, the core of each range, in neuron order.
+    let mut assigned: Vec<Vec<(u64, u64, usize)>> = vec![Vec::new(); layers.len()];
+    for (idx, layer) in layers.iter().enumerate() {
+        let fan_in = &topo.fan_in[idx];
+        let degrees: Vec<u64> = (0..layer.size).map(|j| fan_in.degree(j)).collect();
+        if layer.in_degree.max > max_synapses {
+            return Err(Diagnostic::new(format!(
+                "neurons of layer `{}` have up to {} synapses, a core of {} holds {max_synapses}",
+                layer.name, layer.in_degree.max, profile.name
+            ))
+            .with_span(defs[idx].name.span.clone()));
+        }
+        for (start, end) in split(&degrees, max_neurons, max_synapses) {
+            let neurons = end - start;
+            let synapses: u64 = degrees[start as usize..end as usize].iter().sum();
+            let fits = |core: &Core| {
+                core.neurons + neurons <= max_neurons && core.synapses + synapses <= max_synapses
+            };
+            let best = cores
+                .iter()
+                .enumerate()
+                .filter(|(_, core)| fits(core))
+                .map(|(c, _)| {
+                    let shared: u64 = core_layers[c].iter().map(|&l| affinity[idx][l]).sum();
+                    (c, shared)
+                })
+                // The earliest core wins a tie.
+                .max_by_key(|&(c, shared)| (shared, std::cmp::Reverse(c)))
+                .map(|(c, _)| c);
+            let c = best.unwrap_or_else(|| {
+                cores.push(Core::default());
+                core_layers.push(BTreeSet::new());
+                cores.len() - 1
+            });
+            let core = &mut cores[c];
+            core.neurons += neurons;
+            core.synapses += synapses;
+            match core.slices.last_mut() {
+                Some(last) if last.layer == layer.name && last.end == start => last.end = end,
+                _ => core.slices.push(Slice {
+                    layer: layer.name.clone(),
+                    start,
+                    end,
+                }),
+            }
+            core_layers[c].insert(idx);
+            assigned[idx].push((start, end, c));
+        }
+    }
+
+    let core_of = |layer: usize, neuron: u64| {
+        let ranges = &assigned[layer];
+        ranges[ranges.partition_point(|&(_, end, _)| end <= neuron)].2
+    };
+    let mut routes = 0u64;
+    for src in 0..layers.len() {
+        let edges: Vec<_> = topo
+            .edges
+            .iter()
+            .zip(&topo.stats.connections)
+            .filter(|(edge, conn)| edge.src == src && conn.kind == ConnectKind::Chemical)
+            .map(|(edge, _)| edge)
+            .collect();
+        let dense: BTreeSet<usize> = edges
+            .iter()
+            .filter(|edge| edge.mask.is_empty())
+            .flat_map(|edge| assigned[edge.dst].iter().map(|&(_, _, c)| c))
+            .collect();
+        let masked: Vec<_> = edges.iter().filter(|edge| !edge.mask.is_empty()).collect();
+        for &(start, end, c) in &assigned[src] {
+            if masked.is_empty() {
+                let remote = dense.len() as u64 - u64::from(dense.contains(&c));
+                routes = routes.saturating_add((end - start).saturating_mul(remote));
+                continue;
+            }
+            for i in start..end {
+                let mut targets = dense.clone();
+                for edge in &masked {
+                    for j in 0..layers[edge.dst].size {
+                        if edge.keeps(i, j) {
+                            targets.insert(core_of(edge.dst, j));
+                        }
+                    }
+                }
+                targets.remove(&c);
+                routes = routes.saturating_add(targets.len() as u64);
+            }
+        }
+    }
+
+    Ok(Placement {
+        target: profile.name.clone(),
+        cores,
+        routes,
+    })
+}
+
+/// Splits a layer into the fewest equal contiguous ranges, sizes differing
+/// by at most one, whose neurons and synapses fit a core.
+fn split(degrees: &[u64], max_neurons: u64, max_synapses: u64) -> Vec<(u64, u64)> {
+    let n = degrees.len() as u64;
+    if n == 0 {
+        return Vec::new();
+    }
+    let total: u64 = degrees.iter().sum();
+    let mut k = n
+        .div_ceil(max_neurons)
+        .max(total.div_ceil(max_synapses))
+        .max(1);
+    loop {
+        let ranges: Vec<(u64, u64)> = (0..k)
+            .map(|r| (r * n / k, (r + 1) * n / k))
+            .filter(|(start, end)| start < end)
+            .collect();
+        let fits = ranges.iter().all(|&(start, end)| {
+            degrees[start as usize..end as usize].iter().sum::<u64>() <= max_synapses
+        });
+        // One neuron per range always fits: `place` checks every degree.
+        if fits || k >= n {
+            return ranges;
+        }
+        k += 1;
+    }
+}
+
+pub fn placement_json(placement: &Placement) -> String {
+    let mut w = JsonWriter::new();
+    w.obj_begin();
+    w.kv_str("target", &placement.target);
+    w.comma_nl();
+    w.key("cores");
+    w.array_begin();
+    for (id, core) in placement.cores.iter().enumerate() {
+        if id != 0 {
+            w.comma();
+        }
+        w.nl();
+        w.obj_begin();
+        w.kv_u64("id", id as u64);
+        w.comma_nl();
+        w.kv_u64("neurons", core.neurons);
+        w.comma_nl();
+        w.kv_u64("synapses", core.synapses);
+        w.comma_nl();
+        w.key("slices");
+        w.array_begin();
+        for (idx, slice) in core.slices.iter().enumerate() {
+            if idx != 0 {
+                w.comma();
+            }
+            w.nl();
+            w.obj_begin();
+            w.kv_str("layer", &slice.layer);
+            w.comma_nl();
+            w.kv_u64("start", slice.start);
+            w.comma_nl();
+            w.kv_u64("end", slice.end);
+            w.obj_end();
+        }
+        if !core.slices.is_empty() {
+            w.nl();
+        }
+        w.array_end();
+        w.obj_end();
+    }
+    if !placement.cores.is_empty() {
+        w.nl();
+    }
+    w.array_end();
+    w.comma_nl();
+    w.kv_u64("inter_core_routes", placement.routes);
+    w.nl();
+    w.obj_end();
+    w.nl();
+    w.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::parser::parse_program;
+
+    fn profile(src: &str) -> TargetProfile {
+        TargetProfile::from_toml(src).expect("profile")
+    }
+
+    #[test]
+    fn splits_large_layers_and_packs_connected_small_ones() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms }
+layer In[250] : LIF
+layer Hid[40] : LIF
+layer Out[10] : LIF
+layer Aux[30] : LIF
+connect In -> Hid { w = 0.1 }
+connect Hid -> Out { w = 0.1 }
+run for 10 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let placement = place(&program, &profile("[core]\nmax_neurons = 100\n")).expect("place");
+        let slices: Vec<Vec<(&str, u64, u64)>> = placement
+            .cores
+            .iter()
+            .map(|core| {
+                core.slices
+                    .iter()
+                    .map(|s| (s.layer.as_str(), s.start, s.end))
+                    .collect()
+            })
+            .collect();
+        // In splits into 83/83/84 and Hid no longer fits beside it; Out
+        // joins Hid, and Aux the only core left with room.
+        assert_eq!(
+            slices,
+            [
+                vec![("In", 0, 83)],
+                vec![("In", 83, 166)],
+                vec![("In", 166, 250)],
+                vec![("Hid", 0, 40), ("Out", 0, 10), ("Aux", 0, 30)],
+            ]
+        );
+        assert_eq!(placement.cores[3].synapses, 40 * 250 + 10 * 40);
+        // Every In neuron reaches the Hid core; Hid to Out stays local.
+        assert_eq!(placement.routes, 250);
+
+        let value = json::parse(&placement_json(&placement)).expect("valid json");
+        assert_eq!(
+            value.get("inter_core_routes").and_then(json::Value::as_f64),
+            Some(250.0)
+        );
+
+        let err = place(&program, &profile("[core]\nmax_synapses = 100\n")).unwrap_err();
+        assert_eq!(
+            err.message,
+            "neurons of layer `Hid` have up to 250 synapses, a core of target holds 100"
+        );
+    }
+
+    #[test]
+    fn balances_synapses_and_counts_routes_after_disconnect() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms }
+layer A[4] : LIF
+layer B[4] : LIF
+connect A -> B { w = 0.1 }
+disconnect A -> B where src != dst
+run for 10 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let placement = place(
+            &program,
+            &profile("[core]\nmax_neurons = 8\nmax_synapses = 2\n"),
+        )
+        .expect("place");
+        // B's four synapses need two cores; its first half joins A.
+        assert_eq!(placement.cores.len(), 2);
+        assert_eq!(placement.cores[0].synapses, 2);
+        assert_eq!(placement.cores[1].neurons, 2);
+        // A0 and A1 reach B0 and B1 on their own core; A2 and A3 cross.
+        assert_eq!(placement.routes, 2);
+    }
+}
@@ -0,0 +1,306 @@
+//! Canonical `.cv` text for a parsed program: one item per line, bodies on
+//! one line, and the values the parser substituted for `${...}` and
+//! parameters written out. What `converge elaborate` prints.
+
+use std::fmt::{self, Write};
+
+use crate::ast::{AssertCond, Assign, Call, ConnectKind, Item, Program, SweepDef};
+use crate::lexer::quote;
+
+/// `program` as `.cv` source that parses back to the same model. The
+/// `params` block is left out, as every reference to it has been replaced.
+///
+/// For every program the parser produces, and every program from
+/// `strategies::program` (with the `proptest` feature), parsing the printed
+/// text gives `program` back once spans are cleared with
+/// [`crate::visit::clear_spans`], apart from its `params` and `deprecated`
+/// lists.
+pub fn print_program(program: &Program) -> String {
+    let mut out = String::new();
+    if let Some(pragma) = &program.version {
+        let _ = writeln!(out, "converge \"{}\"", pragma.version);
+    }
+    for item in &program.items {
+        let _ = print_item(&mut out, item, "");
+    }
+    out
+}
+
+fn print_item(out: &mut String, item: &Item, indent: &str) -> fmt::Result {
+    match item {
+        Item::Neuron(def) => {
+            print_preamble(out, def.doc.as_deref(), &def.attributes, indent)?;
+            writeln!(out, "{indent}neuron {} {}", def.name.name, Body(&def.body))
+        }
+        Item::Layer(def) => {
+            print_preamble(out, def.doc.as_deref(), &def.attributes, indent)?;
+            writeln!(
+                out,
+                "{indent}layer {}[{}] : {}",
+                def.name.name, def.size, def.neuron.name
+            )
+        }
+        Item::Connect(def) => {
+            print_preamble(out, def.doc.as_deref(), &def.attributes, indent)?;
+            let kind = match def.kind {
+                ConnectKind::Chemical => "",
+                ConnectKind::Electrical => " electrical",
+            };
+            writeln!(
+                out,
+                "{indent}connect {}{kind} {}",
+                def.label(),
+                Body(&def.body)
+            )
+        }
+        Item::Modulator(def) => {
+            writeln!(
+                out,
+                "{indent}modulator {} {}",
+                def.name.name,
+                Body(&def.body)
+            )
+        }
+        Item::Experiment(def) => writeln!(out, "{indent}experiment {}", Body(&def.body)),
+        Item::Sweep(def) => print_sweep(out, def, indent),
+        Item::Readout(def) => {
+            write!(
+                out,
+                "{indent}readout {} labels {}",
+                def.layer.name,
+                quote(&def.labels)
+            )?;
+            if let Some(rule) = &def.rule {
+                write!(out, " rule = {}", rule.name)?;
+            }
+            writeln!(out)
+        }
+        Item::Stimulus(def) => {
+            write!(out, "{indent}stimulus {} = {}", def.layer.name, def.model)?;
+            if let Some(combine) = def.combine {
+                write!(out, " combine = {}", combine.as_str())?;
+            }
+            writeln!(out)
+        }
+        Item::Run(run) => {
+            write!(out, "{indent}run for {}", run.duration)?;
+            if let Some(step) = &run.step {
+                write!(out, " step {step}")?;
+            }
+            writeln!(out)
+        }
+        Item::Seed(seed) => writeln!(out, "{indent}seed {}", seed.value),
+        Item::Assert(stmt) => match &stmt.cond {
+            AssertCond::Compare { op, value } => {
+                writeln!(
+                    out,
+                    "{indent}assert {} {} {value}",
+                    stmt.metric,
+                    op.as_str()
+                )
+            }
+            AssertCond::Between { low, high } => {
+                writeln!(
+                    out,
+                    "{indent}assert {} between {low} and {high}",
+                    stmt.metric
+                )
+            }
+        },
+        Item::Disconnect(stmt) => {
+            write!(out, "{indent}disconnect {}", stmt.target)?;
+            if let Some(filter) = &stmt.filter {
+                write!(out, " where {filter}")?;
+            }
+            writeln!(out)
+        }
+        Item::Reward(stmt) => writeln!(out, "{indent}reward {} at {}", stmt.value, stmt.at),
+        Item::Record(stmt) => writeln!(
+            out,
+            "{indent}record rate from {} window {}",
+            stmt.layer.name, stmt.window
+        ),
+        Item::Probe(def) => writeln!(out, "{indent}probe {} = {def}", def.name.name),
+        Item::Network(def) => {
+            writeln!(out, "{indent}network {} {{", def.name.name)?;
+            let inner = format!("{indent}    ");
+            for item in &def.items {
+                print_item(out, item, &inner)?;
+            }
+            writeln!(out, "{indent}}}")
+        }
+    }
+}
+
+/// The `///` lines and `@name(...)` attributes before an item.
+fn print_preamble(
+    out: &mut String,
+    doc: Option<&str>,
+    attributes: &[Call],
+    indent: &str,
+) -> fmt::Result {
+    for line in doc.into_iter().flat_map(|doc| doc.split('\n')) {
+        writeln!(out, "{indent}///{}", prefixed(line.trim_end_matches('\r')))?;
+    }
+    for attribute in attributes {
+        if attribute.args.is_empty() {
+            writeln!(out, "{indent}@{}", attribute.name.name)?;
+        } else {
+            writeln!(out, "{indent}@{attribute}")?;
+        }
+    }
+    Ok(())
+}
+
+/// A doc line with the space after `///` put back, when it had text.
+fn prefixed(line: &str) -> String {
+    if line.is_empty() {
+        String::new()
+    } else {
+        format!(" {line}")
+    }
+}
+
+fn print_sweep(out: &mut String, def: &SweepDef, indent: &str) -> fmt::Result {
+    writeln!(out, "{indent}experiment {{")?;
+    for run in &def.runs {
+        writeln!(out, "{indent}    {run}")?;
+    }
+    if !def.report.is_empty() {
+        let metrics: Vec<String> = def.report.iter().map(ToString::to_string).collect();
+        writeln!(out, "{indent}    report {}", metrics.join(", "))?;
+    }
+    writeln!(out, "{indent}}}")
+}
+
+/// `{ key = value, ... }`, or `{}` when empty.
+struct Body<'a>(&'a [Assign]);
+
+impl fmt::Display for Body<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("{}");
+        }
+        f.write_str("{ ")?;
+        for (idx, assign) in self.0.iter().enumerate() {
+            if idx != 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} = {}", assign.key.name, assign.value)?;
+        }
+        f.write_str(" }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::cvir_json;
+    use crate::parser::{parse_program, parse_program_with_env};
+
+    #[test]
+    fn prints_models_that_parse_back_the_same() {
+        let src = "converge \"0.2\"\n\
+                   params { rate = 20 Hz, g = 0.4 }\n\
+                   /// Leaky.\n\
+                   @hw(core=1)\n\
+                   neuron LIF { tau_m = 20 ms, v_th = 1 }\n\
+                   layer In[8] : LIF\n\
+                   layer Out[4] : LIF\n\
+                   connect ff: In -> Out { w = Normal(g, 0.1), d = 1 ms }\n\
+                   connect In <-> Out electrical { g = 0.1 }\n\
+                   disconnect ff where src < 2 and (dst == 0 or dst == 3)\n\
+                   stimulus In = Poisson(rate=rate)\n\
+                   record rate from Out window 10 ms\n\
+                   probe p = v(Out[0..2]) every 0.5 ms\n\
+                   seed 3\n\
+                   run for 50 ms step 0.5 ms\n\
+                   assert spikes(Out) > 0\n\
+                   assert rate(In) between 1 Hz and 90 Hz\n";
+        let program = parse_program(src).expect("parse");
+        let printed = print_program(&program);
+        assert!(printed.contains("connect ff: In -> Out { w = Normal(0.4, 0.1), d = 1 ms }"));
+        assert!(printed.contains("stimulus In = Poisson(rate=20 Hz)"));
+        assert!(!printed.contains("params"));
+        let mut reparsed = parse_program(&printed).expect("reparse");
+        reparsed.params = program.params.clone();
+        assert_eq!(cvir_json(&reparsed), cvir_json(&program));
+    }
+
+    #[test]
+    fn quotes_strings_so_they_lex_back() {
+        let src = "neuron N { note = \"$${HOME} \\\"q\\\" \\\\ tab\\t\" }\n";
+        let program = parse_program(src).expect("parse");
+        let printed = print_program(&program);
+        assert_eq!(printed, src);
+        let env = |_: &str| None;
+        let again = parse_program_with_env(&printed, &[], &env).expect("reparse");
+        assert_eq!(again, program);
+    }
+
+    #[test]
+    fn prints_environment_values_and_networks() {
+        let src = "neuron LIF { tau_m = 20 ms }\n\
+                   network Small {\n\
+                       layer In[${SIZE}] : LIF\n\
+                       stimulus In = Poisson(rate=20 Hz)\n\
+                   }\n\
+                   run for 10 ms\n";
+        let env = |name: &str| (name == "SIZE").then(|| "16".to_string());
+        let program = parse_program_with_env(src, &[], &env).expect("parse");
+        assert_eq!(
+            print_program(&program),
+            "neuron LIF { tau_m = 20 ms }\n\
+             network Small {\n    \
+                 layer In[16] : LIF\n    \
+                 stimulus In = Poisson(rate=20 Hz)\n\
+             }\n\
+             run for 10 ms\n"
+        );
+    }
+}
+
+#[cfg(all(test, feature = "arbitrary"))]
+mod arbitrary_tests {
+    use super::*;
+    use crate::lexer::lex_with_env;
+    use crate::parser::parse_program_with_env;
+    use crate::visit::clear_spans;
+    use arbitrary::{Arbitrary, Unstructured};
+
+    #[test]
+    fn printed_programs_lex_and_reprint_the_same() {
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        for _ in 0..2000 {
+            let bytes: Vec<u8> = (0..1024)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            let Ok(program) = Program::arbitrary_take_rest(Unstructured::new(&bytes)) else {
+                continue;
+            };
+            let printed = print_program(&program);
+            let env = |_: &str| None;
+            if let Err(diag) = lex_with_env(&printed, &env) {
+                panic!("{}\n{printed}", diag.message);
+            }
+            // Not every tree is one the parser builds, such as an empty
+            // sweep, so the check starts from what the parser made of it.
+            if let Ok(mut parsed_once) = parse_program_with_env(&printed, &[], &env) {
+                let printed = print_program(&parsed_once);
+                let mut again = parse_program_with_env(&printed, &[], &env)
+                    .unwrap_or_else(|diag| panic!("{}\n{printed}", diag.message));
+                assert_eq!(print_program(&again), printed);
+                clear_spans(&mut parsed_once);
+                clear_spans(&mut again);
+                parsed_once.params.clear();
+                parsed_once.deprecated.clear();
+                assert_eq!(again, parsed_once);
+            }
+        }
+    }
+}
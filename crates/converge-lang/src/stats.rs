@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{ConnectDef, ConnectKind, Expr, Item, Program, SynapseFilter};
+use crate::emit::{JsonWriter, emit_expr};
+
+/// Static topology of a validated program: degrees per layer and synapse
+/// counts per connection, derived from the AST without building the network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopologyStats {
+    pub layers: Vec<LayerStats>,
+    pub connections: Vec<ConnectionStats>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerStats {
+    pub name: String,
+    pub size: u64,
+    pub in_degree: DegreeStats,
+    pub out_degree: DegreeStats,
+}
+
+/// Degree distribution over the neurons of one layer.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DegreeStats {
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionStats {
+    pub name: Option<String>,
+    pub src: String,
+    pub dst: String,
+    pub kind: ConnectKind,
+    /// Synapses, or gap junctions for electrical connections.
+    pub synapses: u64,
+    /// Weight expression as written, or the coupling `g` for electrical
+    /// connections; `None` means the default of 1.
+    pub weight: Option<Expr>,
+    /// Delay expression as written; `None` means the default of 0 ms.
+    pub delay: Option<Expr>,
+    /// The connection's doc comment.
+    pub description: Option<String>,
+}
+
+pub fn topology_stats(program: &Program) -> TopologyStats {
+    topology(program).stats
+}
+
+/// The statistics plus what they are derived from, for passes that need
+/// individual neurons, such as placement.
+pub(crate) struct Topology<'a> {
+    pub(crate) stats: TopologyStats,
+    /// Per-neuron in-degree of each layer.
+    pub(crate) fan_in: Vec<Fan>,
+    /// One per entry of `stats.connections`.
+    pub(crate) edges: Vec<Edge<'a>>,
+}
+
+pub(crate) struct Edge<'a> {
+    pub(crate) src: usize,
+    pub(crate) dst: usize,
+    /// Filters of the `disconnect` statements that apply.
+    pub(crate) mask: Vec<Option<&'a SynapseFilter>>,
+}
+
+impl Edge<'_> {
+    /// Whether the synapse from source neuron `i` to target `j` survives
+    /// every `disconnect`.
+    pub(crate) fn keeps(&self, i: u64, j: u64) -> bool {
+        !self.mask.iter().any(|f| f.is_none_or(|f| f.matches(i, j)))
+    }
+}
+
+pub(crate) fn topology(program: &Program) -> Topology<'_> {
+    let mut layers = Vec::new();
+    let mut index = HashMap::new();
+    for item in &program.items {
+        if let Item::Layer(def) = item {
+            index.insert(def.name.name.as_str(), layers.len());
+            layers.push(LayerStats {
+                name: def.name.name.clone(),
+                size: def.size,
+                in_degree: DegreeStats::default(),
+                out_degree: DegreeStats::default(),
+            });
+        }
+    }
+
+    // `disconnect` removes synapses from connections declared before it;
+    // `None` removes all of them.
+    let mut masks: Vec<Vec<Option<&SynapseFilter>>> = Vec::new();
+    let mut defs = Vec::new();
+    for item in &program.items {
+        match item {
+            Item::Connect(def) => {
+                defs.push(def);
+                masks.push(Vec::new());
+            }
+            Item::Disconnect(stmt) => {
+                for (def, mask) in defs.iter().zip(masks.iter_mut()) {
+                    if stmt.target.matches(def) {
+                        mask.push(stmt.filter.as_ref());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut fan_in = vec![Fan::default(); layers.len()];
+    let mut fan_out = vec![Fan::default(); layers.len()];
+    let mut connections = Vec::new();
+    let mut edges = Vec::new();
+    for (def, mask) in defs.into_iter().zip(masks) {
+        let ConnectDef { src, dst, body, .. } = def;
+        let (Some(&s), Some(&d)) = (index.get(src.name.as_str()), index.get(dst.name.as_str()))
+        else {
+            continue;
+        };
+        let (src_size, dst_size) = (layers[s].size, layers[d].size);
+        let synapses = if def.kind == ConnectKind::Electrical {
+            // Gap junctions are not directed, so they count toward neither
+            // degree; within one layer each pair is coupled once.
+            let mut kept = 0u64;
+            if mask.is_empty() && s != d {
+                kept = src_size.saturating_mul(dst_size);
+            } else if mask.is_empty() {
+                kept = src_size.saturating_mul(src_size.saturating_sub(1)) / 2;
+            } else {
+                for i in 0..src_size {
+                    let first = if s == d { i + 1 } else { 0 };
+                    for j in first..dst_size {
+                        if !mask.iter().any(|f| f.is_none_or(|f| f.matches(i, j))) {
+                            kept += 1;
+                        }
+                    }
+                }
+            }
+            kept
+        } else if mask.is_empty() {
+            // Connections are dense: every source neuron reaches every target.
+            fan_out[s].uniform = fan_out[s].uniform.saturating_add(dst_size);
+            fan_in[d].uniform = fan_in[d].uniform.saturating_add(src_size);
+            src_size.saturating_mul(dst_size)
+        } else {
+            let mut kept = 0u64;
+            for i in 0..src_size {
+                for j in 0..dst_size {
+                    if mask.iter().any(|f| f.is_none_or(|f| f.matches(i, j))) {
+                        continue;
+                    }
+                    kept += 1;
+                    fan_out[s].add(i, src_size);
+                    fan_in[d].add(j, dst_size);
+                }
+            }
+            kept
+        };
+        let param = |key: &str| {
+            body.iter()
+                .find(|a| a.key.name == key)
+                .map(|a| a.value.clone())
+        };
+        connections.push(ConnectionStats {
+            name: def.name.as_ref().map(|n| n.name.clone()),
+            src: src.name.clone(),
+            dst: dst.name.clone(),
+            kind: def.kind,
+            synapses,
+            weight: param(match def.kind {
+                ConnectKind::Chemical => "w",
+                ConnectKind::Electrical => "g",
+            }),
+            delay: param("d"),
+            description: def.doc.clone(),
+        });
+        edges.push(Edge {
+            src: s,
+            dst: d,
+            mask,
+        });
+    }
+
+    for (idx, layer) in layers.iter_mut().enumerate() {
+        layer.in_degree = fan_in[idx].stats();
+        layer.out_degree = fan_out[idx].stats();
+    }
+
+    Topology {
+        stats: TopologyStats {
+            layers,
+            connections,
+        },
+        fan_in,
+        edges,
+    }
+}
+
+/// Per-neuron degree of one layer: a count every neuron shares, plus
+/// individual counts once a masked connection touches the layer.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Fan {
+    uniform: u64,
+    per_neuron: Vec<u64>,
+}
+
+impl Fan {
+    fn add(&mut self, neuron: u64, size: u64) {
+        if self.per_neuron.is_empty() {
+            self.per_neuron = vec![0; size as usize];
+        }
+        self.per_neuron[neuron as usize] += 1;
+    }
+
+    pub(crate) fn degree(&self, neuron: u64) -> u64 {
+        self.uniform + self.per_neuron.get(neuron as usize).copied().unwrap_or(0)
+    }
+
+    fn stats(&self) -> DegreeStats {
+        if self.per_neuron.is_empty() {
+            return DegreeStats::uniform(self.uniform);
+        }
+        let min = self.per_neuron.iter().min().copied().unwrap_or(0);
+        let max = self.per_neuron.iter().max().copied().unwrap_or(0);
+        let sum: u64 = self.per_neuron.iter().sum();
+        DegreeStats {
+            min: self.uniform + min,
+            max: self.uniform + max,
+            mean: self.uniform as f64 + sum as f64 / self.per_neuron.len() as f64,
+        }
+    }
+}
+
+impl DegreeStats {
+    fn uniform(degree: u64) -> Self {
+        Self {
+            min: degree,
+            max: degree,
+            mean: degree as f64,
+        }
+    }
+}
+
+impl ConnectionStats {
+    /// `name: src -> dst`, or `src -> dst` when unnamed; electrical
+    /// connections use `<->`.
+    pub fn label(&self) -> String {
+        let arrow = self.kind.arrow();
+        match &self.name {
+            Some(name) => format!("{name}: {} {arrow} {}", self.src, self.dst),
+            None => format!("{} {arrow} {}", self.src, self.dst),
+        }
+    }
+}
+
+impl TopologyStats {
+    pub fn total_synapses(&self) -> u64 {
+        self.connections
+            .iter()
+            .fold(0u64, |acc, c| acc.saturating_add(c.synapses))
+    }
+}
+
+impl fmt::Display for TopologyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>10} {:>23} {:>23}",
+            "layer", "size", "in-degree min/mean/max", "out-degree min/mean/max"
+        )?;
+        for layer in &self.layers {
+            writeln!(
+                f,
+                "{:<16} {:>10} {:>23} {:>23}",
+                layer.name,
+                layer.size,
+                layer.in_degree.to_string(),
+                layer.out_degree.to_string()
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<24} {:>14}  {:<20} delay",
+            "connection", "synapses", "weight"
+        )?;
+        for conn in &self.connections {
+            writeln!(
+                f,
+                "{:<24} {:>14}  {:<20} {}",
+                conn.label(),
+                conn.synapses,
+                param_text(conn.weight.as_ref(), "1"),
+                param_text(conn.delay.as_ref(), "0 ms")
+            )?;
+        }
+        write!(f, "total synapses: {}", self.total_synapses())
+    }
+}
+
+impl fmt::Display for DegreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{:.1}/{}", self.min, self.mean, self.max)
+    }
+}
+
+fn param_text(expr: Option<&Expr>, default: &str) -> String {
+    match expr {
+        Some(e) => e.to_string(),
+        None => format!("{default} (default)"),
+    }
+}
+
+pub fn stats_json(stats: &TopologyStats) -> String {
+    let mut w = JsonWriter::new();
+    w.obj_begin();
+    w.key("layers");
+    w.array_begin();
+    for (idx, layer) in stats.layers.iter().enumerate() {
+        if idx != 0 {
+            w.comma();
+        }
+        w.nl();
+        w.obj_begin();
+        w.kv_str("name", &layer.name);
+        w.comma_nl();
+        w.kv_u64("size", layer.size);
+        w.comma_nl();
+        w.key("in_degree");
+        emit_degree(&mut w, &layer.in_degree);
+        w.comma_nl();
+        w.key("out_degree");
+        emit_degree(&mut w, &layer.out_degree);
+        w.obj_end();
+    }
+    if !stats.layers.is_empty() {
+        w.nl();
+    }
+    w.array_end();
+    w.comma_nl();
+    w.key("connections");
+    w.array_begin();
+    for (idx, conn) in stats.connections.iter().enumerate() {
+        if idx != 0 {
+            w.comma();
+        }
+        w.nl();
+        w.obj_begin();
+        w.key("name");
+        match &conn.name {
+            Some(name) => w.str(name),
+            None => w.write("null"),
+        }
+        w.comma_nl();
+        w.kv_str("src", &conn.src);
+        w.comma_nl();
+        w.kv_str("dst", &conn.dst);
+        w.comma_nl();
+        w.key("electrical");
+        w.write(&(conn.kind == ConnectKind::Electrical).to_string());
+        w.comma_nl();
+        w.kv_u64("synapses", conn.synapses);
+        w.comma_nl();
+        w.key("weight");
+        emit_param(&mut w, conn.weight.as_ref());
+        w.comma_nl();
+        w.key("delay");
+        emit_param(&mut w, conn.delay.as_ref());
+        w.obj_end();
+    }
+    if !stats.connections.is_empty() {
+        w.nl();
+    }
+    w.array_end();
+    w.comma_nl();
+    w.kv_u64("total_synapses", stats.total_synapses());
+    w.nl();
+    w.obj_end();
+    w.nl();
+    w.finish()
+}
+
+fn emit_degree(w: &mut JsonWriter, degree: &DegreeStats) {
+    w.obj_begin();
+    w.kv_u64("min", degree.min);
+    w.comma_nl();
+    w.kv_f64("mean", degree.mean);
+    w.comma_nl();
+    w.kv_u64("max", degree.max);
+    w.obj_end();
+}
+
+fn emit_param(w: &mut JsonWriter, expr: Option<&Expr>) {
+    match expr {
+        Some(e) => emit_expr(w, e),
+        None => w.write("null"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn reports_degrees_and_connection_params() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms }
+layer A[10] : LIF
+layer B[4] : LIF
+layer C[2] : LIF
+connect A -> B { w = Normal(0.5, 0.1), d = 2 ms }
+connect B -> C { }
+connect A -> C { w = 0.2 }
+run for 10 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let stats = topology_stats(&program);
+
+        let a = &stats.layers[0];
+        assert_eq!(a.out_degree.max, 6);
+        assert_eq!(a.in_degree.max, 0);
+        let c = &stats.layers[2];
+        assert_eq!(c.in_degree, DegreeStats::uniform(14));
+        assert_eq!(stats.connections[0].synapses, 40);
+        assert_eq!(stats.total_synapses(), 40 + 8 + 20);
+        assert_eq!(
+            stats.connections[0].weight.as_ref().map(|e| e.to_string()),
+            Some("Normal(0.5, 0.1)".to_string())
+        );
+
+        let text = stats.to_string();
+        assert!(text.contains("1 (default)"));
+        let value = json::parse(&stats_json(&stats)).expect("valid json");
+        assert_eq!(
+            value.get("total_synapses").and_then(|v| v.as_f64()),
+            Some(68.0)
+        );
+    }
+
+    #[test]
+    fn counts_synapses_left_after_disconnect() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer A[4] : LIF
+layer B[3] : LIF
+connect rec: A -> A { }
+connect A -> B { }
+disconnect rec where src == dst
+disconnect A -> B where dst == 0 and src < 2
+run for 1 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let stats = topology_stats(&program);
+        assert_eq!(stats.connections[0].synapses, 12);
+        assert_eq!(stats.connections[0].label(), "rec: A -> A");
+        assert_eq!(stats.connections[1].synapses, 10);
+        let a = &stats.layers[0];
+        assert_eq!(a.in_degree, DegreeStats::uniform(3));
+        assert_eq!((a.out_degree.min, a.out_degree.max), (5, 6));
+        let b = &stats.layers[1];
+        assert_eq!((b.in_degree.min, b.in_degree.max), (2, 4));
+    }
+}
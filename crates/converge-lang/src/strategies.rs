@@ -0,0 +1,202 @@
+//! [proptest] strategies for programs, behind the `proptest` feature, for
+//! property tests here and in tools built on the language.
+//!
+//! [`program`] generates models that pass [`crate::validate::validate`]:
+//! neurons, layers of them, connections, stimuli, recordings and a run.
+//! Every program it makes holds to the printer's guarantee, which the tests
+//! below check:
+//!
+//! ```
+//! use converge_lang::{parser, print, strategies, visit};
+//! use proptest::prelude::*;
+//!
+//! proptest!(|(program in strategies::program())| {
+//!     let mut parsed = parser::parse_program(&print::print_program(&program)).unwrap();
+//!     visit::clear_spans(&mut parsed);
+//!     prop_assert_eq!(parsed, program);
+//! });
+//! ```
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::ast::{CompareOp, Expr, Program, Quantity};
+use crate::builder::{ProgramBuilder, call, number, quantity};
+
+/// Item names: a capital letter and up to five letters or digits, so no
+/// name is ever read as a keyword.
+pub fn name() -> impl Strategy<Value = String> {
+    "[A-Z][A-Za-z0-9]{0,5}"
+}
+
+/// A quantity in `unit` between `low` and `high`, in steps of a thousandth
+/// so it prints exactly.
+pub fn quantity_in(unit: &'static str, low: f64, high: f64) -> impl Strategy<Value = Quantity> {
+    let (low, high) = ((low * 1000.0) as i64, (high * 1000.0) as i64);
+    (low..=high).prop_map(move |milli| quantity(milli as f64 / 1000.0, unit))
+}
+
+/// A unitless value between `low` and `high`, in steps of a thousandth.
+pub fn number_in(low: f64, high: f64) -> impl Strategy<Value = Expr> {
+    let (low, high) = ((low * 1000.0) as i64, (high * 1000.0) as i64);
+    (low..=high).prop_map(|milli| number(milli as f64 / 1000.0))
+}
+
+/// A connection weight: a constant, `Normal(mean, sd)` or `Uniform(lo, hi)`.
+pub fn weight() -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        number_in(-2.0, 2.0),
+        (number_in(-1.0, 1.0), number_in(0.0, 0.5)).prop_map(|(m, s)| call("Normal", [m, s])),
+        (number_in(-1.0, 0.0), number_in(0.0, 1.0)).prop_map(|(lo, hi)| call("Uniform", [lo, hi])),
+    ]
+}
+
+/// A valid model: one to three neuron types, one to four layers using them,
+/// up to four chemical and one electrical connection between the layers, a
+/// Poisson stimulus for some layers, optional probes, rate recordings and
+/// assertions, a seed, and a run with a 1 ms or 0.5 ms step.
+pub fn program() -> impl Strategy<Value = Program> {
+    let neurons = vec(
+        (
+            proptest::option::of(quantity_in("ms", 1.0, 50.0)),
+            proptest::option::of(number_in(0.1, 2.0)),
+        ),
+        1..=3,
+    );
+    let layers = vec((name(), 1u64..=32, any::<prop::sample::Index>()), 1..=4);
+    (neurons, layers).prop_flat_map(|(neurons, mut layers)| {
+        // Neuron types are called `N0`, `N1`, ..., and every layer name
+        // ends in `L` and its index, so no two names clash.
+        for (idx, (name, _, _)) in layers.iter_mut().enumerate() {
+            name.push_str(&format!("L{idx}"));
+        }
+        let count = layers.len();
+        let pick = any::<prop::sample::Index>();
+        let connections = vec(
+            (
+                pick.clone(),
+                pick.clone(),
+                weight(),
+                proptest::option::of(0u32..=3),
+            ),
+            0..=4,
+        );
+        let electrical = proptest::option::of((pick.clone(), pick.clone(), number_in(0.0, 0.5)));
+        let stimuli = vec((pick.clone(), quantity_in("Hz", 0.0, 200.0)), 0..=count);
+        let probes = vec((pick.clone(), proptest::option::of(1u32..=4)), 0..=2);
+        let records = vec((pick.clone(), quantity_in("ms", 1.0, 20.0)), 0..=1);
+        let asserts = vec((pick, 0u32..=100), 0..=2);
+        let run = (
+            any::<bool>(),
+            1u32..=200,
+            proptest::option::of(any::<u64>()),
+        );
+        (
+            Just(neurons),
+            Just(layers),
+            connections,
+            electrical,
+            stimuli,
+            (probes, records, asserts),
+            run,
+        )
+            .prop_map(
+                |(neurons, layers, connections, electrical, stimuli, extras, run)| {
+                    let (probes, records, asserts) = extras;
+                    let (half_step, steps, seed) = run;
+                    let mut b = ProgramBuilder::new();
+                    for (idx, (tau_m, v_th)) in neurons.iter().enumerate() {
+                        let body: Vec<(&str, Expr)> = tau_m
+                            .clone()
+                            .map(|q| ("tau_m", q.into()))
+                            .into_iter()
+                            .chain(v_th.clone().map(|v| ("v_th", v)))
+                            .collect();
+                        b = b.neuron(&format!("N{idx}"), body);
+                    }
+                    let layer = |index: &prop::sample::Index| &layers[index.index(layers.len())];
+                    for (name, size, neuron) in &layers {
+                        let neuron = format!("N{}", neuron.index(neurons.len()));
+                        b = b.layer(name, *size, &neuron);
+                    }
+                    for (src, dst, w, d) in connections {
+                        let mut body = vec![("w", w)];
+                        if let Some(ms) = d {
+                            body.push(("d", quantity(f64::from(ms), "ms").into()));
+                        }
+                        b = b.connect(&layer(&src).0, &layer(&dst).0, body);
+                    }
+                    if let Some((a, c, g)) = electrical {
+                        b = b.electrical(&layer(&a).0, &layer(&c).0, [("g", g)]);
+                    }
+                    let mut stimulated = Vec::new();
+                    for (target, rate) in stimuli {
+                        let name = &layer(&target).0;
+                        if !stimulated.contains(name) {
+                            stimulated.push(name.clone());
+                            b = b.poisson(name, rate);
+                        }
+                    }
+                    for (idx, (target, every)) in probes.into_iter().enumerate() {
+                        let (name, size, _) = layer(&target);
+                        let every = every.map(|steps| quantity(f64::from(steps), "ms"));
+                        b = b.probe(&format!("p{idx}"), name, Some(0..*size), every);
+                    }
+                    let mut recorded = Vec::new();
+                    for (target, window) in records {
+                        let name = &layer(&target).0;
+                        if !recorded.contains(name) {
+                            recorded.push(name.clone());
+                            b = b.record_rate(name, window);
+                        }
+                    }
+                    for (target, count) in asserts {
+                        b = b.assert_spikes(&layer(&target).0, CompareOp::Ge, f64::from(count));
+                    }
+                    if let Some(seed) = seed {
+                        b = b.seed(seed);
+                    }
+                    let duration = quantity(f64::from(steps), "ms");
+                    if half_step {
+                        b.run_with_step(duration, quantity(0.5, "ms"))
+                    } else {
+                        b.run(duration)
+                    }
+                    .build_unchecked()
+                },
+            )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::cvir_json;
+    use crate::parser::parse_program;
+    use crate::print::print_program;
+    use crate::validate::validate;
+    use crate::visit::clear_spans;
+
+    proptest! {
+        #[test]
+        fn generated_programs_validate(program in program()) {
+            prop_assert!(validate(&program).is_ok(), "{}", print_program(&program));
+        }
+
+        #[test]
+        fn printing_then_parsing_gives_the_program_back(program in program()) {
+            let printed = print_program(&program);
+            let mut parsed = parse_program(&printed).expect("printed programs parse");
+            clear_spans(&mut parsed);
+            prop_assert_eq!(parsed, program);
+        }
+
+        #[test]
+        fn cvir_depends_only_on_the_elaborated_model(program in program()) {
+            let json = cvir_json(&program);
+            prop_assert_eq!(&cvir_json(&program), &json);
+            let reparsed = parse_program(&print_program(&program)).expect("parse");
+            prop_assert_eq!(cvir_json(&reparsed), json);
+        }
+    }
+}
@@ -0,0 +1,513 @@
+//! Hardware target profiles. A profile is a TOML file declaring the limits
+//! of a neuromorphic chip; [`check_target`] reports every place a program
+//! exceeds them, without building the network.
+//!
+//! ```toml
+//! name = "loihi2"
+//! cores = 128
+//!
+//! [core]
+//! max_neurons = 8192
+//! max_synapses = 65536
+//! max_fan_in = 4096
+//!
+//! [weights]
+//! bits = 8
+//! min = -1.0
+//! max = 1.0
+//!
+//! [delays]
+//! min_steps = 0
+//! max_steps = 62
+//!
+//! [energy]
+//! neuron_update_pj = 0.05
+//! synaptic_event_pj = 0.02
+//! router_hop_pj = 3.5
+//!
+//! [router]
+//! mesh_width = 8
+//! ```
+//!
+//! Every key is optional; a missing limit is not checked.
+
+use crate::ast::{CallArg, ConnectDef, ConnectKind, Expr, Item, Program};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::json::Value;
+use crate::place::place;
+use crate::stats::topology_stats;
+use crate::toml;
+use crate::units::{delay_to_nanos, time_to_nanos};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TargetProfile {
+    pub name: String,
+    /// Cores on the chip.
+    pub cores: Option<u64>,
+    pub max_neurons_per_core: Option<u64>,
+    /// Synapses stored on a core: those into its neurons.
+    pub max_synapses_per_core: Option<u64>,
+    /// Synapses into any one neuron.
+    pub max_fan_in: Option<u64>,
+    /// Signed weight precision. With a weight range it sets the resolution,
+    /// `max(|min|, |max|) / (2^(bits - 1) - 1)`.
+    pub weight_bits: Option<u32>,
+    pub weight_min: Option<f64>,
+    pub weight_max: Option<f64>,
+    /// Synaptic delay range, in simulation steps.
+    pub delay_min_steps: Option<u64>,
+    pub delay_max_steps: Option<u64>,
+    /// Energy per event in picojoules, for `converge sim --energy-report`.
+    pub neuron_update_pj: Option<f64>,
+    pub synaptic_event_pj: Option<f64>,
+    pub router_hop_pj: Option<f64>,
+    /// Columns of the core mesh; square enough to hold every core if unset.
+    pub mesh_width: Option<u64>,
+}
+
+impl TargetProfile {
+    pub fn from_toml(input: &str) -> Result<Self, Diagnostic> {
+        let doc = toml::parse(input)?;
+        let mut profile = Self {
+            name: "target".to_string(),
+            ..Self::default()
+        };
+        for (key, value) in fields(&doc, "")? {
+            match (key.as_str(), value) {
+                ("name", Value::String(name)) => profile.name = name.clone(),
+                ("name", _) => return Err(Diagnostic::new("profile `name` must be a string")),
+                ("cores", v) => profile.cores = Some(count(v, "cores")?),
+                ("core", v) => {
+                    for (key, v) in fields(v, "core")? {
+                        match key.as_str() {
+                            "max_neurons" => {
+                                profile.max_neurons_per_core = Some(count(v, "core.max_neurons")?)
+                            }
+                            "max_synapses" => {
+                                profile.max_synapses_per_core = Some(count(v, "core.max_synapses")?)
+                            }
+                            "max_fan_in" => profile.max_fan_in = Some(count(v, "core.max_fan_in")?),
+                            _ => return Err(unknown_key("core", key)),
+                        }
+                    }
+                }
+                ("weights", v) => {
+                    for (key, v) in fields(v, "weights")? {
+                        match key.as_str() {
+                            "bits" => match count(v, "weights.bits")? {
+                                bits @ 2..=32 => profile.weight_bits = Some(bits as u32),
+                                _ => {
+                                    return Err(Diagnostic::new(
+                                        "profile `weights.bits` must be between 2 and 32",
+                                    ));
+                                }
+                            },
+                            "min" => profile.weight_min = Some(number(v, "weights.min")?),
+                            "max" => profile.weight_max = Some(number(v, "weights.max")?),
+                            _ => return Err(unknown_key("weights", key)),
+                        }
+                    }
+                }
+                ("delays", v) => {
+                    for (key, v) in fields(v, "delays")? {
+                        match key.as_str() {
+                            "min_steps" => {
+                                profile.delay_min_steps = Some(steps(v, "delays.min_steps")?)
+                            }
+                            "max_steps" => {
+                                profile.delay_max_steps = Some(steps(v, "delays.max_steps")?)
+                            }
+                            _ => return Err(unknown_key("delays", key)),
+                        }
+                    }
+                }
+                ("energy", v) => {
+                    for (key, v) in fields(v, "energy")? {
+                        let cost = Some(energy(v, &format!("energy.{key}"))?);
+                        match key.as_str() {
+                            "neuron_update_pj" => profile.neuron_update_pj = cost,
+                            "synaptic_event_pj" => profile.synaptic_event_pj = cost,
+                            "router_hop_pj" => profile.router_hop_pj = cost,
+                            _ => return Err(unknown_key("energy", key)),
+                        }
+                    }
+                }
+                ("router", v) => {
+                    for (key, v) in fields(v, "router")? {
+                        match key.as_str() {
+                            "mesh_width" => {
+                                profile.mesh_width = Some(count(v, "router.mesh_width")?)
+                            }
+                            _ => return Err(unknown_key("router", key)),
+                        }
+                    }
+                }
+                _ => return Err(unknown_key("", key)),
+            }
+        }
+        if let (Some(min), Some(max)) = (profile.weight_min, profile.weight_max)
+            && min > max
+        {
+            return Err(Diagnostic::new(
+                "profile `weights.min` is greater than `weights.max`",
+            ));
+        }
+        if let (Some(min), Some(max)) = (profile.delay_min_steps, profile.delay_max_steps)
+            && min > max
+        {
+            return Err(Diagnostic::new(
+                "profile `delays.min_steps` is greater than `delays.max_steps`",
+            ));
+        }
+        Ok(profile)
+    }
+
+    /// The smallest weight magnitude the target represents, when its
+    /// precision and range are both known.
+    pub fn weight_resolution(&self) -> Option<f64> {
+        let bits = self.weight_bits?;
+        let range = match (self.weight_min, self.weight_max) {
+            (None, None) => return None,
+            (min, max) => min.unwrap_or(0.0).abs().max(max.unwrap_or(0.0).abs()),
+        };
+        Some(range / ((1_u64 << (bits - 1)) - 1) as f64)
+    }
+}
+
+fn fields<'a>(value: &'a Value, table: &str) -> Result<&'a [(String, Value)], Diagnostic> {
+    match value {
+        Value::Object(fields) => Ok(fields),
+        _ => Err(Diagnostic::new(format!(
+            "profile `{table}` must be a table"
+        ))),
+    }
+}
+
+fn unknown_key(table: &str, key: &str) -> Diagnostic {
+    let key = if table.is_empty() {
+        key.to_string()
+    } else {
+        format!("{table}.{key}")
+    };
+    Diagnostic::new(format!("unknown profile key `{key}`"))
+}
+
+fn number(value: &Value, key: &str) -> Result<f64, Diagnostic> {
+    value
+        .as_f64()
+        .ok_or_else(|| Diagnostic::new(format!("profile `{key}` must be a number")))
+}
+
+fn energy(value: &Value, key: &str) -> Result<f64, Diagnostic> {
+    match value.as_f64() {
+        Some(pj) if pj >= 0.0 => Ok(pj),
+        _ => Err(Diagnostic::new(format!(
+            "profile `{key}` must be a non-negative number"
+        ))),
+    }
+}
+
+fn steps(value: &Value, key: &str) -> Result<u64, Diagnostic> {
+    match value.as_f64() {
+        Some(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as u64),
+        _ => Err(Diagnostic::new(format!(
+            "profile `{key}` must be a whole number of steps"
+        ))),
+    }
+}
+
+fn count(value: &Value, key: &str) -> Result<u64, Diagnostic> {
+    match value.as_f64() {
+        Some(n) if n >= 1.0 && n.fract() == 0.0 => Ok(n as u64),
+        _ => Err(Diagnostic::new(format!(
+            "profile `{key}` must be a positive integer"
+        ))),
+    }
+}
+
+/// Checks a validated program against a target profile. Every violation is
+/// reported, with a span at the layer or connection parameter responsible.
+pub fn check_target(program: &Program, profile: &TargetProfile) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    let target = &profile.name;
+    let stats = topology_stats(program);
+    let layers: Vec<_> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Layer(def) => Some(def),
+            _ => None,
+        })
+        .collect();
+
+    match place(program, profile) {
+        Ok(placement) => {
+            if let Some(cores) = profile.cores
+                && placement.cores.len() as u64 > cores
+            {
+                diags.push(Diagnostic::new(format!(
+                    "placement needs {} cores, {target} has {cores}",
+                    placement.cores.len()
+                )));
+            }
+        }
+        // A neuron with more synapses than a core holds.
+        Err(diag) => diags.push(diag),
+    }
+
+    if let Some(max_fan_in) = profile.max_fan_in {
+        for (def, layer) in layers.iter().zip(&stats.layers) {
+            if layer.in_degree.max > max_fan_in {
+                diags.push(
+                    Diagnostic::new(format!(
+                        "layer `{}` has a fan-in of {}, {target} allows {max_fan_in}",
+                        layer.name, layer.in_degree.max
+                    ))
+                    .with_span(def.name.span.clone()),
+                );
+            }
+        }
+    }
+
+    let step_ns = program
+        .items
+        .iter()
+        .find_map(|item| match item {
+            Item::Run(run) => Some(run.step.as_ref()),
+            _ => None,
+        })
+        .map_or(Some(1_000_000), |step| match step {
+            Some(q) => time_to_nanos(q, "run step").ok(),
+            None => Some(1_000_000),
+        });
+    for item in &program.items {
+        if let Item::Connect(def) = item {
+            check_weights(def, profile, &mut diags);
+            if let Some(step_ns) = step_ns {
+                check_delays(def, profile, step_ns, &mut diags);
+            }
+        }
+    }
+    diags
+}
+
+fn check_weights(def: &ConnectDef, profile: &TargetProfile, diags: &mut Vec<Diagnostic>) {
+    if def.kind != ConnectKind::Chemical {
+        return;
+    }
+    let target = &profile.name;
+    let (text, (lo, hi), span) = match def.body.iter().find(|a| a.key.name == "w") {
+        Some(assign) => match range_of(&assign.value, |q| Some(q.value)) {
+            Some(range) => (assign.value.to_string(), range, expr_span(&assign.value)),
+            None => return,
+        },
+        None => ("1 (default)".to_string(), (1.0, 1.0), def.span.clone()),
+    };
+    let below = profile.weight_min.is_some_and(|min| lo < min);
+    let above = profile.weight_max.is_some_and(|max| hi > max);
+    if below || above {
+        let min = profile
+            .weight_min
+            .map_or("-inf".to_string(), |m| m.to_string());
+        let max = profile
+            .weight_max
+            .map_or("inf".to_string(), |m| m.to_string());
+        diags.push(
+            Diagnostic::new(format!(
+                "weights of {text} fall outside the range [{min}, {max}] of {target}"
+            ))
+            .with_span(span.clone()),
+        );
+    }
+    if let (Some(bits), Some(resolution)) = (profile.weight_bits, profile.weight_resolution()) {
+        let largest = lo.abs().max(hi.abs());
+        if largest > 0.0 && largest < resolution / 2.0 {
+            diags.push(
+                Diagnostic::new(format!(
+                    "weights of {text} round to zero at the {bits}-bit resolution \
+                     of {target} ({resolution})"
+                ))
+                .with_span(span),
+            );
+        }
+    }
+}
+
+fn check_delays(
+    def: &ConnectDef,
+    profile: &TargetProfile,
+    step_ns: i64,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let target = &profile.name;
+    let to_steps = |q: &crate::ast::Quantity| {
+        delay_to_nanos(q, step_ns)
+            .ok()
+            .map(|ns| ns / step_ns as f64)
+    };
+    let (expr, (lo, hi)) = match def.body.iter().find(|a| a.key.name == "d") {
+        Some(assign) => match range_of(&assign.value, to_steps) {
+            Some(range) => (Some(&assign.value), range),
+            None => return,
+        },
+        None => (None, (0.0, 0.0)),
+    };
+    let min = profile.delay_min_steps.unwrap_or(0);
+    let max = profile.delay_max_steps.unwrap_or(u64::MAX);
+    if lo < min as f64 || hi > max as f64 {
+        let text = expr.map_or("0 ms (default)".to_string(), |e| e.to_string());
+        let steps = if lo == 1.0 && hi == 1.0 {
+            "1 step".to_string()
+        } else if lo == hi {
+            format!("{lo} steps")
+        } else {
+            format!("{lo} to {hi} steps")
+        };
+        let span = expr.map_or_else(|| def.span.clone(), expr_span);
+        diags.push(
+            Diagnostic::new(format!(
+                "delay of {text} is {steps}, {target} allows {min} to {max}"
+            ))
+            .with_span(span),
+        );
+    }
+}
+
+/// The range a constant or distribution covers, taking `Normal` to three
+/// standard deviations either side of its mean.
+fn range_of(
+    expr: &Expr,
+    value: impl Fn(&crate::ast::Quantity) -> Option<f64>,
+) -> Option<(f64, f64)> {
+    match expr {
+        Expr::Number(q) => value(q).map(|v| (v, v)),
+        Expr::Call(call) => {
+            let args: Vec<f64> = call
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    CallArg::Positional(Expr::Number(q))
+                    | CallArg::Named {
+                        value: Expr::Number(q),
+                        ..
+                    } => value(q),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?;
+            match (call.name.name.as_str(), args.as_slice()) {
+                ("Uniform", &[a, b]) => Some((a.min(b), a.max(b))),
+                ("Normal", &[mean, sd]) => Some((mean - 3.0 * sd.abs(), mean + 3.0 * sd.abs())),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `A -> B`, for limits broken by a parameter left at its default.
+fn expr_span(expr: &Expr) -> Span {
+    match expr {
+        Expr::Number(q) => q.span.clone(),
+        Expr::Ident(id) => id.span.clone(),
+        Expr::Call(call) => call.span.clone(),
+        Expr::String(_) => Span::new(0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    const PROFILE: &str = r#"
+name = "chip"
+cores = 2
+
+[core]
+max_neurons = 100
+max_fan_in = 150
+
+[weights]
+bits = 8
+min = -1.0
+max = 1.0
+
+[delays]
+max_steps = 4
+"#;
+
+    #[test]
+    fn reports_each_exceeded_limit_with_a_span() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms }
+layer A[200] : LIF
+layer B[10] : LIF
+connect A -> B { w = Normal(0.5, 0.25), d = 5 ms }
+connect B -> B { w = 0.001 }
+run for 10 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let profile = TargetProfile::from_toml(PROFILE).expect("profile");
+        let diags = check_target(&program, &profile);
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "placement needs 3 cores, chip has 2",
+                "layer `B` has a fan-in of 210, chip allows 150",
+                "weights of Normal(0.5, 0.25) fall outside the range [-1, 1] of chip",
+                "delay of 5 ms is 5 steps, chip allows 0 to 4",
+                "weights of 0.001 round to zero at the 8-bit resolution of chip \
+                 (0.007874015748031496)",
+            ]
+        );
+        let at = |idx: usize| {
+            let span = diags[idx].span.clone().expect("span");
+            &src[span.start..span.end]
+        };
+        assert_eq!(at(1), "B");
+        assert_eq!(at(2), "Normal(0.5, 0.25)");
+        assert_eq!(at(3), "5 ms");
+        assert_eq!(at(4), "0.001");
+    }
+
+    #[test]
+    fn accepts_programs_within_limits() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms }
+layer A[100] : LIF
+layer B[10] : LIF
+connect A -> B { w = Uniform(-0.5, 0.5), d = Uniform(1 ms, 2 ms) }
+run for 10 ms step 0.5 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let profile = TargetProfile::from_toml(PROFILE).expect("profile");
+        assert_eq!(check_target(&program, &profile), []);
+    }
+
+    #[test]
+    fn rejects_malformed_profiles() {
+        let err = |src: &str| TargetProfile::from_toml(src).unwrap_err().message;
+        assert_eq!(
+            err("[core]\nmax_neuron = 4\n"),
+            "unknown profile key `core.max_neuron`"
+        );
+        assert_eq!(
+            err("cores = 0\n"),
+            "profile `cores` must be a positive integer"
+        );
+        assert_eq!(
+            err("[weights]\nbits = 64\n"),
+            "profile `weights.bits` must be between 2 and 32"
+        );
+        assert_eq!(
+            err("[delays]\nmin_steps = 3\nmax_steps = 1\n"),
+            "profile `delays.min_steps` is greater than `delays.max_steps`"
+        );
+        assert_eq!(
+            err("[energy]\nrouter_hop_pj = -1\n"),
+            "profile `energy.router_hop_pj` must be a non-negative number"
+        );
+        assert!(err("cores = \n").starts_with("invalid TOML"));
+    }
+}
@@ -0,0 +1,260 @@
+//! Helpers for testing code built on the language, such as a custom
+//! [`Pass`], against `.cv` snippets the way this crate tests itself:
+//! [`check`] parses and validates a snippet and gives back its diagnostics
+//! as plain `line:col` text and its CVIR, both stable enough to compare
+//! against a snapshot, and [`assert_pass`] checks that a pass turns one
+//! snippet into another.
+//!
+//! ```
+//! use converge_lang::opt::FoldQuantities;
+//! use converge_lang::test_support::{assert_pass, check};
+//!
+//! let checked = check("layer In[4] : Missing\nstimulus In = Poisson(rate=10 Hz)\nrun for 1 ms\n");
+//! assert_eq!(
+//!     checked.diagnostics_text(),
+//!     "1:15: error: unknown neuron type `Missing`\n"
+//! );
+//!
+//! assert!(assert_pass(
+//!     &mut FoldQuantities,
+//!     "run for 1 ms",
+//!     "run for 1000000 ns"
+//! ));
+//! ```
+
+use std::fmt;
+
+use crate::ast::Program;
+use crate::diagnostic::Diagnostic;
+use crate::emit::cvir_json;
+use crate::opt::Pass;
+use crate::parser::parse_program_recovering;
+use crate::validate::{validate, warnings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A diagnostic reduced to what a test compares: where it starts and what
+/// it says.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reported {
+    pub severity: Severity,
+    /// The 1-based line and column, in characters, or `None` for a
+    /// diagnostic about the whole program.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Reported {
+    fn new(src: &str, severity: Severity, diag: Diagnostic) -> Self {
+        Self {
+            severity,
+            position: diag.span.as_ref().map(|span| span.line_col(src)),
+            message: diag.message,
+        }
+    }
+}
+
+/// `line:col: error: message`, or `error: message` without a position.
+impl fmt::Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((line, col)) = self.position {
+            write!(f, "{line}:{col}: ")?;
+        }
+        let label = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{label}: {}", self.message)
+    }
+}
+
+/// What [`check`] made of a snippet.
+#[derive(Debug, Clone)]
+pub struct Checked {
+    /// The parsed program, even when it doesn't validate; `None` when it
+    /// doesn't parse.
+    pub program: Option<Program>,
+    /// Every parse error, or else every validation error followed by every
+    /// warning.
+    pub diagnostics: Vec<Reported>,
+}
+
+impl Checked {
+    /// Whether the snippet parsed and validated; warnings are allowed.
+    pub fn is_ok(&self) -> bool {
+        self.program.is_some()
+            && self
+                .diagnostics
+                .iter()
+                .all(|d| d.severity == Severity::Warning)
+    }
+
+    /// The diagnostics, one per line, each ending in a newline.
+    pub fn diagnostics_text(&self) -> String {
+        self.diagnostics.iter().map(|d| format!("{d}\n")).collect()
+    }
+
+    /// The program's CVIR, without spans or ids, so it depends only on
+    /// what the snippet means and not on how it is laid out. `None` when
+    /// the snippet doesn't parse.
+    pub fn cvir(&self) -> Option<String> {
+        self.program.as_ref().map(cvir_json)
+    }
+
+    /// The program, for a snippet that is expected to be valid.
+    ///
+    /// # Panics
+    ///
+    /// When the snippet has errors, listing them.
+    #[track_caller]
+    pub fn expect_valid(self) -> Program {
+        match self.program {
+            Some(program)
+                if self
+                    .diagnostics
+                    .iter()
+                    .all(|d| d.severity == Severity::Warning) =>
+            {
+                program
+            }
+            _ => panic!("snippet has errors:\n{}", self.diagnostics_text()),
+        }
+    }
+}
+
+/// Parses `src`, reporting every parse error, and validates the result.
+pub fn check(src: &str) -> Checked {
+    match parse_program_recovering(src) {
+        Ok(program) => {
+            let mut diagnostics: Vec<Reported> = validate(&program)
+                .err()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|d| Reported::new(src, Severity::Error, d))
+                .collect();
+            diagnostics.extend(
+                warnings(&program)
+                    .into_iter()
+                    .map(|d| Reported::new(src, Severity::Warning, d)),
+            );
+            Checked {
+                program: Some(program),
+                diagnostics,
+            }
+        }
+        Err(diags) => Checked {
+            program: None,
+            diagnostics: diags
+                .into_iter()
+                .map(|d| Reported::new(src, Severity::Error, d))
+                .collect(),
+        },
+    }
+}
+
+/// Runs `pass` once on `src` and asserts that the result has the same
+/// CVIR as `expected`, returning whether the pass reported a change.
+///
+/// # Panics
+///
+/// When either snippet has errors, or when the CVIR differs, showing the
+/// first line that does.
+#[track_caller]
+pub fn assert_pass(pass: &mut dyn Pass, src: &str, expected: &str) -> bool {
+    let mut program = check(src).expect_valid();
+    let changed = pass.run(&mut program);
+    let expected = check(expected).expect_valid();
+    assert_same_text(&cvir_json(&program), &cvir_json(&expected));
+    changed
+}
+
+/// Asserts that `actual` is `expected`, showing the first line where they
+/// differ rather than both texts whole.
+///
+/// # Panics
+///
+/// When the texts differ.
+#[track_caller]
+pub fn assert_same_text(actual: &str, expected: &str) {
+    if actual == expected {
+        return;
+    }
+    let (mut actual_lines, mut expected_lines) = (actual.lines(), expected.lines());
+    for line in 1.. {
+        match (actual_lines.next(), expected_lines.next()) {
+            (Some(a), Some(e)) if a == e => {}
+            (a, e) => panic!(
+                "texts differ at line {line}:\n  actual:   {}\n  expected: {}",
+                a.unwrap_or("<end>"),
+                e.unwrap_or("<end>")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opt::{DeadCode, FoldQuantities};
+
+    #[test]
+    fn reports_parse_errors_or_validation_errors_and_warnings() {
+        let checked = check("layer A[2] : LIF\nlayer B[2] LIF\nlayer C[2] : LIF $\n");
+        assert!(checked.program.is_none());
+        assert_eq!(
+            checked.diagnostics_text(),
+            "2:12: error: expected `:`\n3:18: error: unexpected character '$'\n"
+        );
+
+        let checked = check(
+            "neuron LIF { tau_n = 5 ms }\n\
+             layer A[2] : LIF\n\
+             connect A -> Missing {}\n\
+             stimulus A = Poisson(rate=10 Hz)\n\
+             run for 1 ms\n",
+        );
+        assert!(!checked.is_ok());
+        assert!(checked.cvir().is_some());
+        assert_eq!(
+            checked.diagnostics,
+            [
+                Reported {
+                    severity: Severity::Error,
+                    position: Some((3, 14)),
+                    message: "unknown destination layer `Missing`".to_string(),
+                },
+                Reported {
+                    severity: Severity::Warning,
+                    position: Some((1, 14)),
+                    message: "unknown neuron key `tau_n`; did you mean `tau_m`?".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_passes_by_cvir() {
+        let src = "neuron LIF { tau_m = 20 ms }\n\
+                   layer A[2] : LIF\n\
+                   layer Empty[0] : LIF\n\
+                   connect A -> Empty { w = 0.5 }\n\
+                   stimulus A = Poisson(rate=10 Hz)\n\
+                   run for 1 ms\n";
+        let pruned = "neuron LIF { tau_m = 20 ms }\n\
+                      layer A[2] : LIF\n\
+                      stimulus A = Poisson(rate=10 Hz)\n\
+                      run for 1 ms\n";
+        assert!(assert_pass(&mut DeadCode, src, pruned));
+        assert!(!assert_pass(&mut DeadCode, pruned, pruned));
+        let result = std::panic::catch_unwind(|| {
+            assert_pass(&mut FoldQuantities, pruned, pruned);
+        });
+        let message = *result.expect_err("mismatch").downcast::<String>().unwrap();
+        assert!(message.starts_with("texts differ at line "), "{message}");
+        assert!(message.contains("20000000"), "{message}");
+    }
+}
@@ -0,0 +1,409 @@
+//! A TOML subset for tool configuration such as target profiles: tables,
+//! dotted keys, strings, numbers, booleans, arrays and inline tables. Arrays
+//! of tables and dates are not supported. Documents parse into a
+//! [`Value::Object`] tree, with integers as numbers.
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::json::Value;
+
+pub fn parse(input: &str) -> Result<Value, Diagnostic> {
+    let mut p = TomlParser {
+        input,
+        bytes: input.as_bytes(),
+        i: 0,
+    };
+    let mut root = Value::Object(Vec::new());
+    let mut table: Vec<String> = Vec::new();
+    let mut headers: Vec<Vec<String>> = Vec::new();
+    loop {
+        p.skip_blank_lines();
+        if p.i >= p.bytes.len() {
+            return Ok(root);
+        }
+        let start = p.i;
+        if p.bytes[p.i] == b'[' {
+            if p.bytes.get(p.i + 1) == Some(&b'[') {
+                return Err(p.error("arrays of tables are not supported"));
+            }
+            p.i += 1;
+            let path = p.parse_key()?;
+            p.skip_ws();
+            p.expect(b']')?;
+            if headers.contains(&path) {
+                return Err(Diagnostic::new(format!(
+                    "invalid TOML: table `{}` is defined twice",
+                    path.join(".")
+                ))
+                .with_span(Span::new(start, p.i)));
+            }
+            table_at(&mut root, &path).map_err(|m| p.error_at(start, &m))?;
+            headers.push(path.clone());
+            table = path;
+        } else {
+            let key = p.parse_key()?;
+            p.skip_ws();
+            p.expect(b'=')?;
+            let value = p.parse_value()?;
+            let (last, parents) = key.split_last().expect("keys are never empty");
+            let mut path = table.clone();
+            path.extend_from_slice(parents);
+            let target = table_at(&mut root, &path).map_err(|m| p.error_at(start, &m))?;
+            if target.iter().any(|(k, _)| k == last) {
+                return Err(Diagnostic::new(format!(
+                    "invalid TOML: key `{}` is defined twice",
+                    key.join(".")
+                ))
+                .with_span(Span::new(start, p.i)));
+            }
+            target.push((last.clone(), value));
+        }
+        p.end_line()?;
+    }
+}
+
+/// The fields of the table at `path`, creating missing tables on the way.
+fn table_at<'a>(
+    root: &'a mut Value,
+    path: &[String],
+) -> Result<&'a mut Vec<(String, Value)>, String> {
+    let mut fields = match root {
+        Value::Object(fields) => fields,
+        _ => unreachable!("tables are objects"),
+    };
+    for (depth, key) in path.iter().enumerate() {
+        let idx = match fields.iter().position(|(k, _)| k == key) {
+            Some(idx) => idx,
+            None => {
+                fields.push((key.clone(), Value::Object(Vec::new())));
+                fields.len() - 1
+            }
+        };
+        fields = match &mut fields[idx].1 {
+            Value::Object(inner) => inner,
+            _ => return Err(format!("`{}` is not a table", path[..=depth].join("."))),
+        };
+    }
+    Ok(fields)
+}
+
+struct TomlParser<'a> {
+    input: &'a str,
+    bytes: &'a [u8],
+    i: usize,
+}
+
+impl TomlParser<'_> {
+    fn error(&self, message: &str) -> Diagnostic {
+        self.error_at(self.i, message)
+    }
+
+    fn error_at(&self, at: usize, message: &str) -> Diagnostic {
+        Diagnostic::new(format!("invalid TOML: {message}")).with_span(Span::new(at, at + 1))
+    }
+
+    fn skip_ws(&mut self) {
+        while matches!(self.bytes.get(self.i), Some(b' ' | b'\t')) {
+            self.i += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.bytes.get(self.i) == Some(&b'#') {
+            while self.i < self.bytes.len() && self.bytes[self.i] != b'\n' {
+                self.i += 1;
+            }
+        }
+    }
+
+    /// Whitespace, comments and newlines, as allowed between array items.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_ws();
+            self.skip_comment();
+            match self.bytes.get(self.i) {
+                Some(b'\n' | b'\r') => self.i += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_line(&mut self) -> Result<(), Diagnostic> {
+        self.skip_ws();
+        self.skip_comment();
+        match self.bytes.get(self.i) {
+            None | Some(b'\n' | b'\r') => Ok(()),
+            Some(_) => Err(self.error("expected a new line")),
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), Diagnostic> {
+        if self.bytes.get(self.i) == Some(&b) {
+            self.i += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", b as char)))
+        }
+    }
+
+    /// A bare, quoted or dotted key.
+    fn parse_key(&mut self) -> Result<Vec<String>, Diagnostic> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_ws();
+            let part = match self.bytes.get(self.i) {
+                Some(b'"') => self.parse_string()?,
+                Some(b'\'') => self.parse_literal_string()?,
+                _ => {
+                    let start = self.i;
+                    while self
+                        .bytes
+                        .get(self.i)
+                        .is_some_and(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-'))
+                    {
+                        self.i += 1;
+                    }
+                    if start == self.i {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.input[start..self.i].to_string()
+                }
+            };
+            parts.push(part);
+            self.skip_ws();
+            if self.bytes.get(self.i) != Some(&b'.') {
+                return Ok(parts);
+            }
+            self.i += 1;
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Diagnostic> {
+        self.skip_ws();
+        match self.bytes.get(self.i) {
+            None | Some(b'\n' | b'\r') => Err(self.error("expected a value")),
+            Some(b'"') => Ok(Value::String(self.parse_string()?)),
+            Some(b'\'') => Ok(Value::String(self.parse_literal_string()?)),
+            Some(b'[') => self.parse_array(),
+            Some(b'{') => self.parse_inline_table(),
+            Some(b'+' | b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => {
+                for (word, value) in [("true", true), ("false", false)] {
+                    if self.input[self.i..].starts_with(word) {
+                        self.i += word.len();
+                        return Ok(Value::Bool(value));
+                    }
+                }
+                Err(self.error("unexpected character"))
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, Diagnostic> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.bytes.get(self.i) == Some(&b']') {
+                self.i += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_blank_lines();
+            match self.bytes.get(self.i) {
+                Some(b',') => self.i += 1,
+                Some(b']') => {}
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, Diagnostic> {
+        self.expect(b'{')?;
+        let mut table = Value::Object(Vec::new());
+        self.skip_ws();
+        if self.bytes.get(self.i) == Some(&b'}') {
+            self.i += 1;
+            return Ok(table);
+        }
+        loop {
+            let start = self.i;
+            let key = self.parse_key()?;
+            self.skip_ws();
+            self.expect(b'=')?;
+            let value = self.parse_value()?;
+            let (last, parents) = key.split_last().expect("keys are never empty");
+            let target = table_at(&mut table, parents).map_err(|m| self.error_at(start, &m))?;
+            if target.iter().any(|(k, _)| k == last) {
+                return Err(
+                    self.error_at(start, &format!("key `{}` is defined twice", key.join(".")))
+                );
+            }
+            target.push((last.clone(), value));
+            self.skip_ws();
+            match self.bytes.get(self.i) {
+                Some(b',') => self.i += 1,
+                Some(b'}') => {
+                    self.i += 1;
+                    return Ok(table);
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, Diagnostic> {
+        self.expect(b'"')?;
+        let mut s = String::new();
+        while let Some(&b) = self.bytes.get(self.i) {
+            match b {
+                b'"' => {
+                    self.i += 1;
+                    return Ok(s);
+                }
+                b'\n' => break,
+                b'\\' => {
+                    self.i += 1;
+                    let esc = *self
+                        .bytes
+                        .get(self.i)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.i += 1;
+                    match esc {
+                        b'"' => s.push('"'),
+                        b'\\' => s.push('\\'),
+                        b'n' => s.push('\n'),
+                        b'r' => s.push('\r'),
+                        b't' => s.push('\t'),
+                        b'u' => {
+                            let hex = self
+                                .input
+                                .get(self.i..self.i + 4)
+                                .ok_or_else(|| self.error("truncated unicode escape"))?;
+                            let code = u32::from_str_radix(hex, 16)
+                                .map_err(|_| self.error("invalid unicode escape"))?;
+                            self.i += 4;
+                            s.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                        }
+                        _ => return Err(self.error("invalid string escape")),
+                    }
+                }
+                _ => {
+                    let ch = self.input[self.i..].chars().next().unwrap_or('\u{FFFD}');
+                    self.i += ch.len_utf8();
+                    s.push(ch);
+                }
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, Diagnostic> {
+        self.expect(b'\'')?;
+        let start = self.i;
+        while let Some(&b) = self.bytes.get(self.i) {
+            match b {
+                b'\'' => {
+                    self.i += 1;
+                    return Ok(self.input[start..self.i - 1].to_string());
+                }
+                b'\n' => break,
+                _ => self.i += 1,
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn parse_number(&mut self) -> Result<Value, Diagnostic> {
+        let start = self.i;
+        while self
+            .bytes
+            .get(self.i)
+            .is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'_' | b'0'..=b'9'))
+        {
+            self.i += 1;
+        }
+        let text = &self.input[start..self.i];
+        let digits = text.replace('_', "");
+        // Underscores may only separate digits.
+        let bytes = text.as_bytes();
+        let valid_underscores = bytes.iter().enumerate().all(|(k, &b)| {
+            b != b'_'
+                || (k > 0
+                    && bytes[k - 1].is_ascii_digit()
+                    && bytes.get(k + 1).is_some_and(u8::is_ascii_digit))
+        });
+        match digits.parse::<f64>() {
+            Ok(n) if valid_underscores => Ok(Value::Number(n)),
+            _ => Err(Diagnostic::new("invalid TOML: malformed number")
+                .with_span(Span::new(start, self.i))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tables_keys_and_values() {
+        let v = parse(
+            r#"
+# a target
+name = "loihi2"
+cores = 128
+
+[core]
+max_neurons = 8_192   # per core
+delays = [0, 62,]
+"quoted key" = 'C:\raw'
+
+[core.limits]
+weights = { bits = 8, signed = true }
+ratio.float = -2.5e1
+"#,
+        )
+        .unwrap();
+        assert_eq!(v.get("name").and_then(Value::as_str), Some("loihi2"));
+        let core = v.get("core").unwrap();
+        assert_eq!(core.get("max_neurons"), Some(&Value::Number(8192.0)));
+        assert_eq!(
+            core.get("delays")
+                .and_then(Value::as_array)
+                .map(|a| a.len()),
+            Some(2)
+        );
+        assert_eq!(
+            core.get("quoted key").and_then(Value::as_str),
+            Some("C:\\raw")
+        );
+        let limits = core.get("limits").unwrap();
+        assert_eq!(
+            limits.get("weights").and_then(|w| w.get("signed")),
+            Some(&Value::Bool(true))
+        );
+        assert_eq!(
+            limits.get("ratio").and_then(|r| r.get("float")),
+            Some(&Value::Number(-25.0))
+        );
+    }
+
+    #[test]
+    fn rejects_duplicates_and_stray_text() {
+        let err = |src: &str| parse(src).unwrap_err().message;
+        assert_eq!(
+            err("a = 1\na = 2\n"),
+            "invalid TOML: key `a` is defined twice"
+        );
+        assert_eq!(
+            err("[t]\n[t]\n"),
+            "invalid TOML: table `t` is defined twice"
+        );
+        assert_eq!(err("a = 1 b = 2\n"), "invalid TOML: expected a new line");
+        assert_eq!(err("a = 1\n[a]\n"), "invalid TOML: `a` is not a table");
+        assert_eq!(
+            err("[[points]]\n"),
+            "invalid TOML: arrays of tables are not supported"
+        );
+        assert_eq!(err("a = 1__0\n"), "invalid TOML: malformed number");
+    }
+}
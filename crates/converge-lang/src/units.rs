@@ -1,4 +1,4 @@
-use crate::ast::Quantity;
+use crate::ast::{Metric, Quantity};
 use crate::diagnostic::{Diagnostic, Span};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -34,6 +34,20 @@ pub fn time_to_nanos(q: &Quantity, context: &str) -> Result<i64, Diagnostic> {
     Ok(nanos.round() as i64)
 }
 
+/// Whether `unit` counts run steps, as a delay may: `1 step`, `3 steps`.
+pub fn is_step_unit(unit: &str) -> bool {
+    matches!(unit, "step" | "steps")
+}
+
+/// A connection delay in nanoseconds, given in time units or in run steps
+/// of `step_ns` each.
+pub fn delay_to_nanos(q: &Quantity, step_ns: i64) -> Result<f64, Diagnostic> {
+    match &q.unit {
+        Some(unit) if is_step_unit(&unit.name) => Ok(q.value * step_ns as f64),
+        _ => time_to_nanos(q, "delay").map(|ns| ns as f64),
+    }
+}
+
 pub fn rate_to_hz(q: &Quantity, context: &str) -> Result<f64, Diagnostic> {
     let unit = q
         .unit
@@ -69,6 +83,18 @@ pub fn expect_rate(q: &Quantity, context: &str) -> Result<(), Diagnostic> {
     Ok(())
 }
 
+/// Bounds are plain counts for `spikes(..)` and hertz for `rate(..)`.
+pub fn assert_bound(metric: &Metric, q: &Quantity) -> Result<f64, Diagnostic> {
+    match metric {
+        Metric::Spikes { .. } => match &q.unit {
+            Some(unit) => Err(Diagnostic::new("spike count bound must be a plain number")
+                .with_span(unit.span.clone())),
+            None => Ok(q.value),
+        },
+        Metric::Rate { .. } => rate_to_hz(q, "rate assertion"),
+    }
+}
+
 fn missing_unit(context: &str, span: &Span) -> Diagnostic {
     Diagnostic::new(format!("missing unit for {context}")).with_span(span.clone())
 }
@@ -94,6 +120,17 @@ mod tests {
         assert_eq!(time_to_nanos(&q(4.0, "ns"), "t").unwrap(), 4);
     }
 
+    #[test]
+    fn delays_convert_from_time_or_steps() {
+        assert_eq!(delay_to_nanos(&q(2.0, "ms"), 100_000).unwrap(), 2_000_000.0);
+        assert_eq!(
+            delay_to_nanos(&q(3.0, "steps"), 100_000).unwrap(),
+            300_000.0
+        );
+        assert_eq!(delay_to_nanos(&q(1.0, "step"), 100_000).unwrap(), 100_000.0);
+        assert!(delay_to_nanos(&q(1.0, "Hz"), 100_000).is_err());
+    }
+
     #[test]
     fn rate_units_convert() {
         assert_eq!(rate_to_hz(&q(1.0, "Hz"), "r").unwrap(), 1.0);
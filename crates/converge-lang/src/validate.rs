@@ -1,119 +1,1038 @@
 use std::collections::HashMap;
 
-use crate::ast::{ConnectDef, Expr, Item, LayerDef, NeuronDef, Program, StimulusModel};
-use crate::diagnostic::Diagnostic;
-use crate::units::{expect_rate, expect_time, time_to_nanos};
+use crate::ast::{
+    AssertCond, AssertStmt, CallArg, ConnectDef, ConnectKind, ConnectionRef, DisconnectStmt,
+    EncodeScheme, ExperimentDef, Expr, ExternalSignal, Ident, Item, LanguageVersion, LayerDef,
+    ModulatorDef, NeuronDef, ProbeDef, Program, ReadoutDef, RecordStmt, ReportMetric, RewardStmt,
+    RunStmt, SeedStmt, StimulusCombine, StimulusDef, StimulusModel, SweepDef,
+};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::units::{assert_bound, expect_rate, expect_time, is_step_unit, time_to_nanos};
+use crate::visit::{Visit, walk_item};
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "validate", skip_all, fields(items = program.items.len()))
+)]
 pub fn validate(program: &Program) -> Result<(), Vec<Diagnostic>> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut diags = validate_version(program);
+    for network in program.networks() {
+        if names.contains(&network.name.name.as_str()) {
+            diags.push(
+                Diagnostic::new(format!("duplicate network `{}`", network.name.name))
+                    .with_span(network.name.span.clone()),
+            );
+        } else {
+            names.push(&network.name.name);
+        }
+    }
+    if names.is_empty() {
+        diags.extend(validate_model(program));
+    } else {
+        // Each network is checked as its own model; errors in the shared
+        // items would otherwise repeat once per network.
+        for name in names {
+            let model = program
+                .select_network(Some(name))
+                .expect("the network is defined");
+            for diag in validate_model(&model) {
+                if !diags.contains(&diag) {
+                    diags.push(diag);
+                }
+            }
+        }
+    }
+    validate_sweeps(program, &mut diags);
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(errors = diags.len(), "validated program");
+    if diags.is_empty() { Ok(()) } else { Err(diags) }
+}
+
+/// Checks the `converge "<version>"` pragma: a different major version is
+/// incompatible, and a file declaring an older minor version may only use
+/// the syntax that version had. A newer minor version is only a warning.
+fn validate_version(program: &Program) -> Vec<Diagnostic> {
+    let Some(pragma) = &program.version else {
+        return Vec::new();
+    };
+    let current = LanguageVersion::CURRENT;
+    if pragma.version.major != current.major {
+        return vec![
+            Diagnostic::new(format!(
+                "language version {} is incompatible with this toolchain, which reads {}.x \
+                 up to {current}",
+                pragma.version, current.major
+            ))
+            .with_span(pragma.span.clone()),
+        ];
+    }
+    let mut gate = NewerSyntax {
+        declared: pragma.version,
+        diags: Vec::new(),
+    };
+    gate.visit_program(program);
+    gate.diags
+}
+
+/// Flags syntax added after the version a file declares.
+struct NewerSyntax {
+    declared: LanguageVersion,
+    diags: Vec<Diagnostic>,
+}
+
+impl NewerSyntax {
+    /// The version that added `item`'s syntax, when newer than 0.1, with a
+    /// description and where it is.
+    fn since(item: &Item) -> Option<(LanguageVersion, &'static str, Span)> {
+        let v0_2 = LanguageVersion { major: 0, minor: 2 };
+        Some(match item {
+            Item::Assert(stmt) => (v0_2, "`assert` statements", stmt.span.clone()),
+            Item::Disconnect(stmt) => (v0_2, "`disconnect` statements", stmt.span.clone()),
+            Item::Reward(stmt) => (v0_2, "`reward` statements", stmt.span.clone()),
+            Item::Record(stmt) => (v0_2, "`record` statements", stmt.span.clone()),
+            Item::Probe(def) => (v0_2, "`probe` definitions", def.span.clone()),
+            Item::Modulator(def) => (v0_2, "`modulator` definitions", def.name.span.clone()),
+            Item::Experiment(def) => (v0_2, "`experiment` blocks", def.span.clone()),
+            Item::Sweep(def) => (v0_2, "`experiment` blocks", def.span.clone()),
+            Item::Readout(def) => (v0_2, "`readout` blocks", def.span.clone()),
+            Item::Network(def) => (v0_2, "`network` blocks", def.span.clone()),
+            Item::Connect(def) if def.kind == ConnectKind::Electrical => (
+                v0_2,
+                "electrical connections",
+                Span::new(def.src.span.start, def.dst.span.end),
+            ),
+            Item::Connect(ConnectDef {
+                name: Some(name), ..
+            }) => (v0_2, "named connections", name.span.clone()),
+            Item::Stimulus(def) => match &def.model {
+                StimulusModel::Poisson { .. } => return None,
+                StimulusModel::Encode(enc) => (v0_2, "`Encode` stimuli", enc.span.clone()),
+                StimulusModel::External(ext) => (v0_2, "`External` stimuli", ext.span.clone()),
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl Visit for NewerSyntax {
+    fn visit_item(&mut self, item: &Item) {
+        if let Some((since, what, span)) = Self::since(item)
+            && since > self.declared
+        {
+            self.diags.push(
+                Diagnostic::new(format!(
+                    "{what} need language version {since}, but the file declares {}",
+                    self.declared
+                ))
+                .with_span(span),
+            );
+        }
+        walk_item(self, item);
+    }
+}
+
+/// Keys a `neuron` body may set.
+pub const NEURON_KEYS: &[&str] = &[
+    "tau_m",
+    "v_th",
+    "compartments",
+    "g_axial",
+    "modulator",
+    "modulator_gain",
+];
+
+/// Keys a chemical `connect` body may set; electrical connections only take
+/// `g`, which [`validate`] enforces.
+pub const CONNECT_KEYS: &[&str] = &[
+    "w",
+    "d",
+    "compartment",
+    "normalize",
+    "plasticity",
+    "delay_plasticity",
+];
+
+/// Mistakes that still leave a runnable program: keys in `neuron` and
+/// chemical `connect` bodies that nothing reads, so a typo like `tau_n`
+/// silently keeps the default `tau_m`, stimuli that add to an earlier one
+/// on their layer without `combine = sum`, and models or layers that run
+/// but can never spike, which simulate to an all-zero summary.
+pub fn warnings(program: &Program) -> Vec<Diagnostic> {
     let mut diags = Vec::new();
+    if let Some(pragma) = &program.version {
+        let current = LanguageVersion::CURRENT;
+        if pragma.version.major == current.major && pragma.version > current {
+            diags.push(
+                Diagnostic::new(format!(
+                    "the file targets language version {}, newer than the {current} this \
+                     toolchain implements",
+                    pragma.version
+                ))
+                .with_span(pragma.span.clone()),
+            );
+        }
+    }
+    diags.extend(program.deprecated.iter().map(|u| u.to_diagnostic()));
+    let mut keys = UnknownKeys::default();
+    keys.visit_program(program);
+    diags.extend(keys.diags);
+    let mut networks = program.networks().peekable();
+    if networks.peek().is_none() {
+        overlapping_stimuli(program, &mut diags);
+        silent_parts(program, "the model", &mut diags);
+    }
+    for network in networks {
+        let model = program
+            .select_network(Some(&network.name.name))
+            .expect("the network is defined");
+        overlapping_stimuli(&model, &mut diags);
+        silent_parts(
+            &model,
+            &format!("network `{}`", network.name.name),
+            &mut diags,
+        );
+    }
+    diags
+}
 
-    let mut neurons: HashMap<String, crate::diagnostic::Span> = HashMap::new();
-    let mut layers: HashMap<String, (crate::diagnostic::Span, String)> = HashMap::new();
-    let mut seed_count = 0;
-    let mut run_count = 0;
+/// Parts of a model that will run but can never spike: a model without
+/// layers, one without any stimulus, and layers that neither a connection
+/// nor a stimulus reaches. `subject` names the model in the messages.
+fn silent_parts(model: &Program, subject: &str, diags: &mut Vec<Diagnostic>) {
+    let Some(run) = model.items.iter().find_map(|item| match item {
+        Item::Run(run) => Some(run),
+        _ => None,
+    }) else {
+        return;
+    };
+    let layers: Vec<&LayerDef> = model
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Layer(def) => Some(def),
+            _ => None,
+        })
+        .collect();
+    let stimulated: Vec<&str> = model
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Stimulus(def) => Some(def.layer.name.as_str()),
+            _ => None,
+        })
+        .collect();
+    if layers.is_empty() {
+        diags.push(
+            Diagnostic::new(format!(
+                "{subject} has no layers, so the run produces no spikes"
+            ))
+            .with_span(run.span.clone()),
+        );
+        return;
+    }
+    if stimulated.is_empty() {
+        diags.push(
+            Diagnostic::new(format!(
+                "{subject} has no stimulus, so nothing drives its layers and the run \
+                 produces no spikes"
+            ))
+            .with_span(run.span.clone()),
+        );
+        return;
+    }
+    for layer in layers {
+        let name = layer.name.name.as_str();
+        let connected = model.items.iter().any(|item| {
+            matches!(item, Item::Connect(def) if def.src.name == name || def.dst.name == name)
+        });
+        if !connected && !stimulated.contains(&name) {
+            let diag = Diagnostic::new(format!(
+                "layer `{name}` has no connections or stimulus, so it never spikes"
+            ))
+            .with_span(layer.name.span.clone());
+            if !diags.contains(&diag) {
+                diags.push(diag);
+            }
+        }
+    }
+}
 
-    for item in &program.items {
-        match item {
-            Item::Neuron(NeuronDef { name, .. }) => {
-                if neurons.contains_key(&name.name) {
-                    diags.push(
-                        Diagnostic::new(format!("duplicate neuron `{}`", name.name))
-                            .with_span(name.span.clone()),
-                    );
-                } else {
-                    neurons.insert(name.name.clone(), name.span.clone());
+/// Stimuli that add to an earlier one on the same layer without a
+/// `combine` saying so.
+fn overlapping_stimuli(model: &Program, diags: &mut Vec<Diagnostic>) {
+    let mut stimulated: Vec<&str> = Vec::new();
+    for item in &model.items {
+        let Item::Stimulus(def) = item else {
+            continue;
+        };
+        if !stimulated.contains(&def.layer.name.as_str()) {
+            stimulated.push(&def.layer.name);
+            continue;
+        }
+        if def.combine.is_none() {
+            let diag = Diagnostic::new(format!(
+                "this stimulus adds to an earlier one on layer `{}`; say `combine = sum` to \
+                 keep that, or `combine = replace` or `combine = max`",
+                def.layer.name
+            ))
+            .with_span(def.span.clone());
+            if !diags.contains(&diag) {
+                diags.push(diag);
+            }
+        }
+    }
+}
+
+/// [`validate`] with every [`warnings`] entry reported as an error.
+pub fn validate_strict(program: &Program) -> Result<(), Vec<Diagnostic>> {
+    let mut diags = validate(program).err().unwrap_or_default();
+    diags.extend(warnings(program));
+    if diags.is_empty() { Ok(()) } else { Err(diags) }
+}
+
+#[derive(Default)]
+struct UnknownKeys {
+    diags: Vec<Diagnostic>,
+}
+
+impl UnknownKeys {
+    fn check(&mut self, body: &[crate::ast::Assign], known: &[&str], what: &str) {
+        for assign in body {
+            let key = assign.key.name.as_str();
+            if known.contains(&key) {
+                continue;
+            }
+            let hint = match known
+                .iter()
+                .map(|k| (edit_distance(key, k), *k))
+                .filter(|(d, _)| *d <= 2)
+                .min()
+            {
+                Some((_, k)) => format!("did you mean `{k}`?"),
+                None => format!(
+                    "expected one of {}",
+                    known
+                        .iter()
+                        .map(|k| format!("`{k}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+            self.diags.push(
+                Diagnostic::new(format!("unknown {what} key `{key}`; {hint}"))
+                    .with_span(assign.key.span.clone()),
+            );
+        }
+    }
+}
+
+impl Visit for UnknownKeys {
+    fn visit_neuron(&mut self, def: &NeuronDef) {
+        self.check(&def.body, NEURON_KEYS, "neuron");
+    }
+
+    fn visit_connect(&mut self, def: &ConnectDef) {
+        if def.kind == ConnectKind::Chemical {
+            self.check(&def.body, CONNECT_KEYS, "connection");
+        }
+    }
+}
+
+/// Levenshtein distance, by characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let next = (prev + usize::from(ca != cb))
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// Checks `experiment { run ...; report ... }` blocks against the whole
+/// program, since their runs may name any network.
+fn validate_sweeps(program: &Program, diags: &mut Vec<Diagnostic>) {
+    for network in program.networks() {
+        for item in &network.items {
+            if let Item::Sweep(def) = item {
+                diags.push(
+                    Diagnostic::new("an `experiment` with runs must be outside every network")
+                        .with_span(def.span.clone()),
+                );
+            }
+        }
+    }
+    let sweeps: Vec<&SweepDef> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Sweep(def) => Some(def),
+            _ => None,
+        })
+        .collect();
+    if let Some(def) = sweeps.get(1) {
+        diags.push(
+            Diagnostic::new("only one `experiment` with runs is allowed")
+                .with_span(def.span.clone()),
+        );
+    }
+    for def in sweeps {
+        if def.runs.is_empty() {
+            diags.push(
+                Diagnostic::new("an experiment with a `report` needs a `run`")
+                    .with_span(def.span.clone()),
+            );
+        }
+        if def.report.is_empty() {
+            diags.push(
+                Diagnostic::new("an experiment with runs needs a `report`")
+                    .with_span(def.span.clone()),
+            );
+        }
+        for run in &def.runs {
+            if run.first_seed > run.last_seed {
+                diags.push(
+                    Diagnostic::new(format!(
+                        "empty seed range `{}..{}`",
+                        run.first_seed, run.last_seed
+                    ))
+                    .with_span(run.span.clone()),
+                );
+            }
+            let network = run.network.as_ref().map(|n| n.name.as_str());
+            let model = match program.select_network(network) {
+                Ok(model) => model,
+                Err(diag) => {
+                    let span = run.network.as_ref().map_or(&run.span, |n| &n.span);
+                    diags.push(diag.with_span(span.clone()));
+                    continue;
+                }
+            };
+            for metric in &def.report {
+                let ReportMetric::Layer(metric) = metric else {
+                    continue;
+                };
+                let layer = metric.layer();
+                let declared = model
+                    .items
+                    .iter()
+                    .any(|item| matches!(item, Item::Layer(def) if def.name.name == layer.name));
+                if !declared {
+                    let mut message = format!("unknown report layer `{}`", layer.name);
+                    if let Some(network) = network {
+                        message.push_str(&format!(" in network `{network}`"));
+                    }
+                    let diag = Diagnostic::new(message).with_span(layer.span.clone());
+                    if !diags.contains(&diag) {
+                        diags.push(diag);
+                    }
                 }
             }
-            Item::Layer(LayerDef { name, neuron, .. }) => {
-                if layers.contains_key(&name.name) {
+        }
+    }
+}
+
+/// Validates one model: a program without networks, or the items of one
+/// network together with the shared ones.
+fn validate_model(program: &Program) -> Vec<Diagnostic> {
+    let mut decls = Declarations::default();
+    decls.visit_program(program);
+    let mut diags = decls.diags;
+
+    if let Some(span) = decls.seeds.get(1) {
+        diags.push(Diagnostic::new("only one `seed` statement is allowed").with_span(span.clone()));
+    }
+    if let Some(span) = decls.experiments.get(1) {
+        diags.push(Diagnostic::new("only one `experiment` is allowed").with_span(span.clone()));
+    }
+    if let Some(span) = decls.experiments.first() {
+        match decls.presents.split_first() {
+            None => diags.push(
+                Diagnostic::new("an experiment needs an `Encode` stimulus to present")
+                    .with_span(span.clone()),
+            ),
+            Some((first, rest)) => {
+                if let Some((_, span)) = rest.iter().find(|(ns, _)| *ns != first.0) {
                     diags.push(
-                        Diagnostic::new(format!("duplicate layer `{}`", name.name))
-                            .with_span(name.span.clone()),
+                        Diagnostic::new(
+                            "every `Encode` stimulus in an experiment needs the same `present` time",
+                        )
+                        .with_span(span.clone()),
                     );
-                } else {
-                    layers.insert(name.name.clone(), (name.span.clone(), neuron.name.clone()));
                 }
             }
-            Item::Seed(_) => {
-                seed_count += 1;
-            }
-            Item::Run(_) => {
-                run_count += 1;
-            }
-            _ => {}
         }
     }
-
-    if seed_count > 1 {
-        diags.push(Diagnostic::new("only one `seed` statement is allowed"));
+    if let Some(span) = decls.readouts.get(1) {
+        diags.push(Diagnostic::new("only one `readout` is allowed").with_span(span.clone()));
     }
-    if run_count == 0 {
+    if let Some(span) = decls.readouts.first()
+        && decls.experiments.is_empty()
+    {
+        diags.push(
+            Diagnostic::new("a `readout` needs an `experiment` to present samples")
+                .with_span(span.clone()),
+        );
+    }
+    if decls.runs.is_empty() {
         diags.push(Diagnostic::new("missing `run` statement"));
-    } else if run_count > 1 {
-        diags.push(Diagnostic::new("only one `run` statement is allowed"));
+    } else if let Some(span) = decls.runs.get(1) {
+        diags.push(Diagnostic::new("only one `run` statement is allowed").with_span(span.clone()));
+    }
+
+    let mut checks = References {
+        neurons: &decls.neurons,
+        layers: &decls.layers,
+        modulators: &decls.modulators,
+        connections: Vec::new(),
+        diags,
+    };
+    checks.visit_program(program);
+    checks.diags
+}
+
+/// First pass: collects neuron, layer, modulator, connection and probe names,
+/// where each `seed` and `run` is, and what an `experiment` presents.
+#[derive(Default)]
+struct Declarations {
+    neurons: HashMap<String, Span>,
+    layers: HashMap<String, (Span, String)>,
+    modulators: HashMap<String, Span>,
+    connections: HashMap<String, Span>,
+    probes: HashMap<String, Span>,
+    seeds: Vec<Span>,
+    runs: Vec<Span>,
+    experiments: Vec<Span>,
+    readouts: Vec<Span>,
+    /// `present` of every `Encode` stimulus, in nanoseconds.
+    presents: Vec<(i64, Span)>,
+    diags: Vec<Diagnostic>,
+}
+
+impl Visit for Declarations {
+    fn visit_neuron(&mut self, NeuronDef { name, .. }: &NeuronDef) {
+        if self.neurons.contains_key(&name.name) {
+            self.diags.push(
+                Diagnostic::new(format!("duplicate neuron `{}`", name.name))
+                    .with_span(name.span.clone()),
+            );
+        } else {
+            self.neurons.insert(name.name.clone(), name.span.clone());
+        }
     }
 
-    for item in &program.items {
-        match item {
-            Item::Layer(LayerDef { neuron, .. }) => {
-                if !neurons.contains_key(&neuron.name) {
+    fn visit_layer(&mut self, LayerDef { name, neuron, .. }: &LayerDef) {
+        if self.layers.contains_key(&name.name) {
+            self.diags.push(
+                Diagnostic::new(format!("duplicate layer `{}`", name.name))
+                    .with_span(name.span.clone()),
+            );
+        } else {
+            self.layers
+                .insert(name.name.clone(), (name.span.clone(), neuron.name.clone()));
+        }
+    }
+
+    fn visit_modulator(&mut self, ModulatorDef { name, .. }: &ModulatorDef) {
+        if self.modulators.contains_key(&name.name) {
+            self.diags.push(
+                Diagnostic::new(format!("duplicate modulator `{}`", name.name))
+                    .with_span(name.span.clone()),
+            );
+        } else {
+            self.modulators.insert(name.name.clone(), name.span.clone());
+        }
+    }
+
+    fn visit_connect(&mut self, ConnectDef { name, .. }: &ConnectDef) {
+        let Some(name) = name else {
+            return;
+        };
+        if self.connections.contains_key(&name.name) {
+            self.diags.push(
+                Diagnostic::new(format!("duplicate connection `{}`", name.name))
+                    .with_span(name.span.clone()),
+            );
+        } else {
+            self.connections
+                .insert(name.name.clone(), name.span.clone());
+        }
+    }
+
+    fn visit_probe(&mut self, ProbeDef { name, .. }: &ProbeDef) {
+        if self.probes.contains_key(&name.name) {
+            self.diags.push(
+                Diagnostic::new(format!("duplicate probe `{}`", name.name))
+                    .with_span(name.span.clone()),
+            );
+        } else {
+            self.probes.insert(name.name.clone(), name.span.clone());
+        }
+    }
+
+    fn visit_seed(&mut self, stmt: &SeedStmt) {
+        self.seeds.push(stmt.span.clone());
+    }
+
+    fn visit_run(&mut self, stmt: &RunStmt) {
+        self.runs.push(stmt.span.clone());
+    }
+
+    fn visit_experiment(&mut self, def: &ExperimentDef) {
+        self.experiments.push(def.span.clone());
+    }
+
+    fn visit_readout(&mut self, def: &ReadoutDef) {
+        self.readouts.push(def.span.clone());
+    }
+
+    fn visit_stimulus(&mut self, def: &StimulusDef) {
+        if let StimulusModel::Encode(enc) = &def.model
+            && let Ok(ns) = time_to_nanos(&enc.present, "presentation time")
+        {
+            self.presents.push((ns, enc.span.clone()));
+        }
+    }
+}
+
+/// Second pass: resolves references against the declarations and checks
+/// quantities.
+struct References<'a> {
+    neurons: &'a HashMap<String, Span>,
+    layers: &'a HashMap<String, (Span, String)>,
+    modulators: &'a HashMap<String, Span>,
+    /// Connections declared so far, for `disconnect`.
+    connections: Vec<ConnectDef>,
+    diags: Vec<Diagnostic>,
+}
+
+impl References<'_> {
+    fn layer_ref(&mut self, layer: &Ident, role: &str) {
+        if !self.layers.contains_key(&layer.name) {
+            self.diags.push(
+                Diagnostic::new(format!("unknown {role} layer `{}`", layer.name))
+                    .with_span(layer.span.clone()),
+            );
+        }
+    }
+}
+
+impl References<'_> {
+    /// A modulator named by a neuron or plasticity rule.
+    fn modulator_ref(&self, expr: &Expr) -> Result<(), Diagnostic> {
+        match expr {
+            Expr::Ident(id) if self.modulators.contains_key(&id.name) => Ok(()),
+            Expr::Ident(id) => Err(Diagnostic::new(format!("unknown modulator `{}`", id.name))
+                .with_span(id.span.clone())),
+            _ => Err(Diagnostic::new("expected a modulator name").with_span(span_of(expr))),
+        }
+    }
+
+    /// Gap junctions take a unitless coupling `g` and nothing else.
+    fn check_electrical(&mut self, def: &ConnectDef) {
+        for assign in &def.body {
+            if assign.key.name != "g" {
+                self.diags.push(
+                    Diagnostic::new(format!(
+                        "electrical connections only take `g`, not `{}`",
+                        assign.key.name
+                    ))
+                    .with_span(assign.key.span.clone()),
+                );
+            } else if let Err(diag) = validate_unitless_expr(&assign.value, "coupling `g`") {
+                self.diags.push(diag);
+            }
+        }
+        if !def.body.iter().any(|a| a.key.name == "g") {
+            self.diags.push(
+                Diagnostic::new("electrical connection needs a coupling conductance `g`")
+                    .with_span(def.span.clone()),
+            );
+        }
+    }
+
+    /// `plasticity = RSTDP(...)`: every argument is named, taus are positive
+    /// times, `modulator` names a modulator and the rest are unitless.
+    fn validate_plasticity(&self, expr: &Expr) -> Vec<Diagnostic> {
+        let Expr::Call(call) = expr else {
+            return vec![
+                Diagnostic::new("expected a plasticity rule such as `RSTDP(...)`")
+                    .with_span(span_of(expr)),
+            ];
+        };
+        if call.name.name != "RSTDP" {
+            return vec![
+                Diagnostic::new(format!(
+                    "unknown plasticity rule `{}` (expected `RSTDP`)",
+                    call.name.name
+                ))
+                .with_span(call.name.span.clone()),
+            ];
+        }
+        let mut diags = Vec::new();
+        for arg in &call.args {
+            let (name, value) = match arg {
+                CallArg::Named { name, value } => (name, value),
+                CallArg::Positional(value) => {
                     diags.push(
-                        Diagnostic::new(format!("unknown neuron type `{}`", neuron.name))
-                            .with_span(neuron.span.clone()),
+                        Diagnostic::new("`RSTDP` arguments must be named")
+                            .with_span(span_of(value)),
                     );
+                    continue;
+                }
+            };
+            let context = format!("`RSTDP` argument `{}`", name.name);
+            let check = match (name.name.as_str(), value) {
+                ("tau_plus" | "tau_minus" | "tau_e", Expr::Number(q)) => {
+                    expect_positive_time(q, &context)
+                }
+                ("tau_plus" | "tau_minus" | "tau_e", _) => Err(Diagnostic::new(format!(
+                    "expected time quantity for {context}"
+                ))
+                .with_span(span_of(value))),
+                ("a_plus" | "a_minus" | "lr" | "w_min" | "w_max", _) => {
+                    validate_number(value, &context)
                 }
+                ("modulator", _) => self.modulator_ref(value),
+                _ => Err(
+                    Diagnostic::new(format!("unknown `RSTDP` argument `{}`", name.name))
+                        .with_span(name.span.clone()),
+                ),
+            };
+            diags.extend(check.err());
+        }
+        diags
+    }
+}
+
+impl Visit for References<'_> {
+    fn visit_neuron(&mut self, def: &NeuronDef) {
+        for assign in &def.body {
+            let check = match assign.key.name.as_str() {
+                "compartments" => validate_count(&assign.value, "`compartments`"),
+                "g_axial" => validate_unitless_expr(&assign.value, "axial conductance `g_axial`"),
+                "modulator" => self.modulator_ref(&assign.value),
+                "modulator_gain" => validate_number(&assign.value, "`modulator_gain`"),
+                _ => continue,
+            };
+            if let Err(diag) = check {
+                self.diags.push(diag);
             }
-            Item::Connect(ConnectDef { src, dst, body }) => {
-                if !layers.contains_key(&src.name) {
-                    diags.push(
-                        Diagnostic::new(format!("unknown source layer `{}`", src.name))
-                            .with_span(src.span.clone()),
-                    );
+        }
+    }
+
+    fn visit_layer(&mut self, LayerDef { neuron, .. }: &LayerDef) {
+        if !self.neurons.contains_key(&neuron.name) {
+            self.diags.push(
+                Diagnostic::new(format!("unknown neuron type `{}`", neuron.name))
+                    .with_span(neuron.span.clone()),
+            );
+        }
+    }
+
+    fn visit_connect(&mut self, def: &ConnectDef) {
+        self.layer_ref(&def.src, "source");
+        self.layer_ref(&def.dst, "destination");
+        if def.kind == ConnectKind::Electrical {
+            self.check_electrical(def);
+        }
+        for assign in &def.body {
+            let check = match assign.key.name.as_str() {
+                "d" => validate_delay_expr(&assign.value),
+                "w" => match &assign.value {
+                    Expr::Call(call) if call.name.name == "from_file" => validate_weight_file(call),
+                    _ => continue,
+                },
+                "compartment" if def.kind == ConnectKind::Chemical => {
+                    validate_count(&assign.value, "`compartment`")
                 }
-                if !layers.contains_key(&dst.name) {
-                    diags.push(
-                        Diagnostic::new(format!("unknown destination layer `{}`", dst.name))
-                            .with_span(dst.span.clone()),
-                    );
+                "normalize" if def.kind == ConnectKind::Chemical => {
+                    validate_normalize(&assign.value)
                 }
-                for assign in body {
-                    if assign.key.name == "d"
-                        && let Err(diag) = validate_time_expr(&assign.value, "connection delay")
-                    {
-                        diags.push(diag);
+                "plasticity" if def.kind == ConnectKind::Chemical => {
+                    let diags = self.validate_plasticity(&assign.value);
+                    self.diags.extend(diags);
+                    continue;
+                }
+                "delay_plasticity" if def.kind == ConnectKind::Chemical => {
+                    self.diags.extend(validate_delay_plasticity(&assign.value));
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Err(diag) = check {
+                self.diags.push(diag);
+            }
+        }
+        self.connections.push(def.clone());
+    }
+
+    fn visit_disconnect(&mut self, stmt: &DisconnectStmt) {
+        if !self.connections.iter().any(|c| stmt.target.matches(c)) {
+            let span = match &stmt.target {
+                ConnectionRef::Named(name) => name.span.clone(),
+                ConnectionRef::Layers { src, dst } => Span::new(src.span.start, dst.span.end),
+            };
+            self.diags.push(
+                Diagnostic::new(format!(
+                    "`disconnect {}` does not match an earlier connection",
+                    stmt.target
+                ))
+                .with_span(span),
+            );
+        }
+    }
+
+    fn visit_modulator(&mut self, def: &ModulatorDef) {
+        for assign in &def.body {
+            let check = match assign.key.name.as_str() {
+                "tau" => match &assign.value {
+                    Expr::Number(q) => expect_positive_time(q, "modulator `tau`"),
+                    other => Err(
+                        Diagnostic::new("expected time quantity for modulator `tau`")
+                            .with_span(span_of(other)),
+                    ),
+                },
+                "source" => match &assign.value {
+                    Expr::Ident(layer) => {
+                        self.layer_ref(layer, "modulator source");
+                        Ok(())
                     }
+                    other => Err(Diagnostic::new("modulator `source` must name a layer")
+                        .with_span(span_of(other))),
+                },
+                "gain" => validate_number(&assign.value, "modulator `gain`"),
+                other => Err(Diagnostic::new(format!(
+                    "modulators take `tau`, `source` and `gain`, not `{other}`"
+                ))
+                .with_span(assign.key.span.clone())),
+            };
+            if let Err(diag) = check {
+                self.diags.push(diag);
+            }
+        }
+        for (key, what) in [
+            ("tau", "a decay time `tau`"),
+            ("source", "a `source` layer"),
+        ] {
+            if !def.body.iter().any(|a| a.key.name == key) {
+                self.diags.push(
+                    Diagnostic::new(format!("modulator `{}` needs {what}", def.name.name))
+                        .with_span(def.name.span.clone()),
+                );
+            }
+        }
+    }
+
+    fn visit_experiment(&mut self, def: &ExperimentDef) {
+        for assign in &def.body {
+            let check = match (assign.key.name.as_str(), &assign.value) {
+                ("epochs", Expr::Number(q))
+                    if q.unit.is_none() && q.value >= 1.0 && q.value.fract() == 0.0 =>
+                {
+                    Ok(())
                 }
+                ("epochs", other) => Err(Diagnostic::new(
+                    "experiment `epochs` must be a positive integer without a unit",
+                )
+                .with_span(span_of(other))),
+                ("isi", Expr::Number(q)) => match time_to_nanos(q, "experiment `isi`") {
+                    Ok(ns) if ns < 0 => {
+                        Err(Diagnostic::new("experiment `isi` must not be negative")
+                            .with_span(q.span.clone()))
+                    }
+                    other => other.map(drop),
+                },
+                ("isi", other) => Err(Diagnostic::new(
+                    "expected time quantity for experiment `isi`",
+                )
+                .with_span(span_of(other))),
+                ("shuffle", Expr::Ident(id)) if id.name == "true" || id.name == "false" => Ok(()),
+                ("shuffle", other) => Err(Diagnostic::new(
+                    "experiment `shuffle` must be `true` or `false`",
+                )
+                .with_span(span_of(other))),
+                ("readout", Expr::Ident(layer)) => {
+                    self.layer_ref(layer, "experiment readout");
+                    Ok(())
+                }
+                ("readout", other) => {
+                    Err(Diagnostic::new("experiment `readout` must name a layer")
+                        .with_span(span_of(other)))
+                }
+                (other, _) => Err(Diagnostic::new(format!(
+                    "experiments take `epochs`, `isi`, `shuffle` and `readout`, not `{other}`"
+                ))
+                .with_span(assign.key.span.clone())),
+            };
+            if let Err(diag) = check {
+                self.diags.push(diag);
             }
-            Item::Run(run) => {
-                if let Err(diag) = expect_positive_time(&run.duration, "run duration") {
-                    diags.push(diag);
+        }
+    }
+
+    fn visit_readout(&mut self, def: &ReadoutDef) {
+        self.layer_ref(&def.layer, "readout");
+        if def.labels.is_empty() {
+            self.diags.push(
+                Diagnostic::new("readout label file path is empty").with_span(def.span.clone()),
+            );
+        }
+        if let Some(rule) = &def.rule
+            && rule.name != "max_spikes"
+        {
+            self.diags.push(
+                Diagnostic::new(format!(
+                    "unknown readout rule `{}` (expected `max_spikes`)",
+                    rule.name
+                ))
+                .with_span(rule.span.clone()),
+            );
+        }
+    }
+
+    fn visit_reward(&mut self, stmt: &RewardStmt) {
+        if let Some(unit) = &stmt.value.unit {
+            self.diags
+                .push(Diagnostic::new("reward value takes no unit").with_span(unit.span.clone()));
+        }
+        match time_to_nanos(&stmt.at, "reward time") {
+            Ok(ns) if ns < 0 => self.diags.push(
+                Diagnostic::new("reward time must not be negative").with_span(stmt.at.span.clone()),
+            ),
+            Ok(_) => {}
+            Err(diag) => self.diags.push(diag),
+        }
+    }
+
+    fn visit_record(&mut self, stmt: &RecordStmt) {
+        self.layer_ref(&stmt.layer, "recorded");
+        if let Err(diag) = expect_positive_time(&stmt.window, "smoothing window") {
+            self.diags.push(diag);
+        }
+    }
+
+    fn visit_probe(&mut self, def: &ProbeDef) {
+        self.layer_ref(&def.layer, "probed");
+        if let Some((first, end)) = def.neurons
+            && first >= end
+        {
+            self.diags.push(
+                Diagnostic::new(format!(
+                    "probe `{}` has no neurons in `{first}..{end}`",
+                    def.name.name
+                ))
+                .with_span(def.span.clone()),
+            );
+        }
+        if let Some(every) = &def.every
+            && let Err(diag) = expect_positive_time(every, "probe interval")
+        {
+            self.diags.push(diag);
+        }
+    }
+
+    fn visit_run(&mut self, run: &RunStmt) {
+        if let Err(diag) = expect_positive_time(&run.duration, "run duration") {
+            self.diags.push(diag);
+        }
+        if let Some(step) = &run.step
+            && let Err(diag) = expect_positive_time(step, "run step")
+        {
+            self.diags.push(diag);
+        }
+    }
+
+    fn visit_stimulus(&mut self, stim: &StimulusDef) {
+        self.layer_ref(&stim.layer, "stimulus");
+        let has_rate = match &stim.model {
+            StimulusModel::Poisson { correlation, .. } => {
+                correlation.as_ref().is_none_or(|c| c.value == 0.0)
+            }
+            StimulusModel::Encode(enc) => matches!(enc.scheme, EncodeScheme::Rate { .. }),
+            StimulusModel::External(ext) => ext.input == ExternalSignal::Rate,
+        };
+        if stim.combine == Some(StimulusCombine::Max) && !has_rate {
+            self.diags.push(
+                Diagnostic::new(
+                    "`combine = max` needs a stimulus with a rate: `Poisson` without \
+                     `correlation`, `Encode` with `scheme=rate` or `External` with `input=rate`",
+                )
+                .with_span(stim.span.clone()),
+            );
+        }
+        match &stim.model {
+            StimulusModel::Poisson { rate, correlation } => {
+                if let Err(diag) = expect_rate(rate, "Poisson rate") {
+                    self.diags.push(diag);
                 }
-                if let Some(step) = &run.step
-                    && let Err(diag) = expect_positive_time(step, "run step")
+                if let Some(c) = correlation
+                    && (c.unit.is_some() || !(0.0..=1.0).contains(&c.value))
                 {
-                    diags.push(diag);
+                    self.diags.push(
+                        Diagnostic::new("Poisson correlation must be a number from 0 to 1")
+                            .with_span(c.span.clone()),
+                    );
                 }
             }
-            Item::Stimulus(stim) => {
-                if !layers.contains_key(&stim.layer.name) {
-                    diags.push(
-                        Diagnostic::new(format!("unknown stimulus layer `{}`", stim.layer.name))
-                            .with_span(stim.layer.span.clone()),
+            StimulusModel::Encode(enc) => {
+                if enc.path.is_empty() {
+                    self.diags.push(
+                        Diagnostic::new("`Encode` file path is empty").with_span(enc.span.clone()),
                     );
                 }
-                match &stim.model {
-                    StimulusModel::Poisson { rate } => {
-                        if let Err(diag) = expect_rate(rate, "Poisson rate") {
-                            diags.push(diag);
-                        }
-                    }
+                if let EncodeScheme::Rate { max_rate } = &enc.scheme
+                    && let Err(diag) = expect_rate(max_rate, "max_rate")
+                {
+                    self.diags.push(diag);
+                }
+                if let Err(diag) = expect_positive_time(&enc.present, "presentation time") {
+                    self.diags.push(diag);
+                }
+            }
+            StimulusModel::External(ext) => {
+                if let Err(diag) = expect_positive_time(&ext.timeout, "external timeout") {
+                    self.diags.push(diag);
                 }
             }
-            _ => {}
         }
     }
 
-    if diags.is_empty() { Ok(()) } else { Err(diags) }
+    fn visit_assert(&mut self, stmt: &AssertStmt) {
+        self.layer_ref(stmt.metric.layer(), "assertion");
+        validate_assert(stmt, &mut self.diags);
+    }
+}
+
+fn validate_assert(stmt: &AssertStmt, diags: &mut Vec<Diagnostic>) {
+    match &stmt.cond {
+        AssertCond::Compare { value, .. } => {
+            if let Err(diag) = assert_bound(&stmt.metric, value) {
+                diags.push(diag);
+            }
+        }
+        AssertCond::Between { low, high } => {
+            match (
+                assert_bound(&stmt.metric, low),
+                assert_bound(&stmt.metric, high),
+            ) {
+                (Ok(lo), Ok(hi)) if lo > hi => diags.push(
+                    Diagnostic::new("assertion range is empty (lower bound exceeds upper bound)")
+                        .with_span(stmt.span.clone()),
+                ),
+                (lo, hi) => diags.extend(lo.err().into_iter().chain(hi.err())),
+            }
+        }
+    }
 }
 
 fn validate_time_expr(expr: &Expr, context: &str) -> Result<(), Diagnostic> {
@@ -143,12 +1062,218 @@ fn validate_time_expr(expr: &Expr, context: &str) -> Result<(), Diagnostic> {
     }
 }
 
-fn span_of(expr: &Expr) -> crate::diagnostic::Span {
+/// A time as [`validate_time_expr`] takes it, or the same in run steps:
+/// every number in `steps`, and a constant a whole number of them.
+fn validate_delay_expr(expr: &Expr) -> Result<(), Diagnostic> {
+    let in_steps =
+        |q: &crate::ast::Quantity| q.unit.as_ref().is_some_and(|u| is_step_unit(&u.name));
+    let numbers: Vec<&crate::ast::Quantity> = match expr {
+        Expr::Number(q) => vec![q],
+        Expr::Call(call) => call
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                crate::ast::CallArg::Positional(Expr::Number(q))
+                | crate::ast::CallArg::Named {
+                    value: Expr::Number(q),
+                    ..
+                } => Some(q),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    if !numbers.iter().any(|q| in_steps(q)) {
+        return validate_time_expr(expr, "connection delay");
+    }
+    if let Some(q) = numbers.iter().find(|q| !in_steps(q)) {
+        return Err(
+            Diagnostic::new("connection delay mixes `steps` with other units")
+                .with_span(q.span.clone()),
+        );
+    }
+    match expr {
+        Expr::Number(q) if q.value < 0.0 || q.value.fract() != 0.0 => Err(Diagnostic::new(
+            "a delay in steps must be a whole number of them, 0 or more",
+        )
+        .with_span(q.span.clone())),
+        Expr::Call(call) if call.name.name != "Normal" && call.name.name != "Uniform" => {
+            Err(Diagnostic::new("unsupported delay expression").with_span(call.name.span.clone()))
+        }
+        Expr::Call(call) if numbers.len() != call.args.len() => {
+            Err(Diagnostic::new("expected time quantity").with_span(call.name.span.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `normalize = total(<sum>)` or `normalize = per_target(<sum>)`, with the
+/// sum a plain number.
+fn validate_normalize(expr: &Expr) -> Result<(), Diagnostic> {
+    let Expr::Call(call) = expr else {
+        return Err(Diagnostic::new(
+            "expected `total(<sum>)` or `per_target(<sum>)` for `normalize`",
+        )
+        .with_span(span_of(expr)));
+    };
+    if call.name.name != "total" && call.name.name != "per_target" {
+        return Err(Diagnostic::new(format!(
+            "unknown normalization `{}` (expected `total` or `per_target`)",
+            call.name.name
+        ))
+        .with_span(call.name.span.clone()));
+    }
+    match call.args.as_slice() {
+        [CallArg::Positional(sum @ Expr::Number(_))] => {
+            validate_number(sum, &format!("`{}` sum", call.name.name))
+        }
+        _ => Err(Diagnostic::new(format!(
+            "`{}` takes one number, the sum of the weights",
+            call.name.name
+        ))
+        .with_span(call.span.clone())),
+    }
+}
+
+/// `delay_plasticity = DelaySTDP(...)`: every argument is named, `window`
+/// is a positive time, `d_min` and `d_max` are delays, `d_max` is given and
+/// `lr` is unitless.
+fn validate_delay_plasticity(expr: &Expr) -> Vec<Diagnostic> {
+    let call = match expr {
+        Expr::Call(call) if call.name.name == "DelaySTDP" => call,
+        Expr::Call(call) => {
+            return vec![
+                Diagnostic::new(format!(
+                    "unknown delay plasticity rule `{}` (expected `DelaySTDP`)",
+                    call.name.name
+                ))
+                .with_span(call.name.span.clone()),
+            ];
+        }
+        _ => {
+            return vec![
+                Diagnostic::new("expected a delay plasticity rule such as `DelaySTDP(...)`")
+                    .with_span(span_of(expr)),
+            ];
+        }
+    };
+    let mut diags = Vec::new();
+    for arg in &call.args {
+        let (name, value) = match arg {
+            CallArg::Named { name, value } => (name, value),
+            CallArg::Positional(value) => {
+                diags.push(
+                    Diagnostic::new("`DelaySTDP` arguments must be named")
+                        .with_span(span_of(value)),
+                );
+                continue;
+            }
+        };
+        let context = format!("`DelaySTDP` argument `{}`", name.name);
+        let check = match (name.name.as_str(), value) {
+            ("window", Expr::Number(q)) => expect_positive_time(q, &context),
+            ("window", _) => Err(
+                Diagnostic::new(format!("expected time quantity for {context}"))
+                    .with_span(span_of(value)),
+            ),
+            ("d_min" | "d_max", Expr::Number(_)) => validate_delay_expr(value),
+            ("d_min" | "d_max", _) => {
+                Err(Diagnostic::new(format!("expected a delay for {context}"))
+                    .with_span(span_of(value)))
+            }
+            ("lr", _) => validate_number(value, &context),
+            _ => Err(
+                Diagnostic::new(format!("unknown `DelaySTDP` argument `{}`", name.name))
+                    .with_span(name.span.clone()),
+            ),
+        };
+        diags.extend(check.err());
+    }
+    let has_max = call
+        .args
+        .iter()
+        .any(|arg| matches!(arg, CallArg::Named { name, .. } if name.name == "d_max"));
+    if !has_max {
+        diags.push(
+            Diagnostic::new("`DelaySTDP` needs a `d_max`, the longest delay it may learn")
+                .with_span(call.span.clone()),
+        );
+    }
+    diags
+}
+
+fn validate_unitless_expr(expr: &Expr, context: &str) -> Result<(), Diagnostic> {
+    let unitless = |q: &crate::ast::Quantity| match &q.unit {
+        None => Ok(()),
+        Some(unit) => {
+            Err(Diagnostic::new(format!("{context} takes no unit")).with_span(unit.span.clone()))
+        }
+    };
+    match expr {
+        Expr::Number(q) => unitless(q),
+        Expr::Call(call) if call.name.name == "Normal" || call.name.name == "Uniform" => {
+            for arg in &call.args {
+                let expr = match arg {
+                    crate::ast::CallArg::Positional(e) => e,
+                    crate::ast::CallArg::Named { value, .. } => value,
+                };
+                match expr {
+                    Expr::Number(q) => unitless(q)?,
+                    _ => {
+                        return Err(Diagnostic::new(format!("expected number for {context}"))
+                            .with_span(span_of(expr)));
+                    }
+                }
+            }
+            Ok(())
+        }
+        _ => {
+            Err(Diagnostic::new(format!("expected number for {context}")).with_span(span_of(expr)))
+        }
+    }
+}
+
+/// `w = from_file("<path>")`.
+fn validate_weight_file(call: &crate::ast::Call) -> Result<(), Diagnostic> {
+    match call.args.as_slice() {
+        [CallArg::Positional(Expr::String(path))] if path.is_empty() => {
+            Err(Diagnostic::new("`from_file` path is empty").with_span(call.name.span.clone()))
+        }
+        [CallArg::Positional(Expr::String(_))] => Ok(()),
+        _ => {
+            Err(Diagnostic::new("`from_file` takes one file path")
+                .with_span(call.name.span.clone()))
+        }
+    }
+}
+
+/// A single unitless number.
+fn validate_number(expr: &Expr, context: &str) -> Result<(), Diagnostic> {
+    match expr {
+        Expr::Number(_) => validate_unitless_expr(expr, context),
+        _ => {
+            Err(Diagnostic::new(format!("expected number for {context}")).with_span(span_of(expr)))
+        }
+    }
+}
+
+/// A plain non-negative integer, such as a compartment count or index.
+fn validate_count(expr: &Expr, context: &str) -> Result<(), Diagnostic> {
+    match expr {
+        Expr::Number(q) if q.unit.is_none() && q.value >= 0.0 && q.value.fract() == 0.0 => Ok(()),
+        _ => Err(Diagnostic::new(format!(
+            "{context} must be a non-negative integer without a unit"
+        ))
+        .with_span(span_of(expr))),
+    }
+}
+
+fn span_of(expr: &Expr) -> Span {
     match expr {
         Expr::Number(q) => q.span.clone(),
-        Expr::String(_) => crate::diagnostic::Span::new(0, 0),
+        Expr::String(_) => Span::new(0, 0),
         Expr::Ident(id) => id.span.clone(),
-        Expr::Call(call) => call.name.span.clone(),
+        Expr::Call(call) => call.span.clone(),
     }
 }
 
@@ -0,0 +1,878 @@
+//! Traversal of the parse tree.
+//!
+//! [`Visit`] walks a borrowed [`Program`] and [`VisitMut`] a mutable one.
+//! Every method defaults to the matching `walk_*` function, which visits the
+//! node's children in source order, so an implementation only overrides the
+//! nodes it cares about and calls `walk_*` itself to keep descending:
+//!
+//! ```
+//! use converge_lang::ast::{Call, Program};
+//! use converge_lang::parser::parse_program;
+//! use converge_lang::visit::{self, Visit};
+//!
+//! #[derive(Default)]
+//! struct Calls(Vec<String>);
+//!
+//! impl Visit for Calls {
+//!     fn visit_call(&mut self, call: &Call) {
+//!         self.0.push(call.name.name.clone());
+//!         visit::walk_call(self, call);
+//!     }
+//! }
+//!
+//! let program = parse_program("connect A -> B { w = Normal(0.5, 0.1) }\n").unwrap();
+//! let mut calls = Calls::default();
+//! calls.visit_program(&program);
+//! assert_eq!(calls.0, ["Normal"]);
+//! ```
+
+use crate::ast::*;
+use crate::diagnostic::Span;
+
+pub trait Visit {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_item(&mut self, item: &Item) {
+        walk_item(self, item);
+    }
+
+    fn visit_neuron(&mut self, def: &NeuronDef) {
+        walk_neuron(self, def);
+    }
+
+    fn visit_layer(&mut self, def: &LayerDef) {
+        walk_layer(self, def);
+    }
+
+    fn visit_connect(&mut self, def: &ConnectDef) {
+        walk_connect(self, def);
+    }
+
+    fn visit_stimulus(&mut self, def: &StimulusDef) {
+        walk_stimulus(self, def);
+    }
+
+    fn visit_run(&mut self, stmt: &RunStmt) {
+        walk_run(self, stmt);
+    }
+
+    fn visit_seed(&mut self, _stmt: &SeedStmt) {}
+
+    fn visit_assert(&mut self, stmt: &AssertStmt) {
+        walk_assert(self, stmt);
+    }
+
+    fn visit_disconnect(&mut self, stmt: &DisconnectStmt) {
+        walk_disconnect(self, stmt);
+    }
+
+    fn visit_reward(&mut self, stmt: &RewardStmt) {
+        walk_reward(self, stmt);
+    }
+
+    fn visit_record(&mut self, stmt: &RecordStmt) {
+        walk_record(self, stmt);
+    }
+
+    fn visit_probe(&mut self, def: &ProbeDef) {
+        walk_probe(self, def);
+    }
+
+    fn visit_modulator(&mut self, def: &ModulatorDef) {
+        walk_modulator(self, def);
+    }
+
+    fn visit_experiment(&mut self, def: &ExperimentDef) {
+        walk_experiment(self, def);
+    }
+
+    fn visit_readout(&mut self, def: &ReadoutDef) {
+        walk_readout(self, def);
+    }
+
+    fn visit_network(&mut self, def: &NetworkDef) {
+        walk_network(self, def);
+    }
+
+    fn visit_sweep(&mut self, def: &SweepDef) {
+        walk_sweep(self, def);
+    }
+
+    fn visit_metric(&mut self, metric: &Metric) {
+        walk_metric(self, metric);
+    }
+
+    fn visit_assert_cond(&mut self, cond: &AssertCond) {
+        walk_assert_cond(self, cond);
+    }
+
+    fn visit_stimulus_model(&mut self, model: &StimulusModel) {
+        walk_stimulus_model(self, model);
+    }
+
+    fn visit_assign(&mut self, assign: &Assign) {
+        walk_assign(self, assign);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_call(&mut self, call: &Call) {
+        walk_call(self, call);
+    }
+
+    fn visit_call_arg(&mut self, arg: &CallArg) {
+        walk_call_arg(self, arg);
+    }
+
+    fn visit_quantity(&mut self, q: &Quantity) {
+        walk_quantity(self, q);
+    }
+
+    fn visit_ident(&mut self, _ident: &Ident) {}
+}
+
+pub fn walk_program<V: Visit + ?Sized>(v: &mut V, program: &Program) {
+    for item in &program.items {
+        v.visit_item(item);
+    }
+}
+
+pub fn walk_item<V: Visit + ?Sized>(v: &mut V, item: &Item) {
+    match item {
+        Item::Neuron(def) => v.visit_neuron(def),
+        Item::Layer(def) => v.visit_layer(def),
+        Item::Connect(def) => v.visit_connect(def),
+        Item::Stimulus(def) => v.visit_stimulus(def),
+        Item::Run(stmt) => v.visit_run(stmt),
+        Item::Seed(stmt) => v.visit_seed(stmt),
+        Item::Assert(stmt) => v.visit_assert(stmt),
+        Item::Disconnect(stmt) => v.visit_disconnect(stmt),
+        Item::Reward(stmt) => v.visit_reward(stmt),
+        Item::Record(stmt) => v.visit_record(stmt),
+        Item::Probe(def) => v.visit_probe(def),
+        Item::Modulator(def) => v.visit_modulator(def),
+        Item::Experiment(def) => v.visit_experiment(def),
+        Item::Readout(def) => v.visit_readout(def),
+        Item::Network(def) => v.visit_network(def),
+        Item::Sweep(def) => v.visit_sweep(def),
+    }
+}
+
+pub fn walk_network<V: Visit + ?Sized>(v: &mut V, def: &NetworkDef) {
+    v.visit_ident(&def.name);
+    for item in &def.items {
+        v.visit_item(item);
+    }
+}
+
+pub fn walk_sweep<V: Visit + ?Sized>(v: &mut V, def: &SweepDef) {
+    for run in &def.runs {
+        if let Some(network) = &run.network {
+            v.visit_ident(network);
+        }
+    }
+    for metric in &def.report {
+        if let ReportMetric::Layer(metric) = metric {
+            v.visit_metric(metric);
+        }
+    }
+}
+
+pub fn walk_neuron<V: Visit + ?Sized>(v: &mut V, def: &NeuronDef) {
+    v.visit_ident(&def.name);
+    for assign in &def.body {
+        v.visit_assign(assign);
+    }
+}
+
+pub fn walk_modulator<V: Visit + ?Sized>(v: &mut V, def: &ModulatorDef) {
+    v.visit_ident(&def.name);
+    for assign in &def.body {
+        v.visit_assign(assign);
+    }
+}
+
+pub fn walk_experiment<V: Visit + ?Sized>(v: &mut V, def: &ExperimentDef) {
+    for assign in &def.body {
+        v.visit_assign(assign);
+    }
+}
+
+pub fn walk_readout<V: Visit + ?Sized>(v: &mut V, def: &ReadoutDef) {
+    v.visit_ident(&def.layer);
+    if let Some(rule) = &def.rule {
+        v.visit_ident(rule);
+    }
+}
+
+pub fn walk_layer<V: Visit + ?Sized>(v: &mut V, def: &LayerDef) {
+    v.visit_ident(&def.name);
+    v.visit_ident(&def.neuron);
+}
+
+pub fn walk_connect<V: Visit + ?Sized>(v: &mut V, def: &ConnectDef) {
+    if let Some(name) = &def.name {
+        v.visit_ident(name);
+    }
+    v.visit_ident(&def.src);
+    v.visit_ident(&def.dst);
+    for assign in &def.body {
+        v.visit_assign(assign);
+    }
+}
+
+pub fn walk_stimulus<V: Visit + ?Sized>(v: &mut V, def: &StimulusDef) {
+    v.visit_ident(&def.layer);
+    v.visit_stimulus_model(&def.model);
+}
+
+pub fn walk_stimulus_model<V: Visit + ?Sized>(v: &mut V, model: &StimulusModel) {
+    match model {
+        StimulusModel::Poisson { rate, correlation } => {
+            v.visit_quantity(rate);
+            if let Some(c) = correlation {
+                v.visit_quantity(c);
+            }
+        }
+        StimulusModel::Encode(enc) => {
+            if let EncodeScheme::Rate { max_rate } = &enc.scheme {
+                v.visit_quantity(max_rate);
+            }
+            v.visit_quantity(&enc.present);
+        }
+        StimulusModel::External(ext) => v.visit_quantity(&ext.timeout),
+    }
+}
+
+pub fn walk_run<V: Visit + ?Sized>(v: &mut V, stmt: &RunStmt) {
+    v.visit_quantity(&stmt.duration);
+    if let Some(step) = &stmt.step {
+        v.visit_quantity(step);
+    }
+}
+
+pub fn walk_reward<V: Visit + ?Sized>(v: &mut V, stmt: &RewardStmt) {
+    v.visit_quantity(&stmt.value);
+    v.visit_quantity(&stmt.at);
+}
+
+pub fn walk_record<V: Visit + ?Sized>(v: &mut V, stmt: &RecordStmt) {
+    v.visit_ident(&stmt.layer);
+    v.visit_quantity(&stmt.window);
+}
+
+pub fn walk_probe<V: Visit + ?Sized>(v: &mut V, def: &ProbeDef) {
+    v.visit_ident(&def.name);
+    v.visit_ident(&def.layer);
+    if let Some(every) = &def.every {
+        v.visit_quantity(every);
+    }
+}
+
+pub fn walk_assert<V: Visit + ?Sized>(v: &mut V, stmt: &AssertStmt) {
+    v.visit_metric(&stmt.metric);
+    v.visit_assert_cond(&stmt.cond);
+}
+
+pub fn walk_disconnect<V: Visit + ?Sized>(v: &mut V, stmt: &DisconnectStmt) {
+    match &stmt.target {
+        ConnectionRef::Named(name) => v.visit_ident(name),
+        ConnectionRef::Layers { src, dst } => {
+            v.visit_ident(src);
+            v.visit_ident(dst);
+        }
+    }
+}
+
+pub fn walk_metric<V: Visit + ?Sized>(v: &mut V, metric: &Metric) {
+    v.visit_ident(metric.layer());
+}
+
+pub fn walk_assert_cond<V: Visit + ?Sized>(v: &mut V, cond: &AssertCond) {
+    match cond {
+        AssertCond::Compare { value, .. } => v.visit_quantity(value),
+        AssertCond::Between { low, high } => {
+            v.visit_quantity(low);
+            v.visit_quantity(high);
+        }
+    }
+}
+
+pub fn walk_assign<V: Visit + ?Sized>(v: &mut V, assign: &Assign) {
+    v.visit_ident(&assign.key);
+    v.visit_expr(&assign.value);
+}
+
+pub fn walk_expr<V: Visit + ?Sized>(v: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Number(q) => v.visit_quantity(q),
+        Expr::String(_) => {}
+        Expr::Ident(id) => v.visit_ident(id),
+        Expr::Call(call) => v.visit_call(call),
+    }
+}
+
+pub fn walk_call<V: Visit + ?Sized>(v: &mut V, call: &Call) {
+    v.visit_ident(&call.name);
+    for arg in &call.args {
+        v.visit_call_arg(arg);
+    }
+}
+
+pub fn walk_call_arg<V: Visit + ?Sized>(v: &mut V, arg: &CallArg) {
+    match arg {
+        CallArg::Positional(expr) => v.visit_expr(expr),
+        CallArg::Named { name, value } => {
+            v.visit_ident(name);
+            v.visit_expr(value);
+        }
+    }
+}
+
+pub fn walk_quantity<V: Visit + ?Sized>(v: &mut V, q: &Quantity) {
+    if let Some(unit) = &q.unit {
+        v.visit_ident(unit);
+    }
+}
+
+/// [`Visit`] over a mutable tree, for rewriters.
+pub trait VisitMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        walk_item_mut(self, item);
+    }
+
+    fn visit_neuron_mut(&mut self, def: &mut NeuronDef) {
+        walk_neuron_mut(self, def);
+    }
+
+    fn visit_layer_mut(&mut self, def: &mut LayerDef) {
+        walk_layer_mut(self, def);
+    }
+
+    fn visit_connect_mut(&mut self, def: &mut ConnectDef) {
+        walk_connect_mut(self, def);
+    }
+
+    fn visit_stimulus_mut(&mut self, def: &mut StimulusDef) {
+        walk_stimulus_mut(self, def);
+    }
+
+    fn visit_run_mut(&mut self, stmt: &mut RunStmt) {
+        walk_run_mut(self, stmt);
+    }
+
+    fn visit_seed_mut(&mut self, _stmt: &mut SeedStmt) {}
+
+    fn visit_assert_mut(&mut self, stmt: &mut AssertStmt) {
+        walk_assert_mut(self, stmt);
+    }
+
+    fn visit_disconnect_mut(&mut self, stmt: &mut DisconnectStmt) {
+        walk_disconnect_mut(self, stmt);
+    }
+
+    fn visit_reward_mut(&mut self, stmt: &mut RewardStmt) {
+        walk_reward_mut(self, stmt);
+    }
+
+    fn visit_record_mut(&mut self, stmt: &mut RecordStmt) {
+        walk_record_mut(self, stmt);
+    }
+
+    fn visit_probe_mut(&mut self, def: &mut ProbeDef) {
+        walk_probe_mut(self, def);
+    }
+
+    fn visit_modulator_mut(&mut self, def: &mut ModulatorDef) {
+        walk_modulator_mut(self, def);
+    }
+
+    fn visit_experiment_mut(&mut self, def: &mut ExperimentDef) {
+        walk_experiment_mut(self, def);
+    }
+
+    fn visit_readout_mut(&mut self, def: &mut ReadoutDef) {
+        walk_readout_mut(self, def);
+    }
+
+    fn visit_network_mut(&mut self, def: &mut NetworkDef) {
+        walk_network_mut(self, def);
+    }
+
+    fn visit_sweep_mut(&mut self, def: &mut SweepDef) {
+        walk_sweep_mut(self, def);
+    }
+
+    fn visit_metric_mut(&mut self, metric: &mut Metric) {
+        walk_metric_mut(self, metric);
+    }
+
+    fn visit_assert_cond_mut(&mut self, cond: &mut AssertCond) {
+        walk_assert_cond_mut(self, cond);
+    }
+
+    fn visit_stimulus_model_mut(&mut self, model: &mut StimulusModel) {
+        walk_stimulus_model_mut(self, model);
+    }
+
+    fn visit_assign_mut(&mut self, assign: &mut Assign) {
+        walk_assign_mut(self, assign);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_call_mut(&mut self, call: &mut Call) {
+        walk_call_mut(self, call);
+    }
+
+    fn visit_call_arg_mut(&mut self, arg: &mut CallArg) {
+        walk_call_arg_mut(self, arg);
+    }
+
+    fn visit_quantity_mut(&mut self, q: &mut Quantity) {
+        walk_quantity_mut(self, q);
+    }
+
+    fn visit_ident_mut(&mut self, _ident: &mut Ident) {}
+}
+
+pub fn walk_program_mut<V: VisitMut + ?Sized>(v: &mut V, program: &mut Program) {
+    for item in &mut program.items {
+        v.visit_item_mut(item);
+    }
+}
+
+pub fn walk_item_mut<V: VisitMut + ?Sized>(v: &mut V, item: &mut Item) {
+    match item {
+        Item::Neuron(def) => v.visit_neuron_mut(def),
+        Item::Layer(def) => v.visit_layer_mut(def),
+        Item::Connect(def) => v.visit_connect_mut(def),
+        Item::Stimulus(def) => v.visit_stimulus_mut(def),
+        Item::Run(stmt) => v.visit_run_mut(stmt),
+        Item::Seed(stmt) => v.visit_seed_mut(stmt),
+        Item::Assert(stmt) => v.visit_assert_mut(stmt),
+        Item::Disconnect(stmt) => v.visit_disconnect_mut(stmt),
+        Item::Reward(stmt) => v.visit_reward_mut(stmt),
+        Item::Record(stmt) => v.visit_record_mut(stmt),
+        Item::Probe(def) => v.visit_probe_mut(def),
+        Item::Modulator(def) => v.visit_modulator_mut(def),
+        Item::Experiment(def) => v.visit_experiment_mut(def),
+        Item::Readout(def) => v.visit_readout_mut(def),
+        Item::Network(def) => v.visit_network_mut(def),
+        Item::Sweep(def) => v.visit_sweep_mut(def),
+    }
+}
+
+pub fn walk_network_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut NetworkDef) {
+    v.visit_ident_mut(&mut def.name);
+    for item in &mut def.items {
+        v.visit_item_mut(item);
+    }
+}
+
+pub fn walk_sweep_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut SweepDef) {
+    for run in &mut def.runs {
+        if let Some(network) = &mut run.network {
+            v.visit_ident_mut(network);
+        }
+    }
+    for metric in &mut def.report {
+        if let ReportMetric::Layer(metric) = metric {
+            v.visit_metric_mut(metric);
+        }
+    }
+}
+
+pub fn walk_neuron_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut NeuronDef) {
+    v.visit_ident_mut(&mut def.name);
+    for assign in &mut def.body {
+        v.visit_assign_mut(assign);
+    }
+}
+
+pub fn walk_modulator_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut ModulatorDef) {
+    v.visit_ident_mut(&mut def.name);
+    for assign in &mut def.body {
+        v.visit_assign_mut(assign);
+    }
+}
+
+pub fn walk_experiment_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut ExperimentDef) {
+    for assign in &mut def.body {
+        v.visit_assign_mut(assign);
+    }
+}
+
+pub fn walk_readout_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut ReadoutDef) {
+    v.visit_ident_mut(&mut def.layer);
+    if let Some(rule) = &mut def.rule {
+        v.visit_ident_mut(rule);
+    }
+}
+
+pub fn walk_layer_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut LayerDef) {
+    v.visit_ident_mut(&mut def.name);
+    v.visit_ident_mut(&mut def.neuron);
+}
+
+pub fn walk_connect_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut ConnectDef) {
+    if let Some(name) = &mut def.name {
+        v.visit_ident_mut(name);
+    }
+    v.visit_ident_mut(&mut def.src);
+    v.visit_ident_mut(&mut def.dst);
+    for assign in &mut def.body {
+        v.visit_assign_mut(assign);
+    }
+}
+
+pub fn walk_stimulus_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut StimulusDef) {
+    v.visit_ident_mut(&mut def.layer);
+    v.visit_stimulus_model_mut(&mut def.model);
+}
+
+pub fn walk_stimulus_model_mut<V: VisitMut + ?Sized>(v: &mut V, model: &mut StimulusModel) {
+    match model {
+        StimulusModel::Poisson { rate, correlation } => {
+            v.visit_quantity_mut(rate);
+            if let Some(c) = correlation {
+                v.visit_quantity_mut(c);
+            }
+        }
+        StimulusModel::Encode(enc) => {
+            if let EncodeScheme::Rate { max_rate } = &mut enc.scheme {
+                v.visit_quantity_mut(max_rate);
+            }
+            v.visit_quantity_mut(&mut enc.present);
+        }
+        StimulusModel::External(ext) => v.visit_quantity_mut(&mut ext.timeout),
+    }
+}
+
+pub fn walk_run_mut<V: VisitMut + ?Sized>(v: &mut V, stmt: &mut RunStmt) {
+    v.visit_quantity_mut(&mut stmt.duration);
+    if let Some(step) = &mut stmt.step {
+        v.visit_quantity_mut(step);
+    }
+}
+
+pub fn walk_reward_mut<V: VisitMut + ?Sized>(v: &mut V, stmt: &mut RewardStmt) {
+    v.visit_quantity_mut(&mut stmt.value);
+    v.visit_quantity_mut(&mut stmt.at);
+}
+
+pub fn walk_record_mut<V: VisitMut + ?Sized>(v: &mut V, stmt: &mut RecordStmt) {
+    v.visit_ident_mut(&mut stmt.layer);
+    v.visit_quantity_mut(&mut stmt.window);
+}
+
+pub fn walk_probe_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut ProbeDef) {
+    v.visit_ident_mut(&mut def.name);
+    v.visit_ident_mut(&mut def.layer);
+    if let Some(every) = &mut def.every {
+        v.visit_quantity_mut(every);
+    }
+}
+
+pub fn walk_assert_mut<V: VisitMut + ?Sized>(v: &mut V, stmt: &mut AssertStmt) {
+    v.visit_metric_mut(&mut stmt.metric);
+    v.visit_assert_cond_mut(&mut stmt.cond);
+}
+
+pub fn walk_disconnect_mut<V: VisitMut + ?Sized>(v: &mut V, stmt: &mut DisconnectStmt) {
+    match &mut stmt.target {
+        ConnectionRef::Named(name) => v.visit_ident_mut(name),
+        ConnectionRef::Layers { src, dst } => {
+            v.visit_ident_mut(src);
+            v.visit_ident_mut(dst);
+        }
+    }
+}
+
+pub fn walk_metric_mut<V: VisitMut + ?Sized>(v: &mut V, metric: &mut Metric) {
+    match metric {
+        Metric::Spikes { layer } | Metric::Rate { layer } => v.visit_ident_mut(layer),
+    }
+}
+
+pub fn walk_assert_cond_mut<V: VisitMut + ?Sized>(v: &mut V, cond: &mut AssertCond) {
+    match cond {
+        AssertCond::Compare { value, .. } => v.visit_quantity_mut(value),
+        AssertCond::Between { low, high } => {
+            v.visit_quantity_mut(low);
+            v.visit_quantity_mut(high);
+        }
+    }
+}
+
+pub fn walk_assign_mut<V: VisitMut + ?Sized>(v: &mut V, assign: &mut Assign) {
+    v.visit_ident_mut(&mut assign.key);
+    v.visit_expr_mut(&mut assign.value);
+}
+
+pub fn walk_expr_mut<V: VisitMut + ?Sized>(v: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Number(q) => v.visit_quantity_mut(q),
+        Expr::String(_) => {}
+        Expr::Ident(id) => v.visit_ident_mut(id),
+        Expr::Call(call) => v.visit_call_mut(call),
+    }
+}
+
+pub fn walk_call_mut<V: VisitMut + ?Sized>(v: &mut V, call: &mut Call) {
+    v.visit_ident_mut(&mut call.name);
+    for arg in &mut call.args {
+        v.visit_call_arg_mut(arg);
+    }
+}
+
+pub fn walk_call_arg_mut<V: VisitMut + ?Sized>(v: &mut V, arg: &mut CallArg) {
+    match arg {
+        CallArg::Positional(expr) => v.visit_expr_mut(expr),
+        CallArg::Named { name, value } => {
+            v.visit_ident_mut(name);
+            v.visit_expr_mut(value);
+        }
+    }
+}
+
+pub fn walk_quantity_mut<V: VisitMut + ?Sized>(v: &mut V, q: &mut Quantity) {
+    if let Some(unit) = &mut q.unit {
+        v.visit_ident_mut(unit);
+    }
+}
+
+/// Sets every span in `program` to the empty span at offset 0, as
+/// [`crate::builder`] makes them, so programs compare by what they say
+/// rather than where it was written.
+pub fn clear_spans(program: &mut Program) {
+    ClearSpans.visit_program_mut(program);
+    if let Some(pragma) = &mut program.version {
+        pragma.span = Span::new(0, 0);
+    }
+    for assign in &mut program.params {
+        ClearSpans.visit_assign_mut(assign);
+    }
+    for deprecated in &mut program.deprecated {
+        deprecated.span = Span::new(0, 0);
+    }
+}
+
+struct ClearSpans;
+
+impl ClearSpans {
+    fn clear_attributes(&mut self, attributes: &mut [Call]) {
+        for call in attributes {
+            self.visit_call_mut(call);
+        }
+    }
+
+    fn clear_filter(filter: &mut SynapseFilter) {
+        match filter {
+            SynapseFilter::Compare { span, .. } => *span = Span::new(0, 0),
+            SynapseFilter::And(a, b) | SynapseFilter::Or(a, b) => {
+                Self::clear_filter(a);
+                Self::clear_filter(b);
+            }
+        }
+    }
+}
+
+impl VisitMut for ClearSpans {
+    fn visit_neuron_mut(&mut self, def: &mut NeuronDef) {
+        def.span = Span::new(0, 0);
+        self.clear_attributes(&mut def.attributes);
+        walk_neuron_mut(self, def);
+    }
+
+    fn visit_layer_mut(&mut self, def: &mut LayerDef) {
+        def.span = Span::new(0, 0);
+        def.size_span = Span::new(0, 0);
+        self.clear_attributes(&mut def.attributes);
+        walk_layer_mut(self, def);
+    }
+
+    fn visit_connect_mut(&mut self, def: &mut ConnectDef) {
+        def.span = Span::new(0, 0);
+        self.clear_attributes(&mut def.attributes);
+        walk_connect_mut(self, def);
+    }
+
+    fn visit_stimulus_mut(&mut self, def: &mut StimulusDef) {
+        def.span = Span::new(0, 0);
+        walk_stimulus_mut(self, def);
+    }
+
+    fn visit_run_mut(&mut self, stmt: &mut RunStmt) {
+        stmt.span = Span::new(0, 0);
+        walk_run_mut(self, stmt);
+    }
+
+    fn visit_seed_mut(&mut self, stmt: &mut SeedStmt) {
+        stmt.span = Span::new(0, 0);
+    }
+
+    fn visit_assert_mut(&mut self, stmt: &mut AssertStmt) {
+        stmt.span = Span::new(0, 0);
+        walk_assert_mut(self, stmt);
+    }
+
+    fn visit_disconnect_mut(&mut self, stmt: &mut DisconnectStmt) {
+        stmt.span = Span::new(0, 0);
+        if let Some(filter) = &mut stmt.filter {
+            Self::clear_filter(filter);
+        }
+        walk_disconnect_mut(self, stmt);
+    }
+
+    fn visit_reward_mut(&mut self, stmt: &mut RewardStmt) {
+        stmt.span = Span::new(0, 0);
+        walk_reward_mut(self, stmt);
+    }
+
+    fn visit_record_mut(&mut self, stmt: &mut RecordStmt) {
+        stmt.span = Span::new(0, 0);
+        walk_record_mut(self, stmt);
+    }
+
+    fn visit_probe_mut(&mut self, def: &mut ProbeDef) {
+        def.span = Span::new(0, 0);
+        walk_probe_mut(self, def);
+    }
+
+    fn visit_modulator_mut(&mut self, def: &mut ModulatorDef) {
+        def.span = Span::new(0, 0);
+        walk_modulator_mut(self, def);
+    }
+
+    fn visit_experiment_mut(&mut self, def: &mut ExperimentDef) {
+        def.span = Span::new(0, 0);
+        walk_experiment_mut(self, def);
+    }
+
+    fn visit_readout_mut(&mut self, def: &mut ReadoutDef) {
+        def.span = Span::new(0, 0);
+        walk_readout_mut(self, def);
+    }
+
+    fn visit_network_mut(&mut self, def: &mut NetworkDef) {
+        def.span = Span::new(0, 0);
+        walk_network_mut(self, def);
+    }
+
+    fn visit_sweep_mut(&mut self, def: &mut SweepDef) {
+        def.span = Span::new(0, 0);
+        for run in &mut def.runs {
+            run.span = Span::new(0, 0);
+        }
+        walk_sweep_mut(self, def);
+    }
+
+    fn visit_stimulus_model_mut(&mut self, model: &mut StimulusModel) {
+        match model {
+            StimulusModel::Poisson { .. } => {}
+            StimulusModel::Encode(enc) => enc.span = Span::new(0, 0),
+            StimulusModel::External(ext) => ext.span = Span::new(0, 0),
+        }
+        walk_stimulus_model_mut(self, model);
+    }
+
+    fn visit_assign_mut(&mut self, assign: &mut Assign) {
+        assign.span = Span::new(0, 0);
+        walk_assign_mut(self, assign);
+    }
+
+    fn visit_call_mut(&mut self, call: &mut Call) {
+        call.span = Span::new(0, 0);
+        walk_call_mut(self, call);
+    }
+
+    fn visit_quantity_mut(&mut self, q: &mut Quantity) {
+        q.span = Span::new(0, 0);
+        walk_quantity_mut(self, q);
+    }
+
+    fn visit_ident_mut(&mut self, ident: &mut Ident) {
+        ident.span = Span::new(0, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    const SRC: &str = "neuron LIF { tau_m = 20 ms }\nlayer A[3] : LIF\nconnect A -> A { w = Normal(0.5, sd=0.1) }\nstimulus A = Poisson(rate=5 Hz)\nrun for 5 ms\nassert rate(A) between 1 Hz and 9 Hz\n";
+
+    #[test]
+    fn visits_every_ident_in_source_order() {
+        struct Idents(Vec<String>);
+        impl Visit for Idents {
+            fn visit_ident(&mut self, ident: &Ident) {
+                self.0.push(ident.name.clone());
+            }
+        }
+        let program = parse_program(SRC).expect("parse");
+        let mut idents = Idents(Vec::new());
+        idents.visit_program(&program);
+        assert_eq!(
+            idents.0,
+            [
+                "LIF", "tau_m", "ms", "A", "LIF", "A", "A", "w", "Normal", "sd", "A", "Hz", "ms",
+                "A", "Hz", "Hz"
+            ]
+        );
+    }
+
+    #[test]
+    fn rewrites_layer_references() {
+        struct Rename;
+        impl VisitMut for Rename {
+            fn visit_layer_mut(&mut self, def: &mut LayerDef) {
+                def.name.name = "B".to_string();
+            }
+            fn visit_connect_mut(&mut self, def: &mut ConnectDef) {
+                def.src.name = "B".to_string();
+                def.dst.name = "B".to_string();
+            }
+            fn visit_stimulus_mut(&mut self, def: &mut StimulusDef) {
+                def.layer.name = "B".to_string();
+            }
+            fn visit_metric_mut(&mut self, metric: &mut Metric) {
+                match metric {
+                    Metric::Spikes { layer } | Metric::Rate { layer } => {
+                        layer.name = "B".to_string()
+                    }
+                }
+            }
+        }
+        let mut program = parse_program(SRC).expect("parse");
+        Rename.visit_program_mut(&mut program);
+        crate::validate::validate(&program).expect("still valid");
+        let renamed = parse_program(&SRC.replace('A', "B")).expect("parse");
+        assert_eq!(
+            crate::emit::cvir_json(&program),
+            crate::emit::cvir_json(&renamed)
+        );
+    }
+
+    #[test]
+    fn clears_spans_so_formatting_does_not_matter() {
+        let mut program = parse_program(SRC).expect("parse");
+        let mut spaced = parse_program(&SRC.replace(' ', "  ")).expect("parse");
+        assert_ne!(program, spaced);
+        clear_spans(&mut program);
+        clear_spans(&mut spaced);
+        assert_eq!(program, spaced);
+    }
+}
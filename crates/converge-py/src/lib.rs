@@ -0,0 +1,246 @@
+//! Python bindings: `import converge`.
+//!
+//! `parse`, `cvir` and `simulate` return plain dicts (the same JSON the CLI
+//! prints, loaded with `json.loads`), and errors raise `converge.ConvergeError`
+//! with the formatted diagnostics. Spikes come back as `array.array` columns,
+//! which support the buffer protocol, so `numpy.asarray` wraps them without
+//! a numpy build dependency.
+
+use converge_lang::ast::Program;
+use converge_lang::emit::{ast_json, cvir_json};
+use converge_lang::parser::{format_diagnostic, parse_program};
+use converge_sim::{SimConfig, Spike, check_asserts, simulate_with, source_hash, summary_json};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyIndexError, PyKeyError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+create_exception!(converge, ConvergeError, PyException);
+
+#[pymodule]
+fn converge(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ConvergeError", m.py().get_type::<ConvergeError>())?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(validate, m)?)?;
+    m.add_function(wrap_pyfunction!(cvir, m)?)?;
+    m.add_function(wrap_pyfunction!(simulate, m)?)?;
+    m.add_class::<Simulator>()?;
+    Ok(())
+}
+
+/// Parses `src` into the parse-tree dict printed by `converge ast`.
+#[pyfunction]
+fn parse<'py>(py: Python<'py>, src: &str) -> PyResult<Bound<'py, PyAny>> {
+    let program =
+        parse_program(src).map_err(|diag| ConvergeError::new_err(format_diagnostic(src, &diag)))?;
+    json_loads(py, &ast_json(&program))
+}
+
+/// Parses and validates `src`, raising `ConvergeError` on any diagnostic.
+#[pyfunction]
+fn validate(src: &str) -> PyResult<()> {
+    checked(src).map(|_| ())
+}
+
+/// Parses and validates `src` into its CVIR dict.
+#[pyfunction]
+fn cvir<'py>(py: Python<'py>, src: &str) -> PyResult<Bound<'py, PyAny>> {
+    let program = checked(src)?;
+    json_loads(py, &cvir_json(&program))
+}
+
+/// Runs `src` to completion and returns the summary dict. With
+/// `record_spikes`, the dict gains a `spikes` entry of `step`, `layer` and
+/// `neuron` arrays. Failed `assert`s raise unless `check_asserts` is false.
+#[pyfunction]
+#[pyo3(signature = (src, *, record_spikes = false, kahan = false, check_asserts = true))]
+fn simulate<'py>(
+    py: Python<'py>,
+    src: &str,
+    record_spikes: bool,
+    kahan: bool,
+    check_asserts: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    let program = checked(src)?;
+    let config = SimConfig {
+        record_spikes,
+        compensated_summation: kahan,
+        ..SimConfig::default()
+    };
+    let mut summary = py
+        .detach(|| simulate_with(&program, &config))
+        .map_err(|err| ConvergeError::new_err(err.to_string()))?;
+    summary.input_hash = Some(source_hash(src));
+    if check_asserts {
+        assert_ok(src, &program, &summary)?;
+    }
+
+    let dict = json_loads(py, &summary_json(&summary))?;
+    if let Some(raster) = &summary.raster {
+        dict.set_item("spikes", spike_columns(py, raster)?)?;
+    }
+    Ok(dict)
+}
+
+/// A network that advances on demand, for driving a run from Python.
+#[pyclass(unsendable, module = "converge")]
+struct Simulator {
+    src: String,
+    program: Program,
+    inner: converge_sim::Simulator,
+}
+
+#[pymethods]
+impl Simulator {
+    #[new]
+    #[pyo3(signature = (src, *, record_spikes = false, kahan = false))]
+    fn new(src: &str, record_spikes: bool, kahan: bool) -> PyResult<Self> {
+        let program = checked(src)?;
+        let config = SimConfig {
+            record_spikes,
+            compensated_summation: kahan,
+            ..SimConfig::default()
+        };
+        let inner = converge_sim::Simulator::new(&program, &config)
+            .map_err(|err| ConvergeError::new_err(err.to_string()))?;
+        Ok(Self {
+            src: src.to_string(),
+            program,
+            inner,
+        })
+    }
+
+    /// Advances `steps` steps, or to the end of the run when omitted.
+    #[pyo3(signature = (steps = None))]
+    fn step(&mut self, steps: Option<u64>) -> PyResult<()> {
+        let steps = steps.unwrap_or(u64::MAX);
+        for _ in 0..steps {
+            if self.inner.is_finished() {
+                break;
+            }
+            self.inner
+                .step()
+                .map_err(|err| ConvergeError::new_err(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    #[getter]
+    fn finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+
+    #[getter]
+    fn current_step(&self) -> u64 {
+        self.inner.current_step()
+    }
+
+    #[getter]
+    fn total_steps(&self) -> u64 {
+        self.inner.total_steps()
+    }
+
+    #[getter]
+    fn step_ns(&self) -> i64 {
+        self.inner.step_ns()
+    }
+
+    #[getter]
+    fn total_spikes(&self) -> u64 {
+        self.inner.total_spikes()
+    }
+
+    /// Layer names in declaration order.
+    #[getter]
+    fn layers(&self) -> Vec<String> {
+        (0..self.inner.layer_count())
+            .map(|idx| self.inner.layer(idx).name.to_string())
+            .collect()
+    }
+
+    /// A layer's name, spike count, threshold and `membrane` potentials (a
+    /// float64 array) at the current step.
+    fn layer<'py>(
+        &self,
+        py: Python<'py>,
+        layer: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let idx = match layer.extract::<usize>() {
+            Ok(idx) if idx < self.inner.layer_count() => idx,
+            Ok(idx) => return Err(PyIndexError::new_err(format!("no layer {idx}"))),
+            Err(_) => {
+                let name: String = layer.extract()?;
+                (0..self.inner.layer_count())
+                    .find(|&i| self.inner.layer(i).name == name)
+                    .ok_or_else(|| PyKeyError::new_err(name))?
+            }
+        };
+        let view = self.inner.layer(idx);
+        let membrane: Vec<u8> = view.membrane.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let dict = PyDict::new(py);
+        dict.set_item("name", view.name)?;
+        dict.set_item("spikes", view.spikes)?;
+        dict.set_item("v_th", view.v_th)?;
+        dict.set_item("membrane", typed_array(py, "d", &membrane)?)?;
+        Ok(dict)
+    }
+
+    /// Spikes recorded so far as `step`, `layer` and `neuron` arrays; empty
+    /// unless the simulator was built with `record_spikes=True`.
+    fn spikes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        spike_columns(py, self.inner.recorded_spikes())
+    }
+
+    /// The summary dict for the steps run so far. Asserts are checked once
+    /// the run has finished, unless `check_asserts` is false.
+    #[pyo3(signature = (*, check_asserts = true))]
+    fn summary<'py>(&self, py: Python<'py>, check_asserts: bool) -> PyResult<Bound<'py, PyAny>> {
+        let summary = self.inner.summary();
+        if check_asserts && self.inner.is_finished() {
+            assert_ok(&self.src, &self.program, &summary)?;
+        }
+        json_loads(py, &summary_json(&summary))
+    }
+}
+
+fn checked(src: &str) -> PyResult<Program> {
+    let program =
+        parse_program(src).map_err(|diag| ConvergeError::new_err(format_diagnostic(src, &diag)))?;
+    converge_lang::validate::validate(&program).map_err(|diags| {
+        let messages: Vec<String> = diags.iter().map(|d| format_diagnostic(src, d)).collect();
+        ConvergeError::new_err(messages.join("\n"))
+    })?;
+    Ok(program)
+}
+
+fn assert_ok(src: &str, program: &Program, summary: &converge_sim::SimSummary) -> PyResult<()> {
+    let failures = check_asserts(program, summary);
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let messages: Vec<String> = failures.iter().map(|d| format_diagnostic(src, d)).collect();
+    Err(ConvergeError::new_err(messages.join("\n")))
+}
+
+fn json_loads<'py>(py: Python<'py>, json: &str) -> PyResult<Bound<'py, PyAny>> {
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+fn spike_columns<'py>(py: Python<'py>, spikes: &[Spike]) -> PyResult<Bound<'py, PyDict>> {
+    let column = |f: fn(&Spike) -> u64| -> Vec<u8> {
+        spikes.iter().flat_map(|s| f(s).to_ne_bytes()).collect()
+    };
+    let layers: Vec<u8> = spikes.iter().flat_map(|s| s.layer.to_ne_bytes()).collect();
+    let dict = PyDict::new(py);
+    dict.set_item("step", typed_array(py, "Q", &column(|s| s.step))?)?;
+    dict.set_item("layer", typed_array(py, "I", &layers)?)?;
+    dict.set_item("neuron", typed_array(py, "Q", &column(|s| s.neuron))?)?;
+    Ok(dict)
+}
+
+/// `array.array(typecode, bytes)`; `bytes` must hold native-endian items.
+fn typed_array<'py>(py: Python<'py>, typecode: &str, bytes: &[u8]) -> PyResult<Bound<'py, PyAny>> {
+    py.import("array")?
+        .getattr("array")?
+        .call1((typecode, PyBytes::new(py, bytes)))
+}
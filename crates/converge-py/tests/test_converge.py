@@ -0,0 +1,76 @@
+"""Smoke tests for the Python bindings.
+
+Run after `maturin develop -m crates/converge-py/Cargo.toml`:
+
+    python -m unittest discover crates/converge-py/tests
+"""
+
+import array
+import pathlib
+import unittest
+
+import converge
+
+EXAMPLES = pathlib.Path(__file__).resolve().parents[3] / "examples"
+
+SRC = """
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer Input[8] : LIF
+layer Output[2] : LIF
+connect Input -> Output { w = 0.6, d = 1 ms }
+stimulus Input = Poisson(rate=200 Hz)
+seed 5
+run for 40 ms
+"""
+
+
+class BindingsTest(unittest.TestCase):
+    def test_parse_and_cvir_return_dicts(self):
+        ast = converge.parse(SRC)
+        self.assertEqual(ast["items"][1]["kind"], "layer")
+        self.assertEqual(converge.cvir(SRC)["cvir_version"], "0.2")
+
+    def test_errors_raise_converge_error(self):
+        with self.assertRaises(converge.ConvergeError):
+            converge.validate("layer A[1] : Missing\nrun for 1 ms\n")
+        with self.assertRaises(converge.ConvergeError):
+            converge.parse("layer A[")
+
+    def test_simulate_returns_spike_arrays(self):
+        summary = converge.simulate(SRC, record_spikes=True)
+        spikes = summary["spikes"]
+        self.assertIsInstance(spikes["step"], array.array)
+        self.assertEqual(len(spikes["step"]), summary["total_spikes"])
+        self.assertEqual(memoryview(spikes["neuron"]).format, "Q")
+
+    def test_incremental_simulator_matches_batch_run(self):
+        sim = converge.Simulator(SRC, record_spikes=True)
+        self.assertEqual(sim.layers, ["Input", "Output"])
+        sim.step(10)
+        self.assertEqual(sim.current_step, 10)
+        self.assertEqual(len(sim.layer("Output")["membrane"]), 2)
+        sim.step()
+        self.assertTrue(sim.finished)
+        self.assertEqual(
+            sim.summary()["spike_hash"], converge.simulate(SRC)["spike_hash"]
+        )
+        self.assertEqual(len(sim.spikes()["layer"]), sim.total_spikes)
+
+    def test_failed_asserts_raise(self):
+        src = (EXAMPLES / "assert.cv").read_text() + "assert spikes(Input) < 0\n"
+        with self.assertRaises(converge.ConvergeError):
+            converge.simulate(src)
+        converge.simulate(src, check_asserts=False)
+
+    def test_numpy_wraps_spike_buffers(self):
+        try:
+            import numpy
+        except ImportError:
+            self.skipTest("numpy not installed")
+        spikes = converge.simulate(SRC, record_spikes=True)["spikes"]
+        steps = numpy.asarray(spikes["step"])
+        self.assertEqual(steps.dtype, numpy.uint64)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,985 @@
+//! Code generation for fixed networks. The network is built exactly as
+//! `converge sim` builds it, with every weight and delay sampled, lowered
+//! once, and then hard-coded by a backend (Rust or C99) into a standalone
+//! program that repeats the interpreter's arithmetic in the same order, so
+//! its summary matches `converge sim`.
+//!
+//! Generated programs cover point LIF neurons, chemical connections and
+//! Poisson stimuli; anything else is rejected.
+
+use std::fmt::Write;
+
+use converge_lang::ast::{ConnectKind, Item, Program, StimulusModel};
+
+use crate::{
+    AppliedDefault, Arithmetic, SimConfig, SimError, SimErrorKind, Simulator, Stream,
+    defaults_json, streams_json, to_err,
+};
+
+/// One file of generated output, at a path relative to the output directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedFile {
+    pub path: String,
+    pub contents: String,
+}
+
+/// A built network reduced to what generated code needs.
+struct Lowered {
+    duration_ns: i64,
+    step_ns: i64,
+    seed: u64,
+    steps: usize,
+    queue_len: usize,
+    layers: Vec<LoweredLayer>,
+    connections: Vec<LoweredConnection>,
+    defaults: Vec<AppliedDefault>,
+}
+
+struct LoweredLayer {
+    name: String,
+    size: usize,
+    /// `step / tau_m`, as the interpreter computes it.
+    decay: f64,
+    v_th: f64,
+    /// Per-step spike probability of a Poisson stimulus.
+    poisson: Option<f64>,
+}
+
+struct LoweredConnection {
+    label: String,
+    name: Option<String>,
+    src: usize,
+    dst: usize,
+    synapses: Synapses,
+}
+
+enum Synapses {
+    /// No synapses at all; only the summary entry is generated.
+    Empty,
+    /// Every source neuron reaches every target, all with one weight and
+    /// delay.
+    Dense { weight: f64, delay: usize },
+    /// Per source neuron, `rows[i]..rows[i + 1]` indexes the other arrays.
+    Table {
+        rows: Vec<usize>,
+        dst: Vec<usize>,
+        weight: Vec<f64>,
+        delay: Vec<usize>,
+    },
+}
+
+fn lower(program: &Program, config: &SimConfig) -> Result<Lowered, SimError> {
+    let unsupported = |what: &str| {
+        SimError::new(SimErrorKind::Unsupported(format!(
+            "generated code does not support {what}"
+        )))
+    };
+    if config.compensated_summation {
+        return Err(unsupported("compensated summation"));
+    }
+    if config.quantize.is_some() {
+        return Err(unsupported("quantized weights"));
+    }
+    if config.arithmetic != Arithmetic::Float {
+        return Err(unsupported("fixed-point arithmetic"));
+    }
+    let program = &*program.select_network(None).map_err(to_err)?;
+    // Checked before building, which would start listening for a controller.
+    let external = program.items.iter().any(
+        |item| matches!(item, Item::Stimulus(s) if matches!(s.model, StimulusModel::External(_))),
+    );
+    if external {
+        return Err(unsupported("`External` stimuli"));
+    }
+    let sim = Simulator::new(
+        program,
+        &SimConfig {
+            data_dir: config.data_dir.clone(),
+            confine_data: config.confine_data,
+            ..SimConfig::default()
+        },
+    )?;
+    if sim.layers.iter().any(|l| l.compartments > 0) {
+        return Err(unsupported("dendritic compartments"));
+    }
+    if !sim.modulators.is_empty() {
+        return Err(unsupported("modulators"));
+    }
+    if sim.readout.is_some() || sim.classifier.is_some() {
+        return Err(unsupported("experiments and readouts"));
+    }
+    if sim.stimuli.iter().flatten().any(|s| !s.encoded.is_empty()) {
+        return Err(unsupported("`Encode` stimuli"));
+    }
+    if sim
+        .stimuli
+        .iter()
+        .flatten()
+        .any(|s| !s.correlated.is_empty())
+    {
+        return Err(unsupported("correlated `Poisson` stimuli"));
+    }
+
+    let step_s = sim.step_ns as f64 / 1_000_000_000.0;
+    let mut layers = Vec::new();
+    for (layer, stimulus) in sim.layers.iter().zip(&sim.stimuli) {
+        let poisson = stimulus
+            .as_ref()
+            .filter(|s| s.poisson)
+            .map(|s| s.rate_hz(0, 0) * step_s);
+        if poisson.is_some_and(|p| p > 1.0) {
+            return Err(SimError::new(SimErrorKind::RateTooHigh {
+                layer: layer.name.clone(),
+            }));
+        }
+        layers.push(LoweredLayer {
+            name: layer.name.clone(),
+            size: layer.size,
+            decay: sim.step_ns as f64 / layer.tau_m_ns as f64,
+            v_th: layer.v_th,
+            poisson,
+        });
+    }
+
+    let mut connections = Vec::new();
+    for conn in &sim.connections {
+        if conn.kind == ConnectKind::Electrical {
+            return Err(unsupported("gap junctions"));
+        }
+        if conn.plasticity.is_some() || conn.delay_plasticity.is_some() {
+            return Err(unsupported("plasticity"));
+        }
+        let (src, dst) = (conn.src_layer, conn.dst_layer);
+        let arrow = format!("{} -> {}", layers[src].name, layers[dst].name);
+        let label = match &conn.name {
+            Some(name) => format!("{name}: {arrow}"),
+            None => arrow,
+        };
+        let dst_size = layers[dst].size;
+        let first = conn.synapses.iter().flatten().next();
+        let dense = first.is_some_and(|first| {
+            conn.synapses.iter().all(|row| {
+                row.len() == dst_size
+                    && row.iter().enumerate().all(|(j, s)| {
+                        s.dst == j
+                            && s.weight.to_bits() == first.weight.to_bits()
+                            && s.delay_steps == first.delay_steps
+                    })
+            })
+        });
+        let synapses = match first {
+            None => Synapses::Empty,
+            Some(first) if dense => Synapses::Dense {
+                weight: first.weight,
+                delay: first.delay_steps,
+            },
+            Some(_) => {
+                let mut rows = vec![0];
+                let (mut dst, mut weight, mut delay) = (Vec::new(), Vec::new(), Vec::new());
+                for row in &conn.synapses {
+                    for s in row {
+                        dst.push(s.dst);
+                        weight.push(s.weight);
+                        delay.push(s.delay_steps);
+                    }
+                    rows.push(dst.len());
+                }
+                Synapses::Table {
+                    rows,
+                    dst,
+                    weight,
+                    delay,
+                }
+            }
+        };
+        connections.push(LoweredConnection {
+            label,
+            name: conn.name.clone(),
+            src,
+            dst,
+            synapses,
+        });
+    }
+
+    Ok(Lowered {
+        duration_ns: sim.duration_ns,
+        step_ns: sim.step_ns,
+        seed: sim.seed,
+        steps: sim.steps,
+        queue_len: sim.queue_len,
+        layers,
+        connections,
+        defaults: sim.defaults.clone(),
+    })
+}
+
+/// Generates a dependency-free Cargo crate whose binary runs the network
+/// and prints the summary JSON of `converge sim`. `--runs <n>` repeats the
+/// run and reports the mean time per run on stderr. `name` becomes the
+/// crate name.
+pub fn rust_crate(
+    program: &Program,
+    config: &SimConfig,
+    name: &str,
+) -> Result<Vec<GeneratedFile>, SimError> {
+    let net = lower(program, config)?;
+    let name = identifier(name);
+    let manifest = format!(
+        "# Generated by `converge compile --target rust`; do not edit.\n\
+         [package]\n\
+         name = \"{name}\"\n\
+         version = \"0.1.0\"\n\
+         edition = \"2021\"\n\
+         \n\
+         [dependencies]\n\
+         \n\
+         [profile.release]\n\
+         codegen-units = 1\n\
+         \n\
+         # A standalone crate, even when generated inside another workspace.\n\
+         [workspace]\n"
+    );
+    Ok(vec![
+        GeneratedFile {
+            path: "Cargo.toml".to_string(),
+            contents: manifest,
+        },
+        GeneratedFile {
+            path: "src/main.rs".to_string(),
+            contents: rust_main(&net),
+        },
+    ])
+}
+
+/// A valid crate name and C identifier: lowercase alphanumerics and
+/// underscores, starting with a letter.
+fn identifier(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out.insert_str(0, "net_");
+    }
+    out
+}
+
+fn rust_main(net: &Lowered) -> String {
+    let mut s = String::new();
+    let layer_count = net.layers.len();
+    let poisson = net.layers.iter().any(|l| l.poisson.is_some());
+    let sends = |idx: usize| net.connections.iter().any(|c| c.src == idx);
+
+    s.push_str(
+        "//! Generated by `converge compile --target rust`; do not edit.\n\
+         //! Runs the network and prints the summary JSON `converge sim` prints.\n\
+         //! `--runs <n>` repeats the run and reports the mean time per run.\n\n",
+    );
+    let _ = writeln!(s, "const STEPS: usize = {};", net.steps);
+    let _ = writeln!(s, "const QUEUE_LEN: usize = {};\n", net.queue_len);
+    if poisson {
+        s.push_str(
+            "struct Rng(u64);\n\n\
+             impl Rng {\n    \
+                 fn next_f64(&mut self) -> f64 {\n        \
+                     self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);\n        \
+                     (self.0 >> 11) as f64 / (1u64 << 53) as f64\n    \
+                 }\n\
+             }\n\n",
+        );
+    }
+    s.push_str(
+        "/// FNV-1a over each spike's `(step, layer, neuron)`.\n\
+         struct SpikeHash(u64);\n\n\
+         impl SpikeHash {\n    \
+             fn record(&mut self, step: u64, layer: u32, neuron: u64) {\n        \
+                 let bytes = step.to_le_bytes().into_iter();\n        \
+                 let bytes = bytes.chain(layer.to_le_bytes()).chain(neuron.to_le_bytes());\n        \
+                 for b in bytes {\n            \
+                     self.0 ^= b as u64;\n            \
+                     self.0 = self.0.wrapping_mul(0x100000001b3);\n        \
+                 }\n    \
+             }\n\
+         }\n\n",
+    );
+
+    for (idx, conn) in net.connections.iter().enumerate() {
+        if let Synapses::Table {
+            rows,
+            dst,
+            weight,
+            delay,
+        } = &conn.synapses
+        {
+            let _ = writeln!(s, "// `{}`: synapses of each source neuron.", conn.label);
+            rust_array(&mut s, &format!("C{idx}_ROWS"), "u32", rows, |x| {
+                x.to_string()
+            });
+            rust_array(&mut s, &format!("C{idx}_DST"), "u32", dst, |x| {
+                x.to_string()
+            });
+            rust_array(&mut s, &format!("C{idx}_W"), "f64", weight, |x| {
+                format!("{x:?}")
+            });
+            rust_array(&mut s, &format!("C{idx}_DELAY"), "u32", delay, |x| {
+                x.to_string()
+            });
+            s.push('\n');
+        }
+    }
+
+    let conn_count = net.connections.len();
+    let _ = writeln!(
+        s,
+        "/// Spike counts per layer, the spike hash, and deliveries and summed\n\
+         /// weight per connection.\n\
+         fn run() -> ([u64; {layer_count}], u64, [u64; {conn_count}], [f64; {conn_count}]) {{"
+    );
+    if poisson {
+        let _ = writeln!(
+            s,
+            "    let mut rng = Rng({});",
+            Stream::Stimulus.seed(net.seed)
+        );
+    }
+    s.push_str("    let mut hash = SpikeHash(0xcbf29ce484222325);\n");
+    let _ = writeln!(s, "    let mut spikes = [0u64; {layer_count}];");
+    let delivers = net
+        .connections
+        .iter()
+        .any(|c| !matches!(c.synapses, Synapses::Empty));
+    let mutable = if delivers { "mut " } else { "" };
+    let _ = writeln!(s, "    let {mutable}deliveries = [0u64; {conn_count}];");
+    let _ = writeln!(s, "    let {mutable}weight_sum = [0.0f64; {conn_count}];");
+    for (idx, layer) in net.layers.iter().enumerate() {
+        let n = layer.size;
+        let _ = writeln!(s, "    // `{}`", layer.name);
+        let _ = writeln!(s, "    let mut v{idx} = vec![0.0f64; {n}];");
+        let _ = writeln!(s, "    let mut q{idx} = vec![0.0f64; {n} * QUEUE_LEN];");
+        if sends(idx) {
+            let _ = writeln!(
+                s,
+                "    let mut s{idx}: Vec<usize> = Vec::with_capacity({n});"
+            );
+        }
+    }
+    s.push_str("    for step in 0..STEPS {\n");
+    s.push_str("        let bucket = step % QUEUE_LEN;\n");
+    for (idx, layer) in net.layers.iter().enumerate() {
+        let n = layer.size;
+        let _ = writeln!(
+            s,
+            "        for (v, q) in v{idx}.iter_mut().zip(&mut q{idx}[bucket * {n}..(bucket + 1) * {n}]) {{\n            \
+                 *v += *q;\n            \
+                 *q = 0.0;\n        \
+             }}"
+        );
+        if let Some(p) = layer.poisson {
+            let _ = writeln!(
+                s,
+                "        for v in v{idx}.iter_mut() {{\n            \
+                     if rng.next_f64() < {p:?} {{\n                \
+                         *v += 1.0;\n            \
+                     }}\n        \
+                 }}"
+            );
+        }
+    }
+    for (idx, layer) in net.layers.iter().enumerate() {
+        let push = if sends(idx) {
+            format!("\n                s{idx}.push(i);")
+        } else {
+            String::new()
+        };
+        if sends(idx) {
+            let _ = writeln!(s, "        s{idx}.clear();");
+        }
+        let _ = writeln!(
+            s,
+            "        for (i, v) in v{idx}.iter_mut().enumerate() {{\n            \
+                 *v += (-*v) * {decay:?};\n            \
+                 if *v >= {v_th:?} {{\n                \
+                     *v = 0.0;\n                \
+                     spikes[{idx}] += 1;\n                \
+                     hash.record(step as u64, {idx}, i as u64);{push}\n            \
+                 }}\n        \
+             }}",
+            decay = layer.decay,
+            v_th = layer.v_th,
+        );
+    }
+    for (idx, conn) in net.connections.iter().enumerate() {
+        let (src, dst) = (conn.src, conn.dst);
+        let n = net.layers[dst].size;
+        match &conn.synapses {
+            Synapses::Empty => {}
+            Synapses::Dense { weight, delay } => {
+                let _ = writeln!(
+                    s,
+                    "        // `{}`\n        \
+                     let slot = (bucket + {delay}) % QUEUE_LEN;\n        \
+                     for _ in &s{src} {{\n            \
+                         for q in &mut q{dst}[slot * {n}..(slot + 1) * {n}] {{\n                \
+                             *q += {weight:?};\n                \
+                             weight_sum[{idx}] += {weight:?};\n            \
+                         }}\n            \
+                         deliveries[{idx}] += {n};\n        \
+                     }}",
+                    conn.label
+                );
+            }
+            Synapses::Table { .. } => {
+                let _ = writeln!(
+                    s,
+                    "        // `{}`\n        \
+                     for &i in &s{src} {{\n            \
+                         for k in C{idx}_ROWS[i] as usize..C{idx}_ROWS[i + 1] as usize {{\n                \
+                             let slot = (bucket + C{idx}_DELAY[k] as usize) % QUEUE_LEN;\n                \
+                             q{dst}[slot * {n} + C{idx}_DST[k] as usize] += C{idx}_W[k];\n                \
+                             deliveries[{idx}] += 1;\n                \
+                             weight_sum[{idx}] += C{idx}_W[k];\n            \
+                         }}\n        \
+                     }}",
+                    conn.label
+                );
+            }
+        }
+    }
+    s.push_str("    }\n    (spikes, hash.0, deliveries, weight_sum)\n}\n\n");
+
+    s.push_str(
+        "fn main() {\n    \
+             let mut args = std::env::args().skip(1);\n    \
+             let runs = match (args.next().as_deref(), args.next()) {\n        \
+                 (None, _) => 1,\n        \
+                 (Some(\"--runs\"), Some(n)) if n.parse::<u32>().is_ok_and(|n| n > 0) => n.parse().unwrap(),\n        \
+                 _ => {\n            \
+                     eprintln!(\"usage: {} [--runs <n>]\", env!(\"CARGO_PKG_NAME\"));\n            \
+                     std::process::exit(2);\n        \
+                 }\n    \
+             };\n    \
+             let started = std::time::Instant::now();\n    \
+             let mut result = run();\n    \
+             for _ in 1..runs {\n        \
+                 result = run();\n    \
+             }\n    \
+             if runs > 1 {\n        \
+                 eprintln!(\"{runs} runs, {:?} per run\", started.elapsed() / runs);\n    \
+             }\n",
+    );
+    if net.connections.is_empty() {
+        s.push_str("    let (spikes, hash, _, _) = result;\n");
+    } else {
+        s.push_str("    let (spikes, hash, deliveries, weight_sum) = result;\n");
+    }
+    let _ = writeln!(
+        s,
+        "    let total: u64 = spikes.iter().sum();\n    \
+         println!(\"{{{{\");\n    \
+         println!(\"  \\\"duration_ns\\\": {},\");\n    \
+         println!(\"  \\\"step_ns\\\": {},\");\n    \
+         println!(\"  \\\"seed\\\": {},\");\n    \
+         println!(\"  \\\"streams\\\": {},\");\n    \
+         println!(\"  \\\"total_spikes\\\": {{total}},\");\n    \
+         println!(\"  \\\"spike_hash\\\": \\\"{{hash:016x}}\\\",\");\n    \
+         println!(\"  \\\"layers\\\": [\");",
+        net.duration_ns,
+        net.step_ns,
+        net.seed,
+        streams_json(net.seed)
+            .replace('{', "{{")
+            .replace('}', "}}")
+            .replace('"', "\\\"")
+    );
+    for (idx, layer) in net.layers.iter().enumerate() {
+        let comma = if idx + 1 == layer_count { "" } else { "," };
+        let _ = writeln!(
+            s,
+            "    println!(\"    {{{{\");\n    \
+             println!(\"      \\\"name\\\": \\\"{}\\\",\");\n    \
+             println!(\"      \\\"size\\\": {},\");\n    \
+             println!(\"      \\\"spikes\\\": {{}}\", spikes[{idx}]);\n    \
+             println!(\"    }}}}{comma}\");",
+            layer.name, layer.size
+        );
+    }
+    if net.connections.is_empty() {
+        s.push_str("    println!(\"  ],\");\n    println!(\"  \\\"connections\\\": [],\");\n");
+    } else {
+        s.push_str(
+            "    println!(\"  ],\");\n    \
+             println!(\"  \\\"connections\\\": [\");\n    \
+             let mean = |c: usize| if deliveries[c] == 0 { 0.0 } else { weight_sum[c] / deliveries[c] as f64 };\n",
+        );
+    }
+    for (idx, conn) in net.connections.iter().enumerate() {
+        let comma = if idx + 1 == net.connections.len() {
+            ""
+        } else {
+            ","
+        };
+        let _ = writeln!(
+            s,
+            "    println!(\"    {{{{ {}\\\"deliveries\\\": {{}}, \\\"mean_weight\\\": {{}} }}}}{comma}\", deliveries[{idx}], mean({idx}));",
+            connection_fields(conn, net).replace('"', "\\\"")
+        );
+    }
+    if !net.connections.is_empty() {
+        s.push_str("    println!(\"  ],\");\n");
+    }
+    let _ = writeln!(
+        s,
+        "    println!(\"  \\\"defaults\\\": {}\");",
+        defaults_json(&net.defaults)
+            .replace('{', "{{")
+            .replace('}', "}}")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    );
+    s.push_str("    println!(\"}}\");\n}\n");
+    s
+}
+
+/// `"name": .., "src": .., "dst": .., ` of a connection's summary entry.
+fn connection_fields(conn: &LoweredConnection, net: &Lowered) -> String {
+    let name = conn
+        .name
+        .as_ref()
+        .map_or(String::new(), |name| format!("\"name\": \"{name}\", "));
+    format!(
+        "{name}\"src\": \"{}\", \"dst\": \"{}\", ",
+        net.layers[conn.src].name, net.layers[conn.dst].name
+    )
+}
+
+fn rust_array<T>(s: &mut String, name: &str, ty: &str, items: &[T], fmt: impl Fn(&T) -> String) {
+    let _ = write!(s, "static {name}: [{ty}; {}] = [", items.len());
+    array_items(s, items, fmt);
+    s.push_str("];\n");
+}
+
+fn array_items<T>(s: &mut String, items: &[T], fmt: impl Fn(&T) -> String) {
+    for (idx, item) in items.iter().enumerate() {
+        if idx % 16 == 0 {
+            s.push_str("\n    ");
+        } else {
+            s.push(' ');
+        }
+        s.push_str(&fmt(item));
+        s.push(',');
+    }
+    if !items.is_empty() {
+        s.push('\n');
+    }
+}
+
+/// Generates portable C99: `<name>.h` and `<name>.c` with the network in
+/// static storage and no heap allocation, for embedding, plus a `main.c`
+/// that runs it once and prints the summary JSON of `converge sim`.
+pub fn c_sources(
+    program: &Program,
+    config: &SimConfig,
+    name: &str,
+) -> Result<Vec<GeneratedFile>, SimError> {
+    let net = lower(program, config)?;
+    let name = identifier(name);
+    Ok(vec![
+        GeneratedFile {
+            path: format!("{name}.h"),
+            contents: c_header(&net, &name),
+        },
+        GeneratedFile {
+            path: format!("{name}.c"),
+            contents: c_network(&net, &name),
+        },
+        GeneratedFile {
+            path: "main.c".to_string(),
+            contents: c_main(&net, &name),
+        },
+    ])
+}
+
+fn c_header(net: &Lowered, name: &str) -> String {
+    let guard = name.to_ascii_uppercase();
+    // C has no zero-length arrays.
+    let (conn_count, conn_fields) = if net.connections.is_empty() {
+        (String::new(), String::new())
+    } else {
+        (
+            format!("#define {guard}_CONNECTIONS {}\n", net.connections.len()),
+            format!(
+                "    uint64_t deliveries[{guard}_CONNECTIONS];\n    \
+                 double weight_sum[{guard}_CONNECTIONS];\n"
+            ),
+        )
+    };
+    format!(
+        "/* Generated by `converge compile --target c`; do not edit. */\n\
+         #ifndef {guard}_H\n\
+         #define {guard}_H\n\n\
+         #include <stdint.h>\n\n\
+         #define {guard}_LAYERS {layers}\n\
+         {conn_count}\n\
+         typedef struct {{\n    \
+             uint64_t spikes[{guard}_LAYERS];\n    \
+             uint64_t total_spikes;\n    \
+             uint64_t spike_hash;\n\
+         {conn_fields}\
+         }} {name}_summary;\n\n\
+         /* Runs the network from rest; state lives in static storage, so\n   \
+            calls must not overlap. */\n\
+         void {name}_run({name}_summary *out);\n\n\
+         #endif\n",
+        layers = net.layers.len()
+    )
+}
+
+/// The smallest unsigned type that holds every value up to `max`.
+fn c_uint(max: usize) -> &'static str {
+    if max <= u8::MAX as usize {
+        "uint8_t"
+    } else if max <= u16::MAX as usize {
+        "uint16_t"
+    } else {
+        "uint32_t"
+    }
+}
+
+fn c_array(s: &mut String, name: &str, ty: &str, items: &[usize]) {
+    let _ = write!(s, "static const {ty} {name}[{}] = {{", items.len());
+    array_items(s, items, |x| x.to_string());
+    s.push_str("};\n");
+}
+
+fn c_network(net: &Lowered, name: &str) -> String {
+    let mut s = String::new();
+    let poisson = net.layers.iter().any(|l| l.poisson.is_some());
+    let sends = |idx: usize| net.connections.iter().any(|c| c.src == idx);
+
+    let _ = writeln!(
+        s,
+        "/* Generated by `converge compile --target c`; do not edit. */\n\
+         #include <string.h>\n\n\
+         #include \"{name}.h\"\n\n\
+         /* Matches `converge sim` only if every operation is rounded on its\n   \
+            own: build with -ffp-contract=off (GCC's default under -std=c99). */\n\n\
+         #define STEPS {}u\n\
+         #define QUEUE_LEN {}u\n",
+        net.steps, net.queue_len
+    );
+
+    for (idx, conn) in net.connections.iter().enumerate() {
+        if let Synapses::Table {
+            rows,
+            dst,
+            weight,
+            delay,
+        } = &conn.synapses
+        {
+            let _ = writeln!(s, "/* `{}`: synapses of each source neuron. */", conn.label);
+            let max = |items: &[usize]| items.iter().copied().max().unwrap_or(0);
+            c_array(&mut s, &format!("c{idx}_rows"), c_uint(max(rows)), rows);
+            c_array(&mut s, &format!("c{idx}_dst"), c_uint(max(dst)), dst);
+            let _ = write!(s, "static const double c{idx}_w[{}] = {{", weight.len());
+            array_items(&mut s, weight, |x| format!("{x:?}"));
+            s.push_str("};\n");
+            c_array(&mut s, &format!("c{idx}_delay"), c_uint(max(delay)), delay);
+            s.push('\n');
+        }
+    }
+
+    // C has no zero-length arrays; empty layers have no state or loops.
+    let layers = || net.layers.iter().enumerate().filter(|(_, l)| l.size > 0);
+    for (idx, layer) in layers() {
+        let n = layer.size;
+        let _ = writeln!(s, "/* `{}` */", layer.name);
+        let _ = writeln!(s, "static double v{idx}[{n}];");
+        let _ = writeln!(s, "static double q{idx}[{n} * QUEUE_LEN];");
+        if sends(idx) {
+            let _ = writeln!(s, "static uint32_t s{idx}[{n}];");
+        }
+    }
+    s.push('\n');
+
+    if poisson {
+        s.push_str(
+            "static uint64_t rng_state;\n\n\
+             static double rng_next(void)\n\
+             {\n    \
+                 rng_state = rng_state * 6364136223846793005u + 1u;\n    \
+                 return (double)(rng_state >> 11) / (double)((uint64_t)1 << 53);\n\
+             }\n\n",
+        );
+    }
+    let neurons = layers().next().is_some();
+    s.push_str("static uint64_t hash_state;\n\n");
+    if neurons {
+        s.push_str(
+            "/* FNV-1a over each spike's `(step, layer, neuron)`, little-endian. */\n\
+         static void hash_bytes(uint64_t x, unsigned n)\n\
+         {\n    \
+             unsigned b;\n    \
+             for (b = 0; b < n; ++b) {\n        \
+                 hash_state ^= (x >> (8 * b)) & 0xffu;\n        \
+                 hash_state *= 0x100000001b3u;\n    \
+             }\n\
+         }\n\n\
+         static void hash_record(uint64_t step, uint32_t layer, uint64_t neuron)\n\
+         {\n    \
+             hash_bytes(step, 8);\n    \
+             hash_bytes(layer, 4);\n    \
+             hash_bytes(neuron, 8);\n\
+         }\n\n",
+        );
+    }
+
+    let _ = writeln!(s, "void {name}_run({name}_summary *out)\n{{");
+    s.push_str("    uint64_t step;\n");
+    if neurons {
+        s.push_str("    size_t i;\n");
+    }
+    if !net.connections.is_empty() {
+        s.push_str("    size_t k;\n");
+    }
+    s.push('\n');
+    for (idx, _) in layers() {
+        let _ = writeln!(s, "    memset(v{idx}, 0, sizeof v{idx});");
+        let _ = writeln!(s, "    memset(q{idx}, 0, sizeof q{idx});");
+    }
+    s.push_str("    memset(out, 0, sizeof *out);\n");
+    if poisson {
+        let _ = writeln!(s, "    rng_state = {}u;", Stream::Stimulus.seed(net.seed));
+    }
+    s.push_str("    hash_state = 0xcbf29ce484222325u;\n");
+    s.push_str("    for (step = 0; step < STEPS; ++step) {\n");
+    if neurons {
+        s.push_str("        size_t bucket = (size_t)(step % QUEUE_LEN);\n");
+    }
+    for (idx, _) in net.layers.iter().enumerate().filter(|(idx, _)| sends(*idx)) {
+        let _ = writeln!(s, "        size_t n{idx} = 0;");
+    }
+    if net
+        .connections
+        .iter()
+        .any(|c| matches!(c.synapses, Synapses::Dense { .. }))
+    {
+        s.push_str("        size_t slot;\n");
+    }
+    for (idx, layer) in layers() {
+        let n = layer.size;
+        let _ = writeln!(
+            s,
+            "        for (i = 0; i < {n}; ++i) {{\n            \
+                 v{idx}[i] += q{idx}[bucket * {n} + i];\n            \
+                 q{idx}[bucket * {n} + i] = 0.0;\n        \
+             }}"
+        );
+        if let Some(p) = layer.poisson {
+            let _ = writeln!(
+                s,
+                "        for (i = 0; i < {n}; ++i) {{\n            \
+                     if (rng_next() < {p:?}) {{\n                \
+                         v{idx}[i] += 1.0;\n            \
+                     }}\n        \
+                 }}"
+            );
+        }
+    }
+    for (idx, layer) in layers() {
+        let push = if sends(idx) {
+            format!("\n                s{idx}[n{idx}++] = (uint32_t)i;")
+        } else {
+            String::new()
+        };
+        let _ = writeln!(
+            s,
+            "        for (i = 0; i < {n}; ++i) {{\n            \
+                 v{idx}[i] += (-v{idx}[i]) * {decay:?};\n            \
+                 if (v{idx}[i] >= {v_th:?}) {{\n                \
+                     v{idx}[i] = 0.0;\n                \
+                     out->spikes[{idx}] += 1;\n                \
+                     hash_record(step, {idx}, i);{push}\n            \
+                 }}\n        \
+             }}",
+            n = layer.size,
+            decay = layer.decay,
+            v_th = layer.v_th,
+        );
+    }
+    for (idx, conn) in net.connections.iter().enumerate() {
+        let (src, dst) = (conn.src, conn.dst);
+        let n = net.layers[dst].size;
+        match &conn.synapses {
+            Synapses::Empty => {}
+            Synapses::Dense { weight, delay } => {
+                let _ = writeln!(
+                    s,
+                    "        /* `{}` */\n        \
+                     slot = (bucket + {delay}) % QUEUE_LEN;\n        \
+                     for (k = 0; k < n{src}; ++k) {{\n            \
+                         for (i = 0; i < {n}; ++i) {{\n                \
+                             q{dst}[slot * {n} + i] += {weight:?};\n                \
+                             out->weight_sum[{idx}] += {weight:?};\n            \
+                         }}\n            \
+                         out->deliveries[{idx}] += {n};\n        \
+                     }}",
+                    conn.label
+                );
+            }
+            Synapses::Table { .. } => {
+                let _ = writeln!(
+                    s,
+                    "        /* `{}` */\n        \
+                     for (k = 0; k < n{src}; ++k) {{\n            \
+                         size_t j, src = s{src}[k];\n            \
+                         for (j = c{idx}_rows[src]; j < c{idx}_rows[src + 1]; ++j) {{\n                \
+                             size_t to = (bucket + c{idx}_delay[j]) % QUEUE_LEN;\n                \
+                             q{dst}[to * {n} + c{idx}_dst[j]] += c{idx}_w[j];\n                \
+                             out->deliveries[{idx}] += 1;\n                \
+                             out->weight_sum[{idx}] += c{idx}_w[j];\n            \
+                         }}\n        \
+                     }}",
+                    conn.label
+                );
+            }
+        }
+    }
+    s.push_str("    }\n");
+    for idx in 0..net.layers.len() {
+        let _ = writeln!(s, "    out->total_spikes += out->spikes[{idx}];");
+    }
+    s.push_str("    out->spike_hash = hash_state;\n}\n");
+    s
+}
+
+fn c_main(net: &Lowered, name: &str) -> String {
+    let mut s = String::new();
+    let _ = writeln!(
+        s,
+        "/* Generated by `converge compile --target c`; do not edit.\n   \
+            Runs the network and prints the summary JSON `converge sim` prints. */\n\
+         #include <inttypes.h>\n\
+         #include <stdio.h>\n\n\
+         #include \"{name}.h\"\n\n\
+         int main(void)\n\
+         {{\n    \
+             {name}_summary summary;\n\n    \
+             {name}_run(&summary);\n    \
+             printf(\"{{\\n\");\n    \
+             printf(\"  \\\"duration_ns\\\": {},\\n\");\n    \
+             printf(\"  \\\"step_ns\\\": {},\\n\");\n    \
+             printf(\"  \\\"seed\\\": {},\\n\");\n    \
+             printf(\"  \\\"streams\\\": {},\\n\");\n    \
+             printf(\"  \\\"total_spikes\\\": %\" PRIu64 \",\\n\", summary.total_spikes);\n    \
+             printf(\"  \\\"spike_hash\\\": \\\"%016\" PRIx64 \"\\\",\\n\", summary.spike_hash);\n    \
+             printf(\"  \\\"layers\\\": [\\n\");",
+        net.duration_ns,
+        net.step_ns,
+        net.seed,
+        streams_json(net.seed).replace('"', "\\\"")
+    );
+    for (idx, layer) in net.layers.iter().enumerate() {
+        let comma = if idx + 1 == net.layers.len() { "" } else { "," };
+        let _ = writeln!(
+            s,
+            "    printf(\"    {{\\n\");\n    \
+             printf(\"      \\\"name\\\": \\\"{}\\\",\\n\");\n    \
+             printf(\"      \\\"size\\\": {},\\n\");\n    \
+             printf(\"      \\\"spikes\\\": %\" PRIu64 \"\\n\", summary.spikes[{idx}]);\n    \
+             printf(\"    }}{comma}\\n\");",
+            layer.name, layer.size
+        );
+    }
+    if net.connections.is_empty() {
+        s.push_str("    printf(\"  ],\\n\");\n    printf(\"  \\\"connections\\\": [],\\n\");\n");
+    } else {
+        s.push_str("    printf(\"  ],\\n\");\n    printf(\"  \\\"connections\\\": [\\n\");\n");
+    }
+    for (idx, conn) in net.connections.iter().enumerate() {
+        let comma = if idx + 1 == net.connections.len() {
+            ""
+        } else {
+            ","
+        };
+        // `%.17g` reads back as the same double Rust prints in fewer digits.
+        let _ = writeln!(
+            s,
+            "    printf(\"    {{ {}\\\"deliveries\\\": %\" PRIu64 \", \\\"mean_weight\\\": %.17g }}{comma}\\n\",\n           \
+             summary.deliveries[{idx}],\n           \
+             summary.deliveries[{idx}] ? summary.weight_sum[{idx}] / (double)summary.deliveries[{idx}] : 0.0);",
+            connection_fields(conn, net).replace('"', "\\\"")
+        );
+    }
+    if !net.connections.is_empty() {
+        s.push_str("    printf(\"  ],\\n\");\n");
+    }
+    let _ = writeln!(
+        s,
+        "    printf(\"  \\\"defaults\\\": {}\\n\");",
+        defaults_json(&net.defaults)
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    );
+    s.push_str("    printf(\"}\\n\");\n    return 0;\n}\n");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converge_lang::parser::parse_program;
+
+    #[test]
+    fn hard_codes_dense_connections_and_tables_sampled_ones() {
+        let program = parse_program(
+            "neuron LIF { tau_m = 10 ms }\n\
+             layer A[3] : LIF\n\
+             layer B[2] : LIF\n\
+             connect A -> B { w = 0.5, d = 2 ms }\n\
+             connect rec: B -> B { w = Uniform(0.1, 0.2) }\n\
+             stimulus A = Poisson(rate=100 Hz)\n\
+             run for 5 ms\n",
+        )
+        .unwrap();
+        let files = rust_crate(&program, &SimConfig::default(), "2-layer").unwrap();
+        assert!(files[0].contents.contains("name = \"net_2_layer\""));
+        let main = &files[1].contents;
+        assert!(main.contains("*q += 0.5;"));
+        assert!(main.contains("let slot = (bucket + 2) % QUEUE_LEN;"));
+        assert!(main.contains("// `rec: B -> B`: synapses of each source neuron."));
+        assert!(main.contains("static C1_ROWS: [u32; 3] = [\n    0, 2, 4,\n];"));
+        assert!(main.contains("if rng.next_f64() < 0.1 {"));
+        assert!(main.contains("weight_sum[1] += C1_W[k];"));
+        assert!(main.contains("\\\"name\\\": \\\"rec\\\", \\\"src\\\": \\\"B\\\""));
+
+        let files = c_sources(&program, &SimConfig::default(), "2-layer").unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["net_2_layer.h", "net_2_layer.c", "main.c"]);
+        assert!(
+            files[1]
+                .contents
+                .contains("static const uint8_t c1_rows[3] = {\n    0, 2, 4,\n};")
+        );
+        assert!(files[1].contents.contains("q1[slot * 2 + i] += 0.5;"));
+        assert!(files[1].contents.contains("out->deliveries[0] += 2;"));
+
+        let gap = parse_program(
+            "neuron LIF { tau_m = 20 ms }\n\
+             layer A[2] : LIF\n\
+             connect A <-> A electrical { g = 0.1 }\n\
+             run for 1 ms\n",
+        )
+        .unwrap();
+        let err = rust_crate(&gap, &SimConfig::default(), "gap").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "generated code does not support gap junctions"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use converge_lang::diagnostic::Diagnostic;
+use converge_lang::json::{self, Value};
+
+use crate::{SimError, SimErrorKind, SimSummary, Simulator, Spike, summary_json};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDiff {
+    pub path: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.expected, &self.actual) {
+            (Some(e), Some(a)) => write!(f, "{}: expected {e}, got {a}", self.path),
+            (Some(e), None) => write!(f, "{}: expected {e}, missing from output", self.path),
+            (None, Some(a)) => write!(f, "{}: unexpected {a}, not in golden", self.path),
+            (None, None) => write!(f, "{}: differs", self.path),
+        }
+    }
+}
+
+/// Fields that legitimately differ between identical runs, or describe how
+/// a run was made rather than what it produced.
+const VOLATILE_FIELDS: &[&str] = &[
+    "profile",
+    "wall_ns",
+    "schema_version",
+    "toolchain_version",
+    "input_hash",
+    "config",
+    "defaults",
+    "queues",
+];
+
+/// Diffs a summary against a stored golden summary. Numbers match when their
+/// relative difference is within `tolerance`; everything else must be equal.
+pub fn compare_summary(
+    summary: &SimSummary,
+    golden: &str,
+    tolerance: f64,
+) -> Result<Vec<FieldDiff>, Diagnostic> {
+    let expected = json::parse(golden)?;
+    let actual = json::parse(&summary_json(summary))?;
+    let mut diffs = Vec::new();
+    diff_values("", &expected, &actual, tolerance, &mut diffs);
+    Ok(diffs)
+}
+
+fn diff_values(path: &str, expected: &Value, actual: &Value, tol: f64, out: &mut Vec<FieldDiff>) {
+    match (expected, actual) {
+        (Value::Number(e), Value::Number(a)) => {
+            if (e - a).abs() > tol * e.abs().max(a.abs()) {
+                out.push(mismatch(path, expected, actual));
+            }
+        }
+        (Value::Object(e_fields), Value::Object(a_fields)) => {
+            for (key, e) in e_fields {
+                if path.is_empty() && VOLATILE_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let child = join(path, key);
+                match actual.get(key) {
+                    Some(a) => diff_values(&child, e, a, tol, out),
+                    None => out.push(FieldDiff {
+                        path: child,
+                        expected: Some(e.to_string()),
+                        actual: None,
+                    }),
+                }
+            }
+            for (key, a) in a_fields {
+                if path.is_empty() && VOLATILE_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                if expected.get(key).is_none() {
+                    out.push(FieldDiff {
+                        path: join(path, key),
+                        expected: None,
+                        actual: Some(a.to_string()),
+                    });
+                }
+            }
+        }
+        (Value::Array(e_items), Value::Array(a_items)) => {
+            for idx in 0..e_items.len().max(a_items.len()) {
+                let child = format!("{path}[{idx}]");
+                match (e_items.get(idx), a_items.get(idx)) {
+                    (Some(e), Some(a)) => diff_values(&child, e, a, tol, out),
+                    (e, a) => out.push(FieldDiff {
+                        path: child,
+                        expected: e.map(Value::to_string),
+                        actual: a.map(Value::to_string),
+                    }),
+                }
+            }
+        }
+        _ => {
+            if expected != actual {
+                out.push(mismatch(path, expected, actual));
+            }
+        }
+    }
+}
+
+fn mismatch(path: &str, expected: &Value, actual: &Value) -> FieldDiff {
+    FieldDiff {
+        path: if path.is_empty() { "$" } else { path }.to_string(),
+        expected: Some(expected.to_string()),
+        actual: Some(actual.to_string()),
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// Where two runs stepped in lockstep first disagree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub step: u64,
+    pub t_ns: i64,
+    pub layer: String,
+    pub neuron: u64,
+    pub kind: DivergenceKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DivergenceKind {
+    /// The neuron spiked in one run only; `a` says which.
+    Spike { a: bool },
+    /// Somatic potentials after the step.
+    Membrane { a: f64, b: f64 },
+    /// Potentials of dendritic compartment `compartment` (1-based).
+    Compartment { compartment: usize, a: f64, b: f64 },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "step {} (t = {} ns), layer `{}`, neuron {}: ",
+            self.step, self.t_ns, self.layer, self.neuron
+        )?;
+        match self.kind {
+            DivergenceKind::Spike { a } => {
+                write!(f, "spiked in {} only", if a { "a" } else { "b" })
+            }
+            DivergenceKind::Membrane { a, b } => write!(f, "membrane {a} in a, {b} in b"),
+            DivergenceKind::Compartment { compartment, a, b } => {
+                write!(f, "compartment {compartment} {a} in a, {b} in b")
+            }
+        }
+    }
+}
+
+/// Steps `a` and `b` together until one finishes, checking after every step
+/// that the same neurons spiked and that every potential agrees within
+/// `tolerance` (absolute). Reports the first disagreement, by step, then
+/// layer, then neuron. Spikes are only compared when both simulators record
+/// them (`SimConfig::record_spikes`); otherwise a spike in one run shows up
+/// as a membrane difference, since spiking resets the potential.
+pub fn first_divergence(
+    a: &mut Simulator,
+    b: &mut Simulator,
+    tolerance: f64,
+) -> Result<Option<Divergence>, SimError> {
+    check_same_shape(a, b)?;
+    while !a.is_finished() && !b.is_finished() {
+        let (a_spiked, b_spiked) = (a.recorded_spikes().len(), b.recorded_spikes().len());
+        let step = a.current_step();
+        a.step()?;
+        b.step()?;
+        let fired = |spikes: &[Spike]| -> HashSet<(usize, usize)> {
+            spikes
+                .iter()
+                .map(|s| (s.layer as usize, s.neuron as usize))
+                .collect()
+        };
+        let a_fired = fired(&a.recorded_spikes()[a_spiked..]);
+        let b_fired = fired(&b.recorded_spikes()[b_spiked..]);
+        let differs = |x: f64, y: f64| {
+            x.to_bits() != y.to_bits() && ((x - y).abs() > tolerance || (x - y).is_nan())
+        };
+        for idx in 0..a.layer_count() {
+            let (la, lb) = (a.layer(idx), b.layer(idx));
+            let size = la.membrane.len();
+            for neuron in 0..size {
+                let in_a = a_fired.contains(&(idx, neuron));
+                let kind = if in_a != b_fired.contains(&(idx, neuron)) {
+                    Some(DivergenceKind::Spike { a: in_a })
+                } else if differs(la.membrane[neuron], lb.membrane[neuron]) {
+                    Some(DivergenceKind::Membrane {
+                        a: la.membrane[neuron],
+                        b: lb.membrane[neuron],
+                    })
+                } else {
+                    (0..la.dendrites.len() / size.max(1)).find_map(|k| {
+                        let at = k * size + neuron;
+                        differs(la.dendrites[at], lb.dendrites[at]).then_some(
+                            DivergenceKind::Compartment {
+                                compartment: k + 1,
+                                a: la.dendrites[at],
+                                b: lb.dendrites[at],
+                            },
+                        )
+                    })
+                };
+                if let Some(kind) = kind {
+                    return Ok(Some(Divergence {
+                        step,
+                        t_ns: step as i64 * a.step_ns(),
+                        layer: la.name.to_string(),
+                        neuron: neuron as u64,
+                        kind,
+                    }));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn check_same_shape(a: &Simulator, b: &Simulator) -> Result<(), SimError> {
+    let mismatch = |what: String| {
+        Err(SimError::new(SimErrorKind::InvalidInput(format!(
+            "the runs cannot be compared: {what}"
+        ))))
+    };
+    if a.step_ns() != b.step_ns() {
+        return mismatch(format!(
+            "a steps by {} ns, b by {} ns",
+            a.step_ns(),
+            b.step_ns()
+        ));
+    }
+    if a.layer_count() != b.layer_count() {
+        return mismatch(format!(
+            "a has {} layers, b {}",
+            a.layer_count(),
+            b.layer_count()
+        ));
+    }
+    for idx in 0..a.layer_count() {
+        let (la, lb) = (a.layer(idx), b.layer(idx));
+        if la.name != lb.name
+            || la.membrane.len() != lb.membrane.len()
+            || la.dendrites.len() != lb.dendrites.len()
+        {
+            return mismatch(format!(
+                "layer {idx} is `{}`[{}] in a and `{}`[{}] in b",
+                la.name,
+                la.membrane.len(),
+                lb.name,
+                lb.membrane.len()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arithmetic, LayerSummary, RunConfig};
+
+    fn summary(spikes: u64) -> SimSummary {
+        SimSummary {
+            duration_ns: 10_000_000,
+            step_ns: 1_000_000,
+            seed: 1,
+            steps: 10,
+            config: RunConfig::default(),
+            input_hash: None,
+            wall_ns: Some(1234),
+            quantize: None,
+            arithmetic: Arithmetic::Float,
+            total_spikes: spikes,
+            spike_hash: 0xc0ffee,
+            profile: None,
+            raster: None,
+            input: None,
+            voltages: None,
+            rates: Vec::new(),
+            probes: Vec::new(),
+            defaults: Vec::new(),
+            queues: Default::default(),
+            samples: None,
+            classification: None,
+            energy: None,
+            layers: vec![LayerSummary {
+                name: "A".to_string(),
+                size: 2,
+                spikes,
+            }],
+            connections: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn identical_summary_has_no_diffs() {
+        let golden = summary_json(&summary(10));
+        let rerun = SimSummary {
+            wall_ns: Some(5678),
+            input_hash: Some(42),
+            config: RunConfig {
+                profile: true,
+                ..RunConfig::default()
+            },
+            ..summary(10)
+        };
+        assert!(compare_summary(&rerun, &golden, 0.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_field_paths_and_respects_tolerance() {
+        let golden = summary_json(&summary(100));
+        let diffs = compare_summary(&summary(101), &golden, 0.0).unwrap();
+        let paths: Vec<_> = diffs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, ["total_spikes", "layers[0].spikes"]);
+        assert_eq!(diffs[0].to_string(), "total_spikes: expected 100, got 101");
+        assert!(
+            compare_summary(&summary(101), &golden, 0.02)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn lockstep_runs_report_the_first_divergence() {
+        let src = |w: &str| {
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 1.0 }}\n\
+                 layer A[4] : LIF\n\
+                 layer B[3] : LIF\n\
+                 connect A -> B {{ w = {w}, d = 1 ms }}\n\
+                 stimulus A = Poisson(rate=400 Hz)\n\
+                 seed 3\n\
+                 run for 30 ms\n"
+            )
+        };
+        let config = crate::SimConfig {
+            record_spikes: true,
+            ..crate::SimConfig::default()
+        };
+        let sim = |w: &str| {
+            let program = converge_lang::parser::parse_program(&src(w)).unwrap();
+            Simulator::new(&program, &config).unwrap()
+        };
+
+        assert_eq!(
+            first_divergence(&mut sim("0.5"), &mut sim("0.5"), 0.0).unwrap(),
+            None
+        );
+
+        let divergence = first_divergence(&mut sim("0.5"), &mut sim("0.5001"), 0.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.layer, "B");
+        assert!(matches!(divergence.kind, DivergenceKind::Membrane { .. }));
+        assert_eq!(
+            first_divergence(&mut sim("0.5"), &mut sim("0.5001"), 0.01).unwrap(),
+            None
+        );
+
+        let other = converge_lang::parser::parse_program(
+            "neuron LIF { tau_m = 10 ms }\nlayer A[5] : LIF\nrun for 30 ms\n",
+        )
+        .unwrap();
+        let err = first_divergence(
+            &mut sim("0.5"),
+            &mut Simulator::new(&other, &config).unwrap(),
+            0.0,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the runs cannot be compared: a has 2 layers, b 1"
+        );
+    }
+}
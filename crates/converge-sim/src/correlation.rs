@@ -0,0 +1,225 @@
+//! Synchrony between layers, from the spike raster of a finished run.
+//!
+//! Spikes are counted per layer in bins of whole steps. For every pair of
+//! layers, the count correlation is the Pearson correlation of the two
+//! count series, and the cross-correlogram counts spike pairs by how many
+//! bins the second layer's spike falls after the first's, from `-max_lag`
+//! to `max_lag`. A peak at lag 0 means the layers fire together; a peak at
+//! a positive lag means the second follows the first.
+
+use crate::{SimError, SimErrorKind, SimSummary};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correlations {
+    /// Bin width, a whole number of steps.
+    pub bin_ns: i64,
+    pub max_lag: usize,
+    /// Every pair of the chosen layers, in layer order.
+    pub pairs: Vec<PairCorrelation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairCorrelation {
+    pub a: String,
+    pub b: String,
+    /// `None` when either layer's count is the same in every bin.
+    pub count_correlation: Option<f64>,
+    /// Spike pairs at lags `-max_lag..=max_lag` bins.
+    pub correlogram: Vec<u64>,
+}
+
+/// Correlates `layers` (every layer when empty) of a run recorded with
+/// `SimConfig::record_spikes`. The bin is rounded to a whole number of
+/// steps, at least one; a partial last bin is dropped.
+pub fn correlations(
+    summary: &SimSummary,
+    layers: &[String],
+    bin_ns: i64,
+    max_lag: usize,
+) -> Result<Correlations, SimError> {
+    let raster = summary
+        .raster
+        .as_ref()
+        .ok_or_else(|| SimError::new(SimErrorKind::MissingRaster))?;
+    let chosen: Vec<usize> = if layers.is_empty() {
+        (0..summary.layers.len()).collect()
+    } else {
+        layers
+            .iter()
+            .map(|name| {
+                summary
+                    .layers
+                    .iter()
+                    .position(|l| l.name == *name)
+                    .ok_or_else(|| {
+                        SimError::new(SimErrorKind::UnknownLayer {
+                            role: "",
+                            name: name.clone(),
+                        })
+                    })
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let bin_steps = ((bin_ns as f64 / summary.step_ns as f64).round() as u64).max(1);
+    let bins = (summary.steps / bin_steps) as usize;
+    let mut counts = vec![vec![0u64; bins]; summary.layers.len()];
+    for spike in raster {
+        let bin = (spike.step / bin_steps) as usize;
+        if bin < bins {
+            counts[spike.layer as usize][bin] += 1;
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (i, &a) in chosen.iter().enumerate() {
+        for &b in &chosen[i + 1..] {
+            pairs.push(PairCorrelation {
+                a: summary.layers[a].name.clone(),
+                b: summary.layers[b].name.clone(),
+                count_correlation: pearson(&counts[a], &counts[b]),
+                correlogram: correlogram(&counts[a], &counts[b], max_lag),
+            });
+        }
+    }
+    Ok(Correlations {
+        bin_ns: bin_steps as i64 * summary.step_ns,
+        max_lag,
+        pairs,
+    })
+}
+
+fn pearson(a: &[u64], b: &[u64]) -> Option<f64> {
+    if a.is_empty() {
+        return None;
+    }
+    let n = a.len() as f64;
+    let mean_a = a.iter().sum::<u64>() as f64 / n;
+    let mean_b = b.iter().sum::<u64>() as f64 / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (&x, &y) in a.iter().zip(b) {
+        let (dx, dy) = (x as f64 - mean_a, y as f64 - mean_b);
+        cov += dx * dy;
+        var_a += dx * dx;
+        var_b += dy * dy;
+    }
+    (var_a > 0.0 && var_b > 0.0).then(|| cov / (var_a * var_b).sqrt())
+}
+
+/// Entry `k` counts pairs with `b`'s spike `k - max_lag` bins after `a`'s.
+fn correlogram(a: &[u64], b: &[u64], max_lag: usize) -> Vec<u64> {
+    let lags = -(max_lag as i64)..=max_lag as i64;
+    lags.map(|lag| {
+        a.iter()
+            .enumerate()
+            .filter_map(|(t, &x)| {
+                let u = t as i64 + lag;
+                (0..b.len() as i64).contains(&u).then(|| x * b[u as usize])
+            })
+            .sum()
+    })
+    .collect()
+}
+
+pub fn correlations_json(report: &Correlations) -> String {
+    let mut s = String::new();
+    s.push_str("{\n");
+    s.push_str(&format!("  \"bin_ns\": {},\n", report.bin_ns));
+    s.push_str(&format!("  \"max_lag\": {},\n", report.max_lag));
+    if report.pairs.is_empty() {
+        s.push_str("  \"pairs\": []\n");
+    } else {
+        s.push_str("  \"pairs\": [\n");
+        for (idx, pair) in report.pairs.iter().enumerate() {
+            let correlogram: Vec<String> = pair.correlogram.iter().map(u64::to_string).collect();
+            s.push_str(&format!(
+                "    {{ \"a\": \"{}\", \"b\": \"{}\", \"count_correlation\": {}, \"correlogram\": [{}] }}",
+                pair.a,
+                pair.b,
+                pair.count_correlation
+                    .map_or("null".to_string(), |r| r.to_string()),
+                correlogram.join(", ")
+            ));
+            if idx + 1 != report.pairs.len() {
+                s.push(',');
+            }
+            s.push('\n');
+        }
+        s.push_str("  ]\n");
+    }
+    s.push_str("}\n");
+    s
+}
+
+/// One row per pair and lag under `a,b,count_correlation,lag_ns,pairs`.
+pub fn correlations_csv(report: &Correlations) -> String {
+    let mut s = String::from("a,b,count_correlation,lag_ns,pairs\n");
+    for pair in &report.pairs {
+        let r = pair
+            .count_correlation
+            .map_or(String::new(), |r| r.to_string());
+        for (k, count) in pair.correlogram.iter().enumerate() {
+            let lag_ns = (k as i64 - report.max_lag as i64) * report.bin_ns;
+            s.push_str(&format!("{},{},{r},{lag_ns},{count}\n", pair.a, pair.b));
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimConfig, simulate_with};
+    use converge_lang::parser::parse_program;
+
+    #[test]
+    fn correlates_a_layer_with_its_delayed_copy() {
+        // Every In spike makes Out spike 2 ms later.
+        let src = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+                   layer In[20] : LIF\n\
+                   layer Out[20] : LIF\n\
+                   layer Quiet[5] : LIF\n\
+                   connect In -> Out { w = 2.0, d = 2 ms }\n\
+                   stimulus In = Poisson(rate=50 Hz)\n\
+                   seed 5\n\
+                   run for 200 ms step 1 ms\n";
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            record_spikes: true,
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&program, &config).expect("sim");
+        let report = correlations(&summary, &[], 1_000_000, 3).expect("correlations");
+        assert_eq!(report.bin_ns, 1_000_000);
+        let names: Vec<(&str, &str)> = report
+            .pairs
+            .iter()
+            .map(|p| (p.a.as_str(), p.b.as_str()))
+            .collect();
+        assert_eq!(names, [("In", "Out"), ("In", "Quiet"), ("Out", "Quiet")]);
+
+        let pair = &report.pairs[0];
+        let peak = (0..pair.correlogram.len())
+            .max_by_key(|&k| pair.correlogram[k])
+            .unwrap();
+        assert_eq!(peak, 3 + 2, "{:?}", pair.correlogram);
+        assert_eq!(report.pairs[1].count_correlation, None);
+        assert!(report.pairs[1].correlogram.iter().all(|&c| c == 0));
+
+        let json = correlations_json(&report);
+        let parsed = converge_lang::json::parse(&json).expect("json");
+        assert_eq!(
+            parsed
+                .get("pairs")
+                .and_then(|p| p.as_array())
+                .map(<[_]>::len),
+            Some(3)
+        );
+        let csv = correlations_csv(&report);
+        assert_eq!(csv.lines().count(), 1 + 3 * 7);
+        assert!(csv.contains("\nIn,Quiet,,-3000000,0\n"));
+
+        let err = correlations(&summary, &["Nope".to_string()], 1_000_000, 3).unwrap_err();
+        assert_eq!(err.to_string(), "unknown layer `Nope`");
+    }
+}
@@ -0,0 +1,112 @@
+//! The values a run falls back on where the model says nothing, and which
+//! of them a given model relies on. A connection without `w` gets weight 1,
+//! which is easy to miss, so every default in use is listed in the summary
+//! and the debug log.
+
+use std::fmt;
+
+use converge_lang::ast::{ConnectKind, Item, Program};
+
+pub(crate) const STEP_NS: i64 = 1_000_000;
+pub(crate) const TAU_M_NS: i64 = 20_000_000;
+pub(crate) const V_TH: f64 = 1.0;
+pub(crate) const WEIGHT: f64 = 1.0;
+pub(crate) const DELAY_NS: f64 = 0.0;
+
+/// A setting the model left out, and the value the run used for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedDefault {
+    /// `run`, `neuron <name>` or `connect <name>`, with `<src> -> <dst>`
+    /// for an unnamed connection.
+    pub item: String,
+    pub key: &'static str,
+    pub value: String,
+}
+
+impl fmt::Display for AppliedDefault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} = {}", self.item, self.key, self.value)
+    }
+}
+
+/// The defaults `program` relies on, in source order: the run step, the
+/// `tau_m` and `v_th` of neurons that some layer uses, and the `w` and `d`
+/// of chemical connections.
+pub(crate) fn applied_defaults(program: &Program) -> Vec<AppliedDefault> {
+    let ms = |ns: f64| format!("{} ms", ns / 1e6);
+    let mut defaults = Vec::new();
+    let mut add = |item: String, key, value| {
+        defaults.push(AppliedDefault { item, key, value });
+    };
+    for item in &program.items {
+        match item {
+            Item::Run(run) if run.step.is_none() => {
+                add("run".to_string(), "step", ms(STEP_NS as f64));
+            }
+            Item::Neuron(def) => {
+                let used = program
+                    .items
+                    .iter()
+                    .any(|i| matches!(i, Item::Layer(l) if l.neuron.name == def.name.name));
+                if !used {
+                    continue;
+                }
+                let has = |key: &str| def.body.iter().any(|a| a.key.name == key);
+                let item = format!("neuron {}", def.name.name);
+                if !has("tau_m") {
+                    add(item.clone(), "tau_m", ms(TAU_M_NS as f64));
+                }
+                if !has("v_th") {
+                    add(item, "v_th", V_TH.to_string());
+                }
+            }
+            Item::Connect(def) if def.kind == ConnectKind::Chemical => {
+                let has = |key: &str| def.body.iter().any(|a| a.key.name == key);
+                let item = match &def.name {
+                    Some(name) => format!("connect {}", name.name),
+                    None => format!("connect {} -> {}", def.src.name, def.dst.name),
+                };
+                if !has("w") {
+                    add(item.clone(), "w", WEIGHT.to_string());
+                }
+                if !has("d") {
+                    add(item, "d", ms(DELAY_NS));
+                }
+            }
+            _ => {}
+        }
+    }
+    defaults
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converge_lang::parser::parse_program;
+
+    #[test]
+    fn lists_the_defaults_a_model_relies_on() {
+        let src = "neuron LIF { tau_m = 10 ms }\n\
+                   neuron Unused { }\n\
+                   layer In[2] : LIF\n\
+                   layer Out[2] : LIF\n\
+                   connect In -> Out { d = 2 ms }\n\
+                   connect back: Out -> In { w = 0.5 }\n\
+                   connect In <-> Out electrical { g = 0.1 }\n\
+                   run for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        let listed: Vec<String> = applied_defaults(&program)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                "neuron LIF: v_th = 1",
+                "connect In -> Out: w = 1",
+                "connect back: d = 0 ms",
+                "run: step = 1 ms",
+            ]
+        );
+    }
+}
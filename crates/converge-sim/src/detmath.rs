@@ -0,0 +1,111 @@
+//! Transcendental functions built only from IEEE-754 basic operations.
+//!
+//! `f64::ln` and `f64::cos` defer to the platform libm, which is not required
+//! to round identically on every target. Sampling goes through these instead
+//! so a seed produces bit-identical networks on x86_64, aarch64 and friends.
+
+const LN_2: f64 = std::f64::consts::LN_2;
+const FRAC_PI_2: f64 = std::f64::consts::FRAC_PI_2;
+
+/// Natural logarithm. Matches `f64::ln` to within a few ulps.
+pub(crate) fn ln(x: f64) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    }
+    if x == 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if x.is_infinite() {
+        return f64::INFINITY;
+    }
+
+    // Normalize subnormals so the exponent field is meaningful.
+    let (x, bias) = if x < f64::MIN_POSITIVE {
+        (x * (1u64 << 54) as f64, -54)
+    } else {
+        (x, 0)
+    };
+    let bits = x.to_bits();
+    let mut exp = ((bits >> 52) & 0x7ff) as i64 - 1023 + bias;
+    let mut m = f64::from_bits((bits & 0x000f_ffff_ffff_ffff) | 0x3ff0_0000_0000_0000);
+    if m > std::f64::consts::SQRT_2 {
+        m *= 0.5;
+        exp += 1;
+    }
+
+    // ln(m) = 2 atanh(f) with f = (m - 1) / (m + 1), |f| < 0.172.
+    let f = (m - 1.0) / (m + 1.0);
+    let s = f * f;
+    let mut series = 1.0 / 21.0;
+    for k in [19.0, 17.0, 15.0, 13.0, 11.0, 9.0, 7.0, 5.0, 3.0] {
+        series = series * s + 1.0 / k;
+    }
+    series = series * s + 1.0;
+    exp as f64 * LN_2 + 2.0 * f * series
+}
+
+/// `cos(2 * pi * u)`, reducing on `u` directly so no multiple of pi is rounded.
+pub(crate) fn cos_2pi(u: f64) -> f64 {
+    if !u.is_finite() {
+        return f64::NAN;
+    }
+    let t = (u - u.floor()) * 4.0;
+    let k = t.round();
+    let theta = (t - k) * FRAC_PI_2;
+    match (k as i64).rem_euclid(4) {
+        0 => cos_poly(theta),
+        1 => -sin_poly(theta),
+        2 => -cos_poly(theta),
+        _ => sin_poly(theta),
+    }
+}
+
+// Taylor series on |theta| <= pi/4; truncation error is below 1e-17.
+fn cos_poly(theta: f64) -> f64 {
+    let s = theta * theta;
+    let mut acc = 0.0;
+    for n in (1..=8).rev() {
+        let k = (2 * n) as f64;
+        acc = 1.0 - acc * s / (k * (k - 1.0));
+    }
+    acc
+}
+
+fn sin_poly(theta: f64) -> f64 {
+    let s = theta * theta;
+    let mut acc = 0.0;
+    for n in (1..=8).rev() {
+        let k = (2 * n + 1) as f64;
+        acc = 1.0 - acc * s / (k * (k - 1.0));
+    }
+    theta * acc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ln_matches_std() {
+        for &x in &[
+            1e-300, 1e-10, 0.001, 0.25, 0.5, 0.7, 0.9999, 1.0, 1.5, 2.0, 1e10,
+        ] {
+            let err = (ln(x) - x.ln()).abs();
+            assert!(err <= 1e-15 * x.ln().abs().max(1.0), "ln({x}) off by {err}");
+        }
+        assert_eq!(ln(0.0), f64::NEG_INFINITY);
+        assert!(ln(-1.0).is_nan());
+        assert!((ln(5e-324) - 5e-324f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn cos_2pi_matches_std() {
+        for i in 0..=1000 {
+            let u = i as f64 / 1000.0;
+            let expected = (2.0 * std::f64::consts::PI * u).cos();
+            assert!((cos_2pi(u) - expected).abs() < 1e-15, "cos_2pi({u})");
+        }
+        assert_eq!(cos_2pi(0.0), 1.0);
+        assert_eq!(cos_2pi(0.5), -1.0);
+    }
+}
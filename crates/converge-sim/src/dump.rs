@@ -0,0 +1,203 @@
+//! Whole-layer state dumps, written by `converge sim --dump-state <path>`
+//! for checking small models' dynamics by hand. Each dumped step writes one
+//! NDJSON line per dumped layer with `step`, `t_ns`, `layer` and `v`, the
+//! somatic potentials after the step by neuron index, plus `dendrites`, one
+//! array per compartment, for multi-compartment layers.
+//!
+//! Dumps grow with neurons times steps, so [`StateDump::new`] estimates the
+//! output up front and refuses anything over a byte limit.
+
+use std::io::{self, Write};
+
+use crate::{SimError, SimErrorKind, Simulator};
+
+/// The limit `converge sim --dump-state` applies unless `--dump-limit`
+/// raises it.
+pub const DEFAULT_DUMP_LIMIT: u64 = 256 << 20;
+
+/// Upper bound on one potential as written, with its separator.
+const BYTES_PER_VALUE: u64 = 25;
+/// Upper bound on a line without its name and potentials.
+const BYTES_PER_LINE: u64 = 80;
+
+pub struct StateDump<W: Write> {
+    out: W,
+    every: u64,
+    /// Dumped layers, in source order.
+    layers: Vec<usize>,
+    step_ns: i64,
+}
+
+impl<W: Write> StateDump<W> {
+    /// Dumps the named layers of `sim` (every layer when `layers` is empty)
+    /// after steps 0, `every`, `2 * every`, ... Fails when a name is unknown
+    /// or the whole dump would take more than `limit` bytes.
+    pub fn new(
+        out: W,
+        sim: &Simulator,
+        every: u64,
+        layers: &[String],
+        limit: u64,
+    ) -> Result<Self, SimError> {
+        if every == 0 {
+            return Err(SimError::new(SimErrorKind::InvalidInput(
+                "the dump interval must be at least one step".to_string(),
+            )));
+        }
+        let mut picked = Vec::new();
+        for idx in 0..sim.layer_count() {
+            if layers.is_empty() || layers.iter().any(|name| name == sim.layer(idx).name) {
+                picked.push(idx);
+            }
+        }
+        if let Some(unknown) = layers.iter().find(|name| {
+            !picked
+                .iter()
+                .any(|&idx| sim.layer(idx).name == name.as_str())
+        }) {
+            return Err(SimError::new(SimErrorKind::UnknownLayer {
+                role: "dumped",
+                name: unknown.to_string(),
+            }));
+        }
+
+        let line_bytes: u64 = picked
+            .iter()
+            .map(|&idx| {
+                let layer = sim.layer(idx);
+                let values = (layer.membrane.len() + layer.dendrites.len()) as u64;
+                BYTES_PER_LINE + layer.name.len() as u64 + values * BYTES_PER_VALUE
+            })
+            .sum();
+        let dumped_steps = sim.total_steps().div_ceil(every);
+        let bytes = line_bytes.saturating_mul(dumped_steps);
+        if bytes > limit {
+            return Err(SimError::new(SimErrorKind::DumpTooLarge { bytes, limit }));
+        }
+        Ok(Self {
+            out,
+            every,
+            layers: picked,
+            step_ns: sim.step_ns(),
+        })
+    }
+
+    /// Call after every step; writes the state when the step just run is
+    /// due.
+    pub fn after_step(&mut self, sim: &Simulator) -> io::Result<()> {
+        let Some(step) = sim.current_step().checked_sub(1) else {
+            return Ok(());
+        };
+        if !step.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        for &idx in &self.layers {
+            let layer = sim.layer(idx);
+            write!(
+                self.out,
+                "{{\"step\":{step},\"t_ns\":{},\"layer\":\"{}\",\"v\":",
+                step as i64 * self.step_ns,
+                layer.name
+            )?;
+            write_values(&mut self.out, layer.membrane)?;
+            if !layer.dendrites.is_empty() {
+                write!(self.out, ",\"dendrites\":[")?;
+                let size = layer.membrane.len().max(1);
+                for (k, compartment) in layer.dendrites.chunks(size).enumerate() {
+                    if k > 0 {
+                        write!(self.out, ",")?;
+                    }
+                    write_values(&mut self.out, compartment)?;
+                }
+                write!(self.out, "]")?;
+            }
+            writeln!(self.out, "}}")?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the output.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn write_values(out: &mut impl Write, values: &[f64]) -> io::Result<()> {
+    write!(out, "[")?;
+    for (idx, v) in values.iter().enumerate() {
+        if idx > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, "{v}")?;
+    }
+    write!(out, "]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimConfig;
+    use converge_lang::json;
+    use converge_lang::parser::parse_program;
+
+    fn sim(src: &str) -> Simulator {
+        Simulator::new(&parse_program(src).unwrap(), &SimConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn dumps_chosen_layers_at_the_interval() {
+        let mut sim = sim("neuron LIF { tau_m = 10 ms, v_th = 5.0 }\n\
+             layer A[3] : LIF\n\
+             layer B[2] : LIF\n\
+             stimulus A = Poisson(rate=500 Hz)\n\
+             run for 10 ms\n");
+        let layers = ["A".to_string()];
+        let mut dump = StateDump::new(Vec::new(), &sim, 4, &layers, DEFAULT_DUMP_LIMIT).unwrap();
+        let mut expected = Vec::new();
+        while !sim.is_finished() {
+            sim.step().unwrap();
+            if (sim.current_step() - 1).is_multiple_of(4) {
+                expected.push(sim.layer(0).membrane.to_vec());
+            }
+            dump.after_step(&sim).unwrap();
+        }
+        let text = String::from_utf8(dump.out).unwrap();
+        let lines: Vec<json::Value> = text.lines().map(|l| json::parse(l).unwrap()).collect();
+        let steps: Vec<f64> = lines
+            .iter()
+            .map(|l| l.get("step").and_then(|s| s.as_f64()).unwrap())
+            .collect();
+        assert_eq!(steps, [0.0, 4.0, 8.0]);
+        for (line, v) in lines.iter().zip(&expected) {
+            assert_eq!(line.get("layer").and_then(|l| l.as_str()), Some("A"));
+            let dumped: Vec<f64> = line
+                .get("v")
+                .and_then(|v| v.as_array())
+                .unwrap()
+                .iter()
+                .map(|x| x.as_f64().unwrap())
+                .collect();
+            assert_eq!(&dumped, v);
+        }
+    }
+
+    #[test]
+    fn refuses_unknown_layers_and_oversized_dumps() {
+        let sim = sim("neuron LIF { tau_m = 10 ms }\n\
+             layer A[1000] : LIF\n\
+             run for 1000 ms\n");
+        let err = |layers: &[&str], limit: u64| {
+            let layers: Vec<String> = layers.iter().map(|l| l.to_string()).collect();
+            StateDump::new(Vec::new(), &sim, 1, &layers, limit)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(err(&["Z"], DEFAULT_DUMP_LIMIT), "unknown dumped layer `Z`");
+        assert_eq!(
+            err(&[], 1 << 20),
+            "the state dump would take up to 23.9 MiB, over the 1.0 MiB limit"
+        );
+        assert!(StateDump::new(Vec::new(), &sim, 100, &[], 1 << 20).is_ok());
+    }
+}
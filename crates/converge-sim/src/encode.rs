@@ -0,0 +1,314 @@
+//! Dataset encoders for `stimulus <layer> = Encode(...)`. Each row of a CSV
+//! file holds one value per neuron and is presented for `present`. Without an
+//! `experiment` the rows run once in file order; an experiment's [`Plan`]
+//! adds epochs, shuffling and gaps between rows. Values are scaled by the
+//! largest value in the file, so the encoders see them in `[0, 1]`.
+
+use std::path::{Component, Path};
+
+use converge_lang::ast::{EncodeScheme, Encoder};
+use converge_lang::units::{rate_to_hz, time_to_nanos};
+
+use crate::{Rng, SimError, SimErrorKind, unit_err};
+
+/// How rows are ordered over a run: `epochs` passes over the file, each in a
+/// fresh seeded shuffle when `shuffle_seed` is set, with `isi_steps` of
+/// silence after every row.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Plan {
+    pub(crate) epochs: usize,
+    pub(crate) isi_steps: usize,
+    pub(crate) shuffle_seed: Option<u64>,
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Self {
+            epochs: 1,
+            isi_steps: 0,
+            shuffle_seed: None,
+        }
+    }
+}
+
+/// Which row is shown when. Slot `k` starts at step `k * slot_steps`, shows
+/// row `order[k]` for `present_steps`, then stays silent for the rest of
+/// the slot. After the last slot the layer gets no input.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Schedule {
+    order: Vec<usize>,
+    present_steps: usize,
+    slot_steps: usize,
+}
+
+impl Schedule {
+    fn new(rows: usize, present_steps: usize, plan: &Plan) -> Self {
+        let mut rng = plan.shuffle_seed.map(Rng::new);
+        let mut order = Vec::with_capacity(rows * plan.epochs);
+        for _ in 0..plan.epochs {
+            let mut epoch: Vec<usize> = (0..rows).collect();
+            if let Some(rng) = &mut rng {
+                // Fisher-Yates, drawing from the end.
+                for i in (1..rows).rev() {
+                    let j = (rng.next_f64() * (i + 1) as f64) as usize;
+                    epoch.swap(i, j);
+                }
+            }
+            order.extend(epoch);
+        }
+        Self {
+            order,
+            present_steps,
+            slot_steps: present_steps + plan.isi_steps,
+        }
+    }
+
+    /// The slot covering `step`, if the schedule has not ended.
+    pub(crate) fn slot(&self, step: usize) -> Option<usize> {
+        let slot = step / self.slot_steps;
+        (slot < self.order.len()).then_some(slot)
+    }
+
+    /// The row shown in `slot`.
+    pub(crate) fn sample(&self, slot: usize) -> usize {
+        self.order[slot]
+    }
+
+    /// Number of slots.
+    pub(crate) fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Number of slots that have ended within the first `steps` steps.
+    pub(crate) fn finished(&self, steps: usize) -> usize {
+        self.order.len().min(steps / self.slot_steps)
+    }
+
+    /// Number of slots that have started within the first `steps` steps.
+    pub(crate) fn started(&self, steps: usize) -> usize {
+        self.order.len().min(steps.div_ceil(self.slot_steps))
+    }
+
+    /// Steps until the last slot ends.
+    pub(crate) fn steps(&self) -> usize {
+        self.order.len() * self.slot_steps
+    }
+
+    /// The row shown at `step`, if any, and how many steps it has been up.
+    fn row(&self, step: usize) -> Option<(usize, usize)> {
+        let slot = self.slot(step)?;
+        let offset = step % self.slot_steps;
+        (offset < self.present_steps).then(|| (self.order[slot], offset))
+    }
+}
+
+/// A loaded file and its schedule.
+#[derive(Clone)]
+struct Presentation {
+    rows: Vec<Vec<f64>>,
+    schedule: Schedule,
+}
+
+impl Presentation {
+    fn load(
+        enc: &Encoder,
+        size: usize,
+        step_ns: i64,
+        files: DataFiles<'_>,
+        plan: &Plan,
+    ) -> Result<Self, SimError> {
+        let present_ns = time_to_nanos(&enc.present, "presentation time").map_err(unit_err)?;
+        if present_ns <= 0 || present_ns % step_ns != 0 {
+            return Err(SimError::new(SimErrorKind::Invalid(
+                "presentation time must be a positive multiple of the step".to_string(),
+            ))
+            .with_span(enc.present.span.clone()));
+        }
+        let rows = read_data(&enc.path, files, |text| parse_csv(text, size))?;
+        let schedule = Schedule::new(rows.len(), (present_ns / step_ns) as usize, plan);
+        Ok(Self { rows, schedule })
+    }
+
+    /// The row shown at `step`, if any, and how many steps it has been up.
+    fn row(&self, step: usize) -> Option<(&[f64], usize)> {
+        let (row, offset) = self.schedule.row(step)?;
+        Some((&self.rows[row], offset))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Scheme {
+    Rate { max_hz: f64 },
+    Latency,
+}
+
+/// One `Encode` stimulus, ready to drive its layer.
+#[derive(Clone)]
+pub(crate) struct EncodedInput {
+    presentation: Presentation,
+    scheme: Scheme,
+}
+
+impl EncodedInput {
+    pub(crate) fn build(
+        enc: &Encoder,
+        size: usize,
+        step_ns: i64,
+        files: DataFiles<'_>,
+        plan: &Plan,
+    ) -> Result<Self, SimError> {
+        let scheme = match &enc.scheme {
+            EncodeScheme::Rate { max_rate } => Scheme::Rate {
+                max_hz: rate_to_hz(max_rate, "max_rate").map_err(unit_err)?,
+            },
+            EncodeScheme::Latency => Scheme::Latency,
+        };
+        Ok(Self {
+            presentation: Presentation::load(enc, size, step_ns, files, plan)?,
+            scheme,
+        })
+    }
+
+    pub(crate) fn schedule(&self) -> &Schedule {
+        &self.presentation.schedule
+    }
+
+    /// Whether this input is drawn as Poisson spikes, consuming RNG values.
+    pub(crate) fn is_poisson(&self) -> bool {
+        matches!(self.scheme, Scheme::Rate { .. })
+    }
+
+    /// Poisson rate of neuron `i` at `step`, in Hz.
+    pub(crate) fn rate_hz(&self, step: usize, i: usize) -> f64 {
+        match (self.scheme, self.presentation.row(step)) {
+            (Scheme::Rate { max_hz }, Some((row, _))) => row[i] * max_hz,
+            _ => 0.0,
+        }
+    }
+
+    /// Whether neuron `i` gets its latency-coded spike at `step`. A value of
+    /// 1 spikes on the first step of the presentation, values near 0 on the
+    /// last, and 0 not at all.
+    pub(crate) fn fires(&self, step: usize, i: usize) -> bool {
+        match (self.scheme, self.presentation.row(step)) {
+            (Scheme::Latency, Some((row, offset))) if row[i] > 0.0 => {
+                let last = (self.presentation.schedule.present_steps - 1) as f64;
+                ((1.0 - row[i]) * last).round() as usize == offset
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Where the data files a program names are read from.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DataFiles<'a> {
+    /// Relative paths resolve here; against the working directory when
+    /// `None`.
+    pub(crate) dir: Option<&'a Path>,
+    /// See [`crate::SimConfig::confine_data`].
+    pub(crate) confined: bool,
+}
+
+/// Reads a data file named by the program, resolving relative paths against
+/// `files.dir`, and parses it with `parse`, whose errors are prefixed with
+/// the path.
+pub(crate) fn read_data<T>(
+    path: &str,
+    files: DataFiles<'_>,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<T, SimError> {
+    if files.confined {
+        return read_confined(path, files.dir, parse);
+    }
+    let path = match files.dir {
+        Some(dir) => dir.join(path),
+        None => Path::new(path).to_path_buf(),
+    };
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| SimError::io(format!("failed to read `{}`", path.display()), e))?;
+    parse(&text).map_err(|message| {
+        SimError::new(SimErrorKind::BadData {
+            path: path.display().to_string(),
+            message,
+        })
+    })
+}
+
+/// [`read_data`] inside `dir` only. Every failure, from a path outside it to
+/// a malformed row, is the same error naming the path as written, so it
+/// tells a client nothing about the files on the server.
+fn read_confined<T>(
+    path: &str,
+    dir: Option<&Path>,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<T, SimError> {
+    let refused = || {
+        SimError::new(SimErrorKind::DataFileRefused {
+            path: path.to_string(),
+        })
+    };
+    let relative = Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    let Some(dir) = dir.filter(|_| relative && !path.is_empty()) else {
+        return Err(refused());
+    };
+    // Resolved, so a symlink can't lead out of `dir` either.
+    let dir = dir.canonicalize().map_err(|_| refused())?;
+    let file = dir.join(path).canonicalize().map_err(|_| refused())?;
+    if !file.starts_with(&dir) {
+        return Err(refused());
+    }
+    let text = std::fs::read_to_string(&file).map_err(|_| refused())?;
+    parse(&text).map_err(|_| refused())
+}
+
+/// Parses rows of `width` non-negative numbers and scales them by the
+/// largest one. Blank lines and `#` comments are skipped, and so is a first
+/// row that is not numeric, taken to be a header.
+fn parse_csv(text: &str, width: usize) -> Result<Vec<Vec<f64>>, String> {
+    let mut rows: Vec<Vec<f64>> = Vec::new();
+    let mut first = true;
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let header = std::mem::take(&mut first);
+        let parsed: Result<Vec<f64>, &str> = line
+            .split(',')
+            .map(str::trim)
+            .map(|field| field.parse().map_err(|_| field))
+            .collect();
+        let values = match parsed {
+            Ok(values) => values,
+            Err(_) if header => continue,
+            Err(field) => return Err(format!("line {}: `{field}` is not a number", idx + 1)),
+        };
+        if let Some(v) = values.iter().find(|v| !v.is_finite() || **v < 0.0) {
+            return Err(format!(
+                "line {}: values must be finite and non-negative, found {v}",
+                idx + 1
+            ));
+        }
+        if values.len() != width {
+            return Err(format!(
+                "line {}: expected {width} values, found {}",
+                idx + 1,
+                values.len()
+            ));
+        }
+        rows.push(values);
+    }
+    if rows.is_empty() {
+        return Err("no data rows".to_string());
+    }
+    let max = rows.iter().flatten().fold(0.0_f64, |m, &v| m.max(v));
+    if max > 0.0 {
+        for v in rows.iter_mut().flatten() {
+            *v /= max;
+        }
+    }
+    Ok(rows)
+}
@@ -0,0 +1,187 @@
+//! Energy and traffic estimates for a run, on the cores of a target profile.
+//!
+//! The network is placed as by `converge place`, with cores on a 2-D mesh
+//! of `router.mesh_width` columns, numbered row by row. Every neuron is
+//! updated once per step, every spike is one event at each of its chemical
+//! synapses, and a spike is sent once to each other core it has synapses
+//! on, crossing the Manhattan distance between the cores in router hops.
+//! Each count is priced at the profile's `energy` cost in picojoules.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use converge_lang::ast::{ConnectKind, Program};
+use converge_lang::place::place;
+use converge_lang::target::TargetProfile;
+
+use crate::{Connection, LayerState, SimError, to_err};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyReport {
+    pub target: String,
+    pub cores: usize,
+    pub neuron_updates: u64,
+    pub synaptic_events: u64,
+    /// Spikes sent from one core to another.
+    pub spike_messages: u64,
+    pub router_hops: u64,
+    /// Per-event costs in pJ, 0 where the profile has none.
+    pub neuron_update_pj: f64,
+    pub synaptic_event_pj: f64,
+    pub router_hop_pj: f64,
+}
+
+impl EnergyReport {
+    pub fn neuron_energy_pj(&self) -> f64 {
+        self.neuron_updates as f64 * self.neuron_update_pj
+    }
+
+    pub fn synaptic_energy_pj(&self) -> f64 {
+        self.synaptic_events as f64 * self.synaptic_event_pj
+    }
+
+    pub fn router_energy_pj(&self) -> f64 {
+        self.router_hops as f64 * self.router_hop_pj
+    }
+
+    pub fn total_pj(&self) -> f64 {
+        self.neuron_energy_pj() + self.synaptic_energy_pj() + self.router_energy_pj()
+    }
+}
+
+impl fmt::Display for EnergyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "energy on {} ({} cores)", self.target, self.cores)?;
+        let rows = [
+            ("neuron updates", self.neuron_updates, self.neuron_update_pj),
+            (
+                "synaptic events",
+                self.synaptic_events,
+                self.synaptic_event_pj,
+            ),
+            ("router hops", self.router_hops, self.router_hop_pj),
+        ];
+        for (name, count, pj) in rows {
+            writeln!(
+                f,
+                "  {name:<16} {count:>12} x {pj:>8} pJ = {:>10}",
+                format_energy(count as f64 * pj)
+            )?;
+        }
+        writeln!(
+            f,
+            "  {:<16} {:>39}",
+            "total",
+            format_energy(self.total_pj())
+        )?;
+        write!(
+            f,
+            "  inter-core traffic: {} spike messages over {} hops",
+            self.spike_messages, self.router_hops
+        )
+    }
+}
+
+/// Formats picojoules with a unit that keeps the number readable.
+pub fn format_energy(pj: f64) -> String {
+    const UNITS: [&str; 5] = ["pJ", "nJ", "uJ", "mJ", "J"];
+    let mut value = pj;
+    let mut unit = 0;
+    while value.abs() >= 1000.0 && unit + 1 < UNITS.len() {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{value:.3} {}", UNITS[unit])
+}
+
+/// Per-neuron spike counts and the core of every neuron.
+#[derive(Clone)]
+pub(crate) struct EnergyMeter {
+    profile: TargetProfile,
+    cores: usize,
+    /// Core of each neuron, per layer.
+    core_of: Vec<Vec<usize>>,
+    spikes: Vec<Vec<u64>>,
+}
+
+impl EnergyMeter {
+    pub(crate) fn build(
+        program: &Program,
+        profile: &TargetProfile,
+        layers: &[LayerState],
+    ) -> Result<Self, SimError> {
+        let placement = place(program, profile).map_err(to_err)?;
+        let mut core_of: Vec<Vec<usize>> = layers.iter().map(|l| vec![0; l.size]).collect();
+        for (c, core) in placement.cores.iter().enumerate() {
+            for slice in &core.slices {
+                let Some(layer) = layers.iter().position(|l| l.name == slice.layer) else {
+                    continue;
+                };
+                core_of[layer][slice.start as usize..slice.end as usize].fill(c);
+            }
+        }
+        Ok(Self {
+            profile: profile.clone(),
+            cores: placement.cores.len(),
+            core_of,
+            spikes: layers.iter().map(|l| vec![0; l.size]).collect(),
+        })
+    }
+
+    pub(crate) fn record(&mut self, spiked: &[Vec<usize>]) {
+        for (counts, spiked) in self.spikes.iter_mut().zip(spiked) {
+            for &i in spiked {
+                counts[i] += 1;
+            }
+        }
+    }
+
+    pub(crate) fn report(&self, connections: &[Connection], steps: usize) -> EnergyReport {
+        let width = match self.profile.mesh_width {
+            Some(width) => width as usize,
+            None => (1..).find(|w| w * w >= self.cores).unwrap_or(1),
+        };
+        let hops = |a: usize, b: usize| {
+            let (ax, ay) = (a % width, a / width);
+            let (bx, by) = (b % width, b / width);
+            (ax.abs_diff(bx) + ay.abs_diff(by)) as u64
+        };
+        let neurons: usize = self.spikes.iter().map(Vec::len).sum();
+        let mut synaptic_events = 0u64;
+        let mut spike_messages = 0u64;
+        let mut router_hops = 0u64;
+        for (layer, counts) in self.spikes.iter().enumerate() {
+            let outgoing: Vec<&Connection> = connections
+                .iter()
+                .filter(|c| c.src_layer == layer && c.kind == ConnectKind::Chemical)
+                .collect();
+            for (i, &count) in counts.iter().enumerate() {
+                if count == 0 {
+                    continue;
+                }
+                let home = self.core_of[layer][i];
+                let mut targets = BTreeSet::new();
+                for conn in &outgoing {
+                    for syn in conn.row(i) {
+                        synaptic_events += count;
+                        targets.insert(self.core_of[conn.dst_layer][syn.dst]);
+                    }
+                }
+                targets.remove(&home);
+                spike_messages += count * targets.len() as u64;
+                router_hops += count * targets.iter().map(|&c| hops(home, c)).sum::<u64>();
+            }
+        }
+        EnergyReport {
+            target: self.profile.name.clone(),
+            cores: self.cores,
+            neuron_updates: (neurons * steps) as u64,
+            synaptic_events,
+            spike_messages,
+            router_hops,
+            neuron_update_pj: self.profile.neuron_update_pj.unwrap_or(0.0),
+            synaptic_event_pj: self.profile.synaptic_event_pj.unwrap_or(0.0),
+            router_hop_pj: self.profile.router_hop_pj.unwrap_or(0.0),
+        }
+    }
+}
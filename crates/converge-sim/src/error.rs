@@ -0,0 +1,335 @@
+//! What can go wrong building or running a simulation. [`SimErrorKind`]
+//! says what happened, with the values involved, so callers can match on
+//! it; [`SimError`] adds where in the model it happened, when it comes from
+//! one item or value.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use converge_lang::diagnostic::{Diagnostic, Span};
+
+#[derive(Debug)]
+pub struct SimError {
+    pub kind: SimErrorKind,
+    /// The part of the model the error is about, when it comes from one
+    /// item or value, such as the `d = ...` of a connection whose delay is
+    /// negative.
+    pub span: Option<Span>,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SimErrorKind {
+    /// The program has no `run` statement.
+    MissingRun,
+    /// A quantity in the wrong unit, or a number where a quantity is
+    /// needed.
+    BadUnit(String),
+    /// The run's duration or step is not positive, or the duration is not
+    /// a whole number of steps.
+    BadRunTiming {
+        duration_ns: i64,
+        step_ns: i64,
+    },
+    /// A synaptic delay, as drawn, is below zero.
+    NegativeDelay {
+        delay_ns: f64,
+    },
+    /// A synaptic delay that is not a whole number of steps.
+    UnevenDelay {
+        delay_ns: i64,
+        step_ns: i64,
+    },
+    /// A Poisson stimulus on `layer` would need more than one spike per
+    /// neuron per step.
+    RateTooHigh {
+        layer: String,
+    },
+    /// A name that is not a layer of the network. `role` is how the layer
+    /// was used, such as `stimulus` or `source`, or empty.
+    UnknownLayer {
+        role: &'static str,
+        name: String,
+    },
+    UnknownNeuron(String),
+    UnknownModulator(String),
+    /// A neuron, connection, modulator or learning rule parameter that is
+    /// unknown, missing or out of range.
+    InvalidParameter(String),
+    /// Probe `probe` reads neurons `first..end` of `layer`, which only has
+    /// `size`.
+    ProbeOutOfRange {
+        probe: String,
+        layer: String,
+        first: u64,
+        end: u64,
+        size: usize,
+    },
+    /// A `readout` in a program without an `experiment`.
+    ReadoutWithoutExperiment,
+    /// An epoch count for a program without an `experiment`.
+    EpochsWithoutExperiment,
+    /// An `experiment` with no `Encode` stimulus to present.
+    ExperimentWithoutEncode,
+    /// The `Encode` files of an experiment have different row counts.
+    UnevenEncodeRows,
+    /// A sweep of a program whose `experiment` has no runs.
+    SweepWithoutRuns,
+    /// A `readout` whose label count isn't the dataset's row count.
+    LabelCountMismatch {
+        labels: usize,
+        rows: usize,
+    },
+    /// Training without a `readout` that has labels.
+    TrainingWithoutLabels,
+    /// Training a network with no connection into the readout layer and no
+    /// `plasticity`.
+    NothingToTrain,
+    /// An `Encode` dataset or weight file outside the directory data is
+    /// confined to, or one that can't be read. The message never includes
+    /// the file's contents.
+    DataFileRefused {
+        path: String,
+    },
+    /// Any other part of the model the simulator can't build, such as an
+    /// `isi` or probe interval that isn't a whole number of steps.
+    Invalid(String),
+    /// A feature the chosen arithmetic, storage or code generator doesn't
+    /// support.
+    Unsupported(String),
+    /// A layer index past the end of the network.
+    LayerIndexOutOfRange {
+        index: usize,
+    },
+    /// `got` values for `layer`, which has `expected` neurons. `what` says
+    /// where they came from, such as `currents`.
+    LayerSizeMismatch {
+        layer: String,
+        expected: usize,
+        got: u64,
+        what: &'static str,
+    },
+    /// An injected current that is NaN or infinite.
+    NonFiniteCurrent {
+        value: f64,
+    },
+    /// A snapshot taken from another network.
+    ForeignSnapshot,
+    /// An input recording made with another step than the run's.
+    ReplayStepMismatch {
+        recorded_ns: i64,
+        run_ns: i64,
+    },
+    /// An input recording shorter than the run.
+    ReplayTooShort {
+        recorded_steps: usize,
+        steps: usize,
+    },
+    /// Correlations asked of a run that didn't record its spikes.
+    MissingRaster,
+    /// Any other request from the caller that doesn't fit the network, such
+    /// as runs with different steps to compare.
+    InvalidInput(String),
+    /// The allocator refused `bytes` for the network, such as the synapse
+    /// rows of a huge dense connection or the delivery queues of a very long
+    /// delay.
+    OutOfMemory {
+        bytes: u64,
+    },
+    /// A state dump would take more than `limit` bytes.
+    DumpTooLarge {
+        bytes: u64,
+        limit: u64,
+    },
+    /// A data file the model reads, such as an `Encode` dataset or a
+    /// weight file, has malformed contents.
+    BadData {
+        path: String,
+        message: String,
+    },
+    /// The controller behind an `External` stimulus misbehaved.
+    External {
+        port: u16,
+        message: String,
+    },
+    /// Reading, writing or listening failed; `context` says on what.
+    Io {
+        context: String,
+        source: io::Error,
+    },
+    /// A run of an `experiment` sweep failed.
+    InRun {
+        run: String,
+        seed: u64,
+        error: Box<SimError>,
+    },
+}
+
+impl SimError {
+    pub fn new(kind: SimErrorKind) -> Self {
+        Self { kind, span: None }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// [`Self::with_span`], unless the error already points somewhere more
+    /// precise.
+    pub(crate) fn or_span(self, span: Span) -> Self {
+        match self.span {
+            Some(_) => self,
+            None => self.with_span(span),
+        }
+    }
+
+    /// An [`SimErrorKind::Io`] error.
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        Self::new(SimErrorKind::Io {
+            context: context.into(),
+            source,
+        })
+    }
+
+    /// The error as a [`Diagnostic`], for
+    /// [`converge_lang::parser::format_diagnostic`] to show against the
+    /// source.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            message: self.to_string(),
+            span: self.span.clone(),
+        }
+    }
+}
+
+impl From<SimErrorKind> for SimError {
+    fn from(kind: SimErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl Error for SimError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            SimErrorKind::Io { source, .. } => Some(source),
+            SimErrorKind::InRun { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SimErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingRun => f.write_str("missing run statement"),
+            Self::BadRunTiming {
+                duration_ns,
+                step_ns,
+            } => {
+                if *duration_ns <= 0 || *step_ns <= 0 {
+                    f.write_str("duration and step must be positive")
+                } else {
+                    f.write_str("duration must be divisible by step")
+                }
+            }
+            Self::NegativeDelay { .. } => f.write_str("negative delay is not allowed"),
+            Self::UnevenDelay { .. } => f.write_str("delay must be divisible by step"),
+            Self::RateTooHigh { layer } => {
+                write!(f, "stimulus rate for `{layer}` too high for step")
+            }
+            Self::UnknownLayer { role: "", name } => write!(f, "unknown layer `{name}`"),
+            Self::UnknownLayer { role, name } => write!(f, "unknown {role} layer `{name}`"),
+            Self::UnknownNeuron(name) => write!(f, "unknown neuron type `{name}`"),
+            Self::UnknownModulator(name) => write!(f, "unknown modulator `{name}`"),
+            Self::BadUnit(message)
+            | Self::InvalidParameter(message)
+            | Self::Invalid(message)
+            | Self::Unsupported(message)
+            | Self::InvalidInput(message) => f.write_str(message),
+            Self::ProbeOutOfRange {
+                probe,
+                layer,
+                first,
+                end,
+                size,
+            } => write!(
+                f,
+                "probe `{probe}` reads neurons {first}..{end} of `{layer}`, which has {size}"
+            ),
+            Self::ReadoutWithoutExperiment => {
+                f.write_str("a `readout` needs an `experiment` to present samples")
+            }
+            Self::EpochsWithoutExperiment => f.write_str("an epoch count needs an `experiment`"),
+            Self::ExperimentWithoutEncode => {
+                f.write_str("an experiment needs an `Encode` stimulus to present")
+            }
+            Self::UnevenEncodeRows => {
+                f.write_str("every `Encode` file in an experiment needs the same number of rows")
+            }
+            Self::SweepWithoutRuns => f.write_str("the program has no `experiment` with runs"),
+            Self::LabelCountMismatch { labels, rows } => write!(
+                f,
+                "readout has {labels} label(s) but the dataset has {rows} row(s)"
+            ),
+            Self::TrainingWithoutLabels => f.write_str("training needs a `readout` with labels"),
+            Self::NothingToTrain => f.write_str(
+                "nothing to train: no connection into the readout layer and no `plasticity`",
+            ),
+            Self::DataFileRefused { path } => write!(f, "cannot read data file `{path}`"),
+            Self::LayerIndexOutOfRange { index } => write!(f, "no layer {index}"),
+            Self::LayerSizeMismatch {
+                layer,
+                expected,
+                got,
+                what,
+            } => write!(
+                f,
+                "layer `{layer}` has {expected} neurons, got {got} {what}"
+            ),
+            Self::NonFiniteCurrent { value } => {
+                write!(f, "injected current {value} is not finite")
+            }
+            Self::ForeignSnapshot => f.write_str("snapshot is from a different network"),
+            Self::ReplayStepMismatch {
+                recorded_ns,
+                run_ns,
+            } => write!(
+                f,
+                "the input recording steps by {recorded_ns} ns, the run by {run_ns} ns"
+            ),
+            Self::ReplayTooShort {
+                recorded_steps,
+                steps,
+            } => write!(
+                f,
+                "the input recording covers {recorded_steps} steps, the run needs {steps}"
+            ),
+            Self::MissingRaster => {
+                f.write_str("correlations need the spike raster; set `SimConfig::record_spikes`")
+            }
+            Self::OutOfMemory { bytes } => write!(
+                f,
+                "out of memory allocating {} for the network",
+                crate::format_bytes(*bytes)
+            ),
+            Self::DumpTooLarge { bytes, limit } => write!(
+                f,
+                "the state dump would take up to {}, over the {} limit",
+                crate::format_bytes(*bytes),
+                crate::format_bytes(*limit)
+            ),
+            Self::BadData { path, message } => write!(f, "`{path}`: {message}"),
+            Self::External { port, message } => write!(f, "`External` on port {port}: {message}"),
+            Self::Io { context, source } => write!(f, "{context}: {source}"),
+            Self::InRun { run, seed, error } => write!(f, "{run}: seed {seed}: {error}"),
+        }
+    }
+}
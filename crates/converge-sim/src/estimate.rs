@@ -0,0 +1,497 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem::size_of;
+
+use converge_lang::ast::{ConnectDef, ConnectKind, Item, Program};
+use converge_lang::diagnostic::Diagnostic;
+
+use crate::plasticity::DelayStdp;
+use crate::{
+    Dist, LayerQueue, SimConfig, SimError, SimErrorKind, Synapse, collect_neuron_defs, find_dist,
+    lif_params, run_timing, to_err,
+};
+
+/// Queues at least this large draw a warning when a delay distribution's
+/// tail, rather than its typical delay, is what sizes them.
+const LONG_TAIL_BYTES: u64 = 1 << 20;
+
+/// Pre-run cost of a program, computed from the AST without building the
+/// network. Byte counts follow the same accounting as
+/// [`Profile::network_bytes`](crate::Profile::network_bytes), so for programs
+/// with constant delays the estimate matches a profiled run exactly.
+/// Synapses removed by `disconnect` are still counted, so the estimate is an
+/// upper bound for programs that use it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub layers: usize,
+    pub neurons: u64,
+    pub connections: usize,
+    pub synapses: u64,
+    pub steps: u64,
+    /// Longest delay in steps. Normal delays are bounded at mean + 6 sigma.
+    pub max_delay_steps: u64,
+    pub state_bytes: u64,
+    /// Synapse tables, plus learning state for plastic connections.
+    pub synapse_bytes: u64,
+    pub queue_bytes: u64,
+    /// `queue_bytes` by destination layer, in declaration order.
+    pub layer_queues: Vec<LayerQueue>,
+    /// Delay distributions whose tail sizes the queues at least ten times
+    /// larger than their mean would, when that comes to a mebibyte or
+    /// more: every queue holds as many steps as the longest delay drawn.
+    pub warnings: Vec<Diagnostic>,
+    /// Neuron updates over the whole run.
+    pub neuron_updates: u64,
+    /// Synaptic deliveries over the whole run if every neuron fired every
+    /// step; real runs deliver a fraction of this proportional to firing rate.
+    pub max_synaptic_events: u64,
+}
+
+impl Estimate {
+    pub fn total_bytes(&self) -> u64 {
+        self.state_bytes
+            .saturating_add(self.synapse_bytes)
+            .saturating_add(self.queue_bytes)
+    }
+}
+
+pub fn estimate(program: &Program, config: &SimConfig) -> Result<Estimate, SimError> {
+    let program = &*program.select_network(None).map_err(to_err)?;
+    let timing = run_timing(program)?;
+
+    let neuron_defs = collect_neuron_defs(program)?;
+    let mut sizes = HashMap::new();
+    // Potentials per layer: the soma plus every dendritic compartment.
+    let mut layer_compartments = Vec::new();
+    for item in &program.items {
+        if let Item::Layer(def) = item {
+            sizes.insert(def.name.name.as_str(), def.size);
+            let dendrites = match neuron_defs.get(&def.neuron.name) {
+                Some(neuron) => lif_params(neuron)?.compartments as u64,
+                None => 0,
+            };
+            layer_compartments.push((&def.name.name, def.size.saturating_mul(1 + dendrites)));
+        }
+    }
+    let compartments = layer_compartments
+        .iter()
+        .fold(0u64, |sum, (_, n)| sum.saturating_add(*n));
+    let neurons: u64 = sizes.values().sum();
+    let layer_size = |name: &str| {
+        sizes.get(name).copied().ok_or_else(|| {
+            SimError::new(SimErrorKind::UnknownLayer {
+                role: "",
+                name: name.to_string(),
+            })
+        })
+    };
+
+    let mut connections = 0;
+    let mut synapses = 0u64;
+    let mut synapse_bytes = 0u64;
+    let mut max_delay_steps = 0u64;
+    // Distribution delays: the `d = ...`, and their mean and bound in steps.
+    let mut tails = Vec::new();
+    let mut coupled = HashSet::new();
+    for item in &program.items {
+        let Item::Connect(ConnectDef {
+            src,
+            dst,
+            kind,
+            body,
+            ..
+        }) = item
+        else {
+            continue;
+        };
+        let src_size = layer_size(&src.name)?;
+        let dst_size = layer_size(&dst.name)?;
+        let count = if *kind == ConnectKind::Electrical {
+            // Gap junctions need a scratch potential per coupled neuron, and
+            // couple each pair within one layer once.
+            coupled.insert(src.name.as_str());
+            coupled.insert(dst.name.as_str());
+            if src.name == dst.name {
+                src_size.saturating_mul(src_size.saturating_sub(1)) / 2
+            } else {
+                src_size.saturating_mul(dst_size)
+            }
+        } else {
+            src_size.saturating_mul(dst_size)
+        };
+        connections += 1;
+        synapses = synapses.saturating_add(count);
+        synapse_bytes = synapse_bytes
+            .saturating_add(src_size.saturating_mul(size_of::<Vec<Synapse>>() as u64))
+            .saturating_add(count.saturating_mul(size_of::<Synapse>() as u64));
+        if body.iter().any(|a| a.key.name == "plasticity") {
+            // R-STDP: a trace per source and destination neuron, and an
+            // eligibility trace per synapse.
+            let f64_bytes = size_of::<f64>() as u64;
+            synapse_bytes = synapse_bytes
+                .saturating_add(src_size.saturating_add(dst_size).saturating_mul(f64_bytes))
+                .saturating_add(src_size.saturating_mul(size_of::<Vec<f64>>() as u64))
+                .saturating_add(count.saturating_mul(f64_bytes));
+        }
+        if let Some(assign) = body.iter().find(|a| a.key.name == "delay_plasticity") {
+            // Delay STDP: a last spike step per source and destination
+            // neuron, and a shift per synapse; delays may grow to `d_max`.
+            let rule = DelayStdp::from_expr(&assign.value, timing.step_ns)
+                .map_err(|e| e.or_span(assign.span.clone()))?;
+            max_delay_steps = max_delay_steps.max(rule.d_max as u64);
+            synapse_bytes = synapse_bytes
+                .saturating_add(
+                    src_size
+                        .saturating_add(dst_size)
+                        .saturating_mul(size_of::<Option<usize>>() as u64),
+                )
+                .saturating_add(src_size.saturating_mul(size_of::<Vec<f64>>() as u64))
+                .saturating_add(count.saturating_mul(size_of::<f64>() as u64));
+        }
+
+        let dist = find_dist(body, "d", Some(timing.step_ns))?;
+        let (mean_ns, delay_ns) = match dist {
+            Dist::Const(d) => (d, d),
+            Dist::Uniform(a, b) => ((a + b) / 2.0, a.max(b)),
+            Dist::Normal(mu, sigma) => (mu, mu + 6.0 * sigma.abs()),
+        };
+        let to_steps = |ns: f64| (ns.max(0.0) / timing.step_ns as f64).ceil() as u64;
+        let delay_steps = to_steps(delay_ns);
+        max_delay_steps = max_delay_steps.max(delay_steps);
+        if !matches!(dist, Dist::Const(_))
+            && let Some(assign) = body.iter().find(|a| a.key.name == "d")
+        {
+            tails.push((assign, to_steps(mean_ns), delay_steps));
+        }
+    }
+
+    let f64_bytes = size_of::<f64>() as u64;
+    let queue_copies = if config.compensated_summation { 2 } else { 1 };
+    // Every layer keeps one bucket per step of the longest delay, plus one.
+    let bytes_for = |compartments: u64, delay_steps: u64| {
+        compartments
+            .saturating_mul(delay_steps.saturating_add(1))
+            .saturating_mul(f64_bytes * queue_copies)
+    };
+    let queue_bytes = bytes_for(compartments, max_delay_steps);
+    let layer_queues = layer_compartments
+        .iter()
+        .map(|(name, n)| LayerQueue {
+            layer: name.to_string(),
+            bytes: bytes_for(*n, max_delay_steps),
+        })
+        .collect();
+    let warnings = tails
+        .into_iter()
+        .filter(|&(_, mean, bound)| {
+            bound >= 10 * mean.max(1) && bytes_for(compartments, bound) >= LONG_TAIL_BYTES
+        })
+        .map(|(assign, mean, bound)| {
+            Diagnostic::new(format!(
+                "delays drawn from `{}` can reach {bound} steps against a mean of {mean}; \
+                 the delivery queues hold as many steps as the longest delay drawn, which \
+                 could take {} rather than {}",
+                assign.value,
+                format_bytes(bytes_for(compartments, bound)),
+                format_bytes(bytes_for(compartments, mean))
+            ))
+            .with_span(assign.span.clone())
+        })
+        .collect();
+    let steps = timing.steps as u64;
+
+    Ok(Estimate {
+        layers: sizes.len(),
+        neurons,
+        connections,
+        synapses,
+        steps,
+        max_delay_steps,
+        state_bytes: compartments
+            .saturating_add(coupled.iter().map(|name| sizes[name]).sum::<u64>())
+            .saturating_mul(f64_bytes),
+        synapse_bytes,
+        queue_bytes,
+        layer_queues,
+        warnings,
+        neuron_updates: steps.saturating_mul(neurons),
+        max_synaptic_events: steps.saturating_mul(synapses),
+    })
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "layers:          {}", self.layers)?;
+        writeln!(f, "neurons:         {}", self.neurons)?;
+        writeln!(
+            f,
+            "synapses:        {} in {} connection(s)",
+            self.synapses, self.connections
+        )?;
+        writeln!(f, "steps:           {}", self.steps)?;
+        writeln!(f, "max delay:       {} step(s)", self.max_delay_steps)?;
+        writeln!(f, "memory:")?;
+        writeln!(f, "  neuron state   {:>12}", format_bytes(self.state_bytes))?;
+        writeln!(
+            f,
+            "  synapses       {:>12}",
+            format_bytes(self.synapse_bytes)
+        )?;
+        writeln!(f, "  delay queues   {:>12}", format_bytes(self.queue_bytes))?;
+        for queue in &self.layer_queues {
+            writeln!(
+                f,
+                "    {:<12} {:>12}",
+                queue.layer,
+                format_bytes(queue.bytes)
+            )?;
+        }
+        writeln!(
+            f,
+            "  total          {:>12}",
+            format_bytes(self.total_bytes())
+        )?;
+        writeln!(f, "work:")?;
+        writeln!(f, "  neuron updates {:>12}", self.neuron_updates)?;
+        write!(
+            f,
+            "  deliveries     {:>12} (upper bound, every neuron firing every step)",
+            self.max_synaptic_events
+        )
+    }
+}
+
+pub fn estimate_json(estimate: &Estimate) -> String {
+    let fields: [(&str, u64); 12] = [
+        ("layers", estimate.layers as u64),
+        ("neurons", estimate.neurons),
+        ("connections", estimate.connections as u64),
+        ("synapses", estimate.synapses),
+        ("steps", estimate.steps),
+        ("max_delay_steps", estimate.max_delay_steps),
+        ("state_bytes", estimate.state_bytes),
+        ("synapse_bytes", estimate.synapse_bytes),
+        ("queue_bytes", estimate.queue_bytes),
+        ("total_bytes", estimate.total_bytes()),
+        ("neuron_updates", estimate.neuron_updates),
+        ("max_synaptic_events", estimate.max_synaptic_events),
+    ];
+    let mut s = String::new();
+    s.push_str("{\n");
+    for (name, value) in fields {
+        s.push_str(&format!("  \"{name}\": {value},\n"));
+    }
+    let queues: Vec<String> = estimate
+        .layer_queues
+        .iter()
+        .map(|q| format!("\"{}\": {}", q.layer, q.bytes))
+        .collect();
+    s.push_str(&format!(
+        "  \"layer_queue_bytes\": {{ {} }}\n}}\n",
+        queues.join(", ")
+    ));
+    s
+}
+
+/// Formats a byte count with binary units, e.g. `1.5 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate_with;
+    use converge_lang::parser::parse_program;
+
+    #[test]
+    fn estimate_matches_profiled_network() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms, v_th = 1.0 }
+layer A[10] : LIF
+layer B[4] : LIF
+connect A -> B { w = 0.5, d = 3 ms }
+stimulus A = Poisson(rate=50 Hz)
+run for 100 ms step 1 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            profile: true,
+            ..SimConfig::default()
+        };
+        let estimate = estimate(&program, &config).expect("estimate");
+        assert_eq!(estimate.neurons, 14);
+        assert_eq!(estimate.synapses, 40);
+        assert_eq!(estimate.steps, 100);
+        assert_eq!(estimate.max_delay_steps, 3);
+        assert_eq!(estimate.queue_bytes, 14 * 4 * 8);
+        assert!(estimate.warnings.is_empty());
+
+        let summary = simulate_with(&program, &config).expect("simulate");
+        assert_eq!(summary.queues.max_delay_steps, 3);
+        assert_eq!(summary.queues.layers, estimate.layer_queues);
+        assert_eq!(
+            estimate.layer_queues,
+            [
+                LayerQueue {
+                    layer: "A".to_string(),
+                    bytes: 10 * 4 * 8,
+                },
+                LayerQueue {
+                    layer: "B".to_string(),
+                    bytes: 4 * 4 * 8,
+                },
+            ]
+        );
+        let profile = summary.profile.expect("profile");
+        assert_eq!(estimate.total_bytes(), profile.network_bytes);
+        assert!(estimate_json(&estimate).contains("\"synapses\": 40,"));
+    }
+
+    #[test]
+    fn estimate_counts_gap_junctions() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms, v_th = 1.0 }
+layer A[5] : LIF
+layer B[3] : LIF
+connect A <-> A electrical { g = 0.1 }
+connect A <-> B electrical { g = Uniform(0.1, 0.2) }
+run for 10 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            profile: true,
+            ..SimConfig::default()
+        };
+        let estimate = estimate(&program, &config).expect("estimate");
+        assert_eq!(estimate.synapses, 10 + 15);
+        assert_eq!(estimate.state_bytes, (8 + 8) * 8);
+        let profile = simulate_with(&program, &config)
+            .expect("simulate")
+            .profile
+            .expect("profile");
+        assert_eq!(estimate.total_bytes(), profile.network_bytes);
+    }
+
+    #[test]
+    fn estimate_counts_dendritic_compartments() {
+        let src = r#"
+neuron Pyr { tau_m = 20 ms, compartments = 3 }
+layer A[4] : Pyr
+connect A -> A { w = 0.1, d = 2 ms, compartment = 3 }
+run for 10 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            profile: true,
+            ..SimConfig::default()
+        };
+        let estimate = estimate(&program, &config).expect("estimate");
+        assert_eq!(estimate.state_bytes, 4 * 4 * 8);
+        assert_eq!(estimate.queue_bytes, 4 * 4 * 3 * 8);
+        let profile = simulate_with(&program, &config)
+            .expect("simulate")
+            .profile
+            .expect("profile");
+        assert_eq!(estimate.total_bytes(), profile.network_bytes);
+    }
+
+    #[test]
+    fn estimate_counts_plasticity_state() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms }
+layer A[3] : LIF
+layer B[2] : LIF
+connect A -> B { w = 0.5, plasticity = RSTDP(lr = 0.1) }
+run for 10 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            profile: true,
+            ..SimConfig::default()
+        };
+        let estimate = estimate(&program, &config).expect("estimate");
+        let tables = 3 * size_of::<Vec<Synapse>>() as u64 + 6 * size_of::<Synapse>() as u64;
+        let learning = (3 + 2) * 8 + 3 * size_of::<Vec<f64>>() as u64 + 6 * 8;
+        assert_eq!(estimate.synapse_bytes, tables + learning);
+        let profile = simulate_with(&program, &config)
+            .expect("simulate")
+            .profile
+            .expect("profile");
+        assert_eq!(estimate.total_bytes(), profile.network_bytes);
+
+        let delays = src.replace(
+            "plasticity = RSTDP(lr = 0.1)",
+            "delay_plasticity = DelaySTDP(d_max = 4 ms)",
+        );
+        let program = parse_program(&delays).expect("parse");
+        let estimate = super::estimate(&program, &config).expect("estimate");
+        let learning =
+            (3 + 2) * size_of::<Option<usize>>() as u64 + 3 * size_of::<Vec<f64>>() as u64 + 6 * 8;
+        assert_eq!(estimate.synapse_bytes, tables + learning);
+        assert_eq!(estimate.max_delay_steps, 4);
+        let profile = simulate_with(&program, &config)
+            .expect("simulate")
+            .profile
+            .expect("profile");
+        assert_eq!(estimate.total_bytes(), profile.network_bytes);
+    }
+
+    #[test]
+    fn estimate_handles_huge_networks_without_allocating() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms }
+layer A[1000000] : LIF
+layer B[1000000] : LIF
+connect A -> B { w = 0.1, d = Uniform(1 ms, 5 ms) }
+run for 1 s
+"#;
+        let program = parse_program(src).expect("parse");
+        let estimate = estimate(&program, &SimConfig::default()).expect("estimate");
+        assert_eq!(estimate.synapses, 1_000_000_000_000);
+        assert_eq!(estimate.max_delay_steps, 5);
+        assert!(estimate.total_bytes() > 1 << 40);
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+    }
+
+    #[test]
+    fn estimate_warns_about_long_tailed_delays() {
+        let src = r#"
+neuron LIF { tau_m = 20 ms }
+layer A[100000] : LIF
+connect A -> A { w = 0.1, d = Normal(2 ms, 50 ms) }
+run for 1 s step 1 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let estimate = estimate(&program, &SimConfig::default()).expect("estimate");
+        assert_eq!(estimate.max_delay_steps, 302);
+        let [warning] = &estimate.warnings[..] else {
+            panic!("expected one warning: {:?}", estimate.warnings);
+        };
+        assert!(
+            warning
+                .message
+                .starts_with("delays drawn from `Normal(2 ms, 50 ms)` can reach 302 steps"),
+            "{}",
+            warning.message
+        );
+        assert!(
+            estimate_json(&estimate).ends_with("\"layer_queue_bytes\": { \"A\": 242400000 }\n}\n")
+        );
+
+        let narrow = src.replace("50 ms", "0.5 ms");
+        let program = parse_program(&narrow).expect("parse");
+        let estimate = super::estimate(&program, &SimConfig::default()).expect("estimate");
+        assert!(estimate.warnings.is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+//! `experiment { ... }`: how `Encode` rows are scheduled over a run,
+//! per-presentation spike counts of a readout layer, and `readout` scoring
+//! against dataset labels.
+
+use std::collections::HashMap;
+
+use converge_lang::ast::{ExperimentDef, Expr, Item, Program, ReadoutDef};
+use converge_lang::units::time_to_nanos;
+
+use crate::encode::{DataFiles, Plan, Schedule, read_data};
+use crate::{Classification, LayerState, SampleCounts, SimError, SimErrorKind, Stream, unit_err};
+
+pub(crate) struct Experiment {
+    pub(crate) plan: Plan,
+    pub(crate) readout: Option<usize>,
+}
+
+pub(crate) fn build_experiment(
+    program: &Program,
+    layer_index: &HashMap<String, usize>,
+    step_ns: i64,
+    seed: u64,
+) -> Result<Option<Experiment>, SimError> {
+    let Some(ExperimentDef { body, .. }) = program.items.iter().find_map(|item| match item {
+        Item::Experiment(def) => Some(def),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+    let mut plan = Plan::default();
+    let mut readout = None;
+    for assign in body {
+        match (assign.key.name.as_str(), &assign.value) {
+            ("epochs", Expr::Number(q)) if q.value >= 1.0 => plan.epochs = q.value as usize,
+            ("isi", Expr::Number(q)) => {
+                let isi_ns = time_to_nanos(q, "experiment `isi`").map_err(unit_err)?;
+                if isi_ns < 0 || isi_ns % step_ns != 0 {
+                    return Err(SimError::new(SimErrorKind::Invalid(
+                        "experiment `isi` must be a non-negative multiple of the step".to_string(),
+                    ))
+                    .with_span(q.span.clone()));
+                }
+                plan.isi_steps = (isi_ns / step_ns) as usize;
+            }
+            ("shuffle", Expr::Ident(id)) if id.name == "true" => {
+                plan.shuffle_seed = Some(Stream::Shuffle.seed(seed));
+            }
+            ("shuffle", Expr::Ident(id)) if id.name == "false" => plan.shuffle_seed = None,
+            ("readout", Expr::Ident(layer)) => {
+                readout = Some(*layer_index.get(&layer.name).ok_or_else(|| {
+                    SimError::new(SimErrorKind::UnknownLayer {
+                        role: "experiment readout",
+                        name: layer.name.clone(),
+                    })
+                    .with_span(layer.span.clone())
+                })?);
+            }
+            (key, _) => {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                    "invalid experiment parameter `{key}`"
+                )))
+                .with_span(assign.span.clone()));
+            }
+        }
+    }
+    Ok(Some(Experiment { plan, readout }))
+}
+
+/// Spike counts of the readout layer, one row per schedule slot. A slot
+/// counts spikes during its presentation and the gap after it.
+#[derive(Clone)]
+pub(crate) struct Readout {
+    pub(crate) layer: usize,
+    schedule: Schedule,
+    epochs: usize,
+    counts: Vec<Vec<u64>>,
+}
+
+impl Readout {
+    pub(crate) fn new(layer: usize, size: usize, schedule: Schedule, epochs: usize) -> Self {
+        Self {
+            layer,
+            counts: vec![vec![0; size]; schedule.len()],
+            schedule,
+            epochs,
+        }
+    }
+
+    pub(crate) fn record(&mut self, step: usize, spiked: &[usize]) {
+        if let Some(slot) = self.schedule.slot(step) {
+            for &i in spiked {
+                self.counts[slot][i] += 1;
+            }
+        }
+    }
+
+    /// Counts for every slot that has started within `steps`.
+    pub(crate) fn samples(&self, steps: usize) -> Vec<SampleCounts> {
+        let per_epoch = self.schedule.len() / self.epochs;
+        self.counts[..self.schedule.started(steps)]
+            .iter()
+            .enumerate()
+            .map(|(slot, counts)| SampleCounts {
+                epoch: (slot / per_epoch) as u32,
+                sample: self.schedule.sample(slot) as u64,
+                counts: counts.clone(),
+            })
+            .collect()
+    }
+}
+
+/// `readout <layer> labels "<file>"`: a winner-take-all prediction for every
+/// finished presentation, scored against the label of its dataset row.
+#[derive(Clone)]
+pub(crate) struct Classifier {
+    pub(crate) tally: Readout,
+    name: String,
+    labels: Vec<usize>,
+}
+
+impl Classifier {
+    pub(crate) fn build(
+        program: &Program,
+        layer_index: &HashMap<String, usize>,
+        layers: &[LayerState],
+        experiment: Option<(&Schedule, usize)>,
+        files: DataFiles<'_>,
+    ) -> Result<Option<Self>, SimError> {
+        let Some(def) = program.items.iter().find_map(|item| match item {
+            Item::Readout(def) => Some(def),
+            _ => None,
+        }) else {
+            return Ok(None);
+        };
+        let ReadoutDef {
+            layer,
+            labels,
+            rule,
+            ..
+        } = def;
+        if let Some(rule) = rule
+            && rule.name != "max_spikes"
+        {
+            return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                "unknown readout rule `{}`",
+                rule.name
+            )))
+            .with_span(rule.span.clone()));
+        }
+        let Some((schedule, epochs)) = experiment else {
+            return Err(SimError::new(SimErrorKind::ReadoutWithoutExperiment));
+        };
+        let idx = *layer_index.get(&layer.name).ok_or_else(|| {
+            SimError::new(SimErrorKind::UnknownLayer {
+                role: "readout",
+                name: layer.name.clone(),
+            })
+            .with_span(layer.span.clone())
+        })?;
+        let size = layers[idx].size;
+        let labels = read_data(labels, files, |text| parse_labels(text, size))?;
+        let rows = schedule.len() / epochs;
+        if labels.len() != rows {
+            return Err(SimError::new(SimErrorKind::LabelCountMismatch {
+                labels: labels.len(),
+                rows,
+            })
+            .with_span(def.span.clone()));
+        }
+        Ok(Some(Self {
+            tally: Readout::new(idx, size, schedule.clone(), epochs),
+            name: layer.name.clone(),
+            labels,
+        }))
+    }
+
+    pub(crate) fn schedule(&self) -> &Schedule {
+        &self.tally.schedule
+    }
+
+    /// Class index of every dataset row.
+    pub(crate) fn labels(&self) -> &[usize] {
+        &self.labels
+    }
+
+    /// Scores every presentation that has ended within `steps`. The neuron
+    /// with the most spikes is the prediction, ties going to the lowest
+    /// index; a presentation without readout spikes predicts nothing.
+    pub(crate) fn classification(&self, steps: usize) -> Classification {
+        let size = self.tally.counts.first().map_or(0, Vec::len);
+        let mut result = Classification {
+            layer: self.name.clone(),
+            presentations: 0,
+            correct: 0,
+            silent: 0,
+            confusion: vec![vec![0; size]; size],
+        };
+        let schedule = &self.tally.schedule;
+        for (slot, counts) in self.tally.counts[..schedule.finished(steps)]
+            .iter()
+            .enumerate()
+        {
+            let label = self.labels[schedule.sample(slot)];
+            result.presentations += 1;
+            let Some((predicted, &most)) = counts
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|&(_, count)| count)
+            else {
+                continue;
+            };
+            if most == 0 {
+                result.silent += 1;
+                continue;
+            }
+            result.confusion[label][predicted] += 1;
+            if predicted == label {
+                result.correct += 1;
+            }
+        }
+        result
+    }
+}
+
+/// One class index per dataset row, separated by commas, whitespace or line
+/// breaks. Blank lines and `#` comments are skipped, and so is a first line
+/// that is not numeric.
+fn parse_labels(text: &str, classes: usize) -> Result<Vec<usize>, String> {
+    let mut labels = Vec::new();
+    let mut first = true;
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let header = std::mem::take(&mut first);
+        let parsed: Result<Vec<usize>, &str> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .map(|field| field.parse().map_err(|_| field))
+            .collect();
+        match parsed {
+            Ok(values) => {
+                if let Some(label) = values.iter().find(|&&label| label >= classes) {
+                    return Err(format!(
+                        "line {}: label {label} is out of range for {classes} readout neuron(s)",
+                        idx + 1
+                    ));
+                }
+                labels.extend(values);
+            }
+            Err(_) if header => {}
+            Err(field) => {
+                return Err(format!("line {}: `{field}` is not a class index", idx + 1));
+            }
+        }
+    }
+    Ok(labels)
+}
@@ -0,0 +1,187 @@
+//! `External` stimuli: per-step input from a controller outside the
+//! simulator, for closed-loop experiments.
+//!
+//! The simulator listens on `127.0.0.1:<port>` when it is built and waits
+//! for one controller to connect before the first step. Every step it sends
+//! the controller a line `{"step":N,"t_ns":T}` and reads back one line of
+//! numbers separated by commas or whitespace: one per neuron, or a single
+//! value for the whole layer. With `input=rate` they are Poisson rates in
+//! Hz, added to the layer's other rates; with `input=current` they are added
+//! to the membrane potential. When no line arrives within `timeout`, or the
+//! controller has disconnected, `on_timeout` decides: `hold` reuses the
+//! last values received, `zero` uses zeros, and `error` fails the run. A
+//! reply that arrives late is used for the next step.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use converge_lang::ast::{ExternalInput, ExternalSignal, TimeoutPolicy};
+use converge_lang::units::time_to_nanos;
+
+use crate::{SimError, SimErrorKind, unit_err};
+
+pub(crate) struct ExternalSource {
+    port: u16,
+    input: ExternalSignal,
+    timeout: Duration,
+    policy: TimeoutPolicy,
+    listener: TcpListener,
+    controller: Option<Controller>,
+    /// Values for the current step.
+    values: Vec<f64>,
+    /// The last values the controller sent.
+    last: Vec<f64>,
+}
+
+struct Controller {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// A line read up to a timeout, completed by the next read.
+    partial: Vec<u8>,
+    closed: bool,
+}
+
+impl ExternalSource {
+    pub(crate) fn bind(ext: &ExternalInput, size: usize) -> Result<Self, SimError> {
+        let timeout_ns = time_to_nanos(&ext.timeout, "external timeout").map_err(unit_err)?;
+        let listener = TcpListener::bind(("127.0.0.1", ext.port)).map_err(|e| {
+            SimError::io(
+                format!("failed to listen on port {} for `External`", ext.port),
+                e,
+            )
+        })?;
+        Ok(Self {
+            port: ext.port,
+            input: ext.input,
+            timeout: Duration::from_nanos(timeout_ns.max(1) as u64),
+            policy: ext.on_timeout,
+            listener,
+            controller: None,
+            values: vec![0.0; size],
+            last: vec![0.0; size],
+        })
+    }
+
+    pub(crate) fn is_poisson(&self) -> bool {
+        self.input == ExternalSignal::Rate
+    }
+
+    /// Poisson rate of neuron `i` this step, in Hz.
+    pub(crate) fn rate_hz(&self, i: usize) -> f64 {
+        match self.input {
+            ExternalSignal::Rate => self.values[i],
+            ExternalSignal::Current => 0.0,
+        }
+    }
+
+    /// Membrane input of neuron `i` this step.
+    pub(crate) fn current(&self, i: usize) -> f64 {
+        match self.input {
+            ExternalSignal::Rate => 0.0,
+            ExternalSignal::Current => self.values[i],
+        }
+    }
+
+    /// Asks the controller for the values of `step` and waits for them.
+    pub(crate) fn receive(&mut self, step: usize, t_ns: i64) -> Result<(), SimError> {
+        let port = self.port;
+        let fail = |message: String| SimError::new(SimErrorKind::External { port, message });
+        if self.controller.is_none() {
+            let (stream, _) = self
+                .listener
+                .accept()
+                .map_err(|e| fail(format!("failed to accept a controller: {e}")))?;
+            let writer = stream
+                .try_clone()
+                .and_then(|writer| {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_nodelay(true)?;
+                    Ok(writer)
+                })
+                .map_err(|e| fail(e.to_string()))?;
+            self.controller = Some(Controller {
+                reader: BufReader::new(stream),
+                writer,
+                partial: Vec::new(),
+                closed: false,
+            });
+        }
+        let controller = self.controller.as_mut().expect("accepted above");
+        let line = controller.exchange(step, t_ns);
+        let missing = match line {
+            Some(line) => {
+                let values = parse_values(&line, self.values.len(), self.input)
+                    .map_err(|e| fail(format!("{e} at step {step}")))?;
+                self.values.copy_from_slice(&values);
+                self.last.copy_from_slice(&values);
+                return Ok(());
+            }
+            None if controller.closed => "the controller disconnected",
+            None => "no input within the timeout",
+        };
+        match self.policy {
+            TimeoutPolicy::Hold => self.values.copy_from_slice(&self.last),
+            TimeoutPolicy::Zero => self.values.fill(0.0),
+            TimeoutPolicy::Error => return Err(fail(format!("{missing} at step {step}"))),
+        }
+        Ok(())
+    }
+}
+
+impl Controller {
+    /// Sends the prompt for `step` and reads the reply, or `None` on a
+    /// timeout or once the controller is gone.
+    fn exchange(&mut self, step: usize, t_ns: i64) -> Option<String> {
+        if self.closed {
+            return None;
+        }
+        if writeln!(self.writer, "{{\"step\":{step},\"t_ns\":{t_ns}}}").is_err() {
+            self.closed = true;
+            return None;
+        }
+        match self.reader.read_until(b'\n', &mut self.partial) {
+            Ok(_) if self.partial.ends_with(b"\n") => {
+                let line = String::from_utf8_lossy(&self.partial).into_owned();
+                self.partial.clear();
+                Some(line)
+            }
+            // End of stream, possibly after an unterminated line.
+            Ok(_) => {
+                self.closed = true;
+                None
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                None
+            }
+            Err(_) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+}
+
+fn parse_values(line: &str, size: usize, input: ExternalSignal) -> Result<Vec<f64>, String> {
+    let values = line
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|field| !field.is_empty())
+        .map(|field| match field.parse::<f64>() {
+            Ok(v) if v.is_finite() => Ok(v),
+            _ => Err(format!("invalid value `{field}`")),
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+    if input == ExternalSignal::Rate && values.iter().any(|&v| v < 0.0) {
+        return Err("rates must not be negative".to_string());
+    }
+    match values.len() {
+        1 => Ok(vec![values[0]; size]),
+        n if n == size => Ok(values),
+        n => Err(format!("expected 1 or {size} values, got {n}")),
+    }
+}
@@ -0,0 +1,89 @@
+//! Q16.16 fixed-point arithmetic, as on typical digital neuromorphic cores.
+//!
+//! With [`Arithmetic::Fixed`], membrane potentials, thresholds, leak factors
+//! and synaptic input are signed 32-bit values with 16 fractional bits. The
+//! simulator keeps potentials in `f64` so views and traces stay unchanged,
+//! but every stored potential is a Q16.16 value and every update goes
+//! through the integer kernel below, so results match an integer
+//! implementation bit for bit.
+
+use std::fmt;
+
+/// Fractional bits of a Q16.16 value.
+pub const FRAC_BITS: u32 = 16;
+
+const ONE: i64 = 1 << FRAC_BITS;
+
+/// How the simulator does membrane arithmetic.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    #[default]
+    Float,
+    /// Q16.16 fixed point.
+    Fixed,
+}
+
+impl fmt::Display for Arithmetic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Float => write!(f, "float"),
+            Self::Fixed => write!(f, "q16.16"),
+        }
+    }
+}
+
+/// Scales a parameter to Q16.16, rounding half away from zero and
+/// saturating at the `i32` range.
+pub fn to_q16(x: f64) -> i32 {
+    (x * ONE as f64).round() as i32
+}
+
+pub fn from_q16(q: i32) -> f64 {
+    q as f64 / ONE as f64
+}
+
+/// Q16.16 product, rounded toward negative infinity like an arithmetic
+/// right shift, and saturating.
+pub fn mul_q16(a: i32, b: i32) -> i32 {
+    saturate((a as i64 * b as i64) >> FRAC_BITS)
+}
+
+/// The per-step leak factor `step / tau` in Q16.16, rounded to nearest
+/// using integer arithmetic only.
+pub fn decay_q16(step_ns: i64, tau_ns: i64) -> i32 {
+    let scaled = step_ns as i128 * ONE as i128;
+    saturate_i128((scaled + tau_ns as i128 / 2) / tau_ns as i128)
+}
+
+/// One leak step of a potential: `v - v * decay`. Input added since the
+/// last step is saturated into range first.
+pub(crate) fn leak(v: f64, decay: i32) -> i32 {
+    let q = to_q16(v);
+    saturate(q as i64 - mul_q16(q, decay) as i64)
+}
+
+fn saturate(x: i64) -> i32 {
+    x.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+fn saturate_i128(x: i128) -> i32 {
+    x.clamp(i32::MIN as i128, i32::MAX as i128) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_and_rounds_like_integer_hardware() {
+        assert_eq!(to_q16(1.0), 65_536);
+        assert_eq!(to_q16(-0.5), -32_768);
+        assert_eq!(to_q16(1e12), i32::MAX);
+        assert_eq!(from_q16(to_q16(0.25)), 0.25);
+        // 1 ms over 20 ms is 3276.8 LSBs, rounded up.
+        assert_eq!(decay_q16(1_000_000, 20_000_000), 3_277);
+        assert_eq!(mul_q16(-1, 1), -1);
+        assert_eq!(mul_q16(i32::MAX, i32::MAX), i32::MAX);
+        assert_eq!(leak(1.0, decay_q16(1_000_000, 20_000_000)), 65_536 - 3_277);
+    }
+}
@@ -1,18 +1,257 @@
+pub mod codegen;
+pub mod compare;
+mod correlation;
+mod defaults;
+mod detmath;
+mod dump;
+mod encode;
+mod energy;
+mod error;
+mod estimate;
+mod experiment;
+mod external;
+pub mod fixed;
+#[cfg(feature = "nwb")]
+mod hdf5;
+mod modulator;
+#[cfg(feature = "nwb")]
+pub mod nwb;
+mod plasticity;
+mod profile;
+mod quantize;
+pub mod raster;
+mod replay;
+mod scratch;
+mod sink;
+mod stream;
+mod sweep;
+mod trace;
+mod train;
+
+pub use correlation::{
+    Correlations, PairCorrelation, correlations, correlations_csv, correlations_json,
+};
+pub use defaults::AppliedDefault;
+pub use dump::{DEFAULT_DUMP_LIMIT, StateDump};
+pub use energy::{EnergyReport, format_energy};
+pub use error::{SimError, SimErrorKind};
+pub use estimate::{Estimate, estimate, estimate_json, format_bytes};
+pub use fixed::Arithmetic;
+pub use profile::{PhaseProfile, Profile};
+pub use quantize::Quantize;
+pub use sink::{
+    BinarySink, CsvSink, NdjsonSink, ProbeSample, RateSample, RecordSink, RunInfo, Sample,
+};
+pub use stream::{Stream, streams_json};
+pub use sweep::{
+    MetricStats, SweepGroup, SweepReport, SweepResult, find_sweep, run_sweep, sweep_json,
+};
+pub use train::{write_weights_csv, write_weights_pgm};
+
 use std::collections::HashMap;
 use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 
 use converge_lang::ast::{
-    Assign, CallArg, ConnectDef, Expr, Item, NeuronDef, Program, StimulusDef, StimulusModel,
+    AssertCond, AssertStmt, Assign, CallArg, ConnectDef, ConnectKind, Expr, Item, Metric,
+    NeuronDef, ProbeDef, ProbeVariable, Program, Quantity, RecordStmt, RewardStmt, StimulusCombine,
+    StimulusDef, StimulusModel, SynapseFilter,
 };
-use converge_lang::units::{rate_to_hz, time_to_nanos};
+use converge_lang::diagnostic::{Diagnostic, Span};
+use converge_lang::target::TargetProfile;
+use converge_lang::units::{assert_bound, delay_to_nanos, is_step_unit, rate_to_hz, time_to_nanos};
+
+use crate::encode::{DataFiles, EncodedInput, Plan};
+use crate::energy::EnergyMeter;
+use crate::experiment::{Classifier, Readout, build_experiment};
+use crate::external::ExternalSource;
+use crate::modulator::{Modulator, build_modulators, modulator_index, modulator_ref};
+use crate::plasticity::{DelayPlasticity, DelayStdp, Plasticity, RStdp};
+use crate::profile::{PhaseClock, SimPhase};
+use crate::quantize::{Grid, quantize_connections};
+use crate::raster::Recording;
+use crate::replay::InputReplay;
+use crate::scratch::{MappedRows, RowWriter};
+use crate::train::{Trainer, is_trained, load_weights};
 
 #[derive(Debug, Clone)]
 pub struct SimSummary {
     pub duration_ns: i64,
     pub step_ns: i64,
     pub seed: u64,
+    /// Steps actually run, fewer than the duration covers when the summary
+    /// is taken part way.
+    pub steps: u64,
+    /// The settings the run used.
+    pub config: RunConfig,
+    /// [`source_hash`] of the program text, filled in by callers that read
+    /// one.
+    pub input_hash: Option<u64>,
+    /// Wall-clock time from building the simulator to taking the summary;
+    /// `None` on targets without a clock.
+    pub wall_ns: Option<u64>,
+    /// Weight precision, for runs with `SimConfig::quantize`.
+    pub quantize: Option<Quantize>,
+    pub arithmetic: Arithmetic,
     pub total_spikes: u64,
+    pub spike_hash: u64,
     pub layers: Vec<LayerSummary>,
+    /// One entry per chemical connection, in declaration order.
+    pub connections: Vec<ConnectionSummary>,
+    /// Settings the model left out, with the values the run used.
+    pub defaults: Vec<AppliedDefault>,
+    pub queues: QueueSummary,
+    pub profile: Option<Profile>,
+    /// Every spike in emission order, when `SimConfig::record_spikes` is set.
+    pub raster: Option<Vec<Spike>>,
+    /// Every Poisson stimulus spike in draw order, when
+    /// `SimConfig::record_input` is set.
+    pub input: Option<Vec<Spike>>,
+    /// Membrane traces, when `SimConfig::trace_neurons` is nonzero.
+    pub voltages: Option<Vec<VoltageTrace>>,
+    /// One per `record rate` item, in source order.
+    pub rates: Vec<RateTrace>,
+    /// One per `probe` item, in source order.
+    pub probes: Vec<ProbeTrace>,
+    /// Readout spike counts per presentation, when an `experiment` names a
+    /// `readout` layer.
+    pub samples: Option<Vec<SampleCounts>>,
+    /// Prediction scores, when the program has a `readout`.
+    pub classification: Option<Classification>,
+    /// Energy and traffic estimate, with `SimConfig::energy`.
+    pub energy: Option<EnergyReport>,
+}
+
+/// The [`SimConfig`] settings a run used, as recorded in its summary. Paths
+/// and recordings are reduced to whether they were given.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunConfig {
+    pub compensated_summation: bool,
+    pub profile: bool,
+    pub record_spikes: bool,
+    pub record_input: bool,
+    pub trace_neurons: usize,
+    pub learning_rate: Option<f64>,
+    pub epochs: Option<usize>,
+    /// Name of the `SimConfig::energy` target.
+    pub energy: Option<String>,
+    pub scratch: bool,
+    pub replay_input: bool,
+}
+
+impl RunConfig {
+    pub fn new(config: &SimConfig) -> Self {
+        Self {
+            compensated_summation: config.compensated_summation,
+            profile: config.profile,
+            record_spikes: config.record_spikes,
+            record_input: config.record_input,
+            trace_neurons: config.trace_neurons,
+            learning_rate: config.learning_rate,
+            epochs: config.epochs,
+            energy: config.energy.as_ref().map(|t| t.name.clone()),
+            scratch: config.scratch.is_some(),
+            replay_input: config.replay_input.is_some(),
+        }
+    }
+}
+
+/// How well a `readout` layer's winner-take-all response matched the labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    pub layer: String,
+    /// Presentations that have ended.
+    pub presentations: u64,
+    pub correct: u64,
+    /// Presentations without a readout spike, which count as wrong.
+    pub silent: u64,
+    /// `confusion[label][predicted]`, over presentations with a prediction.
+    pub confusion: Vec<Vec<u64>>,
+}
+
+impl Classification {
+    /// Fraction of presentations predicted correctly; 0 before any ended.
+    pub fn accuracy(&self) -> f64 {
+        if self.presentations == 0 {
+            0.0
+        } else {
+            self.correct as f64 / self.presentations as f64
+        }
+    }
+}
+
+/// Readout spikes during one presentation of an `experiment`, including the
+/// gap after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleCounts {
+    pub epoch: u32,
+    /// Row of the dataset that was presented.
+    pub sample: u64,
+    /// One count per readout neuron.
+    pub counts: Vec<u64>,
+}
+
+/// Membrane potential of one neuron, sampled once per step after decay and
+/// reset, so a spiking step reads 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoltageTrace {
+    pub layer: u32,
+    pub neuron: u64,
+    pub samples: Vec<f64>,
+}
+
+/// A layer's population rate in hertz, sampled once per step. Each step
+/// moves it toward that step's rate by `step_ns / window_ns` of the gap, so
+/// it follows changes slower than the window and smooths out faster ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateTrace {
+    pub layer: u32,
+    pub window_ns: i64,
+    pub samples: Vec<f64>,
+}
+
+/// What a `probe` item sampled: the membrane potential of neurons
+/// `first..first + samples.len()` of a layer, after decay and reset like a
+/// [`VoltageTrace`], on steps 0, `every_steps`, `2 * every_steps` and so on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeTrace {
+    pub name: String,
+    pub layer: u32,
+    pub first: u64,
+    pub every_steps: u64,
+    /// One series per neuron.
+    pub samples: Vec<Vec<f64>>,
+}
+
+impl ProbeTrace {
+    fn due(&self, step: usize) -> bool {
+        (step as u64).is_multiple_of(self.every_steps)
+    }
+}
+
+impl RateTrace {
+    fn record(&mut self, spikes: usize, size: usize, step_ns: i64) {
+        let prev = self.samples.last().copied().unwrap_or(0.0);
+        let now = if size == 0 {
+            0.0
+        } else {
+            spikes as f64 / (size as f64 * step_ns as f64 * 1e-9)
+        };
+        let alpha = step_ns as f64 / self.window_ns as f64;
+        self.samples.push(prev + (now - prev) * alpha);
+    }
+}
+
+/// One recorded spike. `layer` indexes `SimSummary::layers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spike {
+    pub step: u64,
+    pub layer: u32,
+    pub neuron: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -22,484 +261,4369 @@ pub struct LayerSummary {
     pub spikes: u64,
 }
 
-#[derive(Debug)]
-pub struct SimError {
-    pub message: String,
+/// The delivery queue of one layer: a bucket of pending input per step of
+/// the longest delay, plus one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerQueue {
+    pub layer: String,
+    pub bytes: u64,
+}
+
+/// How far ahead the simulator schedules input, fixed when the network is
+/// built.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueueSummary {
+    /// The longest synaptic delay drawn, in steps, or the `d_max` of a
+    /// delay-learning rule when that is longer.
+    pub max_delay_steps: u64,
+    /// Every layer's queue, in declaration order.
+    pub layers: Vec<LayerQueue>,
 }
 
-impl fmt::Display for SimError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
+/// Spikes one chemical connection carried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionSummary {
+    /// The name from `connect name: ...`, if any.
+    pub name: Option<String>,
+    pub src: String,
+    pub dst: String,
+    /// Synaptic events: one per synapse of each source spike.
+    pub deliveries: u64,
+    /// Weight summed over all deliveries.
+    pub weight_sum: f64,
+}
+
+impl ConnectionSummary {
+    /// Average weight delivered; 0 without deliveries.
+    pub fn mean_weight(&self) -> f64 {
+        if self.deliveries == 0 {
+            0.0
+        } else {
+            self.weight_sum / self.deliveries as f64
+        }
     }
 }
 
-impl std::error::Error for SimError {}
+/// Borrowed view of one layer mid-run, for dashboards and probes.
+#[derive(Debug, Clone, Copy)]
+pub struct LayerView<'a> {
+    pub name: &'a str,
+    pub spikes: u64,
+    pub v_th: f64,
+    /// Somatic potentials, one per neuron.
+    pub membrane: &'a [f64],
+    /// Dendritic potentials, compartment-major: compartment `k` (1-based) of
+    /// neuron `i` is at `(k - 1) * membrane.len() + i`. Empty for point
+    /// neurons.
+    pub dendrites: &'a [f64],
+}
+
+/// Borrowed view of one connection, in source order.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionView<'a> {
+    /// Set for `connect name: A -> B`.
+    pub name: Option<&'a str>,
+    pub kind: ConnectKind,
+    pub src_layer: usize,
+    pub dst_layer: usize,
+    /// Synapses, or gap junctions for electrical connections.
+    pub synapses: usize,
+    /// Set when `delay_plasticity` moves the connection's delays.
+    pub learns_delays: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimConfig {
+    /// Accumulate delivered weights with Kahan compensation.
+    pub compensated_summation: bool,
+    /// Record per-phase wall-clock time into `SimSummary::profile`.
+    pub profile: bool,
+    /// Keep every spike in `SimSummary::raster`.
+    pub record_spikes: bool,
+    /// Trace the membrane potential of the first this-many neurons of every
+    /// layer into `SimSummary::voltages`.
+    pub trace_neurons: usize,
+    /// Directory that relative `Encode` file paths are resolved against;
+    /// the working directory when unset.
+    pub data_dir: Option<PathBuf>,
+    /// Read data files only from inside `data_dir`, as `converge serve`
+    /// does for programs from its clients. Absolute paths, `..` and
+    /// symlinks that lead out of it are refused, as is every file when
+    /// `data_dir` is unset, and a file that can't be read or parsed is
+    /// reported without its contents.
+    pub confine_data: bool,
+    /// Train the connections into the `readout` layer with the delta rule at
+    /// this rate, after every presentation.
+    pub learning_rate: Option<f64>,
+    /// Replaces the `experiment`'s `epochs`.
+    pub epochs: Option<usize>,
+    /// Round weights to fixed point and accumulate input as integers.
+    pub quantize: Option<Quantize>,
+    /// Membrane arithmetic; Q16.16 with [`Arithmetic::Fixed`].
+    pub arithmetic: Arithmetic,
+    /// Count events on this target for `SimSummary::energy`.
+    pub energy: Option<TargetProfile>,
+    /// Stream the synapses of static chemical connections into
+    /// memory-mapped files in this directory instead of RAM.
+    pub scratch: Option<PathBuf>,
+    /// Keep every Poisson stimulus spike in `SimSummary::input`.
+    pub record_input: bool,
+    /// Apply these recorded Poisson spikes instead of drawing new ones.
+    pub replay_input: Option<Recording>,
+}
+
+impl SimConfig {
+    pub(crate) fn data_files(&self) -> DataFiles<'_> {
+        DataFiles {
+            dir: self.data_dir.as_deref(),
+            confined: self.confine_data,
+        }
+    }
+}
 
 pub fn simulate(program: &Program) -> Result<SimSummary, SimError> {
-    let seed = program
-        .items
-        .iter()
-        .find_map(|item| match item {
-            Item::Seed(s) => Some(s.value),
-            _ => None,
-        })
-        .unwrap_or(0);
+    simulate_with(program, &SimConfig::default())
+}
 
-    let run = program
-        .items
-        .iter()
-        .find_map(|item| match item {
-            Item::Run(run) => Some(run),
-            _ => None,
-        })
-        .ok_or_else(|| SimError {
-            message: "missing run statement".to_string(),
-        })?;
+pub fn simulate_with(program: &Program, config: &SimConfig) -> Result<SimSummary, SimError> {
+    let mut sim = Simulator::new(program, config)?;
+    sim.run()?;
+    Ok(sim.summary())
+}
 
-    let duration_ns = time_to_nanos(&run.duration, "run duration").map_err(to_err)?;
-    let step_ns = match &run.step {
-        Some(step) => time_to_nanos(step, "run step").map_err(to_err)?,
-        None => 1_000_000,
-    };
+/// The state of a [`Simulator`] between two steps, from
+/// [`Simulator::snapshot`].
+#[derive(Clone)]
+pub struct Snapshot {
+    step: usize,
+    layers: Vec<LayerState>,
+    queues: Vec<DeliveryQueue>,
+    /// `None` for connections whose weights never change.
+    learned: Vec<Option<Learned>>,
+    modulators: Vec<Modulator>,
+    next_reward: usize,
+    pending_reward: f64,
+    injected: Vec<Vec<f64>>,
+    stimulus_rng: Rng,
+    hasher: SpikeHasher,
+    total_spikes: u64,
+    traffic: Vec<Traffic>,
+    raster: Option<Vec<Spike>>,
+    input: Option<Vec<Spike>>,
+    voltages: Option<Vec<VoltageTrace>>,
+    rates: Vec<RateTrace>,
+    probes: Vec<ProbeTrace>,
+    readout: Option<Readout>,
+    classifier: Option<Classifier>,
+    trainer: Option<Trainer>,
+    energy: Option<EnergyMeter>,
+}
 
-    if duration_ns <= 0 || step_ns <= 0 {
-        return Err(SimError {
-            message: "duration and step must be positive".to_string(),
-        });
+/// Synapses and plasticity state of a connection whose weights or delays
+/// change.
+#[derive(Clone)]
+struct Learned {
+    synapses: Vec<Vec<Synapse>>,
+    plasticity: Option<Plasticity>,
+    delay_plasticity: Option<DelayPlasticity>,
+}
+
+impl Snapshot {
+    /// The step the simulator resumes from.
+    pub fn step(&self) -> u64 {
+        self.step as u64
     }
-    if duration_ns % step_ns != 0 {
-        return Err(SimError {
-            message: "duration must be divisible by step".to_string(),
+}
+
+/// A built network that advances one step at a time, for callers that need
+/// to observe or interleave work with a run.
+pub struct Simulator {
+    duration_ns: i64,
+    step_ns: i64,
+    seed: u64,
+    steps: usize,
+    step: usize,
+    layers: Vec<LayerState>,
+    stimuli: Vec<Option<LayerStimulus>>,
+    connections: Vec<Connection>,
+    modulators: Vec<Modulator>,
+    /// Per-layer scratch for the gap-junction pass; empty for layers without
+    /// electrical connections.
+    coupling: Vec<Vec<f64>>,
+    queues: Vec<DeliveryQueue>,
+    queue_len: usize,
+    /// `reward` pulses as `(step, value)`, by step then source order.
+    rewards: Vec<(usize, f64)>,
+    next_reward: usize,
+    /// Reward added through `add_reward` for the next step.
+    pending_reward: f64,
+    /// Currents added through `inject` for the next step; empty for layers
+    /// without any.
+    injected: Vec<Vec<f64>>,
+    /// Draws Poisson stimulus spikes, from [`Stream::Stimulus`].
+    stimulus_rng: Rng,
+    /// Recorded Poisson spikes that replace the draws.
+    replay: Option<InputReplay>,
+    hasher: SpikeHasher,
+    total_spikes: u64,
+    /// Per connection, for `SimSummary::connections`.
+    traffic: Vec<Traffic>,
+    defaults: Vec<AppliedDefault>,
+    raster: Option<Vec<Spike>>,
+    input: Option<Vec<Spike>>,
+    voltages: Option<Vec<VoltageTrace>>,
+    rates: Vec<RateTrace>,
+    probes: Vec<ProbeTrace>,
+    readout: Option<Readout>,
+    classifier: Option<Classifier>,
+    trainer: Option<Trainer>,
+    energy: Option<EnergyMeter>,
+    quantize: Option<Quantize>,
+    arithmetic: Arithmetic,
+    run_config: RunConfig,
+    started: Option<Instant>,
+    clock: PhaseClock,
+    sinks: Vec<Box<dyn RecordSink + Send>>,
+}
+
+impl Simulator {
+    /// Builds the network of `program`. A program with several `network`
+    /// blocks must be narrowed with [`Program::select_network`] first.
+    pub fn new(program: &Program, config: &SimConfig) -> Result<Self, SimError> {
+        let program = &*program.select_network(None).map_err(to_err)?;
+        let RunTiming {
+            seed,
+            mut duration_ns,
+            step_ns,
+            mut steps,
+        } = run_timing(program)?;
+
+        let started = profile::now();
+        let mut clock = PhaseClock::new(config.profile);
+        let _phase = trace::phase!("build");
+        let neuron_defs = collect_neuron_defs(program)?;
+        let modulator_index = modulator_index(program);
+        let (mut layers, layer_index) = build_layers(program, &neuron_defs, &modulator_index)?;
+        let modulators = build_modulators(program, &layer_index)?;
+        let mut experiment = build_experiment(program, &layer_index, step_ns, seed)?;
+        if let Some(epochs) = config.epochs {
+            let Some(experiment) = &mut experiment else {
+                return Err(SimError::new(SimErrorKind::EpochsWithoutExperiment));
+            };
+            experiment.plan.epochs = epochs.max(1);
+        }
+        let plan = experiment.as_ref().map(|e| e.plan).unwrap_or_default();
+        let stimuli = collect_stimuli(program, &layer_index, &layers, step_ns, config, &plan)?;
+        let mut readout = None;
+        let mut presented = None;
+        if let Some(experiment) = &experiment {
+            let mut schedules = stimuli
+                .iter()
+                .flatten()
+                .flat_map(|s| &s.encoded)
+                .map(EncodedInput::schedule);
+            let schedule = schedules
+                .next()
+                .ok_or_else(|| SimError::new(SimErrorKind::ExperimentWithoutEncode))?;
+            if schedules.any(|s| s != schedule) {
+                return Err(SimError::new(SimErrorKind::UnevenEncodeRows));
+            }
+            // The run lasts at least until the last presentation ends.
+            steps = steps.max(schedule.steps());
+            duration_ns = steps as i64 * step_ns;
+            readout = experiment.readout.map(|layer| {
+                Readout::new(
+                    layer,
+                    layers[layer].size,
+                    schedule.clone(),
+                    experiment.plan.epochs,
+                )
+            });
+            presented = Some((schedule.clone(), experiment.plan.epochs));
+        }
+        let classifier = Classifier::build(
+            program,
+            &layer_index,
+            &layers,
+            presented.as_ref().map(|(s, epochs)| (s, *epochs)),
+            config.data_files(),
+        )?;
+        let rewards = collect_rewards(program, step_ns)?;
+        let rates = collect_rate_traces(program, &layer_index, step_ns, steps)?;
+        let probes = collect_probes(program, &layer_index, &layers, step_ns, steps)?;
+        if config.scratch.is_some() {
+            // These rewrite weights in place, which a read-only mapping can't.
+            let unsupported = if config.learning_rate.is_some() {
+                Some("training")
+            } else if config.quantize.is_some() {
+                Some("quantized weights")
+            } else if config.arithmetic == Arithmetic::Fixed {
+                Some("fixed-point arithmetic")
+            } else {
+                None
+            };
+            if let Some(what) = unsupported {
+                return Err(SimError::new(SimErrorKind::Unsupported(format!(
+                    "{what} needs synapses in memory; drop the scratch directory"
+                ))));
+            }
+        }
+        let mut connections = build_connections(
+            program,
+            &layer_index,
+            &modulator_index,
+            &mut layers,
+            step_ns,
+            seed,
+            config,
+        )?;
+        let trainer = config
+            .learning_rate
+            .map(|rate| {
+                let sizes: Vec<usize> = layers.iter().map(|l| l.size).collect();
+                Trainer::build(rate, classifier.as_ref(), &connections, &sizes)
+            })
+            .transpose()?;
+        let energy = config
+            .energy
+            .as_ref()
+            .map(|profile| EnergyMeter::build(program, profile, &layers))
+            .transpose()?;
+
+        let max_delay = connections
+            .iter()
+            .flat_map(|c| c.synapses.iter().flatten().map(|s| s.delay_steps))
+            .chain(
+                connections
+                    .iter()
+                    .flat_map(|c| c.mapped.as_ref())
+                    .map(|m| m.max_delay()),
+            )
+            .chain(
+                connections
+                    .iter()
+                    .flat_map(|c| c.delay_plasticity.as_ref())
+                    .map(DelayPlasticity::max_delay),
+            )
+            .max()
+            .unwrap_or(0);
+        let queue_len = max_delay + 1;
+
+        let mut coupling = vec![Vec::new(); layers.len()];
+        for conn in connections
+            .iter()
+            .filter(|c| c.kind == ConnectKind::Electrical)
+        {
+            for idx in [conn.src_layer, conn.dst_layer] {
+                coupling[idx] = vec![0.0; layers[idx].size];
+            }
+        }
+
+        let fixed = config.arithmetic == Arithmetic::Fixed;
+        if fixed {
+            check_fixed_support(&layers, &connections)?;
+        }
+        // Q16.16 weights are 32-bit codes whose LSB is no finer than the
+        // membrane's.
+        let weight_bits = match config.quantize {
+            Some(quantize) => Some(quantize.bits()),
+            None => fixed.then_some(32),
+        };
+        let lsb = weight_bits
+            .map(|bits| {
+                let cap = fixed.then_some(fixed::FRAC_BITS as i32);
+                quantize_connections(&mut connections, bits, cap)
+            })
+            .transpose()?
+            .map(|frac_bits| 2f64.powi(-frac_bits));
+        let queues: Vec<DeliveryQueue> = layers
+            .iter()
+            .map(|layer| {
+                DeliveryQueue::new(
+                    layer.size * (1 + layer.compartments),
+                    queue_len,
+                    config.compensated_summation,
+                    lsb,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        clock.lap(SimPhase::Build);
+        trace::debug!(
+            layers = layers.len(),
+            neurons = layers.iter().map(|l| l.size).sum::<usize>(),
+            connections = connections.len(),
+            steps,
+            "built network"
+        );
+        let defaults = defaults::applied_defaults(program);
+        if !defaults.is_empty() {
+            trace::debug!(
+                "effective model: {}",
+                defaults
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            );
+        }
+
+        let voltages = (config.trace_neurons > 0).then(|| {
+            layers
+                .iter()
+                .enumerate()
+                .flat_map(|(idx, layer)| {
+                    (0..config.trace_neurons.min(layer.size)).map(move |neuron| VoltageTrace {
+                        layer: idx as u32,
+                        neuron: neuron as u64,
+                        samples: Vec::with_capacity(steps),
+                    })
+                })
+                .collect()
         });
+
+        let replay = config
+            .replay_input
+            .as_ref()
+            .map(|recording| InputReplay::new(recording, &layers, step_ns, steps))
+            .transpose()?;
+
+        let layer_count = layers.len();
+        let traffic = vec![Traffic::default(); connections.len()];
+        Ok(Self {
+            defaults,
+            duration_ns,
+            step_ns,
+            seed,
+            steps,
+            step: 0,
+            layers,
+            stimuli,
+            connections,
+            modulators,
+            coupling,
+            queues,
+            queue_len,
+            rewards,
+            next_reward: 0,
+            pending_reward: 0.0,
+            injected: vec![Vec::new(); layer_count],
+            stimulus_rng: Rng::new(Stream::Stimulus.seed(seed)),
+            replay,
+            hasher: SpikeHasher::new(),
+            total_spikes: 0,
+            traffic,
+            raster: config.record_spikes.then(Vec::new),
+            input: config.record_input.then(Vec::new),
+            voltages,
+            rates,
+            probes,
+            readout,
+            classifier,
+            trainer,
+            energy,
+            quantize: config.quantize,
+            arithmetic: config.arithmetic,
+            run_config: RunConfig::new(config),
+            started,
+            clock,
+            sinks: Vec::new(),
+        })
+    }
+
+    pub fn total_steps(&self) -> u64 {
+        self.steps as u64
     }
 
-    let steps = (duration_ns / step_ns) as usize;
+    pub fn current_step(&self) -> u64 {
+        self.step as u64
+    }
 
-    let neuron_defs = collect_neuron_defs(program)?;
-    let (mut layers, layer_index) = build_layers(program, &neuron_defs)?;
-    let stimuli = collect_stimuli(program, &layer_index)?;
-    let connections = build_connections(program, &layer_index, &mut layers, step_ns, seed)?;
+    pub fn step_ns(&self) -> i64 {
+        self.step_ns
+    }
 
-    let mut rng = Rng::new(seed);
-    let mut total_spikes = 0u64;
+    pub fn duration_ns(&self) -> i64 {
+        self.duration_ns
+    }
 
-    let max_delay = connections
-        .iter()
-        .flat_map(|c| c.synapses.iter().flatten().map(|s| s.delay_steps))
-        .max()
-        .unwrap_or(0);
-    let queue_len = max_delay + 1;
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-    let mut queues: Vec<Vec<Vec<f64>>> = layers
-        .iter()
-        .map(|layer| vec![vec![0.0; layer.size]; queue_len])
-        .collect();
+    pub fn total_spikes(&self) -> u64 {
+        self.total_spikes
+    }
 
-    for step in 0..steps {
-        let bucket = step % queue_len;
-        let mut spiked: Vec<Vec<usize>> = vec![Vec::new(); layers.len()];
+    pub fn is_finished(&self) -> bool {
+        self.step >= self.steps
+    }
 
-        for (layer_idx, layer) in layers.iter_mut().enumerate() {
-            let incoming = &mut queues[layer_idx][bucket];
-            for (i, incoming_val) in incoming.iter_mut().enumerate() {
-                layer.v[i] += *incoming_val;
-                *incoming_val = 0.0;
-            }
+    /// Runs all remaining steps.
+    pub fn run(&mut self) -> Result<(), SimError> {
+        let _phase = trace::phase!("simulate");
+        while !self.is_finished() {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Advances one step. Does nothing once the run duration is reached.
+    pub fn step(&mut self) -> Result<(), SimError> {
+        if self.is_finished() {
+            return Ok(());
+        }
+        let step = self.step;
+        let step_ns = self.step_ns;
+        let bucket = step % self.queue_len;
+        let mut spiked: Vec<Vec<usize>> = vec![Vec::new(); self.layers.len()];
+        self.clock.mark();
 
-            if let Some(rate_hz) = stimuli.get(&layer_idx) {
-                let p = rate_hz * (step_ns as f64 / 1_000_000_000.0);
-                if p > 1.0 {
-                    return Err(SimError {
-                        message: "stimulus rate too high for step".to_string(),
+        for (layer_idx, layer) in self.layers.iter_mut().enumerate() {
+            self.queues[layer_idx]
+                .drain_into(bucket, layer.v.iter_mut().chain(&mut layer.dendrites));
+            self.clock.lap(SimPhase::Delivery);
+
+            let mut stochastic = |v: &mut f64, neuron: usize| {
+                *v += 1.0;
+                if let Some(input) = &mut self.input {
+                    input.push(Spike {
+                        step: step as u64,
+                        layer: layer_idx as u32,
+                        neuron: neuron as u64,
                     });
                 }
+            };
+            if let Some(replay) = &self.replay {
+                for i in replay.spikes(step, layer_idx) {
+                    stochastic(&mut layer.v[i], i);
+                }
+            }
+            if let Some(stimulus) = &mut self.stimuli[layer_idx] {
+                for source in &mut stimulus.external {
+                    source.receive(step, step as i64 * step_ns)?;
+                }
+                for shared in &mut stimulus.correlated {
+                    shared.fired = self.replay.is_none() && self.stimulus_rng.next_f64() < shared.p;
+                }
                 for i in 0..layer.size {
-                    if rng.next_f64() < p {
-                        layer.v[i] += 1.0;
+                    if stimulus.poisson && self.replay.is_none() {
+                        let p = stimulus.rate_hz(step, i) * (step_ns as f64 / 1_000_000_000.0);
+                        if p > 1.0 {
+                            return Err(SimError::new(SimErrorKind::RateTooHigh {
+                                layer: layer.name.clone(),
+                            }));
+                        }
+                        if self.stimulus_rng.next_f64() < p {
+                            stochastic(&mut layer.v[i], i);
+                        }
+                    }
+                    for shared in &stimulus.correlated {
+                        if shared.fired && self.stimulus_rng.next_f64() < shared.correlation {
+                            stochastic(&mut layer.v[i], i);
+                        }
+                    }
+                    let timed = stimulus.timed_spikes(step, i);
+                    if timed > 0 {
+                        layer.v[i] += timed as f64;
+                    }
+                    if !stimulus.external.is_empty() {
+                        layer.v[i] += stimulus.current(i);
                     }
                 }
             }
+            for (v, current) in layer
+                .v
+                .iter_mut()
+                .zip(std::mem::take(&mut self.injected[layer_idx]))
+            {
+                *v += current;
+            }
+            self.clock.lap(SimPhase::Stimulus);
+        }
+
+        self.couple();
+        self.clock.lap(SimPhase::Delivery);
 
+        for (layer_idx, layer) in self.layers.iter_mut().enumerate() {
             let decay = step_ns as f64 / layer.tau_m_ns as f64;
+            layer.conduct(decay);
+            for d in &mut layer.dendrites {
+                *d += (-*d) * decay;
+            }
+            let v_th = match layer.modulator {
+                Some(m) => layer.v_th + layer.modulator_gain * self.modulators[m].level,
+                None => layer.v_th,
+            };
+            let fixed = (self.arithmetic == Arithmetic::Fixed).then(|| {
+                (
+                    fixed::decay_q16(step_ns, layer.tau_m_ns),
+                    fixed::to_q16(v_th),
+                )
+            });
             for i in 0..layer.size {
-                layer.v[i] += (-layer.v[i]) * decay;
-                if layer.v[i] >= layer.v_th {
+                let fired = match fixed {
+                    Some((decay, v_th)) => {
+                        let q = fixed::leak(layer.v[i], decay);
+                        layer.v[i] = fixed::from_q16(q);
+                        q >= v_th
+                    }
+                    None => {
+                        layer.v[i] += (-layer.v[i]) * decay;
+                        layer.v[i] >= v_th
+                    }
+                };
+                if fired {
                     layer.v[i] = 0.0;
                     layer.spikes += 1;
-                    total_spikes += 1;
+                    self.total_spikes += 1;
+                    self.hasher.record(step as u64, layer_idx as u32, i as u64);
+                    if let Some(raster) = &mut self.raster {
+                        raster.push(Spike {
+                            step: step as u64,
+                            layer: layer_idx as u32,
+                            neuron: i as u64,
+                        });
+                    }
                     spiked[layer_idx].push(i);
                 }
             }
+            if let Some(traces) = &mut self.voltages {
+                for trace in traces.iter_mut().filter(|t| t.layer as usize == layer_idx) {
+                    trace.samples.push(layer.v[trace.neuron as usize]);
+                }
+            }
+            for trace in self
+                .rates
+                .iter_mut()
+                .filter(|t| t.layer as usize == layer_idx)
+            {
+                trace.record(spiked[layer_idx].len(), layer.size, step_ns);
+            }
+            for probe in self
+                .probes
+                .iter_mut()
+                .filter(|p| p.layer as usize == layer_idx && p.due(step))
+            {
+                let first = probe.first as usize;
+                for (i, series) in probe.samples.iter_mut().enumerate() {
+                    series.push(layer.v[first + i]);
+                }
+            }
+            self.clock.lap(SimPhase::Membrane);
         }
 
-        for conn in &connections {
-            if spiked[conn.src_layer].is_empty() {
+        for (conn, traffic) in self.connections.iter().zip(&mut self.traffic) {
+            if conn.kind == ConnectKind::Electrical || spiked[conn.src_layer].is_empty() {
                 continue;
             }
+            let offset = conn.compartment * self.layers[conn.dst_layer].size;
+            let queue = &mut self.queues[conn.dst_layer];
+            let mut deliver = |syn: &Synapse| {
+                traffic.deliveries += 1;
+                traffic.weight_sum += syn.weight;
+                let target_bucket = (bucket + syn.delay_steps) % self.queue_len;
+                match &conn.grid {
+                    Some(grid) => {
+                        queue.add_units(target_bucket, offset + syn.dst, grid.units(syn.weight))
+                    }
+                    None => queue.add(target_bucket, offset + syn.dst, syn.weight),
+                }
+            };
             for &src_i in &spiked[conn.src_layer] {
-                for syn in &conn.synapses[src_i] {
-                    let target_bucket = (bucket + syn.delay_steps) % queue_len;
-                    queues[conn.dst_layer][target_bucket][syn.dst] += syn.weight;
+                match &conn.mapped {
+                    Some(mapped) => mapped.row(src_i).for_each(|syn| deliver(&syn)),
+                    None => conn.synapses[src_i].iter().for_each(&mut deliver),
                 }
             }
         }
-    }
+        self.clock.lap(SimPhase::Delivery);
 
-    let layers_summary = layers
-        .iter()
-        .map(|l| LayerSummary {
-            name: l.name.clone(),
-            size: l.size as u64,
-            spikes: l.spikes,
-        })
-        .collect();
+        if let Some(readout) = &mut self.readout {
+            readout.record(step, &spiked[readout.layer]);
+        }
+        if let Some(classifier) = &mut self.classifier {
+            let tally = &mut classifier.tally;
+            tally.record(step, &spiked[tally.layer]);
+        }
+        if let Some(trainer) = &mut self.trainer {
+            trainer.step(step, &spiked, &mut self.connections);
+        }
+        if let Some(energy) = &mut self.energy {
+            energy.record(&spiked);
+        }
+        for modulator in &mut self.modulators {
+            modulator.step(&spiked, step_ns);
+        }
+        let mut reward = std::mem::take(&mut self.pending_reward);
+        while let Some(&(at, value)) = self.rewards.get(self.next_reward) {
+            if at > step {
+                break;
+            }
+            reward += value;
+            self.next_reward += 1;
+        }
+        for conn in &mut self.connections {
+            if let Some(plasticity) = &mut conn.plasticity {
+                let modulation = plasticity
+                    .modulator()
+                    .map_or(0.0, |m| self.modulators[m].level);
+                plasticity.step(
+                    &mut conn.synapses,
+                    &spiked[conn.src_layer],
+                    &spiked[conn.dst_layer],
+                    reward + modulation,
+                    step_ns,
+                );
+            }
+            if let Some(delays) = &mut conn.delay_plasticity {
+                delays.step(
+                    &mut conn.synapses,
+                    &spiked[conn.src_layer],
+                    &spiked[conn.dst_layer],
+                    step,
+                    step_ns,
+                );
+            }
+        }
+        self.clock.lap(SimPhase::Plasticity);
 
-    Ok(SimSummary {
-        duration_ns,
-        step_ns,
-        seed,
-        total_spikes,
-        layers: layers_summary,
-    })
-}
+        trace::debug!(
+            step,
+            spikes = spiked.iter().map(Vec::len).sum::<usize>(),
+            total_spikes = self.total_spikes,
+            "step"
+        );
+        self.step += 1;
+        self.emit(step, &spiked)
+    }
 
-pub fn summary_json(summary: &SimSummary) -> String {
-    let mut s = String::new();
-    s.push_str("{\n");
-    s.push_str(&format!("  \"duration_ns\": {},\n", summary.duration_ns));
-    s.push_str(&format!("  \"step_ns\": {},\n", summary.step_ns));
-    s.push_str(&format!("  \"seed\": {},\n", summary.seed));
-    s.push_str(&format!("  \"total_spikes\": {},\n", summary.total_spikes));
-    s.push_str("  \"layers\": [\n");
-    for (idx, layer) in summary.layers.iter().enumerate() {
-        s.push_str("    {\n");
-        s.push_str(&format!("      \"name\": \"{}\",\n", layer.name));
-        s.push_str(&format!("      \"size\": {},\n", layer.size));
-        s.push_str(&format!("      \"spikes\": {}\n", layer.spikes));
-        s.push_str("    }");
-        if idx + 1 != summary.layers.len() {
-            s.push(',');
+    /// Streams one finished step to the sinks.
+    fn emit(&mut self, step: usize, spiked: &[Vec<usize>]) -> Result<(), SimError> {
+        for sink in &mut self.sinks {
+            for (layer, neurons) in spiked.iter().enumerate() {
+                for &i in neurons {
+                    sink.on_spike(&Spike {
+                        step: step as u64,
+                        layer: layer as u32,
+                        neuron: i as u64,
+                    })
+                    .map_err(sink_error)?;
+                }
+            }
+            for trace in self.voltages.iter().flatten() {
+                let Some(&v) = trace.samples.last() else {
+                    continue;
+                };
+                sink.on_sample(&Sample {
+                    step: step as u64,
+                    layer: trace.layer,
+                    neuron: trace.neuron,
+                    v,
+                })
+                .map_err(sink_error)?;
+            }
+            for trace in &self.rates {
+                let Some(&hz) = trace.samples.last() else {
+                    continue;
+                };
+                sink.on_rate(&RateSample {
+                    step: step as u64,
+                    layer: trace.layer,
+                    hz,
+                })
+                .map_err(sink_error)?;
+            }
+            for (idx, probe) in self.probes.iter().enumerate() {
+                if !probe.due(step) {
+                    continue;
+                }
+                for (i, series) in probe.samples.iter().enumerate() {
+                    let Some(&v) = series.last() else {
+                        continue;
+                    };
+                    sink.on_probe(&ProbeSample {
+                        step: step as u64,
+                        probe: idx as u32,
+                        layer: probe.layer,
+                        neuron: probe.first + i as u64,
+                        v,
+                    })
+                    .map_err(sink_error)?;
+                }
+            }
+            sink.on_step_end(step as u64).map_err(sink_error)?;
         }
-        s.push('\n');
+        Ok(())
     }
-    s.push_str("  ]\n");
-    s.push_str("}\n");
-    s
-}
 
-#[derive(Clone)]
-struct LayerState {
-    name: String,
-    size: usize,
-    tau_m_ns: i64,
-    v_th: f64,
-    v: Vec<f64>,
-    spikes: u64,
-}
+    /// Streams the spikes, traced samples, rates and probe samples of every
+    /// later step to `sink`, starting it now.
+    pub fn add_sink(&mut self, mut sink: Box<dyn RecordSink + Send>) -> Result<(), SimError> {
+        let run = RunInfo {
+            duration_ns: self.duration_ns,
+            step_ns: self.step_ns,
+            seed: self.seed,
+            layers: self
+                .layers
+                .iter()
+                .map(|l| (l.name.clone(), l.size as u64))
+                .collect(),
+            probes: self.probes.iter().map(|p| p.name.clone()).collect(),
+        };
+        sink.on_start(&run).map_err(sink_error)?;
+        self.sinks.push(sink);
+        Ok(())
+    }
 
-#[derive(Clone)]
-struct Connection {
-    src_layer: usize,
-    dst_layer: usize,
-    synapses: Vec<Vec<Synapse>>,
-}
+    /// Summarizes the steps run so far, like [`Simulator::summary`], and
+    /// hands the summary to every sink.
+    pub fn finish(&mut self) -> Result<SimSummary, SimError> {
+        let summary = self.summary();
+        for sink in &mut self.sinks {
+            sink.on_summary(&summary).map_err(sink_error)?;
+        }
+        Ok(summary)
+    }
 
-#[derive(Clone)]
-struct Synapse {
-    dst: usize,
-    weight: f64,
-    delay_steps: usize,
-}
+    /// Gap-junction pass: each junction moves both membranes toward each
+    /// other by `g * (v_other - v_self) * dt / tau_m`, so `g` is relative to
+    /// the leak. Potentials are read from before the pass, which makes the
+    /// result independent of junction order.
+    fn couple(&mut self) {
+        if self.coupling.iter().all(Vec::is_empty) {
+            return;
+        }
+        let step_ns = self.step_ns as f64;
+        for conn in &self.connections {
+            if conn.kind != ConnectKind::Electrical {
+                continue;
+            }
+            let (a, b) = (conn.src_layer, conn.dst_layer);
+            let rate_a = step_ns / self.layers[a].tau_m_ns as f64;
+            let rate_b = step_ns / self.layers[b].tau_m_ns as f64;
+            for (i, junctions) in conn.synapses.iter().enumerate() {
+                let va = self.layers[a].v[i];
+                for j in junctions {
+                    let flow = j.weight * (self.layers[b].v[j.dst] - va);
+                    self.coupling[a][i] += flow * rate_a;
+                    self.coupling[b][j.dst] -= flow * rate_b;
+                }
+            }
+        }
+        for (layer, delta) in self.layers.iter_mut().zip(&mut self.coupling) {
+            for (v, d) in layer.v.iter_mut().zip(delta.iter_mut()) {
+                *v += *d;
+                *d = 0.0;
+            }
+        }
+    }
 
-#[derive(Clone)]
-enum Dist {
-    Const(f64),
-    Uniform(f64, f64),
-    Normal(f64, f64),
-}
+    /// Adds `value` to the reward delivered to plastic connections on the
+    /// next step, on top of any scheduled `reward` items, for rewards
+    /// computed by the host.
+    pub fn add_reward(&mut self, value: f64) {
+        self.pending_reward += value;
+    }
 
-fn collect_neuron_defs(program: &Program) -> Result<HashMap<String, NeuronDef>, SimError> {
-    let mut map = HashMap::new();
-    for item in &program.items {
-        if let Item::Neuron(def) = item {
-            map.insert(def.name.name.clone(), def.clone());
+    /// Adds `currents`, one per neuron, to the membrane potentials of layer
+    /// `layer` on the next step, alongside its stimuli.
+    pub fn inject(&mut self, layer: usize, currents: &[f64]) -> Result<(), SimError> {
+        let Some(state) = self.layers.get(layer) else {
+            return Err(SimError::new(SimErrorKind::LayerIndexOutOfRange {
+                index: layer,
+            }));
+        };
+        if currents.len() != state.size {
+            return Err(SimError::new(SimErrorKind::LayerSizeMismatch {
+                layer: state.name.clone(),
+                expected: state.size,
+                got: currents.len() as u64,
+                what: "currents",
+            }));
         }
+        if let Some(&bad) = currents.iter().find(|c| !c.is_finite()) {
+            return Err(SimError::new(SimErrorKind::NonFiniteCurrent { value: bad }));
+        }
+        let pending = &mut self.injected[layer];
+        if pending.is_empty() {
+            pending.extend_from_slice(currents);
+        } else {
+            pending.iter_mut().zip(currents).for_each(|(p, c)| *p += c);
+        }
+        Ok(())
     }
-    Ok(map)
-}
 
-fn build_layers(
-    program: &Program,
-    neuron_defs: &HashMap<String, NeuronDef>,
-) -> Result<(Vec<LayerState>, HashMap<String, usize>), SimError> {
-    let mut layers = Vec::new();
-    let mut index = HashMap::new();
+    /// Captures everything a run changes, so [`restore`](Self::restore) can
+    /// resume from this step. Sinks and `External` controllers are not part
+    /// of it: after a restore they see the replayed steps again.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            step: self.step,
+            layers: self.layers.clone(),
+            queues: self.queues.clone(),
+            learned: self
+                .connections
+                .iter()
+                .map(|conn| {
+                    let learns = conn.plasticity.is_some() || conn.delay_plasticity.is_some();
+                    (learns || self.trainer.is_some()).then(|| Learned {
+                        synapses: conn.synapses.clone(),
+                        plasticity: conn.plasticity.clone(),
+                        delay_plasticity: conn.delay_plasticity.clone(),
+                    })
+                })
+                .collect(),
+            modulators: self.modulators.clone(),
+            next_reward: self.next_reward,
+            pending_reward: self.pending_reward,
+            injected: self.injected.clone(),
+            stimulus_rng: self.stimulus_rng.clone(),
+            hasher: self.hasher.clone(),
+            total_spikes: self.total_spikes,
+            traffic: self.traffic.clone(),
+            raster: self.raster.clone(),
+            input: self.input.clone(),
+            voltages: self.voltages.clone(),
+            rates: self.rates.clone(),
+            probes: self.probes.clone(),
+            readout: self.readout.clone(),
+            classifier: self.classifier.clone(),
+            trainer: self.trainer.clone(),
+            energy: self.energy.clone(),
+        }
+    }
 
-    for item in &program.items {
-        if let Item::Layer(def) = item {
-            let neuron = neuron_defs.get(&def.neuron.name).ok_or_else(|| SimError {
-                message: format!("unknown neuron type `{}`", def.neuron.name),
-            })?;
-            let params = lif_params(neuron)?;
+    /// Rewinds or fast-forwards to a snapshot of this simulator, or of one
+    /// built from the same program and configuration.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SimError> {
+        let same_shape = snapshot.layers.len() == self.layers.len()
+            && snapshot
+                .layers
+                .iter()
+                .zip(&self.layers)
+                .all(|(a, b)| a.size == b.size && a.dendrites.len() == b.dendrites.len())
+            && snapshot.learned.len() == self.connections.len()
+            && snapshot.queues.len() == self.queues.len()
+            && snapshot.step <= self.steps;
+        if !same_shape {
+            return Err(SimError::new(SimErrorKind::ForeignSnapshot));
+        }
+        let snapshot = snapshot.clone();
+        self.step = snapshot.step;
+        self.layers = snapshot.layers;
+        self.queues = snapshot.queues;
+        for (conn, learned) in self.connections.iter_mut().zip(snapshot.learned) {
+            if let Some(learned) = learned {
+                conn.synapses = learned.synapses;
+                conn.plasticity = learned.plasticity;
+                conn.delay_plasticity = learned.delay_plasticity;
+            }
+        }
+        self.modulators = snapshot.modulators;
+        self.next_reward = snapshot.next_reward;
+        self.pending_reward = snapshot.pending_reward;
+        self.injected = snapshot.injected;
+        self.stimulus_rng = snapshot.stimulus_rng;
+        self.hasher = snapshot.hasher;
+        self.total_spikes = snapshot.total_spikes;
+        self.traffic = snapshot.traffic;
+        self.raster = snapshot.raster;
+        self.input = snapshot.input;
+        self.voltages = snapshot.voltages;
+        self.rates = snapshot.rates;
+        self.probes = snapshot.probes;
+        self.readout = snapshot.readout;
+        self.classifier = snapshot.classifier;
+        self.trainer = snapshot.trainer;
+        self.energy = snapshot.energy;
+        Ok(())
+    }
 
-            let size = def.size as usize;
-            index.insert(def.name.name.clone(), layers.len());
-            layers.push(LayerState {
-                name: def.name.name.clone(),
-                size,
-                tau_m_ns: params.tau_m_ns,
-                v_th: params.v_th,
-                v: vec![0.0; size],
-                spikes: 0,
-            });
+    /// Current level of the modulator declared as `modulator <name>`.
+    pub fn modulator_level(&self, name: &str) -> Option<f64> {
+        self.modulators
+            .iter()
+            .find(|m| m.name == name)
+            .map(|m| m.level)
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Live state of one layer, by source order.
+    pub fn layer(&self, idx: usize) -> LayerView<'_> {
+        let layer = &self.layers[idx];
+        LayerView {
+            name: &layer.name,
+            spikes: layer.spikes,
+            v_th: layer.v_th,
+            membrane: &layer.v,
+            dendrites: &layer.dendrites,
         }
     }
 
-    Ok((layers, index))
-}
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
 
-fn collect_stimuli(
-    program: &Program,
-    layer_index: &HashMap<String, usize>,
-) -> Result<HashMap<usize, f64>, SimError> {
-    let mut map: HashMap<usize, f64> = HashMap::new();
-    for item in &program.items {
-        if let Item::Stimulus(StimulusDef { layer, model }) = item {
-            let idx = *layer_index.get(&layer.name).ok_or_else(|| SimError {
-                message: format!("unknown stimulus layer `{}`", layer.name),
-            })?;
-            let rate = match model {
-                StimulusModel::Poisson { rate } => {
-                    rate_to_hz(rate, "Poisson rate").map_err(to_err)?
+    /// One connection, by source order.
+    pub fn connection(&self, idx: usize) -> ConnectionView<'_> {
+        let conn = &self.connections[idx];
+        ConnectionView {
+            name: conn.name.as_deref(),
+            kind: conn.kind,
+            src_layer: conn.src_layer,
+            dst_layer: conn.dst_layer,
+            synapses: conn.synapse_count(),
+            learns_delays: conn.delay_plasticity.is_some(),
+        }
+    }
+
+    /// Weights of one chemical connection as a dense matrix, one row per
+    /// source neuron; removed synapses read as 0.
+    pub fn weights(&self, idx: usize) -> Vec<Vec<f64>> {
+        let conn = &self.connections[idx];
+        let dst_size = self.layers[conn.dst_layer].size;
+        (0..self.layers[conn.src_layer].size)
+            .map(|i| {
+                let mut row = vec![0.0; dst_size];
+                for syn in conn.row(i) {
+                    row[syn.dst] = syn.weight;
                 }
-            };
-            let entry = map.entry(idx).or_insert(0.0);
-            *entry += rate;
+                row
+            })
+            .collect()
+    }
+
+    /// Delays of one chemical connection in run steps, as a dense matrix
+    /// like [`Self::weights`]; removed synapses read as 0.
+    pub fn delays(&self, idx: usize) -> Vec<Vec<f64>> {
+        let conn = &self.connections[idx];
+        let dst_size = self.layers[conn.dst_layer].size;
+        (0..self.layers[conn.src_layer].size)
+            .map(|i| {
+                let mut row = vec![0.0; dst_size];
+                for syn in conn.row(i) {
+                    row[syn.dst] = syn.delay_steps as f64;
+                }
+                row
+            })
+            .collect()
+    }
+
+    /// Connections whose weights or delays learn during a run: chemical
+    /// connections into the `readout` layer when training, and plastic
+    /// ones.
+    pub fn trained_connections(&self) -> Vec<usize> {
+        let readout = match (&self.trainer, &self.classifier) {
+            (Some(_), Some(classifier)) => classifier.tally.layer,
+            _ => usize::MAX,
+        };
+        (0..self.connections.len())
+            .filter(|&idx| is_trained(&self.connections[idx], readout))
+            .collect()
+    }
+
+    /// Index of the connection declared as `connect name: ...`.
+    pub fn connection_index(&self, name: &str) -> Option<usize> {
+        self.connections
+            .iter()
+            .position(|c| c.name.as_deref() == Some(name))
+    }
+
+    /// Summarizes the steps run so far.
+    pub fn summary(&self) -> SimSummary {
+        let layers = self
+            .layers
+            .iter()
+            .map(|l| LayerSummary {
+                name: l.name.clone(),
+                size: l.size as u64,
+                spikes: l.spikes,
+            })
+            .collect();
+
+        SimSummary {
+            duration_ns: self.duration_ns,
+            step_ns: self.step_ns,
+            seed: self.seed,
+            steps: self.step as u64,
+            config: self.run_config.clone(),
+            input_hash: None,
+            wall_ns: self.started.map(|t| t.elapsed().as_nanos() as u64),
+            quantize: self.quantize,
+            arithmetic: self.arithmetic,
+            total_spikes: self.total_spikes,
+            spike_hash: self.hasher.finish(),
+            layers,
+            connections: self
+                .connections
+                .iter()
+                .zip(&self.traffic)
+                .filter(|(conn, _)| conn.kind != ConnectKind::Electrical)
+                .map(|(conn, traffic)| ConnectionSummary {
+                    name: conn.name.clone(),
+                    src: self.layers[conn.src_layer].name.clone(),
+                    dst: self.layers[conn.dst_layer].name.clone(),
+                    deliveries: traffic.deliveries,
+                    weight_sum: traffic.weight_sum,
+                })
+                .collect(),
+            defaults: self.defaults.clone(),
+            queues: QueueSummary {
+                max_delay_steps: self.queue_len as u64 - 1,
+                layers: self
+                    .layers
+                    .iter()
+                    .zip(&self.queues)
+                    .map(|(layer, queue)| LayerQueue {
+                        layer: layer.name.clone(),
+                        bytes: queue.bytes() as u64,
+                    })
+                    .collect(),
+            },
+            profile: self.clock.finish(network_bytes(
+                &self.layers,
+                &self.coupling,
+                &self.connections,
+                &self.queues,
+            )),
+            raster: self.raster.clone(),
+            input: self.input.clone(),
+            voltages: self.voltages.clone(),
+            rates: self.rates.clone(),
+            probes: self.probes.clone(),
+            samples: self.readout.as_ref().map(|r| r.samples(self.step)),
+            classification: self
+                .classifier
+                .as_ref()
+                .map(|c| c.classification(self.step)),
+            energy: self
+                .energy
+                .as_ref()
+                .map(|e| e.report(&self.connections, self.step)),
         }
     }
-    Ok(map)
+
+    /// Spikes recorded so far; empty unless `SimConfig::record_spikes` is set.
+    pub fn recorded_spikes(&self) -> &[Spike] {
+        self.raster.as_deref().unwrap_or(&[])
+    }
 }
 
-fn build_connections(
-    program: &Program,
-    layer_index: &HashMap<String, usize>,
-    layers: &mut [LayerState],
-    step_ns: i64,
+struct RunTiming {
     seed: u64,
-) -> Result<Vec<Connection>, SimError> {
-    let mut rng = Rng::new(seed ^ 0x9E3779B97F4A7C15);
-    let mut connections = Vec::new();
+    duration_ns: i64,
+    step_ns: i64,
+    steps: usize,
+}
 
-    for item in &program.items {
-        let Item::Connect(ConnectDef { src, dst, body }) = item else {
-            continue;
-        };
-        let src_idx = *layer_index.get(&src.name).ok_or_else(|| SimError {
-            message: format!("unknown source layer `{}`", src.name),
-        })?;
-        let dst_idx = *layer_index.get(&dst.name).ok_or_else(|| SimError {
-            message: format!("unknown destination layer `{}`", dst.name),
-        })?;
+fn run_timing(program: &Program) -> Result<RunTiming, SimError> {
+    let seed = program
+        .items
+        .iter()
+        .find_map(|item| match item {
+            Item::Seed(s) => Some(s.value),
+            _ => None,
+        })
+        .unwrap_or(0);
 
-        let weight_dist = find_dist(body, "w", false)?;
-        let delay_dist = find_dist(body, "d", true)?;
+    let run = program
+        .items
+        .iter()
+        .find_map(|item| match item {
+            Item::Run(run) => Some(run),
+            _ => None,
+        })
+        .ok_or_else(|| SimError::new(SimErrorKind::MissingRun))?;
 
-        let src_size = layers[src_idx].size;
-        let dst_size = layers[dst_idx].size;
-        let mut synapses = vec![Vec::with_capacity(dst_size); src_size];
+    let duration_ns = time_to_nanos(&run.duration, "run duration").map_err(unit_err)?;
+    let step_ns = match &run.step {
+        Some(step) => time_to_nanos(step, "run step").map_err(unit_err)?,
+        None => defaults::STEP_NS,
+    };
 
-        for syn_list in synapses.iter_mut() {
-            for dst_i in 0..dst_size {
-                let weight = sample_dist(&weight_dist, &mut rng);
-                let delay_ns = sample_dist(&delay_dist, &mut rng);
-                if delay_ns < 0.0 {
-                    return Err(SimError {
-                        message: "negative delay is not allowed".to_string(),
-                    });
-                }
-                let delay_ns_i = delay_ns.round() as i64;
-                if delay_ns_i % step_ns != 0 {
-                    return Err(SimError {
-                        message: "delay must be divisible by step".to_string(),
-                    });
-                }
-                let delay_steps = (delay_ns_i / step_ns) as usize;
-                syn_list.push(Synapse {
-                    dst: dst_i,
-                    weight,
-                    delay_steps,
-                });
-            }
+    if duration_ns <= 0 || step_ns <= 0 {
+        return Err(SimError::new(SimErrorKind::BadRunTiming {
+            duration_ns,
+            step_ns,
+        })
+        .with_span(run.span.clone()));
+    }
+    if duration_ns % step_ns != 0 {
+        return Err(SimError::new(SimErrorKind::BadRunTiming {
+            duration_ns,
+            step_ns,
+        })
+        .with_span(run.span.clone()));
+    }
+
+    Ok(RunTiming {
+        seed,
+        duration_ns,
+        step_ns,
+        steps: (duration_ns / step_ns) as usize,
+    })
+}
+
+/// Dendritic compartments and gap junctions have no integer kernel yet.
+fn check_fixed_support(layers: &[LayerState], connections: &[Connection]) -> Result<(), SimError> {
+    if let Some(layer) = layers.iter().find(|l| l.compartments > 0) {
+        return Err(SimError::new(SimErrorKind::Unsupported(format!(
+            "fixed-point arithmetic does not support dendritic compartments (layer `{}`)",
+            layer.name
+        ))));
+    }
+    if connections
+        .iter()
+        .any(|c| c.kind == ConnectKind::Electrical)
+    {
+        return Err(SimError::new(SimErrorKind::Unsupported(
+            "fixed-point arithmetic does not support gap junctions".to_string(),
+        )));
+    }
+    Ok(())
+}
+
+fn network_bytes(
+    layers: &[LayerState],
+    coupling: &[Vec<f64>],
+    connections: &[Connection],
+    queues: &[DeliveryQueue],
+) -> u64 {
+    let f64_size = std::mem::size_of::<f64>();
+    let layer_bytes: usize = layers
+        .iter()
+        .flat_map(|l| [&l.v, &l.dendrites])
+        .chain(coupling)
+        .map(|v| v.capacity() * f64_size)
+        .sum();
+    let synapse_bytes: usize = connections
+        .iter()
+        .flat_map(|c| c.synapses.iter())
+        .map(|list| {
+            std::mem::size_of::<Vec<Synapse>>() + list.capacity() * std::mem::size_of::<Synapse>()
+        })
+        .sum::<usize>()
+        + connections
+            .iter()
+            .filter_map(|c| c.plasticity.as_ref())
+            .map(Plasticity::bytes)
+            .sum::<usize>()
+        + connections
+            .iter()
+            .filter_map(|c| c.delay_plasticity.as_ref())
+            .map(DelayPlasticity::bytes)
+            .sum::<usize>();
+    let queue_bytes: usize = queues.iter().map(DeliveryQueue::bytes).sum();
+    (layer_bytes + synapse_bytes + queue_bytes) as u64
+}
+
+pub fn check_asserts(program: &Program, summary: &SimSummary) -> Vec<Diagnostic> {
+    let Ok(program) = program.select_network(None) else {
+        return Vec::new();
+    };
+    program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Assert(stmt) => check_assert(stmt, summary).err(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn check_assert(stmt: &AssertStmt, summary: &SimSummary) -> Result<(), Diagnostic> {
+    let layer_name = &stmt.metric.layer().name;
+    let layer = summary
+        .layers
+        .iter()
+        .find(|l| &l.name == layer_name)
+        .ok_or_else(|| {
+            Diagnostic::new(format!("unknown assertion layer `{layer_name}`"))
+                .with_span(stmt.metric.layer().span.clone())
+        })?;
+
+    let (observed, unit) = match stmt.metric {
+        Metric::Spikes { .. } => (layer.spikes as f64, ""),
+        Metric::Rate { .. } => (
+            rate_hz(layer.spikes, layer.size, summary.duration_ns),
+            " Hz",
+        ),
+    };
+
+    let (holds, expected) = match &stmt.cond {
+        AssertCond::Compare { op, value } => {
+            let bound = assert_bound(&stmt.metric, value)?;
+            (
+                op.holds(observed, bound),
+                format!("{} {value}", op.as_str()),
+            )
         }
+        AssertCond::Between { low, high } => {
+            let lo = assert_bound(&stmt.metric, low)?;
+            let hi = assert_bound(&stmt.metric, high)?;
+            (
+                lo <= observed && observed <= hi,
+                format!("between {low} and {high}"),
+            )
+        }
+    };
 
-        connections.push(Connection {
-            src_layer: src_idx,
-            dst_layer: dst_idx,
-            synapses,
-        });
+    if holds {
+        Ok(())
+    } else {
+        Err(Diagnostic::new(format!(
+            "assertion failed: {} {expected} (observed {observed}{unit})",
+            stmt.metric
+        ))
+        .with_span(stmt.span.clone()))
     }
+}
 
-    Ok(connections)
+/// Mean firing rate of `neurons` neurons that fired `spikes` times in
+/// `duration_ns`, in Hz.
+fn rate_hz(spikes: u64, neurons: u64, duration_ns: i64) -> f64 {
+    let neuron_seconds = neurons as f64 * (duration_ns as f64 / 1_000_000_000.0);
+    if neuron_seconds > 0.0 {
+        spikes as f64 / neuron_seconds
+    } else {
+        0.0
+    }
 }
 
-fn lif_params(neuron: &NeuronDef) -> Result<LifParams, SimError> {
-    let mut tau_m_ns = 20_000_000;
-    let mut v_th = 1.0;
-    for assign in &neuron.body {
-        match assign.key.name.as_str() {
-            "tau_m" => {
-                if let Expr::Number(q) = &assign.value {
-                    tau_m_ns = time_to_nanos(q, "tau_m").map_err(to_err)?;
-                    if tau_m_ns <= 0 {
-                        return Err(SimError {
-                            message: "tau_m must be positive".to_string(),
-                        });
-                    }
-                } else {
-                    return Err(SimError {
-                        message: "tau_m must be a time quantity".to_string(),
-                    });
-                }
-            }
-            "v_th" => {
-                if let Expr::Number(q) = &assign.value {
-                    v_th = q.value;
-                } else {
-                    return Err(SimError {
-                        message: "v_th must be a number".to_string(),
-                    });
-                }
-            }
-            _ => {}
+/// Version of the [`summary_json`] layout, raised whenever a field changes
+/// meaning or is removed.
+pub const SUMMARY_SCHEMA_VERSION: u32 = 1;
+
+/// Identifies the program text a summary came from, for
+/// `SimSummary::input_hash`: 64-bit FNV-1a over its UTF-8 bytes.
+pub fn source_hash(src: &str) -> u64 {
+    let mut hasher = SpikeHasher::new();
+    hasher.write(src.as_bytes());
+    hasher.finish()
+}
+
+/// [`source_hash`] over everything `reader` yields, read in chunks so
+/// large output files can be fingerprinted.
+pub fn content_hash(mut reader: impl io::Read) -> io::Result<u64> {
+    let mut hasher = SpikeHasher::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(hasher.finish()),
+            Ok(n) => hasher.write(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
         }
     }
-    Ok(LifParams { tau_m_ns, v_th })
 }
 
-struct LifParams {
-    tau_m_ns: i64,
-    v_th: f64,
+/// The `config` object of [`summary_json`].
+pub fn run_config_json(config: &RunConfig) -> String {
+    fn opt<T: fmt::Display>(value: &Option<T>) -> String {
+        value
+            .as_ref()
+            .map_or_else(|| "null".to_string(), T::to_string)
+    }
+    let fields = [
+        (
+            "compensated_summation",
+            config.compensated_summation.to_string(),
+        ),
+        ("profile", config.profile.to_string()),
+        ("record_spikes", config.record_spikes.to_string()),
+        ("record_input", config.record_input.to_string()),
+        ("trace_neurons", config.trace_neurons.to_string()),
+        ("learning_rate", opt(&config.learning_rate)),
+        ("epochs", opt(&config.epochs)),
+        (
+            "energy",
+            opt(&config.energy.as_ref().map(|name| format!("\"{name}\""))),
+        ),
+        ("scratch", config.scratch.to_string()),
+        ("replay_input", config.replay_input.to_string()),
+    ];
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("    \"{key}\": {value}"))
+        .collect();
+    format!("{{\n{}\n  }}", fields.join(",\n"))
 }
 
-fn find_dist(body: &[Assign], key: &str, is_time: bool) -> Result<Dist, SimError> {
-    let expr = body.iter().find(|a| a.key.name == key).map(|a| &a.value);
-    match expr {
-        Some(expr) => dist_from_expr(expr, is_time),
-        None => Ok(if is_time {
-            Dist::Const(0.0)
-        } else {
-            Dist::Const(1.0)
-        }),
+/// The `defaults` array of a summary, indented to sit at its top level.
+pub(crate) fn defaults_json(defaults: &[AppliedDefault]) -> String {
+    if defaults.is_empty() {
+        return "[]".to_string();
     }
+    let entries: Vec<String> = defaults
+        .iter()
+        .map(|d| {
+            format!(
+                "    {{ \"item\": \"{}\", \"key\": \"{}\", \"value\": \"{}\" }}",
+                d.item, d.key, d.value
+            )
+        })
+        .collect();
+    format!("[\n{}\n  ]", entries.join(",\n"))
 }
 
-fn dist_from_expr(expr: &Expr, is_time: bool) -> Result<Dist, SimError> {
-    match expr {
-        Expr::Number(q) => {
-            let value = if is_time {
-                time_to_nanos(q, "delay").map_err(to_err)? as f64
-            } else {
-                q.value
-            };
-            Ok(Dist::Const(value))
+pub fn summary_json(summary: &SimSummary) -> String {
+    let mut s = String::new();
+    s.push_str("{\n");
+    s.push_str(&format!(
+        "  \"schema_version\": {SUMMARY_SCHEMA_VERSION},\n"
+    ));
+    s.push_str(&format!(
+        "  \"toolchain_version\": \"{}\",\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    if let Some(hash) = summary.input_hash {
+        s.push_str(&format!(
+            "  \"input_hash\": \"{}\",\n",
+            format_spike_hash(hash)
+        ));
+    }
+    if let Some(wall_ns) = summary.wall_ns {
+        s.push_str(&format!("  \"wall_ns\": {wall_ns},\n"));
+    }
+    s.push_str(&format!("  \"duration_ns\": {},\n", summary.duration_ns));
+    s.push_str(&format!("  \"step_ns\": {},\n", summary.step_ns));
+    s.push_str(&format!("  \"steps\": {},\n", summary.steps));
+    s.push_str(&format!("  \"seed\": {},\n", summary.seed));
+    s.push_str(&format!("  \"streams\": {},\n", streams_json(summary.seed)));
+    if let Some(quantize) = summary.quantize {
+        s.push_str(&format!("  \"quantize\": \"{quantize}\",\n"));
+    }
+    if summary.arithmetic != Arithmetic::Float {
+        s.push_str(&format!("  \"arithmetic\": \"{}\",\n", summary.arithmetic));
+    }
+    s.push_str(&format!(
+        "  \"config\": {},\n",
+        run_config_json(&summary.config)
+    ));
+    s.push_str(&format!("  \"total_spikes\": {},\n", summary.total_spikes));
+    s.push_str(&format!(
+        "  \"spike_hash\": \"{}\",\n",
+        format_spike_hash(summary.spike_hash)
+    ));
+    s.push_str("  \"layers\": [\n");
+    for (idx, layer) in summary.layers.iter().enumerate() {
+        s.push_str("    {\n");
+        s.push_str(&format!("      \"name\": \"{}\",\n", layer.name));
+        s.push_str(&format!("      \"size\": {},\n", layer.size));
+        s.push_str(&format!("      \"spikes\": {}\n", layer.spikes));
+        s.push_str("    }");
+        if idx + 1 != summary.layers.len() {
+            s.push(',');
         }
-        Expr::Call(call) => {
-            let mut args = Vec::new();
-            for arg in &call.args {
-                let expr = match arg {
-                    CallArg::Positional(e) => e,
-                    CallArg::Named { value, .. } => value,
-                };
-                if let Expr::Number(q) = expr {
-                    let value = if is_time {
-                        time_to_nanos(q, "delay").map_err(to_err)? as f64
-                    } else {
-                        q.value
-                    };
-                    args.push(value);
-                } else {
-                    return Err(SimError {
-                        message: "distribution arguments must be numbers".to_string(),
-                    });
-                }
+        s.push('\n');
+    }
+    s.push_str("  ],\n");
+    s.push_str("  \"connections\": [");
+    for (idx, conn) in summary.connections.iter().enumerate() {
+        if idx != 0 {
+            s.push(',');
+        }
+        let name = conn
+            .name
+            .as_ref()
+            .map_or(String::new(), |name| format!("\"name\": \"{name}\", "));
+        s.push_str(&format!(
+            "\n    {{ {name}\"src\": \"{}\", \"dst\": \"{}\", \"deliveries\": {}, \"mean_weight\": {} }}",
+            conn.src,
+            conn.dst,
+            conn.deliveries,
+            conn.mean_weight()
+        ));
+    }
+    if !summary.connections.is_empty() {
+        s.push_str("\n  ");
+    }
+    s.push_str(&format!(
+        "],\n  \"defaults\": {}",
+        defaults_json(&summary.defaults)
+    ));
+    let queues: Vec<String> = summary
+        .queues
+        .layers
+        .iter()
+        .map(|q| format!("\"{}\": {}", q.layer, q.bytes))
+        .collect();
+    s.push_str(&format!(
+        ",\n  \"queues\": {{ \"max_delay_steps\": {}, \"layer_bytes\": {{ {} }} }}",
+        summary.queues.max_delay_steps,
+        queues.join(", ")
+    ));
+    if let Some(profile) = &summary.profile {
+        s.push_str(",\n  \"profile\": {\n");
+        s.push_str(&format!(
+            "    \"network_bytes\": {},\n",
+            profile.network_bytes
+        ));
+        s.push_str("    \"phases\": [\n");
+        for (idx, phase) in profile.phases.iter().enumerate() {
+            s.push_str(&format!(
+                "      {{ \"name\": \"{}\", \"ns\": {} }}",
+                phase.name, phase.nanos
+            ));
+            if idx + 1 != profile.phases.len() {
+                s.push(',');
             }
-            if args.len() != 2 {
-                return Err(SimError {
-                    message: "distribution requires two arguments".to_string(),
-                });
+            s.push('\n');
+        }
+        s.push_str("    ]\n  }");
+    }
+    if let Some(samples) = &summary.samples {
+        s.push_str(",\n  \"samples\": [\n");
+        for (idx, sample) in samples.iter().enumerate() {
+            let counts: Vec<String> = sample.counts.iter().map(u64::to_string).collect();
+            s.push_str(&format!(
+                "    {{ \"epoch\": {}, \"sample\": {}, \"counts\": [{}] }}",
+                sample.epoch,
+                sample.sample,
+                counts.join(", ")
+            ));
+            if idx + 1 != samples.len() {
+                s.push(',');
             }
-            match call.name.name.as_str() {
-                "Uniform" => Ok(Dist::Uniform(args[0], args[1])),
-                "Normal" => Ok(Dist::Normal(args[0], args[1])),
-                _ => Err(SimError {
-                    message: "unsupported distribution".to_string(),
-                }),
+            s.push('\n');
+        }
+        s.push_str("  ]");
+    }
+    if let Some(c) = &summary.classification {
+        s.push_str(",\n  \"classification\": {\n");
+        s.push_str(&format!("    \"layer\": \"{}\",\n", c.layer));
+        s.push_str(&format!("    \"presentations\": {},\n", c.presentations));
+        s.push_str(&format!("    \"correct\": {},\n", c.correct));
+        s.push_str(&format!("    \"silent\": {},\n", c.silent));
+        s.push_str(&format!("    \"accuracy\": {},\n", c.accuracy()));
+        s.push_str("    \"confusion\": [");
+        for (idx, row) in c.confusion.iter().enumerate() {
+            let row: Vec<String> = row.iter().map(u64::to_string).collect();
+            if idx != 0 {
+                s.push(',');
             }
+            s.push_str(&format!("\n      [{}]", row.join(", ")));
         }
-        _ => Err(SimError {
-            message: "expected number or distribution".to_string(),
-        }),
+        s.push_str("\n    ]\n  }");
     }
+    if let Some(e) = &summary.energy {
+        s.push_str(",\n  \"energy\": {\n");
+        s.push_str(&format!("    \"target\": \"{}\",\n", e.target));
+        s.push_str(&format!("    \"cores\": {},\n", e.cores));
+        s.push_str(&format!("    \"neuron_updates\": {},\n", e.neuron_updates));
+        s.push_str(&format!(
+            "    \"synaptic_events\": {},\n",
+            e.synaptic_events
+        ));
+        s.push_str(&format!("    \"spike_messages\": {},\n", e.spike_messages));
+        s.push_str(&format!("    \"router_hops\": {},\n", e.router_hops));
+        s.push_str(&format!("    \"neuron_pj\": {},\n", e.neuron_energy_pj()));
+        s.push_str(&format!(
+            "    \"synaptic_pj\": {},\n",
+            e.synaptic_energy_pj()
+        ));
+        s.push_str(&format!("    \"router_pj\": {},\n", e.router_energy_pj()));
+        s.push_str(&format!("    \"total_pj\": {}\n  }}", e.total_pj()));
+    }
+    s.push_str("\n}\n");
+    s
 }
 
-fn sample_dist(dist: &Dist, rng: &mut Rng) -> f64 {
-    match dist {
-        Dist::Const(v) => *v,
-        Dist::Uniform(a, b) => a + (b - a) * rng.next_f64(),
-        Dist::Normal(mu, sigma) => {
-            let (u1, u2) = (rng.next_f64(), rng.next_f64());
-            let z0 = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
-            mu + z0 * sigma
-        }
-    }
+/// An empty `Vec` with room for `len` items, or
+/// [`SimErrorKind::OutOfMemory`] where `Vec::with_capacity` would abort.
+fn try_with_capacity<T>(len: usize) -> Result<Vec<T>, SimError> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(len).map_err(|_| {
+        SimError::new(SimErrorKind::OutOfMemory {
+            bytes: (len as u64).saturating_mul(std::mem::size_of::<T>() as u64),
+        })
+    })?;
+    Ok(v)
 }
 
-fn to_err(diag: converge_lang::diagnostic::Diagnostic) -> SimError {
-    SimError {
-        message: diag.message,
+/// `len` buckets of `size` copies of `zero`, allocated with
+/// [`try_with_capacity`].
+fn try_buckets<T: Clone>(len: usize, size: usize, zero: T) -> Result<Vec<Vec<T>>, SimError> {
+    let mut buckets = try_with_capacity(len)?;
+    for _ in 0..len {
+        let mut bucket = try_with_capacity(size)?;
+        bucket.resize(size, zero.clone());
+        buckets.push(bucket);
     }
+    Ok(buckets)
 }
 
-struct Rng {
-    state: u64,
+/// Ring buffer of pending synaptic input for one layer, one slot per step of
+/// delay. Additions land in delivery order, optionally Kahan-compensated.
+#[derive(Clone)]
+struct DeliveryQueue {
+    sums: Vec<Vec<f64>>,
+    comp: Option<Vec<Vec<f64>>>,
+    /// Quantized runs sum whole LSBs here instead, with the LSB's value.
+    fixed: Option<(Vec<Vec<i64>>, f64)>,
 }
 
-impl Rng {
-    fn new(seed: u64) -> Self {
+impl DeliveryQueue {
+    fn new(size: usize, len: usize, compensated: bool, lsb: Option<f64>) -> Result<Self, SimError> {
+        if let Some(lsb) = lsb {
+            return Ok(Self {
+                sums: Vec::new(),
+                comp: None,
+                fixed: Some((try_buckets(len, size, 0)?, lsb)),
+            });
+        }
+        Ok(Self {
+            sums: try_buckets(len, size, 0.0)?,
+            comp: compensated
+                .then(|| try_buckets(len, size, 0.0))
+                .transpose()?,
+            fixed: None,
+        })
+    }
+
+    /// Heap bytes held by the buckets.
+    fn bytes(&self) -> usize {
+        let floats = self.sums.iter().chain(self.comp.iter().flatten());
+        let fixed = self.fixed.iter().flat_map(|(sums, _)| sums);
+        floats
+            .map(|bucket| bucket.capacity() * std::mem::size_of::<f64>())
+            .chain(fixed.map(|bucket| bucket.capacity() * std::mem::size_of::<i64>()))
+            .sum()
+    }
+
+    fn add_units(&mut self, bucket: usize, i: usize, units: i64) {
+        if let Some((sums, _)) = &mut self.fixed {
+            let sum = &mut sums[bucket][i];
+            *sum = sum.saturating_add(units);
+        }
+    }
+
+    fn add(&mut self, bucket: usize, i: usize, x: f64) {
+        let sum = &mut self.sums[bucket][i];
+        match &mut self.comp {
+            Some(comp) => {
+                let c = &mut comp[bucket][i];
+                let y = x - *c;
+                let t = *sum + y;
+                *c = (t - *sum) - y;
+                *sum = t;
+            }
+            None => *sum += x,
+        }
+    }
+
+    fn drain_into<'a>(&mut self, bucket: usize, v: impl Iterator<Item = &'a mut f64>) {
+        if let Some((sums, lsb)) = &mut self.fixed {
+            for (v_i, pending) in v.zip(sums[bucket].iter_mut()) {
+                *v_i += *pending as f64 * *lsb;
+                *pending = 0;
+            }
+            return;
+        }
+        for (v_i, pending) in v.zip(self.sums[bucket].iter_mut()) {
+            *v_i += *pending;
+            *pending = 0.0;
+        }
+        if let Some(comp) = &mut self.comp {
+            comp[bucket].fill(0.0);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct LayerState {
+    name: String,
+    size: usize,
+    tau_m_ns: i64,
+    v_th: f64,
+    v: Vec<f64>,
+    /// Dendritic compartments per neuron, chained from the soma.
+    compartments: usize,
+    g_axial: f64,
+    /// Compartment-major, as in [`LayerView::dendrites`].
+    dendrites: Vec<f64>,
+    /// The threshold is shifted by `modulator_gain` times this level.
+    modulator: Option<usize>,
+    modulator_gain: f64,
+    spikes: u64,
+}
+
+impl LayerState {
+    /// Axial pass along each neuron's soma-dendrite chain. Neighbouring
+    /// compartments move toward each other by `g_axial * (v_next - v_self) *
+    /// dt / tau_m`, reading potentials from before the pass.
+    fn conduct(&mut self, decay: f64) {
+        let Some(last) = self.compartments.checked_sub(1) else {
+            return;
+        };
+        let g = self.g_axial * decay;
+        let size = self.size;
+        for i in 0..size {
+            // `inflow` is what compartment k received from k - 1 (the
+            // negated flow k - 1 received from k).
+            let mut inflow = 0.0;
+            let mut v = self.v[i];
+            for k in 0..=last {
+                let next = self.dendrites[k * size + i];
+                let flow = g * (next - v);
+                let updated = v + flow - inflow;
+                match k {
+                    0 => self.v[i] = updated,
+                    _ => self.dendrites[(k - 1) * size + i] = updated,
+                }
+                inflow = flow;
+                v = next;
+            }
+            self.dendrites[last * size + i] = v - inflow;
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Connection {
+    name: Option<String>,
+    kind: ConnectKind,
+    src_layer: usize,
+    dst_layer: usize,
+    /// Destination compartment; 0 is the soma.
+    compartment: usize,
+    synapses: Vec<Vec<Synapse>>,
+    plasticity: Option<Plasticity>,
+    delay_plasticity: Option<DelayPlasticity>,
+    /// Fixed-point grid of a quantized run.
+    grid: Option<Grid>,
+    /// Set when the synapses live in a scratch file; `synapses` is then
+    /// empty.
+    mapped: Option<Arc<MappedRows>>,
+}
+
+impl Connection {
+    fn synapse_count(&self) -> usize {
+        match &self.mapped {
+            Some(mapped) => mapped.len(),
+            None => self.synapses.iter().map(Vec::len).sum(),
+        }
+    }
+
+    /// Synapses of source neuron `i`, wherever they are stored.
+    fn row(&self, i: usize) -> Box<dyn Iterator<Item = Synapse> + '_> {
+        match &self.mapped {
+            Some(mapped) => Box::new(mapped.row(i)),
+            None => Box::new(self.synapses[i].iter().cloned()),
+        }
+    }
+}
+
+/// Deliveries of one connection so far.
+#[derive(Clone, Default)]
+struct Traffic {
+    deliveries: u64,
+    weight_sum: f64,
+}
+
+#[derive(Clone)]
+struct Synapse {
+    dst: usize,
+    weight: f64,
+    delay_steps: usize,
+}
+
+#[derive(Clone)]
+enum Dist {
+    Const(f64),
+    Uniform(f64, f64),
+    Normal(f64, f64),
+}
+
+fn collect_neuron_defs(program: &Program) -> Result<HashMap<String, NeuronDef>, SimError> {
+    let mut map = HashMap::new();
+    for item in &program.items {
+        if let Item::Neuron(def) = item {
+            map.insert(def.name.name.clone(), def.clone());
+        }
+    }
+    Ok(map)
+}
+
+fn build_layers(
+    program: &Program,
+    neuron_defs: &HashMap<String, NeuronDef>,
+    modulator_index: &HashMap<String, usize>,
+) -> Result<(Vec<LayerState>, HashMap<String, usize>), SimError> {
+    let mut layers = Vec::new();
+    let mut index = HashMap::new();
+
+    for item in &program.items {
+        if let Item::Layer(def) = item {
+            let neuron = neuron_defs.get(&def.neuron.name).ok_or_else(|| {
+                SimError::new(SimErrorKind::UnknownNeuron(def.neuron.name.clone()))
+                    .with_span(def.neuron.span.clone())
+            })?;
+            let params = lif_params(neuron)?;
+            let modulator = neuron
+                .body
+                .iter()
+                .find(|a| a.key.name == "modulator")
+                .map(|a| modulator_ref(&a.value, modulator_index))
+                .transpose()?;
+
+            let size = def.size as usize;
+            index.insert(def.name.name.clone(), layers.len());
+            layers.push(LayerState {
+                name: def.name.name.clone(),
+                size,
+                tau_m_ns: params.tau_m_ns,
+                v_th: params.v_th,
+                v: vec![0.0; size],
+                compartments: params.compartments,
+                g_axial: params.g_axial,
+                dendrites: vec![0.0; size * params.compartments],
+                modulator,
+                modulator_gain: params.modulator_gain,
+                spikes: 0,
+            });
+        }
+    }
+
+    Ok((layers, index))
+}
+
+/// External input to one layer: a uniform Poisson rate plus any dataset
+/// encoders.
+#[derive(Default)]
+struct LayerStimulus {
+    /// The layer's stimulus rates, in groups that combine in source order.
+    rates: Vec<RateGroup>,
+    /// Whether any input is Poisson, so each neuron draws every step.
+    poisson: bool,
+    encoded: Vec<EncodedInput>,
+    external: Vec<ExternalSource>,
+    /// `Poisson` stimuli with a `correlation`, in source order.
+    correlated: Vec<CorrelatedPoisson>,
+}
+
+/// Stimulus rates on one layer that add up: constant `Poisson` rates, then
+/// the `Encode` inputs and `External` sources in the given ranges of the
+/// layer's lists. A group combines with the groups before it by `combine`,
+/// which is never `Replace`, as replaced stimuli are left out.
+struct RateGroup {
+    combine: StimulusCombine,
+    rate_hz: f64,
+    encoded: Range<usize>,
+    external: Range<usize>,
+}
+
+/// A `Poisson` stimulus whose neurons share spikes: each step a shared
+/// process fires with probability `p`, and when it does every neuron copies
+/// the spike with probability `correlation`, so each neuron spikes with
+/// probability `p * correlation` per step.
+struct CorrelatedPoisson {
+    p: f64,
+    correlation: f64,
+    /// Whether the shared process fired this step.
+    fired: bool,
+}
+
+impl LayerStimulus {
+    fn rate_hz(&self, step: usize, neuron: usize) -> f64 {
+        self.rates.iter().fold(0.0, |rate, group| {
+            let own = self.encoded[group.encoded.clone()]
+                .iter()
+                .fold(group.rate_hz, |rate, enc| rate + enc.rate_hz(step, neuron));
+            let own = self.external[group.external.clone()]
+                .iter()
+                .fold(own, |rate, ext| rate + ext.rate_hz(neuron));
+            match group.combine {
+                StimulusCombine::Max => rate.max(own),
+                StimulusCombine::Sum | StimulusCombine::Replace => rate + own,
+            }
+        })
+    }
+
+    /// The index of the group the next stimulus's rate joins: the last
+    /// one, unless either of them takes the maximum.
+    fn rate_group(&mut self, combine: StimulusCombine) -> usize {
+        let (encoded, external) = (self.encoded.len(), self.external.len());
+        let joins = matches!(self.rates.last(), Some(last) if last.combine != StimulusCombine::Max)
+            && combine != StimulusCombine::Max;
+        if !joins {
+            self.rates.push(RateGroup {
+                combine: if combine == StimulusCombine::Max {
+                    StimulusCombine::Max
+                } else {
+                    StimulusCombine::Sum
+                },
+                rate_hz: 0.0,
+                encoded: encoded..encoded,
+                external: external..external,
+            });
+        }
+        self.rates.len() - 1
+    }
+
+    /// Membrane input from `External` currents for `neuron` this step.
+    fn current(&self, neuron: usize) -> f64 {
+        self.external.iter().map(|ext| ext.current(neuron)).sum()
+    }
+
+    /// Latency-coded spikes for `neuron` this step.
+    fn timed_spikes(&self, step: usize, neuron: usize) -> usize {
+        self.encoded
+            .iter()
+            .filter(|enc| enc.fires(step, neuron))
+            .count()
+    }
+}
+
+fn collect_stimuli(
+    program: &Program,
+    layer_index: &HashMap<String, usize>,
+    layers: &[LayerState],
+    step_ns: i64,
+    config: &SimConfig,
+    plan: &Plan,
+) -> Result<Vec<Option<LayerStimulus>>, SimError> {
+    let mut stimuli: Vec<Option<LayerStimulus>> = (0..layer_index.len()).map(|_| None).collect();
+    // A `combine = replace` stimulus drops every earlier one on its layer.
+    let mut first_kept: HashMap<&str, usize> = HashMap::new();
+    for (pos, item) in program.items.iter().enumerate() {
+        if let Item::Stimulus(def) = item
+            && def.combine == Some(StimulusCombine::Replace)
+        {
+            first_kept.insert(&def.layer.name, pos);
+        }
+    }
+    for (pos, item) in program.items.iter().enumerate() {
+        if let Item::Stimulus(StimulusDef {
+            layer,
+            model,
+            combine,
+            ..
+        }) = item
+        {
+            if first_kept
+                .get(layer.name.as_str())
+                .is_some_and(|&kept| pos < kept)
+            {
+                continue;
+            }
+            let combine = combine.unwrap_or(StimulusCombine::Sum);
+            let idx = *layer_index.get(&layer.name).ok_or_else(|| {
+                SimError::new(SimErrorKind::UnknownLayer {
+                    role: "stimulus",
+                    name: layer.name.clone(),
+                })
+                .with_span(layer.span.clone())
+            })?;
+            // Rates targeting one layer are summed in source order.
+            let stimulus = stimuli[idx].get_or_insert_with(LayerStimulus::default);
+            match model {
+                StimulusModel::Poisson { rate, correlation } => {
+                    let rate_hz = rate_to_hz(rate, "Poisson rate").map_err(unit_err)?;
+                    match correlation {
+                        Some(c) if c.value > 0.0 => {
+                            let p = rate_hz * (step_ns as f64 / 1_000_000_000.0) / c.value;
+                            if p > 1.0 {
+                                return Err(SimError::new(SimErrorKind::RateTooHigh {
+                                    layer: layer.name.clone(),
+                                })
+                                .with_span(rate.span.clone()));
+                            }
+                            stimulus.correlated.push(CorrelatedPoisson {
+                                p,
+                                correlation: c.value,
+                                fired: false,
+                            });
+                        }
+                        _ => {
+                            let group = stimulus.rate_group(combine);
+                            stimulus.rates[group].rate_hz += rate_hz;
+                            stimulus.poisson = true;
+                        }
+                    }
+                }
+                StimulusModel::Encode(enc) => {
+                    let input = EncodedInput::build(
+                        enc,
+                        layers[idx].size,
+                        step_ns,
+                        config.data_files(),
+                        plan,
+                    )?;
+                    stimulus.poisson |= input.is_poisson();
+                    let group = stimulus.rate_group(combine);
+                    stimulus.encoded.push(input);
+                    stimulus.rates[group].encoded.end = stimulus.encoded.len();
+                }
+                StimulusModel::External(ext) => {
+                    let source = ExternalSource::bind(ext, layers[idx].size)?;
+                    stimulus.poisson |= source.is_poisson();
+                    let group = stimulus.rate_group(combine);
+                    stimulus.external.push(source);
+                    stimulus.rates[group].external.end = stimulus.external.len();
+                }
+            }
+        }
+    }
+    Ok(stimuli)
+}
+
+fn collect_rewards(program: &Program, step_ns: i64) -> Result<Vec<(usize, f64)>, SimError> {
+    let mut rewards = Vec::new();
+    for item in &program.items {
+        if let Item::Reward(RewardStmt { value, at, .. }) = item {
+            let at_ns = time_to_nanos(at, "reward time").map_err(unit_err)?;
+            if at_ns < 0 {
+                return Err(SimError::new(SimErrorKind::Invalid(
+                    "reward time must not be negative".to_string(),
+                ))
+                .with_span(at.span.clone()));
+            }
+            rewards.push(((at_ns / step_ns) as usize, value.value));
+        }
+    }
+    // Stable, so pulses on one step are summed in source order.
+    rewards.sort_by_key(|&(step, _)| step);
+    Ok(rewards)
+}
+
+/// A trace per `record rate` item. The smoothing is only stable with a
+/// window of at least one step.
+fn collect_rate_traces(
+    program: &Program,
+    layer_index: &HashMap<String, usize>,
+    step_ns: i64,
+    steps: usize,
+) -> Result<Vec<RateTrace>, SimError> {
+    let mut traces = Vec::new();
+    for item in &program.items {
+        let Item::Record(RecordStmt { layer, window, .. }) = item else {
+            continue;
+        };
+        let idx = *layer_index.get(&layer.name).ok_or_else(|| {
+            SimError::new(SimErrorKind::UnknownLayer {
+                role: "recorded",
+                name: layer.name.clone(),
+            })
+            .with_span(layer.span.clone())
+        })?;
+        let window_ns = time_to_nanos(window, "smoothing window").map_err(unit_err)?;
+        if window_ns < step_ns {
+            return Err(SimError::new(SimErrorKind::Invalid(format!(
+                "smoothing window {window} for `{}` is shorter than the run step",
+                layer.name
+            )))
+            .with_span(window.span.clone()));
+        }
+        traces.push(RateTrace {
+            layer: idx as u32,
+            window_ns,
+            samples: Vec::with_capacity(steps),
+        });
+    }
+    Ok(traces)
+}
+
+/// A trace per `probe` item. The interval is rounded to whole steps and
+/// must be at least one.
+fn collect_probes(
+    program: &Program,
+    layer_index: &HashMap<String, usize>,
+    layers: &[LayerState],
+    step_ns: i64,
+    steps: usize,
+) -> Result<Vec<ProbeTrace>, SimError> {
+    let mut probes = Vec::new();
+    for item in &program.items {
+        let Item::Probe(def) = item else {
+            continue;
+        };
+        let ProbeDef {
+            name,
+            variable: ProbeVariable::Membrane,
+            layer,
+            neurons,
+            every,
+            ..
+        } = def;
+        let idx = *layer_index.get(&layer.name).ok_or_else(|| {
+            SimError::new(SimErrorKind::UnknownLayer {
+                role: "probed",
+                name: layer.name.clone(),
+            })
+            .with_span(layer.span.clone())
+        })?;
+        let size = layers[idx].size as u64;
+        let (first, end) = neurons.unwrap_or((0, size));
+        if first >= end || end > size {
+            return Err(SimError::new(SimErrorKind::ProbeOutOfRange {
+                probe: name.name.clone(),
+                layer: layer.name.clone(),
+                first,
+                end,
+                size: layers[idx].size,
+            })
+            .with_span(def.span.clone()));
+        }
+        let every_steps = match every {
+            Some(every) => {
+                let ns = time_to_nanos(every, "probe interval").map_err(unit_err)?;
+                let every_steps = (ns as f64 / step_ns as f64).round() as u64;
+                if every_steps == 0 {
+                    return Err(SimError::new(SimErrorKind::Invalid(format!(
+                        "probe interval {every} for `{}` is shorter than the run step",
+                        name.name
+                    )))
+                    .with_span(every.span.clone()));
+                }
+                every_steps
+            }
+            None => 1,
+        };
+        let count = steps.div_ceil(every_steps as usize);
+        probes.push(ProbeTrace {
+            name: name.name.clone(),
+            layer: idx as u32,
+            first,
+            every_steps,
+            samples: vec![Vec::with_capacity(count); (end - first) as usize],
+        });
+    }
+    Ok(probes)
+}
+
+fn build_connections(
+    program: &Program,
+    layer_index: &HashMap<String, usize>,
+    modulator_index: &HashMap<String, usize>,
+    layers: &mut [LayerState],
+    step_ns: i64,
+    seed: u64,
+    config: &SimConfig,
+) -> Result<Vec<Connection>, SimError> {
+    let mut rng = Rng::new(Stream::Connectivity.seed(seed));
+    let mut connections = Vec::new();
+    let mut defs: Vec<&ConnectDef> = Vec::new();
+    let mut rules: Vec<Option<RStdp>> = Vec::new();
+    let mut delay_rules: Vec<Option<(DelayStdp, Span)>> = Vec::new();
+
+    for (pos, item) in program.items.iter().enumerate() {
+        let def = match item {
+            Item::Connect(def) => def,
+            Item::Disconnect(stmt) => {
+                // Synapses are sampled before any are removed, so a lesion
+                // leaves the weights and delays of the rest unchanged.
+                for (def, conn) in defs.iter().zip(connections.iter_mut()) {
+                    if stmt.target.matches(def) {
+                        disconnect(conn, stmt.filter.as_ref());
+                    }
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        defs.push(def);
+        let ConnectDef {
+            name,
+            src,
+            dst,
+            kind,
+            body,
+            ..
+        } = def;
+        let src_idx = *layer_index.get(&src.name).ok_or_else(|| {
+            SimError::new(SimErrorKind::UnknownLayer {
+                role: "source",
+                name: src.name.clone(),
+            })
+            .with_span(src.span.clone())
+        })?;
+        let dst_idx = *layer_index.get(&dst.name).ok_or_else(|| {
+            SimError::new(SimErrorKind::UnknownLayer {
+                role: "destination",
+                name: dst.name.clone(),
+            })
+            .with_span(dst.span.clone())
+        })?;
+
+        let src_size = layers[src_idx].size;
+        let dst_size = layers[dst_idx].size;
+
+        if *kind == ConnectKind::Electrical {
+            let synapses = build_junctions(body, src_idx == dst_idx, src_size, dst_size, &mut rng)
+                .map_err(|e| e.or_span(span_of_key(def, "g")))?;
+            trace::trace!(
+                name = name.as_ref().map_or("", |n| n.name.as_str()),
+                a = %src.name,
+                b = %dst.name,
+                junctions = synapses.iter().map(Vec::len).sum::<usize>(),
+                "built gap junctions"
+            );
+            connections.push(Connection {
+                name: name.as_ref().map(|n| n.name.clone()),
+                kind: ConnectKind::Electrical,
+                src_layer: src_idx,
+                dst_layer: dst_idx,
+                compartment: 0,
+                synapses,
+                plasticity: None,
+                delay_plasticity: None,
+                grid: None,
+                mapped: None,
+            });
+            rules.push(None);
+            delay_rules.push(None);
+            continue;
+        }
+
+        let compartment = find_compartment(body)?;
+        if compartment > layers[dst_idx].compartments {
+            return Err(SimError::new(SimErrorKind::Invalid(format!(
+                "connection targets compartment {compartment}, but `{}` has {} dendritic compartment(s)",
+                dst.name, layers[dst_idx].compartments
+            )))
+            .with_span(span_of_key(def, "compartment")));
+        }
+        // Weights from a file replace sampling, so they draw nothing.
+        let weight_file = load_weights(body, src_size, dst_size, config.data_files())?;
+        let weight_dist = match weight_file {
+            Some(_) => Dist::Const(0.0),
+            None => find_dist(body, "w", None)?,
+        };
+        let delay_dist = find_dist(body, "d", Some(step_ns))?;
+        let whole_steps = delay_in_steps(body);
+        let delay_span = || span_of_key(def, "d");
+        // Plastic connections change their synapses, so they stay in RAM.
+        let plastic = body
+            .iter()
+            .any(|a| a.key.name == "plasticity" || a.key.name == "delay_plasticity");
+        // Normalizing needs every row at once, so it stays in RAM too.
+        let normalize = find_normalize(body)?;
+        let mut writer = match config.scratch.as_deref() {
+            Some(dir) if !plastic && normalize.is_none() => {
+                Some(RowWriter::create(dir, &dst.name)?)
+            }
+            _ => None,
+        };
+        // A spilled row is written once, so the later `disconnect`s of its
+        // connection are applied to it as it is sampled.
+        let lesions: Vec<Option<&SynapseFilter>> = match writer {
+            Some(_) => program.items[pos + 1..]
+                .iter()
+                .filter_map(|later| match later {
+                    Item::Disconnect(stmt) if stmt.target.matches(def) => {
+                        Some(stmt.filter.as_ref())
+                    }
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        // Not `vec![..; n]`: clones of an empty `Vec` drop its capacity.
+        let mut synapses: Vec<Vec<Synapse>> = match writer {
+            Some(_) => Vec::new(),
+            None => {
+                let mut rows = try_with_capacity(src_size)?;
+                for _ in 0..src_size {
+                    rows.push(try_with_capacity(dst_size)?);
+                }
+                rows
+            }
+        };
+        let mut spilled = match writer {
+            Some(_) => try_with_capacity(dst_size)?,
+            None => Vec::new(),
+        };
+
+        for src_i in 0..src_size {
+            let syn_list = match writer {
+                Some(_) => {
+                    spilled.clear();
+                    &mut spilled
+                }
+                None => &mut synapses[src_i],
+            };
+            for dst_i in 0..dst_size {
+                let weight = match &weight_file {
+                    Some(rows) => rows[src_i][dst_i],
+                    None => sample_dist(&weight_dist, &mut rng),
+                };
+                let mut delay_ns = sample_dist(&delay_dist, &mut rng);
+                if whole_steps {
+                    delay_ns = (delay_ns / step_ns as f64).round() * step_ns as f64;
+                }
+                if delay_ns < 0.0 {
+                    return Err(SimError::new(SimErrorKind::NegativeDelay { delay_ns })
+                        .with_span(delay_span()));
+                }
+                let delay_ns_i = delay_ns.round() as i64;
+                if delay_ns_i % step_ns != 0 {
+                    return Err(SimError::new(SimErrorKind::UnevenDelay {
+                        delay_ns: delay_ns_i,
+                        step_ns,
+                    })
+                    .with_span(delay_span()));
+                }
+                let delay_steps = (delay_ns_i / step_ns) as usize;
+                syn_list.push(Synapse {
+                    dst: dst_i,
+                    weight,
+                    delay_steps,
+                });
+            }
+            if let Some(writer) = &mut writer {
+                for filter in &lesions {
+                    match filter {
+                        Some(f) => syn_list.retain(|s| !f.matches(src_i as u64, s.dst as u64)),
+                        None => syn_list.clear(),
+                    }
+                }
+                writer.push_row(syn_list)?;
+            }
+        }
+        if let Some(normalize) = normalize {
+            normalize.apply(&mut synapses, dst_size);
+        }
+
+        trace::trace!(
+            name = name.as_ref().map_or("", |n| n.name.as_str()),
+            src = %src.name,
+            dst = %dst.name,
+            synapses = src_size * dst_size,
+            "built connection"
+        );
+        connections.push(Connection {
+            name: name.as_ref().map(|n| n.name.clone()),
+            kind: ConnectKind::Chemical,
+            src_layer: src_idx,
+            dst_layer: dst_idx,
+            compartment,
+            synapses,
+            plasticity: None,
+            delay_plasticity: None,
+            grid: None,
+            mapped: writer.map(RowWriter::finish).transpose()?.map(Arc::new),
+        });
+        rules.push(
+            body.iter()
+                .find(|a| a.key.name == "plasticity")
+                .map(|a| RStdp::from_expr(&a.value, modulator_index))
+                .transpose()?,
+        );
+        delay_rules.push(
+            body.iter()
+                .find(|a| a.key.name == "delay_plasticity")
+                .map(|a| {
+                    DelayStdp::from_expr(&a.value, step_ns)
+                        .map(|rule| (rule, a.span.clone()))
+                        .map_err(|e| e.or_span(a.span.clone()))
+                })
+                .transpose()?,
+        );
+    }
+
+    // Learning state mirrors the final synapse tables, so it is built once
+    // every `disconnect` has been applied.
+    for ((conn, rule), delay_rule) in connections.iter_mut().zip(rules).zip(delay_rules) {
+        let dst_size = layers[conn.dst_layer].size;
+        if let Some(rule) = rule {
+            conn.plasticity = Some(Plasticity::new(rule, &conn.synapses, dst_size));
+        }
+        if let Some((rule, span)) = delay_rule {
+            let state = DelayPlasticity::new(rule, &conn.synapses, dst_size)
+                .map_err(|e| e.with_span(span))?;
+            conn.delay_plasticity = Some(state);
+        }
+    }
+
+    Ok(connections)
+}
+
+/// Gap junctions reuse the synapse table with `weight` as the coupling `g`
+/// and no delay. Within one layer each unordered pair is coupled once, from
+/// the lower index to the higher.
+fn build_junctions(
+    body: &[Assign],
+    same_layer: bool,
+    src_size: usize,
+    dst_size: usize,
+    rng: &mut Rng,
+) -> Result<Vec<Vec<Synapse>>, SimError> {
+    let g_dist = find_dist(body, "g", None)?;
+    let mut junctions = try_with_capacity(src_size)?;
+    for src_i in 0..src_size {
+        let first = if same_layer { src_i + 1 } else { 0 };
+        let mut list = try_with_capacity(dst_size.saturating_sub(first))?;
+        for dst_i in first..dst_size {
+            let g = sample_dist(&g_dist, rng);
+            if g < 0.0 {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(
+                    "negative gap junction conductance is not allowed".to_string(),
+                )));
+            }
+            list.push(Synapse {
+                dst: dst_i,
+                weight: g,
+                delay_steps: 0,
+            });
+        }
+        junctions.push(list);
+    }
+    Ok(junctions)
+}
+
+/// The `key = ...` of a connection, or the whole connection when it leaves
+/// `key` out.
+fn span_of_key(def: &ConnectDef, key: &str) -> Span {
+    def.body
+        .iter()
+        .find(|a| a.key.name == key)
+        .map_or_else(|| def.span.clone(), |a| a.span.clone())
+}
+
+fn disconnect(conn: &mut Connection, filter: Option<&SynapseFilter>) {
+    for (src, syn_list) in conn.synapses.iter_mut().enumerate() {
+        match filter {
+            Some(f) => syn_list.retain(|s| !f.matches(src as u64, s.dst as u64)),
+            None => syn_list.clear(),
+        }
+    }
+}
+
+fn lif_params(neuron: &NeuronDef) -> Result<LifParams, SimError> {
+    let mut tau_m_ns = defaults::TAU_M_NS;
+    let mut v_th = defaults::V_TH;
+    let mut compartments = 0;
+    let mut g_axial = 1.0;
+    let mut modulator_gain = 1.0;
+    for assign in &neuron.body {
+        match assign.key.name.as_str() {
+            "tau_m" => {
+                if let Expr::Number(q) = &assign.value {
+                    tau_m_ns = time_to_nanos(q, "tau_m").map_err(unit_err)?;
+                    if tau_m_ns <= 0 {
+                        return Err(SimError::new(SimErrorKind::InvalidParameter(
+                            "tau_m must be positive".to_string(),
+                        ))
+                        .with_span(assign.span.clone()));
+                    }
+                } else {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(
+                        "tau_m must be a time quantity".to_string(),
+                    ))
+                    .with_span(assign.span.clone()));
+                }
+            }
+            "v_th" => {
+                if let Expr::Number(q) = &assign.value {
+                    v_th = q.value;
+                } else {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(
+                        "v_th must be a number".to_string(),
+                    ))
+                    .with_span(assign.span.clone()));
+                }
+            }
+            "compartments" => {
+                compartments = count_param(&assign.value, "compartments")
+                    .map_err(|e| e.with_span(assign.span.clone()))?;
+            }
+            "g_axial" => match &assign.value {
+                Expr::Number(q) if q.unit.is_none() && q.value >= 0.0 => g_axial = q.value,
+                _ => {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(
+                        "g_axial must be a non-negative number".to_string(),
+                    ))
+                    .with_span(assign.span.clone()));
+                }
+            },
+            "modulator_gain" => match &assign.value {
+                Expr::Number(q) if q.unit.is_none() => modulator_gain = q.value,
+                _ => {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(
+                        "modulator_gain must be a number".to_string(),
+                    ))
+                    .with_span(assign.span.clone()));
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(LifParams {
+        tau_m_ns,
+        v_th,
+        compartments,
+        g_axial,
+        modulator_gain,
+    })
+}
+
+struct LifParams {
+    tau_m_ns: i64,
+    v_th: f64,
+    compartments: usize,
+    g_axial: f64,
+    modulator_gain: f64,
+}
+
+/// `normalize = total(x)` or `normalize = per_target(x)`: the sum the
+/// connection's weights are scaled to, all together or per destination
+/// neuron.
+#[derive(Clone, Copy)]
+enum Normalize {
+    Total(f64),
+    PerTarget(f64),
+}
+
+impl Normalize {
+    /// Scales the weights to the sum. A group whose weights sum to 0 has
+    /// nothing to scale and is left alone.
+    fn apply(self, synapses: &mut [Vec<Synapse>], dst_size: usize) {
+        let (sum, groups) = match self {
+            Self::Total(sum) => (sum, 1),
+            Self::PerTarget(sum) => (sum, dst_size),
+        };
+        let group = |syn: &Synapse| match self {
+            Self::Total(_) => 0,
+            Self::PerTarget(_) => syn.dst,
+        };
+        let mut totals = vec![0.0; groups];
+        for syn in synapses.iter().flatten() {
+            totals[group(syn)] += syn.weight;
+        }
+        for syn in synapses.iter_mut().flatten() {
+            let total = totals[group(syn)];
+            if total != 0.0 {
+                syn.weight *= sum / total;
+            }
+        }
+    }
+}
+
+fn find_normalize(body: &[Assign]) -> Result<Option<Normalize>, SimError> {
+    let Some(assign) = body.iter().find(|a| a.key.name == "normalize") else {
+        return Ok(None);
+    };
+    let invalid = || {
+        SimError::new(SimErrorKind::InvalidParameter(
+            "normalize must be `total(<sum>)` or `per_target(<sum>)`".to_string(),
+        ))
+        .with_span(assign.span.clone())
+    };
+    let Expr::Call(call) = &assign.value else {
+        return Err(invalid());
+    };
+    let sum = match call.args.as_slice() {
+        [CallArg::Positional(Expr::Number(q))] if q.unit.is_none() => q.value,
+        _ => return Err(invalid()),
+    };
+    match call.name.name.as_str() {
+        "total" => Ok(Some(Normalize::Total(sum))),
+        "per_target" => Ok(Some(Normalize::PerTarget(sum))),
+        _ => Err(invalid()),
+    }
+}
+
+/// The dendritic compartment a connection targets; the soma when absent.
+fn find_compartment(body: &[Assign]) -> Result<usize, SimError> {
+    body.iter()
+        .find(|a| a.key.name == "compartment")
+        .map_or(Ok(0), |a| {
+            count_param(&a.value, "compartment").map_err(|e| e.with_span(a.span.clone()))
+        })
+}
+
+fn count_param(expr: &Expr, key: &str) -> Result<usize, SimError> {
+    match expr {
+        Expr::Number(q) if q.unit.is_none() && q.value >= 0.0 && q.value.fract() == 0.0 => {
+            Ok(q.value as usize)
+        }
+        _ => Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+            "{key} must be a non-negative integer"
+        )))),
+    }
+}
+
+/// The distribution `key` is drawn from, in nanoseconds when `delay_step`
+/// gives the run step `key` is a delay in. Errors without a more precise
+/// span point at the whole `key = ...`.
+fn find_dist(body: &[Assign], key: &str, delay_step: Option<i64>) -> Result<Dist, SimError> {
+    match body.iter().find(|a| a.key.name == key) {
+        Some(assign) => {
+            dist_from_expr(&assign.value, delay_step).map_err(|e| e.or_span(assign.span.clone()))
+        }
+        None => Ok(match delay_step {
+            Some(_) => Dist::Const(defaults::DELAY_NS),
+            None => Dist::Const(defaults::WEIGHT),
+        }),
+    }
+}
+
+/// Whether the connection's delay is given in run steps, so each one drawn
+/// is rounded to a whole step.
+fn delay_in_steps(body: &[Assign]) -> bool {
+    let in_steps = |expr: &Expr| match expr {
+        Expr::Number(q) => q.unit.as_ref().is_some_and(|u| is_step_unit(&u.name)),
+        _ => false,
+    };
+    body.iter()
+        .filter(|a| a.key.name == "d")
+        .any(|a| match &a.value {
+            Expr::Call(call) => call.args.iter().any(|arg| match arg {
+                CallArg::Positional(e) | CallArg::Named { value: e, .. } => in_steps(e),
+            }),
+            other => in_steps(other),
+        })
+}
+
+fn dist_from_expr(expr: &Expr, delay_step: Option<i64>) -> Result<Dist, SimError> {
+    let value = |q: &Quantity| match delay_step {
+        Some(step_ns) => delay_to_nanos(q, step_ns).map_err(unit_err),
+        None => Ok(q.value),
+    };
+    match expr {
+        Expr::Number(q) => Ok(Dist::Const(value(q)?)),
+        Expr::Call(call) => {
+            let mut args = Vec::new();
+            for arg in &call.args {
+                let expr = match arg {
+                    CallArg::Positional(e) => e,
+                    CallArg::Named { value, .. } => value,
+                };
+                if let Expr::Number(q) = expr {
+                    args.push(value(q)?);
+                } else {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(
+                        "distribution arguments must be numbers".to_string(),
+                    )));
+                }
+            }
+            if args.len() != 2 {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(
+                    "distribution requires two arguments".to_string(),
+                )));
+            }
+            match call.name.name.as_str() {
+                "Uniform" => Ok(Dist::Uniform(args[0], args[1])),
+                "Normal" => Ok(Dist::Normal(args[0], args[1])),
+                _ => Err(SimError::new(SimErrorKind::InvalidParameter(
+                    "unsupported distribution".to_string(),
+                ))),
+            }
+        }
+        _ => Err(SimError::new(SimErrorKind::InvalidParameter(
+            "expected number or distribution".to_string(),
+        ))),
+    }
+}
+
+fn sample_dist(dist: &Dist, rng: &mut Rng) -> f64 {
+    match dist {
+        Dist::Const(v) => *v,
+        Dist::Uniform(a, b) => a + (b - a) * rng.next_f64(),
+        Dist::Normal(mu, sigma) => {
+            let (u1, u2) = (rng.next_f64(), rng.next_f64());
+            let z0 = (-2.0 * detmath::ln(u1)).sqrt() * detmath::cos_2pi(u2);
+            mu + z0 * sigma
+        }
+    }
+}
+
+fn sink_error(e: io::Error) -> SimError {
+    SimError::io("failed to record", e)
+}
+
+/// A unit conversion error as a [`SimErrorKind::BadUnit`].
+fn unit_err(diag: Diagnostic) -> SimError {
+    SimError {
+        kind: SimErrorKind::BadUnit(diag.message),
+        span: diag.span,
+    }
+}
+
+/// Any other diagnostic from the language crate, such as an unknown
+/// network, as a [`SimErrorKind::Invalid`].
+fn to_err(diag: Diagnostic) -> SimError {
+    SimError {
+        kind: SimErrorKind::Invalid(diag.message),
+        span: diag.span,
+    }
+}
+
+/// Writes the recorded raster in the layout of [`NdjsonSink`].
+pub fn write_spikes_ndjson(out: &mut impl Write, summary: &SimSummary) -> io::Result<()> {
+    let mut sink = NdjsonSink::new(out);
+    sink.on_start(&RunInfo::of(summary))?;
+    for spike in summary.raster.iter().flatten() {
+        sink.on_spike(spike)?;
+    }
+    sink.on_summary(summary)
+}
+
+pub fn format_spike_hash(hash: u64) -> String {
+    format!("{hash:016x}")
+}
+
+/// 64-bit FNV-1a over `(step: u64, layer: u32, neuron: u64)` little-endian
+/// tuples, fed in simulation order.
+#[derive(Clone)]
+struct SpikeHasher {
+    state: u64,
+}
+
+impl SpikeHasher {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    fn new() -> Self {
+        Self {
+            state: Self::OFFSET,
+        }
+    }
+
+    fn record(&mut self, step: u64, layer: u32, neuron: u64) {
+        self.write(&step.to_le_bytes());
+        self.write(&layer.to_le_bytes());
+        self.write(&neuron.to_le_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.state ^= b as u64;
+            self.state = self.state.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+#[derive(Clone)]
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
         Self { state: seed }
     }
 
-    fn next_u64(&mut self) -> u64 {
-        self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1);
-        self.state
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1);
+        self.state
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        let v = self.next_u64() >> 11;
+        (v as f64) / ((1u64 << 53) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converge_lang::parser::parse_program;
+
+    /// [`summary_json`] without the fields that differ between runs with
+    /// the same results.
+    fn results_json(summary: &SimSummary) -> String {
+        summary_json(&SimSummary {
+            wall_ns: None,
+            config: RunConfig::default(),
+            ..summary.clone()
+        })
+    }
+
+    #[test]
+    fn deterministic_summary() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer Input[2] : LIF
+layer Output[2] : LIF
+connect Input -> Output { w = 1.0, d = 1 ms }
+stimulus Input = Poisson(rate=50 Hz)
+run for 10 ms step 1 ms
+seed 42
+"#;
+        let program = parse_program(src).expect("parse");
+        let a = simulate(&program).expect("sim");
+        let b = simulate(&program).expect("sim");
+        assert_eq!(a.total_spikes, b.total_spikes);
+        assert_eq!(a.layers[0].spikes, b.layers[0].spikes);
+    }
+
+    #[test]
+    fn huge_delay_queue_is_out_of_memory() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer A[2] : LIF
+layer B[2] : LIF
+connect A -> B { w = 1.0, d = 1000000 s }
+run for 1 ms step 1 us
+"#;
+        let program = parse_program(src).expect("parse");
+        let err = simulate(&program).expect_err("queue too big");
+        assert!(
+            matches!(err.kind, SimErrorKind::OutOfMemory { .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn summary_counts_deliveries_per_connection() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer A[4] : LIF
+layer B[3] : LIF
+connect A -> B { w = 0.5, d = 1 ms }
+connect quiet: B -> A { w = 0.25 }
+connect A <-> A electrical { g = 0.1 }
+stimulus A = Poisson(rate=400 Hz)
+seed 3
+run for 30 ms
+"#;
+        let summary = simulate(&parse_program(src).expect("parse")).expect("sim");
+        let (a, b) = (summary.layers[0].spikes, summary.layers[1].spikes);
+        assert!(a > 0);
+        assert_eq!(summary.connections.len(), 2);
+        let forward = &summary.connections[0];
+        assert_eq!((forward.name.as_deref(), forward.src.as_str()), (None, "A"));
+        assert_eq!(forward.deliveries, a * 3);
+        assert_eq!(forward.mean_weight(), 0.5);
+        let back = &summary.connections[1];
+        assert_eq!(back.name.as_deref(), Some("quiet"));
+        assert_eq!(back.deliveries, b * 4);
+        assert!(summary_json(&summary).contains(&format!(
+            "{{ \"src\": \"A\", \"dst\": \"B\", \"deliveries\": {}, \"mean_weight\": 0.5 }}",
+            a * 3
+        )));
+    }
+
+    #[test]
+    fn summary_records_run_metadata() {
+        let src = "neuron LIF { tau_m = 10 ms }\nlayer A[2] : LIF\nrun for 10 ms\n";
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            trace_neurons: 2,
+            record_spikes: true,
+            ..SimConfig::default()
+        };
+        let mut sim = Simulator::new(&program, &config).expect("build");
+        for _ in 0..4 {
+            sim.step().expect("step");
+        }
+        let mut summary = sim.summary();
+        assert_eq!(summary.steps, 4);
+        assert!(summary.wall_ns.is_some());
+        summary.input_hash = Some(source_hash(src));
+
+        let json = converge_lang::json::parse(&summary_json(&summary)).expect("valid json");
+        let field = |key: &str| json.get(key).map(|v| v.to_string());
+        assert_eq!(
+            field("schema_version"),
+            Some(SUMMARY_SCHEMA_VERSION.to_string())
+        );
+        assert_eq!(
+            field("toolchain_version"),
+            Some(format!("\"{}\"", env!("CARGO_PKG_VERSION")))
+        );
+        assert_eq!(
+            field("input_hash"),
+            Some(format!("\"{}\"", format_spike_hash(source_hash(src))))
+        );
+        assert_eq!(field("steps"), Some("4".to_string()));
+        let config = json.get("config").expect("config");
+        assert_eq!(
+            config.get("trace_neurons").map(|v| v.to_string()),
+            Some("2".to_string())
+        );
+        assert_eq!(
+            config.get("record_spikes").map(|v| v.to_string()),
+            Some("true".to_string())
+        );
+        assert_eq!(
+            config.get("epochs").map(|v| v.to_string()),
+            Some("null".to_string())
+        );
+        assert_ne!(source_hash(src), source_hash("run for 10 ms\n"));
+    }
+
+    #[test]
+    fn silent_models_run_to_an_empty_summary() {
+        let sources = [
+            "run for 10 ms\n",
+            "neuron LIF {}\nlayer A[3] : LIF\nlayer Empty[0] : LIF\nrun for 10 ms\n",
+            "neuron LIF {}\nlayer A[3] : LIF\nlayer B[2] : LIF\n\
+             connect A -> B { w = 0.5, d = 2 ms }\nrun for 10 ms\n",
+        ];
+        let mut hashes = Vec::new();
+        for src in sources {
+            let program = parse_program(src).expect("parse");
+            let config = SimConfig {
+                record_spikes: true,
+                ..SimConfig::default()
+            };
+            let summary = simulate_with(&program, &config).expect(src);
+            assert_eq!(summary.steps, 10);
+            assert_eq!(summary.total_spikes, 0);
+            assert!(summary.layers.iter().all(|l| l.spikes == 0));
+            assert_eq!(summary.raster.as_deref(), Some(&[][..]));
+            converge_lang::json::parse(&summary_json(&summary)).expect("valid json");
+            hashes.push(summary.spike_hash);
+        }
+        assert!(hashes.iter().all(|&h| h == hashes[0]));
+    }
+
+    #[test]
+    fn spike_hash_is_deterministic() {
+        let src = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+                   layer Input[4] : LIF\n\
+                   layer Output[2] : LIF\n\
+                   connect Input -> Output { w = 1.0, d = 1 ms }\n\
+                   stimulus Input = Poisson(rate=200 Hz)\n\
+                   run for 20 ms\n\
+                   seed 42\n";
+        let program = parse_program(src).expect("parse");
+        let a = simulate(&program).expect("sim");
+        let b = simulate(&program).expect("sim");
+        assert!(a.total_spikes > 0);
+        assert_eq!(a.spike_hash, b.spike_hash);
+    }
+
+    #[test]
+    fn spike_hash_tracks_spike_trains() {
+        let src = |seed: u64| {
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 1.0 }}\n\
+                 layer Input[4] : LIF\n\
+                 stimulus Input = Poisson(rate=500 Hz)\n\
+                 run for 20 ms\n\
+                 seed {seed}\n"
+            )
+        };
+        let a = simulate(&parse_program(&src(1)).unwrap()).unwrap();
+        let b = simulate(&parse_program(&src(2)).unwrap()).unwrap();
+        assert_ne!(a.spike_hash, b.spike_hash);
+
+        let silent =
+            simulate(&parse_program("neuron N { }\nlayer A[1] : N\nrun for 1 ms\n").unwrap())
+                .unwrap();
+        assert_eq!(silent.spike_hash, SpikeHasher::OFFSET);
+    }
+
+    #[test]
+    fn asserts_report_failures_with_spans() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer Input[4] : LIF
+stimulus Input = Poisson(rate=500 Hz)
+run for 20 ms step 1 ms
+seed 1
+assert spikes(Input) > 0
+assert rate(Input) between 0 Hz and 1 kHz
+assert spikes(Input) == 0
+"#;
+        let program = parse_program(src).expect("parse");
+        let summary = simulate(&program).expect("sim");
+        let failures = check_asserts(&program, &summary);
+        assert_eq!(failures.len(), 1);
+        assert!(
+            failures[0]
+                .message
+                .starts_with("assertion failed: spikes(Input) == 0")
+        );
+        assert!(failures[0].span.is_some());
+    }
+
+    #[test]
+    fn profile_is_opt_in_and_embedded_in_json() {
+        let program = parse_program("neuron N { }\nlayer A[3] : N\nrun for 2 ms\n").expect("parse");
+        assert!(simulate(&program).unwrap().profile.is_none());
+
+        let config = SimConfig {
+            profile: true,
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&program, &config).unwrap();
+        let profile = summary.profile.as_ref().expect("profile");
+        let names: Vec<_> = profile.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["build", "delivery", "stimulus", "membrane", "plasticity"]
+        );
+        assert!(profile.network_bytes >= 3 * 8);
+
+        let json = converge_lang::json::parse(&summary_json(&summary)).expect("valid json");
+        assert!(json.get("profile").and_then(|p| p.get("phases")).is_some());
+    }
+
+    #[test]
+    fn stepping_matches_batch_run() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer Input[4] : LIF
+layer Output[2] : LIF
+connect Input -> Output { w = 0.7, d = 2 ms }
+stimulus Input = Poisson(rate=400 Hz)
+run for 30 ms
+seed 3
+"#;
+        let program = parse_program(src).expect("parse");
+        let batch = simulate(&program).expect("sim");
+
+        let mut sim = Simulator::new(&program, &SimConfig::default()).expect("build");
+        assert_eq!(sim.total_steps(), 30);
+        while !sim.is_finished() {
+            sim.step().expect("step");
+        }
+        sim.step().expect("stepping a finished run is a no-op");
+        let stepped = sim.summary();
+        assert_eq!(stepped.spike_hash, batch.spike_hash);
+        assert_eq!(stepped.total_spikes, batch.total_spikes);
+    }
+
+    #[test]
+    fn looks_up_named_connections() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms }
+layer A[3] : LIF
+layer B[2] : LIF
+connect A -> B { w = 0.5 }
+connect fb: B -> A { w = 0.1 }
+run for 1 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let sim = Simulator::new(&program, &SimConfig::default()).expect("build");
+        assert_eq!(sim.connection_count(), 2);
+        assert_eq!(sim.connection(0).name, None);
+        let idx = sim.connection_index("fb").expect("named connection");
+        let conn = sim.connection(idx);
+        assert_eq!((conn.src_layer, conn.dst_layer, conn.synapses), (1, 0, 6));
+        assert_eq!(sim.connection_index("ff"), None);
     }
 
-    fn next_f64(&mut self) -> f64 {
-        let v = self.next_u64() >> 11;
-        (v as f64) / ((1u64 << 53) as f64)
+    #[test]
+    fn gap_junctions_pull_membranes_together() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 100.0 }
+layer A[1] : LIF
+layer B[1] : LIF
+stimulus A = Poisson(rate=500 Hz)
+run for 10 ms
+"#;
+        let coupled = format!("{src}connect gj: A <-> B electrical {{ g = 0.5 }}\n");
+        let run = |src: &str| {
+            let mut sim =
+                Simulator::new(&parse_program(src).expect("parse"), &SimConfig::default())
+                    .expect("build");
+            sim.run().expect("run");
+            (sim.layer(0).membrane[0], sim.layer(1).membrane[0])
+        };
+        let (a_alone, b_alone) = run(src);
+        let (a, b) = run(&coupled);
+        assert_eq!(b_alone, 0.0);
+        assert!(b > 0.0 && a < a_alone, "a={a} b={b} alone={a_alone}");
+
+        let sim = Simulator::new(
+            &parse_program(&coupled).expect("parse"),
+            &SimConfig::default(),
+        )
+        .expect("build");
+        let conn = sim.connection(sim.connection_index("gj").expect("named"));
+        assert_eq!((conn.kind, conn.synapses), (ConnectKind::Electrical, 1));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use converge_lang::parser::parse_program;
+    #[test]
+    fn dendritic_input_reaches_the_soma_through_the_chain() {
+        // `A` fires every step and drives the far end of `B`'s dendrite.
+        let src = r#"
+neuron Drive { tau_m = 10 ms, v_th = 0.5 }
+neuron Pyr { tau_m = 10 ms, v_th = 100.0, compartments = 2, g_axial = 2 }
+layer A[1] : Drive
+layer B[1] : Pyr
+connect A -> B { w = 1.0, compartment = 2 }
+stimulus A = Poisson(rate=1000 Hz)
+run for 10 ms
+"#;
+        let mut sim = Simulator::new(&parse_program(src).expect("parse"), &SimConfig::default())
+            .expect("build");
+        sim.run().expect("run");
+        let b = sim.layer(1);
+        let (soma, proximal, distal) = (b.membrane[0], b.dendrites[0], b.dendrites[1]);
+        assert!(
+            0.0 < soma && soma < proximal && proximal < distal,
+            "soma={soma} proximal={proximal} distal={distal}"
+        );
+
+        let err = Simulator::new(
+            &parse_program(&src.replace("compartment = 2", "compartment = 3")).expect("parse"),
+            &SimConfig::default(),
+        )
+        .err()
+        .expect("out of range");
+        assert_eq!(
+            err.to_string(),
+            "connection targets compartment 3, but `B` has 2 dendritic compartment(s)"
+        );
+    }
 
     #[test]
-    fn deterministic_summary() {
+    fn normalize_scales_weight_sums() {
+        let weights = |normalize: &str| {
+            let lesion = match normalize {
+                "" => "",
+                _ => "disconnect A -> B where src == 0\n",
+            };
+            let src = format!(
+                "neuron LIF {{ tau_m = 20 ms }}\n\
+                 layer A[10] : LIF\n\
+                 layer B[4] : LIF\n\
+                 connect A -> B {{ w = Uniform(0, 1){normalize} }}\n\
+                 {lesion}\
+                 run for 1 ms\n"
+            );
+            let sim = Simulator::new(&parse_program(&src).expect("parse"), &SimConfig::default())
+                .expect("build");
+            sim.weights(0)
+        };
+        let column = |w: &[Vec<f64>], j: usize| w.iter().map(|row| row[j]).sum::<f64>();
+        let raw = weights("");
+        let per_target = weights(", normalize = per_target(0.5)");
+        for j in 0..4 {
+            // Normalized before the `disconnect`, which then takes its share.
+            let kept = column(&raw, j) - raw[0][j];
+            let expected = 0.5 * kept / column(&raw, j);
+            assert!((column(&per_target, j) - expected).abs() < 1e-12);
+        }
+        let total = weights(", normalize = total(2)");
+        let all: f64 = raw.iter().flatten().sum();
+        let kept = all - raw[0].iter().sum::<f64>();
+        let sum: f64 = total.iter().flatten().sum();
+        assert!((sum - 2.0 * kept / all).abs() < 1e-12, "{sum}");
+    }
+
+    #[test]
+    fn delay_stdp_aligns_arrivals_with_firing() {
+        // `B` fires when `A`'s spikes arrive through the fixed 5 ms
+        // connection, so the silent plastic one learns to arrive then too.
+        let src = r#"
+neuron Fast { tau_m = 10 ms, v_th = 0.5 }
+layer A[1] : Fast
+layer B[1] : Fast
+connect A -> B { w = 1.0, d = 5 ms }
+connect learn: A -> B { w = 0, d = 1 step, delay_plasticity = DelaySTDP(lr = 0.5, d_max = 12 steps) }
+stimulus A = Poisson(rate=40 Hz)
+seed 3
+run for 2 s
+"#;
+        let mut sim = Simulator::new(&parse_program(src).expect("parse"), &SimConfig::default())
+            .expect("build");
+        assert_eq!(sim.queue_len, 13);
+        sim.run().expect("run");
+        let learned = sim.delays(1)[0][0];
+        assert!((4.0..=7.0).contains(&learned), "{learned}");
+        assert!(sim.connection(1).learns_delays);
+        assert!(sim.trained_connections().contains(&1));
+
+        let bad = src.replace("d = 1 step,", "d = 20 steps,");
+        let err = Simulator::new(&parse_program(&bad).expect("parse"), &SimConfig::default())
+            .err()
+            .expect("out of bounds");
+        assert_eq!(
+            err.to_string(),
+            "a delay of 20 steps is outside the `DelaySTDP` bounds of 0 to 12 steps"
+        );
+    }
+
+    #[test]
+    fn reward_gates_stdp_weight_changes() {
+        // `A` fires every step and `B` one step later, so every pairing is
+        // causal and eligibility only grows.
+        let src = r#"
+neuron Fast { tau_m = 10 ms, v_th = 0.5 }
+layer A[1] : Fast
+layer B[1] : Fast
+connect A -> B { w = 1.0, plasticity = RSTDP(a_minus = 0, w_max = 2) }
+stimulus A = Poisson(rate=1000 Hz)
+run for 20 ms
+"#;
+        let weight = |src: &str| {
+            let mut sim =
+                Simulator::new(&parse_program(src).expect("parse"), &SimConfig::default())
+                    .expect("build");
+            sim.run().expect("run");
+            sim.connections[0].synapses[0][0].weight
+        };
+        assert_eq!(weight(src), 1.0);
+        let rewarded = weight(&format!("{src}reward 1.0 at 10 ms\n"));
+        let punished = weight(&format!("{src}reward -1.0 at 10 ms\n"));
+        assert!(rewarded > 1.0 && punished < 1.0, "{rewarded} {punished}");
+        assert_eq!(weight(&format!("{src}reward 100 at 10 ms\n")), 2.0);
+
+        let mut sim = Simulator::new(&parse_program(src).expect("parse"), &SimConfig::default())
+            .expect("build");
+        for _ in 0..10 {
+            sim.step().expect("step");
+        }
+        sim.add_reward(1.0);
+        sim.run().expect("run");
+        assert_eq!(sim.connections[0].synapses[0][0].weight, rewarded);
+    }
+
+    #[test]
+    fn modulators_shift_thresholds_and_drive_learning() {
+        // Both `VTA` neurons fire every step, so `DA` follows
+        // level = level * (1 - dt / tau) + 2 * gain.
+        let src = r#"
+modulator DA { tau = 10 ms, source = VTA, gain = 0.5 }
+neuron Fast { tau_m = 10 ms, v_th = 0.5 }
+neuron Damped { tau_m = 10 ms, v_th = 0.5, modulator = DA, modulator_gain = 100 }
+layer VTA[2] : Fast
+layer B[1] : Damped
+layer C[1] : Fast
+connect VTA -> C { w = 1.0, plasticity = RSTDP(a_minus = 0, w_max = 2, modulator = DA) }
+stimulus VTA = Poisson(rate=1000 Hz)
+stimulus B = Poisson(rate=1000 Hz)
+run for 10 ms
+"#;
+        let mut sim = Simulator::new(&parse_program(src).expect("parse"), &SimConfig::default())
+            .expect("build");
+        sim.step().expect("step");
+        assert_eq!(sim.modulator_level("DA"), Some(1.0));
+        sim.step().expect("step");
+        assert_eq!(sim.modulator_level("DA"), Some(1.9));
+        assert_eq!(sim.modulator_level("NE"), None);
+        sim.run().expect("run");
+        // `B` fires on the first step only, before any dopamine arrives.
+        assert_eq!(sim.layer(1).spikes, 1);
+        let weight = sim.connections[0].synapses[0][0].weight;
+        assert!(weight > 1.0, "{weight}");
+    }
+
+    #[test]
+    fn disconnect_removes_matching_synapses() {
         let src = r#"
 neuron LIF { tau_m = 10 ms, v_th = 1.0 }
-layer Input[2] : LIF
+layer A[4] : LIF
+connect rec: A -> A { w = Normal(0.5, 0.2), d = 1 ms }
+stimulus A = Poisson(rate=300 Hz)
+run for 20 ms
+"#;
+        let lesioned = format!("{src}disconnect rec where src == dst\n");
+        let full = Simulator::new(&parse_program(src).expect("parse"), &SimConfig::default())
+            .expect("build");
+        let sim = Simulator::new(
+            &parse_program(&lesioned).expect("parse"),
+            &SimConfig::default(),
+        )
+        .expect("build");
+        assert_eq!(sim.connection(0).synapses, 12);
+        for (a, b) in full.connections[0]
+            .synapses
+            .iter()
+            .zip(&sim.connections[0].synapses)
+        {
+            let kept: Vec<_> = a
+                .iter()
+                .filter(|s| b.iter().any(|t| t.dst == s.dst))
+                .collect();
+            assert_eq!(kept.len(), b.len());
+            assert!(kept.iter().zip(b).all(|(s, t)| s.weight == t.weight));
+        }
+
+        let silenced = format!("{src}disconnect rec\n");
+        let sim = Simulator::new(
+            &parse_program(&silenced).expect("parse"),
+            &SimConfig::default(),
+        )
+        .expect("build");
+        assert_eq!(sim.connection(0).synapses, 0);
+    }
+
+    #[test]
+    fn records_spikes_and_voltage_traces() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer Input[4] : LIF
 layer Output[2] : LIF
-connect Input -> Output { w = 1.0, d = 1 ms }
-stimulus Input = Poisson(rate=50 Hz)
-run for 10 ms step 1 ms
-seed 42
+connect Input -> Output { w = 0.7, d = 2 ms }
+stimulus Input = Poisson(rate=400 Hz)
+run for 30 ms
+seed 3
 "#;
         let program = parse_program(src).expect("parse");
-        let a = simulate(&program).expect("sim");
-        let b = simulate(&program).expect("sim");
-        assert_eq!(a.total_spikes, b.total_spikes);
-        assert_eq!(a.layers[0].spikes, b.layers[0].spikes);
+        let config = SimConfig {
+            record_spikes: true,
+            trace_neurons: 3,
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&program, &config).expect("sim");
+        let raster = summary.raster.as_ref().expect("raster");
+        assert_eq!(raster.len() as u64, summary.total_spikes);
+
+        let voltages = summary.voltages.as_ref().expect("voltages");
+        assert_eq!(voltages.len(), 3 + 2);
+        assert!(voltages.iter().all(|t| t.samples.len() == 30));
+        for spike in raster.iter().filter(|s| s.layer == 0 && s.neuron < 3) {
+            assert_eq!(
+                voltages[spike.neuron as usize].samples[spike.step as usize],
+                0.0
+            );
+        }
+
+        let plain = simulate(&program).expect("sim");
+        assert!(plain.raster.is_none() && plain.voltages.is_none());
+        assert_eq!(plain.spike_hash, summary.spike_hash);
+    }
+
+    #[test]
+    fn smooths_population_rates() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer Input[4] : LIF
+stimulus Input = Poisson(rate=400 Hz)
+record rate from Input window 1 ms
+record rate from Input window 10 ms
+run for 30 ms step 1 ms
+seed 3
+"#;
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            record_spikes: true,
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&program, &config).expect("sim");
+        let [raw, smooth] = &summary.rates[..] else {
+            panic!("expected two rate traces");
+        };
+        assert_eq!((raw.window_ns, smooth.window_ns), (1_000_000, 10_000_000));
+        // A one-step window is the step's own rate: 250 Hz per spike here.
+        let mut counts = [0usize; 30];
+        for spike in summary.raster.as_ref().expect("raster") {
+            counts[spike.step as usize] += 1;
+        }
+        let expected: Vec<f64> = counts.iter().map(|&n| n as f64 * 250.0).collect();
+        assert_eq!(raw.samples, expected);
+        let mut r = 0.0;
+        for (now, smoothed) in raw.samples.iter().zip(&smooth.samples) {
+            r += (now - r) * 0.1;
+            assert_eq!(*smoothed, r);
+        }
+
+        let fast = src.replace("window 1 ms", "window 500 us");
+        let err = simulate(&parse_program(&fast).expect("parse")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "smoothing window 500 us for `Input` is shorter than the run step"
+        );
+    }
+
+    #[test]
+    fn setup_errors_point_at_the_source() {
+        let src = "neuron LIF { tau_m = 20 ms }\n\
+                   layer In[4] : LIF\n\
+                   layer Out[4] : LIF\n\
+                   connect In -> Out { w = 0.5, d = Uniform(-3 ms, -1 ms) }\n\
+                   run for 10 ms\n";
+        let text = |src: &str, err: &SimError| {
+            let span = err.span.clone().expect("span");
+            src[span.start..span.end].to_string()
+        };
+        let err = simulate(&parse_program(src).expect("parse")).unwrap_err();
+        assert_eq!(err.to_string(), "negative delay is not allowed");
+        assert_eq!(text(src, &err), "d = Uniform(-3 ms, -1 ms)");
+        assert_eq!(err.to_diagnostic().span, err.span);
+
+        let uneven = src.replace("Uniform(-3 ms, -1 ms)", "1500 us");
+        let err = simulate(&parse_program(&uneven).expect("parse")).unwrap_err();
+        assert_eq!(err.to_string(), "delay must be divisible by step");
+        assert_eq!(text(&uneven, &err), "d = 1500 us");
+
+        let short = src.replace("run for 10 ms", "run for 10 ms step 3 ms");
+        let err = simulate(&parse_program(&short).expect("parse")).unwrap_err();
+        assert_eq!(text(&short, &err), "run for 10 ms step 3 ms");
+    }
+
+    #[test]
+    fn errors_can_be_matched_by_kind() {
+        let src = "neuron LIF { tau_m = 20 ms }\n\
+                   layer In[4] : LIF\n\
+                   connect In -> In { d = 1500 us }\n\
+                   stimulus In = Poisson(rate=20 Hz)\n\
+                   run for 10 ms\n";
+        let fail = |src: &str| simulate(&parse_program(src).expect("parse")).unwrap_err();
+
+        let err = fail(src);
+        assert!(matches!(
+            err.kind,
+            SimErrorKind::UnevenDelay {
+                delay_ns: 1_500_000,
+                step_ns: 1_000_000
+            }
+        ));
+        let err = fail(&src.replace("1500 us", "-1 ms"));
+        assert!(matches!(err.kind, SimErrorKind::NegativeDelay { delay_ns } if delay_ns == -1e6));
+        let err = fail(&src.replace("run for 10 ms\n", ""));
+        assert!(matches!(err.kind, SimErrorKind::MissingRun));
+        let err = fail(&src.replace("tau_m = 20 ms", "tau_m = 20"));
+        assert!(matches!(err.kind, SimErrorKind::BadUnit(_)), "{err}");
+        let err = fail(&src.replace("1500 us", "1 ms").replace("20 Hz", "2000 Hz"));
+        assert!(matches!(&err.kind, SimErrorKind::RateTooHigh { layer } if layer == "In"));
+        assert_eq!(err.to_string(), "stimulus rate for `In` too high for step");
+
+        let err = SimError::io("failed to record", io::Error::other("disk full"));
+        assert_eq!(err.to_string(), "failed to record: disk full");
+        let source = std::error::Error::source(&err).expect("source");
+        assert_eq!(source.to_string(), "disk full");
+    }
+
+    #[test]
+    fn samples_probed_neurons() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer Input[4] : LIF
+stimulus Input = Poisson(rate=400 Hz)
+probe mid = v(Input[1..3]) every 2 ms
+probe all = v(Input)
+run for 9 ms step 1 ms
+seed 3
+"#;
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            trace_neurons: 4,
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&program, &config).expect("sim");
+        let voltages = summary.voltages.as_ref().expect("voltages");
+        let [mid, all] = &summary.probes[..] else {
+            panic!("expected two probes");
+        };
+        assert_eq!(
+            (mid.name.as_str(), mid.first, mid.every_steps),
+            ("mid", 1, 2)
+        );
+        assert_eq!(mid.samples.len(), 2);
+        for (i, series) in mid.samples.iter().enumerate() {
+            let every_other: Vec<f64> =
+                voltages[1 + i].samples.iter().step_by(2).copied().collect();
+            assert_eq!(*series, every_other);
+            assert_eq!(series.len(), 5);
+        }
+        let traced: Vec<&Vec<f64>> = voltages.iter().map(|t| &t.samples).collect();
+        assert_eq!(all.samples.iter().collect::<Vec<_>>(), traced);
+
+        let fast = src.replace("every 2 ms", "every 400 us");
+        let err = simulate(&parse_program(&fast).expect("parse")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "probe interval 400 us for `mid` is shorter than the run step"
+        );
+        let wide = src.replace("[1..3]", "[2..5]");
+        let err = simulate(&parse_program(&wide).expect("parse")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "probe `mid` reads neurons 2..5 of `Input`, which has 4"
+        );
+        assert!(matches!(
+            err.kind,
+            SimErrorKind::ProbeOutOfRange {
+                first: 2,
+                end: 5,
+                size: 4,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn encode_presents_csv_rows_in_order() {
+        let dir = std::env::temp_dir().join(format!("converge-encode-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rows.csv"), "a,b\n# first row\n0,4\n\n2,0\n").unwrap();
+        std::fs::write(dir.join("wide.csv"), "1,2,3\n").unwrap();
+        let config = SimConfig {
+            record_spikes: true,
+            data_dir: Some(dir.clone()),
+            ..SimConfig::default()
+        };
+        let src = |file: &str| {
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 0.5 }}\n\
+                 layer In[2] : LIF\n\
+                 stimulus In = Encode(\"{file}\", scheme=rate, max_rate=1 kHz, present=10 ms)\n\
+                 run for 30 ms step 1 ms\n"
+            )
+        };
+
+        // At 1 kHz and 1 ms steps the largest value fires every step, half
+        // of it every other step on average, and zero never.
+        let summary = simulate_with(&parse_program(&src("rows.csv")).unwrap(), &config).unwrap();
+        let spikes: Vec<(u64, u64)> = summary
+            .raster
+            .iter()
+            .flatten()
+            .map(|s| (s.step, s.neuron))
+            .collect();
+        assert!((0..10).all(|step| spikes.contains(&(step, 1))));
+        assert!(
+            spikes
+                .iter()
+                .all(|&(step, n)| (n == 1) == (step < 10) && step < 20)
+        );
+        assert!(spikes.iter().any(|&(step, _)| step >= 10));
+
+        let err = simulate_with(&parse_program(&src("wide.csv")).unwrap(), &config).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("line 1: expected 2 values, found 3"),
+            "{}",
+            err.to_string()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn latency_encoding_spikes_larger_values_first() {
+        let dir = std::env::temp_dir().join(format!("converge-latency-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rows.csv"), "4,2,0\n0,0,4\n").unwrap();
+        let config = SimConfig {
+            record_spikes: true,
+            data_dir: Some(dir.clone()),
+            ..SimConfig::default()
+        };
+        let src = |seed: u64| {
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 0.5 }}\n\
+                 layer In[3] : LIF\n\
+                 stimulus In = Encode(\"rows.csv\", scheme=latency, present=5 ms)\n\
+                 run for 15 ms step 1 ms\n\
+                 seed {seed}\n"
+            )
+        };
+        let summary = simulate_with(&parse_program(&src(1)).unwrap(), &config).unwrap();
+        let spikes: Vec<(u64, u64)> = summary
+            .raster
+            .iter()
+            .flatten()
+            .map(|s| (s.step, s.neuron))
+            .collect();
+        assert_eq!(spikes, [(0, 0), (2, 1), (5, 2)]);
+
+        // Latency spikes draw nothing from the RNG.
+        let reseeded = simulate_with(&parse_program(&src(2)).unwrap(), &config).unwrap();
+        assert_eq!(reseeded.spike_hash, summary.spike_hash);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn experiments_schedule_epochs_and_count_readout_spikes() {
+        let dir = std::env::temp_dir().join(format!("converge-experiment-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rows.csv"), "4,0\n0,4\n").unwrap();
+        let config = SimConfig {
+            data_dir: Some(dir.clone()),
+            ..SimConfig::default()
+        };
+        let src = |shuffle: &str| {
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 0.5 }}\n\
+                 layer In[2] : LIF\n\
+                 stimulus In = Encode(\"rows.csv\", scheme=latency, present=5 ms)\n\
+                 experiment {{ epochs = 2, isi = 5 ms, shuffle = {shuffle}, readout = In }}\n\
+                 run for 1 ms step 1 ms\n\
+                 seed 3\n"
+            )
+        };
+
+        // Two epochs of two 10 ms slots stretch the 1 ms run to 40 ms.
+        let summary = simulate_with(&parse_program(&src("false")).unwrap(), &config).unwrap();
+        assert_eq!(summary.duration_ns, 40_000_000);
+        let samples: Vec<(u32, u64, Vec<u64>)> = summary
+            .samples
+            .clone()
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.epoch, s.sample, s.counts))
+            .collect();
+        assert_eq!(
+            samples,
+            [
+                (0, 0, vec![1, 0]),
+                (0, 1, vec![0, 1]),
+                (1, 0, vec![1, 0]),
+                (1, 1, vec![0, 1]),
+            ]
+        );
+        assert!(summary_json(&summary).contains("\"samples\": ["));
+
+        // Shuffling permutes each epoch but keeps every row with its spikes.
+        let shuffled = simulate_with(&parse_program(&src("true")).unwrap(), &config).unwrap();
+        for epoch in shuffled.samples.unwrap().chunks(2) {
+            let mut rows: Vec<u64> = epoch.iter().map(|s| s.sample).collect();
+            rows.sort();
+            assert_eq!(rows, [0, 1]);
+            for s in epoch {
+                assert_eq!(s.counts[s.sample as usize], 1);
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn readout_scores_presentations_against_labels() {
+        let dir = std::env::temp_dir().join(format!("converge-readout-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rows.csv"), "4,0\n0,4\n0,0\n").unwrap();
+        std::fs::write(dir.join("labels.csv"), "label\n0\n0\n1\n").unwrap();
+        std::fs::write(dir.join("short.csv"), "0\n1\n").unwrap();
+        let config = SimConfig {
+            data_dir: Some(dir.clone()),
+            ..SimConfig::default()
+        };
+        let src = |labels: &str| {
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 0.5 }}\n\
+                 layer In[2] : LIF\n\
+                 stimulus In = Encode(\"rows.csv\", scheme=latency, present=5 ms)\n\
+                 experiment {{ epochs = 2 }}\n\
+                 readout In labels \"{labels}\" rule = max_spikes\n\
+                 run for 1 ms step 1 ms\n"
+            )
+        };
+
+        // Row 0 is right, row 1 is predicted as class 1 against label 0, and
+        // the empty row 2 draws no spikes at all.
+        let summary = simulate_with(&parse_program(&src("labels.csv")).unwrap(), &config).unwrap();
+        let classification = summary.classification.clone().unwrap();
+        assert_eq!(
+            classification,
+            Classification {
+                layer: "In".to_string(),
+                presentations: 6,
+                correct: 2,
+                silent: 2,
+                confusion: vec![vec![2, 2], vec![0, 0]],
+            }
+        );
+        assert!((classification.accuracy() - 1.0 / 3.0).abs() < 1e-12);
+        assert!(summary_json(&summary).contains("\"classification\": {"));
+
+        let err = simulate_with(&parse_program(&src("short.csv")).unwrap(), &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "readout has 2 label(s) but the dataset has 3 row(s)"
+        );
+        assert!(matches!(
+            err.kind,
+            SimErrorKind::LabelCountMismatch { labels: 2, rows: 3 }
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn training_learns_readout_weights_that_load_back() {
+        let dir = std::env::temp_dir().join(format!("converge-train-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rows.csv"), "4,0\n0,4\n").unwrap();
+        std::fs::write(dir.join("labels.csv"), "1\n0\n").unwrap();
+        let src = |w: &str| {
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 0.5 }}\n\
+                 layer In[2] : LIF\n\
+                 layer Out[2] : LIF\n\
+                 connect ff: In -> Out {{ w = {w} }}\n\
+                 stimulus In = Encode(\"rows.csv\", scheme=latency, present=5 ms)\n\
+                 experiment {{ epochs = 1 }}\n\
+                 readout Out labels \"labels.csv\"\n\
+                 run for 1 ms step 1 ms\n"
+            )
+        };
+        let config = SimConfig {
+            data_dir: Some(dir.clone()),
+            learning_rate: Some(1.0),
+            epochs: Some(2),
+            ..SimConfig::default()
+        };
+
+        // The silent first epoch teaches the crossed mapping; the second
+        // epoch gets both rows right.
+        let program = parse_program(&src("0")).unwrap();
+        let mut sim = Simulator::new(&program, &config).unwrap();
+        sim.run().unwrap();
+        let classification = sim.summary().classification.unwrap();
+        assert_eq!((classification.correct, classification.silent), (2, 2));
+        assert_eq!(sim.trained_connections(), [0]);
+        let weights = sim.weights(0);
+        assert_eq!(weights, [[0.0, 1.0], [1.0, 0.0]]);
+
+        let mut pgm = Vec::new();
+        write_weights_pgm(&mut pgm, &weights).unwrap();
+        assert_eq!(
+            pgm,
+            b"P5\n# weights from 0 to 1\n2 2\n255\n\x00\xff\xff\x00"
+        );
+
+        let mut csv = Vec::new();
+        write_weights_csv(&mut csv, &weights).unwrap();
+        std::fs::write(dir.join("ff.csv"), csv).unwrap();
+        let trained = parse_program(&src("from_file(\"ff.csv\")")).unwrap();
+        let config = SimConfig {
+            data_dir: Some(dir.clone()),
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&trained, &config).unwrap();
+        assert_eq!(summary.classification.unwrap().accuracy(), 1.0);
+
+        let err = simulate_with(
+            &parse_program(&src("from_file(\"labels.csv\")")).unwrap(),
+            &config,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("line 1: expected 2 weights, found 1"),
+            "{err}"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn quantized_runs_snap_weights_and_sum_exactly() {
+        let src = |w_b: &str| {
+            format!(
+                "neuron LIF {{ tau_m = 20 ms, v_th = 1.0 }}\n\
+                 layer In[20] : LIF\n\
+                 layer Out[10] : LIF\n\
+                 connect a: In -> Out {{ w = 0.3, d = 1 ms }}\n\
+                 connect b: In -> Out {{ w = {w_b}, d = 2 ms }}\n\
+                 stimulus In = Poisson(rate=200 Hz)\n\
+                 seed 42\n\
+                 run for 100 ms step 1 ms\n"
+            )
+        };
+        let program = parse_program(&src("0.1")).unwrap();
+        let config = SimConfig {
+            quantize: Some(Quantize::Int8),
+            ..SimConfig::default()
+        };
+
+        // Each connection gets the finest power-of-two scale that fits its
+        // largest weight in a signed byte: 2^-8 for 0.3, 2^-10 for 0.1.
+        let sim = Simulator::new(&program, &config).unwrap();
+        assert_eq!(sim.weights(0)[0][0], 77.0 / 256.0);
+        assert_eq!(sim.weights(1)[0][0], 102.0 / 1024.0);
+
+        // Integer sums leave nothing for compensation to fix.
+        let plain = simulate_with(&program, &config).unwrap();
+        let kahan = simulate_with(
+            &program,
+            &SimConfig {
+                compensated_summation: true,
+                ..config.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(plain.spike_hash, kahan.spike_hash);
+        assert_eq!(plain.quantize, Some(Quantize::Int8));
+        assert!(summary_json(&plain).contains("\"quantize\": \"int8\""));
+
+        let err =
+            simulate_with(&parse_program(&src("0.000000000001")).unwrap(), &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "weights span too many orders of magnitude for one int8 accumulator"
+        );
+    }
+
+    #[test]
+    fn fixed_point_runs_match_integer_arithmetic() {
+        // A 1 kHz stimulus at a 1 ms step adds exactly 1.0 every step.
+        let program = parse_program(
+            "neuron LIF { tau_m = 20 ms, v_th = 1.5 }\n\
+             layer A[1] : LIF\n\
+             stimulus A = Poisson(rate=1000 Hz)\n\
+             run for 30 ms step 1 ms\n",
+        )
+        .unwrap();
+        let config = SimConfig {
+            arithmetic: Arithmetic::Fixed,
+            ..SimConfig::default()
+        };
+        let mut sim = Simulator::new(&program, &config).unwrap();
+        let (one, decay, v_th) = (65_536_i64, 3_277_i64, 98_304_i64);
+        let mut q = 0_i64;
+        let mut spikes = 0;
+        while !sim.is_finished() {
+            sim.step().unwrap();
+            q += one;
+            q -= (q * decay) >> 16;
+            if q >= v_th {
+                q = 0;
+                spikes += 1;
+            }
+            assert_eq!(sim.layer(0).membrane[0], q as f64 / one as f64);
+        }
+        assert_eq!(sim.layer(0).spikes, spikes);
+        let summary = sim.summary();
+        assert_eq!(summary.arithmetic, Arithmetic::Fixed);
+        assert!(summary_json(&summary).contains("\"arithmetic\": \"q16.16\""));
+
+        let gap = parse_program(
+            "neuron LIF { tau_m = 20 ms }\n\
+             layer A[2] : LIF\n\
+             connect A <-> A electrical { g = 0.1 }\n\
+             run for 1 ms\n",
+        )
+        .unwrap();
+        let err = simulate_with(&gap, &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fixed-point arithmetic does not support gap junctions"
+        );
+    }
+
+    #[test]
+    fn energy_report_counts_events_and_inter_core_traffic() {
+        let program = parse_program(
+            "neuron LIF { tau_m = 20 ms, v_th = 1.0 }\n\
+             layer In[8] : LIF\n\
+             layer Out[4] : LIF\n\
+             layer Far[2] : LIF\n\
+             connect In -> Out { w = 0.6, d = 1 ms }\n\
+             connect In -> Far { w = 0.6, d = 1 ms }\n\
+             stimulus In = Poisson(rate=200 Hz)\n\
+             seed 7\n\
+             run for 50 ms step 1 ms\n",
+        )
+        .unwrap();
+        // In fills core 0; Out and Far share core 1, one hop away.
+        let profile = TargetProfile::from_toml(
+            "name = \"chip\"\n\
+             [core]\nmax_neurons = 8\n\
+             [energy]\nsynaptic_event_pj = 2.0\nrouter_hop_pj = 10\n\
+             [router]\nmesh_width = 4\n",
+        )
+        .unwrap();
+        let config = SimConfig {
+            energy: Some(profile),
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&program, &config).unwrap();
+        let energy = summary.energy.clone().unwrap();
+        let input = summary.layers[0].spikes;
+        assert!(input > 0);
+        assert_eq!(energy.cores, 2);
+        assert_eq!(energy.neuron_updates, 14 * 50);
+        assert_eq!(energy.synaptic_events, input * 6);
+        assert_eq!(energy.spike_messages, input);
+        assert_eq!(energy.router_hops, input);
+        assert_eq!(energy.total_pj(), (input * 12 + input * 10) as f64);
+        assert_eq!(format_energy(2_500.0), "2.500 nJ");
+        assert!(summary_json(&summary).contains("\"energy\": {"));
+    }
+
+    #[test]
+    fn optimized_programs_simulate_the_same() {
+        let src = "neuron A { tau_m = 20 ms }\n\
+                   neuron B { tau_m = 0.02 s }\n\
+                   layer In[8] : A\n\
+                   layer Out[4] : B\n\
+                   layer Idle[3] : A\n\
+                   connect In -> Out { w = Uniform(0.3, 0.7), d = 2 ms }\n\
+                   connect lesion: Out -> In { w = 0.4 }\n\
+                   disconnect lesion\n\
+                   stimulus In = Poisson(rate=0.2 kHz)\n\
+                   run for 50 ms step 1 ms\n";
+        let program = parse_program(src).unwrap();
+        let mut optimized = program.clone();
+        converge_lang::opt::optimize(&mut optimized);
+        let before = simulate(&program).unwrap();
+        let after = simulate(&optimized).unwrap();
+        assert!(before.total_spikes > 0);
+        assert_eq!(after.total_spikes, before.total_spikes);
+        // Only the trailing, silent layer is gone, so the hash holds too.
+        assert_eq!(after.spike_hash, before.spike_hash);
+        assert_eq!(after.layers.len(), 2);
+    }
+
+    #[test]
+    fn scratch_runs_match_in_memory_runs() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer A[20] : LIF
+layer B[10] : LIF
+connect A -> B { w = Normal(0.3, 0.1), d = 2 ms }
+connect rec: B -> B { w = Normal(0.2, 0.1), d = 1 ms }
+connect gap: A <-> A electrical { g = 0.05 }
+disconnect rec where src == dst
+stimulus A = Poisson(rate=300 Hz)
+seed 5
+run for 60 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let dir = std::env::temp_dir().join(format!("converge-scratch-{}", std::process::id()));
+        let config = SimConfig {
+            scratch: Some(dir.clone()),
+            ..SimConfig::default()
+        };
+        let in_memory = simulate(&program).unwrap();
+        let mut sim = Simulator::new(&program, &config).unwrap();
+        assert!(sim.connections[..2].iter().all(|c| c.mapped.is_some()));
+        assert_eq!(sim.connection(0).synapses, 200);
+        assert_eq!(sim.connection(1).synapses, 90);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        sim.run().unwrap();
+        let mapped = sim.summary();
+        assert!(in_memory.total_spikes > 0);
+        assert_eq!(results_json(&mapped), results_json(&in_memory));
+        drop(sim);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        let training = SimConfig {
+            learning_rate: Some(0.1),
+            ..config
+        };
+        let err = Simulator::new(&program, &training).err().unwrap();
+        assert!(err.to_string().contains("drop the scratch directory"));
+
+        // 5000 s in 1 us steps is more steps than a record's `u32` holds.
+        let long = "neuron LIF { tau_m = 10 ms }\nlayer A[2] : LIF\nlayer B[2] : LIF\n\
+                    connect A -> B { w = 1.0, d = 5000 s }\nrun for 1 ms step 1 us\n";
+        let scratch = SimConfig {
+            scratch: Some(dir.clone()),
+            ..SimConfig::default()
+        };
+        let err = Simulator::new(&parse_program(long).expect("parse"), &scratch)
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, SimErrorKind::Unsupported(_)), "{err}");
+        assert!(
+            err.to_string()
+                .contains("can't hold the delay of 5000000000 steps of a synapse into `B`"),
+            "{err}"
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Connects to an `External` stimulus on `port`, answers the first
+    /// prompts with `replies`, then hangs up or, with `linger`, keeps reading
+    /// prompts without answering. Returns the prompts it saw.
+    fn controller(
+        port: u16,
+        replies: &'static [&'static str],
+        linger: bool,
+    ) -> std::thread::JoinHandle<Vec<String>> {
+        use std::io::{BufRead, BufReader, Write};
+        std::thread::spawn(move || {
+            let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut prompts = Vec::new();
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                prompts.push(line);
+                match replies.get(prompts.len() - 1) {
+                    Some(reply) => writeln!(writer, "{reply}").unwrap(),
+                    None if linger => {}
+                    None => break,
+                }
+            }
+            prompts
+        })
+    }
+
+    fn external_run(
+        replies: &'static [&'static str],
+        linger: bool,
+        policy: &str,
+    ) -> (Result<SimSummary, SimError>, Vec<String>) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let src = format!(
+            "neuron LIF {{ tau_m = 10 ms, v_th = 1.0 }}\n\
+             layer Motor[2] : LIF\n\
+             stimulus Motor = External(port={port}, input=current, timeout=50 ms, on_timeout={policy})\n\
+             run for 6 ms step 1 ms\n"
+        );
+        let config = SimConfig {
+            record_spikes: true,
+            ..SimConfig::default()
+        };
+        let mut sim = Simulator::new(&parse_program(&src).unwrap(), &config).unwrap();
+        let client = controller(port, replies, linger);
+        let result = sim.run().map(|()| sim.summary());
+        drop(sim);
+        (result, client.join().unwrap())
+    }
+
+    fn fired(summary: SimSummary) -> Vec<(u64, u64)> {
+        summary
+            .raster
+            .iter()
+            .flatten()
+            .map(|s| (s.step, s.neuron))
+            .collect()
+    }
+
+    #[test]
+    fn external_currents_drive_each_step() {
+        let replies = &["0, 2", "0 2", "2", "0,0", "0,2", "0,2"];
+        let (summary, prompts) = external_run(replies, false, "error");
+        assert_eq!(
+            fired(summary.unwrap()),
+            [(0, 1), (1, 1), (2, 0), (2, 1), (4, 1), (5, 1)]
+        );
+        assert_eq!(prompts[0], r#"{"step":0,"t_ns":0}"#);
+        assert_eq!(prompts[5], r#"{"step":5,"t_ns":5000000}"#);
+
+        let (err, _) = external_run(&["0,2", "0,2,2"], false, "error");
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .ends_with("expected 1 or 2 values, got 3 at step 1")
+        );
+    }
+
+    #[test]
+    fn external_timeout_policies() {
+        let replies = &["0,2", "0,2"];
+        let (held, _) = external_run(replies, false, "hold");
+        assert_eq!(
+            fired(held.unwrap()),
+            (0..6).map(|step| (step, 1)).collect::<Vec<_>>()
+        );
+        let (zeroed, _) = external_run(replies, true, "zero");
+        assert_eq!(fired(zeroed.unwrap()), [(0, 1), (1, 1)]);
+
+        let (err, _) = external_run(replies, false, "error");
+        let message = err.unwrap_err().to_string();
+        assert!(message.starts_with("`External` on port "), "{message}");
+        assert!(message.ends_with(": the controller disconnected at step 2"));
+        let (err, prompts) = external_run(replies, true, "error");
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .ends_with(": no input within the timeout at step 2")
+        );
+        assert_eq!(prompts.len(), 3);
+    }
+
+    #[test]
+    fn restore_replays_from_a_snapshot() {
+        let src = r#"
+neuron LIF { tau_m = 10 ms, v_th = 1.0 }
+layer A[20] : LIF
+layer B[10] : LIF
+connect A -> B { w = Normal(0.4, 0.1), d = 2 ms, plasticity = RSTDP(w_max = 2) }
+stimulus A = Poisson(rate=300 Hz)
+reward 1.0 at 25 ms
+seed 8
+run for 40 ms
+"#;
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            record_spikes: true,
+            trace_neurons: 2,
+            ..SimConfig::default()
+        };
+        let finish = |sim: &mut Simulator| {
+            sim.run().unwrap();
+            (results_json(&sim.summary()), sim.weights(0))
+        };
+        let mut sim = Simulator::new(&program, &config).unwrap();
+        for _ in 0..15 {
+            sim.step().unwrap();
+        }
+        let snapshot = sim.snapshot();
+        assert_eq!(snapshot.step(), 15);
+        let first = finish(&mut sim);
+        assert!(sim.summary().total_spikes > 0);
+
+        sim.restore(&snapshot).unwrap();
+        assert_eq!(sim.current_step(), 15);
+        assert_eq!(finish(&mut sim), first);
+        let mut fresh = Simulator::new(&program, &config).unwrap();
+        fresh.restore(&snapshot).unwrap();
+        assert_eq!(finish(&mut fresh), first);
+
+        let other =
+            parse_program("neuron LIF { tau_m = 10 ms }\nlayer A[3] : LIF\nrun for 40 ms\n")
+                .expect("parse");
+        let err = Simulator::new(&other, &config)
+            .unwrap()
+            .restore(&snapshot)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "snapshot is from a different network");
+    }
+
+    #[test]
+    fn injected_currents_apply_to_the_next_step() {
+        let src = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+                   layer A[3] : LIF\n\
+                   run for 5 ms step 1 ms\n";
+        let config = SimConfig {
+            record_spikes: true,
+            ..SimConfig::default()
+        };
+        let mut sim = Simulator::new(&parse_program(src).unwrap(), &config).unwrap();
+        sim.step().unwrap();
+        sim.inject(0, &[0.0, 0.6, 1.5]).unwrap();
+        sim.inject(0, &[0.0, 0.6, 0.0]).unwrap();
+        sim.run().unwrap();
+        let spikes: Vec<(u64, u64)> = sim
+            .recorded_spikes()
+            .iter()
+            .map(|s| (s.step, s.neuron))
+            .collect();
+        assert_eq!(spikes, [(1, 1), (1, 2)]);
+
+        let mut message =
+            |layer, currents: &[f64]| sim.inject(layer, currents).unwrap_err().to_string();
+        assert_eq!(message(1, &[0.0]), "no layer 1");
+        assert_eq!(
+            message(0, &[1.0]),
+            "layer `A` has 3 neurons, got 1 currents"
+        );
+        assert_eq!(
+            message(0, &[0.0, f64::NAN, 0.0]),
+            "injected current NaN is not finite"
+        );
+    }
+
+    #[test]
+    fn sweeps_aggregate_one_run_per_seed() {
+        let model = "neuron LIF { tau_m = 10 ms }\n\
+                     layer In[8] : LIF\n\
+                     stimulus In = Poisson(rate=200 Hz)\n\
+                     run for 20 ms\n";
+        let src = format!(
+            "{model}seed 99\nexperiment {{ run with seed 4..6; report total_spikes, spikes(In) }}\n"
+        );
+        let program = parse_program(&src).expect("parse");
+        let mut seen = Vec::new();
+        let report = run_sweep(&program, &SimConfig::default(), |_, result| {
+            seen.push(result.seed)
+        })
+        .expect("sweep");
+        assert_eq!(seen, [4, 5, 6]);
+        assert_eq!(report.metrics, ["total_spikes", "spikes(In)"]);
+        let group = &report.groups[0];
+        for run in &group.runs {
+            let single = parse_program(&format!("{model}seed {}\n", run.seed)).expect("parse");
+            let summary = simulate(&single).expect("simulate");
+            assert_eq!(run.spike_hash, summary.spike_hash, "seed {}", run.seed);
+            assert_eq!(run.values, [summary.total_spikes as f64; 2]);
+        }
+        let totals: Vec<f64> = group.runs.iter().map(|r| r.values[0]).collect();
+        let stats = group.stats[0];
+        assert_eq!(stats.mean, totals.iter().sum::<f64>() / 3.0);
+        assert_eq!(
+            stats.min,
+            totals.iter().copied().fold(f64::INFINITY, f64::min)
+        );
+        assert!(stats.std > 0.0, "{totals:?}");
+    }
+
+    #[test]
+    fn streams_keep_subsystems_independent() {
+        let base = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+                    layer A[20] : LIF\n\
+                    layer B[10] : LIF\n\
+                    connect A -> B { w = Normal(0.4, 0.1), d = 2 ms }\n\
+                    stimulus A = Poisson(rate=300 Hz)\n\
+                    seed 8\n\
+                    run for 40 ms\n";
+        let build = |src: &str| {
+            let mut sim =
+                Simulator::new(&parse_program(src).unwrap(), &SimConfig::default()).unwrap();
+            sim.run().unwrap();
+            sim
+        };
+        let spikes = |sim: &Simulator| -> Vec<u64> {
+            sim.summary().layers.iter().map(|l| l.spikes).collect()
+        };
+        let plain = build(base);
+        assert!(spikes(&plain)[1] > 0);
+
+        // Another stimulus draws from the stimulus stream only.
+        let stimulated = build(&format!("{base}stimulus B = Poisson(rate=50 Hz)\n"));
+        assert_eq!(stimulated.weights(0), plain.weights(0));
+
+        // Another connection draws from the connectivity stream only.
+        let connected = build(&format!(
+            "{base}layer C[5] : LIF\nconnect B -> C {{ w = Uniform(0.1, 0.5), d = 1 ms }}\n"
+        ));
+        assert_eq!(connected.weights(0), plain.weights(0));
+        assert_eq!(spikes(&connected)[..2], spikes(&plain)[..]);
+    }
+
+    #[test]
+    fn replayed_input_matches_the_recording() {
+        let src = |v_th: f64| {
+            format!(
+                "neuron LIF {{ tau_m = 10 ms, v_th = 1.0 }}\n\
+                 neuron Out {{ tau_m = 10 ms, v_th = {v_th} }}\n\
+                 layer A[20] : LIF\n\
+                 layer B[10] : Out\n\
+                 connect A -> B {{ w = Normal(0.4, 0.1), d = 2 ms }}\n\
+                 stimulus A = Poisson(rate=300 Hz)\n\
+                 stimulus B = Poisson(rate=50 Hz)\n\
+                 seed 8\n\
+                 run for 40 ms\n"
+            )
+        };
+        let run = |src: &str, replay: Option<&raster::Recording>| {
+            let config = SimConfig {
+                record_input: true,
+                replay_input: replay.cloned(),
+                ..SimConfig::default()
+            };
+            simulate_with(&parse_program(src).unwrap(), &config).unwrap()
+        };
+        let original = run(&src(1.0), None);
+        let input = original.input.clone().unwrap();
+        assert!(input.iter().any(|s| s.layer == 1));
+        let mut bytes = Vec::new();
+        raster::write_input(&mut bytes, &original).unwrap();
+        let recording = raster::decode(&bytes).unwrap();
+        assert_eq!(recording.spikes, input);
+
+        let replayed = run(&src(1.0), Some(&recording));
+        assert_eq!(results_json(&replayed), results_json(&original));
+        assert_eq!(replayed.input.unwrap(), input);
+
+        // A changed network sees the same input.
+        let changed = run(&src(0.5), Some(&recording));
+        assert_eq!(changed.input.unwrap(), input);
+        assert_ne!(changed.spike_hash, original.spike_hash);
+
+        let longer = src(1.0).replace("40 ms", "60 ms");
+        let err = Simulator::new(
+            &parse_program(&longer).unwrap(),
+            &SimConfig {
+                replay_input: Some(recording),
+                ..SimConfig::default()
+            },
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "the input recording covers 40 steps, the run needs 60"
+        );
+    }
+
+    #[test]
+    fn stimuli_combine_as_asked() {
+        let input_rate = |combine: &str| {
+            let src = format!(
+                "neuron Quiet {{ v_th = 1000 }}\n\
+                 layer A[100] : Quiet\n\
+                 stimulus A = Poisson(rate=200 Hz)\n\
+                 stimulus A = Poisson(rate=150 Hz){combine}\n\
+                 seed 2\n\
+                 run for 1 s\n"
+            );
+            let config = SimConfig {
+                record_input: true,
+                ..SimConfig::default()
+            };
+            let summary = simulate_with(&parse_program(&src).unwrap(), &config).unwrap();
+            let input = summary.input.unwrap();
+            (input.len() as f64 / 100.0, input)
+        };
+        let (sum, input) = input_rate("");
+        assert!((sum - 350.0).abs() < 15.0, "{sum}");
+        assert_eq!(input_rate(" combine = sum").1, input);
+        let (max, _) = input_rate(" combine = max");
+        assert!((max - 200.0).abs() < 12.0, "{max}");
+        let (replaced, _) = input_rate(" combine = replace");
+        assert!((replaced - 150.0).abs() < 12.0, "{replaced}");
+    }
+
+    #[test]
+    fn correlated_poisson_input_shares_spikes() {
+        let src = "neuron Quiet { v_th = 1000 }
+                   layer A[100] : Quiet
+                   layer B[100] : Quiet
+                   stimulus A = Poisson(rate=20 Hz)
+                   stimulus B = Poisson(rate=20 Hz, correlation=0.5)
+                   seed 4
+                   run for 2 s
+";
+        let config = SimConfig {
+            record_input: true,
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&parse_program(src).unwrap(), &config).unwrap();
+        let input = summary.input.unwrap();
+        // The mean pairwise correlation of the neurons' per-step spike
+        // trains, from the variance of the layer's spike count per step.
+        let stats = |layer: u32| {
+            let mut counts = vec![0.0; 2000];
+            for spike in input.iter().filter(|s| s.layer == layer) {
+                counts[spike.step as usize] += 1.0;
+            }
+            let n = 100.0;
+            let mean = counts.iter().sum::<f64>() / counts.len() as f64;
+            let var =
+                counts.iter().map(|c| (c - mean) * (c - mean)).sum::<f64>() / counts.len() as f64;
+            let p = mean / n;
+            let rate_hz = p * 1000.0;
+            let correlation = (var - n * p * (1.0 - p)) / (n * (n - 1.0) * p * (1.0 - p));
+            (rate_hz, correlation)
+        };
+        let (rate, correlation) = stats(0);
+        assert!((rate - 20.0).abs() < 2.0, "{rate}");
+        assert!(correlation.abs() < 0.02, "{correlation}");
+        let (rate, correlation) = stats(1);
+        assert!((rate - 20.0).abs() < 3.0, "{rate}");
+        assert!((correlation - 0.5).abs() < 0.1, "{correlation}");
+
+        let too_high = src.replace("correlation=0.5", "correlation=0.01");
+        let err = simulate(&parse_program(&too_high).unwrap()).unwrap_err();
+        assert!(
+            matches!(err.kind, SimErrorKind::RateTooHigh { .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn delays_can_be_counted_in_steps() {
+        let delays = |d: &str| {
+            let src = format!(
+                "neuron LIF {{ tau_m = 20 ms }}\n\
+                 layer A[4] : LIF\n\
+                 connect A -> A {{ d = {d} }}\n\
+                 run for 3 ms step 0.3 ms\n"
+            );
+            let sim = Simulator::new(&parse_program(&src).expect("parse"), &SimConfig::default())?;
+            Ok::<_, SimError>(
+                sim.connections[0]
+                    .synapses
+                    .iter()
+                    .flatten()
+                    .map(|s| s.delay_steps)
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(delays("3 steps").unwrap(), [3; 16]);
+        assert_eq!(delays("1 step").unwrap(), [1; 16]);
+        assert!(matches!(
+            delays("1 ms").unwrap_err().kind,
+            SimErrorKind::UnevenDelay { .. }
+        ));
+        // Drawn delays are rounded to whole steps.
+        let drawn = delays("Uniform(1 steps, 4 steps)").unwrap();
+        assert!(drawn.iter().all(|d| (1..=4).contains(d)), "{drawn:?}");
+        assert!(drawn.iter().any(|&d| d != drawn[0]), "{drawn:?}");
     }
 }
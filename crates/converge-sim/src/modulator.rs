@@ -0,0 +1,110 @@
+//! Global neuromodulators: scalar levels that decay toward zero with `tau`
+//! and rise by `gain` for every spike of their source layer. Neurons read
+//! them as a threshold shift and R-STDP as a continuous reward.
+
+use std::collections::HashMap;
+
+use converge_lang::ast::{Expr, Item, ModulatorDef, Program};
+use converge_lang::units::time_to_nanos;
+
+use crate::{SimError, SimErrorKind, unit_err};
+
+#[derive(Clone)]
+pub(crate) struct Modulator {
+    pub(crate) name: String,
+    tau_ns: i64,
+    source: usize,
+    gain: f64,
+    pub(crate) level: f64,
+}
+
+impl Modulator {
+    /// Decays the level, then adds this step's source spikes.
+    pub(crate) fn step(&mut self, spiked: &[Vec<usize>], step_ns: i64) {
+        self.level += (-self.level) * (step_ns as f64 / self.tau_ns as f64);
+        self.level += self.gain * spiked[self.source].len() as f64;
+    }
+}
+
+/// Modulator indices by name, in source order.
+pub(crate) fn modulator_index(program: &Program) -> HashMap<String, usize> {
+    program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Modulator(def) => Some(def.name.name.clone()),
+            _ => None,
+        })
+        .enumerate()
+        .map(|(idx, name)| (name, idx))
+        .collect()
+}
+
+pub(crate) fn build_modulators(
+    program: &Program,
+    layer_index: &HashMap<String, usize>,
+) -> Result<Vec<Modulator>, SimError> {
+    let mut modulators = Vec::new();
+    for item in &program.items {
+        let Item::Modulator(ModulatorDef { name, body, .. }) = item else {
+            continue;
+        };
+        let mut tau_ns = None;
+        let mut source = None;
+        let mut gain = 1.0;
+        for assign in body {
+            match (assign.key.name.as_str(), &assign.value) {
+                ("tau", Expr::Number(q)) => {
+                    tau_ns = Some(time_to_nanos(q, "modulator tau").map_err(unit_err)?);
+                }
+                ("source", Expr::Ident(layer)) => {
+                    source = Some(*layer_index.get(&layer.name).ok_or_else(|| {
+                        SimError::new(SimErrorKind::UnknownLayer {
+                            role: "modulator source",
+                            name: layer.name.clone(),
+                        })
+                        .with_span(layer.span.clone())
+                    })?);
+                }
+                ("gain", Expr::Number(q)) => gain = q.value,
+                (key, _) => {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                        "invalid modulator parameter `{key}`"
+                    )))
+                    .with_span(assign.span.clone()));
+                }
+            }
+        }
+        let (Some(tau_ns), Some(source)) = (tau_ns.filter(|&t| t > 0), source) else {
+            return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                "modulator `{}` needs a positive `tau` and a `source` layer",
+                name.name
+            )))
+            .with_span(name.span.clone()));
+        };
+        modulators.push(Modulator {
+            name: name.name.clone(),
+            tau_ns,
+            source,
+            gain,
+            level: 0.0,
+        });
+    }
+    Ok(modulators)
+}
+
+/// Resolves a `modulator = <name>` reference.
+pub(crate) fn modulator_ref(
+    expr: &Expr,
+    index: &HashMap<String, usize>,
+) -> Result<usize, SimError> {
+    match expr {
+        Expr::Ident(id) => index.get(&id.name).copied().ok_or_else(|| {
+            SimError::new(SimErrorKind::UnknownModulator(id.name.clone()))
+                .with_span(id.span.clone())
+        }),
+        _ => Err(SimError::new(SimErrorKind::InvalidParameter(
+            "expected a modulator name".to_string(),
+        ))),
+    }
+}
@@ -0,0 +1,408 @@
+//! Export of a run in the layout of Neurodata Without Borders (NWB 2.x), for
+//! electrophysiology analysis tools. The file is written by our own HDF5
+//! encoder and nothing here opens it with a real HDF5 or NWB reader, so
+//! compatibility with pynwb and friends is untested. Needs the `nwb` feature.
+//!
+//! ```text
+//! /                          NWBFile: identifier, session times
+//!   units/                   one row per neuron, across layers in order
+//!     id, spike_times, spike_times_index, layer, neuron
+//!   acquisition/
+//!     membrane_<layer>       TimeSeries of traced membrane potentials
+//!     rate_<layer>           TimeSeries of a `record rate` trace, in Hz
+//!     probe_<name>           TimeSeries of a `probe`'s neurons
+//!   analysis/ processing/ stimulus/ general/
+//! ```
+//!
+//! Spike times are in seconds from the start of the run. Object IDs are
+//! derived from the identifier, so one identifier always gives one file.
+
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::SimSummary;
+use crate::hdf5::{self, Dataset, Group, Node, Value};
+
+pub const NWB_VERSION: &str = "2.7.0";
+
+/// What the file says about the session it records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    /// Unique to this file.
+    pub identifier: String,
+    pub description: String,
+    /// ISO 8601, as from [`iso8601`].
+    pub start_time: String,
+}
+
+/// `t` as an ISO 8601 date and time in UTC.
+pub fn iso8601(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    // Days to a civil date, after Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}+00:00",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// Writes the spikes of a run recorded with `SimConfig::record_spikes`,
+/// and its membrane and rate traces, as an NWB file.
+pub fn write_nwb(out: &mut impl Write, summary: &SimSummary, session: &Session) -> io::Result<()> {
+    let ids = ObjectIds(&session.identifier);
+    let text = |s: &str| Value::Text(s.to_string());
+    let step_s = summary.step_ns as f64 * 1e-9;
+
+    let mut acquisition = Group::default();
+    let mut names = Vec::new();
+    for (idx, layer) in summary.layers.iter().enumerate() {
+        let traces: Vec<&[f64]> = summary
+            .voltages
+            .iter()
+            .flatten()
+            .filter(|t| t.layer as usize == idx)
+            .map(|t| t.samples.as_slice())
+            .collect();
+        if traces.is_empty() {
+            continue;
+        }
+        let name = unique(&mut names, format!("membrane_{}", layer.name));
+        let description = format!(
+            "membrane potential of the first {} neurons of `{}`",
+            traces.len(),
+            layer.name
+        );
+        let series = time_series(&ids, &name, &description, "a.u.", &traces, step_s);
+        acquisition = acquisition.member(&name, series);
+    }
+    for trace in &summary.rates {
+        let layer = &summary.layers[trace.layer as usize].name;
+        let name = unique(&mut names, format!("rate_{layer}"));
+        let description = format!(
+            "population rate of `{layer}`, smoothed over {} ns",
+            trace.window_ns
+        );
+        let series = time_series(&ids, &name, &description, "Hz", &[&trace.samples], step_s);
+        acquisition = acquisition.member(&name, series);
+    }
+    for probe in &summary.probes {
+        let name = unique(&mut names, format!("probe_{}", probe.name));
+        let layer = &summary.layers[probe.layer as usize].name;
+        let description = format!(
+            "membrane potential of neurons {}..{} of `{layer}`, from probe `{}`",
+            probe.first,
+            probe.first + probe.samples.len() as u64,
+            probe.name
+        );
+        let traces: Vec<&[f64]> = probe.samples.iter().map(Vec::as_slice).collect();
+        let interval_s = step_s * probe.every_steps as f64;
+        let series = time_series(&ids, &name, &description, "a.u.", &traces, interval_s);
+        acquisition = acquisition.member(&name, series);
+    }
+
+    let root = Group::default()
+        .attr("namespace", text("core"))
+        .attr("neurodata_type", text("NWBFile"))
+        .attr("nwb_version", text(NWB_VERSION))
+        .attr("object_id", text(&ids.get("/")))
+        .member(
+            "file_create_date",
+            dataset(Value::TextArray(vec![iso8601(SystemTime::now())])),
+        )
+        .member("identifier", dataset(text(&session.identifier)))
+        .member("session_description", dataset(text(&session.description)))
+        .member("session_start_time", dataset(text(&session.start_time)))
+        .member(
+            "timestamps_reference_time",
+            dataset(text(&session.start_time)),
+        )
+        .member("acquisition", Node::Group(acquisition))
+        .member("analysis", Node::Group(Group::default()))
+        .member("general", Node::Group(Group::default()))
+        .member("processing", Node::Group(Group::default()))
+        .member(
+            "stimulus",
+            Node::Group(
+                Group::default()
+                    .member("presentation", Node::Group(Group::default()))
+                    .member("templates", Node::Group(Group::default())),
+            ),
+        )
+        .member("units", units(&ids, summary));
+    hdf5::write(out, &root)
+}
+
+fn dataset(value: Value) -> Node {
+    Node::Dataset(Dataset::new(value))
+}
+
+/// The `Units` table: spike times grouped by neuron, with each neuron's
+/// layer and index in it.
+fn units(ids: &ObjectIds, summary: &SimSummary) -> Node {
+    let text = |s: &str| Value::Text(s.to_string());
+    let mut first = Vec::with_capacity(summary.layers.len());
+    let mut total = 0;
+    for layer in &summary.layers {
+        first.push(total);
+        total += layer.size as usize;
+    }
+    let mut times: Vec<Vec<f64>> = vec![Vec::new(); total];
+    let step_s = summary.step_ns as f64 * 1e-9;
+    for spike in summary.raster.iter().flatten() {
+        let unit = first[spike.layer as usize] + spike.neuron as usize;
+        times[unit].push(spike.step as f64 * step_s);
+    }
+    let mut index = Vec::with_capacity(total);
+    let mut end = 0;
+    for unit in &times {
+        end += unit.len() as u64;
+        index.push(end);
+    }
+    let mut layers = Vec::with_capacity(total);
+    let mut neurons = Vec::with_capacity(total);
+    for layer in &summary.layers {
+        layers.extend(std::iter::repeat_n(layer.name.clone(), layer.size as usize));
+        neurons.extend(0..layer.size as i64);
+    }
+    let spike_times: Vec<f64> = times.into_iter().flatten().collect();
+
+    let column = |name: &str, kind: &str, description: &str, value: Value| {
+        Dataset::new(value)
+            .attr("description", text(description))
+            .attr("namespace", text("hdmf-common"))
+            .attr("neurodata_type", text(kind))
+            .attr("object_id", text(&ids.get(&format!("/units/{name}"))))
+    };
+    let index = column(
+        "spike_times_index",
+        "VectorIndex",
+        "Index for VectorData 'spike_times'",
+        Value::U64Array(index),
+    )
+    .attr("target", Value::Ref("/units/spike_times".to_string()));
+    Node::Group(
+        Group::default()
+            .attr(
+                "colnames",
+                Value::TextArray(vec![
+                    "spike_times".to_string(),
+                    "layer".to_string(),
+                    "neuron".to_string(),
+                ]),
+            )
+            .attr("description", text("simulated neurons"))
+            .attr("namespace", text("core"))
+            .attr("neurodata_type", text("Units"))
+            .attr("object_id", text(&ids.get("/units")))
+            .member(
+                "id",
+                Node::Dataset(
+                    Dataset::new(Value::I64Array((0..total as i64).collect()))
+                        .attr("namespace", text("hdmf-common"))
+                        .attr("neurodata_type", text("ElementIdentifiers"))
+                        .attr("object_id", text(&ids.get("/units/id"))),
+                ),
+            )
+            .member(
+                "spike_times",
+                Node::Dataset(column(
+                    "spike_times",
+                    "VectorData",
+                    "the spike times for each unit in seconds",
+                    Value::F64Array {
+                        shape: vec![spike_times.len() as u64],
+                        data: spike_times,
+                    },
+                )),
+            )
+            .member("spike_times_index", Node::Dataset(index))
+            .member(
+                "layer",
+                Node::Dataset(column(
+                    "layer",
+                    "VectorData",
+                    "the layer of each neuron",
+                    Value::TextArray(layers),
+                )),
+            )
+            .member(
+                "neuron",
+                Node::Dataset(column(
+                    "neuron",
+                    "VectorData",
+                    "the index of each neuron in its layer",
+                    Value::I64Array(neurons),
+                )),
+            ),
+    )
+}
+
+/// A `TimeSeries` of `traces`, one column each, sampled every step from 0.
+fn time_series(
+    ids: &ObjectIds,
+    name: &str,
+    description: &str,
+    unit: &str,
+    traces: &[&[f64]],
+    step_s: f64,
+) -> Node {
+    let text = |s: &str| Value::Text(s.to_string());
+    let samples = traces.first().map_or(0, |t| t.len());
+    let mut data = Vec::with_capacity(samples * traces.len());
+    for i in 0..samples {
+        data.extend(traces.iter().map(|t| t[i]));
+    }
+    let shape = if traces.len() == 1 {
+        vec![samples as u64]
+    } else {
+        vec![samples as u64, traces.len() as u64]
+    };
+    Node::Group(
+        Group::default()
+            .attr("comments", text("no comments"))
+            .attr("description", text(description))
+            .attr("namespace", text("core"))
+            .attr("neurodata_type", text("TimeSeries"))
+            .attr("object_id", text(&ids.get(&format!("/acquisition/{name}"))))
+            .member(
+                "data",
+                Node::Dataset(
+                    Dataset::new(Value::F64Array { shape, data })
+                        .attr("conversion", Value::F32(1.0))
+                        .attr("offset", Value::F32(0.0))
+                        .attr("resolution", Value::F32(-1.0))
+                        .attr("unit", text(unit)),
+                ),
+            )
+            .member(
+                "starting_time",
+                Node::Dataset(
+                    Dataset::new(Value::F64(0.0))
+                        .attr("rate", Value::F32((1.0 / step_s) as f32))
+                        .attr("unit", text("seconds")),
+                ),
+            ),
+    )
+}
+
+/// `name`, or with a numeric suffix if an earlier call already returned it.
+fn unique(names: &mut Vec<String>, name: String) -> String {
+    let mut candidate = name.clone();
+    let mut n = 2;
+    while names.contains(&candidate) {
+        candidate = format!("{name}_{n}");
+        n += 1;
+    }
+    names.push(candidate.clone());
+    candidate
+}
+
+/// UUIDs (version 4 layout) from the file's identifier and an object's path.
+struct ObjectIds<'a>(&'a str);
+
+impl ObjectIds<'_> {
+    fn get(&self, path: &str) -> String {
+        let fnv = |salt: u8| {
+            let mut h: u64 = 0xcbf29ce484222325;
+            for &b in self
+                .0
+                .as_bytes()
+                .iter()
+                .chain(&[0, salt])
+                .chain(path.as_bytes())
+            {
+                h ^= b as u64;
+                h = h.wrapping_mul(0x100000001b3);
+            }
+            h
+        };
+        let (hi, lo) = (fnv(1), fnv(2));
+        let hi = (hi & !0xf000) | 0x4000;
+        let lo = (lo & !(0xc << 60)) | (0x8 << 60);
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            hi >> 32,
+            (hi >> 16) & 0xffff,
+            hi & 0xffff,
+            lo >> 48,
+            lo & 0xffff_ffff_ffff
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimConfig, simulate_with};
+    use converge_lang::parser::parse_program;
+    use std::time::Duration;
+
+    #[test]
+    fn formats_utc_dates() {
+        assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00+00:00");
+        let t = UNIX_EPOCH + Duration::from_secs(951_826_332);
+        assert_eq!(iso8601(t), "2000-02-29T12:12:12+00:00");
+    }
+
+    #[test]
+    fn object_ids_are_version_4_uuids() {
+        let ids = ObjectIds("run-1");
+        let id = ids.get("/units");
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert!("89ab".contains(&id[19..20]), "{id}");
+        assert_ne!(id, ids.get("/"));
+        assert_eq!(id, ObjectIds("run-1").get("/units"));
+    }
+
+    #[test]
+    fn writes_units_and_traces() {
+        let src = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+                   layer In[6] : LIF\n\
+                   layer Out[3] : LIF\n\
+                   connect In -> Out { w = 0.6, d = 1 ms }\n\
+                   stimulus In = Poisson(rate=400 Hz)\n\
+                   record rate from In window 5 ms\n\
+                   probe first = v(Out[0..1]) every 2 ms\n\
+                   seed 4\n\
+                   run for 30 ms\n";
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            record_spikes: true,
+            trace_neurons: 2,
+            ..SimConfig::default()
+        };
+        let summary = simulate_with(&program, &config).expect("sim");
+        let session = Session {
+            identifier: "test".to_string(),
+            description: "a test run".to_string(),
+            start_time: iso8601(UNIX_EPOCH),
+        };
+        let mut bytes = Vec::new();
+        write_nwb(&mut bytes, &summary, &session).unwrap();
+        assert_eq!(&bytes[..4], b"\x89HDF");
+        let contains = |s: &str| bytes.windows(s.len()).any(|w| w == s.as_bytes());
+        for name in [
+            "NWBFile",
+            "spike_times_index",
+            "membrane_In",
+            "membrane_Out",
+            "rate_In",
+            "probe_first",
+            NWB_VERSION,
+        ] {
+            assert!(contains(name), "missing {name}");
+        }
+    }
+}
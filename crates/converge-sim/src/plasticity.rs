@@ -0,0 +1,430 @@
+//! Reward-modulated STDP (R-STDP).
+//!
+//! Pre- and postsynaptic spikes leave decaying traces. Pairing them does not
+//! change weights directly. Instead it charges a per-synapse eligibility
+//! trace, and weights move only when a reward arrives:
+//! `w += lr * reward * e`, clamped to `[w_min, w_max]`.
+//!
+//! Delay STDP moves delays instead of weights, so that a synapse's spikes
+//! arrive when its target fires: arriving before a postsynaptic spike
+//! lengthens the delay, arriving after one shortens it, by `lr` steps
+//! scaled down linearly to nothing at `window` apart. The fractional
+//! shifts add up per synapse, and the delay moves by each whole step they
+//! reach, within `[d_min, d_max]`.
+
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use converge_lang::ast::{CallArg, Expr};
+use converge_lang::units::{delay_to_nanos, time_to_nanos};
+
+use crate::modulator::modulator_ref;
+use crate::{SimError, SimErrorKind, Synapse, unit_err};
+
+/// Parameters of `plasticity = RSTDP(...)`.
+#[derive(Debug, Clone)]
+pub(crate) struct RStdp {
+    pub(crate) a_plus: f64,
+    pub(crate) a_minus: f64,
+    pub(crate) tau_plus_ns: i64,
+    pub(crate) tau_minus_ns: i64,
+    pub(crate) tau_e_ns: i64,
+    pub(crate) lr: f64,
+    pub(crate) w_min: f64,
+    pub(crate) w_max: f64,
+    /// Modulator whose level is added to the reward every step.
+    pub(crate) modulator: Option<usize>,
+}
+
+impl Default for RStdp {
+    fn default() -> Self {
+        Self {
+            a_plus: 0.01,
+            a_minus: 0.012,
+            tau_plus_ns: 20_000_000,
+            tau_minus_ns: 20_000_000,
+            tau_e_ns: 200_000_000,
+            lr: 1.0,
+            w_min: 0.0,
+            w_max: 1.0,
+            modulator: None,
+        }
+    }
+}
+
+impl RStdp {
+    pub(crate) fn from_expr(
+        expr: &Expr,
+        modulators: &HashMap<String, usize>,
+    ) -> Result<Self, SimError> {
+        let call = match expr {
+            Expr::Call(call) if call.name.name == "RSTDP" => call,
+            Expr::Call(call) => {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(
+                    "plasticity must be `RSTDP(...)`".to_string(),
+                ))
+                .with_span(call.span.clone()));
+            }
+            _ => {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(
+                    "plasticity must be `RSTDP(...)`".to_string(),
+                )));
+            }
+        };
+        let mut rule = Self::default();
+        for arg in &call.args {
+            let CallArg::Named { name, value } = arg else {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(
+                    "`RSTDP` arguments must be named".to_string(),
+                ))
+                .with_span(call.span.clone()));
+            };
+            if name.name == "modulator" {
+                rule.modulator = Some(modulator_ref(value, modulators)?);
+                continue;
+            }
+            let Expr::Number(q) = value else {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                    "`RSTDP` argument `{}` must be a number",
+                    name.name
+                )))
+                .with_span(name.span.clone()));
+            };
+            let time = || -> Result<i64, SimError> {
+                let ns = time_to_nanos(q, &name.name).map_err(unit_err)?;
+                if ns <= 0 {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                        "`RSTDP` argument `{}` must be positive",
+                        name.name
+                    )))
+                    .with_span(q.span.clone()));
+                }
+                Ok(ns)
+            };
+            match name.name.as_str() {
+                "a_plus" => rule.a_plus = q.value,
+                "a_minus" => rule.a_minus = q.value,
+                "tau_plus" => rule.tau_plus_ns = time()?,
+                "tau_minus" => rule.tau_minus_ns = time()?,
+                "tau_e" => rule.tau_e_ns = time()?,
+                "lr" => rule.lr = q.value,
+                "w_min" => rule.w_min = q.value,
+                "w_max" => rule.w_max = q.value,
+                other => {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                        "unknown `RSTDP` argument `{other}`"
+                    )))
+                    .with_span(name.span.clone()));
+                }
+            }
+        }
+        if rule.w_min > rule.w_max {
+            return Err(SimError::new(SimErrorKind::InvalidParameter(
+                "`RSTDP` needs w_min <= w_max".to_string(),
+            ))
+            .with_span(call.span.clone()));
+        }
+        Ok(rule)
+    }
+}
+
+/// Learning state for one plastic connection. `eligibility` is parallel to
+/// the connection's synapse table.
+#[derive(Clone)]
+pub(crate) struct Plasticity {
+    rule: RStdp,
+    pre: Vec<f64>,
+    post: Vec<f64>,
+    eligibility: Vec<Vec<f64>>,
+}
+
+impl Plasticity {
+    /// Called once the synapse table is final, after any `disconnect`.
+    pub(crate) fn new(rule: RStdp, synapses: &[Vec<Synapse>], dst_size: usize) -> Self {
+        Self {
+            rule,
+            pre: vec![0.0; synapses.len()],
+            post: vec![0.0; dst_size],
+            eligibility: synapses.iter().map(|l| vec![0.0; l.len()]).collect(),
+        }
+    }
+
+    pub(crate) fn modulator(&self) -> Option<usize> {
+        self.rule.modulator
+    }
+
+    /// One learning step, after this step's spikes have been delivered.
+    /// Traces and eligibility decay first. Then each pre spike depresses by
+    /// `a_minus * post` and each post spike potentiates by `a_plus * pre`,
+    /// using traces from before this step's spikes, so same-step pairs do
+    /// not count. Finally a nonzero `reward` moves the weights.
+    pub(crate) fn step(
+        &mut self,
+        synapses: &mut [Vec<Synapse>],
+        pre_spiked: &[usize],
+        post_spiked: &[usize],
+        reward: f64,
+        step_ns: i64,
+    ) {
+        let dt = step_ns as f64;
+        let rule = &self.rule;
+        decay(&mut self.pre, dt / rule.tau_plus_ns as f64);
+        decay(&mut self.post, dt / rule.tau_minus_ns as f64);
+        let e_decay = dt / rule.tau_e_ns as f64;
+        for elig in &mut self.eligibility {
+            decay(elig, e_decay);
+        }
+
+        for &i in pre_spiked {
+            for (syn, e) in synapses[i].iter().zip(&mut self.eligibility[i]) {
+                *e -= rule.a_minus * self.post[syn.dst];
+            }
+        }
+        if !post_spiked.is_empty() {
+            let mut fired = vec![false; self.post.len()];
+            for &j in post_spiked {
+                fired[j] = true;
+            }
+            for (i, (syns, elig)) in synapses.iter().zip(&mut self.eligibility).enumerate() {
+                for (syn, e) in syns.iter().zip(elig) {
+                    if fired[syn.dst] {
+                        *e += rule.a_plus * self.pre[i];
+                    }
+                }
+            }
+        }
+        for &i in pre_spiked {
+            self.pre[i] += 1.0;
+        }
+        for &j in post_spiked {
+            self.post[j] += 1.0;
+        }
+
+        if reward != 0.0 {
+            let scale = rule.lr * reward;
+            for (syns, elig) in synapses.iter_mut().zip(&self.eligibility) {
+                for (syn, e) in syns.iter_mut().zip(elig) {
+                    syn.weight = (syn.weight + scale * e).clamp(rule.w_min, rule.w_max);
+                }
+            }
+        }
+    }
+
+    /// Heap bytes held by traces and eligibility, in the same accounting as
+    /// `Estimate`.
+    pub(crate) fn bytes(&self) -> usize {
+        let f64_size = size_of::<f64>();
+        (self.pre.capacity() + self.post.capacity()) * f64_size
+            + self
+                .eligibility
+                .iter()
+                .map(|e| size_of::<Vec<f64>>() + e.capacity() * f64_size)
+                .sum::<usize>()
+    }
+}
+
+/// Parameters of `delay_plasticity = DelaySTDP(...)`, with the bounds in
+/// run steps.
+#[derive(Debug, Clone)]
+pub(crate) struct DelayStdp {
+    pub(crate) lr: f64,
+    pub(crate) window_ns: i64,
+    pub(crate) d_min: usize,
+    pub(crate) d_max: usize,
+}
+
+impl DelayStdp {
+    pub(crate) fn from_expr(expr: &Expr, step_ns: i64) -> Result<Self, SimError> {
+        let call = match expr {
+            Expr::Call(call) if call.name.name == "DelaySTDP" => call,
+            _ => {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(
+                    "delay_plasticity must be `DelaySTDP(...)`".to_string(),
+                )));
+            }
+        };
+        let mut rule = Self {
+            lr: 0.1,
+            window_ns: 20_000_000,
+            d_min: 0,
+            d_max: 0,
+        };
+        let mut d_max = None;
+        for arg in &call.args {
+            let CallArg::Named {
+                name,
+                value: Expr::Number(q),
+            } = arg
+            else {
+                return Err(SimError::new(SimErrorKind::InvalidParameter(
+                    "`DelaySTDP` arguments must be named numbers".to_string(),
+                ))
+                .with_span(call.span.clone()));
+            };
+            let steps = || -> Result<usize, SimError> {
+                let ns = delay_to_nanos(q, step_ns).map_err(unit_err)?.round() as i64;
+                if ns < 0 || ns % step_ns != 0 {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                        "`DelaySTDP` argument `{}` must be a whole number of steps, 0 or more",
+                        name.name
+                    )))
+                    .with_span(q.span.clone()));
+                }
+                Ok((ns / step_ns) as usize)
+            };
+            match name.name.as_str() {
+                "lr" => rule.lr = q.value,
+                "window" => {
+                    rule.window_ns = time_to_nanos(q, "window").map_err(unit_err)?;
+                    if rule.window_ns <= 0 {
+                        return Err(SimError::new(SimErrorKind::InvalidParameter(
+                            "`DelaySTDP` argument `window` must be positive".to_string(),
+                        ))
+                        .with_span(q.span.clone()));
+                    }
+                }
+                "d_min" => rule.d_min = steps()?,
+                "d_max" => d_max = Some(steps()?),
+                other => {
+                    return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                        "unknown `DelaySTDP` argument `{other}`"
+                    )))
+                    .with_span(name.span.clone()));
+                }
+            }
+        }
+        rule.d_max = d_max.ok_or_else(|| {
+            SimError::new(SimErrorKind::InvalidParameter(
+                "`DelaySTDP` needs a `d_max`".to_string(),
+            ))
+            .with_span(call.span.clone())
+        })?;
+        if rule.d_min > rule.d_max {
+            return Err(SimError::new(SimErrorKind::InvalidParameter(
+                "`DelaySTDP` needs d_min <= d_max".to_string(),
+            ))
+            .with_span(call.span.clone()));
+        }
+        Ok(rule)
+    }
+}
+
+/// Delay learning state for one connection. `shift` is parallel to the
+/// connection's synapse table.
+#[derive(Clone)]
+pub(crate) struct DelayPlasticity {
+    rule: DelayStdp,
+    /// The step each neuron last spiked on.
+    last_pre: Vec<Option<usize>>,
+    last_post: Vec<Option<usize>>,
+    shift: Vec<Vec<f64>>,
+}
+
+impl DelayPlasticity {
+    /// Called once the synapse table is final, after any `disconnect`.
+    /// Fails when a delay starts outside the rule's bounds.
+    pub(crate) fn new(
+        rule: DelayStdp,
+        synapses: &[Vec<Synapse>],
+        dst_size: usize,
+    ) -> Result<Self, SimError> {
+        if let Some(syn) = synapses
+            .iter()
+            .flatten()
+            .find(|s| !(rule.d_min..=rule.d_max).contains(&s.delay_steps))
+        {
+            return Err(SimError::new(SimErrorKind::InvalidParameter(format!(
+                "a delay of {} steps is outside the `DelaySTDP` bounds of {} to {} steps",
+                syn.delay_steps, rule.d_min, rule.d_max
+            ))));
+        }
+        Ok(Self {
+            rule,
+            last_pre: vec![None; synapses.len()],
+            last_post: vec![None; dst_size],
+            shift: synapses.iter().map(|l| vec![0.0; l.len()]).collect(),
+        })
+    }
+
+    /// The longest delay the rule can reach, in steps.
+    pub(crate) fn max_delay(&self) -> usize {
+        self.rule.d_max
+    }
+
+    /// One learning step, after this step's spikes have been delivered.
+    /// Each pre spike is compared with the last post spike before this
+    /// step, and each post spike with the arrival of the last pre spike
+    /// before this step, so same-step pairs do not count.
+    pub(crate) fn step(
+        &mut self,
+        synapses: &mut [Vec<Synapse>],
+        pre_spiked: &[usize],
+        post_spiked: &[usize],
+        step: usize,
+        step_ns: i64,
+    ) {
+        let rule = &self.rule;
+        let steps_per_window = rule.window_ns as f64 / step_ns as f64;
+        let amount = |apart: usize| rule.lr * (1.0 - apart as f64 / steps_per_window).max(0.0);
+
+        for &i in pre_spiked {
+            for (syn, shift) in synapses[i].iter_mut().zip(&mut self.shift[i]) {
+                if let Some(post) = self.last_post[syn.dst] {
+                    *shift -= amount(step + syn.delay_steps - post);
+                    nudge(syn, shift, rule);
+                }
+            }
+        }
+        if !post_spiked.is_empty() {
+            let mut fired = vec![false; self.last_post.len()];
+            for &j in post_spiked {
+                fired[j] = true;
+            }
+            for (i, (syns, shifts)) in synapses.iter_mut().zip(&mut self.shift).enumerate() {
+                let Some(pre) = self.last_pre[i] else {
+                    continue;
+                };
+                for (syn, shift) in syns.iter_mut().zip(shifts) {
+                    let arrival = pre + syn.delay_steps;
+                    if fired[syn.dst] && arrival <= step {
+                        *shift += amount(step - arrival);
+                        nudge(syn, shift, rule);
+                    }
+                }
+            }
+        }
+        for &i in pre_spiked {
+            self.last_pre[i] = Some(step);
+        }
+        for &j in post_spiked {
+            self.last_post[j] = Some(step);
+        }
+    }
+
+    /// Heap bytes held by spike times and shifts, in the same accounting as
+    /// `Estimate`.
+    pub(crate) fn bytes(&self) -> usize {
+        (self.last_pre.capacity() + self.last_post.capacity()) * size_of::<Option<usize>>()
+            + self
+                .shift
+                .iter()
+                .map(|s| size_of::<Vec<f64>>() + s.capacity() * size_of::<f64>())
+                .sum::<usize>()
+    }
+}
+
+/// Moves the delay by the whole steps in `shift`, keeping the fraction.
+fn nudge(syn: &mut Synapse, shift: &mut f64, rule: &DelayStdp) {
+    let whole = shift.trunc();
+    if whole != 0.0 {
+        *shift -= whole;
+        let delay = (syn.delay_steps as f64 + whole).clamp(rule.d_min as f64, rule.d_max as f64);
+        syn.delay_steps = delay as usize;
+    }
+}
+
+fn decay(values: &mut [f64], rate: f64) {
+    for x in values {
+        *x += (-*x) * rate;
+    }
+}
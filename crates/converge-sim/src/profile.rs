@@ -0,0 +1,136 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Per-phase wall-clock time for one run. Counting allocations would need a
+/// custom global allocator, which `forbid(unsafe_code)` rules out, so memory is
+/// reported as the bytes held by the built network instead.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub phases: Vec<PhaseProfile>,
+    /// Heap bytes held by layer state, synapse tables and delay queues.
+    pub network_bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhaseProfile {
+    pub name: String,
+    pub nanos: u64,
+}
+
+impl Profile {
+    pub fn push(&mut self, name: &str, elapsed: Duration) {
+        self.phases.push(PhaseProfile {
+            name: name.to_string(),
+            nanos: elapsed.as_nanos() as u64,
+        });
+    }
+
+    pub fn total_nanos(&self) -> u64 {
+        self.phases.iter().map(|p| p.nanos).sum()
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total_nanos().max(1);
+        writeln!(f, "profile:")?;
+        for phase in &self.phases {
+            writeln!(
+                f,
+                "  {:<10} {:>12.3} ms {:>6.1}%",
+                phase.name,
+                phase.nanos as f64 / 1e6,
+                phase.nanos as f64 * 100.0 / total as f64
+            )?;
+        }
+        writeln!(
+            f,
+            "  {:<10} {:>12.3} ms",
+            "total",
+            self.total_nanos() as f64 / 1e6
+        )?;
+        write!(f, "  network memory: {} bytes", self.network_bytes)
+    }
+}
+
+/// Accumulates wall-clock time into simulator phases by lapping a single
+/// clock. Disabled clocks never read the time.
+///
+/// `wasm32-unknown-unknown` has no clock (`Instant::now` panics there), so
+/// profiling is always off on that target.
+pub(crate) struct PhaseClock {
+    last: Option<Instant>,
+    totals: [Duration; SimPhase::COUNT],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SimPhase {
+    Build,
+    Delivery,
+    Stimulus,
+    Membrane,
+    Plasticity,
+}
+
+impl SimPhase {
+    const COUNT: usize = 5;
+    const ALL: [SimPhase; SimPhase::COUNT] = [
+        SimPhase::Build,
+        SimPhase::Delivery,
+        SimPhase::Stimulus,
+        SimPhase::Membrane,
+        SimPhase::Plasticity,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            SimPhase::Build => "build",
+            SimPhase::Delivery => "delivery",
+            SimPhase::Stimulus => "stimulus",
+            SimPhase::Membrane => "membrane",
+            SimPhase::Plasticity => "plasticity",
+        }
+    }
+}
+
+/// The current time, or `None` on targets without a clock.
+pub(crate) fn now() -> Option<Instant> {
+    (!cfg!(all(target_arch = "wasm32", target_os = "unknown"))).then(Instant::now)
+}
+
+impl PhaseClock {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            last: if enabled { now() } else { None },
+            totals: [Duration::ZERO; SimPhase::COUNT],
+        }
+    }
+
+    /// Restarts the lap without charging the time since the previous one.
+    pub(crate) fn mark(&mut self) {
+        if let Some(last) = &mut self.last {
+            *last = Instant::now();
+        }
+    }
+
+    /// Charges the time since the previous lap to `phase`.
+    pub(crate) fn lap(&mut self, phase: SimPhase) {
+        if let Some(last) = &mut self.last {
+            let now = Instant::now();
+            self.totals[phase as usize] += now - *last;
+            *last = now;
+        }
+    }
+
+    pub(crate) fn finish(&self, network_bytes: u64) -> Option<Profile> {
+        self.last?;
+        let mut profile = Profile {
+            phases: Vec::new(),
+            network_bytes,
+        };
+        for phase in SimPhase::ALL {
+            profile.push(phase.name(), self.totals[phase as usize]);
+        }
+        Some(profile)
+    }
+}
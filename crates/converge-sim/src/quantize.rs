@@ -0,0 +1,131 @@
+//! Quantized-weight runs. Each chemical connection gets a signed fixed-point
+//! grid: `bits`-bit codes scaled by a power of two chosen so its largest
+//! weight just fits. Weights are rounded to that grid when the network is
+//! built, and every delivery adds an integer number of network-wide LSBs,
+//! so input sums are exact and independent of delivery order.
+
+use std::fmt;
+
+use converge_lang::ast::ConnectKind;
+
+use crate::{Connection, SimError, SimErrorKind};
+
+/// Weight precision for `SimConfig::quantize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantize {
+    Int8,
+    Int16,
+}
+
+impl Quantize {
+    /// Parses `int8` or `int16`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "int8" => Some(Self::Int8),
+            "int16" => Some(Self::Int16),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn bits(self) -> u32 {
+        match self {
+            Self::Int8 => 8,
+            Self::Int16 => 16,
+        }
+    }
+}
+
+impl fmt::Display for Quantize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "int{}", self.bits())
+    }
+}
+
+/// One connection's grid: weight `w` is the code `round(w * 2^frac_bits)`,
+/// saturated to the code range, and delivers `code << shift` LSBs.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Grid {
+    frac_bits: i32,
+    shift: u32,
+    max_code: i64,
+}
+
+impl Grid {
+    fn code(&self, weight: f64) -> i64 {
+        let code = (weight * 2f64.powi(self.frac_bits)).round() as i64;
+        code.clamp(-self.max_code - 1, self.max_code)
+    }
+
+    /// The weight on this grid nearest to `weight`.
+    fn snap(&self, weight: f64) -> f64 {
+        self.code(weight) as f64 * 2f64.powi(-self.frac_bits)
+    }
+
+    /// Network LSBs delivered by a synapse of this weight. Weights that
+    /// learn during the run are rounded to the grid here.
+    pub(crate) fn units(&self, weight: f64) -> i64 {
+        self.code(weight) << self.shift
+    }
+}
+
+/// Gives every chemical connection a grid of `bits`-bit codes and snaps its
+/// weights to it. `max_frac_bits` caps every grid's precision, for
+/// accumulators with a fixed LSB. Returns the network's fractional bits, the
+/// finest of all grids.
+pub(crate) fn quantize_connections(
+    connections: &mut [Connection],
+    bits: u32,
+    max_frac_bits: Option<i32>,
+) -> Result<i32, SimError> {
+    let max_code = (1_i64 << (bits - 1)) - 1;
+    let cap = max_frac_bits.unwrap_or(i32::MAX);
+    let frac_bits: Vec<Option<i32>> = connections
+        .iter()
+        .map(|conn| {
+            let max_abs = conn
+                .synapses
+                .iter()
+                .flatten()
+                .fold(0.0_f64, |m, s| m.max(s.weight.abs()));
+            (conn.kind == ConnectKind::Chemical && max_abs > 0.0)
+                .then(|| fit_frac_bits(max_abs, max_code).min(cap))
+        })
+        .collect();
+    let network = frac_bits.iter().flatten().copied().max().unwrap_or(0);
+    for (conn, frac_bits) in connections.iter_mut().zip(frac_bits) {
+        if conn.kind != ConnectKind::Chemical {
+            continue;
+        }
+        let frac_bits = frac_bits.unwrap_or(network);
+        let shift = (network - frac_bits) as u32;
+        // Codes use `bits - 1` magnitude bits; shifted sums need headroom.
+        if shift + bits > 40 {
+            return Err(SimError::new(SimErrorKind::Unsupported(format!(
+                "weights span too many orders of magnitude for one int{bits} accumulator"
+            ))));
+        }
+        let grid = Grid {
+            frac_bits,
+            shift,
+            max_code,
+        };
+        for syn in conn.synapses.iter_mut().flatten() {
+            syn.weight = grid.snap(syn.weight);
+        }
+        conn.grid = Some(grid);
+    }
+    Ok(network)
+}
+
+/// The most fractional bits that keep `max_abs` within `max_code`.
+fn fit_frac_bits(max_abs: f64, max_code: i64) -> i32 {
+    let max_code = max_code as f64;
+    let mut frac_bits = 0;
+    while max_abs * 2f64.powi(frac_bits) > max_code {
+        frac_bits -= 1;
+    }
+    while max_abs * 2f64.powi(frac_bits + 1) <= max_code {
+        frac_bits += 1;
+    }
+    frac_bits
+}
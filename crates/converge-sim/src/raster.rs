@@ -0,0 +1,337 @@
+//! Compact binary spike recordings, written by `converge sim --spikes
+//! <path>.cvr` (through [`BinarySink`]) and read back with [`decode`].
+//! `converge sim --record-input` writes the Poisson input in the same format.
+//!
+//! The file starts with the magic `CVRS`, a version byte and the same header
+//! as the NDJSON recording: `duration_ns`, `step_ns`, `seed`, then each
+//! layer's name and size. Spikes follow as one frame per step that has any,
+//! in step order:
+//!
+//! - the step, as the difference from the previous frame's step (from 0 for
+//!   the first frame);
+//! - the number of layer blocks, one per layer that spiked, in layer order;
+//! - per block, the layer index as the difference from the previous block's
+//!   (absolute for the first), then `count << 1 | bitmap`. With `bitmap`
+//!   set, `ceil(size / 8)` bytes follow with bit `n % 8` of byte `n / 8` set
+//!   when neuron `n` spiked; otherwise `count` neuron indices follow, each
+//!   as the gap after the previous one (absolute for the first). The writer
+//!   picks whichever is shorter.
+//!
+//! Every integer is an unsigned LEB128 varint and names are UTF-8 preceded
+//! by their length. Decoding yields the spikes in emission order, the order
+//! the spike hash consumes them.
+
+use std::io::{self, Write};
+
+use converge_lang::diagnostic::Diagnostic;
+
+use crate::sink::{BinarySink, RecordSink, RunInfo};
+use crate::{SimSummary, Spike};
+
+pub const MAGIC: &[u8; 4] = b"CVRS";
+const VERSION: u8 = 1;
+
+/// A decoded recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub duration_ns: i64,
+    pub step_ns: i64,
+    pub seed: u64,
+    /// Name and size of every layer; `Spike::layer` indexes this.
+    pub layers: Vec<(String, u64)>,
+    pub spikes: Vec<Spike>,
+}
+
+/// Whether `bytes` start like a binary recording rather than NDJSON.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Writes the recorded raster in the binary format. The raster must be in
+/// emission order, as `SimSummary::raster` always is.
+pub fn write_binary(out: &mut impl Write, summary: &SimSummary) -> io::Result<()> {
+    write_spikes(out, summary, summary.raster.iter().flatten())
+}
+
+/// Writes the stochastic input recorded with `SimConfig::record_input` in
+/// the same format, for `SimConfig::replay_input`.
+pub fn write_input(out: &mut impl Write, summary: &SimSummary) -> io::Result<()> {
+    write_spikes(out, summary, summary.input.iter().flatten())
+}
+
+fn write_spikes<'a>(
+    out: &mut impl Write,
+    summary: &SimSummary,
+    spikes: impl Iterator<Item = &'a Spike>,
+) -> io::Result<()> {
+    let mut sink = BinarySink::new(out);
+    sink.on_start(&RunInfo::of(summary))?;
+    for spike in spikes {
+        sink.on_spike(spike)?;
+    }
+    sink.on_summary(summary)
+}
+
+pub(crate) fn write_header(buf: &mut Vec<u8>, run: &RunInfo) {
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+    put(buf, run.duration_ns as u64);
+    put(buf, run.step_ns as u64);
+    put(buf, run.seed);
+    put(buf, run.layers.len() as u64);
+    for (name, size) in &run.layers {
+        put(buf, name.len() as u64);
+        buf.extend_from_slice(name.as_bytes());
+        put(buf, *size);
+    }
+}
+
+/// Appends the frame of one step's spikes, which must be sorted by layer
+/// then neuron and lie within `layers`.
+pub(crate) fn write_frame(
+    buf: &mut Vec<u8>,
+    frame: &[Spike],
+    prev_step: u64,
+    layers: &[(String, u64)],
+) {
+    put(buf, frame[0].step - prev_step);
+    let blocks: Vec<&[Spike]> = frame.chunk_by(|a, b| a.layer == b.layer).collect();
+    put(buf, blocks.len() as u64);
+    let mut prev_layer = 0;
+    for block in blocks {
+        let layer = block[0].layer;
+        put(buf, u64::from(layer - prev_layer));
+        prev_layer = layer;
+        write_block(buf, block, layers[layer as usize].1);
+    }
+}
+
+fn write_block(buf: &mut Vec<u8>, block: &[Spike], size: u64) {
+    let count = block.len() as u64;
+    let mut gaps = Vec::new();
+    let mut next = 0;
+    for spike in block {
+        put(&mut gaps, spike.neuron - next);
+        next = spike.neuron + 1;
+    }
+    let bitmap_len = size.div_ceil(8) as usize;
+    if bitmap_len < gaps.len() {
+        put(buf, count << 1 | 1);
+        let mut bitmap = vec![0u8; bitmap_len];
+        for spike in block {
+            bitmap[(spike.neuron / 8) as usize] |= 1 << (spike.neuron % 8);
+        }
+        buf.extend_from_slice(&bitmap);
+    } else {
+        put(buf, count << 1);
+        buf.extend_from_slice(&gaps);
+    }
+}
+
+fn put(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Reads a recording written by [`write_binary`].
+pub fn decode(bytes: &[u8]) -> Result<Recording, Diagnostic> {
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        return Err(Diagnostic::new("spike file: not a binary recording"));
+    };
+    let mut r = Reader { bytes: rest };
+    let version = r.bytes(1)?[0];
+    if version != VERSION {
+        return Err(Diagnostic::new(format!(
+            "spike file: unsupported version {version}"
+        )));
+    }
+    let duration_ns = r.varint()? as i64;
+    let step_ns = r.varint()? as i64;
+    let seed = r.varint()?;
+    let layer_count = r.varint()?;
+    let mut layers = Vec::new();
+    for _ in 0..layer_count {
+        let len = r.len()?;
+        let name = std::str::from_utf8(r.bytes(len)?)
+            .map_err(|_| Diagnostic::new("spike file: layer name is not UTF-8"))?;
+        layers.push((name.to_string(), r.varint()?));
+    }
+
+    let mut spikes = Vec::new();
+    let mut step = 0u64;
+    while !r.bytes.is_empty() {
+        step = step
+            .checked_add(r.varint()?)
+            .ok_or_else(|| Diagnostic::new("spike file: step out of range"))?;
+        let mut layer = 0u64;
+        for _ in 0..r.varint()? {
+            layer = layer.saturating_add(r.varint()?);
+            let size = match layers.get(layer as usize) {
+                Some((_, size)) => *size,
+                None => {
+                    return Err(Diagnostic::new(format!(
+                        "spike file: unknown layer {layer}"
+                    )));
+                }
+            };
+            let header = r.varint()?;
+            let (count, bitmap) = (header >> 1, header & 1 == 1);
+            let mut push = |neuron: u64| -> Result<(), Diagnostic> {
+                if neuron >= size {
+                    return Err(Diagnostic::new(format!(
+                        "spike file: neuron {neuron} outside layer `{}`",
+                        layers[layer as usize].0
+                    )));
+                }
+                spikes.push(Spike {
+                    step,
+                    layer: layer as u32,
+                    neuron,
+                });
+                Ok(())
+            };
+            if bitmap {
+                let bytes = r.bytes(size.div_ceil(8) as usize)?;
+                let mut found = 0;
+                for (idx, &byte) in bytes.iter().enumerate() {
+                    for bit in (0..8).filter(|bit| byte & (1 << bit) != 0) {
+                        push(idx as u64 * 8 + bit)?;
+                        found += 1;
+                    }
+                }
+                if found != count {
+                    return Err(Diagnostic::new(
+                        "spike file: bitmap disagrees with its spike count",
+                    ));
+                }
+            } else {
+                let mut next = 0u64;
+                for _ in 0..count {
+                    let neuron = next.saturating_add(r.varint()?);
+                    push(neuron)?;
+                    next = neuron + 1;
+                }
+            }
+        }
+    }
+    Ok(Recording {
+        duration_ns,
+        step_ns,
+        seed,
+        layers,
+        spikes,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Diagnostic> {
+        if self.bytes.len() < n {
+            return Err(truncated());
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, Diagnostic> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.bytes(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Diagnostic::new("spike file: malformed integer"))
+    }
+
+    /// A length, bounded by what is left to read.
+    fn len(&mut self) -> Result<usize, Diagnostic> {
+        let len = self.varint()?;
+        if len > self.bytes.len() as u64 {
+            return Err(truncated());
+        }
+        Ok(len as usize)
+    }
+}
+
+fn truncated() -> Diagnostic {
+    Diagnostic::new("spike file: truncated")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimConfig, simulate_with, write_spikes_ndjson};
+    use converge_lang::parser::parse_program;
+
+    fn recorded(src: &str) -> SimSummary {
+        let program = parse_program(src).expect("parse");
+        let config = SimConfig {
+            record_spikes: true,
+            ..SimConfig::default()
+        };
+        simulate_with(&program, &config).expect("simulate")
+    }
+
+    #[test]
+    fn round_trips_sparse_and_dense_activity() {
+        let summary = recorded(
+            "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+             neuron Eager { v_th = 0.0 }\n\
+             layer Sparse[300] : LIF\n\
+             layer Dense[200] : Eager\n\
+             stimulus Sparse = Poisson(rate=200 Hz)\n\
+             seed 9\n\
+             run for 100 ms\n",
+        );
+        let mut binary = Vec::new();
+        write_binary(&mut binary, &summary).unwrap();
+        assert!(is_binary(&binary));
+        let recording = decode(&binary).unwrap();
+        assert_eq!(recording.spikes, *summary.raster.as_ref().unwrap());
+        assert_eq!(
+            (recording.duration_ns, recording.step_ns, recording.seed),
+            (summary.duration_ns, summary.step_ns, 9)
+        );
+        assert_eq!(recording.layers[1], ("Dense".to_string(), 200));
+
+        let mut ndjson = Vec::new();
+        write_spikes_ndjson(&mut ndjson, &summary).unwrap();
+        assert!(!is_binary(&ndjson));
+        assert!(
+            binary.len() * 10 < ndjson.len(),
+            "{} vs {}",
+            binary.len(),
+            ndjson.len()
+        );
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let summary = recorded(
+            "neuron LIF { v_th = 0.0 }\n\
+             layer A[3] : LIF\n\
+             run for 2 ms\n",
+        );
+        let mut binary = Vec::new();
+        write_binary(&mut binary, &summary).unwrap();
+        assert!(decode(&binary).is_ok());
+        let message = |bytes: &[u8]| decode(bytes).unwrap_err().message;
+        assert_eq!(
+            message(&binary[..binary.len() - 1]),
+            "spike file: truncated"
+        );
+        assert_eq!(message(b"{}"), "spike file: not a binary recording");
+        let mut newer = binary.clone();
+        newer[4] = 2;
+        assert_eq!(message(&newer), "spike file: unsupported version 2");
+    }
+}
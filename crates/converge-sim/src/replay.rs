@@ -0,0 +1,78 @@
+//! Replaying recorded stochastic input. A run with `SimConfig::record_input`
+//! keeps every Poisson stimulus spike; feeding that recording back through
+//! `SimConfig::replay_input` applies exactly those spikes instead of drawing
+//! new ones, so a modified network sees the same input realization.
+//!
+//! Recorded layers are matched to the network's by name and must keep their
+//! size. During a replay Poisson stimuli draw nothing: a layer the recording
+//! doesn't list gets no Poisson input.
+
+use crate::raster::Recording;
+use crate::{LayerState, SimError, SimErrorKind};
+
+#[derive(Debug, Clone)]
+pub(crate) struct InputReplay {
+    /// `(step, layer, neuron)` with `layer` indexing the network's layers,
+    /// sorted.
+    spikes: Vec<(usize, usize, usize)>,
+}
+
+impl InputReplay {
+    pub(crate) fn new(
+        recording: &Recording,
+        layers: &[LayerState],
+        step_ns: i64,
+        steps: usize,
+    ) -> Result<Self, SimError> {
+        if recording.step_ns != step_ns {
+            return Err(SimError::new(SimErrorKind::ReplayStepMismatch {
+                recorded_ns: recording.step_ns,
+                run_ns: step_ns,
+            }));
+        }
+        let recorded_steps = (recording.duration_ns / recording.step_ns.max(1)) as usize;
+        if recorded_steps < steps {
+            return Err(SimError::new(SimErrorKind::ReplayTooShort {
+                recorded_steps,
+                steps,
+            }));
+        }
+        let mut index = Vec::with_capacity(recording.layers.len());
+        for (name, size) in &recording.layers {
+            let Some(idx) = layers.iter().position(|l| &l.name == name) else {
+                return Err(SimError::new(SimErrorKind::UnknownLayer {
+                    role: "recorded",
+                    name: name.clone(),
+                }));
+            };
+            if layers[idx].size as u64 != *size {
+                return Err(SimError::new(SimErrorKind::LayerSizeMismatch {
+                    layer: name.clone(),
+                    expected: layers[idx].size,
+                    got: *size,
+                    what: "in the input recording",
+                }));
+            }
+            index.push(idx);
+        }
+        let mut spikes: Vec<(usize, usize, usize)> = recording
+            .spikes
+            .iter()
+            .map(|s| (s.step as usize, index[s.layer as usize], s.neuron as usize))
+            .collect();
+        spikes.sort_unstable();
+        Ok(Self { spikes })
+    }
+
+    /// Neurons of `layer` that received a recorded spike at `step`, in
+    /// index order.
+    pub(crate) fn spikes(&self, step: usize, layer: usize) -> impl Iterator<Item = usize> + '_ {
+        let start = self
+            .spikes
+            .partition_point(|&(s, l, _)| (s, l) < (step, layer));
+        self.spikes[start..]
+            .iter()
+            .take_while(move |&&(s, l, _)| (s, l) == (step, layer))
+            .map(|&(_, _, neuron)| neuron)
+    }
+}
@@ -0,0 +1,162 @@
+//! Synapse tables kept in memory-mapped scratch files instead of RAM, for
+//! networks too large to materialize (`SimConfig::scratch`).
+//!
+//! A connection is sampled one source neuron at a time, each row written
+//! straight to a file in the scratch directory, so no more than one row is
+//! held in memory while building. The file is then mapped read-only and the
+//! delivery loop reads synapses from the mapping, leaving the OS to page
+//! them in and out. Only the per-row offsets stay in RAM. Records are 16
+//! bytes: `dst: u32`, `delay_steps: u32`, `weight: f64`, little-endian. A
+//! target or delay too big for its field is refused rather than truncated.
+//!
+//! Files are removed when the simulator is dropped.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memmap2::Mmap;
+
+use crate::{SimError, SimErrorKind, Synapse};
+
+const RECORD: usize = 16;
+
+/// Distinguishes the files of connections, and of simulators in one process.
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) struct RowWriter {
+    out: BufWriter<File>,
+    /// The target layer, for errors.
+    layer: String,
+    /// Not yet mapped; owning it removes the file if building fails.
+    rows: MappedRows,
+}
+
+impl RowWriter {
+    pub(crate) fn create(dir: &Path, layer: &str) -> Result<Self, SimError> {
+        let fail = |e: std::io::Error| {
+            SimError::io(
+                format!("failed to create scratch file in `{}`", dir.display()),
+                e,
+            )
+        };
+        std::fs::create_dir_all(dir).map_err(fail)?;
+        let path = dir.join(format!(
+            "converge-{}-{}.syn",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Readable too, for the mapping made by `finish`.
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(fail)?;
+        Ok(Self {
+            out: BufWriter::new(file),
+            layer: layer.to_string(),
+            rows: MappedRows {
+                map: None,
+                path,
+                offsets: vec![0],
+                max_delay: 0,
+            },
+        })
+    }
+
+    /// Appends the synapses of the next source neuron. A target index or
+    /// delay that doesn't fit its `u32` field is an error, not truncated.
+    pub(crate) fn push_row(&mut self, row: &[Synapse]) -> Result<(), SimError> {
+        for syn in row {
+            let too_big = |what: String| {
+                SimError::new(SimErrorKind::Unsupported(format!(
+                    "scratch storage can't hold {what} of a synapse into `{}`; \
+                     its records keep at most {}",
+                    self.layer,
+                    u32::MAX
+                )))
+            };
+            let dst = u32::try_from(syn.dst)
+                .map_err(|_| too_big(format!("target neuron {}", syn.dst)))?;
+            let delay = u32::try_from(syn.delay_steps)
+                .map_err(|_| too_big(format!("the delay of {} steps", syn.delay_steps)))?;
+            let mut record = [0u8; RECORD];
+            record[..4].copy_from_slice(&dst.to_le_bytes());
+            record[4..8].copy_from_slice(&delay.to_le_bytes());
+            record[8..].copy_from_slice(&syn.weight.to_le_bytes());
+            self.out
+                .write_all(&record)
+                .map_err(|e| self.rows.write_error(e))?;
+            self.rows.max_delay = self.rows.max_delay.max(syn.delay_steps);
+        }
+        let end = self.rows.len() as u64 + row.len() as u64;
+        self.rows.offsets.push(end);
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Result<MappedRows, SimError> {
+        let Self { out, mut rows, .. } = self;
+        let file = out
+            .into_inner()
+            .map_err(|e| rows.write_error(e.into_error()))?;
+        if rows.len() > 0 {
+            // SAFETY: the file is private to this process, named uniquely and
+            // never written again once mapped.
+            let map = unsafe { Mmap::map(&file) }
+                .map_err(|e| SimError::io(format!("failed to map `{}`", rows.path.display()), e))?;
+            rows.map = Some(map);
+        }
+        Ok(rows)
+    }
+}
+
+/// The synapse rows of one connection, in a mapped scratch file.
+pub(crate) struct MappedRows {
+    map: Option<Mmap>,
+    path: PathBuf,
+    /// Record index where each source neuron's row starts, then the end.
+    offsets: Vec<u64>,
+    max_delay: usize,
+}
+
+impl MappedRows {
+    /// Total synapses.
+    pub(crate) fn len(&self) -> usize {
+        self.offsets.last().copied().unwrap_or(0) as usize
+    }
+
+    pub(crate) fn max_delay(&self) -> usize {
+        self.max_delay
+    }
+
+    /// Synapses of source neuron `i`, in the order they were sampled.
+    pub(crate) fn row(&self, i: usize) -> impl Iterator<Item = Synapse> + '_ {
+        let (start, end) = (self.offsets[i] as usize, self.offsets[i + 1] as usize);
+        let bytes = match &self.map {
+            Some(map) => &map[start * RECORD..end * RECORD],
+            None => &[],
+        };
+        bytes.chunks_exact(RECORD).map(|record| {
+            let word = |at: usize| u32::from_le_bytes(record[at..at + 4].try_into().unwrap());
+            Synapse {
+                dst: word(0) as usize,
+                delay_steps: word(4) as usize,
+                weight: f64::from_le_bytes(record[8..].try_into().unwrap()),
+            }
+        })
+    }
+
+    fn write_error(&self, e: std::io::Error) -> SimError {
+        SimError::io(format!("failed to write `{}`", self.path.display()), e)
+    }
+}
+
+impl Drop for MappedRows {
+    fn drop(&mut self) {
+        // Unmap first: some platforms refuse to delete a mapped file.
+        self.map = None;
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
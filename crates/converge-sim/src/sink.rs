@@ -0,0 +1,536 @@
+//! Streaming run output. A [`RecordSink`] attached with
+//! [`Simulator::add_sink`](crate::Simulator::add_sink) sees every spike,
+//! every sample of the neurons traced by `SimConfig::trace_neurons`, every
+//! rate from a `record rate` item and every sample of a `probe` as the step
+//! producing it ends, then the summary from
+//! [`Simulator::finish`](crate::Simulator::finish). Nothing is buffered for
+//! the sink, so it can feed a database or a message queue as the run goes.
+//!
+//! [`CsvSink`], [`NdjsonSink`] and [`BinarySink`] write to any `Write`.
+
+use std::io::{self, Write};
+
+use crate::raster::{write_frame, write_header};
+use crate::{SimSummary, Spike};
+
+/// What a run records, known before its first step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunInfo {
+    pub duration_ns: i64,
+    pub step_ns: i64,
+    pub seed: u64,
+    /// Name and size of every layer; `Spike::layer` indexes this.
+    pub layers: Vec<(String, u64)>,
+    /// Name of every `probe`; `ProbeSample::probe` indexes this.
+    pub probes: Vec<String>,
+}
+
+impl RunInfo {
+    pub fn of(summary: &SimSummary) -> Self {
+        Self {
+            duration_ns: summary.duration_ns,
+            step_ns: summary.step_ns,
+            seed: summary.seed,
+            layers: summary
+                .layers
+                .iter()
+                .map(|l| (l.name.clone(), l.size))
+                .collect(),
+            probes: summary.probes.iter().map(|p| p.name.clone()).collect(),
+        }
+    }
+}
+
+/// A traced neuron's membrane potential at the end of a step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub step: u64,
+    pub layer: u32,
+    pub neuron: u64,
+    pub v: f64,
+}
+
+/// A `record rate` trace's smoothed rate at the end of a step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateSample {
+    pub step: u64,
+    pub layer: u32,
+    pub hz: f64,
+}
+
+/// One neuron's membrane potential, on a step its `probe` samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeSample {
+    pub step: u64,
+    pub probe: u32,
+    pub layer: u32,
+    pub neuron: u64,
+    pub v: f64,
+}
+
+/// Receives run output as it is produced. Within a step, spikes come in
+/// emission order, then the samples, then the rates, then the probe
+/// samples, then `on_step_end`.
+pub trait RecordSink {
+    /// Called once, when the sink is attached.
+    fn on_start(&mut self, _run: &RunInfo) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_spike(&mut self, spike: &Spike) -> io::Result<()>;
+
+    fn on_sample(&mut self, _sample: &Sample) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_rate(&mut self, _rate: &RateSample) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_probe(&mut self, _sample: &ProbeSample) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called after everything recorded on `step`, even when there was
+    /// nothing.
+    fn on_step_end(&mut self, _step: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the run is over; the built-in sinks flush here.
+    fn on_summary(&mut self, _summary: &SimSummary) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The layout of `converge sim --spikes`: a header line with `duration_ns`,
+/// `step_ns`, `seed` and `layers` (`name`, `size`), then one line per spike
+/// with `step`, `t_ns`, `layer` (by name) and `neuron`. Samples are lines
+/// with the same fields plus `v`; rates have `rate_hz` instead of `neuron`,
+/// and probe samples add the `probe` name to a sample's fields.
+pub struct NdjsonSink<W: Write> {
+    out: W,
+    run: Option<RunInfo>,
+}
+
+impl<W: Write> NdjsonSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, run: None }
+    }
+}
+
+impl<W: Write> RecordSink for NdjsonSink<W> {
+    fn on_start(&mut self, run: &RunInfo) -> io::Result<()> {
+        let layers: Vec<String> = run
+            .layers
+            .iter()
+            .map(|(name, size)| format!("{{\"name\":\"{name}\",\"size\":{size}}}"))
+            .collect();
+        writeln!(
+            self.out,
+            "{{\"duration_ns\":{},\"step_ns\":{},\"seed\":{},\"layers\":[{}]}}",
+            run.duration_ns,
+            run.step_ns,
+            run.seed,
+            layers.join(",")
+        )?;
+        self.run = Some(run.clone());
+        Ok(())
+    }
+
+    fn on_spike(&mut self, spike: &Spike) -> io::Result<()> {
+        let run = started(&self.run)?;
+        writeln!(
+            self.out,
+            "{{\"step\":{},\"t_ns\":{},\"layer\":\"{}\",\"neuron\":{}}}",
+            spike.step,
+            spike.step as i64 * run.step_ns,
+            layer_name(run, spike.layer)?,
+            spike.neuron
+        )
+    }
+
+    fn on_sample(&mut self, sample: &Sample) -> io::Result<()> {
+        let run = started(&self.run)?;
+        writeln!(
+            self.out,
+            "{{\"step\":{},\"t_ns\":{},\"layer\":\"{}\",\"neuron\":{},\"v\":{}}}",
+            sample.step,
+            sample.step as i64 * run.step_ns,
+            layer_name(run, sample.layer)?,
+            sample.neuron,
+            sample.v
+        )
+    }
+
+    fn on_rate(&mut self, rate: &RateSample) -> io::Result<()> {
+        let run = started(&self.run)?;
+        writeln!(
+            self.out,
+            "{{\"step\":{},\"t_ns\":{},\"layer\":\"{}\",\"rate_hz\":{}}}",
+            rate.step,
+            rate.step as i64 * run.step_ns,
+            layer_name(run, rate.layer)?,
+            rate.hz
+        )
+    }
+
+    fn on_probe(&mut self, sample: &ProbeSample) -> io::Result<()> {
+        let run = started(&self.run)?;
+        writeln!(
+            self.out,
+            "{{\"step\":{},\"t_ns\":{},\"probe\":\"{}\",\"layer\":\"{}\",\"neuron\":{},\"v\":{}}}",
+            sample.step,
+            sample.step as i64 * run.step_ns,
+            probe_name(run, sample.probe)?,
+            layer_name(run, sample.layer)?,
+            sample.neuron,
+            sample.v
+        )
+    }
+
+    fn on_summary(&mut self, _summary: &SimSummary) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// One row per event under the header `event,step,t_ns,layer,neuron,v`,
+/// with `event` `spike`, `sample`, `rate` or `probe:<name>`. `v` is empty
+/// for spikes, and rates leave `neuron` empty and put the rate in hertz in
+/// `v`.
+pub struct CsvSink<W: Write> {
+    out: W,
+    run: Option<RunInfo>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, run: None }
+    }
+}
+
+impl<W: Write> RecordSink for CsvSink<W> {
+    fn on_start(&mut self, run: &RunInfo) -> io::Result<()> {
+        writeln!(self.out, "event,step,t_ns,layer,neuron,v")?;
+        self.run = Some(run.clone());
+        Ok(())
+    }
+
+    fn on_spike(&mut self, spike: &Spike) -> io::Result<()> {
+        let run = started(&self.run)?;
+        writeln!(
+            self.out,
+            "spike,{},{},{},{},",
+            spike.step,
+            spike.step as i64 * run.step_ns,
+            layer_name(run, spike.layer)?,
+            spike.neuron
+        )
+    }
+
+    fn on_sample(&mut self, sample: &Sample) -> io::Result<()> {
+        let run = started(&self.run)?;
+        writeln!(
+            self.out,
+            "sample,{},{},{},{},{}",
+            sample.step,
+            sample.step as i64 * run.step_ns,
+            layer_name(run, sample.layer)?,
+            sample.neuron,
+            sample.v
+        )
+    }
+
+    fn on_rate(&mut self, rate: &RateSample) -> io::Result<()> {
+        let run = started(&self.run)?;
+        writeln!(
+            self.out,
+            "rate,{},{},{},,{}",
+            rate.step,
+            rate.step as i64 * run.step_ns,
+            layer_name(run, rate.layer)?,
+            rate.hz
+        )
+    }
+
+    fn on_probe(&mut self, sample: &ProbeSample) -> io::Result<()> {
+        let run = started(&self.run)?;
+        writeln!(
+            self.out,
+            "probe:{},{},{},{},{},{}",
+            probe_name(run, sample.probe)?,
+            sample.step,
+            sample.step as i64 * run.step_ns,
+            layer_name(run, sample.layer)?,
+            sample.neuron,
+            sample.v
+        )
+    }
+
+    fn on_summary(&mut self, _summary: &SimSummary) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The compressed format of [`crate::raster`]. A step's frame is written
+/// when the step ends. Samples, rates and probe samples
+/// are not recorded.
+pub struct BinarySink<W: Write> {
+    out: W,
+    run: Option<RunInfo>,
+    frame: Vec<Spike>,
+    prev_step: u64,
+}
+
+impl<W: Write> BinarySink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            run: None,
+            frame: Vec::new(),
+            prev_step: 0,
+        }
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        let Some(first) = self.frame.first() else {
+            return Ok(());
+        };
+        let step = first.step;
+        let mut buf = Vec::new();
+        write_frame(
+            &mut buf,
+            &self.frame,
+            self.prev_step,
+            &started(&self.run)?.layers,
+        );
+        self.out.write_all(&buf)?;
+        self.prev_step = step;
+        self.frame.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordSink for BinarySink<W> {
+    fn on_start(&mut self, run: &RunInfo) -> io::Result<()> {
+        let mut buf = Vec::new();
+        write_header(&mut buf, run);
+        self.out.write_all(&buf)?;
+        self.run = Some(run.clone());
+        Ok(())
+    }
+
+    fn on_spike(&mut self, spike: &Spike) -> io::Result<()> {
+        let run = started(&self.run)?;
+        let key = |s: &Spike| (s.step, s.layer, s.neuron);
+        let last = self.frame.last().map(key);
+        if last.is_some_and(|last| key(spike) <= last) || spike.step < self.prev_step {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "spikes are not in emission order",
+            ));
+        }
+        if run
+            .layers
+            .get(spike.layer as usize)
+            .is_none_or(|(_, size)| spike.neuron >= *size)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "spike of unknown neuron {} in layer {}",
+                    spike.neuron, spike.layer
+                ),
+            ));
+        }
+        if last.is_some_and(|(step, _, _)| step != spike.step) {
+            self.write_pending()?;
+        }
+        self.frame.push(*spike);
+        Ok(())
+    }
+
+    fn on_step_end(&mut self, _step: u64) -> io::Result<()> {
+        self.write_pending()
+    }
+
+    fn on_summary(&mut self, _summary: &SimSummary) -> io::Result<()> {
+        self.write_pending()?;
+        self.out.flush()
+    }
+}
+
+fn started(run: &Option<RunInfo>) -> io::Result<&RunInfo> {
+    run.as_ref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "sink used before `on_start`"))
+}
+
+fn probe_name(run: &RunInfo, probe: u32) -> io::Result<&str> {
+    run.probes
+        .get(probe as usize)
+        .map(String::as_str)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown probe {probe}"),
+            )
+        })
+}
+
+fn layer_name(run: &RunInfo, layer: u32) -> io::Result<&str> {
+    run.layers
+        .get(layer as usize)
+        .map(|(name, _)| name.as_str())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown layer {layer}"),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{SimConfig, Simulator, raster, write_spikes_ndjson};
+    use converge_lang::parser::parse_program;
+
+    const SRC: &str = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\n\
+                       layer In[6] : LIF\n\
+                       layer Out[3] : LIF\n\
+                       connect In -> Out { w = 0.6, d = 1 ms }\n\
+                       stimulus In = Poisson(rate=400 Hz)\n\
+                       seed 4\n\
+                       run for 30 ms\n";
+
+    /// A `Write` the test can read back after the simulator owns the sink.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn take(&self) -> Vec<u8> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn streams_what_the_summary_records() {
+        let program = parse_program(SRC).expect("parse");
+        let config = SimConfig {
+            record_spikes: true,
+            ..SimConfig::default()
+        };
+        let (ndjson, binary) = (Shared::default(), Shared::default());
+        let mut sim = Simulator::new(&program, &config).unwrap();
+        sim.add_sink(Box::new(NdjsonSink::new(ndjson.clone())))
+            .unwrap();
+        sim.add_sink(Box::new(BinarySink::new(binary.clone())))
+            .unwrap();
+        sim.run().unwrap();
+        let summary = sim.finish().unwrap();
+        assert!(summary.total_spikes > 0);
+
+        let mut expected = Vec::new();
+        write_spikes_ndjson(&mut expected, &summary).unwrap();
+        assert_eq!(ndjson.take(), expected);
+        let recording = raster::decode(&binary.take()).unwrap();
+        assert_eq!(recording.spikes, *summary.raster.as_ref().unwrap());
+    }
+
+    #[test]
+    fn csv_rows_carry_spikes_and_traced_samples() {
+        let program = parse_program(SRC).expect("parse");
+        let config = SimConfig {
+            trace_neurons: 1,
+            ..SimConfig::default()
+        };
+        let csv = Shared::default();
+        let mut sim = Simulator::new(&program, &config).unwrap();
+        sim.add_sink(Box::new(CsvSink::new(csv.clone()))).unwrap();
+        sim.run().unwrap();
+        let summary = sim.finish().unwrap();
+
+        let text = String::from_utf8(csv.take()).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("event,step,t_ns,layer,neuron,v"));
+        let rows: Vec<&str> = lines.collect();
+        let spikes = rows.iter().filter(|r| r.starts_with("spike,")).count();
+        assert_eq!(spikes as u64, summary.total_spikes);
+        // One sample per traced neuron, first of each layer, every step.
+        let samples = rows.iter().filter(|r| r.starts_with("sample,")).count();
+        assert_eq!(samples, 2 * 30);
+        assert!(rows.contains(&"sample,0,0,Out,0,0"));
+    }
+
+    #[test]
+    fn streams_probe_samples() {
+        let src = format!("{SRC}probe p = v(Out[0..2]) every 10 ms\n");
+        let program = parse_program(&src).expect("parse");
+        let (ndjson, csv) = (Shared::default(), Shared::default());
+        let mut sim = Simulator::new(&program, &SimConfig::default()).unwrap();
+        sim.add_sink(Box::new(NdjsonSink::new(ndjson.clone())))
+            .unwrap();
+        sim.add_sink(Box::new(CsvSink::new(csv.clone()))).unwrap();
+        sim.run().unwrap();
+        let summary = sim.finish().unwrap();
+        let samples = &summary.probes[0].samples;
+        assert_eq!(samples[1].len(), 3);
+
+        let text = String::from_utf8(ndjson.take()).unwrap();
+        let rows: Vec<&str> = text.lines().filter(|l| l.contains("\"probe\"")).collect();
+        assert_eq!(rows.len(), 2 * 3);
+        assert_eq!(
+            rows[5],
+            format!(
+                "{{\"step\":20,\"t_ns\":20000000,\"probe\":\"p\",\"layer\":\"Out\",\"neuron\":1,\"v\":{}}}",
+                samples[1][2]
+            )
+        );
+        let text = String::from_utf8(csv.take()).unwrap();
+        let rows: Vec<&str> = text.lines().filter(|l| l.starts_with("probe:p,")).collect();
+        assert_eq!(rows.len(), 2 * 3);
+        assert_eq!(rows[1], format!("probe:p,0,0,Out,1,{}", samples[1][0]));
+    }
+
+    #[test]
+    fn streams_smoothed_rates() {
+        let src = format!("{SRC}record rate from In window 5 ms\n");
+        let program = parse_program(&src).expect("parse");
+        let (ndjson, csv) = (Shared::default(), Shared::default());
+        let mut sim = Simulator::new(&program, &SimConfig::default()).unwrap();
+        sim.add_sink(Box::new(NdjsonSink::new(ndjson.clone())))
+            .unwrap();
+        sim.add_sink(Box::new(CsvSink::new(csv.clone()))).unwrap();
+        sim.run().unwrap();
+        let summary = sim.finish().unwrap();
+        let rates = &summary.rates[0].samples;
+        assert_eq!(rates.len(), 30);
+
+        let text = String::from_utf8(ndjson.take()).unwrap();
+        let rows: Vec<&str> = text.lines().filter(|l| l.contains("rate_hz")).collect();
+        assert_eq!(rows.len(), 30);
+        assert_eq!(
+            rows[29],
+            format!(
+                "{{\"step\":29,\"t_ns\":29000000,\"layer\":\"In\",\"rate_hz\":{}}}",
+                rates[29]
+            )
+        );
+        let text = String::from_utf8(csv.take()).unwrap();
+        let rows: Vec<&str> = text.lines().filter(|l| l.starts_with("rate,")).collect();
+        assert_eq!(rows.len(), 30);
+        assert_eq!(rows[0], format!("rate,0,0,In,,{}", rates[0]));
+    }
+}
@@ -0,0 +1,81 @@
+//! Named random streams.
+//!
+//! Each subsystem draws from its own generator, seeded from the run seed and
+//! the stream's name, so drawing more numbers in one never shifts the
+//! numbers another sees: adding a stimulus leaves every sampled weight
+//! unchanged, and adding a connection leaves the stimulus spikes unchanged.
+
+use crate::format_spike_hash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stream {
+    /// Weights, delays and gap-junction conductances, sampled when the
+    /// network is built.
+    Connectivity,
+    /// Poisson stimulus spikes.
+    Stimulus,
+    /// Membrane noise. Reserved: no model draws from it yet.
+    Noise,
+    /// Stochastic plasticity rules. Reserved: no rule draws from it yet.
+    Plasticity,
+    /// The order an `experiment` with `shuffle = true` presents samples in.
+    Shuffle,
+}
+
+impl Stream {
+    pub const ALL: [Stream; 5] = [
+        Stream::Connectivity,
+        Stream::Stimulus,
+        Stream::Noise,
+        Stream::Plasticity,
+        Stream::Shuffle,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Connectivity => "connectivity",
+            Stream::Stimulus => "stimulus",
+            Stream::Noise => "noise",
+            Stream::Plasticity => "plasticity",
+            Stream::Shuffle => "shuffle",
+        }
+    }
+
+    /// The stream's seed for a run seeded with `seed`: the FNV-1a hash of
+    /// the name mixed into the run seed by the SplitMix64 finalizer.
+    pub fn seed(self, seed: u64) -> u64 {
+        let name = self.name().bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+        let mut z = (seed ^ name).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// `{ "connectivity": "<hex>", ... }`: every stream's seed, as summaries
+/// list them.
+pub fn streams_json(seed: u64) -> String {
+    let streams: Vec<String> = Stream::ALL
+        .iter()
+        .map(|s| format!("\"{}\": \"{}\"", s.name(), format_spike_hash(s.seed(seed))))
+        .collect();
+    format!("{{ {} }}", streams.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_are_distinct_and_follow_the_seed() {
+        let seeds: Vec<u64> = Stream::ALL.iter().map(|s| s.seed(42)).collect();
+        for (i, a) in seeds.iter().enumerate() {
+            assert!(!seeds[i + 1..].contains(a), "{seeds:x?}");
+        }
+        assert_ne!(Stream::Stimulus.seed(42), Stream::Stimulus.seed(43));
+        assert_eq!(Stream::Stimulus.seed(42), Stream::Stimulus.seed(42));
+        assert!(streams_json(42).starts_with("{ \"connectivity\": \""));
+    }
+}
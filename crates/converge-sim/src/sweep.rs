@@ -0,0 +1,215 @@
+//! `experiment { run <network> with seed 1..10; report ... }`: runs a model
+//! once per seed and aggregates the reported metrics into one report.
+//!
+//! Every run starts from the selected model with its `seed` replaced, so a
+//! sweep reproduces exactly: `converge sim --network <network>` with the
+//! same `seed` gives the run's spike hash.
+
+use converge_lang::ast::{Item, Metric, Program, ReportMetric, SeedStmt, SweepDef, SweepRun};
+
+use crate::{
+    SimConfig, SimError, SimErrorKind, SimSummary, format_spike_hash, rate_hz, simulate_with,
+    to_err,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepReport {
+    /// The reported metrics, as written: `total_spikes`, `rate(Out)`, ...
+    pub metrics: Vec<String>,
+    /// One group per `run` line, in source order.
+    pub groups: Vec<SweepGroup>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepGroup {
+    pub network: Option<String>,
+    pub first_seed: u64,
+    pub last_seed: u64,
+    pub runs: Vec<SweepResult>,
+    /// Aggregates of every metric over the group's runs, in metric order.
+    pub stats: Vec<MetricStats>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResult {
+    pub seed: u64,
+    pub spike_hash: u64,
+    /// One value per metric, in metric order.
+    pub values: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricStats {
+    pub mean: f64,
+    /// Sample standard deviation; zero for a single run.
+    pub std: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl MetricStats {
+    fn of(values: &[f64]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let var = if values.len() > 1 {
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        } else {
+            0.0
+        };
+        Self {
+            mean,
+            std: var.sqrt(),
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// The program's `experiment` with runs, if it has one.
+pub fn find_sweep(program: &Program) -> Option<&SweepDef> {
+    program.items.iter().find_map(|item| match item {
+        Item::Sweep(def) => Some(def),
+        _ => None,
+    })
+}
+
+/// Runs every seed of every `run` line of the program's sweep in order,
+/// calling `on_done` after each run.
+pub fn run_sweep(
+    program: &Program,
+    config: &SimConfig,
+    mut on_done: impl FnMut(&SweepRun, &SweepResult),
+) -> Result<SweepReport, SimError> {
+    let def = find_sweep(program).ok_or_else(|| SimError::new(SimErrorKind::SweepWithoutRuns))?;
+    let mut groups = Vec::new();
+    for run in &def.runs {
+        let network = run.network.as_ref().map(|n| n.name.as_str());
+        let model = program.select_network(network).map_err(to_err)?;
+        let mut runs = Vec::new();
+        for seed in run.seeds() {
+            let mut seeded = model.clone().into_owned();
+            seeded.items.retain(|item| !matches!(item, Item::Seed(_)));
+            seeded.items.push(Item::Seed(SeedStmt {
+                value: seed,
+                span: run.span.clone(),
+            }));
+            let summary = simulate_with(&seeded, config).map_err(|e| {
+                SimError::new(SimErrorKind::InRun {
+                    run: run.to_string(),
+                    seed,
+                    error: Box::new(e),
+                })
+            })?;
+            let values = def
+                .report
+                .iter()
+                .map(|metric| metric_value(metric, &summary))
+                .collect::<Result<Vec<f64>, SimError>>()?;
+            let result = SweepResult {
+                seed,
+                spike_hash: summary.spike_hash,
+                values,
+            };
+            on_done(run, &result);
+            runs.push(result);
+        }
+        let stats = (0..def.report.len())
+            .map(|i| MetricStats::of(&runs.iter().map(|r| r.values[i]).collect::<Vec<_>>()))
+            .collect();
+        groups.push(SweepGroup {
+            network: network.map(str::to_string),
+            first_seed: run.first_seed,
+            last_seed: run.last_seed,
+            runs,
+            stats,
+        });
+    }
+    Ok(SweepReport {
+        metrics: def.report.iter().map(|m| m.to_string()).collect(),
+        groups,
+    })
+}
+
+fn metric_value(metric: &ReportMetric, summary: &SimSummary) -> Result<f64, SimError> {
+    let neurons: u64 = summary.layers.iter().map(|l| l.size).sum();
+    Ok(match metric {
+        ReportMetric::TotalSpikes => summary.total_spikes as f64,
+        ReportMetric::MeanRate => rate_hz(summary.total_spikes, neurons, summary.duration_ns),
+        ReportMetric::Layer(metric) => {
+            let name = &metric.layer().name;
+            let layer = summary
+                .layers
+                .iter()
+                .find(|l| &l.name == name)
+                .ok_or_else(|| {
+                    SimError::new(SimErrorKind::UnknownLayer {
+                        role: "report",
+                        name: name.clone(),
+                    })
+                })?;
+            match metric {
+                Metric::Spikes { .. } => layer.spikes as f64,
+                Metric::Rate { .. } => rate_hz(layer.spikes, layer.size, summary.duration_ns),
+            }
+        }
+    })
+}
+
+pub fn sweep_json(report: &SweepReport) -> String {
+    let mut s = String::new();
+    s.push_str("{\n");
+    let metrics: Vec<String> = report.metrics.iter().map(|m| format!("\"{m}\"")).collect();
+    s.push_str(&format!("  \"metrics\": [{}],\n", metrics.join(", ")));
+    s.push_str("  \"groups\": [\n");
+    for (idx, group) in report.groups.iter().enumerate() {
+        s.push_str("    {\n");
+        match &group.network {
+            Some(name) => s.push_str(&format!("      \"network\": \"{name}\",\n")),
+            None => s.push_str("      \"network\": null,\n"),
+        }
+        s.push_str(&format!(
+            "      \"seeds\": [{}, {}],\n",
+            group.first_seed, group.last_seed
+        ));
+        s.push_str("      \"runs\": [\n");
+        for (idx, run) in group.runs.iter().enumerate() {
+            let values: Vec<String> = report
+                .metrics
+                .iter()
+                .zip(&run.values)
+                .map(|(metric, value)| format!(", \"{metric}\": {value}"))
+                .collect();
+            s.push_str(&format!(
+                "        {{ \"seed\": {}, \"spike_hash\": \"{}\"{} }}",
+                run.seed,
+                format_spike_hash(run.spike_hash),
+                values.concat()
+            ));
+            if idx + 1 != group.runs.len() {
+                s.push(',');
+            }
+            s.push('\n');
+        }
+        s.push_str("      ],\n");
+        s.push_str("      \"stats\": {\n");
+        for (idx, (metric, stats)) in report.metrics.iter().zip(&group.stats).enumerate() {
+            s.push_str(&format!(
+                "        \"{metric}\": {{ \"mean\": {}, \"std\": {}, \"min\": {}, \"max\": {} }}",
+                stats.mean, stats.std, stats.min, stats.max
+            ));
+            if idx + 1 != report.metrics.len() {
+                s.push(',');
+            }
+            s.push('\n');
+        }
+        s.push_str("      }\n");
+        s.push_str("    }");
+        if idx + 1 != report.groups.len() {
+            s.push(',');
+        }
+        s.push('\n');
+    }
+    s.push_str("  ]\n");
+    s.push_str("}\n");
+    s
+}
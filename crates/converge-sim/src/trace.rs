@@ -0,0 +1,32 @@
+//! Logging shims that compile to nothing unless the `tracing` feature is on,
+//! so the default build stays dependency-free.
+
+pub(crate) struct Phase {
+    #[cfg(feature = "tracing")]
+    pub(crate) _span: tracing::span::EnteredSpan,
+}
+
+macro_rules! phase {
+    ($name:literal) => {
+        $crate::trace::Phase {
+            #[cfg(feature = "tracing")]
+            _span: tracing::info_span!($name).entered(),
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+pub(crate) use {debug, phase, trace};
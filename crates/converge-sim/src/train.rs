@@ -0,0 +1,226 @@
+//! Supervised training of the connections into a `readout` layer, and the
+//! weight files that `w = from_file("...")` loads back.
+//!
+//! After every presentation the readout's prediction is compared with the
+//! row's label and each trained weight moves by the delta rule
+//! `w += rate * (target - output) * x`, where `target` and `output` are
+//! one-hot over the readout neurons and `x` is the presynaptic spike count
+//! during the presentation and the gap after it. A silent readout has no
+//! output, so the labelled neuron is still pushed toward firing.
+
+use std::io::{self, Write};
+
+use converge_lang::ast::{Assign, CallArg, ConnectKind, Expr};
+
+use crate::encode::{DataFiles, Schedule, read_data};
+use crate::experiment::Classifier;
+use crate::{Connection, SimError, SimErrorKind};
+
+#[derive(Clone)]
+pub(crate) struct Trainer {
+    rate: f64,
+    layer: usize,
+    schedule: Schedule,
+    labels: Vec<usize>,
+    /// Chemical connections into the readout layer without a `plasticity`
+    /// rule of their own.
+    connections: Vec<usize>,
+    /// Spike counts of every layer during the current slot; empty for
+    /// layers that no trained connection reads.
+    counts: Vec<Vec<u64>>,
+}
+
+impl Trainer {
+    pub(crate) fn build(
+        rate: f64,
+        classifier: Option<&Classifier>,
+        connections: &[Connection],
+        layer_sizes: &[usize],
+    ) -> Result<Self, SimError> {
+        let Some(classifier) = classifier else {
+            return Err(SimError::new(SimErrorKind::TrainingWithoutLabels));
+        };
+        let layer = classifier.tally.layer;
+        let trained: Vec<usize> = connections
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                c.kind == ConnectKind::Chemical && c.dst_layer == layer && c.plasticity.is_none()
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        if trained.is_empty() && connections.iter().all(|c| c.plasticity.is_none()) {
+            return Err(SimError::new(SimErrorKind::NothingToTrain));
+        }
+        let mut counts = vec![Vec::new(); layer_sizes.len()];
+        counts[layer] = vec![0; layer_sizes[layer]];
+        for &idx in &trained {
+            let src = connections[idx].src_layer;
+            counts[src] = vec![0; layer_sizes[src]];
+        }
+        Ok(Self {
+            rate,
+            layer,
+            schedule: classifier.schedule().clone(),
+            labels: classifier.labels().to_vec(),
+            connections: trained,
+            counts,
+        })
+    }
+
+    /// Counts this step's spikes and, on the last step of a slot, applies
+    /// the update for its presentation.
+    pub(crate) fn step(
+        &mut self,
+        step: usize,
+        spiked: &[Vec<usize>],
+        connections: &mut [Connection],
+    ) {
+        let Some(slot) = self.schedule.slot(step) else {
+            return;
+        };
+        for (counts, spiked) in self.counts.iter_mut().zip(spiked) {
+            if !counts.is_empty() {
+                for &i in spiked {
+                    counts[i] += 1;
+                }
+            }
+        }
+        if self.schedule.finished(step + 1) <= slot {
+            return;
+        }
+
+        let output = &self.counts[self.layer];
+        let predicted = output
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|&(_, count)| count)
+            .filter(|&(_, &count)| count > 0)
+            .map(|(idx, _)| idx);
+        let label = self.labels[self.schedule.sample(slot)];
+        let one_hot = |hit: bool| if hit { 1.0 } else { 0.0 };
+        let error = |j: usize| one_hot(j == label) - one_hot(Some(j) == predicted);
+        if predicted != Some(label) {
+            for &idx in &self.connections {
+                let conn = &mut connections[idx];
+                for (syns, &x) in conn.synapses.iter_mut().zip(&self.counts[conn.src_layer]) {
+                    if x == 0 {
+                        continue;
+                    }
+                    for syn in syns {
+                        syn.weight += self.rate * error(syn.dst) * x as f64;
+                    }
+                }
+            }
+        }
+        for counts in &mut self.counts {
+            counts.fill(0);
+        }
+    }
+}
+
+/// Whether training changes this connection's weights or delays, so it is
+/// worth writing out.
+pub(crate) fn is_trained(conn: &Connection, readout_layer: usize) -> bool {
+    conn.kind == ConnectKind::Chemical
+        && (conn.dst_layer == readout_layer
+            || conn.plasticity.is_some()
+            || conn.delay_plasticity.is_some())
+}
+
+/// Loads `w = from_file("...")`: one row per source neuron, one
+/// comma-separated weight per destination neuron, as written by
+/// [`write_weights_csv`]. Returns `None` when `w` is not a `from_file` call.
+pub(crate) fn load_weights(
+    body: &[Assign],
+    src_size: usize,
+    dst_size: usize,
+    files: DataFiles<'_>,
+) -> Result<Option<Vec<Vec<f64>>>, SimError> {
+    let Some(Expr::Call(call)) = body.iter().find(|a| a.key.name == "w").map(|a| &a.value) else {
+        return Ok(None);
+    };
+    if call.name.name != "from_file" {
+        return Ok(None);
+    }
+    let [CallArg::Positional(Expr::String(path))] = call.args.as_slice() else {
+        return Err(SimError::new(SimErrorKind::InvalidParameter(
+            "`from_file` takes one file path".to_string(),
+        )));
+    };
+    read_data(path, files, |text| parse_weights(text, src_size, dst_size)).map(Some)
+}
+
+fn parse_weights(text: &str, rows: usize, width: usize) -> Result<Vec<Vec<f64>>, String> {
+    let mut weights = Vec::with_capacity(rows);
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let row = line
+            .split(',')
+            .map(str::trim)
+            .map(|field| match field.parse::<f64>() {
+                Ok(w) if w.is_finite() => Ok(w),
+                _ => Err(format!("line {}: `{field}` is not a weight", idx + 1)),
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        if row.len() != width {
+            return Err(format!(
+                "line {}: expected {width} weights, found {}",
+                idx + 1,
+                row.len()
+            ));
+        }
+        weights.push(row);
+    }
+    if weights.len() != rows {
+        return Err(format!(
+            "expected {rows} rows of weights, found {}",
+            weights.len()
+        ));
+    }
+    Ok(weights)
+}
+
+/// Writes a weight matrix in the format `from_file` reads. Weights print in
+/// Rust's shortest round-trip form, so loading them back is exact.
+pub fn write_weights_csv(out: &mut impl Write, weights: &[Vec<f64>]) -> io::Result<()> {
+    writeln!(
+        out,
+        "# one row per source neuron, one column per destination neuron"
+    )?;
+    for row in weights {
+        let row: Vec<String> = row.iter().map(f64::to_string).collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+/// Writes a weight matrix as a grayscale PGM image, one pixel row per
+/// source neuron, scaled from black at the smallest weight to white at the
+/// largest; a header comment gives the range. A matrix of equal weights is
+/// black.
+pub fn write_weights_pgm(out: &mut impl Write, weights: &[Vec<f64>]) -> io::Result<()> {
+    let width = weights.first().map_or(0, Vec::len);
+    let (min, max) = weights
+        .iter()
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &w| {
+            (lo.min(w), hi.max(w))
+        });
+    let (min, max) = if min > max { (0.0, 0.0) } else { (min, max) };
+    writeln!(out, "P5")?;
+    writeln!(out, "# weights from {min} to {max}")?;
+    writeln!(out, "{width} {}", weights.len())?;
+    writeln!(out, "255")?;
+    let scale = if max > min { 255.0 / (max - min) } else { 0.0 };
+    let pixels: Vec<u8> = weights
+        .iter()
+        .flatten()
+        .map(|&w| ((w - min) * scale).round() as u8)
+        .collect();
+    out.write_all(&pixels)
+}
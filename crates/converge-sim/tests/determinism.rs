@@ -0,0 +1,59 @@
+//! Pinned results that must hold bit-for-bit on every supported platform.
+//! A change here is a change to simulation semantics: update the values only
+//! alongside a note in `docs/semantics.md` and the changelog.
+
+use converge_lang::parser::parse_program;
+use converge_sim::{SimConfig, SimSummary, format_spike_hash, simulate_with};
+
+const RECURRENT: &str = r#"
+neuron LIF { tau_m = 15 ms, v_th = 1.0 }
+layer In[16] : LIF
+layer Exc[12] : LIF
+layer Inh[4] : LIF
+connect In -> Exc { w = Uniform(0.2, 0.9), d = 1 ms }
+connect Exc -> Inh { w = Normal(0.5, 0.2), d = 2 ms }
+connect Inh -> Exc { w = Uniform(-0.8, -0.1), d = 1 ms }
+connect Exc -> Exc { w = Normal(0.05, 0.1), d = 3 ms }
+stimulus In = Poisson(rate=300 Hz)
+stimulus Exc = Poisson(rate=20 Hz)
+seed 20240611
+run for 200 ms step 0.5 ms
+"#;
+
+fn run(src: &str, compensated: bool) -> SimSummary {
+    let program = parse_program(src).expect("parse");
+    let config = SimConfig {
+        compensated_summation: compensated,
+        ..SimConfig::default()
+    };
+    simulate_with(&program, &config).expect("sim")
+}
+
+fn spikes(summary: &SimSummary) -> Vec<u64> {
+    summary.layers.iter().map(|l| l.spikes).collect()
+}
+
+#[test]
+fn recurrent_network_is_pinned() {
+    let summary = run(RECURRENT, false);
+    assert_eq!(spikes(&summary), [460, 700, 472]);
+    assert_eq!(format_spike_hash(summary.spike_hash), "42f0013d31170d89");
+}
+
+#[test]
+fn compensated_summation_is_pinned() {
+    let summary = run(RECURRENT, true);
+    assert_eq!(spikes(&summary), [460, 700, 472]);
+    assert_eq!(format_spike_hash(summary.spike_hash), "42f0013d31170d89");
+}
+
+#[test]
+fn assert_example_is_pinned() {
+    let src = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../examples/assert.cv"
+    ));
+    let summary = run(src, false);
+    assert_eq!(spikes(&summary), [42, 72]);
+    assert_eq!(format_spike_hash(summary.spike_hash), "8d3b32a57455c565");
+}
@@ -0,0 +1,147 @@
+//! Browser bindings for `converge-lang` and `converge-sim`.
+//!
+//! Every entry point takes program source and returns a JSON string, so the
+//! JS side only needs `JSON.parse`. Failures are reported in the result, never
+//! thrown:
+//!
+//! ```text
+//! {"ok": true, ...}
+//! {"ok": false, "diagnostics": [{"message", "start", "end", "line", "col"}]}
+//! ```
+//!
+//! Build with `cargo build -p converge-wasm --target wasm32-unknown-unknown`
+//! and run `wasm-bindgen --target web` on the output.
+
+use converge_lang::ast::Program;
+use converge_lang::diagnostic::Diagnostic;
+use converge_lang::emit::{ast_json, cvir_json};
+use converge_lang::json::Value;
+use converge_lang::parser::parse_program_recovering;
+use converge_sim::{SimConfig, check_asserts, simulate_with, source_hash, summary_json};
+use wasm_bindgen::prelude::*;
+
+/// Parses `src`; on success the result carries the parse tree as `ast`.
+#[wasm_bindgen]
+pub fn parse(src: &str) -> String {
+    match parse_program_recovering(src) {
+        Ok(program) => ok_with("ast", &ast_json(&program)),
+        Err(diags) => failure(src, &diags),
+    }
+}
+
+/// Parses and validates `src`.
+#[wasm_bindgen]
+pub fn validate(src: &str) -> String {
+    match checked(src) {
+        Ok(_) => "{\"ok\": true}\n".to_string(),
+        Err(diags) => failure(src, &diags),
+    }
+}
+
+/// Parses and validates `src`; on success the result carries CVIR as `cvir`.
+#[wasm_bindgen]
+pub fn cvir(src: &str) -> String {
+    match checked(src) {
+        Ok(program) => ok_with("cvir", &cvir_json(&program)),
+        Err(diags) => failure(src, &diags),
+    }
+}
+
+/// Runs `src` to completion. The summary includes the spike raster when
+/// `record_spikes` is set, and `ok` is false if any `assert` failed, with
+/// the summary still attached.
+#[wasm_bindgen]
+pub fn simulate(src: &str, record_spikes: bool) -> String {
+    let program = match checked(src) {
+        Ok(p) => p,
+        Err(diags) => return failure(src, &diags),
+    };
+    let config = SimConfig {
+        record_spikes,
+        ..SimConfig::default()
+    };
+    let mut summary = match simulate_with(&program, &config) {
+        Ok(s) => s,
+        Err(err) => return failure(src, &[Diagnostic::new(err.to_string())]),
+    };
+    summary.input_hash = Some(source_hash(src));
+    let failures = check_asserts(&program, &summary);
+    format!(
+        "{{\"ok\": {}, \"summary\": {}, \"diagnostics\": {}}}\n",
+        failures.is_empty(),
+        summary_json(&summary).trim_end(),
+        diagnostics_json(src, &failures)
+    )
+}
+
+fn checked(src: &str) -> Result<Program, Vec<Diagnostic>> {
+    let program = parse_program_recovering(src)?;
+    converge_lang::validate::validate(&program)?;
+    Ok(program)
+}
+
+fn ok_with(key: &str, json: &str) -> String {
+    format!("{{\"ok\": true, \"{key}\": {}}}\n", json.trim_end())
+}
+
+fn failure(src: &str, diags: &[Diagnostic]) -> String {
+    format!(
+        "{{\"ok\": false, \"diagnostics\": {}}}\n",
+        diagnostics_json(src, diags)
+    )
+}
+
+/// Diagnostics with byte offsets and a 1-based line and column, the column
+/// in UTF-16 code units as JavaScript indexes strings, or nulls for
+/// diagnostics without a span.
+fn diagnostics_json(src: &str, diags: &[Diagnostic]) -> String {
+    let entries: Vec<String> = diags
+        .iter()
+        .map(|diag| {
+            let message = Value::String(diag.message.clone());
+            match &diag.span {
+                Some(span) => {
+                    let (line, col) = span.line_col_utf16(src);
+                    format!(
+                        "{{\"message\": {message}, \"start\": {}, \"end\": {}, \"line\": {line}, \"col\": {col}}}",
+                        span.start, span.end
+                    )
+                }
+                None => format!(
+                    "{{\"message\": {message}, \"start\": null, \"end\": null, \"line\": null, \"col\": null}}"
+                ),
+            }
+        })
+        .collect();
+    format!("[{}]", entries.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converge_lang::json;
+
+    const SRC: &str = "neuron LIF { tau_m = 10 ms, v_th = 1.0 }\nlayer A[4] : LIF\nstimulus A = Poisson(rate=200 Hz)\nrun for 20 ms\nassert spikes(A) >= 1\n";
+
+    #[test]
+    fn results_are_json_with_diagnostics() {
+        let result = json::parse(&simulate(SRC, true)).expect("valid json");
+        assert_eq!(result.get("ok"), Some(&Value::Bool(true)));
+        let summary = result.get("summary").expect("summary");
+        assert!(summary.get("total_spikes").is_some());
+        assert!(json::parse(&parse(SRC)).unwrap().get("ast").is_some());
+        assert!(json::parse(&cvir(SRC)).unwrap().get("cvir").is_some());
+
+        let result = json::parse(&validate("layer A[4] : Missing\nrun for 1 ms\n")).unwrap();
+        assert_eq!(result.get("ok"), Some(&Value::Bool(false)));
+        let diags = result.get("diagnostics").and_then(Value::as_array).unwrap();
+        assert_eq!(diags[0].get("line").and_then(Value::as_f64), Some(1.0));
+        assert_eq!(diags[0].get("col").and_then(Value::as_f64), Some(14.0));
+
+        // Columns count UTF-16 units, as JavaScript indexes strings.
+        let result = json::parse(&validate("layer Aé[4] : Missing\nrun for 1 ms\n")).unwrap();
+        let diags = result.get("diagnostics").and_then(Value::as_array).unwrap();
+        assert_eq!(diags[0].get("start").and_then(Value::as_f64), Some(15.0));
+        assert_eq!(diags[0].get("col").and_then(Value::as_f64), Some(15.0));
+    }
+}
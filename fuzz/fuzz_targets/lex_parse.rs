@@ -0,0 +1,24 @@
+//! Arbitrary text through the lexer, parser and validator, with and without
+//! error recovery: every input must end in tokens, a program or
+//! diagnostics, never a panic.
+
+#![no_main]
+
+use converge_lang::lexer::{lex_recovering, lex_with_env};
+use converge_lang::parser::{parse_program_recovering_with_env, parse_program_with_env};
+use converge_lang::validate::{validate, warnings};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|src: &str| {
+    // A fixed environment, so a crash reproduces from its input alone.
+    let env = |name: &str| (name == "N").then(|| "4".to_string());
+    let _ = lex_with_env(src, &env);
+    let _ = lex_recovering(src, &env);
+    let _ = parse_program_recovering_with_env(src, &env);
+    if let Ok(program) = parse_program_with_env(src, &[], &env) {
+        let _ = validate(&program);
+        let _ = warnings(&program);
+        let _ = converge_lang::emit::cvir_json(&program);
+        let _ = converge_lang::print::print_program(&program);
+    }
+});
@@ -0,0 +1,38 @@
+//! Arbitrary syntax trees through the printer and back: printed text always
+//! lexes, and once parsed, prints the same and parses back to the same tree
+//! after every further round trip.
+
+#![no_main]
+
+use converge_lang::ast::Program;
+use converge_lang::lexer::lex_with_env;
+use converge_lang::parser::parse_program_with_env;
+use converge_lang::print::print_program;
+use converge_lang::visit::clear_spans;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|program: Program| {
+    let env = |_: &str| None;
+    let printed = print_program(&program);
+    if let Err(diag) = lex_with_env(&printed, &env) {
+        panic!("printed text does not lex: {}\n{printed}", diag.message);
+    }
+    // Not every tree is one the parser builds, such as an empty sweep, so
+    // the check starts from what the parser made of it.
+    let Ok(mut parsed) = parse_program_with_env(&printed, &[], &env) else {
+        return;
+    };
+    let printed = print_program(&parsed);
+    let mut again = parse_program_with_env(&printed, &[], &env).unwrap_or_else(|diag| {
+        panic!(
+            "printed program does not parse: {}\n{printed}",
+            diag.message
+        )
+    });
+    assert_eq!(print_program(&again), printed);
+    clear_spans(&mut parsed);
+    clear_spans(&mut again);
+    parsed.params.clear();
+    parsed.deprecated.clear();
+    assert_eq!(again, parsed);
+});